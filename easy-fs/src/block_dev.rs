@@ -6,4 +6,6 @@ pub trait BlockDevice: Send + Sync + Any {
     fn read_block(&self, block_id: usize, buf: &mut [u8]);
     ///Write data from buffer to block
     fn write_block(&self, block_id: usize, buf: &[u8]);
+    ///Complete the requests the device signalled, called from its interrupt
+    fn handle_irq(&self) {}
 }
@@ -308,6 +308,52 @@ impl DiskInode {
         self.indirect2 = 0;
         v
     }
+    /// Number of low-level indirect1 blocks under indirect2 for `data_blocks`
+    fn sub_indirect1_blocks(data_blocks: usize) -> usize {
+        data_blocks
+            .saturating_sub(INDIRECT1_BOUND)
+            .div_ceil(INODE_INDIRECT1_COUNT)
+    }
+    /// Decrease the size of current disk inode and return blocks that should be deallocated.
+    /// The bytes past `new_size` in its last block are left as they are.
+    pub fn decrease_size(&mut self, new_size: u32, block_device: &Arc<dyn BlockDevice>) -> Vec<u32> {
+        assert!(new_size <= self.size);
+        let mut v: Vec<u32> = Vec::new();
+        let current_blocks = self.data_blocks() as usize;
+        let total_blocks = Self::_data_blocks(new_size) as usize;
+        // data blocks, read before the index blocks are given back
+        for inner_id in total_blocks..current_blocks {
+            v.push(self.get_block_id(inner_id as u32, block_device));
+        }
+        // low-level indirect1
+        let (sub_total, sub_current) = (
+            Self::sub_indirect1_blocks(total_blocks),
+            Self::sub_indirect1_blocks(current_blocks),
+        );
+        if sub_total < sub_current {
+            get_block_cache(self.indirect2 as usize, Arc::clone(block_device))
+                .lock()
+                .read(0, |indirect2: &IndirectBlock| {
+                    v.extend_from_slice(&indirect2[sub_total..sub_current]);
+                });
+        }
+        // indirect2 block
+        if total_blocks <= INDIRECT1_BOUND && current_blocks > INDIRECT1_BOUND {
+            v.push(self.indirect2);
+            self.indirect2 = 0;
+        }
+        // indirect1 block
+        if total_blocks <= DIRECT_BOUND && current_blocks > DIRECT_BOUND {
+            v.push(self.indirect1);
+            self.indirect1 = 0;
+        }
+        // direct
+        for entry in self.direct.iter_mut().take(current_blocks).skip(total_blocks) {
+            *entry = 0;
+        }
+        self.size = new_size;
+        v
+    }
     /// Read data from current disk inode
     pub fn read_at(
         &self,
@@ -11,7 +11,8 @@ mod vfs;
 /// Use a block size of 512 bytes
 pub const BLOCK_SZ: usize = 512;
 use bitmap::Bitmap;
-use block_cache::{block_cache_sync_all, get_block_cache};
+pub use block_cache::block_cache_sync_all;
+use block_cache::get_block_cache;
 pub use block_dev::BlockDevice;
 pub use efs::EasyFileSystem;
 use layout::*;
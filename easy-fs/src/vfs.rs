@@ -1,6 +1,6 @@
 use super::{
     block_cache_sync_all, get_block_cache, BlockDevice, DirEntry, DiskInode, DiskInodeType,
-    EasyFileSystem, DIRENT_SZ,
+    EasyFileSystem, BLOCK_SZ, DIRENT_SZ,
 };
 use alloc::string::String;
 use alloc::sync::Arc;
@@ -41,6 +41,18 @@ impl Inode {
             .lock()
             .modify(self.block_offset, f)
     }
+    /// Whether current inode is a directory
+    pub fn is_dir(&self) -> bool {
+        self.read_disk_inode(|disk_inode| disk_inode.is_dir())
+    }
+    /// Id of current inode, 0 for the root
+    pub fn inode_id(&self) -> u32 {
+        self.fs.lock().get_inode_id(self.block_id as u32, self.block_offset)
+    }
+    /// Size of current inode in bytes
+    pub fn size(&self) -> usize {
+        self.read_disk_inode(|disk_inode| disk_inode.size as usize)
+    }
     /// Find inode under a disk inode by name
     fn find_inode_id(&self, name: &str, disk_inode: &DiskInode) -> Option<u32> {
         // assert it is a directory
@@ -183,4 +195,35 @@ impl Inode {
         });
         block_cache_sync_all();
     }
+    /// Set the size of current inode, growing it with zeros
+    pub fn set_size(&self, new_size: u32) {
+        let mut fs = self.fs.lock();
+        self.modify_disk_inode(|disk_inode| {
+            let size = disk_inode.size;
+            if new_size >= size {
+                // blocks come zeroed from dealloc
+                self.increase_size(new_size, disk_inode, &mut fs);
+                return;
+            }
+            // zero the tail of the last block kept, a later growth reads it
+            let tail_end = (new_size as usize).next_multiple_of(BLOCK_SZ).min(size as usize);
+            if tail_end > new_size as usize {
+                let zeros = [0u8; BLOCK_SZ];
+                disk_inode.write_at(
+                    new_size as usize,
+                    &zeros[..tail_end - new_size as usize],
+                    &self.block_device,
+                );
+            }
+            let data_blocks_dealloc = disk_inode.decrease_size(new_size, &self.block_device);
+            assert!(
+                data_blocks_dealloc.len()
+                    == (DiskInode::total_blocks(size) - DiskInode::total_blocks(new_size)) as usize
+            );
+            for data_block in data_blocks_dealloc.into_iter() {
+                fs.dealloc_data(data_block);
+            }
+        });
+        block_cache_sync_all();
+    }
 }
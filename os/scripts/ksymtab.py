@@ -0,0 +1,88 @@
+#!/usr/bin/env python3
+"""
+Fill the .ksymtab section of a linked kernel with its function symbols,
+in place, see src/tools/symbols.rs for the layout.
+
+Usage: ksymtab.py <kernel ELF>
+
+Needs rust-nm and rust-objcopy (cargo-binutils), and pyelftools.
+"""
+
+import os
+import re
+import struct
+import subprocess
+import sys
+import tempfile
+
+from elftools.elf.elffile import ELFFile
+
+SECTION = ".ksymtab"
+MAGIC = b"KSYMTAB\0"
+# longer names are cut, a backtrace line stays readable
+MAX_NAME = 120
+HASH = re.compile(r"::h[0-9a-f]{16}$")
+
+
+def section_size(elf_path):
+    with open(elf_path, "rb") as f:
+        section = ELFFile(f).get_section_by_name(SECTION)
+        if section is None:
+            sys.exit(f"ksymtab: no {SECTION} section in {elf_path}")
+        return section["sh_size"]
+
+
+def function_symbols(elf_path):
+    """(address, name) of the text symbols, by address"""
+    output = subprocess.check_output(
+        ["rust-nm", "--defined-only", "--demangle", "-n", elf_path]
+    ).decode()
+    symbols = {}
+    for line in output.splitlines():
+        parts = line.split(" ", 2)
+        if len(parts) != 3 or parts[1] not in "tTwW":
+            continue
+        address = int(parts[0], 16)
+        name = HASH.sub("", parts[2])[:MAX_NAME]
+        # aliases of one address: keep the first
+        symbols.setdefault(address, name)
+    return sorted(symbols.items())
+
+
+def build(symbols):
+    entries = bytearray()
+    names = bytearray()
+    for address, name in symbols:
+        encoded = name.encode()
+        entries += struct.pack("<QII", address, len(names), len(encoded))
+        names += encoded
+    return MAGIC + struct.pack("<Q", len(symbols)) + entries + names
+
+
+def main():
+    if len(sys.argv) != 2:
+        sys.exit(__doc__)
+    elf_path = sys.argv[1]
+    size = section_size(elf_path)
+    symbols = function_symbols(elf_path)
+    table = build(symbols)
+    used = len(table)
+    if used > size:
+        sys.exit(f"ksymtab: {used} bytes of symbols, {SECTION} holds {size}, "
+                 "raise KSYMTAB_SIZE in src/tools/symbols.rs")
+    # the section keeps its size, nothing else in the image moves
+    table += bytes(size - used)
+
+    with tempfile.NamedTemporaryFile(delete=False) as blob:
+        blob.write(table)
+    try:
+        subprocess.check_call(
+            ["rust-objcopy", f"--update-section={SECTION}={blob.name}", elf_path]
+        )
+    finally:
+        os.unlink(blob.name)
+    print(f"ksymtab: {len(symbols)} symbols, {used}/{size} bytes")
+
+
+if __name__ == "__main__":
+    main()
@@ -0,0 +1,258 @@
+//! Just enough of a flattened device tree reader to describe the machine
+//!
+//! Reads `/memory*` nodes, the children of `/reserved-memory`, the memory
+//! reservation block, the `timebase-frequency` of `/cpus` and the
+//! `riscv,isa` of its first cpu, the `bootargs` and initrd range of
+//! `/chosen`, and the `compatible`, `reg` and `interrupts`
+//! of every other node. Everything else in the blob is skipped.
+//! Must run while physical memory is directly accessible (before paging
+//! is enabled), what is kept is copied out of the blob.
+
+use alloc::{string::String, vec::Vec};
+
+const FDT_MAGIC: u32 = 0xd00d_feed;
+const FDT_BEGIN_NODE: u32 = 1;
+const FDT_END_NODE: u32 = 2;
+const FDT_PROP: u32 = 3;
+const FDT_NOP: u32 = 4;
+const FDT_END: u32 = 9;
+
+/// Deeper nodes use the cells of this depth
+const MAX_DEPTH: usize = 16;
+
+/// A node with a `compatible` property
+#[derive(Debug, Default)]
+pub struct FdtDevice {
+    /// Most specific first
+    pub compatible: Vec<String>,
+    /// First `(start, size)` of `reg`
+    pub reg: Option<(usize, usize)>,
+    /// First cell of `interrupts`, the PLIC source on RISC-V
+    pub irq: Option<u32>,
+}
+
+impl FdtDevice {
+    pub fn is_compatible(&self, name: &str) -> bool {
+        self.compatible.iter().any(|compatible| compatible == name)
+    }
+}
+
+/// What a device tree says, ranges as `(start, size)`
+pub struct DeviceTree {
+    /// The blob itself
+    pub blob: (usize, usize),
+    pub ram: Vec<(usize, usize)>,
+    pub reserved: Vec<(usize, usize)>,
+    /// Rate of `mtime`, Hz
+    pub timebase_frequency: Option<usize>,
+    /// ISA string of the first cpu, like `rv64imafdc_zicsr_svpbmt`
+    pub isa: Option<String>,
+    /// The kernel command line, see `cmdline`
+    pub bootargs: Option<String>,
+    /// The image the bootloader loaded for a RAM disk, see `drivers::block::ramdisk`
+    pub initrd: Option<(usize, usize)>,
+    /// In the order of the blob
+    pub devices: Vec<FdtDevice>,
+}
+
+impl DeviceTree {
+    /// The first device compatible with one of `names`
+    pub fn find(&self, names: &[&str]) -> Option<&FdtDevice> {
+        self.devices
+            .iter()
+            .find(|device| names.iter().any(|name| device.is_compatible(name)))
+    }
+}
+
+struct Blob {
+    base: usize,
+}
+
+impl Blob {
+    fn be32(&self, offset: usize) -> u32 {
+        u32::from_be(unsafe { ((self.base + offset) as *const u32).read_unaligned() })
+    }
+
+    fn be64(&self, offset: usize) -> u64 {
+        u64::from_be(unsafe { ((self.base + offset) as *const u64).read_unaligned() })
+    }
+
+    /// NUL terminated string at `offset`
+    fn str_at(&self, offset: usize) -> &'static [u8] {
+        let start = (self.base + offset) as *const u8;
+        let mut len = 0;
+        while unsafe { *start.add(len) } != 0 {
+            len += 1;
+        }
+        unsafe { core::slice::from_raw_parts(start, len) }
+    }
+
+    fn bytes(&self, offset: usize, len: usize) -> &'static [u8] {
+        unsafe { core::slice::from_raw_parts((self.base + offset) as *const u8, len) }
+    }
+
+    /// Big-endian number of `cells` 32-bit cells at `offset`
+    fn cells(&self, offset: usize, cells: usize) -> usize {
+        (0..cells).fold(0usize, |value, cell| (value << 32) | self.be32(offset + cell * 4) as usize)
+    }
+}
+
+/// Parse the blob at `pa`, `None` if there is no valid one.
+pub fn parse(pa: usize) -> Option<DeviceTree> {
+    if pa == 0 || pa % 4 != 0 {
+        return None;
+    }
+    let blob = Blob { base: pa };
+    if blob.be32(0) != FDT_MAGIC {
+        return None;
+    }
+    let total_size = blob.be32(4) as usize;
+    let off_struct = blob.be32(8) as usize;
+    let off_strings = blob.be32(12) as usize;
+    let off_rsvmap = blob.be32(16) as usize;
+
+    let mut tree = DeviceTree {
+        blob: (pa, total_size),
+        ram: Vec::new(),
+        reserved: Vec::new(),
+        timebase_frequency: None,
+        isa: None,
+        bootargs: None,
+        initrd: None,
+        devices: Vec::new(),
+    };
+
+    // memory reservation block: (address, size) pairs up to (0, 0)
+    let mut entry = off_rsvmap;
+    loop {
+        let (start, size) = (blob.be64(entry) as usize, blob.be64(entry + 8) as usize);
+        if size == 0 {
+            break;
+        }
+        tree.reserved.push((start, size));
+        entry += 16;
+    }
+
+    // `(#address-cells, #size-cells)` each node sets for its children,
+    // by depth, the root being at depth 1
+    let mut cells = [(2usize, 1usize); MAX_DEPTH];
+    let mut depth = 0usize;
+    let mut in_memory = false;
+    let mut in_reserved_parent = false;
+    let mut in_cpus = false;
+    let mut in_chosen = false;
+    let (mut initrd_start, mut initrd_end) = (None, None);
+    // the properties of a node come before its children: it is complete
+    // at the next node boundary
+    let mut node = FdtDevice::default();
+
+    let mut offset = off_struct;
+    loop {
+        let token = blob.be32(offset);
+        offset += 4;
+        match token {
+            FDT_BEGIN_NODE => {
+                finish_node(&mut node, &mut tree.devices);
+                let name = blob.str_at(offset);
+                offset = (offset + name.len() + 1 + 3) & !3;
+                depth += 1;
+                cells[depth.min(MAX_DEPTH - 1)] = (2, 1);
+                if depth == 2 {
+                    in_memory = name.starts_with(b"memory");
+                    in_reserved_parent = name == b"reserved-memory";
+                    in_cpus = name == b"cpus";
+                    in_chosen = name == b"chosen";
+                }
+            }
+            FDT_END_NODE => {
+                finish_node(&mut node, &mut tree.devices);
+                if depth == 2 {
+                    in_memory = false;
+                    in_reserved_parent = false;
+                    in_cpus = false;
+                    in_chosen = false;
+                }
+                depth = depth.saturating_sub(1);
+            }
+            FDT_PROP => {
+                let len = blob.be32(offset) as usize;
+                let name = blob.str_at(off_strings + blob.be32(offset + 4) as usize);
+                let value = offset + 8;
+                offset = (value + len + 3) & !3;
+
+                let own = depth.min(MAX_DEPTH - 1);
+                let parent = cells[depth.saturating_sub(1).min(MAX_DEPTH - 1)];
+                match name {
+                    b"#address-cells" => cells[own].0 = blob.be32(value) as usize,
+                    b"#size-cells" => cells[own].1 = blob.be32(value) as usize,
+                    b"reg" => {
+                        let mut ranges = Vec::new();
+                        read_reg(&blob, value, len, parent, &mut ranges);
+                        node.reg = ranges.first().copied();
+                        if depth == 2 && in_memory {
+                            tree.ram.extend(ranges);
+                        } else if depth == 3 && in_reserved_parent {
+                            tree.reserved.extend(ranges);
+                        }
+                    }
+                    b"compatible" => {
+                        node.compatible = blob
+                            .bytes(value, len)
+                            .split(|&byte| byte == 0)
+                            .filter(|compatible| !compatible.is_empty())
+                            .map(|compatible| String::from_utf8_lossy(compatible).into_owned())
+                            .collect();
+                    }
+                    b"interrupts" if len >= 4 => node.irq = Some(blob.be32(value)),
+                    // on /cpus, or on each cpu node
+                    b"timebase-frequency" if in_cpus && tree.timebase_frequency.is_none() => {
+                        tree.timebase_frequency = Some(blob.cells(value, len / 4));
+                    }
+                    b"riscv,isa" if depth == 3 && in_cpus && tree.isa.is_none() => {
+                        let isa = blob.bytes(value, len).split(|&byte| byte == 0).next().unwrap_or_default();
+                        tree.isa = Some(String::from_utf8_lossy(isa).into_owned());
+                    }
+                    b"bootargs" if depth == 2 && in_chosen => {
+                        let bootargs = blob.bytes(value, len).split(|&byte| byte == 0).next().unwrap_or_default();
+                        tree.bootargs = Some(String::from_utf8_lossy(bootargs).into_owned());
+                    }
+                    // 32 or 64 bits, as long as the property is
+                    b"linux,initrd-start" if depth == 2 && in_chosen => initrd_start = Some(blob.cells(value, len / 4)),
+                    b"linux,initrd-end" if depth == 2 && in_chosen => initrd_end = Some(blob.cells(value, len / 4)),
+                    _ => {}
+                }
+            }
+            FDT_NOP => {}
+            FDT_END => break,
+            _ => {
+                log::warn!("fdt: bad token {:#x} at {:#x}", token, offset - 4);
+                break;
+            }
+        }
+    }
+    tree.initrd = initrd_start
+        .zip(initrd_end)
+        .filter(|&(start, end)| start < end)
+        .map(|(start, end)| (start, end - start));
+    Some(tree)
+}
+
+/// Keep `node` if it names a device, and start the next one.
+fn finish_node(node: &mut FdtDevice, devices: &mut Vec<FdtDevice>) {
+    let node = core::mem::take(node);
+    if !node.compatible.is_empty() {
+        devices.push(node);
+    }
+}
+
+fn read_reg(blob: &Blob, value: usize, len: usize, (address_cells, size_cells): (usize, usize), out: &mut Vec<(usize, usize)>) {
+    let entry_size = (address_cells + size_cells) * 4;
+    if entry_size == 0 {
+        return;
+    }
+    for entry in (value..value + len).step_by(entry_size) {
+        let start = blob.cells(entry, address_cells);
+        let size = blob.cells(entry + address_cells * 4, size_cells);
+        out.push((start, size));
+    }
+}
@@ -1,13 +1,70 @@
+//! Constants of the Kendryte K210 (Sipeed MAIX boards)
 
 
-pub const CLOCK_FREQ: usize = 400_000_000; // 400Mhz
+/// Rate of `mtime`: the CPU clock divided by 62, with the 403MHz the
+/// RustSBI of the k210 leaves the PLL at
+pub const CLOCK_FREQ: usize = 403_000_000 / 62;
 
 
 /// [start, size]
 pub const MMIO: &[(usize, usize)] = &[
-    (0x0010_0000, 0x00_2000), // VIRT_TEST/RTC  in virt machine
+    (0x0C00_0000, 0x00_3000), // PLIC priority and enable
+    (0x0C20_0000, 0x00_1000), // PLIC hart 0 contexts
+    (0x3800_0000, 0x00_1000), // UARTHS
+    (0x3800_1000, 0x00_1000), // GPIOHS, the chip select of the SD card
+    (0x5020_0000, 0x00_1000), // GPIO
+    (0x5024_0000, 0x00_1000), // SPI slave
+    (0x502B_0000, 0x00_1000), // FPIOA, the pin mux
+    (0x502D_0000, 0x00_1000), // TIMER0
+    (0x502E_0000, 0x00_1000), // TIMER1
+    (0x502F_0000, 0x00_1000), // TIMER2
+    (0x5044_0000, 0x00_1000), // SYSCTL, clocks and resets
+    (0x5200_0000, 0x00_1000), // SPI0, the SD card
+    (0x5300_0000, 0x00_1000), // SPI1
+    (0x5400_0000, 0x00_1000), // SPI2
 ];
 
+pub const PLIC_BASE: usize = 0x0c00_0000;
 
+/// No battery backed clock, the time of day starts at the epoch
+pub const RTC_BASE: Option<usize> = None;
 
-type BlockDeviceImpl = crate::drivers::block::SDCardWrapper;
\ No newline at end of file
+/// The UARTHS belongs to the SBI, console input is polled through it
+pub const CONSOLE_UART: Option<(usize, u32)> = None;
+
+
+
+pub type BlockDeviceImpl = crate::drivers::block::SDCardWrapper;
+/// Name of the root block device, as `/proc/iosched` shows it
+pub const BLOCK_DEVICE_NAME: &str = "mmcblk0";
+/// The SD card is not in the tree, it is assumed
+pub const DISK_IN_TREE: bool = false;
+
+/// The kernel is flashed right after the SBI, no room for a command line
+pub const CMDLINE_PA: Option<usize> = None;
+
+/// The SD card is driven by polling over SPI
+pub const BLOCK_IRQ: Option<u32> = None;
+
+/// Physical ranges devices may access directly: the 6MiB general SRAM
+pub const DMA_REGIONS: &[(usize, usize)] = &[
+    (0x8000_0000, 0x60_0000),
+];
+
+/// L1 data cache line size
+pub const DMA_ALIGN: usize = 64;
+
+/// Write back dirty lines of `[pa, pa + len)` before a device reads them.
+///
+/// The K210 core exposes no cache maintenance instruction to S-mode,
+/// the fence at least orders our stores before the DMA is started.
+#[inline(always)]
+pub fn dcache_clean(_pa: usize, _len: usize) {
+    unsafe { core::arch::asm!("fence rw, rw") };
+}
+
+/// Drop cached lines of `[pa, pa + len)` around a device write.
+#[inline(always)]
+pub fn dcache_invalidate(_pa: usize, _len: usize) {
+    unsafe { core::arch::asm!("fence rw, rw") };
+}
@@ -1,6 +1,14 @@
+pub mod fdt;
+pub mod platform;
+
+#[cfg(feature = "board_k210")]
 mod k210;
+#[cfg(feature = "board_qemu")]
 mod qemu;
 
+#[cfg(all(feature = "board_k210", feature = "board_qemu"))]
+compile_error!("board_k210 and board_qemu are exclusive, build the k210 with --no-default-features");
+
 #[cfg(feature = "board_k210")]
 pub use k210::*;
 
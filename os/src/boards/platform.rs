@@ -0,0 +1,140 @@
+//! The machine the kernel runs on, as found at boot
+//!
+//! The device tree handed over by the firmware gives the RAM, the rate of
+//! `mtime`, the extensions of the harts and the devices the kernel drives: PLIC, console UART, RTC and
+//! virtio block device, or else the image of a RAM disk. What the tree doesn't say, or everything on a
+//! board booted without one, comes from the board constants.
+//!
+//! Set once by [`init`], read-only afterwards. Until then [`platform`]
+//! answers with the board constants.
+
+use alloc::vec::Vec;
+use spin::Once;
+
+use super::{fdt::DeviceTree, BLOCK_IRQ, CLOCK_FREQ, CONSOLE_UART, DISK_IN_TREE, MMIO, PLIC_BASE, RTC_BASE};
+use crate::config::{PAGE_SIZE, PHYSTOP};
+
+const VIRTIO_MAGIC: u32 = 0x7472_6976;
+const VIRTIO_DEVICE_BLOCK: u32 = 2;
+
+#[derive(Debug)]
+pub struct Platform {
+    /// Rate of `mtime`, Hz
+    pub clock_freq: usize,
+    /// End of the RAM the kernel identity maps and allocates frames from
+    pub ram_end: usize,
+    pub plic_base: usize,
+    /// `(base, PLIC source)` of the 16550a the console input comes from
+    pub console_uart: Option<(usize, u32)>,
+    /// Goldfish RTC
+    pub rtc_base: Option<usize>,
+    /// Base of the virtio block device, the board's default slot if `None`
+    pub virtio_block: Option<usize>,
+    /// PLIC source of the block device
+    pub block_irq: Option<u32>,
+    /// The board has its disk, virtio block device or SD card. Assumed
+    /// without a tree
+    pub disk: bool,
+    /// `(start, size)` of the RAM disk image the bootloader loaded
+    pub initrd: Option<(usize, usize)>,
+    /// Device windows the kernel maps, `(start, size)`
+    pub mmio: &'static [(usize, usize)],
+    /// Page table entries can set the memory type, MMIO is mapped as I/O
+    pub svpbmt: bool,
+}
+
+static BOARD: Platform = Platform {
+    clock_freq: CLOCK_FREQ,
+    ram_end: PHYSTOP,
+    plic_base: PLIC_BASE,
+    console_uart: CONSOLE_UART,
+    rtc_base: RTC_BASE,
+    virtio_block: None,
+    block_irq: BLOCK_IRQ,
+    disk: true,
+    initrd: None,
+    mmio: MMIO,
+    svpbmt: false,
+};
+
+static PLATFORM: Once<Platform> = Once::new();
+
+pub fn platform() -> &'static Platform {
+    PLATFORM.get().unwrap_or(&BOARD)
+}
+
+pub fn clock_freq() -> usize {
+    platform().clock_freq
+}
+
+/// Describe the machine from `tree`, if there is one.
+///
+/// Must run before paging is enabled: virtio devices are told apart by
+/// reading their registers. `ram_end` is the end of the RAM range the
+/// kernel image lies in.
+pub fn init(tree: Option<&DeviceTree>) {
+    extern "C" {
+        fn skernel();
+    }
+
+    let Some(tree) = tree else {
+        return;
+    };
+    let kernel = skernel as usize;
+    let ram_end = tree
+        .ram
+        .iter()
+        .find(|&&(start, size)| start <= kernel && kernel < start + size)
+        .map_or(BOARD.ram_end, |&(start, size)| start + size);
+    let plic = tree.find(&["riscv,plic0", "sifive,plic-1.0.0"]).and_then(|device| device.reg);
+    let uart = tree
+        .find(&["ns16550a"])
+        .and_then(|device| Some((device.reg?, device.irq?)));
+    let rtc = tree.find(&["google,goldfish-rtc"]).and_then(|device| device.reg);
+    let virtio_block = tree
+        .devices
+        .iter()
+        .filter(|device| device.is_compatible("virtio,mmio"))
+        .find_map(|device| {
+            let (base, size) = device.reg?;
+            is_virtio_block(base).then_some(((base, size), device.irq))
+        });
+
+    // the windows of the tree first, the board fills in what it doesn't name
+    let mut mmio: Vec<(usize, usize)> = [plic, uart.map(|(reg, _)| reg), rtc, virtio_block.map(|(reg, _)| reg)]
+        .into_iter()
+        .flatten()
+        .map(|(start, size)| (start & !(PAGE_SIZE - 1), (size + start % PAGE_SIZE + PAGE_SIZE - 1) & !(PAGE_SIZE - 1)))
+        .collect();
+    let found = mmio.len();
+    for &(start, size) in MMIO {
+        if !mmio[..found].iter().any(|&(other, other_size)| start < other + other_size && other < start + size) {
+            mmio.push((start, size));
+        }
+    }
+
+    let platform = PLATFORM.call_once(|| Platform {
+        clock_freq: tree.timebase_frequency.unwrap_or(BOARD.clock_freq),
+        ram_end,
+        plic_base: plic.map_or(BOARD.plic_base, |(base, _)| base),
+        console_uart: uart.map(|((base, _), irq)| (base, irq)).or(BOARD.console_uart),
+        rtc_base: rtc.map(|(base, _)| base).or(BOARD.rtc_base),
+        virtio_block: virtio_block.map(|((base, _), _)| base),
+        block_irq: virtio_block.and_then(|(_, irq)| irq).or(BOARD.block_irq),
+        disk: virtio_block.is_some() || !DISK_IN_TREE,
+        initrd: tree.initrd,
+        mmio: mmio.leak(),
+        svpbmt: tree
+            .isa
+            .as_deref()
+            .is_some_and(|isa| isa.split('_').any(|extension| extension == "svpbmt")),
+    });
+    log::info!("platform: {:x?}", platform);
+}
+
+/// Whether the virtio-mmio slot at `base` holds a block device, slots
+/// without a device read a device ID of 0.
+fn is_virtio_block(base: usize) -> bool {
+    let read = |offset: usize| unsafe { core::ptr::read_volatile((base + offset) as *const u32) };
+    read(0x00) == VIRTIO_MAGIC && read(0x08) == VIRTIO_DEVICE_BLOCK
+}
@@ -18,10 +18,48 @@
 pub const CLOCK_FREQ: usize = 12_500_000;
 
 pub type BlockDeviceImpl = crate::drivers::block::VirtIOBlock;
+/// Name of the root block device, as `/proc/iosched` shows it
+pub const BLOCK_DEVICE_NAME: &str = "vda";
+/// The tree lists the virtio block device, there is no disk without it
+pub const DISK_IN_TREE: bool = true;
+
+/// Where `make run CMDLINE="..."` loads the kernel command line, in the
+/// RAM between the SBI and the kernel, left zeroed otherwise
+pub const CMDLINE_PA: Option<usize> = Some(0x8010_0000);
+
+/// PLIC source of the virtio block device
+pub const BLOCK_IRQ: Option<u32> = Some(1);
 
 
 /// [start, size]
 pub const MMIO: &[(usize, usize)] = &[
     (0x0010_0000, 0x00_2000), // VIRT_TEST/RTC  in virt machine
+    (0x0c00_0000, 0x40_0000), // PLIC in virt machine
+    (0x1000_0000, 0x00_1000), // UART0 (16550a) in virt machine
     (0x1000_1000, 0x00_1000), // Virtio Block in virt machine
-];
\ No newline at end of file
+];
+
+pub const PLIC_BASE: usize = 0x0c00_0000;
+
+/// Goldfish RTC, the time of day at boot
+pub const RTC_BASE: Option<usize> = Some(0x0010_1000);
+
+/// `(base, PLIC source)` of the 16550a the console input comes from
+pub const CONSOLE_UART: Option<(usize, u32)> = Some((0x1000_0000, 10));
+
+/// Physical ranges devices may access directly: all of the RAM, however
+/// much the device tree reports
+pub const DMA_REGIONS: &[(usize, usize)] = &[
+    (0x8000_0000, usize::MAX - 0x8000_0000),
+];
+
+/// QEMU has no cache to maintain
+pub const DMA_ALIGN: usize = 1;
+
+/// DMA is coherent on QEMU.
+#[inline(always)]
+pub fn dcache_clean(_pa: usize, _len: usize) {}
+
+/// DMA is coherent on QEMU.
+#[inline(always)]
+pub fn dcache_invalidate(_pa: usize, _len: usize) {}
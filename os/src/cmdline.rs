@@ -0,0 +1,141 @@
+//! Kernel command line
+//!
+//! Read once at boot, from `bootargs` of the `/chosen` node of the device
+//! tree, or else from the NUL terminated string a loader left at the
+//! board's `CMDLINE_PA` (`make run CMDLINE="..."` on QEMU). Options are
+//! whitespace separated `key=value`, a bare `key` meaning `key=1`, the
+//! last one of a key wins:
+//! - `log=error|warn|info|debug`: the log level, see `io::logging`
+//! - `log.<module>=<level>`: the log level of the modules under a path
+//!   prefix, e.g. `log.mm=warn`, see `io::klog`
+//! - `init=<path>`: the first user program, `/init_proc` by default
+//! - `sched=fifo|rr|priority`: the scheduling policy, see `task::scheduler`
+//! - `deterministic=1`: the test mode, reproducible scheduling, see
+//!   `task::determinism`
+//!
+//! Modules query [`get`] during their init. An option missing from the
+//! command line falls back to the build environment (`LOG`, `SCHEDULER`,
+//! `DETERMINISTIC`), so a kernel booted without one behaves as built.
+//! The command line is readable from `/proc/cmdline`.
+
+use alloc::{string::String, vec::Vec};
+use os_macros::{kernel_test, monitor_command};
+use spin::Once;
+
+use crate::{boards::CMDLINE_PA, println};
+
+/// Longest command line read from `CMDLINE_PA`
+const CMDLINE_MAX: usize = 4096;
+
+/// Options the kernel knows, the others are kept and warned about
+const KNOWN_KEYS: [&str; 4] = ["deterministic", "init", "log", "sched"];
+/// Options taking a `.suffix`, see [`scoped`]
+const KNOWN_SCOPES: [&str; 1] = ["log"];
+
+/// Values of the options a command line doesn't give, from the build
+/// environment
+const BUILD_DEFAULTS: [(&str, Option<&str>); 3] = [
+    ("deterministic", option_env!("DETERMINISTIC")),
+    ("log", option_env!("LOG")),
+    ("sched", option_env!("SCHEDULER")),
+];
+
+struct CmdLine {
+    raw: String,
+    /// `(key, value)` in the order given
+    options: Vec<(String, String)>,
+}
+
+static CMDLINE: Once<CmdLine> = Once::new();
+
+/// Split `raw` into its `(key, value)` options.
+fn parse(raw: &str) -> Vec<(String, String)> {
+    raw.split_whitespace()
+        .map(|option| match option.split_once('=') {
+            Some((key, value)) => (String::from(key), String::from(value)),
+            None => (String::from(option), String::from("1")),
+        })
+        .collect()
+}
+
+/// The string a loader left at `pa`, read by physical address.
+fn read_at(pa: usize) -> String {
+    let bytes = unsafe { core::slice::from_raw_parts(pa as *const u8, CMDLINE_MAX) };
+    let len = bytes.iter().position(|&byte| byte == 0).unwrap_or(CMDLINE_MAX);
+    String::from_utf8_lossy(&bytes[..len]).into_owned()
+}
+
+/// Take the command line from `bootargs`, or else from `CMDLINE_PA`.
+///
+/// Must run once the heap is up but before paging is enabled, like the
+/// device tree is read.
+pub fn init(bootargs: Option<&str>) {
+    let raw = match bootargs {
+        Some(bootargs) if !bootargs.trim().is_empty() => String::from(bootargs),
+        _ => CMDLINE_PA.map(read_at).unwrap_or_default(),
+    };
+    let cmdline = CMDLINE.call_once(|| CmdLine { options: parse(&raw), raw });
+    log::info!("cmdline: `{}`", cmdline.raw);
+    for (key, _) in cmdline.options.iter().filter(|(key, _)| !is_known(key)) {
+        log::warn!("cmdline: unknown option `{}`", key);
+    }
+}
+
+fn is_known(key: &str) -> bool {
+    KNOWN_KEYS.contains(&key)
+        || key.split_once('.').is_some_and(|(scope, _)| KNOWN_SCOPES.contains(&scope))
+}
+
+/// The options `scope.<suffix>=value`, as `(suffix, value)` in the order
+/// given
+pub fn scoped(scope: &'static str) -> impl Iterator<Item = (&'static str, &'static str)> {
+    CMDLINE.get().into_iter().flat_map(move |cmdline| {
+        cmdline.options.iter().filter_map(move |(key, value)| {
+            let (other, suffix) = key.split_once('.')?;
+            (other == scope).then_some((suffix, value.as_str()))
+        })
+    })
+}
+
+/// The value of option `key`, from the command line or else the build
+/// environment.
+pub fn get(key: &str) -> Option<&'static str> {
+    let given = CMDLINE.get().and_then(|cmdline| {
+        cmdline.options.iter().rev().find(|(other, _)| other == key).map(|(_, value)| value.as_str())
+    });
+    given.or_else(|| {
+        BUILD_DEFAULTS
+            .iter()
+            .find(|(other, _)| *other == key)
+            .and_then(|(_, value)| *value)
+            .filter(|value| !value.is_empty())
+    })
+}
+
+/// Whether option `key` is set, to `1` or `true`
+pub fn flag(key: &str) -> bool {
+    matches!(get(key), Some("1") | Some("true"))
+}
+
+/// The command line as given, empty without one
+pub fn raw() -> &'static str {
+    CMDLINE.get().map_or("", |cmdline| cmdline.raw.as_str())
+}
+
+#[monitor_command(name = "cmdline", help = "Show the kernel command line and the options in effect")]
+fn cmdline_command(_args: &[&str]) {
+    println!("{}", raw());
+    for key in KNOWN_KEYS {
+        println!("  {} = {}", key, get(key).unwrap_or("(unset)"));
+    }
+}
+
+#[kernel_test]
+fn cmdline_parse_test() {
+    let options = parse("  log=debug init=/bin/sh  quiet sched=rr=x ");
+    let options: Vec<(&str, &str)> = options.iter().map(|(key, value)| (key.as_str(), value.as_str())).collect();
+    assert_eq!(options, [("log", "debug"), ("init", "/bin/sh"), ("quiet", "1"), ("sched", "rr=x")]);
+    assert!(parse(" \t\n").is_empty());
+    assert!(is_known("log.mm::tlb"));
+    assert!(!is_known("logs.mm"));
+}
@@ -32,14 +32,50 @@ pub const VALID_KERNEL_HIGH_BITS: usize = HIGH_BITS_MASK;
 
 
 pub const USER_STACK_SIZE: usize = 1 * PAGE_SIZE;      // Size of the user stack (8 KiB)
+/// A user stack grows down on faults up to this size, an unmapped guard
+/// page lies right below
+pub const USER_STACK_MAX_SIZE: usize = 16 * PAGE_SIZE;
 pub const GUARD_PAGE_SIZE: usize = 2 * PAGE_SIZE;      // Size of guard page
+/// Unmapped gap kept around every area placed by `MemorySet::map_anonymous`,
+/// 0 disables it
+pub const USER_GUARD_GAP: usize = 1 * PAGE_SIZE;
+/// User stack slots reserved above the ELF image, one per thread
+pub const MAX_USER_STACKS: usize = 64;
+/// Load address of position independent executables
+pub const PIE_BASE: usize = 0x1000_0000;
+/// Load address of flat binaries, where the user programs are linked
+pub const FLAT_BASE: usize = 0x1_0000;
+/// Where `MemorySet::map_anonymous` starts looking for room
+pub const USER_MMAP_BASE: usize = 0x10_0000_0000;
+/// Randomize the layout of every user address space built by `exec`,
+/// unless the task asked not to with `personality(ADDR_NO_RANDOMIZE)`
+pub const ASLR: bool = true;
+/// With `ASLR`, a position independent executable is loaded up to this
+/// many pages above `PIE_BASE`
+pub const ASLR_PIE_PAGES: usize = 1 << 16;
+/// With `ASLR`, the user stack slots start up to this many pages above
+/// the image
+pub const ASLR_STACK_PAGES: usize = 1 << 12;
+/// With `ASLR`, `MemorySet::map_anonymous` starts looking up to this
+/// many pages above `USER_MMAP_BASE`
+pub const ASLR_MMAP_PAGES: usize = 1 << 20;
 pub const KERNEL_STACK_SIZE: usize = 4 * PAGE_SIZE;    // Size of the kernel stack (8 KiB)
 
+/// Default length of the time slices of the preemptive schedulers, can be
+/// changed at run time with the `kernel.sched_time_slice_ms` sysctl
+pub const TIME_SLICE_MS: usize = 16;
+
+/// A hart which neither scheduled nor ran user code for this many seconds
+/// is locked up in the kernel, see `processor::watchdog`. Can be changed
+/// at run time with the `kernel.watchdog_thresh` sysctl, 0 disables it
+pub const WATCHDOG_THRESH_S: usize = 10;
+
 // The half of k210 SRAM
 pub const KERNEL_HEAP_SIZE: usize = 0x30_0000;
 // pub const KERNEL_HEAP_SIZE: usize = 0x10_00;
 
-// The memory size of K210 is 8MiB
+// The memory size of K210 is 8MiB, where RAM ends without a device tree
+// (see `boards::platform`), and the start of the trap contexts
 pub const PHYSTOP: usize = 0x80800000;
 
 
@@ -60,6 +96,9 @@ pub const PPN_MASK: usize = (1 << PPN_WIDTH) - 1;
 pub const VPN_MASK: usize = (1 << VPN_WIDTH) - 1;
 pub const OFFSET_MASK: usize = PAGE_SIZE - 1;
 pub const SATP_PPN_MASK: usize = (1 << SATP_ROOT_PPN_BITS) - 1;
+/// The ASID field sits right above the root PPN
+pub const SATP_ASID_BITS: usize = 16;
+pub const SATP_ASID_MASK: usize = (1 << SATP_ASID_BITS) - 1;
 
 
 
@@ -70,7 +109,13 @@ pub const TRAMPOLINE: usize = USER_HIGH_VA - PAGE_SIZE;  // 0xFFFFFFFFBFFFF000
 
 #[allow(unused)]
 pub const USYSCALL: usize = TRAMPOLINE - PAGE_SIZE;     // 0xFFFFFFFFBFFFD000
-pub const KERNEL_STACK_BASE: usize = USYSCALL - PAGE_SIZE;
+/// User page with the stub signal handlers return into, it calls `sigreturn`.
+/// Takes the `USYSCALL` slot, unused otherwise
+pub const SIGRETURN_TRAMPOLINE: usize = USYSCALL;
+/// Kernel stacks go down from here, each in the upper half of a slot of
+/// twice its size, the canary page right below, see `KernelStackGuard`
+pub const KERNEL_STACK_BASE: usize = (USYSCALL - PAGE_SIZE) & !(KERNEL_STACK_SLOT - 1);
+pub const KERNEL_STACK_SLOT: usize = 2 * KERNEL_STACK_SIZE;
 
 pub const TRAP_CONTEXT_START: usize = PHYSTOP;
 
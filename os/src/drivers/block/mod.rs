@@ -1,16 +1,84 @@
+mod queue;
+pub mod ramdisk;
+#[cfg(feature = "board_k210")]
 mod sdcard;
+#[cfg(feature = "board_qemu")]
 mod virtio_blk;
 
+pub use queue::{IoClass, IoPriority, QueueStatsSnapshot, QueuedBlockDevice};
+pub use ramdisk::RamDisk;
+#[cfg(feature = "board_k210")]
 pub use sdcard::SDCardWrapper;
+#[cfg(feature = "board_qemu")]
 pub use virtio_blk::VirtIOBlock;
 
-use crate::{boards::BlockDeviceImpl, println};
-use alloc::sync::Arc;
+use super::plic;
+use crate::{boards::{platform::platform, BlockDeviceImpl, BLOCK_DEVICE_NAME}, print, println};
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::sync::atomic::{AtomicBool, Ordering};
 use easy_fs::BlockDevice;
 use lazy_static::*;
+use os_macros::{monitor_command, shutdown_hook};
 
 lazy_static! {
-    pub static ref BLOCK_DEVICE: Arc<dyn BlockDevice> = Arc::new(BlockDeviceImpl::new());
+    /// The root device behind its request queue
+    pub static ref BLOCK_QUEUE: Arc<QueuedBlockDevice> = Arc::new(root_device());
+    pub static ref BLOCK_DEVICE: Arc<dyn BlockDevice> = BLOCK_QUEUE.clone();
+}
+
+/// The disk of the board, or else the RAM disk if there is an image for it.
+fn root_device() -> QueuedBlockDevice {
+    if !platform().disk {
+        match RamDisk::initrd() {
+            Some(ramdisk) => {
+                log::info!("block: no disk, the root is a {} blocks ram disk", ramdisk.blocks());
+                return QueuedBlockDevice::new("ram0", Arc::new(ramdisk));
+            }
+            None => log::warn!("block: no disk and no ram disk image, trying the disk anyway"),
+        }
+    }
+    QueuedBlockDevice::new(BLOCK_DEVICE_NAME, Arc::new(BlockDeviceImpl::new()))
+}
+
+/// Every device behind a request queue, the root one only for now
+pub fn queues() -> Vec<Arc<QueuedBlockDevice>> {
+    vec![BLOCK_QUEUE.clone()]
+}
+
+/// Set once the completion interrupt of the root device is routed
+static IRQ_ROUTED: AtomicBool = AtomicBool::new(false);
+
+/// Whether requests of the root device may sleep until their completion
+/// interrupt, instead of polling for it.
+pub fn irq_routed() -> bool {
+    IRQ_ROUTED.load(Ordering::Acquire)
+}
+
+/// Route the completion interrupt of the root device, if the board has one.
+pub fn init() {
+    if let Some(irq) = platform().block_irq {
+        plic::register_irq(irq, plic::DEFAULT_PRIORITY, handle_irq);
+        IRQ_ROUTED.store(true, Ordering::Release);
+    }
+}
+
+fn handle_irq() {
+    BLOCK_QUEUE.handle_irq();
+}
+
+/// Set at shutdown, no block request may be issued afterwards.
+static QUIESCED: AtomicBool = AtomicBool::new(false);
+
+pub fn is_quiesced() -> bool {
+    QUIESCED.load(Ordering::Acquire)
+}
+
+/// Submitters wait for their requests, so none is in flight here:
+/// quiescing only means refusing new ones.
+/// Runs after the block cache has been synced.
+#[shutdown_hook(priority = 100)]
+fn quiesce_block_device() {
+    QUIESCED.store(true, Ordering::Release);
 }
 
 #[allow(unused)]
@@ -28,3 +96,20 @@ pub fn block_device_test() {
     }
     println!("block device test passed!");
 }
+
+#[monitor_command(name = "blkdump", help = "Hex dump a block of the root device: blkdump <block_id>")]
+fn blkdump_command(args: &[&str]) {
+    let Some(block_id) = args.get(1).and_then(|arg| arg.parse::<usize>().ok()) else {
+        println!("usage: blkdump <block_id>");
+        return;
+    };
+    let mut buffer = [0u8; 512];
+    BLOCK_DEVICE.read_block(block_id, &mut buffer);
+    for (line, chunk) in buffer.chunks(16).enumerate() {
+        print!("{:04x}:", line * 16);
+        for byte in chunk {
+            print!(" {:02x}", byte);
+        }
+        println!("");
+    }
+}
@@ -0,0 +1,403 @@
+//! Block request queue with I/O priorities
+//!
+//! Every request carries the [`IoPriority`] of the task issuing it. The
+//! block cache calls the device synchronously in the caller's context, so
+//! the priority is simply read from the current task at submission.
+//!
+//! The [`Elevator`] serves the best class first (real-time, best-effort,
+//! idle), then the best level, then in block order: it sweeps up from the
+//! block after the last one dispatched, and wraps around to the lowest
+//! block once nothing is left above (C-LOOK), so the head of a disk
+//! moves one way. A request waiting longer than its class deadline is
+//! aged to the front, so nothing starves.
+//!
+//! The request picked is dispatched with the pending requests of the
+//! same direction which extend its range of blocks, up to [`MAX_MERGE`]
+//! of them: a batch. Its submitters all go to the device at once, a
+//! virtio queue takes them together, and the next batch waits for the
+//! last of them to complete.
+//!
+//! Requests wait on a [`WaitQueue`] for their turn. In atomic context (or
+//! before the first task runs) sleeping is illegal: such requests skip the
+//! queue. Today easy-fs serializes all block access behind its cache lock,
+//! so the ordering only matters once several submitters run concurrently.
+//!
+//! The depth of the queue and how many requests were merged are in
+//! `/proc/iosched`, one line per device.
+
+use alloc::{sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use easy_fs::BlockDevice;
+use os_macros::{kernel_test, monitor_command};
+
+use crate::{
+    mm::gfp::in_atomic_context,
+    println,
+    sync::spin::mutex::IRQSpinLock,
+    task::{current_task, WaitQueue},
+    timer::get_time_ms,
+};
+
+type Mutex<T> = IRQSpinLock<T>;
+
+/// Requests dispatched together at most
+pub const MAX_MERGE: usize = 16;
+
+/// Scheduling class, ordered from most to least urgent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum IoClass {
+    RealTime = 1,
+    BestEffort = 2,
+    Idle = 3,
+}
+
+/// A class and a level, 0 being the most urgent level
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IoPriority {
+    pub class: IoClass,
+    pub level: u8,
+}
+
+impl IoPriority {
+    const CLASS_SHIFT: usize = 13;
+    const LEVELS: u8 = 8;
+
+    pub const DEFAULT: IoPriority = IoPriority { class: IoClass::BestEffort, level: 4 };
+
+    /// Decode the Linux `ioprio` encoding, `class << 13 | level`.
+    ///
+    /// Class 0 (none) maps to the default.
+    pub fn from_raw(raw: usize) -> Option<Self> {
+        let level = (raw & ((1 << Self::CLASS_SHIFT) - 1)) as u8;
+        let class = match raw >> Self::CLASS_SHIFT {
+            0 => return Some(Self::DEFAULT),
+            1 => IoClass::RealTime,
+            2 => IoClass::BestEffort,
+            3 => IoClass::Idle,
+            _ => return None,
+        };
+        (level < Self::LEVELS).then_some(IoPriority { class, level })
+    }
+
+    pub fn to_raw(self) -> usize {
+        (self.class as usize) << Self::CLASS_SHIFT | self.level as usize
+    }
+
+    /// How long a request of this class may wait before it is aged to the front
+    fn deadline_ms(self) -> usize {
+        match self.class {
+            IoClass::RealTime => 10,
+            IoClass::BestEffort => 100,
+            IoClass::Idle => 1000,
+        }
+    }
+}
+
+impl Default for IoPriority {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Direction of a request, only requests of one direction merge
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Ticket {
+    seq: usize,
+    priority: IoPriority,
+    /// `get_time_ms()` past which the request jumps the queue
+    deadline: usize,
+    direction: Direction,
+    block_id: usize,
+}
+
+/// Picks which pending requests run next
+pub struct Elevator {
+    pending: Vec<Ticket>,
+    next_seq: usize,
+    /// Tickets of the batch dispatched whose submitters didn't go yet
+    dispatched: Vec<usize>,
+    /// Requests of the batch dispatched not completed yet
+    in_flight: usize,
+    /// Block after the last one dispatched, where the sweep goes on
+    head: usize,
+}
+
+/// What [`Elevator::dispatch`] let through
+#[derive(Debug, PartialEq, Eq)]
+struct Batch {
+    /// Requests merged into the one picked
+    merged: usize,
+    /// The one picked was aged to the front
+    aged: bool,
+}
+
+impl Elevator {
+    pub const fn new() -> Self {
+        Self { pending: Vec::new(), next_seq: 0, dispatched: Vec::new(), in_flight: 0, head: 0 }
+    }
+
+    fn push(&mut self, priority: IoPriority, direction: Direction, block_id: usize, now: usize) -> usize {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.pending.push(Ticket { seq, priority, deadline: now + priority.deadline_ms(), direction, block_id });
+        seq
+    }
+
+    /// Order of service, deadlines aside: class, level, then the sweep
+    /// from `head` up, wrapping around
+    fn sweep_key(&self, ticket: &Ticket) -> (IoClass, u8, bool, usize, usize) {
+        let priority = ticket.priority;
+        (priority.class, priority.level, ticket.block_id < self.head, ticket.block_id, ticket.seq)
+    }
+
+    /// The ticket to serve at `now`, and whether it was picked by aging.
+    fn pick(&self, now: usize) -> Option<(usize, bool)> {
+        let best = self.pending.iter().min_by_key(|ticket| self.sweep_key(ticket));
+        // the oldest expired request first
+        if let Some(expired) = self.pending.iter().filter(|ticket| ticket.deadline <= now).min_by_key(|ticket| ticket.seq) {
+            return Some((expired.seq, best.map(|best| best.seq) != Some(expired.seq)));
+        }
+        best.map(|ticket| (ticket.seq, false))
+    }
+
+    /// Let the request picked at `now` through, with the pending requests
+    /// of its direction extending its range, both ways.
+    fn dispatch(&mut self, now: usize) -> Option<Batch> {
+        let (seq, aged) = self.pick(now)?;
+        let lead = self.pending.remove(self.pending.iter().position(|ticket| ticket.seq == seq)?);
+        let (mut first, mut last) = (lead.block_id, lead.block_id);
+        self.dispatched.push(lead.seq);
+        while self.dispatched.len() < MAX_MERGE {
+            let Some(index) = self.pending.iter().position(|ticket| {
+                ticket.direction == lead.direction
+                    && (ticket.block_id == last + 1 || ticket.block_id.checked_add(1) == Some(first))
+            }) else {
+                break;
+            };
+            let ticket = self.pending.remove(index);
+            if ticket.block_id == last + 1 {
+                last = ticket.block_id;
+            } else {
+                first = ticket.block_id;
+            }
+            self.dispatched.push(ticket.seq);
+        }
+        self.head = last + 1;
+        self.in_flight = self.dispatched.len();
+        Some(Batch { merged: self.dispatched.len() - 1, aged })
+    }
+
+    /// Whether `seq` was dispatched, its submitter may go then.
+    fn take_dispatched(&mut self, seq: usize) -> bool {
+        match self.dispatched.iter().position(|&dispatched| dispatched == seq) {
+            Some(index) => {
+                self.dispatched.swap_remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// A request of the batch completed, returns whether it was the last.
+    fn complete(&mut self) -> bool {
+        self.in_flight -= 1;
+        self.in_flight == 0
+    }
+}
+
+/// Per-class counters, indexed by `IoClass as usize - 1`
+struct QueueStats {
+    dispatched: [AtomicUsize; 3],
+    batches: AtomicUsize,
+    merged: AtomicUsize,
+    max_depth: AtomicUsize,
+    aged: AtomicUsize,
+    bypassed: AtomicUsize,
+}
+
+/// The counters of a queue, for `/proc/iosched`
+pub struct QueueStatsSnapshot {
+    pub name: &'static str,
+    /// Requests waiting now
+    pub depth: usize,
+    pub max_depth: usize,
+    /// Requests served, per class
+    pub dispatched: [usize; 3],
+    pub batches: usize,
+    /// Requests which went along with another one
+    pub merged: usize,
+    pub aged: usize,
+    pub bypassed: usize,
+}
+
+/// A block device whose requests go through an [`Elevator`]
+pub struct QueuedBlockDevice {
+    name: &'static str,
+    device: Arc<dyn BlockDevice>,
+    elevator: Mutex<Elevator>,
+    /// Requests waiting for their turn
+    waiters: WaitQueue,
+    stats: QueueStats,
+}
+
+impl QueuedBlockDevice {
+    pub fn new(name: &'static str, device: Arc<dyn BlockDevice>) -> Self {
+        const ZERO: AtomicUsize = AtomicUsize::new(0);
+        Self {
+            name,
+            device,
+            elevator: Mutex::new(Elevator::new()),
+            waiters: WaitQueue::new(),
+            stats: QueueStats {
+                dispatched: [ZERO; 3],
+                batches: ZERO,
+                merged: ZERO,
+                max_depth: ZERO,
+                aged: ZERO,
+                bypassed: ZERO,
+            },
+        }
+    }
+
+    pub fn stats(&self) -> QueueStatsSnapshot {
+        let stats = &self.stats;
+        QueueStatsSnapshot {
+            name: self.name,
+            depth: self.elevator.lock().pending.len(),
+            max_depth: stats.max_depth.load(Ordering::Relaxed),
+            dispatched: stats.dispatched.each_ref().map(|count| count.load(Ordering::Relaxed)),
+            batches: stats.batches.load(Ordering::Relaxed),
+            merged: stats.merged.load(Ordering::Relaxed),
+            aged: stats.aged.load(Ordering::Relaxed),
+            bypassed: stats.bypassed.load(Ordering::Relaxed),
+        }
+    }
+
+    fn submit(&self, direction: Direction, block_id: usize, request: impl FnOnce(&dyn BlockDevice)) {
+        let Some(task) = current_task().filter(|_| !in_atomic_context()) else {
+            self.stats.bypassed.fetch_add(1, Ordering::Relaxed);
+            return request(self.device.as_ref());
+        };
+        let priority = task.lock().io_priority;
+
+        let mut elevator = self.elevator.lock();
+        let seq = elevator.push(priority, direction, block_id, get_time_ms());
+        self.stats.max_depth.fetch_max(elevator.pending.len(), Ordering::Relaxed);
+        while !elevator.take_dispatched(seq) {
+            if elevator.in_flight == 0 {
+                if let Some(batch) = elevator.dispatch(get_time_ms()) {
+                    self.stats.batches.fetch_add(1, Ordering::Relaxed);
+                    self.stats.merged.fetch_add(batch.merged, Ordering::Relaxed);
+                    if batch.aged {
+                        self.stats.aged.fetch_add(1, Ordering::Relaxed);
+                    }
+                    // the submitters of the batch sleeping go too
+                    if elevator.dispatched != [seq] {
+                        self.waiters.wake_all();
+                    }
+                    continue;
+                }
+            }
+            self.waiters.sleep_on_with(move || drop(elevator));
+            elevator = self.elevator.lock();
+        }
+        drop(elevator);
+
+        request(self.device.as_ref());
+        self.stats.dispatched[priority.class as usize - 1].fetch_add(1, Ordering::Relaxed);
+
+        if self.elevator.lock().complete() {
+            self.waiters.wake_all();
+        }
+    }
+}
+
+impl BlockDevice for QueuedBlockDevice {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        self.submit(Direction::Read, block_id, |device| device.read_block(block_id, buf));
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        self.submit(Direction::Write, block_id, |device| device.write_block(block_id, buf));
+    }
+
+    /// Completions bypass the queue, the request being served waits on them
+    fn handle_irq(&self) {
+        self.device.handle_irq();
+    }
+}
+
+#[monitor_command(name = "iosched", help = "Show block requests served per I/O class")]
+fn iosched_command(_args: &[&str]) {
+    let stats = super::BLOCK_QUEUE.stats();
+    for (class, name) in ["realtime", "best-effort", "idle"].iter().enumerate() {
+        println!("{:<12} {}", name, stats.dispatched[class]);
+    }
+    println!("batches: {}, merged: {}", stats.batches, stats.merged);
+    println!("depth: {}, max: {}", stats.depth, stats.max_depth);
+    println!("aged to the front: {}", stats.aged);
+    println!("bypassed (atomic context): {}", stats.bypassed);
+}
+
+#[kernel_test]
+fn elevator_order_test() {
+    let realtime = IoPriority { class: IoClass::RealTime, level: 0 };
+    let idle = IoPriority { class: IoClass::Idle, level: 7 };
+    let mut elevator = Elevator::new();
+
+    let idle_seq = elevator.push(idle, Direction::Read, 0, 0);
+    let best_effort_seq = elevator.push(IoPriority::DEFAULT, Direction::Read, 10, 0);
+    let realtime_seq = elevator.push(realtime, Direction::Read, 20, 0);
+    assert_eq!(elevator.pick(0), Some((realtime_seq, false)));
+    elevator.pending.retain(|ticket| ticket.seq != realtime_seq);
+    assert_eq!(elevator.pick(0), Some((best_effort_seq, false)));
+
+    // past its deadline the idle request goes first
+    let late = idle.deadline_ms();
+    assert_eq!(elevator.pick(late), Some((idle_seq, true)));
+
+    assert_eq!(IoPriority::from_raw(IoPriority::DEFAULT.to_raw()), Some(IoPriority::DEFAULT));
+    assert_eq!(IoPriority::from_raw(4 << 13), None);
+}
+
+#[kernel_test]
+fn elevator_merge_test() {
+    let mut elevator = Elevator::new();
+    let priority = IoPriority::DEFAULT;
+    elevator.head = 5;
+    let below = elevator.push(priority, Direction::Read, 3, 0);
+    let lead = elevator.push(priority, Direction::Read, 7, 0);
+    let after = elevator.push(priority, Direction::Read, 8, 0);
+    let before = elevator.push(priority, Direction::Read, 6, 0);
+    let write = elevator.push(priority, Direction::Write, 9, 0);
+
+    // the sweep goes on up from 5: 6 to 8 are one range, the write and
+    // block 3 are left
+    assert_eq!(elevator.pick(0), Some((before, false)));
+    assert_eq!(elevator.dispatch(0), Some(Batch { merged: 2, aged: false }));
+    assert_eq!(elevator.in_flight, 3);
+    assert!(elevator.take_dispatched(lead) && elevator.take_dispatched(after) && elevator.take_dispatched(before));
+    assert!(!elevator.take_dispatched(below));
+    assert!(!elevator.complete() && !elevator.complete() && elevator.complete());
+
+    // then the write above, then the wrap around to block 3
+    assert_eq!(elevator.dispatch(0), Some(Batch { merged: 0, aged: false }));
+    assert!(elevator.take_dispatched(write));
+    assert_eq!(elevator.pick(0), Some((below, false)));
+
+    // a batch stops at `MAX_MERGE` requests
+    let mut elevator = Elevator::new();
+    for block_id in 0..MAX_MERGE + 1 {
+        elevator.push(priority, Direction::Write, block_id, 0);
+    }
+    assert_eq!(elevator.dispatch(0), Some(Batch { merged: MAX_MERGE - 1, aged: false }));
+    assert_eq!(elevator.pending.len(), 1);
+}
@@ -0,0 +1,108 @@
+//! RAM disk
+//!
+//! A block device over memory holding a file system image, the root device
+//! when the board has no disk (see [`super::root_device`]). The image is:
+//! - linked into the kernel with the `initramfs` feature, from the file the
+//!   `INITRAMFS_IMG` environment variable names at build time
+//!   (`make run INITRAMFS=1` packs `fs.img` in and runs without a disk)
+//! - or else loaded by the bootloader, at the `linux,initrd-start` and
+//!   `linux,initrd-end` of `/chosen`. It is reserved from the frame
+//!   allocator by `memmap`, and has to lie in the RAM the kernel maps
+//!
+//! Writes land in the image, they are lost at shutdown.
+
+use os_macros::kernel_test;
+
+use super::BlockDevice;
+use crate::{boards::platform::platform, sync::spin::mutex::IRQSpinLock};
+use easy_fs::BLOCK_SZ;
+
+type Mutex<T> = IRQSpinLock<T>;
+
+pub struct RamDisk {
+    image: Mutex<&'static mut [u8]>,
+}
+
+impl RamDisk {
+    /// A disk of the whole blocks of `image`.
+    pub fn new(image: &'static mut [u8]) -> Self {
+        let len = image.len() / BLOCK_SZ * BLOCK_SZ;
+        Self { image: Mutex::new(&mut image[..len]) }
+    }
+
+    pub fn blocks(&self) -> usize {
+        self.image.lock().len() / BLOCK_SZ
+    }
+
+    /// The image linked in, or else the one the bootloader loaded
+    pub fn initrd() -> Option<Self> {
+        linked_image().or_else(loaded_image).map(Self::new)
+    }
+
+    fn block(image: &mut [u8], block_id: usize) -> &mut [u8] {
+        let blocks = image.len() / BLOCK_SZ;
+        assert!(block_id < blocks, "ram disk block {} past its {} blocks", block_id, blocks);
+        &mut image[block_id * BLOCK_SZ..(block_id + 1) * BLOCK_SZ]
+    }
+}
+
+impl BlockDevice for RamDisk {
+    fn read_block(&self, block_id: usize, buf: &mut [u8]) {
+        buf.copy_from_slice(Self::block(&mut self.image.lock(), block_id));
+    }
+
+    fn write_block(&self, block_id: usize, buf: &[u8]) {
+        Self::block(&mut self.image.lock(), block_id).copy_from_slice(buf);
+    }
+}
+
+#[cfg(feature = "initramfs")]
+fn linked_image() -> Option<&'static mut [u8]> {
+    const SIZE: usize = include_bytes!(env!("INITRAMFS_IMG")).len();
+    /// In `.data`, written to like a disk
+    #[repr(C, align(4096))]
+    struct Image([u8; SIZE]);
+    static mut IMAGE: Image = Image(*include_bytes!(env!("INITRAMFS_IMG")));
+    static TAKEN: core::sync::atomic::AtomicBool = core::sync::atomic::AtomicBool::new(false);
+
+    if TAKEN.swap(true, core::sync::atomic::Ordering::AcqRel) {
+        return None;
+    }
+    Some(unsafe { &mut (*core::ptr::addr_of_mut!(IMAGE)).0 })
+}
+
+#[cfg(not(feature = "initramfs"))]
+fn linked_image() -> Option<&'static mut [u8]> {
+    None
+}
+
+fn loaded_image() -> Option<&'static mut [u8]> {
+    extern "C" {
+        fn ekernel();
+    }
+    let (start, size) = platform().initrd?;
+    if start < ekernel as usize || start + size > platform().ram_end {
+        log::warn!("ramdisk: initrd [{:#x}, {:#x}) is outside the mapped RAM", start, start + size);
+        return None;
+    }
+    // reserved at boot, nothing else uses it
+    Some(unsafe { core::slice::from_raw_parts_mut(start as *mut u8, size) })
+}
+
+#[kernel_test]
+fn ramdisk_test() {
+    use alloc::{boxed::Box, vec};
+
+    // a partial block at the end is left out
+    let image: &'static mut [u8] = Box::leak(vec![0u8; 4 * BLOCK_SZ + 100].into_boxed_slice());
+    let disk = RamDisk::new(image);
+    assert_eq!(disk.blocks(), 4);
+
+    let written = [0x5au8; BLOCK_SZ];
+    let mut read = [0u8; BLOCK_SZ];
+    disk.write_block(3, &written);
+    disk.read_block(3, &mut read);
+    assert_eq!(read, written);
+    disk.read_block(2, &mut read);
+    assert_eq!(read, [0u8; BLOCK_SZ]);
+}
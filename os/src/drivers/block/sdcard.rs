@@ -5,6 +5,7 @@
 use crate::{println, sync::spin::mutex::IRQSpinLock};
 
 use super::BlockDevice;
+use crate::drivers::dma;
 use core::convert::TryInto;
 use k210_hal::prelude::*;
 use k210_pac::{Peripherals, SPI0};
@@ -735,11 +736,11 @@ fn init_sdcard() -> SDCard<SPIImpl<SPI0>> {
 
     let spi = peripherals.SPI0.constrain();
     let sd = SDCard::new(spi, SD_CS, SD_CS_GPIONUM);
-    let info = sd.init().unwrap();
-    let num_sectors = info.CardCapacity / 512;
+    let info = sd.init().expect("no usable SD card");
+    let num_sectors = info.CardCapacity / SEC_LEN as u64;
     assert!(num_sectors > 0);
 
-    println!("init sdcard!");
+    println!("[sdcard] {} sectors of {} bytes", num_sectors, SEC_LEN);
     sd
 }
 
@@ -747,21 +748,15 @@ pub struct SDCardWrapper(Mutex<SDCard<SPIImpl<SPI0>>>);
 
 impl SDCardWrapper {
     pub fn new() -> Self {
-        unsafe { Self(Mutex::new(init_sdcard())) }
+        Self(Mutex::new(init_sdcard()))
     }
 }
 
 impl BlockDevice for SDCardWrapper {
     fn read_block(&self, block_id: usize, buf: &mut [u8]) {
-        self.0
-            .lock()
-            .read_sector(buf, block_id as u32)
-            .unwrap();
+        dma::from_device(buf, |buf| self.0.lock().read_sector(buf, block_id as u32)).unwrap();
     }
     fn write_block(&self, block_id: usize, buf: &[u8]) {
-        self.0
-            .lock()
-            .write_sector(buf, block_id as u32)
-            .unwrap();
+        dma::to_device(buf, |buf| self.0.lock().write_sector(buf, block_id as u32)).unwrap();
     }
 }
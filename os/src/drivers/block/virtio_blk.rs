@@ -1,47 +1,107 @@
-use crate::mm::address::{PhysAddr, StepByOne, VirtAddr};
+//! VirtIO block device
+//!
+//! Requests are submitted without waiting, the device raises an interrupt
+//! once it used them. A task sleeps on the [`Event`] of its request's
+//! token (the head descriptor), [`VirtIOBlock::handle_irq`] pops the used
+//! tokens and notifies their events: other tasks run during the transfer.
+//!
+//! Until the interrupt is routed (see [`super::irq_routed`]), and in
+//! contexts that can't sleep, the submitter polls the used ring itself.
+//! Every completion goes through the used ring the same way, a poller
+//! and the interrupt handler never lose each other's requests.
+use crate::boards::platform::platform;
+use crate::mm::address::{PhysAddr, VirtAddr};
+use crate::mm::gfp::in_atomic_context;
 use crate::mm::memory_set::kernel_token;
 use crate::mm::page_table::PageTable;
+use crate::sync::event::Event;
+use crate::task::current_task;
 use crate::{mm::address::PhysPageNum, sync::spin::mutex::IRQSpinLock};
-use crate::mm::frame_allocator::{frame_alloc, frame_dealloc, FrameTracker};
-use super::BlockDevice;
+use crate::mm::frame_allocator::FrameRange;
+use super::{irq_routed, is_quiesced, BlockDevice};
 use alloc::vec::Vec;
 use lazy_static::*;
-use virtio_drivers::{Hal, VirtIOBlk, VirtIOHeader};
+use virtio_drivers::{BlkResp, RespStatus, VirtIOBlk, VirtIOHeader, Hal};
 
-#[allow(unused)]
+/// Slot of the device when the platform doesn't name one
 const VIRTIO0: usize = 0x10001000;
 
 type Mutex<T> = IRQSpinLock<T>;
 
-pub struct VirtIOBlock(Mutex<VirtIOBlk<'static, VirtioHal>>);
+pub struct VirtIOBlock {
+    blk: Mutex<VirtIOBlk<'static, VirtioHal>>,
+    /// Completion of the request holding each token
+    completions: Vec<Event>,
+}
 
 lazy_static! {
     
-    static ref QUEUE_FRAMES: Mutex<Vec<FrameTracker>> = unsafe { Mutex::new(Vec::new()) };
+    static ref QUEUE_FRAMES: Mutex<Vec<FrameRange>> = unsafe { Mutex::new(Vec::new()) };
 }
 
 impl BlockDevice for VirtIOBlock {
     fn read_block(&self, block_id: usize, buf: &mut [u8]) {
-        self.0
-            .lock()
-            .read_block(block_id, buf)
-            .expect("Error when reading VirtIOBlk");
+        assert!(!is_quiesced(), "block request after the device was quiesced");
+        let status = self.request(|blk, resp| unsafe { blk.read_block_nb(block_id, buf, resp) });
+        assert_eq!(status, RespStatus::Ok, "Error when reading VirtIOBlk");
     }
     fn write_block(&self, block_id: usize, buf: &[u8]) {
-        self.0
-            .lock()
-            .write_block(block_id, buf)
-            .expect("Error when writing VirtIOBlk");
+        assert!(!is_quiesced(), "block request after the device was quiesced");
+        let status = self.request(|blk, resp| unsafe { blk.write_block_nb(block_id, buf, resp) });
+        assert_eq!(status, RespStatus::Ok, "Error when writing VirtIOBlk");
+    }
+
+    fn handle_irq(&self) {
+        let mut blk = self.blk.lock();
+        blk.ack_interrupt();
+        self.complete_used(&mut blk);
     }
 }
 
 impl VirtIOBlock {
     #[allow(unused)]
     pub fn new() -> Self {
-        unsafe {
-            Self(Mutex::new(
-                VirtIOBlk::<VirtioHal>::new(&mut *(VIRTIO0 as *mut VirtIOHeader)).unwrap(),
-            ))
+        let base = platform().virtio_block.unwrap_or(VIRTIO0);
+        let blk = unsafe { VirtIOBlk::<VirtioHal>::new(&mut *(base as *mut VirtIOHeader)).unwrap() };
+        let completions = (0..blk.virt_queue_size()).map(|_| Event::new()).collect();
+        Self {
+            blk: Mutex::new(blk),
+            completions,
+        }
+    }
+
+    /// Submit with `submit` and wait for the device to use the request.
+    ///
+    /// The buffers handed to `submit` must outlive the request, they are
+    /// borrowed from the caller's frame which doesn't return before that.
+    fn request(
+        &self,
+        submit: impl FnOnce(&mut VirtIOBlk<'static, VirtioHal>, &mut BlkResp) -> virtio_drivers::Result<u16>,
+    ) -> RespStatus {
+        let mut resp = BlkResp::default();
+        let (token, seen) = {
+            let mut blk = self.blk.lock();
+            let token = submit(&mut blk, &mut resp).expect("VirtIOBlk queue full") as usize;
+            // the interrupt handler needs the device lock to complete it
+            (token, self.completions[token].generation())
+        };
+
+        let completion = &self.completions[token];
+        if irq_routed() && current_task().is_some() && !in_atomic_context() {
+            completion.wait(seen);
+        } else {
+            while completion.generation() == seen {
+                self.complete_used(&mut self.blk.lock());
+                core::hint::spin_loop();
+            }
+        }
+        resp.status()
+    }
+
+    /// Pop every used request and notify its submitter.
+    fn complete_used(&self, blk: &mut VirtIOBlk<'static, VirtioHal>) {
+        while let Ok(token) = blk.pop_used() {
+            self.completions[token as usize].notify();
         }
     }
 }
@@ -50,27 +110,23 @@ pub struct VirtioHal;
 
 impl Hal for VirtioHal {
     fn dma_alloc(pages: usize) -> usize {
-        let mut ppn_base = PhysPageNum(0);
-        for i in 0..pages {
-            let frame = frame_alloc().unwrap();
-            if i == 0 {
-                ppn_base = frame.ppn;
-            }
-            assert_eq!(frame.ppn.0, ppn_base.0 + i);
-            QUEUE_FRAMES.lock().push(frame);
-        }
-        let pa: PhysAddr = ppn_base.into();
-        pa.0
+        let frames = FrameRange::alloc(pages, 1).expect("no contiguous frames left for a virtio queue");
+        let pa = frames.pa().0;
+        QUEUE_FRAMES.lock().push(frames);
+        pa
     }
 
     fn dma_dealloc(pa: usize, pages: usize) -> i32 {
-        let pa = PhysAddr::from(pa);
-        let mut ppn_base: PhysPageNum = pa.into();
-        for _ in 0..pages {
-            frame_dealloc(ppn_base);
-            ppn_base.step();
+        let ppn: PhysPageNum = PhysAddr::from(pa).into();
+        let mut queue_frames = QUEUE_FRAMES.lock();
+        match queue_frames.iter().position(|frames| frames.start == ppn && frames.pages == pages) {
+            Some(index) => {
+                // freed when dropped
+                queue_frames.swap_remove(index);
+                0
+            }
+            None => -1,
         }
-        0
     }
 
     fn phys_to_virt(addr: usize) -> usize {
@@ -0,0 +1,166 @@
+//! Console input
+//!
+//! Boards with a console UART we drive (`Platform::console_uart`) feed input
+//! from its receive interrupt ([`receive`]). Elsewhere the SBI console,
+//! which raises no interrupt, is polled on every timer interrupt ([`poll`]),
+//! which bounds the input latency to one tick.
+//!
+//! Characters go through a small canonical line discipline before anyone
+//! can read them:
+//! - input is echoed, backspace/delete erases the last character
+//! - readers only see complete lines, ended by `\r` or `\n` (read as `\n`)
+//! - `Ctrl-D` hands out the line being typed without a newline, or makes
+//!   the next read return 0 (end of file) if the line is empty
+//!
+//! Readers of fd 0 block on a wait queue until a line is complete, pollers
+//! are notified at the same time.
+
+use alloc::{collections::vec_deque::VecDeque, sync::Arc, vec::Vec};
+
+use crate::{
+    fs::poll::PollWaiters,
+    mm::Buffer,
+    print,
+    sbi::console_getchar,
+    sync::{event::Event, spin::mutex::IRQSpinLock},
+    task::WaitQueue,
+};
+
+type Mutex<T> = IRQSpinLock<T>;
+
+/// Longest line that can be typed, further characters are dropped
+const MAX_LINE: usize = 256;
+/// Characters taken from the SBI per poll, so a paste can't stall a tick
+const POLL_BUDGET: usize = 64;
+
+const CTRL_D: u8 = 0x04;
+
+struct LineDiscipline {
+    /// The line being typed
+    line: Vec<u8>,
+    /// Completed lines, not read yet
+    ready: VecDeque<u8>,
+    /// An end of file waits for a reader
+    eof: bool,
+}
+
+impl LineDiscipline {
+    const fn new() -> Self {
+        Self {
+            line: Vec::new(),
+            ready: VecDeque::new(),
+            eof: false,
+        }
+    }
+
+    /// Feed one input character, returns whether readers have something new.
+    fn input(&mut self, ch: u8) -> bool {
+        match ch {
+            b'\r' | b'\n' => {
+                self.line.push(b'\n');
+                self.ready.extend(self.line.drain(..));
+                print!("\n");
+                true
+            }
+            0x08 | 0x7f => {
+                if self.line.pop().is_some() {
+                    print!("\x08 \x08");
+                }
+                false
+            }
+            CTRL_D => {
+                if self.line.is_empty() {
+                    self.eof = true;
+                } else {
+                    self.ready.extend(self.line.drain(..));
+                }
+                true
+            }
+            ch => {
+                if self.line.len() < MAX_LINE {
+                    self.line.push(ch);
+                    print!("{}", ch as char);
+                }
+                false
+            }
+        }
+    }
+}
+
+static LDISC: Mutex<LineDiscipline> = Mutex::new(LineDiscipline::new());
+static READERS: WaitQueue = WaitQueue::new();
+static POLLERS: PollWaiters = PollWaiters::new();
+
+/// Feed received characters into the line discipline.
+///
+/// Called from interrupt context, by the driver of the input device.
+pub fn receive(chars: impl Iterator<Item = u8>) {
+    let mut ldisc = LDISC.lock();
+    let mut wake = false;
+    for ch in chars {
+        wake |= ldisc.input(ch);
+    }
+    drop(ldisc);
+    if wake {
+        READERS.wake_all();
+        POLLERS.notify();
+    }
+}
+
+/// Whether a [`read`] would return at once.
+pub fn input_ready() -> bool {
+    let ldisc = LDISC.lock();
+    !ldisc.ready.is_empty() || ldisc.eof
+}
+
+/// Notify `poller` whenever input turns ready.
+pub fn register_poller(poller: &Arc<Event>) {
+    POLLERS.register(poller);
+}
+
+/// Drain the pending SBI input into the line discipline.
+///
+/// Called from the timer interrupt, does nothing when the console UART
+/// interrupts on input by itself.
+pub fn poll() {
+    if crate::boards::platform::platform().console_uart.is_some() {
+        return;
+    }
+    let sbi_input = core::iter::from_fn(|| match console_getchar() {
+        0 | usize::MAX => None,
+        ch => Some(ch as u8),
+    });
+    receive(sbi_input.take(POLL_BUDGET));
+}
+
+/// Read at most one line into `buf`, blocking until one is complete.
+///
+/// Returns 0 at end of file.
+pub fn read(buf: &mut dyn Buffer) -> usize {
+    let Ok(pieces) = buf.as_bytes_mut() else {
+        return 0;
+    };
+    loop {
+        let mut ldisc = LDISC.lock();
+        if !ldisc.ready.is_empty() {
+            let mut read_size = 0;
+            for byte_ref in pieces.into_iter().flatten() {
+                let Some(byte) = ldisc.ready.pop_front() else {
+                    break;
+                };
+                *byte_ref = byte;
+                read_size += 1;
+                if byte == b'\n' {
+                    break;
+                }
+            }
+            return read_size;
+        }
+        if ldisc.eof {
+            ldisc.eof = false;
+            return 0;
+        }
+        // queued before the lock is released, a poll can't wake nobody
+        READERS.sleep_on_with(move || drop(ldisc));
+    }
+}
@@ -0,0 +1,99 @@
+//! DMA helpers for platforms without an IOMMU
+//!
+//! Devices see physical addresses and, on non-coherent platforms such as
+//! the K210, bypass the data cache. A buffer can be handed to a device
+//! only if it is identity mapped (its VA is its PA), inside a board DMA
+//! window, and cache-line aligned so maintenance can't clobber neighbours.
+//!
+//! [`to_device`] and [`from_device`] check that, transparently copy
+//! through a [`BounceBuffer`] when it doesn't hold, and call the board's
+//! cache maintenance hooks around the transfer:
+//! - `dcache_clean` before the device reads memory
+//! - `dcache_invalidate` before and after the device writes memory
+//!
+//! On QEMU DMA is coherent, the hooks are no-ops.
+
+use crate::{
+    boards::{dcache_clean, dcache_invalidate, platform::platform, DMA_ALIGN, DMA_REGIONS},
+    config::PAGE_SIZE,
+    mm::frame_allocator::FrameRange,
+};
+
+/// Whether a device may access `[addr, addr + len)` directly.
+///
+/// Kernel memory inside the DMA windows and below the end of RAM is
+/// identity mapped, anything else (e.g. kernel stacks, mapped high) fails
+/// the check.
+pub fn is_dma_safe(addr: usize, len: usize) -> bool {
+    let Some(end) = addr.checked_add(len) else {
+        return false;
+    };
+    addr % DMA_ALIGN == 0
+        && end % DMA_ALIGN == 0
+        && end <= platform().ram_end
+        && DMA_REGIONS
+            .iter()
+            .any(|&(start, size)| start <= addr && end <= start + size)
+}
+
+/// A physically contiguous, identity mapped, page aligned buffer.
+pub struct BounceBuffer {
+    frames: FrameRange,
+    len: usize,
+}
+
+impl BounceBuffer {
+    pub fn new(len: usize) -> Self {
+        let pages = (len + PAGE_SIZE - 1) / PAGE_SIZE;
+        let frames = FrameRange::alloc(pages.max(1), 1).expect("no contiguous frames left for a DMA bounce buffer");
+        Self { frames, len }
+    }
+
+    pub fn pa(&self) -> usize {
+        self.frames.pa().into()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.pa() as *const u8, self.len) }
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.pa() as *mut u8, self.len) }
+    }
+}
+
+/// Run a transfer from memory to a device, `transfer` gets a DMA-safe view of `buf`.
+pub fn to_device<R>(buf: &[u8], transfer: impl FnOnce(&[u8]) -> R) -> R {
+    if is_dma_safe(buf.as_ptr() as usize, buf.len()) {
+        dcache_clean(buf.as_ptr() as usize, buf.len());
+        return transfer(buf);
+    }
+
+    let mut bounce = BounceBuffer::new(buf.len());
+    bounce.as_mut_slice().copy_from_slice(buf);
+    dcache_clean(bounce.pa(), bounce.len());
+    transfer(bounce.as_slice())
+}
+
+/// Run a transfer from a device to memory, `transfer` gets a DMA-safe view of `buf`.
+pub fn from_device<R>(buf: &mut [u8], transfer: impl FnOnce(&mut [u8]) -> R) -> R {
+    if is_dma_safe(buf.as_ptr() as usize, buf.len()) {
+        let (addr, len) = (buf.as_ptr() as usize, buf.len());
+        // dirty lines must not be written back over the incoming data
+        dcache_invalidate(addr, len);
+        let result = transfer(buf);
+        dcache_invalidate(addr, len);
+        return result;
+    }
+
+    let mut bounce = BounceBuffer::new(buf.len());
+    dcache_invalidate(bounce.pa(), bounce.len());
+    let result = transfer(bounce.as_mut_slice());
+    dcache_invalidate(bounce.pa(), bounce.len());
+    buf.copy_from_slice(bounce.as_slice());
+    result
+}
@@ -1,3 +1,23 @@
 pub mod block;
+pub mod console;
+pub mod dma;
+pub mod plic;
+pub mod rtc;
+pub mod uart;
 
 pub use block::BLOCK_DEVICE;
+
+use crate::boards::platform::platform;
+
+/// Bring up the interrupt driven devices of the board.
+pub fn init() {
+    plic::init_hart();
+    if let Some((_, irq)) = platform().console_uart {
+        if let Some(uart) = uart::console_uart() {
+            uart.init();
+        }
+        plic::register_irq(irq, plic::DEFAULT_PRIORITY, uart::handle_irq);
+    }
+    block::init();
+    rtc::init();
+}
@@ -0,0 +1,99 @@
+//! Platform-Level Interrupt Controller
+//!
+//! Routes device interrupts to the S-mode context of each hart, which
+//! sees them as `SupervisorExternal`. Every interrupt is claimed,
+//! handled, then completed; the PLIC won't raise the same source again
+//! before it is completed.
+//!
+//! Drivers install their handler for a source with [`register_irq`],
+//! [`handle_external_irq`] dispatches to it from the trap handlers.
+
+use alloc::collections::btree_map::BTreeMap;
+use core::ptr::{read_volatile, write_volatile};
+
+use crate::{boards::platform::platform, processor::current_processor_id, sync::spin::mutex::IRQSpinLock};
+
+type Mutex<T> = IRQSpinLock<T>;
+
+pub type IrqHandler = fn();
+
+/// Priority given to sources registered without a preference
+pub const DEFAULT_PRIORITY: u32 = 1;
+
+/// Installed handlers, by source
+static HANDLERS: Mutex<BTreeMap<u32, IrqHandler>> = Mutex::new(BTreeMap::new());
+
+const PRIORITY_BASE: usize = 0x0;
+const ENABLE_BASE: usize = 0x2000;
+const ENABLE_STRIDE: usize = 0x80;
+const CONTEXT_BASE: usize = 0x20_0000;
+const CONTEXT_STRIDE: usize = 0x1000;
+
+/// Register offsets inside a context
+const THRESHOLD: usize = 0x0;
+const CLAIM: usize = 0x4;
+
+/// The S-mode context of `hart`, M-mode ones come first
+fn s_context(hart: usize) -> usize {
+    2 * hart + 1
+}
+
+fn reg(offset: usize) -> *mut u32 {
+    (platform().plic_base + offset) as *mut u32
+}
+
+pub fn set_priority(irq: u32, priority: u32) {
+    unsafe { write_volatile(reg(PRIORITY_BASE + 4 * irq as usize), priority) }
+}
+
+/// Let `irq` interrupt the S-mode of `hart`.
+pub fn enable(hart: usize, irq: u32) {
+    let enable = reg(ENABLE_BASE + s_context(hart) * ENABLE_STRIDE + 4 * (irq as usize / 32));
+    unsafe { write_volatile(enable, read_volatile(enable) | 1 << (irq % 32)) }
+}
+
+/// Only sources of a priority above `threshold` interrupt `hart`.
+pub fn set_threshold(hart: usize, threshold: u32) {
+    unsafe { write_volatile(reg(CONTEXT_BASE + s_context(hart) * CONTEXT_STRIDE + THRESHOLD), threshold) }
+}
+
+/// The highest priority pending source of `hart`, `None` if there is none.
+pub fn claim(hart: usize) -> Option<u32> {
+    let irq = unsafe { read_volatile(reg(CONTEXT_BASE + s_context(hart) * CONTEXT_STRIDE + CLAIM)) };
+    (irq != 0).then_some(irq)
+}
+
+pub fn complete(hart: usize, irq: u32) {
+    unsafe { write_volatile(reg(CONTEXT_BASE + s_context(hart) * CONTEXT_STRIDE + CLAIM), irq) }
+}
+
+/// Install `handler` for `irq` and let it interrupt the current hart.
+///
+/// Source 0 doesn't exist, and a source has at most one handler.
+pub fn register_irq(irq: u32, priority: u32, handler: IrqHandler) {
+    assert_ne!(irq, 0, "PLIC source 0 is reserved");
+    let previous = HANDLERS.lock().insert(irq, handler);
+    assert!(previous.is_none(), "irq {} registered twice", irq);
+    set_priority(irq, priority);
+    enable(current_processor_id().into(), irq);
+}
+
+/// Let every registered source through on the current hart.
+pub fn init_hart() {
+    set_threshold(current_processor_id().into(), 0);
+}
+
+/// `SupervisorExternal` interrupt: serve every source pending for the
+/// current hart.
+pub fn handle_external_irq() {
+    let hart = current_processor_id().into();
+    while let Some(irq) = claim(hart) {
+        // the handler runs unlocked, it may well register another one
+        let handler = HANDLERS.lock().get(&irq).copied();
+        match handler {
+            Some(handler) => handler(),
+            None => log::warn!("unexpected external interrupt {}", irq),
+        }
+        complete(hart, irq);
+    }
+}
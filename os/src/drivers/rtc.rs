@@ -0,0 +1,29 @@
+//! The goldfish RTC of QEMU's virt machine
+//!
+//! Only read once at boot: [`crate::timer`] keeps the time of day from
+//! then on, off the `time` CSR.
+use core::ptr::read_volatile;
+
+use crate::{boards::platform::platform, timer};
+
+/// Low 32 bits of the time, reading it latches the high ones
+const TIME_LOW: usize = 0x00;
+const TIME_HIGH: usize = 0x04;
+
+/// Nanoseconds since the epoch, if the board has an RTC.
+pub fn read_ns() -> Option<u64> {
+    let base = platform().rtc_base?;
+    let (low, high) = unsafe {
+        let low = read_volatile((base + TIME_LOW) as *const u32);
+        (low, read_volatile((base + TIME_HIGH) as *const u32))
+    };
+    Some((high as u64) << 32 | low as u64)
+}
+
+/// Start `CLOCK_REALTIME` at the time of the RTC.
+pub fn init() {
+    match read_ns() {
+        Some(now) => timer::set_realtime_ns(now),
+        None => log::info!("no RTC, the time of day starts at the epoch"),
+    }
+}
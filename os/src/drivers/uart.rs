@@ -0,0 +1,68 @@
+//! 16550a UART
+//!
+//! Only the receive side is driven here: output keeps going through the
+//! SBI console. The UART raises an interrupt for every received byte
+//! (`IER.ERBFI`), [`handle_irq`] drains the FIFO into the console input
+//! layer (`drivers::console`).
+
+use core::ptr::{read_volatile, write_volatile};
+
+use super::console;
+
+/// Receiver buffer, read side of offset 0
+const RBR: usize = 0;
+/// Interrupt enable
+const IER: usize = 1;
+/// FIFO control, write side of offset 2
+const FCR: usize = 2;
+/// Line status
+const LSR: usize = 5;
+
+const IER_RX_AVAILABLE: u8 = 1 << 0;
+const FCR_ENABLE: u8 = 1 << 0;
+const FCR_CLEAR: u8 = 0b11 << 1;
+const LSR_DATA_READY: u8 = 1 << 0;
+
+pub struct Uart {
+    base: usize,
+}
+
+impl Uart {
+    /// # Safety
+    /// `base` must be the identity mapped registers of a 16550a.
+    pub const unsafe fn new(base: usize) -> Self {
+        Self { base }
+    }
+
+    fn read_reg(&self, reg: usize) -> u8 {
+        unsafe { read_volatile((self.base + reg) as *const u8) }
+    }
+
+    fn write_reg(&self, reg: usize, value: u8) {
+        unsafe { write_volatile((self.base + reg) as *mut u8, value) }
+    }
+
+    /// Enable the FIFOs and the receive interrupt.
+    ///
+    /// Baud rate and line format are left as the firmware set them.
+    pub fn init(&self) {
+        self.write_reg(FCR, FCR_ENABLE | FCR_CLEAR);
+        self.write_reg(IER, IER_RX_AVAILABLE);
+    }
+
+    pub fn getchar(&self) -> Option<u8> {
+        (self.read_reg(LSR) & LSR_DATA_READY != 0).then(|| self.read_reg(RBR))
+    }
+}
+
+/// The console UART of the board, if it has one we can drive
+pub fn console_uart() -> Option<Uart> {
+    crate::boards::platform::platform().console_uart.map(|(base, _)| unsafe { Uart::new(base) })
+}
+
+/// Receive interrupt of the console UART.
+pub fn handle_irq() {
+    if let Some(uart) = console_uart() {
+        console::receive(core::iter::from_fn(|| uart.getchar()));
+    }
+}
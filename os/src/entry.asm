@@ -10,6 +10,13 @@ _start:
     la sp, boot_stack_top
     call rust_main
 
+    # Secondary harts, started by the boot hart through the SBI HSM
+    # extension: a0 is the hart id, a1 the top of the stack it allocated
+    .globl _start_secondary
+_start_secondary:
+    mv sp, a1
+    call rust_main_secondary
+
     # Allocate space for stack
 
     # * Low Address 0x00...0
@@ -0,0 +1,153 @@
+//! `/dev` character devices
+//!
+//! - `/dev/null` reads end of file at once, swallows every write
+//! - `/dev/zero` reads as many zero bytes as asked, swallows every write
+//! - `/dev/tty` is the console: the line discipline of `drivers::console`
+//!   on read, the same output as stdout on write
+//! - `/dev/rtc` reads the time since boot in microseconds, a native
+//!   endian `u64`, fresh on every read
+//!
+//! The set of devices is fixed, the root is their only directory.
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+
+use super::{
+    vfs::{FileSystem, Inode},
+    File, FileKind, FileRef, Metadata, OpenFlags, Stdin, Stdout,
+};
+use crate::{mm::Buffer, syscall::error::Errno, timer::get_time_us};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Device {
+    Null,
+    Zero,
+    Tty,
+    Rtc,
+}
+
+impl Device {
+    const ALL: [Device; 4] = [Device::Null, Device::Zero, Device::Tty, Device::Rtc];
+
+    fn name(self) -> &'static str {
+        match self {
+            Device::Null => "null",
+            Device::Zero => "zero",
+            Device::Tty => "tty",
+            Device::Rtc => "rtc",
+        }
+    }
+}
+
+pub struct DevFs;
+
+impl FileSystem for DevFs {
+    fn name(&self) -> &'static str {
+        "devfs"
+    }
+
+    fn root(&self) -> Arc<dyn Inode> {
+        Arc::new(DevInode { device: None })
+    }
+}
+
+/// A device, or the root if `None`
+struct DevInode {
+    device: Option<Device>,
+}
+
+impl Inode for DevInode {
+    fn is_dir(&self) -> bool {
+        self.device.is_none()
+    }
+
+    fn lookup(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        let device = Device::ALL.into_iter().find(|device| device.name() == name)?;
+        Some(Arc::new(DevInode { device: Some(device) }))
+    }
+
+    fn list(&self) -> Vec<String> {
+        Device::ALL.iter().map(|device| device.name().to_string()).collect()
+    }
+
+    fn metadata(&self) -> Metadata {
+        match self.device {
+            Some(_) => Metadata::new(FileKind::CharDevice, 0),
+            None => Metadata::new(FileKind::Directory, 0),
+        }
+    }
+
+    fn open(&self, flags: OpenFlags) -> Option<FileRef> {
+        let (readable, writable) = flags.read_write();
+        let device = self.device?;
+        Some(Arc::new(DeviceFile {
+            device,
+            readable,
+            // the clock can't be set
+            writable: writable && device != Device::Rtc,
+        }))
+    }
+}
+
+/// An open device
+pub struct DeviceFile {
+    device: Device,
+    readable: bool,
+    writable: bool,
+}
+
+impl File for DeviceFile {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+
+    fn writable(&self) -> bool {
+        self.writable
+    }
+
+    fn read(&self, buf: &mut dyn Buffer) -> usize {
+        match self.device {
+            Device::Null => 0,
+            Device::Zero => {
+                let Ok(pieces) = buf.as_bytes_mut() else {
+                    return 0;
+                };
+                pieces.into_iter().for_each(|slice| slice.fill(0));
+                buf.len()
+            }
+            Device::Tty => Stdin.read(buf),
+            Device::Rtc => {
+                let now = (get_time_us() as u64).to_ne_bytes();
+                // a short buffer gets the first bytes
+                let Ok(pieces) = buf.as_bytes_mut() else {
+                    return 0;
+                };
+                let mut read_size = 0;
+                for slice in pieces {
+                    let len = slice.len().min(now.len() - read_size);
+                    slice[..len].copy_from_slice(&now[read_size..read_size + len]);
+                    read_size += len;
+                }
+                read_size
+            }
+        }
+    }
+
+    fn write(&self, buf: &dyn Buffer) -> usize {
+        match self.device {
+            Device::Null | Device::Zero => buf.len(),
+            Device::Tty => Stdout.write(buf),
+            Device::Rtc => 0,
+        }
+    }
+
+    /// Like Linux, `null` and `zero` accept any seek and stay at 0
+    fn seek(&self, _offset: isize, _whence: usize) -> Result<usize, Errno> {
+        match self.device {
+            Device::Null | Device::Zero => Ok(0),
+            Device::Tty | Device::Rtc => Err(Errno::ESPIPE),
+        }
+    }
+}
@@ -0,0 +1,101 @@
+//! easy-fs on the block device, mounted on `/`
+//!
+//! easy-fs has a single directory, its root: every file is an entry of it.
+use alloc::{string::String, sync::Arc, vec::Vec};
+use easy_fs::{block_cache_sync_all, EasyFileSystem};
+use lazy_static::*;
+use os_macros::shutdown_hook;
+
+use super::{
+    vfs::{FileSystem, Inode},
+    FileKind, Metadata,
+};
+use crate::{drivers::BLOCK_DEVICE, syscall::error::Errno};
+
+pub struct EasyFs {
+    root: Arc<easy_fs::Inode>,
+}
+
+lazy_static! {
+    static ref EASY_FS: Arc<EasyFs> = {
+        log::debug!("fs openning");
+        let efs = EasyFileSystem::open(BLOCK_DEVICE.clone());
+        log::debug!("fs open success");
+        Arc::new(EasyFs {
+            root: Arc::new(EasyFileSystem::root_inode(&efs)),
+        })
+    };
+}
+
+impl EasyFs {
+    /// The file system of the block device, opened on first use
+    pub fn get() -> Arc<dyn FileSystem> {
+        EASY_FS.clone()
+    }
+}
+
+impl FileSystem for EasyFs {
+    fn name(&self) -> &'static str {
+        "easyfs"
+    }
+
+    fn root(&self) -> Arc<dyn Inode> {
+        self.root.clone()
+    }
+}
+
+impl Inode for easy_fs::Inode {
+    fn is_dir(&self) -> bool {
+        easy_fs::Inode::is_dir(self)
+    }
+
+    fn lookup(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        self.find(name).map(|inode| inode as _)
+    }
+
+    fn create(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        easy_fs::Inode::create(self, name).map(|inode| inode as _)
+    }
+
+    fn list(&self) -> Vec<String> {
+        self.ls()
+    }
+
+    fn size(&self) -> usize {
+        easy_fs::Inode::size(self)
+    }
+
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+        easy_fs::Inode::read_at(self, offset, buf)
+    }
+
+    fn write_at(&self, offset: usize, buf: &[u8]) -> usize {
+        easy_fs::Inode::write_at(self, offset, buf)
+    }
+
+    fn truncate(&self, len: usize) -> Result<(), Errno> {
+        if easy_fs::Inode::is_dir(self) {
+            return Err(Errno::EINVAL);
+        }
+        // sizes are 32-bit on disk
+        let len = u32::try_from(len).map_err(|_| Errno::EFBIG)?;
+        self.set_size(len);
+        Ok(())
+    }
+
+    /// easy-fs numbers its inodes from 0, the root: shifted by one, 0
+    /// being no number for `stat`
+    fn metadata(&self) -> Metadata {
+        let kind = if easy_fs::Inode::is_dir(self) { FileKind::Directory } else { FileKind::Regular };
+        Metadata {
+            ino: self.inode_id() as u64 + 1,
+            ..Metadata::new(kind, easy_fs::Inode::size(self))
+        }
+    }
+}
+
+/// Write every dirty cached block back before powering off.
+#[shutdown_hook(priority = 10)]
+fn sync_block_cache() {
+    block_cache_sync_all();
+}
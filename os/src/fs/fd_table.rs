@@ -0,0 +1,108 @@
+//! File descriptor table
+//!
+//! Maps the fds of a task group to open files. A new fd always takes the
+//! lowest free slot, the table grows up to its limit, the `RLIMIT_NOFILE`
+//! of the task group, [`MAX_FDS`] at most.
+//! Each fd carries its own close-on-exec flag, duplicates don't share it.
+//!
+//! Forked children get a copy of the table: fds refer to the same open
+//! files, but closing one in the child leaves the parent's alone.
+
+use alloc::{sync::Arc, vec::Vec};
+
+use super::{File, Stdin, Stdout};
+use crate::syscall::error::Errno;
+
+pub type FileRef = Arc<dyn File + Send + Sync>;
+
+/// Most fds a table holds, the hard `RLIMIT_NOFILE`
+pub const MAX_FDS: usize = 256;
+
+#[derive(Clone)]
+struct FdEntry {
+    file: FileRef,
+    cloexec: bool,
+}
+
+#[derive(Clone)]
+pub struct FdTable {
+    entries: Vec<Option<FdEntry>>,
+    /// New fds are below it
+    limit: usize,
+}
+
+impl FdTable {
+    /// 0 is stdin, 1 and 2 are stdout
+    pub fn with_stdio() -> Self {
+        let mut table = Self { entries: Vec::new(), limit: MAX_FDS };
+        table.alloc_fd(Arc::new(Stdin), false).unwrap();
+        table.alloc_fd(Arc::new(Stdout), false).unwrap();
+        table.alloc_fd(Arc::new(Stdout), false).unwrap();
+        table
+    }
+
+    pub fn get(&self, fd: usize) -> Result<FileRef, Errno> {
+        self.entries
+            .get(fd)
+            .and_then(|entry| entry.as_ref())
+            .map(|entry| entry.file.clone())
+            .ok_or(Errno::EBADF)
+    }
+
+    /// Limit the fds allocated from now on to `[0, limit)`, `MAX_FDS` at
+    /// most. The fds open already stay.
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit.min(MAX_FDS);
+    }
+
+    /// Install `file` at the lowest free fd.
+    pub fn alloc_fd(&mut self, file: FileRef, cloexec: bool) -> Result<usize, Errno> {
+        let fd = match self.entries.iter().position(|entry| entry.is_none()) {
+            Some(fd) if fd < self.limit => fd,
+            Some(_) => return Err(Errno::EMFILE),
+            None if self.entries.len() < self.limit => {
+                self.entries.push(None);
+                self.entries.len() - 1
+            }
+            None => return Err(Errno::EMFILE),
+        };
+        self.entries[fd] = Some(FdEntry { file, cloexec });
+        Ok(fd)
+    }
+
+    /// Install `file` at `fd`, returns the file it replaces.
+    pub fn install(&mut self, fd: usize, file: FileRef, cloexec: bool) -> Result<Option<FileRef>, Errno> {
+        if fd >= self.limit {
+            return Err(Errno::EBADF);
+        }
+        if fd >= self.entries.len() {
+            self.entries.resize(fd + 1, None);
+        }
+        let old = self.entries[fd].replace(FdEntry { file, cloexec });
+        Ok(old.map(|entry| entry.file))
+    }
+
+    /// Free `fd`, returns the file it referred to.
+    pub fn close(&mut self, fd: usize) -> Result<FileRef, Errno> {
+        self.entries
+            .get_mut(fd)
+            .and_then(|entry| entry.take())
+            .map(|entry| entry.file)
+            .ok_or(Errno::EBADF)
+    }
+
+    /// Refer to the file of `fd` from the lowest free fd too.
+    pub fn dup(&mut self, fd: usize, cloexec: bool) -> Result<usize, Errno> {
+        let file = self.get(fd)?;
+        self.alloc_fd(file, cloexec)
+    }
+
+    /// Close every fd marked close-on-exec.
+    pub fn close_on_exec(&mut self) {
+        for entry in self.entries.iter_mut() {
+            if entry.as_ref().is_some_and(|entry| entry.cloexec) {
+                *entry = None;
+            }
+        }
+    }
+}
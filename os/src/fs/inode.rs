@@ -1,78 +1,79 @@
-//! `Arc<Inode>` -> `OSInodeInner`: In order to open files concurrently
-//! we need to wrap `Inode` into `Arc`,but `Mutex` in `Inode` prevents
-//! file systems from being accessed simultaneously
-//!
-//! `Mutex<OSInodeInner>` -> `OSInode`: for static `ROOT_INODE`,we
-//! need to wrap `OSInodeInner` into `Mutex`
-use super::File;
+//! `OSInode`: an open file over a VFS [`Inode`], with its own offset.
+//! Opening the same inode twice gives two independent offsets.
+use super::vfs::{self, Inode};
+use super::{seek_offset, File, Metadata};
 use crate::println;
-use crate::{drivers::BLOCK_DEVICE, sync::spin::mutex::IRQSpinLock};
-use crate::mm::UserBuffer;
+use crate::sync::spin::mutex::IRQSpinLock;
+use crate::mm::Buffer;
+use crate::syscall::error::Errno;
 use alloc::sync::Arc;
-use alloc::vec::Vec;
 use bitflags::*;
-use easy_fs::{EasyFileSystem, Inode};
-use lazy_static::*;
+use os_macros::monitor_command;
 
 type Mutex<T> = IRQSpinLock<T>;
 
 
 /// A wrapper around a filesystem inode
 /// to implement File trait atop
+///
+/// The offset is locked for a whole read or write: tasks sharing the open
+/// file (a forked fd, a `dup`) never read or write the same bytes twice.
 pub struct OSInode {
     readable: bool,
     writable: bool,
+    /// Every write goes to the end of the file
+    append: bool,
     inner: Mutex<OSInodeInner>,
 }
 /// The OS inode inner in 'Mutex'
 pub struct OSInodeInner {
     offset: usize,
-    inode: Arc<Inode>,
+    inode: Arc<dyn Inode>,
 }
 
 impl OSInode {
     /// Construct an OS inode from a inode
-    pub fn new(readable: bool, writable: bool, inode: Arc<Inode>) -> Self {
+    pub fn new(flags: OpenFlags, inode: Arc<dyn Inode>) -> Self {
+        let (readable, writable) = flags.read_write();
         Self {
             readable,
             writable,
-            inner: unsafe { Mutex::new(OSInodeInner { offset: 0, inode }) },
+            append: flags.contains(OpenFlags::APPEND),
+            inner: Mutex::new(OSInodeInner { offset: 0, inode }),
         }
     }
-    /// Read all data inside a inode into vector
-    pub fn read_all(&self) -> Vec<u8> {
-        let mut inner = self.inner.lock();
-        let mut buffer = [0u8; 512];
-        let mut v: Vec<u8> = Vec::new();
-        loop {
-            let len = inner.inode.read_at(inner.offset, &mut buffer);
-            if len == 0 {
-                break;
-            }
-            inner.offset += len;
-            v.extend_from_slice(&buffer[..len]);
-        }
-        v
-    }
 }
 
-lazy_static! {
-    pub static ref ROOT_INODE: Arc<Inode> = {
-        log::debug!("fs openning");
-        let efs = EasyFileSystem::open(BLOCK_DEVICE.clone());
-        log::debug!("fs open success");
-        Arc::new(EasyFileSystem::root_inode(&efs))
-    };
-}
 /// List all files in the filesystems
 pub fn list_apps() {
     println!("/**** APPS ****");
-    for app in ROOT_INODE.ls() {
+    for app in vfs::lookup("/").map(|root| root.list()).unwrap_or_default() {
         println!("{}", app);
     }
     println!("**************/");
 }
 
+#[monitor_command(name = "ls", help = "List files in the root directory")]
+fn ls_command(_args: &[&str]) {
+    list_apps();
+}
+
+#[monitor_command(name = "cat", help = "Print a file: cat <path>")]
+fn cat_command(args: &[&str]) {
+    let Some(path) = args.get(1) else {
+        println!("usage: cat <path>");
+        return;
+    };
+    match vfs::lookup(path) {
+        Ok(inode) if !inode.is_dir() => {
+            let data = inode.read_all();
+            println!("{}", alloc::string::String::from_utf8_lossy(&data));
+        }
+        Ok(_) => println!("cat: {}: is a directory", path),
+        Err(errno) => println!("cat: {}: {}", path, errno.message()),
+    }
+}
+
 bitflags! {
     ///Open file flags
     pub struct OpenFlags: u32 {
@@ -86,6 +87,10 @@ bitflags! {
         const CREATE = 1 << 9;
         ///Clear file and return an empty one
         const TRUNC = 1 << 10;
+        ///Write at the end of the file
+        const APPEND = 1 << 11;
+        ///Close the fd on exec
+        const CLOEXEC = 1 << 19;
     }
 }
 
@@ -102,32 +107,6 @@ impl OpenFlags {
         }
     }
 }
-///Open file with flags
-pub fn open_file(name: &str, flags: OpenFlags) -> Option<Arc<OSInode>> {
-    let (readable, writable) = flags.read_write();
-    if flags.contains(OpenFlags::CREATE) {
-        if let Some(inode) = ROOT_INODE.find(name) {
-            // clear size
-            inode.clear();
-            Some(Arc::new(OSInode::new(readable, writable, inode)))
-        } else {
-            // create file
-            ROOT_INODE
-                .create(name)
-                .map(|inode| Arc::new(OSInode::new(readable, writable, inode)))
-        }
-    } else {
-        ROOT_INODE
-            .find(name)
-            .map(|inode| {
-                log::debug!("find {} success", name);
-                if flags.contains(OpenFlags::TRUNC) {
-                    inode.clear();
-                }
-                Arc::new(OSInode::new(readable, writable, inode))
-            })
-    }
-}
 
 impl File for OSInode {
     fn readable(&self) -> bool {
@@ -136,11 +115,14 @@ impl File for OSInode {
     fn writable(&self) -> bool {
         self.writable
     }
-    fn read(&self, mut buf: UserBuffer) -> usize {
+    fn read(&self, buf: &mut dyn Buffer) -> usize {
+        let Ok(pieces) = buf.as_bytes_mut() else {
+            return 0;
+        };
         let mut inner = self.inner.lock();
         let mut total_read_size = 0usize;
-        for slice in buf.buffers.iter_mut() {
-            let read_size = inner.inode.read_at(inner.offset, *slice);
+        for slice in pieces {
+            let read_size = inner.inode.read_at(inner.offset, slice);
             if read_size == 0 {
                 break;
             }
@@ -149,15 +131,41 @@ impl File for OSInode {
         }
         total_read_size
     }
-    fn write(&self, buf: UserBuffer) -> usize {
+    fn write(&self, buf: &dyn Buffer) -> usize {
+        let Ok(pieces) = buf.as_bytes() else {
+            return 0;
+        };
         let mut inner = self.inner.lock();
+        if self.append {
+            inner.offset = inner.inode.size();
+        }
         let mut total_write_size = 0usize;
-        for slice in buf.buffers.iter() {
-            let write_size = inner.inode.write_at(inner.offset, *slice);
+        for slice in pieces {
+            let write_size = inner.inode.write_at(inner.offset, slice);
             assert_eq!(write_size, slice.len());
             inner.offset += write_size;
             total_write_size += write_size;
         }
         total_write_size
     }
+    fn seek(&self, offset: isize, whence: usize) -> Result<usize, Errno> {
+        let mut inner = self.inner.lock();
+        inner.offset = seek_offset(inner.offset, inner.inode.size(), offset, whence)?;
+        Ok(inner.offset)
+    }
+    fn truncate(&self, len: usize) -> Result<(), Errno> {
+        if !self.writable {
+            return Err(Errno::EINVAL);
+        }
+        // the offset lock keeps a concurrent write from growing it back
+        let inner = self.inner.lock();
+        inner.inode.truncate(len)
+    }
+    fn inode(&self) -> Option<Arc<dyn Inode>> {
+        Some(self.inner.lock().inode.clone())
+    }
+    fn metadata(&self) -> Metadata {
+        let inode = self.inner.lock().inode.clone();
+        inode.metadata()
+    }
 }
@@ -1,20 +1,90 @@
 //! File system in os
+mod devfs;
+mod easyfs;
+mod fd_table;
 mod inode;
+mod pipe;
+pub mod poll;
+mod procfs;
+mod snapshot;
+mod stat;
 mod stdio;
 mod syscall;
+pub mod vfs;
 
-use crate::mm::UserBuffer;
+use alloc::sync::Arc;
+
+use crate::{mm::Buffer, sync::event::Event, syscall::error::Errno};
 /// File trait
 pub trait File: Send + Sync {
     /// If readable
     fn readable(&self) -> bool;
     /// If writable
     fn writable(&self) -> bool;
-    /// Read file to `UserBuffer`
-    fn read(&self, buf: UserBuffer) -> usize;
-    /// Write `UserBuffer` to file
-    fn write(&self, buf: UserBuffer) -> usize;
+    /// Read from the file into `buf`, returns the number of bytes read.
+    ///
+    /// The callers check that `buf` can be written first, nothing is read
+    /// into a buffer that can't.
+    fn read(&self, buf: &mut dyn Buffer) -> usize;
+    /// Write `buf` to the file, returns the number of bytes written.
+    ///
+    /// The callers check that `buf` can be read first, nothing is written
+    /// from a buffer that can't.
+    fn write(&self, buf: &dyn Buffer) -> usize;
+    /// Move the offset as `lseek` does, returns the new offset.
+    ///
+    /// Streams (pipes, terminals) have no offset: `ESPIPE`.
+    fn seek(&self, _offset: isize, _whence: usize) -> Result<usize, Errno> {
+        Err(Errno::ESPIPE)
+    }
+    /// If a read wouldn't block, see [`poll`]
+    fn poll_readable(&self) -> bool {
+        self.readable()
+    }
+    /// If a write wouldn't block
+    fn poll_writable(&self) -> bool {
+        self.writable()
+    }
+    /// Notify `poller` whenever the file may have turned readable or
+    /// writable. Files which never block have nothing to notify.
+    fn register_poller(&self, _poller: &Arc<Event>) {}
+    /// Set the size of the file to `len` bytes, as `ftruncate` does.
+    ///
+    /// Only regular files have a size to set: `EINVAL`.
+    fn truncate(&self, _len: usize) -> Result<(), Errno> {
+        Err(Errno::EINVAL)
+    }
+    /// The node under the file, for `mmap`. Streams have none
+    fn inode(&self) -> Option<Arc<dyn vfs::Inode>> {
+        None
+    }
+    /// What `fstat` reports, a character device by default: a stream
+    /// with no node behind it
+    fn metadata(&self) -> Metadata {
+        Metadata::new(FileKind::CharDevice, 0)
+    }
+}
+
+/// `whence` of `lseek`
+pub const SEEK_SET: usize = 0;
+pub const SEEK_CUR: usize = 1;
+pub const SEEK_END: usize = 2;
+
+/// The offset `lseek(offset, whence)` moves to from `current`, in a file
+/// of `size` bytes. Seeking past the end is fine, before the start isn't.
+pub fn seek_offset(current: usize, size: usize, offset: isize, whence: usize) -> Result<usize, Errno> {
+    let base = match whence {
+        SEEK_SET => 0,
+        SEEK_CUR => current,
+        SEEK_END => size,
+        _ => return Err(Errno::EINVAL),
+    };
+    base.checked_add_signed(offset).ok_or(Errno::EINVAL)
 }
 
-pub use inode::{list_apps, open_file, OSInode, OpenFlags};
+pub use fd_table::{FdTable, FileRef, MAX_FDS};
+pub use inode::{list_apps, OSInode, OpenFlags};
+pub use pipe::{make_pipe, Pipe};
+pub use snapshot::SnapshotFile;
+pub use stat::{FileKind, Metadata, Stat};
 pub use stdio::{Stdin, Stdout};
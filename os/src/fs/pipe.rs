@@ -0,0 +1,211 @@
+//! Pipe
+//!
+//! Both ends share a ring buffer. A reader blocks while the buffer is empty
+//! and a writer while it is full, on the buffer's wait queues.
+//! Once every write end is closed, reading an empty pipe returns 0 (EOF);
+//! once every read end is closed, writing stops short.
+//! Pollers are notified along with the wait queues.
+use alloc::sync::Arc;
+
+use super::{poll::PollWaiters, File, FileKind, Metadata};
+use crate::{
+    mm::Buffer,
+    sync::{event::Event, spin::mutex::IRQSpinLock},
+    task::WaitQueue,
+};
+
+type Mutex<T> = IRQSpinLock<T>;
+
+const RING_BUFFER_SIZE: usize = 512;
+
+/// One end of a pipe
+pub struct Pipe {
+    readable: bool,
+    writable: bool,
+    buffer: Arc<PipeRingBuffer>,
+}
+
+struct PipeRingBuffer {
+    inner: Mutex<RingBufferInner>,
+    /// Readers waiting for data
+    read_wait: WaitQueue,
+    /// Writers waiting for room
+    write_wait: WaitQueue,
+    /// Pollers of either end
+    pollers: PollWaiters,
+}
+
+struct RingBufferInner {
+    arr: [u8; RING_BUFFER_SIZE],
+    head: usize,
+    len: usize,
+    /// Open read ends
+    readers: usize,
+    /// Open write ends
+    writers: usize,
+}
+
+impl RingBufferInner {
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn is_full(&self) -> bool {
+        self.len == RING_BUFFER_SIZE
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let byte = self.arr[self.head];
+        self.head = (self.head + 1) % RING_BUFFER_SIZE;
+        self.len -= 1;
+        byte
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        let tail = (self.head + self.len) % RING_BUFFER_SIZE;
+        self.arr[tail] = byte;
+        self.len += 1;
+    }
+}
+
+/// Create a pipe, returns its `(read end, write end)`.
+pub fn make_pipe() -> (Arc<Pipe>, Arc<Pipe>) {
+    let buffer = Arc::new(PipeRingBuffer {
+        inner: Mutex::new(RingBufferInner {
+            arr: [0; RING_BUFFER_SIZE],
+            head: 0,
+            len: 0,
+            readers: 1,
+            writers: 1,
+        }),
+        read_wait: WaitQueue::new(),
+        write_wait: WaitQueue::new(),
+        pollers: PollWaiters::new(),
+    });
+    let read_end = Arc::new(Pipe {
+        readable: true,
+        writable: false,
+        buffer: buffer.clone(),
+    });
+    let write_end = Arc::new(Pipe {
+        readable: false,
+        writable: true,
+        buffer,
+    });
+    (read_end, write_end)
+}
+
+impl File for Pipe {
+    fn readable(&self) -> bool {
+        self.readable
+    }
+
+    fn writable(&self) -> bool {
+        self.writable
+    }
+
+    /// Some data, or no write end left (a read returns 0 at once)
+    fn poll_readable(&self) -> bool {
+        let ring = self.buffer.inner.lock();
+        self.readable && (!ring.is_empty() || ring.writers == 0)
+    }
+
+    /// Some room, or no read end left (a write returns at once)
+    fn poll_writable(&self) -> bool {
+        let ring = self.buffer.inner.lock();
+        self.writable && (!ring.is_full() || ring.readers == 0)
+    }
+
+    fn register_poller(&self, poller: &Arc<Event>) {
+        self.buffer.pollers.register(poller);
+    }
+
+    fn metadata(&self) -> Metadata {
+        Metadata::new(FileKind::Fifo, 0)
+    }
+
+    /// Blocks until at least one byte is available, then reads what fits.
+    fn read(&self, buf: &mut dyn Buffer) -> usize {
+        assert!(self.readable);
+        let want = buf.len();
+        let Ok(pieces) = buf.as_bytes_mut() else {
+            return 0;
+        };
+        let mut bytes = pieces.into_iter().flatten();
+        let mut read_size = 0usize;
+        loop {
+            let mut ring = self.buffer.inner.lock();
+            if ring.is_empty() {
+                if read_size > 0 || ring.writers == 0 {
+                    return read_size;
+                }
+                // the ring lock is released only once we are queued,
+                // a writer can't slip in between the check and the sleep
+                self.buffer.read_wait.sleep_on_with(move || drop(ring));
+                continue;
+            }
+
+            while !ring.is_empty() && read_size < want {
+                *bytes.next().unwrap() = ring.read_byte();
+                read_size += 1;
+            }
+            drop(ring);
+            self.buffer.write_wait.wake_all();
+            self.buffer.pollers.notify();
+
+            if read_size == want {
+                return read_size;
+            }
+        }
+    }
+
+    /// Blocks until every byte is written, or every read end is closed.
+    fn write(&self, buf: &dyn Buffer) -> usize {
+        assert!(self.writable);
+        let want = buf.len();
+        let Ok(pieces) = buf.as_bytes() else {
+            return 0;
+        };
+        let mut bytes = pieces.into_iter().flatten();
+        let mut write_size = 0usize;
+        loop {
+            let mut ring = self.buffer.inner.lock();
+            if ring.readers == 0 {
+                return write_size;
+            }
+            if ring.is_full() {
+                self.buffer.write_wait.sleep_on_with(move || drop(ring));
+                continue;
+            }
+
+            while !ring.is_full() && write_size < want {
+                ring.write_byte(*bytes.next().unwrap());
+                write_size += 1;
+            }
+            drop(ring);
+            self.buffer.read_wait.wake_all();
+            self.buffer.pollers.notify();
+
+            if write_size == want {
+                return write_size;
+            }
+        }
+    }
+}
+
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        let mut ring = self.buffer.inner.lock();
+        if self.readable {
+            ring.readers -= 1;
+        }
+        if self.writable {
+            ring.writers -= 1;
+        }
+        drop(ring);
+        // let blocked peers notice the closed end
+        self.buffer.read_wait.wake_all();
+        self.buffer.write_wait.wake_all();
+        self.buffer.pollers.notify();
+    }
+}
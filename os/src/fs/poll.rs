@@ -0,0 +1,125 @@
+//! Poll
+//!
+//! A poller owns an [`Event`] and registers it with every file it watches,
+//! through [`File::register_poller`]. A file which may turn readable or
+//! writable keeps the registered events in a [`PollWaiters`] and notifies
+//! them on every change; the poller then re-checks all of its files.
+//!
+//! Files only hold the events weakly, nothing has to be unregistered: the
+//! event dies with the poll, and dead entries are dropped on the next
+//! registration or notification.
+
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use crate::{
+    sync::{event::Event, spin::mutex::IRQSpinLock},
+    timer::{add_timer, cancel_timer, get_time},
+};
+
+use super::FileRef;
+
+type Mutex<T> = IRQSpinLock<T>;
+
+/// `events` and `revents` bits of `poll`, Linux values
+pub const POLLIN: i16 = 0x001;
+pub const POLLOUT: i16 = 0x004;
+/// Only in `revents`: the fd is not open
+pub const POLLNVAL: i16 = 0x020;
+
+/// `struct pollfd` of the Linux ABI
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PollFd {
+    pub fd: i32,
+    pub events: i16,
+    pub revents: i16,
+}
+
+/// The pollers registered with a file
+pub struct PollWaiters {
+    waiters: Mutex<Vec<Weak<Event>>>,
+}
+
+impl PollWaiters {
+    pub const fn new() -> Self {
+        Self {
+            waiters: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn register(&self, poller: &Arc<Event>) {
+        let mut waiters = self.waiters.lock();
+        waiters.retain(|waiter| waiter.strong_count() > 0);
+        waiters.push(Arc::downgrade(poller));
+    }
+
+    /// Tell every live poller to re-check its files.
+    ///
+    /// Can be called from interrupt context.
+    pub fn notify(&self) {
+        let pollers: Vec<_> = {
+            let mut waiters = self.waiters.lock();
+            waiters.retain(|waiter| waiter.strong_count() > 0);
+            waiters.iter().filter_map(Weak::upgrade).collect()
+        };
+        for poller in pollers {
+            poller.notify();
+        }
+    }
+}
+
+/// The `revents` of `fd` for `file`, `None` if it is not open
+fn revents(fd: &PollFd, file: Option<&FileRef>) -> i16 {
+    let Some(file) = file else {
+        return POLLNVAL;
+    };
+    let mut revents = 0;
+    if fd.events & POLLIN != 0 && file.poll_readable() {
+        revents |= POLLIN;
+    }
+    if fd.events & POLLOUT != 0 && file.poll_writable() {
+        revents |= POLLOUT;
+    }
+    revents
+}
+
+/// Fill the `revents` of `fds`, blocking until at least one is set or
+/// `deadline` (see [`get_time`]) passed. `files[i]` is the file of `fds[i]`.
+///
+/// Returns how many `revents` are set, 0 on timeout. A negative `fd` is
+/// skipped, its `revents` is 0.
+pub fn poll(fds: &mut [PollFd], files: &[Option<FileRef>], deadline: Option<usize>) -> usize {
+    let poller = Arc::new(Event::new());
+    for file in files.iter().flatten() {
+        file.register_poller(&poller);
+    }
+    let timer = deadline.map(|deadline| {
+        let poller = poller.clone();
+        add_timer(deadline, move || {
+            poller.notify();
+        })
+    });
+
+    let ready = loop {
+        let seen = poller.generation();
+        let mut ready = 0;
+        for (fd, file) in fds.iter_mut().zip(files) {
+            fd.revents = if fd.fd < 0 { 0 } else { revents(fd, file.as_ref()) };
+            if fd.revents != 0 {
+                ready += 1;
+            }
+        }
+        if ready > 0 || deadline.is_some_and(|deadline| get_time() >= deadline) {
+            break ready;
+        }
+        poller.wait(seen);
+    };
+
+    if let Some(timer) = timer {
+        cancel_timer(&timer);
+    }
+    ready
+}
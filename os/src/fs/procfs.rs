@@ -0,0 +1,287 @@
+//! `/proc` status files
+//!
+//! Mirrors the process/thread split of the task model:
+//! - `/proc/<pid>/status` describes the task group led by `<pid>`,
+//!   its counters summed over every thread, exited ones included
+//! - `/proc/<pid>/task/<tid>/status` describes one thread of that group
+//! - `/proc/<pid>/output` is the captured output of `<pid>`, readable
+//!   even after it exited (see `task::capture`)
+//! - `/proc/<tid>/sched` are the scheduling counters of one task, thread
+//!   or leader (see `task::stats`)
+//! - `/proc/cmdline` is the kernel command line (see `cmdline`)
+//! - `/proc/iomem` is the boot memory map, reserved ranges included
+//!   (see `mm::memmap`)
+//! - `/proc/iosched` has a line per block device: the depth of its
+//!   request queue and the requests merged (see `drivers::block::queue`)
+//! - `/proc/meminfo` is the usage of the frame allocator and kernel heap,
+//!   with the failed frame requests and the tasks killed out of memory
+//! - `/proc/slabinfo` lists the slab caches of the kernel heap
+//! - `/proc/uptime` is the time since boot, in seconds
+//! - `/proc/mounts` lists the mounted file systems
+//!
+//! Files are [`SnapshotFile`]s, rendered once when opened. Directories
+//! only exist through their files: a lookup succeeds if some file lies
+//! below, and only the root lists its entries, the static files.
+use core::fmt::Write;
+
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+
+use super::{
+    vfs::{self, FileSystem, Inode},
+    FileRef, OpenFlags, SnapshotFile,
+};
+use crate::{
+    cmdline,
+    config::PAGE_SIZE,
+    drivers::block,
+    mm::{
+        frame_allocator::{available_frames, frame_stats, total_frames},
+        heap_allocator::{heap_stats, slab_stats},
+        memmap, oom,
+    },
+    task::{capture::find_capture, find_task, stats::StatsSnapshot, TaskControlBlock, TaskState},
+    timer::{cycles_to_ms, get_time_ms},
+};
+
+/// Files at the root of the procfs
+const STATIC_FILES: [&str; 7] = ["cmdline", "iomem", "iosched", "meminfo", "mounts", "slabinfo", "uptime"];
+
+pub struct ProcFs;
+
+impl FileSystem for ProcFs {
+    fn name(&self) -> &'static str {
+        "procfs"
+    }
+
+    fn root(&self) -> Arc<dyn Inode> {
+        Arc::new(ProcInode { parts: Vec::new() })
+    }
+}
+
+/// A path in the procfs
+struct ProcInode {
+    parts: Vec<String>,
+}
+
+impl ProcInode {
+    fn parts(&self) -> Vec<&str> {
+        self.parts.iter().map(String::as_str).collect()
+    }
+}
+
+impl Inode for ProcInode {
+    fn is_dir(&self) -> bool {
+        matches!(self.parts().as_slice(), [] | [_] | [_, "task"] | [_, "task", _])
+            && !STATIC_FILES.contains(&self.parts.join("/").as_str())
+    }
+
+    fn lookup(&self, name: &str) -> Option<Arc<dyn Inode>> {
+        let mut parts = self.parts.clone();
+        parts.push(name.to_string());
+        let child = ProcInode { parts };
+        let exists = match child.parts().as_slice() {
+            [pid] | [pid, "task"] => open_proc(&[*pid, "status"]).is_some(),
+            [pid, "task", tid] => open_proc(&[*pid, "task", *tid, "status"]).is_some(),
+            parts => open_proc(parts).is_some(),
+        };
+        exists.then(|| Arc::new(child) as _)
+    }
+
+    fn list(&self) -> Vec<String> {
+        if self.parts.is_empty() {
+            STATIC_FILES.iter().map(|name| name.to_string()).collect()
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn open(&self, _flags: OpenFlags) -> Option<FileRef> {
+        open_proc(&self.parts()).map(|file| file as _)
+    }
+}
+
+/// Open the file at `parts` below the procfs root, `None` if it names nothing.
+fn open_proc(parts: &[&str]) -> Option<Arc<SnapshotFile>> {
+    match parts {
+        ["cmdline"] => Some(cmdline()),
+        ["iomem"] => Some(iomem()),
+        ["iosched"] => Some(iosched()),
+        ["meminfo"] => Some(meminfo()),
+        ["mounts"] => Some(mounts()),
+        ["slabinfo"] => Some(slabinfo()),
+        ["uptime"] => Some(uptime()),
+        [pid, "status"] => {
+            let leader = find_task(pid.parse().ok()?)?;
+            leader.is_leader().then(|| group_status(&leader))
+        }
+        [tid, "sched"] => {
+            let task = find_task(tid.parse().ok()?)?;
+            Some(task_sched(&task))
+        }
+        [pid, "output"] => {
+            let capture = find_capture(pid.parse().ok()?)?;
+            Some(Arc::new(SnapshotFile::from_bytes(capture.contents())))
+        }
+        [pid, "task", tid, "status"] => {
+            let pid: usize = pid.parse().ok()?;
+            let thread = find_task(tid.parse().ok()?)?;
+            (group_id(&thread)? == pid).then(|| thread_status(&thread))
+        }
+        _ => None,
+    }
+}
+
+fn cmdline() -> Arc<SnapshotFile> {
+    Arc::new(SnapshotFile::new(|out| writeln!(out, "{}", cmdline::raw())))
+}
+
+fn iomem() -> Arc<SnapshotFile> {
+    Arc::new(SnapshotFile::new(|out| {
+        for region in memmap::regions() {
+            writeln!(out, "{:08x}-{:08x} : {}", region.start, region.end - 1, region.kind.name())?;
+        }
+        Ok(())
+    }))
+}
+
+/// One line per device, columns like `slabinfo`
+fn iosched() -> Arc<SnapshotFile> {
+    Arc::new(SnapshotFile::new(|out| {
+        writeln!(out, "# name <depth> <max_depth> <dispatched> <batches> <merged> <aged> <bypassed>")?;
+        for queue in block::queues() {
+            let stats = queue.stats();
+            writeln!(
+                out,
+                "{:<8} {:>4} {:>4} {:>8} {:>8} {:>8} {:>6} {:>6}",
+                stats.name,
+                stats.depth,
+                stats.max_depth,
+                stats.dispatched.iter().sum::<usize>(),
+                stats.batches,
+                stats.merged,
+                stats.aged,
+                stats.bypassed
+            )?;
+        }
+        Ok(())
+    }))
+}
+
+fn meminfo() -> Arc<SnapshotFile> {
+    Arc::new(SnapshotFile::new(|out| {
+        let heap = heap_stats();
+        writeln!(out, "MemTotal:\t{} kB", total_frames() * PAGE_SIZE / 1024)?;
+        writeln!(out, "MemFree:\t{} kB", available_frames() * PAGE_SIZE / 1024)?;
+        let frames = frame_stats();
+        writeln!(out, "MemPeak:\t{} kB", frames.peak_used * PAGE_SIZE / 1024)?;
+        writeln!(out, "MemLow:\t\t{} kB", frames.low_watermark * PAGE_SIZE / 1024)?;
+        writeln!(out, "AllocFailures:\t{}", frames.failures)?;
+        writeln!(out, "OomKills:\t{}", oom::oom_kills())?;
+        writeln!(out, "HeapTotal:\t{} kB", heap.total / 1024)?;
+        writeln!(out, "HeapUsed:\t{} kB", heap.allocated / 1024)?;
+        writeln!(out, "HeapGrown:\t{} kB", heap.grown / 1024)?;
+        writeln!(out, "HeapArenaUsed:\t{} kB", heap.arena_in_use / 1024)?;
+        writeln!(out, "Slab:\t\t{} kB", heap.slab_in_use / 1024)
+    }))
+}
+
+/// One line per cache, columns like the Linux one
+fn slabinfo() -> Arc<SnapshotFile> {
+    Arc::new(SnapshotFile::new(|out| {
+        writeln!(out, "# name <active_objs> <objsize> <objperslab> <slabsize> <num_slabs> <allocs>")?;
+        for stats in slab_stats() {
+            writeln!(
+                out,
+                "{:<20} {:>6} {:>6} {:>4} {:>6} {:>4} {:>8}",
+                stats.name,
+                stats.in_use,
+                stats.object_size,
+                stats.objects_per_slab,
+                stats.slab_size,
+                stats.slabs,
+                stats.allocs
+            )?;
+        }
+        Ok(())
+    }))
+}
+
+fn uptime() -> Arc<SnapshotFile> {
+    Arc::new(SnapshotFile::new(|out| {
+        let ms = get_time_ms();
+        writeln!(out, "{}.{:02}", ms / 1000, ms % 1000 / 10)
+    }))
+}
+
+fn mounts() -> Arc<SnapshotFile> {
+    Arc::new(SnapshotFile::new(|out| {
+        for (path, fs) in vfs::mounts() {
+            writeln!(out, "{} {} {} rw 0 0", fs, path, fs)?;
+        }
+        Ok(())
+    }))
+}
+
+fn group_id(task: &Arc<TaskControlBlock>) -> Option<usize> {
+    let leader = task.lock().user_res.as_ref()?.group_leader.upgrade()?;
+    Some(leader.get_tid().into())
+}
+
+fn group_status(leader: &Arc<TaskControlBlock>) -> Arc<SnapshotFile> {
+    Arc::new(SnapshotFile::new(|out| {
+        let Some((task_group, group_stats, memory_set)) = leader.lock().user_res.as_ref().map(|user_res| {
+            (user_res.task_group.clone(), user_res.group_stats.clone(), user_res.memory_set.clone())
+        }) else {
+            return write_header(out, leader);
+        };
+        let resident_pages = memory_set.lock().resident_pages();
+        let (threads, total) = {
+            let members = task_group.lock();
+            (members.len(), group_stats.total(&members))
+        };
+
+        write_header(out, leader)?;
+        writeln!(out, "Tgid:\t{}", usize::from(leader.get_tid()))?;
+        writeln!(out, "Threads:\t{}", threads)?;
+        writeln!(out, "VmRSS:\t{} kB", resident_pages * PAGE_SIZE / 1024)?;
+        write_stats(out, total)
+    }))
+}
+
+fn thread_status(thread: &Arc<TaskControlBlock>) -> Arc<SnapshotFile> {
+    Arc::new(SnapshotFile::new(|out| {
+        write_header(out, thread)?;
+        write_stats(out, thread.stats().snapshot())
+    }))
+}
+
+fn task_sched(task: &Arc<TaskControlBlock>) -> Arc<SnapshotFile> {
+    Arc::new(SnapshotFile::new(|out| {
+        let running = task.lock().get_state() == TaskState::Running;
+        let stat = task.stats().sched_stat(running);
+        writeln!(out, "Name:\t{}", task.get_name())?;
+        writeln!(out, "RunTime:\t{} ns", stat.run_ns)?;
+        writeln!(out, "WaitTime:\t{} ns", stat.wait_ns)?;
+        writeln!(out, "Switches:\t{}", stat.switches)?;
+        writeln!(out, "VoluntarySwitches:\t{}", stat.voluntary_switches)?;
+        writeln!(out, "InvoluntarySwitches:\t{}", stat.involuntary_switches)
+    }))
+}
+
+fn write_header(out: &mut String, task: &Arc<TaskControlBlock>) -> core::fmt::Result {
+    writeln!(out, "Name:\t{}", task.get_name())?;
+    writeln!(out, "State:\t{}", task.lock().get_state())?;
+    writeln!(out, "Pid:\t{}", usize::from(task.get_tid()))?;
+    let ppid = task.get_ppid().map_or(0, usize::from);
+    writeln!(out, "PPid:\t{}", ppid)
+}
+
+fn write_stats(out: &mut String, stats: StatsSnapshot) -> core::fmt::Result {
+    writeln!(out, "CpuTime:\t{} ms", cycles_to_ms(stats.cpu_cycles))?;
+    writeln!(out, "Syscalls:\t{}", stats.syscalls)?;
+    writeln!(out, "Switches:\t{}", stats.switches)
+}
@@ -0,0 +1,129 @@
+//! Read-copy snapshot files
+//!
+//! Files that describe kernel state (e.g. the future `/proc` entries) must
+//! not hold scheduler or mm locks while their content is copied to user
+//! space: the copy may fault, and the fault path needs those same locks.
+//!
+//! [`SnapshotFile`] splits the two phases:
+//! 1. The generator runs **once**, at open time, under whatever locks it
+//!    needs, and renders its content into a private kernel buffer.
+//! 2. Every `read` only copies from that buffer, with no lock other than
+//!    the snapshot's own offset held.
+use core::fmt::Write;
+
+use alloc::{string::String, vec::Vec};
+use os_macros::kernel_test;
+
+use super::{seek_offset, File, FileKind, Metadata};
+use crate::{mm::Buffer, sync::spin::mutex::IRQSpinLock, syscall::error::Errno, timer::realtime_ns};
+
+type Mutex<T> = IRQSpinLock<T>;
+
+/// A read-only file backed by a buffer rendered at creation time.
+pub struct SnapshotFile {
+    data: Vec<u8>,
+    offset: Mutex<usize>,
+    /// Realtime of the rendering, the modification time for `fstat`
+    rendered_ns: u64,
+}
+
+impl SnapshotFile {
+    /// Render the content with `generate` and keep it for later reads.
+    ///
+    /// All locks taken inside `generate` are released before this returns.
+    pub fn new(generate: impl FnOnce(&mut String) -> core::fmt::Result) -> Self {
+        let mut content = String::new();
+        if generate(&mut content).is_err() {
+            log::warn!("snapshot generator failed, content truncated");
+        }
+        Self::from_bytes(content.into_bytes())
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Self {
+        Self {
+            data,
+            offset: Mutex::new(0),
+            rendered_ns: realtime_ns(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl File for SnapshotFile {
+    fn readable(&self) -> bool {
+        true
+    }
+
+    fn writable(&self) -> bool {
+        false
+    }
+
+    fn read(&self, buf: &mut dyn Buffer) -> usize {
+        let Ok(pieces) = buf.as_bytes_mut() else {
+            return 0;
+        };
+        let mut offset = self.offset.lock();
+        let mut total_read_size = 0usize;
+        for slice in pieces {
+            // seeking past the end is allowed, reads find nothing there
+            let remaining = self.data.get(*offset..).unwrap_or_default();
+            if remaining.is_empty() {
+                break;
+            }
+            let read_size = remaining.len().min(slice.len());
+            slice[..read_size].copy_from_slice(&remaining[..read_size]);
+            *offset += read_size;
+            total_read_size += read_size;
+        }
+        total_read_size
+    }
+
+    /// Not `writable`, `write` refuses the fd before: nothing is written.
+    fn write(&self, _buf: &dyn Buffer) -> usize {
+        0
+    }
+
+    fn seek(&self, offset: isize, whence: usize) -> Result<usize, Errno> {
+        let mut current = self.offset.lock();
+        *current = seek_offset(*current, self.data.len(), offset, whence)?;
+        Ok(*current)
+    }
+
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            mtime_ns: Some(self.rendered_ns),
+            ..Metadata::new(FileKind::Regular, self.data.len())
+        }
+    }
+}
+
+#[kernel_test]
+fn snapshot_releases_lock_test() {
+    let state = Mutex::new(42usize);
+
+    let snapshot = SnapshotFile::new(|out| {
+        let value = state.lock();
+        writeln!(out, "value: {}", *value)
+    });
+
+    // the generator's lock must be free before any copy-out happens
+    assert!(state.try_lock().is_some());
+
+    let mut out = [0u8; 20];
+    let read_size = snapshot.read(&mut &mut out[..]);
+    assert_eq!(read_size, snapshot.len());
+    assert_eq!(snapshot.as_bytes(), b"value: 42\n");
+    assert_eq!(&out[..read_size], snapshot.as_bytes());
+
+    // the offset is kept between reads
+    assert_eq!(snapshot.read(&mut &mut out[..]), 0);
+    assert!(!snapshot.writable());
+    assert_eq!(snapshot.write(&&b"ignored"[..]), 0);
+}
@@ -0,0 +1,112 @@
+//! File metadata, and the `struct stat` it is reported with
+//!
+//! Every [`Inode`](super::vfs::Inode) and every [`File`](super::File)
+//! describes itself with a [`Metadata`]. File systems fill in what they
+//! keep: easy-fs has inode numbers but no timestamps, `/proc` files have
+//! no number, and once open the time they were rendered at. Nothing has
+//! owners or permissions, the mode bits are the same for every file of a
+//! kind.
+
+use os_macros::kernel_test;
+
+use crate::timer::TimeSpec;
+
+/// Block size `st_blocks` counts in, whatever the file system
+const STAT_BLOCK_SIZE: usize = 512;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    Regular,
+    Directory,
+    CharDevice,
+    Fifo,
+}
+
+impl FileKind {
+    /// `st_mode`: the type bits, and the permissions every file of the kind gets
+    fn mode(self) -> u32 {
+        match self {
+            FileKind::Regular => 0o100_644,
+            FileKind::Directory => 0o040_755,
+            FileKind::CharDevice => 0o020_666,
+            FileKind::Fifo => 0o010_600,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Metadata {
+    pub kind: FileKind,
+    /// Unique in its file system, 0 if it doesn't number its nodes
+    pub ino: u64,
+    pub nlink: u32,
+    /// Bytes
+    pub size: usize,
+    /// Last modification, nanoseconds since the epoch, if kept
+    pub mtime_ns: Option<u64>,
+}
+
+impl Metadata {
+    /// A node without number nor timestamp
+    pub fn new(kind: FileKind, size: usize) -> Self {
+        Self {
+            kind,
+            ino: 0,
+            // a directory is linked from its parent and by its `.`
+            nlink: if kind == FileKind::Directory { 2 } else { 1 },
+            size,
+            mtime_ns: None,
+        }
+    }
+}
+
+/// `struct stat` of the Linux ABI on riscv64
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Stat {
+    pub st_dev: u64,
+    pub st_ino: u64,
+    pub st_mode: u32,
+    pub st_nlink: u32,
+    pub st_uid: u32,
+    pub st_gid: u32,
+    pub st_rdev: u64,
+    __pad1: u64,
+    pub st_size: i64,
+    pub st_blksize: i32,
+    __pad2: i32,
+    pub st_blocks: i64,
+    pub st_atime: TimeSpec,
+    pub st_mtime: TimeSpec,
+    pub st_ctime: TimeSpec,
+    __unused: [u32; 2],
+}
+
+impl From<Metadata> for Stat {
+    fn from(metadata: Metadata) -> Self {
+        // without a record of the others, every time is the modification one
+        let time = TimeSpec::from_ns(metadata.mtime_ns.unwrap_or(0));
+        Self {
+            st_ino: metadata.ino,
+            st_mode: metadata.kind.mode(),
+            st_nlink: metadata.nlink,
+            st_size: metadata.size as i64,
+            st_blksize: STAT_BLOCK_SIZE as i32,
+            st_blocks: metadata.size.div_ceil(STAT_BLOCK_SIZE) as i64,
+            st_atime: time,
+            st_mtime: time,
+            st_ctime: time,
+            ..Self::default()
+        }
+    }
+}
+
+#[kernel_test]
+fn stat_test() {
+    // the size user space allocates
+    assert_eq!(core::mem::size_of::<Stat>(), 128);
+    let stat = Stat::from(Metadata::new(FileKind::Directory, 513));
+    assert_eq!(stat.st_mode, 0o040_755);
+    assert_eq!(stat.st_nlink, 2);
+    assert_eq!(stat.st_blocks, 2);
+}
@@ -1,10 +1,12 @@
 //!Stdin & Stdout
-use sbi_rt::legacy::console_getchar;
+use alloc::sync::Arc;
 
 use super::File;
-use crate::mm::UserBuffer;
+use crate::drivers::console;
+use crate::mm::Buffer;
 use crate::print;
-use crate::task::yield_current;
+use crate::sync::event::Event;
+use crate::task::current_task;
 ///Standard input
 pub struct Stdin;
 ///Standard output
@@ -18,26 +20,18 @@ impl File for Stdin {
         false
     }
     
-    fn read(&self, mut user_buf: UserBuffer) -> usize {
-        assert_eq!(user_buf.len(), 1);
-        // busy loop
-        let mut c: usize;
-        loop {
-            c = console_getchar();
-            if c == 0 {
-                yield_current();
-                continue;
-            } else {
-                break;
-            }
-        }
-        let ch = c as u8;
-        unsafe {
-            user_buf.buffers[0].as_mut_ptr().write_volatile(ch);
-        }
-        1
+    fn poll_readable(&self) -> bool {
+        console::input_ready()
+    }
+    fn register_poller(&self, poller: &Arc<Event>) {
+        console::register_poller(poller);
     }
-    fn write(&self, _user_buf: UserBuffer) -> usize {
+
+    /// Blocks until a line is typed, see `drivers::console`.
+    fn read(&self, user_buf: &mut dyn Buffer) -> usize {
+        console::read(user_buf)
+    }
+    fn write(&self, _user_buf: &dyn Buffer) -> usize {
         panic!("Cannot write to stdin!");
     }
 }
@@ -49,12 +43,19 @@ impl File for Stdout {
     fn writable(&self) -> bool {
         true
     }
-    fn read(&self, _user_buf: UserBuffer) -> usize {
+    fn read(&self, _user_buf: &mut dyn Buffer) -> usize {
         panic!("Cannot read from stdout!");
     }
-    fn write(&self, user_buf: UserBuffer) -> usize {
-        for buffer in user_buf.buffers.iter() {
-            print!("{}", core::str::from_utf8(*buffer).unwrap());
+    fn write(&self, user_buf: &dyn Buffer) -> usize {
+        let Ok(pieces) = user_buf.as_bytes() else {
+            return 0;
+        };
+        let capture = current_task().and_then(|task| task.lock().output_capture.clone());
+        for buffer in pieces {
+            print!("{}", core::str::from_utf8(buffer).unwrap());
+            if let Some(capture) = capture.as_ref() {
+                capture.write(buffer);
+            }
         }
         user_buf.len()
     }
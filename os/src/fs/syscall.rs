@@ -1,92 +1,510 @@
 use core::panic;
 
+use alloc::{vec, vec::Vec};
 use os_macros::syscall_register;
 
-use crate::{mm::{page_table::translated_byte_buffer, user_ptr::UserPtr, UserBuffer}, print, task::{current_task, current_user_token}};
+use crate::{config::PAGE_SIZE, mm::{page_table::copy_to_user, user_ptr::UserPtr, Buffer, UserBuffer}, print, syscall::{args::{populate_user, read_user_str}, error::Errno}, task::{current_task, current_user_token}, timer::{get_time, ns_to_cycles, TimeSpec}};
 
-use super::{open_file, OpenFlags};
+
+use super::{make_pipe, poll::{self, PollFd}, vfs, OpenFlags, Stat, MAX_FDS};
 
 const FD_STDOUT: usize = 1;
 
 
+/// Write `len` bytes at `buf` to `fd`.
+///
+/// # Returns
+/// - The number of bytes written
+/// - `-EBADF` if `fd` isn't open for writing
+/// - `-EFAULT` if the buffer is not mapped
 #[syscall_register(SYSCALL_WRITE)]
 pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> isize {
     let task_guard = current_task().unwrap().lock();
 
-    let token = task_guard.user_res.as_ref().unwrap().memory_set.lock().token();
+    let token = {
+        let mut memory_set = task_guard.user_res.as_ref().unwrap().memory_set.lock();
+        // the buffer is accessed through the page table, lazy pages don't fault
+        if memory_set.populate_range(buf as usize, len).is_err() {
+            return Errno::EFAULT.as_ret();
+        }
+        memory_set.token()
+    };
 
     let fd_table = task_guard.user_res.as_ref().unwrap().fd_table.lock();
-    if fd >= fd_table.len() {
-        return -1;
-    }
-    if let Some(file) = &fd_table[fd] {
-        if !file.writable() {
-            return -1;
-        }
-        let file = file.clone();
-        // release current task TCB manually to avoid multi-borrow
-        drop(fd_table);
-        drop(task_guard);
-        file.write(UserBuffer::new(translated_byte_buffer(token, buf, len).unwrap())) as isize
-    } else {
-        -1
+    let file = match fd_table.get(fd) {
+        Ok(file) if file.writable() => file,
+        Ok(_) => return Errno::EBADF.as_ret(),
+        Err(errno) => return errno.as_ret(),
+    };
+    // release current task TCB manually to avoid multi-borrow
+    drop(fd_table);
+    drop(task_guard);
+    match UserBuffer::new(token, buf, len) {
+        Ok(buf) if buf.as_bytes().is_ok() => file.write(&buf) as isize,
+        _ => Errno::EFAULT.as_ret(),
     }
 }
 
+/// Bytes a single `read` moves at most, like Linux `MAX_RW_COUNT`
+const MAX_READ: usize = 16 * PAGE_SIZE;
+
+/// Read up to `len` bytes from `fd` into `buf`.
+///
+/// The buffer is checked writable by the user before the file fills it, a
+/// read-only buffer is not written to. At most [`MAX_READ`] bytes are read.
+///
+/// # Returns
+/// - The number of bytes read, 0 at the end of the file
+/// - `-EBADF` if `fd` isn't open for reading
+/// - `-EFAULT` if the buffer is not mapped writable
 #[syscall_register(SYSCALL_READ)]
-pub fn sys_read(fd: usize, buf: *const u8, len: usize) -> isize {
+pub fn sys_read(fd: usize, buf: *mut u8, len: usize) -> isize {
+    let len = len.min(MAX_READ);
     let task_guard = current_task().unwrap().lock();
 
-    let token = task_guard.user_res.as_ref().unwrap().memory_set.lock().token();
+    let token = {
+        let mut memory_set = task_guard.user_res.as_ref().unwrap().memory_set.lock();
+        // the buffer is accessed through the page table, lazy pages don't fault
+        if memory_set.populate_range(buf as usize, len).is_err() {
+            return Errno::EFAULT.as_ret();
+        }
+        memory_set.token()
+    };
 
     let fd_table = task_guard.user_res.as_ref().unwrap().fd_table.lock();
-    if fd >= fd_table.len() {
-        return -1;
+    let file = match fd_table.get(fd) {
+        Ok(file) if file.readable() => file,
+        Ok(_) => return Errno::EBADF.as_ret(),
+        Err(errno) => return errno.as_ret(),
+    };
+    // release current task TCB manually, reading may block (e.g. a pipe)
+    drop(fd_table);
+    drop(task_guard);
+
+    let Ok(mut buf) = UserBuffer::new(token, buf, len) else {
+        return Errno::EFAULT.as_ret();
+    };
+    if buf.as_bytes_mut().is_err() {
+        return Errno::EFAULT.as_ret();
     }
-    if let Some(file) = &fd_table[fd] {
-        let file = file.clone();
-        if !file.readable() {
-            return -1;
+    file.read(&mut buf) as isize
+}
+
+
+
+/// Bytes `sendfile` moves through its kernel buffer at a time
+const SENDFILE_CHUNK: usize = PAGE_SIZE;
+
+/// Copy up to `count` bytes from `in_fd` to `out_fd`, in the kernel.
+///
+/// The data goes from the source file to the destination through a
+/// kernel buffer of [`SENDFILE_CHUNK`] bytes, a [`Buffer`] to both files,
+/// never through user memory.
+/// With `offset` null, the read starts at the offset of `in_fd` and moves
+/// it; else it starts at `*offset`, which is moved instead, and the
+/// offset of `in_fd` is left alone. The copy stops early at the end of
+/// the source, or when the destination takes less than it was given,
+/// like a pipe whose readers are gone: without `offset`, the bytes read
+/// but not written are skipped in `in_fd` then.
+///
+/// # Returns
+/// - The number of bytes copied
+/// - `-EBADF` if `in_fd` isn't open for reading, or `out_fd` for writing
+/// - `-ESPIPE` if `offset` is given and `in_fd` is a pipe or a terminal
+/// - `-EINVAL` if `*offset` is negative
+/// - `-EFAULT` if `offset` is not mapped
+#[syscall_register(SYSCALL_SENDFILE)]
+pub fn sys_sendfile(out_fd: usize, in_fd: usize, offset: *mut isize, count: usize) -> isize {
+    let current_task = current_task().unwrap();
+    let task = current_task.lock();
+    let token = task.get_user_token();
+    let fd_table = task.user_res.as_ref().unwrap().fd_table.lock();
+    let (in_file, out_file) = match (fd_table.get(in_fd), fd_table.get(out_fd)) {
+        (Ok(in_file), Ok(out_file)) if in_file.readable() && out_file.writable() => (in_file, out_file),
+        (Err(errno), _) | (_, Err(errno)) => return errno.as_ret(),
+        _ => return Errno::EBADF.as_ret(),
+    };
+    // copying may block on either end
+    drop(fd_table);
+    drop(task);
+
+    // read at `*offset` from the inode, rather than through the file
+    let source = match offset.is_null() {
+        true => None,
+        false => {
+            let Some(inode) = in_file.inode() else {
+                return Errno::ESPIPE.as_ret();
+            };
+            let Ok(start) = UserPtr::new(token, offset as *const isize).read() else {
+                return Errno::EFAULT.as_ret();
+            };
+            let Ok(start) = usize::try_from(start) else {
+                return Errno::EINVAL.as_ret();
+            };
+            Some((inode, start))
+        }
+    };
+
+    let mut chunk = vec![0u8; SENDFILE_CHUNK.min(count)];
+    let mut sent = 0;
+    while sent < count {
+        let want = (count - sent).min(chunk.len());
+        let read_size = match &source {
+            Some((inode, start)) => inode.read_at(start + sent, &mut chunk[..want]),
+            None => in_file.read(&mut &mut chunk[..want]),
+        };
+        if read_size == 0 {
+            break;
+        }
+        let write_size = out_file.write(&&chunk[..read_size]);
+        sent += write_size;
+        if write_size < read_size {
+            break;
         }
-        file.read(UserBuffer::new(translated_byte_buffer(token, buf, len).unwrap())) as isize
-    } else {
-        -1
+    }
+
+    if let Some((_, start)) = source {
+        if UserPtr::new(token, offset as *const isize).write((start + sent) as isize).is_err() {
+            return Errno::EFAULT.as_ret();
+        }
+    }
+    sent as isize
+}
+
+/// Move the offset of `fd`, `whence` is `SEEK_SET`, `SEEK_CUR` or `SEEK_END`.
+///
+/// # Returns
+/// - The new offset
+/// - `-EBADF` if `fd` isn't open
+/// - `-ESPIPE` if `fd` is a pipe or a terminal
+/// - `-EINVAL` for an unknown `whence`, or an offset before the start
+#[syscall_register(SYSCALL_LSEEK)]
+pub fn sys_lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    let current_task = current_task().unwrap();
+    let task = current_task.lock();
+    let file = task.user_res.as_ref().unwrap().fd_table.lock().get(fd);
+    drop(task);
+    match file.and_then(|file| file.seek(offset, whence)) {
+        Ok(offset) => offset as isize,
+        Err(errno) => errno.as_ret(),
+    }
+}
+
+/// Set the size of the file open as `fd` to `len` bytes, growing it
+/// with zeros. The offset stays where it is.
+///
+/// # Returns
+/// - `-EBADF` if `fd` isn't open
+/// - `-EINVAL` if `fd` isn't open for writing, or isn't a regular file
+/// - `-EFBIG` if `len` is more than the file system can hold
+#[syscall_register(SYSCALL_FTRUNCATE)]
+pub fn sys_ftruncate(fd: usize, len: usize) -> isize {
+    let current_task = current_task().unwrap();
+    let task = current_task.lock();
+    let file = task.user_res.as_ref().unwrap().fd_table.lock().get(fd);
+    drop(task);
+    match file.and_then(|file| file.truncate(len)) {
+        Ok(()) => 0,
+        Err(errno) => errno.as_ret(),
     }
 }
 
+/// Write the [`Stat`] of `fd` to `buf`.
+///
+/// # Returns
+/// - `-EBADF` if `fd` isn't open
+/// - `-EFAULT` if `buf` is not mapped writable
+#[syscall_register(SYSCALL_FSTAT)]
+pub fn sys_fstat(fd: usize, buf: *mut Stat) -> isize {
+    let current_task = current_task().unwrap();
+    let task = current_task.lock();
+    let token = task.get_user_token();
+    let file = task.user_res.as_ref().unwrap().fd_table.lock().get(fd);
+    drop(task);
+    let stat = match file {
+        Ok(file) => Stat::from(file.metadata()),
+        Err(errno) => return errno.as_ret(),
+    };
+    match UserPtr::new(token, buf).write(stat) {
+        Ok(()) => 0,
+        Err(_) => Errno::EFAULT.as_ret(),
+    }
+}
 
+/// Write the [`Stat`] of the node at `path` to `buf`, like `fstatat`.
+///
+/// Tasks have no working directory, a relative `path` is taken from `/`
+/// whatever `dirfd` is, and links don't exist: `flags` are ignored.
+///
+/// # Returns
+/// - `-ENOENT` if `path` doesn't exist
+/// - `-ENOTDIR` if a component of `path` isn't a directory
+/// - `-EFAULT` if `path` or `buf` is not mapped
+#[syscall_register(SYSCALL_STAT)]
+pub fn sys_stat(_dirfd: isize, path: *const u8, buf: *mut Stat, _flags: u32) -> isize {
+    let token = current_user_token();
+    let Ok(path) = read_user_str(path) else {
+        return Errno::EFAULT.as_ret();
+    };
+    let stat = match vfs::lookup(&path) {
+        Ok(inode) => Stat::from(inode.metadata()),
+        Err(errno) => return errno.as_ret(),
+    };
+    match UserPtr::new(token, buf).write(stat) {
+        Ok(()) => 0,
+        Err(_) => Errno::EFAULT.as_ret(),
+    }
+}
 
+/// Open `path`, see [`vfs::open`].
+///
+/// # Returns
+/// - The new fd
+/// - `-ENOENT` if `path` doesn't exist and `O_CREAT` isn't given, or its
+///   parent doesn't exist
+/// - `-ENOTDIR` if a parent in `path` isn't a directory
+/// - `-EISDIR` if `path` is a directory opened for writing
+/// - `-EFAULT` if `path` is not mapped
+/// - `-EINVAL` for unknown `flags`
 #[syscall_register(SYSCALL_OPEN)]
 pub fn sys_open(file: *const u8, flags: u32) -> isize{
     let current_task = current_task().unwrap();
-    let token = current_task.lock().get_user_token();
+    let Ok(path) = read_user_str(file) else {
+        return Errno::EFAULT.as_ret();
+    };
 
-    let user_file = UserPtr::new(token, file);
-    let path = user_file.read_to_string();
+    let Some(flags) = OpenFlags::from_bits(flags) else {
+        return Errno::EINVAL.as_ret();
+    };
+    let file = match vfs::open(path.as_str(), flags) {
+        Ok(file) => file,
+        Err(errno) => return errno.as_ret(),
+    };
 
-    if let Some(inode) = open_file(path.as_str(), OpenFlags::from_bits(flags).unwrap()) {
-        let mut task = current_task.lock();
-        let user_res = task.user_res.as_mut().unwrap();
-
-        let fd = user_res.alloc_fd();
-        user_res.fd_table.lock()[fd] = Some(inode);
-        fd as isize
-    } else {
-        -1
+    let task = current_task.lock();
+    let mut fd_table = task.user_res.as_ref().unwrap().fd_table.lock();
+    match fd_table.alloc_fd(file, flags.contains(OpenFlags::CLOEXEC)) {
+        Ok(fd) => fd as isize,
+        Err(errno) => errno.as_ret(),
     }
-
 }
 
+/// Close `fd`, `-EBADF` if it isn't open.
 #[syscall_register(SYSCALL_CLOSE)]
 pub fn sys_close(fd: usize) -> isize{
-    let task = current_task().as_ref().unwrap().lock();
+    let current_task = current_task().unwrap();
+    let task = current_task.lock();
+    let file = task.user_res.as_ref().unwrap().fd_table.lock().close(fd);
+    drop(task);
+    // the last reference may go here, e.g. a pipe end waking its peers
+    match file {
+        Ok(file) => {
+            drop(file);
+            0
+        }
+        Err(errno) => errno.as_ret(),
+    }
+}
+
+/// Duplicate `old_fd` onto the lowest free fd, which is not close-on-exec.
+///
+/// # Returns
+/// - The new fd
+/// - `-EBADF` if `old_fd` isn't open, `-EMFILE` if the table is full
+#[syscall_register(SYSCALL_DUP)]
+pub fn sys_dup(old_fd: usize) -> isize {
+    let current_task = current_task().unwrap();
+    let task = current_task.lock();
+    let result = task.user_res.as_ref().unwrap().fd_table.lock().dup(old_fd, false);
+    match result {
+        Ok(fd) => fd as isize,
+        Err(errno) => errno.as_ret(),
+    }
+}
+
+/// Make `new_fd` refer to the file of `old_fd`, closing what `new_fd`
+/// referred to. `flags` may be `O_CLOEXEC`.
+///
+/// Like `dup2`, duplicating an open fd onto itself is a no-op.
+///
+/// # Returns
+/// - `new_fd`
+/// - `-EBADF` if `old_fd` isn't open or `new_fd` is out of range
+/// - `-EINVAL` for unknown `flags`
+#[syscall_register(SYSCALL_DUP2)]
+pub fn sys_dup2(old_fd: usize, new_fd: usize, flags: u32) -> isize {
+    let Some(flags) = OpenFlags::from_bits(flags).filter(|flags| (OpenFlags::CLOEXEC).contains(*flags)) else {
+        return Errno::EINVAL.as_ret();
+    };
+    let current_task = current_task().unwrap();
+    let task = current_task.lock();
     let mut fd_table = task.user_res.as_ref().unwrap().fd_table.lock();
-    if fd >= fd_table.len() {
-        return -1;
+    let file = match fd_table.get(old_fd) {
+        Ok(file) => file,
+        Err(errno) => return errno.as_ret(),
+    };
+    if old_fd == new_fd {
+        return new_fd as isize;
+    }
+    let replaced = fd_table.install(new_fd, file, flags.contains(OpenFlags::CLOEXEC));
+    drop(fd_table);
+    drop(task);
+    match replaced {
+        Ok(_) => new_fd as isize,
+        Err(errno) => errno.as_ret(),
     }
-    if fd_table[fd].is_none() {
-        return -1;
+}
+
+/// Create a pipe, its read end and write end fds are stored in `pipe[0]` and `pipe[1]`.
+///
+/// # Returns
+/// - 0 on success
+/// - `-EMFILE` if the fd table is full
+/// - `-EFAULT` if `pipe` is not mapped, no fd is left open then
+#[syscall_register(SYSCALL_PIPE)]
+pub fn sys_pipe(pipe: *mut usize) -> isize {
+    let current_task = current_task().unwrap();
+    let mut task = current_task.lock();
+    let user_res = task.user_res.as_mut().unwrap();
+    let token = user_res.memory_set.lock().token();
+
+    let (pipe_read, pipe_write) = make_pipe();
+    let mut fd_table = user_res.fd_table.lock();
+    let Ok(read_fd) = fd_table.alloc_fd(pipe_read, false) else {
+        return Errno::EMFILE.as_ret();
+    };
+    let Ok(write_fd) = fd_table.alloc_fd(pipe_write, false) else {
+        let _ = fd_table.close(read_fd);
+        return Errno::EMFILE.as_ret();
+    };
+    drop(fd_table);
+    drop(task);
+
+    let fds = [read_fd, write_fd];
+    let bytes = unsafe {
+        core::slice::from_raw_parts(fds.as_ptr() as *const u8, core::mem::size_of_val(&fds))
+    };
+    let copied = populate_user(pipe as usize, bytes.len())
+        .and_then(|()| copy_to_user(token, pipe as *mut u8, bytes).map_err(|_| Errno::EFAULT));
+    match copied {
+        Ok(()) => 0,
+        Err(_) => {
+            let task = current_task.lock();
+            let mut fd_table = task.user_res.as_ref().unwrap().fd_table.lock();
+            let _ = fd_table.close(read_fd);
+            let _ = fd_table.close(write_fd);
+            Errno::EFAULT.as_ret()
+        }
+    }
+}
+
+/// Mount a file system of type `fstype` on `target`.
+///
+/// `source` and `data` are unused: every file system type has a single
+/// instance (e.g. `easyfs` is the block device), no flags are supported.
+///
+/// # Returns
+/// - `-ENODEV` for an unknown `fstype`
+/// - `-EBUSY` if something is mounted on `target` already
+/// - `-ENOENT`/`-ENOTDIR` if the parent of `target` isn't a directory
+/// - `-EINVAL` for non-zero `flags`
+/// - `-EFAULT` if `target` or `fstype` is not mapped
+#[syscall_register(SYSCALL_MOUNT)]
+pub fn sys_mount(_source: *const u8, target: *const u8, fstype: *const u8, flags: usize, _data: *const u8) -> isize {
+    if flags != 0 {
+        return Errno::EINVAL.as_ret();
+    }
+    let (Ok(target), Ok(fstype)) = (
+        read_user_str(target),
+        read_user_str(fstype),
+    ) else {
+        return Errno::EFAULT.as_ret();
+    };
+
+    let Some(fs) = vfs::filesystem(fstype.as_str()) else {
+        return Errno::ENODEV.as_ret();
+    };
+    match vfs::mount(target.as_str(), fs) {
+        Ok(()) => 0,
+        Err(errno) => errno.as_ret(),
+    }
+}
+
+/// Unmount the file system mounted on `target`.
+///
+/// # Returns
+/// - `-EINVAL` if nothing is mounted on `target`, or for non-zero `flags`
+/// - `-EBUSY` for `/`, or if another file system is mounted below `target`
+/// - `-EFAULT` if `target` is not mapped
+#[syscall_register(SYSCALL_UMOUNT)]
+pub fn sys_umount(target: *const u8, flags: usize) -> isize {
+    if flags != 0 {
+        return Errno::EINVAL.as_ret();
+    }
+    let Ok(target) = read_user_str(target) else {
+        return Errno::EFAULT.as_ret();
+    };
+    match vfs::umount(target.as_str()) {
+        Ok(()) => 0,
+        Err(errno) => errno.as_ret(),
+    }
+}
+
+/// Wait until one of the `nfds` fds at `fds` is ready for the events it
+/// asks for, or the relative `timeout` expired. A null `timeout` waits
+/// forever, a zero one doesn't block. `sigmask` is ignored, signals don't
+/// interrupt the wait.
+///
+/// # Returns
+/// - The number of fds whose `revents` is set, 0 on timeout
+/// - `-EINVAL` if `nfds` is above the fd table size, or for an invalid `timeout`
+/// - `-EFAULT` if `fds` or `timeout` is not mapped
+#[syscall_register(SYSCALL_PPOLL)]
+pub fn sys_ppoll(fds: *mut PollFd, nfds: usize, timeout: *const TimeSpec, _sigmask: usize) -> isize {
+    if nfds > MAX_FDS {
+        return Errno::EINVAL.as_ret();
+    }
+    let task_guard = current_task().unwrap().lock();
+    let user_res = task_guard.user_res.as_ref().unwrap();
+    let token = {
+        let mut memory_set = user_res.memory_set.lock();
+        // both are accessed through the page table, lazy pages don't fault
+        if memory_set.populate_range(fds as usize, nfds * core::mem::size_of::<PollFd>()).is_err()
+            || (!timeout.is_null()
+                && memory_set.populate_range(timeout as usize, core::mem::size_of::<TimeSpec>()).is_err())
+        {
+            return Errno::EFAULT.as_ret();
+        }
+        memory_set.token()
+    };
+
+    let deadline = if timeout.is_null() {
+        None
+    } else {
+        let Ok(timeout) = UserPtr::new(token, timeout).read() else {
+            return Errno::EFAULT.as_ret();
+        };
+        let Some(ns) = timeout.to_ns() else {
+            return Errno::EINVAL.as_ret();
+        };
+        Some(get_time().saturating_add(ns_to_cycles(ns)))
+    };
+    let Ok(mut poll_fds) = UserPtr::new(token, fds as *const PollFd).read_slice(nfds) else {
+        return Errno::EFAULT.as_ret();
+    };
+    let files: Vec<_> = {
+        let fd_table = user_res.fd_table.lock();
+        poll_fds
+            .iter()
+            .map(|poll_fd| usize::try_from(poll_fd.fd).ok().and_then(|fd| fd_table.get(fd).ok()))
+            .collect()
+    };
+    // polling blocks
+    drop(task_guard);
+
+    let ready = poll::poll(&mut poll_fds, &files, deadline);
+    match UserPtr::new(token, fds as *const PollFd).write_slice(&poll_fds) {
+        Ok(()) => ready as isize,
+        Err(_) => Errno::EFAULT.as_ret(),
     }
-    fd_table[fd].take();
-    0
 }
@@ -0,0 +1,268 @@
+//! Virtual file system
+//!
+//! Every file system implements [`FileSystem`] and hands out [`Inode`]s.
+//! File systems are mounted on absolute paths ([`mount`]), a path is looked
+//! up from the root of the file system mounted on its longest prefix:
+//! `/proc/1/status` is `1/status` in the procfs mounted on `/proc`.
+//!
+//! Tasks have no working directory yet, a relative path is taken from `/`.
+//! `.` and `..` are resolved on the path itself, before the lookup.
+//!
+//! A mount point doesn't have to exist in the file system below it,
+//! only its parent has to be a directory: easy-fs is a single flat
+//! directory, `proc` isn't one of its entries.
+
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use lazy_static::*;
+use os_macros::kernel_test;
+
+use super::{devfs::DevFs, easyfs::EasyFs, procfs::ProcFs, FileKind, FileRef, Metadata, OSInode, OpenFlags};
+use crate::{sync::spin::mutex::IRQSpinLock, syscall::error::Errno};
+
+type Mutex<T> = IRQSpinLock<T>;
+
+/// A node of a file system: a directory or a file
+pub trait Inode: Send + Sync {
+    fn is_dir(&self) -> bool;
+    /// The entry `name` of this directory
+    fn lookup(&self, _name: &str) -> Option<Arc<dyn Inode>> {
+        None
+    }
+    /// Create the file `name` in this directory
+    fn create(&self, _name: &str) -> Option<Arc<dyn Inode>> {
+        None
+    }
+    /// Names of the entries of this directory
+    fn list(&self) -> Vec<String> {
+        Vec::new()
+    }
+    /// Size of this file in bytes
+    fn size(&self) -> usize {
+        0
+    }
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> usize {
+        0
+    }
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> usize {
+        0
+    }
+    /// Set the size of this file to `len` bytes, growing it with zeros.
+    ///
+    /// `EINVAL` for nodes without a size to set: directories, devices,
+    /// generated files.
+    fn truncate(&self, _len: usize) -> Result<(), Errno> {
+        Err(Errno::EINVAL)
+    }
+    /// What `stat` reports, see [`Metadata`]
+    fn metadata(&self) -> Metadata {
+        let kind = if self.is_dir() { FileKind::Directory } else { FileKind::Regular };
+        Metadata::new(kind, self.size())
+    }
+    /// Open this node as its own kind of file.
+    ///
+    /// `None`, the default, opens an [`OSInode`] over `read_at`/`write_at`.
+    fn open(&self, _flags: OpenFlags) -> Option<FileRef> {
+        None
+    }
+}
+
+impl dyn Inode {
+    /// Read the whole file
+    pub fn read_all(&self) -> Vec<u8> {
+        let mut buffer = [0u8; 512];
+        let mut data = Vec::new();
+        loop {
+            let len = self.read_at(data.len(), &mut buffer);
+            if len == 0 {
+                return data;
+            }
+            data.extend_from_slice(&buffer[..len]);
+        }
+    }
+}
+
+pub trait FileSystem: Send + Sync {
+    /// Type name, as given to `mount`
+    fn name(&self) -> &'static str;
+    fn root(&self) -> Arc<dyn Inode>;
+}
+
+/// The file system of type `name`
+pub fn filesystem(name: &str) -> Option<Arc<dyn FileSystem>> {
+    match name {
+        // a single disk, every mount shares it
+        "easyfs" => Some(EasyFs::get()),
+        "procfs" => Some(Arc::new(ProcFs)),
+        "devfs" => Some(Arc::new(DevFs)),
+        _ => None,
+    }
+}
+
+struct Mount {
+    /// Normalized components of the mount point
+    path: Vec<String>,
+    fs: Arc<dyn FileSystem>,
+}
+
+lazy_static! {
+    static ref MOUNTS: Mutex<Vec<Mount>> = Mutex::new(alloc::vec![
+        Mount { path: Vec::new(), fs: EasyFs::get() },
+        Mount { path: alloc::vec!["proc".to_string()], fs: Arc::new(ProcFs) },
+        Mount { path: alloc::vec!["dev".to_string()], fs: Arc::new(DevFs) },
+    ]);
+}
+
+/// The components of `path`, with `.` and `..` resolved
+fn components(path: &str) -> Vec<String> {
+    let mut components: Vec<String> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            // `..` of the root is the root
+            ".." => {
+                components.pop();
+            }
+            name => components.push(name.to_string()),
+        }
+    }
+    components
+}
+
+fn resolve(path: &[String]) -> Result<Arc<dyn Inode>, Errno> {
+    let (fs, depth) = {
+        let mounts = MOUNTS.lock();
+        let mount = mounts
+            .iter()
+            .filter(|mount| path.starts_with(&mount.path))
+            .max_by_key(|mount| mount.path.len())
+            .ok_or(Errno::ENOENT)?;
+        (mount.fs.clone(), mount.path.len())
+    };
+    let mut inode = fs.root();
+    for name in &path[depth..] {
+        if !inode.is_dir() {
+            return Err(Errno::ENOTDIR);
+        }
+        inode = inode.lookup(name).ok_or(Errno::ENOENT)?;
+    }
+    Ok(inode)
+}
+
+/// Find the node at `path`.
+pub fn lookup(path: &str) -> Result<Arc<dyn Inode>, Errno> {
+    resolve(&components(path))
+}
+
+/// Open `path`, creating it in its parent directory with `O_CREAT`.
+///
+/// An existing file opened for writing is emptied by `O_TRUNC`, files
+/// without a size to set (devices, `/proc`) are opened as they are.
+pub fn open(path: &str, flags: OpenFlags) -> Result<FileRef, Errno> {
+    let path = components(path);
+    let (_, writable) = flags.read_write();
+    let inode = match resolve(&path) {
+        Ok(inode) => {
+            if flags.contains(OpenFlags::TRUNC) && writable && !inode.is_dir() {
+                let _ = inode.truncate(0);
+            }
+            inode
+        }
+        Err(Errno::ENOENT) if flags.contains(OpenFlags::CREATE) => {
+            let (name, parent) = path.split_last().ok_or(Errno::EISDIR)?;
+            let parent = resolve(parent)?;
+            if !parent.is_dir() {
+                return Err(Errno::ENOTDIR);
+            }
+            parent.create(name).ok_or(Errno::EACCES)?
+        }
+        Err(errno) => return Err(errno),
+    };
+
+    if writable && inode.is_dir() {
+        return Err(Errno::EISDIR);
+    }
+    match inode.open(flags) {
+        Some(file) => Ok(file),
+        None => Ok(Arc::new(OSInode::new(flags, inode))),
+    }
+}
+
+/// Mount `fs` on `target`, whose parent has to be a directory.
+///
+/// # Returns
+/// - `EBUSY` if something is mounted on `target` already
+pub fn mount(target: &str, fs: Arc<dyn FileSystem>) -> Result<(), Errno> {
+    let path = components(target);
+    if let Some((_, parent)) = path.split_last() {
+        if !resolve(parent)?.is_dir() {
+            return Err(Errno::ENOTDIR);
+        }
+    }
+    let mut mounts = MOUNTS.lock();
+    if mounts.iter().any(|mount| mount.path == path) {
+        return Err(Errno::EBUSY);
+    }
+    log::info!("mount {} on /{}", fs.name(), path.join("/"));
+    mounts.push(Mount { path, fs });
+    Ok(())
+}
+
+/// Unmount the file system mounted on `target`.
+///
+/// Files opened through it stay usable.
+///
+/// # Returns
+/// - `EINVAL` if nothing is mounted on `target`
+/// - `EBUSY` for `/`, or if something is mounted below `target`
+pub fn umount(target: &str) -> Result<(), Errno> {
+    let path = components(target);
+    if path.is_empty() {
+        return Err(Errno::EBUSY);
+    }
+    let mut mounts = MOUNTS.lock();
+    let index = mounts
+        .iter()
+        .position(|mount| mount.path == path)
+        .ok_or(Errno::EINVAL)?;
+    if mounts
+        .iter()
+        .any(|mount| mount.path.len() > path.len() && mount.path.starts_with(&path))
+    {
+        return Err(Errno::EBUSY);
+    }
+    let mount = mounts.remove(index);
+    log::info!("umount {} from /{}", mount.fs.name(), path.join("/"));
+    Ok(())
+}
+
+/// `(mount point, file system type)` of every mount, in mount order
+pub fn mounts() -> Vec<(String, &'static str)> {
+    MOUNTS
+        .lock()
+        .iter()
+        .map(|mount| (alloc::format!("/{}", mount.path.join("/")), mount.fs.name()))
+        .collect()
+}
+
+#[kernel_test]
+fn mount_crossing_test() {
+    assert_eq!(components("a/./b/../../c/"), alloc::vec!["c".to_string()]);
+    assert_eq!(components("/.."), Vec::<String>::new());
+
+    assert!(lookup("/mnt/iomem").is_err());
+    mount("/mnt", Arc::new(ProcFs)).unwrap();
+    assert_eq!(mount("/mnt", Arc::new(ProcFs)), Err(Errno::EBUSY));
+    // the longest mount point wins, `..` goes back up through it
+    assert!(!lookup("/mnt/iomem").unwrap().is_dir());
+    assert!(lookup("/mnt/../proc/iomem").is_ok());
+    assert_eq!(lookup("/mnt/iomem/x").err(), Some(Errno::ENOTDIR));
+
+    umount("/mnt").unwrap();
+    assert_eq!(umount("/mnt"), Err(Errno::EINVAL));
+    assert_eq!(umount("/"), Err(Errno::EBUSY));
+    assert!(lookup("/mnt/iomem").is_err());
+}
@@ -41,11 +41,11 @@ impl InterruptController {
     }
 
     pub fn intr_disable_nested() {
-
-        let processor = get_current_processor();
-
         let old_intr_state = InterruptController::get_state();
         InterruptController::global_disable();
+        // only now: the task may be preempted, and move to another hart,
+        // up to the line above
+        let processor = get_current_processor();
         let old_nest_cnt = processor.increment_nest();
 
 
@@ -3,26 +3,148 @@
 /// output individual characters. It includes a custom `print!` and `println!`
 /// macro for formatting and printing text similarly to Rust’s standard `print!`
 /// and `println!` macros.
-/// 
+///
+/// An SBI call per byte is slow, and the printing code may hold locks: once
+/// its timer runs (see [`start_buffering`]), a hart only queues its output
+/// in its own ring of [`TX_RING_SIZE`] bytes, with interrupts disabled. The
+/// ring is sent from the timer interrupt and before the hart idles, see
+/// [`flush`], or at once by the writer which finds it full. Once the kernel
+/// panics, the rings are drained and everything is sent at once, see
+/// [`enter_panic_mode`].
+
+use crate::{
+    interupt::InterruptController,
+    processor::{current_processor_id, CPU_NUM},
+    sbi::console_putchar,
+};
+use core::{
+    fmt::{self, Write},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use os_macros::kernel_test;
+use spin::Mutex;
+
+/// Bytes of output a hart keeps until it is flushed
+pub const TX_RING_SIZE: usize = 4096;
+
+struct TxRing {
+    bytes: [u8; TX_RING_SIZE],
+    /// Index of the next byte to send
+    head: usize,
+    /// Bytes queued
+    len: usize,
+    /// Whether the output is queued at all: not before the timer of the
+    /// hart flushes it
+    buffered: bool,
+}
+
+impl TxRing {
+    const fn new() -> Self {
+        Self { bytes: [0; TX_RING_SIZE], head: 0, len: 0, buffered: false }
+    }
+
+    /// Queue `byte`, `false` if the ring is full.
+    fn push(&mut self, byte: u8) -> bool {
+        if self.len == TX_RING_SIZE {
+            return false;
+        }
+        self.bytes[(self.head + self.len) % TX_RING_SIZE] = byte;
+        self.len += 1;
+        true
+    }
+
+    fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+        let byte = self.bytes[self.head];
+        self.head = (self.head + 1) % TX_RING_SIZE;
+        self.len -= 1;
+        Some(byte)
+    }
+
+    /// Send everything queued, in order.
+    fn drain(&mut self) {
+        while let Some(byte) = self.pop() {
+            console_putchar(byte as usize);
+        }
+    }
+}
+
+/// Each taken by its hart with interrupts disabled, by another one only
+/// to drain it when the kernel goes down
+static TX_RINGS: [Mutex<TxRing>; CPU_NUM] = [const { Mutex::new(TxRing::new()) }; CPU_NUM];
 
-use crate::sbi::console_putchar;
-use core::fmt::{self, Write};
+/// Set by the panic handler: the output isn't queued anymore
+static PANICKING: AtomicBool = AtomicBool::new(false);
 
+/// Run `f` on the ring of this hart, with interrupts disabled: a handler
+/// printing would otherwise wait for the lock held by the code it
+/// interrupted.
+fn with_local_ring<R>(f: impl FnOnce(&mut TxRing) -> R) -> R {
+    InterruptController::intr_disable_nested();
+    let hart: usize = current_processor_id().into();
+    let ret = f(&mut TX_RINGS[hart].lock());
+    InterruptController::intr_enable_nested();
+    ret
+}
 
+/// Queue the output of this hart from now on, once its timer runs.
+pub fn start_buffering() {
+    with_local_ring(|ring| ring.buffered = true);
+}
 
+/// Send the output queued by this hart. Called from the timer interrupt
+/// and before the hart idles.
+pub fn flush() {
+    with_local_ring(TxRing::drain);
+}
 
+/// Send the output queued by every hart, for the shutdown. A ring whose
+/// hart is in the middle of using it is skipped, rather than waited for.
+pub fn flush_all() {
+    for ring in TX_RINGS.iter() {
+        if let Some(mut ring) = ring.try_lock() {
+            ring.drain();
+        }
+    }
+}
 
+/// Stop queueing the output, after sending what is queued: the panic
+/// message goes out at once, even if the kernel hangs right after.
+pub fn enter_panic_mode() {
+    PANICKING.store(true, Ordering::Release);
+    flush_all();
+}
 
-/// A struct implementing `Write` to send characters to the console via `console_putchar`.
+fn write_bytes(bytes: &[u8]) {
+    if PANICKING.load(Ordering::Acquire) {
+        bytes.iter().for_each(|&byte| console_putchar(byte as usize));
+        return;
+    }
+    with_local_ring(|ring| {
+        for &byte in bytes {
+            if !ring.buffered {
+                console_putchar(byte as usize);
+            } else if !ring.push(byte) {
+                // full: the writer waits for the console, nothing is lost
+                ring.drain();
+                ring.push(byte);
+            }
+        }
+    });
+}
+
+/// A struct implementing `Write` to send text to the console, through the
+/// ring of the hart.
 struct Stdout;
 
 impl Write for Stdout {
-    /// Implements `write_str` by iterating over each character in the given
-    /// string `s` and sending it to `console_putchar`.
+    /// Implements `write_str` by queueing the bytes of `s`, see
+    /// [`write_bytes`].
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        for c in s.chars() {
-            console_putchar(c as usize);
-        }
+        write_bytes(s.as_bytes());
         Ok(())
     }
 }
@@ -30,7 +152,7 @@ impl Write for Stdout {
 /// Prints formatted output to the console.
 ///
 /// This function takes formatted arguments and sends them to `Stdout`
-/// using `write_fmt`, which invokes `console_putchar` for each character.
+/// using `write_fmt`, which queues the text on the ring of this hart.
 ///
 /// # Parameters
 /// - `args`: The formatted arguments to print, created using `format_args!`.
@@ -125,4 +247,26 @@ macro_rules! color_println {
     };
 }
 
-
+#[kernel_test]
+fn tx_ring_test() {
+    let mut ring = TxRing::new();
+    assert_eq!(ring.pop(), None);
+    for i in 0..TX_RING_SIZE {
+        assert!(ring.push(i as u8));
+    }
+    assert!(!ring.push(0));
+    // around the end of the array, in order
+    for i in 0..10 {
+        assert_eq!(ring.pop(), Some(i as u8));
+    }
+    for i in 0..10u8 {
+        assert!(ring.push(100 + i));
+    }
+    for i in 10..TX_RING_SIZE {
+        assert_eq!(ring.pop(), Some(i as u8));
+    }
+    for i in 0..10u8 {
+        assert_eq!(ring.pop(), Some(100 + i));
+    }
+    assert_eq!(ring.pop(), None);
+}
@@ -0,0 +1,249 @@
+//! Kernel log ring and runtime level filters
+//!
+//! Every record the logger lets through is also kept, without colors, in
+//! a ring of [`KLOG_SIZE`] bytes: older lines are overwritten first. User
+//! space reads it with `sys_klog`, like `dmesg` on Linux.
+//!
+//! The level starts as the `LOG` build option, and can be changed at run
+//! time, for everything or for the modules under a path prefix (without
+//! the crate name, e.g. `task::scheduler` or `mm`), the longest matching
+//! prefix wins. The levels of prefixes can be given at boot too, as
+//! `log.<prefix>=<level>` options of the command line.
+//!
+//! The logger may run in any context, interrupt handlers and lock
+//! internals included: the locks here are plain `spin` ones, which don't
+//! log themselves, taken with interrupts disabled.
+use alloc::{string::String, vec::Vec};
+use core::fmt::{self, Write};
+
+use log::{Level, LevelFilter, Metadata};
+use os_macros::{kernel_test, monitor_command};
+use spin::{Mutex, RwLock};
+
+use crate::{interupt::InterruptController, println, timer::get_time_us};
+
+/// Bytes of log kept
+pub const KLOG_SIZE: usize = 16 * 1024;
+
+/// A line longer than this is cut in the ring, not on the console
+const LINE_SIZE: usize = 256;
+
+struct LogRing {
+    bytes: [u8; KLOG_SIZE],
+    /// Bytes ever written, the next one goes at `end % KLOG_SIZE`
+    end: usize,
+    /// Oldest byte still readable, moved by overwriting and clearing
+    start: usize,
+    /// First byte a destructive read hasn't returned yet
+    unread: usize,
+}
+
+impl LogRing {
+    const fn new() -> Self {
+        Self {
+            bytes: [0; KLOG_SIZE],
+            end: 0,
+            start: 0,
+            unread: 0,
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.bytes[self.end % KLOG_SIZE] = byte;
+            self.end += 1;
+        }
+        self.start = self.start.max(self.end.saturating_sub(KLOG_SIZE));
+        self.unread = self.unread.max(self.start);
+    }
+
+    /// At most `len` bytes from `from`, which must be readable
+    fn copy_from(&self, from: usize, len: usize) -> Vec<u8> {
+        (from..self.end.min(from + len))
+            .map(|pos| self.bytes[pos % KLOG_SIZE])
+            .collect()
+    }
+}
+
+static RING: Mutex<LogRing> = Mutex::new(LogRing::new());
+
+struct Filters {
+    default: LevelFilter,
+    /// By module path prefix
+    prefixes: Vec<(String, LevelFilter)>,
+}
+
+impl Filters {
+    fn level_of(&self, target: &str) -> LevelFilter {
+        // `os::mm::tlb` is matched as `mm::tlb`
+        let path = target.split_once("::").map_or("", |(_, path)| path);
+        self.prefixes
+            .iter()
+            .filter(|(prefix, _)| {
+                path.strip_prefix(prefix.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(self.default, |(_, level)| *level)
+    }
+
+    /// The most verbose level of all, for the `log` macros to skip the
+    /// records no filter could let through
+    fn max_level(&self) -> LevelFilter {
+        self.prefixes
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, LevelFilter::max)
+    }
+}
+
+static FILTERS: RwLock<Filters> = RwLock::new(Filters {
+    default: LevelFilter::Off,
+    prefixes: Vec::new(),
+});
+
+/// Run `f` with interrupts disabled, a handler logging on this hart
+/// would otherwise wait for the lock held by the code it interrupted.
+fn without_interrupts<R>(f: impl FnOnce() -> R) -> R {
+    InterruptController::intr_disable_nested();
+    let ret = f();
+    InterruptController::intr_enable_nested();
+    ret
+}
+
+/// Set the level of the modules under `prefix`, of all those without a
+/// more specific one if `prefix` is empty.
+///
+/// `None` removes the level of `prefix`, it follows the shorter ones again.
+pub fn set_level(prefix: &str, level: Option<LevelFilter>) {
+    let prefix = prefix.trim_matches(':');
+    let max_level = without_interrupts(|| {
+        let mut filters = FILTERS.write();
+        filters.prefixes.retain(|(other, _)| other != prefix);
+        match (prefix, level) {
+            ("", level) => filters.default = level.unwrap_or(LevelFilter::Off),
+            (prefix, Some(level)) => filters.prefixes.push((String::from(prefix), level)),
+            (_, None) => {}
+        }
+        filters.max_level()
+    });
+    log::set_max_level(max_level);
+}
+
+/// Whether the logger lets `metadata` through.
+pub fn enabled(metadata: &Metadata) -> bool {
+    metadata.level() <= FILTERS.read().level_of(metadata.target())
+}
+
+/// A line formatted on the stack, cut at [`LINE_SIZE`]
+struct Line {
+    bytes: [u8; LINE_SIZE],
+    len: usize,
+}
+
+impl Write for Line {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let len = s.len().min(LINE_SIZE - self.len);
+        self.bytes[self.len..self.len + len].copy_from_slice(&s.as_bytes()[..len]);
+        self.len += len;
+        Ok(())
+    }
+}
+
+/// Keep a record in the ring.
+pub fn record(level: Level, args: &fmt::Arguments) {
+    let time = get_time_us();
+    let mut line = Line { bytes: [0; LINE_SIZE], len: 0 };
+    // formatted before locking: a `Display` may log, or take locks
+    let _ = write!(line, "[{:>5}.{:06}] {:<5} {}", time / 1_000_000, time % 1_000_000, level, args);
+    if line.len == LINE_SIZE {
+        line.bytes[LINE_SIZE - 1] = b'\n';
+    } else {
+        line.bytes[line.len] = b'\n';
+        line.len += 1;
+    }
+    without_interrupts(|| RING.lock().push(&line.bytes[..line.len]));
+}
+
+/// The last `len` bytes of the ring, which is left as is.
+pub fn read_all(len: usize) -> Vec<u8> {
+    without_interrupts(|| {
+        let ring = RING.lock();
+        ring.copy_from(ring.start.max(ring.end.saturating_sub(len)), len)
+    })
+}
+
+/// At most `len` bytes not returned by a previous call, consumed.
+pub fn read_unread(len: usize) -> Vec<u8> {
+    without_interrupts(|| {
+        let mut ring = RING.lock();
+        let bytes = ring.copy_from(ring.unread, len);
+        ring.unread += bytes.len();
+        bytes
+    })
+}
+
+/// Drop everything in the ring.
+pub fn clear() {
+    without_interrupts(|| {
+        let mut ring = RING.lock();
+        ring.start = ring.end;
+        ring.unread = ring.end;
+    })
+}
+
+/// Bytes not returned by [`read_unread`] yet
+pub fn unread_len() -> usize {
+    without_interrupts(|| {
+        let ring = RING.lock();
+        ring.end - ring.unread
+    })
+}
+
+#[monitor_command(name = "loglevel", help = "Show or set log levels: loglevel [prefix] [off|error|warn|info|debug|trace|-]")]
+fn loglevel_command(args: &[&str]) {
+    let (prefix, level) = match args[1..] {
+        [] => {
+            let filters = FILTERS.read();
+            println!("{:<24} {}", "(default)", filters.default);
+            for (prefix, level) in filters.prefixes.iter() {
+                println!("{:<24} {}", prefix, level);
+            }
+            return;
+        }
+        [level] => ("", level),
+        [prefix, level] => (prefix, level),
+        _ => {
+            println!("loglevel: too many arguments");
+            return;
+        }
+    };
+    let level = match level {
+        "-" => None,
+        level => match level.parse::<LevelFilter>() {
+            Ok(level) => Some(level),
+            Err(_) => {
+                println!("loglevel: unknown level `{}`", level);
+                return;
+            }
+        },
+    };
+    set_level(prefix, level);
+}
+
+#[kernel_test]
+fn log_filter_prefix_test() {
+    let filters = Filters {
+        default: LevelFilter::Warn,
+        prefixes: alloc::vec![
+            (String::from("mm"), LevelFilter::Info),
+            (String::from("mm::tlb"), LevelFilter::Trace),
+        ],
+    };
+    assert_eq!(filters.level_of("os::task::scheduler"), LevelFilter::Warn);
+    assert_eq!(filters.level_of("os::mm::memory_set"), LevelFilter::Info);
+    assert_eq!(filters.level_of("os::mm::tlb"), LevelFilter::Trace);
+    // not a path component
+    assert_eq!(filters.level_of("os::mmio"), LevelFilter::Warn);
+    assert_eq!(filters.max_level(), LevelFilter::Trace);
+}
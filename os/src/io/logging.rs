@@ -4,19 +4,30 @@
 //! based on their severity level (error, warn, info, debug, trace). It relies on the `log` crate
 //! to capture log messages and format them using ANSI escape codes for color output in the Linux console.
 //!
-//! 
+//! Each line tells when, where and from what module it was logged:
+//!
+//! ```text
+//! [KERNEL][    2.041733][ INFO][0,3 user_shell][mm::memory_set] message
+//! ```
+//!
+//! seconds since boot, the level, the hart and the tid and name of the
+//! task it runs (`-` in the scheduler loop), and the module path without
+//! the crate name, which is what the levels of `klog` are set by.
 
 
 // use lazy_static::lazy_static;
+use core::fmt;
+
 use log::{self, Level, LevelFilter, Log, Metadata, Record};
 
-use crate::color_println;
+use crate::{color_println, processor::get_current_processor, timer::get_time_us};
 
-use super::console::Color;
+use super::{console::Color, klog};
 
 /// # Initialization
 /// The logger is initialized using the `init` function, which sets up the logging system based on the
-/// `LOG` environment variable. The available log levels are:
+/// `log` option of the kernel command line, or the `LOG` build environment variable before the
+/// command line is read (see `cmdline`). The available log levels, in any case, are:
 /// - "error" -> `LevelFilter::Error`
 /// - "warn" -> `LevelFilter::Warn`
 /// - "info" -> `LevelFilter::Info`
@@ -29,31 +40,53 @@ use super::console::Color;
 /// # Example
 /// To use this module, simply call `init()` from `rust_main` and use the `log!` macros
 /// (like `error!`, `warn!`, `info!`, etc.) for logging.
+///
+/// The level is only the initial one, see `klog::set_level` to change it.
 pub fn init() {
     static LOGGER: OSLogger = OSLogger;
     log::set_logger(&LOGGER).unwrap();
-    log::set_max_level(
-        match option_env!("LOG") {
-            Some("ERROR") => LevelFilter::Error,
-            Some("WARN") => LevelFilter::Warn,
-            Some("INFO") => LevelFilter::Info,
-            Some("DEBUG") => LevelFilter::Trace,
-            _ => LevelFilter::Off,
+    set_boot_level();
+}
+
+/// Set the level from the `log` option, again once the command line is read,
+/// and the levels of modules from the `log.<module>` ones.
+pub fn set_boot_level() {
+    let level = crate::cmdline::get("log").unwrap_or_default();
+    klog::set_level("", Some(parse_level(level).unwrap_or(LevelFilter::Off)));
+    for (prefix, level) in crate::cmdline::scoped("log") {
+        match parse_level(level) {
+            Some(level) => klog::set_level(prefix, Some(level)),
+            None => log::warn!("cmdline: unknown log level `{}` for `{}`", level, prefix),
         }
-    );
+    }
 }
 
-/// A custom logger that prints log messages to the console with color coding.
+/// A level of the command line, `debug` shows everything
+fn parse_level(level: &str) -> Option<LevelFilter> {
+    match level {
+        _ if level.eq_ignore_ascii_case("off") => Some(LevelFilter::Off),
+        _ if level.eq_ignore_ascii_case("error") => Some(LevelFilter::Error),
+        _ if level.eq_ignore_ascii_case("warn") => Some(LevelFilter::Warn),
+        _ if level.eq_ignore_ascii_case("info") => Some(LevelFilter::Info),
+        _ if level.eq_ignore_ascii_case("debug") || level.eq_ignore_ascii_case("trace") => {
+            Some(LevelFilter::Trace)
+        }
+        _ => None,
+    }
+}
+
+/// A custom logger that prints log messages to the console with color coding,
+/// and keeps them in the kernel log ring.
 ///
 /// This logger formats the log message based on its severity level, using ANSI escape sequences
 /// for color output. It supports all log levels provided by the `log` crate.
 struct OSLogger;
 
 impl Log for OSLogger {
-    /// Determines whether the log message should be processed, based on the log level.
-    #[warn(unused_variables)]
-    fn enabled(&self, _metadata: &Metadata) -> bool {
-        true
+    /// Determines whether the log message should be processed, based on
+    /// the level of its module.
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        klog::enabled(metadata)
     }
 
     /// Processes the log message and prints it to the console with color formatting.
@@ -63,16 +96,37 @@ impl Log for OSLogger {
         }
 
         let color = level_to_color(record.level());
+        let time = get_time_us();
+        // `os::mm::tlb` as `mm::tlb`
+        let module = record.target().split_once("::").map_or(record.target(), |(_, path)| path);
 
         color_println!(
-            color,"[KERNEL][{:>5}][0,-] {}\n", record.level(), record.args(),
+            color,"[KERNEL][{:>5}.{:06}][{:>5}][{}][{}] {}\n",
+            time / 1_000_000, time % 1_000_000, record.level(), Origin, module, record.args(),
         );
+        klog::record(record.level(), record.args());
     }
 
     /// Flushes the log output (no-op in this case).
     fn flush(&self) {}
 }
 
+/// The hart logging, and the task it runs, as `hart,tid name`
+///
+/// Read from the processor local state: the logger may run with the lock
+/// of the task held.
+struct Origin;
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let processor = get_current_processor();
+        match processor.get_current_task() {
+            Some(task) => write!(f, "{},{} {}", processor.hart_id(), usize::from(task.get_tid()), task.get_name()),
+            None => write!(f, "{},-", processor.hart_id()),
+        }
+    }
+}
+
 /// Converts a log level to the corresponding ANSI color code.
 ///
 /// This function maps the log levels (error, warn, info, debug, trace) to the respective
@@ -1,6 +1,13 @@
 pub mod console;
+pub mod klog;
 mod logging;
+mod syscall;
 
 pub fn init() {
     logging::init();
 }
+
+/// Take the log level of the command line, once `cmdline::init` read it.
+pub fn set_boot_log_level() {
+    logging::set_boot_level();
+}
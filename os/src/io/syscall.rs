@@ -0,0 +1,83 @@
+use os_macros::syscall_register;
+
+use crate::{
+    mm::page_table::copy_to_user,
+    syscall::{args::read_user_str, error::Errno},
+    task::current_task,
+};
+
+use super::klog;
+
+/// `action` of `klog`, Linux `syslog` values
+const SYSLOG_ACTION_READ: usize = 2;
+const SYSLOG_ACTION_READ_ALL: usize = 3;
+const SYSLOG_ACTION_READ_CLEAR: usize = 4;
+const SYSLOG_ACTION_CLEAR: usize = 5;
+const SYSLOG_ACTION_CONSOLE_LEVEL: usize = 8;
+const SYSLOG_ACTION_SIZE_UNREAD: usize = 9;
+const SYSLOG_ACTION_SIZE_BUFFER: usize = 10;
+
+/// Read or control the kernel log ring, like Linux `syslog`.
+///
+/// - `READ` returns at most `len` bytes not read before, without waiting
+/// - `READ_ALL` returns the last `len` bytes, `READ_CLEAR` clears them then
+/// - `CLEAR` drops everything
+/// - `CONSOLE_LEVEL` sets the level to `len`, from 0 for `off` to 5 for
+///   `trace`. Of the modules under the NUL-terminated prefix `buf` if not
+///   null, of all the others else
+/// - `SIZE_UNREAD` and `SIZE_BUFFER` return what `READ` would, the size
+///   of the ring
+///
+/// # Returns
+/// - the number of bytes read, 0 for the other actions
+/// - `-EINVAL` for an unknown `action` or level
+/// - `-EFAULT` if `buf` is not mapped
+#[syscall_register(SYSCALL_SYSLOG)]
+pub fn sys_klog(action: usize, buf: *mut u8, len: usize) -> isize {
+    let bytes = match action {
+        SYSLOG_ACTION_READ => klog::read_unread(len),
+        SYSLOG_ACTION_READ_ALL | SYSLOG_ACTION_READ_CLEAR => {
+            let bytes = klog::read_all(len);
+            if action == SYSLOG_ACTION_READ_CLEAR {
+                klog::clear();
+            }
+            bytes
+        }
+        SYSLOG_ACTION_CLEAR => {
+            klog::clear();
+            return 0;
+        }
+        SYSLOG_ACTION_CONSOLE_LEVEL => {
+            let Some(level) = log::LevelFilter::iter().nth(len) else {
+                return Errno::EINVAL.as_ret();
+            };
+            let prefix = if buf.is_null() {
+                alloc::string::String::new()
+            } else {
+                match read_user_str(buf) {
+                    Ok(prefix) => prefix,
+                    Err(_) => return Errno::EFAULT.as_ret(),
+                }
+            };
+            klog::set_level(&prefix, Some(level));
+            return 0;
+        }
+        SYSLOG_ACTION_SIZE_UNREAD => return klog::unread_len() as isize,
+        SYSLOG_ACTION_SIZE_BUFFER => return klog::KLOG_SIZE as isize,
+        _ => return Errno::EINVAL.as_ret(),
+    };
+
+    let token = {
+        let task_guard = current_task().unwrap().lock();
+        let mut memory_set = task_guard.user_res.as_ref().unwrap().memory_set.lock();
+        // written through the page table, a lazy page doesn't fault
+        if memory_set.populate_range(buf as usize, bytes.len()).is_err() {
+            return Errno::EFAULT.as_ret();
+        }
+        memory_set.token()
+    };
+    match copy_to_user(token, buf, &bytes) {
+        Ok(()) => bytes.len() as isize,
+        Err(_) => Errno::EFAULT.as_ret(),
+    }
+}
@@ -5,9 +5,29 @@
 /// behavior for the program when a panic occurs, ensuring the program can shut down gracefully
 /// or perform other custom operations when an error occurs.
 
-use core::panic::PanicInfo;
+use core::{
+    panic::PanicInfo,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
-use crate::{println, sbi::shutdown, tools::backtrace::trace};
+use crate::{
+    io::console,
+    println,
+    shutdown::panic_shutdown,
+    tools::{backtrace::trace, symbols::Symbolized},
+};
+
+/// The `fn(&PanicInfo)` set by [`set_panic_hook`], 0 for none
+static PANIC_HOOK: AtomicUsize = AtomicUsize::new(0);
+
+/// Have `hook` called by the panic handler once the message is printed,
+/// `None` to reset it. A hook which returns lets the handler go on with the
+/// backtrace and the shutdown, one which doesn't recovers from the panic:
+/// the kernel test runner switches back to itself.
+#[allow(unused)]
+pub fn set_panic_hook(hook: Option<fn(&PanicInfo)>) {
+    PANIC_HOOK.store(hook.map_or(0, |hook| hook as usize), Ordering::Release);
+}
 
 /// Custom panic handler that is triggered when the program encounters a panic.
 ///
@@ -22,13 +42,17 @@ use crate::{println, sbi::shutdown, tools::backtrace::trace};
 ///   This includes the message, file, and line number where the panic occurred.
 ///
 /// # Behavior
+/// - The console stops queueing output, see [`console::enter_panic_mode`].
 /// - If the panic contains location information (i.e., file and line), it is printed.
 /// - If no location is available, only the panic message is printed.
-/// - The system is then shut down by calling the `shutdown` function.
+/// - The hook of [`set_panic_hook`] runs, if any.
+/// - The system is then shut down by calling `panic_shutdown`, which only runs
+///   the panic-safe shutdown hooks.
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
+    // what was queued first, then the message, synchronously
+    console::enter_panic_mode();
 
-    
     // Check if panic has a location (file and line number) information
     if let Some(location) = info.location() {
         // If panic occurred in a specific location, print the file, line, and the message
@@ -43,18 +67,24 @@ fn panic(info: &PanicInfo) -> ! {
         println!("Panicked: {}", info.message());
     }
 
+    let hook = PANIC_HOOK.load(Ordering::Acquire);
+    if hook != 0 {
+        // only ever stored from a `fn(&PanicInfo)`
+        let hook: fn(&PanicInfo) = unsafe { core::mem::transmute(hook) };
+        hook(info);
+    }
+
     // 收集栈回溯
     let backtrace = trace(18);
 
     // 打印回溯信息
     println!("Backtrace ({} frames):", backtrace.len());
     for (i, frame) in backtrace.iter().enumerate() {
-        println!("  #{:02} fp={:#x} ra={:#x}", i, frame.fp, frame.ra);
+        println!("  #{:02} fp={:#x} ra={:#x} {}", i, frame.fp, frame.ra, Symbolized(frame.ra));
     }
 
-    // Call shutdown function from the SBI to halt the system
-    // The argument `true` indicates that the shutdown should be initiated due to a panic
-    shutdown(true)
+    // Run the panic-safe shutdown hooks and halt the system with a failure
+    panic_shutdown()
 }
 
 
@@ -66,6 +66,11 @@ mod register;
 mod tools;
 mod test_framework;
 mod fs;
+mod monitor;
+mod shutdown;
+mod sysctl;
+mod random;
+mod cmdline;
 
 extern crate alloc;
 mod mm;
@@ -82,10 +87,11 @@ global_asm!(include_str!("entry.asm"));
 
 
 /// - hart_id would be place in a0
+/// - the device tree address in a1, `entry.asm` leaves it untouched
 /// - Would be called by `entry.asm`.
 /// - Don't return.
 #[no_mangle]
-pub fn rust_main(hart_id: usize) -> ! {
+pub fn rust_main(hart_id: usize, dtb_pa: usize) -> ! {
     clear_bss();
     init_processor(hart_id);
 
@@ -95,7 +101,8 @@ pub fn rust_main(hart_id: usize) -> ! {
     log::debug!("Debug Logger turn on");
     log::info!("Current hart id: {}", hart_id);
     
-    mm::init();
+    mm::init(dtb_pa);
+    io::set_boot_log_level();
     mm::heap_allocator::heap_test();
 
     mm::memory_set::remap_test();
@@ -109,6 +116,8 @@ pub fn rust_main(hart_id: usize) -> ! {
 
     syscall::init();
 
+    monitor::init();
+
     log::info!("XUX-OS initilize successed!");
     print_info();
     log::debug!("print end");
@@ -117,19 +126,49 @@ pub fn rust_main(hart_id: usize) -> ! {
     #[cfg(test)]
     test_main();
 
+    #[cfg(feature = "monitor")]
+    monitor::run();
+
     task::init_scheduler();
 
+    drivers::init();
     trap::enable_timer_interrupt();
+    trap::enable_external_interrupt();
+    trap::enable_soft_interrupt();
     timer::set_next_trigger();
+    io::console::start_buffering();
     
     log::info!("test successed!Welcom ot xux-os!");
 
+    processor::start_secondary_harts(hart_id);
+
 
     schedule_loop();
     unreachable!();
     
 }
 
+/// Entry of the secondary harts, called by `entry.asm` with paging off.
+///
+/// The boot hart set up everything shared, only what is per hart is left:
+/// the kernel page table, `tp`, the trap entry and the timer.
+#[no_mangle]
+pub fn rust_main_secondary(hart_id: usize) -> ! {
+    // `tp` first, every lock goes through the processor local state
+    processor::init_secondary_processor(hart_id);
+    mm::KERNEL_SPACE.lock().activate();
+    trap::init();
+    trap::enable_timer_interrupt();
+    trap::enable_soft_interrupt();
+    timer::set_next_trigger();
+    io::console::start_buffering();
+    processor::set_online();
+    log::info!("hart {} online", hart_id);
+
+    schedule_loop();
+    unreachable!();
+}
+
 /// Clears the `.bss` section by setting each byte to zero.
 ///
 /// The `.bss` section is used to store uninitialized global and static variables,
@@ -0,0 +1,139 @@
+//! Address space identifiers
+//!
+//! Every user address space gets its own ASID: the TLB tells their
+//! translations apart, switching between them flushes nothing. The
+//! kernel space, and the user spaces left without an ASID (more spaces
+//! than ASIDs, or none implemented like on the k210), share
+//! [`SHARED_ASID`], whose translations are flushed on every switch (see
+//! `trap.S`).
+//!
+//! The ASID of a dropped space may still tag translations in the TLB of
+//! any hart. Released ASIDs are only handed out again once the fresh
+//! ones run out, after a single flush of every hart for all of them:
+//! each such round starts a new generation.
+use alloc::vec::Vec;
+
+use os_macros::kernel_test;
+use riscv::register::satp;
+
+use super::tlb;
+use crate::{
+    config::{SATP_ASID_MASK, SATP_ROOT_PPN_BITS},
+    processor::ipi,
+    sync::spin::mutex::IRQSpinLock,
+};
+
+type Mutex<T> = IRQSpinLock<T>;
+
+/// The ASID of the kernel space, and of the user spaces without one
+pub const SHARED_ASID: usize = 0;
+
+/// The ASID of an address space, released when dropped
+pub struct Asid(usize);
+
+impl Asid {
+    /// A fresh ASID, or [`SHARED_ASID`] if all are taken.
+    pub fn alloc() -> Self {
+        Self(ASID_ALLOCATOR.lock().alloc())
+    }
+
+    /// [`SHARED_ASID`], for the kernel space
+    pub fn shared() -> Self {
+        Self(SHARED_ASID)
+    }
+
+    pub fn get(&self) -> usize {
+        self.0
+    }
+}
+
+impl Drop for Asid {
+    fn drop(&mut self) {
+        if self.0 != SHARED_ASID {
+            ASID_ALLOCATOR.lock().release(self.0);
+        }
+    }
+}
+
+struct AsidAllocator {
+    /// Number of ASIDs the harts implement, [`SHARED_ASID`] included
+    limit: usize,
+    /// Never handed out from here up
+    next: usize,
+    /// Flushed from every TLB since released
+    free: Vec<usize>,
+    /// Released during the current generation
+    released: Vec<usize>,
+    generation: usize,
+}
+
+impl AsidAllocator {
+    const fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            next: SHARED_ASID + 1,
+            free: Vec::new(),
+            released: Vec::new(),
+            generation: 0,
+        }
+    }
+
+    fn alloc(&mut self) -> usize {
+        if let Some(asid) = self.free.pop() {
+            return asid;
+        }
+        if self.next < self.limit {
+            self.next += 1;
+            return self.next - 1;
+        }
+        if self.released.is_empty() {
+            return SHARED_ASID;
+        }
+        // no hart caches a translation of the released ones after this
+        tlb::flush_all();
+        ipi::tlb_shootdown();
+        self.generation += 1;
+        log::debug!("ASID generation {}, {} recycled", self.generation, self.released.len());
+        self.free = core::mem::take(&mut self.released);
+        self.free.pop().unwrap()
+    }
+
+    fn release(&mut self, asid: usize) {
+        self.released.push(asid);
+    }
+}
+
+/// Only [`SHARED_ASID`] until [`init`] found out how many there are
+static ASID_ALLOCATOR: Mutex<AsidAllocator> = Mutex::new(AsidAllocator::new(1));
+
+/// Find out how many ASIDs the hart implements, once paging is on.
+///
+/// The ASID field of `satp` keeps only the bits implemented: writing all
+/// ones reads back the highest ASID.
+pub fn init() {
+    let satp = satp::read().bits();
+    unsafe {
+        satp::write(satp | SATP_ASID_MASK << SATP_ROOT_PPN_BITS);
+    }
+    let max_asid = (satp::read().bits() >> SATP_ROOT_PPN_BITS) & SATP_ASID_MASK;
+    unsafe {
+        satp::write(satp);
+    }
+    tlb::flush_all();
+    ASID_ALLOCATOR.lock().limit = max_asid + 1;
+    log::info!("{} ASIDs", max_asid + 1);
+}
+
+#[kernel_test]
+fn asid_recycle_test() {
+    let mut allocator = AsidAllocator::new(3);
+    assert_eq!(allocator.alloc(), 1);
+    assert_eq!(allocator.alloc(), 2);
+    // all taken, share
+    assert_eq!(allocator.alloc(), SHARED_ASID);
+    allocator.release(1);
+    assert_eq!(allocator.generation, 0);
+    assert_eq!(allocator.alloc(), 1);
+    assert_eq!(allocator.generation, 1);
+    assert_eq!(allocator.alloc(), SHARED_ASID);
+}
@@ -0,0 +1,278 @@
+//! Executable formats `exec` knows
+//!
+//! The first format of [`FORMATS`] recognizing the file takes it:
+//! - ELF executables, see `elf`
+//! - scripts starting with a `#!interpreter [arg]` line: the interpreter
+//!   runs instead, with `argv` `[interpreter, arg, script]`. It may be a
+//!   script itself, up to [`MAX_INTERPRETERS`] deep
+//! - anything else is a flat binary: raw code and data linked at
+//!   [`FLAT_BASE`], entered at its first byte
+//!
+//! The program starts with `argv` and `envp` on its stack, and `argc` and
+//! `argv` in `a0` and `a1` too, see [`Program::push_initial_stack`].
+//!
+//! A flat binary says nothing of its `.bss`: [`FLAT_BSS_SIZE`] bytes past
+//! its end are mapped zeroed for it.
+
+use alloc::{string::String, vec, vec::Vec};
+use os_macros::kernel_test;
+
+use super::{
+    elf::{ElfImage, AT_ENTRY, AT_NULL, AT_PAGESZ},
+    error::MemoryError,
+    memory_set::MemorySet,
+    page_table::copy_to_user,
+};
+use crate::{
+    config::{FLAT_BASE, PAGE_SIZE, USER_STACK_SIZE},
+    fs::vfs,
+    syscall::error::Errno,
+};
+
+/// Interpreters followed before `exec` gives up with `ELOOP`, like Linux
+pub const MAX_INTERPRETERS: usize = 4;
+/// Bytes the strings of `argv` and `envp` and their pointers may take on
+/// the initial stack, `E2BIG` past it: the rest is left to the program
+pub const ARG_MAX: usize = USER_STACK_SIZE / 2;
+/// Longest `#!` line, the newline included
+const SHEBANG_MAX: usize = 256;
+/// Zeroed bytes mapped past a flat binary
+pub const FLAT_BSS_SIZE: usize = 64 * PAGE_SIZE;
+
+/// A file `exec` was asked to run, and the `argv` and `envp` it gets
+pub struct Binprm {
+    pub path: String,
+    pub data: Vec<u8>,
+    pub argv: Vec<String>,
+    pub envp: Vec<String>,
+}
+
+impl Binprm {
+    /// Read the file at `path`, run with `argv` and `envp`.
+    ///
+    /// `E2BIG` if they take more than [`ARG_MAX`] bytes.
+    pub fn open(path: &str, argv: Vec<String>, envp: Vec<String>) -> Result<Self, Errno> {
+        let size: usize = argv.iter().chain(&envp).map(|arg| arg.len() + 1 + size_of::<usize>()).sum();
+        if size > ARG_MAX {
+            return Err(Errno::E2BIG);
+        }
+        let inode = vfs::lookup(path)?;
+        if inode.is_dir() {
+            return Err(Errno::EACCES);
+        }
+        Ok(Self { path: String::from(path), data: inode.read_all(), argv, envp })
+    }
+}
+
+/// A format of executables
+pub trait BinaryFmt: Sync {
+    fn name(&self) -> &'static str;
+    /// Whether `data` is of this format, checked in the order of [`FORMATS`]
+    fn recognizes(&self, data: &[u8]) -> bool;
+    /// The file to run instead of `prm`, for a format which is
+    /// interpreted. `None` for one which is mapped.
+    fn interpreter(&self, _prm: &Binprm) -> Result<Option<Binprm>, Errno> {
+        Ok(None)
+    }
+    /// Check `prm` before the image of the caller is torn down.
+    fn load<'a>(&self, prm: &'a Binprm) -> Result<Executable<'a>, Errno>;
+}
+
+struct ElfFormat;
+struct ScriptFormat;
+struct FlatFormat;
+
+/// The formats, the catch-all flat binaries last
+pub static FORMATS: [&dyn BinaryFmt; 3] = [&ElfFormat, &ScriptFormat, &FlatFormat];
+
+impl BinaryFmt for ElfFormat {
+    fn name(&self) -> &'static str {
+        "elf"
+    }
+
+    fn recognizes(&self, data: &[u8]) -> bool {
+        data.starts_with(b"\x7fELF")
+    }
+
+    fn load<'a>(&self, prm: &'a Binprm) -> Result<Executable<'a>, Errno> {
+        match ElfImage::parse(&prm.data) {
+            Ok(image) => Ok(Executable::Elf(image)),
+            Err(err) => {
+                log::debug!("exec: {} is not runnable: {:?}", prm.path, err);
+                Err(Errno::from(err))
+            }
+        }
+    }
+}
+
+impl BinaryFmt for ScriptFormat {
+    fn name(&self) -> &'static str {
+        "script"
+    }
+
+    fn recognizes(&self, data: &[u8]) -> bool {
+        data.starts_with(b"#!")
+    }
+
+    fn interpreter(&self, prm: &Binprm) -> Result<Option<Binprm>, Errno> {
+        let (interpreter, arg) = parse_shebang(&prm.data)?;
+        let mut argv = vec![String::from(interpreter)];
+        argv.extend(arg.map(String::from));
+        argv.push(prm.path.clone());
+        // the script's own argv[0] is replaced by its path
+        argv.extend(prm.argv.iter().skip(1).cloned());
+        Binprm::open(interpreter, argv, prm.envp.clone()).map(Some)
+    }
+
+    fn load<'a>(&self, _prm: &'a Binprm) -> Result<Executable<'a>, Errno> {
+        unreachable!("a script runs its interpreter")
+    }
+}
+
+/// The interpreter of a `#!` line, and its optional argument: the rest
+/// of the line, as on Linux
+fn parse_shebang(data: &[u8]) -> Result<(&str, Option<&str>), Errno> {
+    let head = &data[2..data.len().min(SHEBANG_MAX)];
+    let line = match head.iter().position(|&byte| byte == b'\n') {
+        Some(len) => &head[..len],
+        None if data.len() <= SHEBANG_MAX => head,
+        None => return Err(Errno::ENOEXEC),
+    };
+    let line = core::str::from_utf8(line).map_err(|_| Errno::ENOEXEC)?.trim();
+    let (interpreter, arg) = match line.split_once(|ch: char| ch == ' ' || ch == '\t') {
+        Some((interpreter, arg)) => (interpreter, Some(arg.trim()).filter(|arg| !arg.is_empty())),
+        None => (line, None),
+    };
+    if interpreter.is_empty() {
+        return Err(Errno::ENOEXEC);
+    }
+    Ok((interpreter, arg))
+}
+
+impl BinaryFmt for FlatFormat {
+    fn name(&self) -> &'static str {
+        "flat"
+    }
+
+    fn recognizes(&self, data: &[u8]) -> bool {
+        !data.is_empty()
+    }
+
+    fn load<'a>(&self, prm: &'a Binprm) -> Result<Executable<'a>, Errno> {
+        Ok(Executable::Flat(FlatImage { data: &prm.data }))
+    }
+}
+
+/// A raw binary, mapped at [`FLAT_BASE`]
+pub struct FlatImage<'a> {
+    pub data: &'a [u8],
+}
+
+/// A checked executable, ready to be mapped
+pub enum Executable<'a> {
+    Elf(ElfImage<'a>),
+    Flat(FlatImage<'a>),
+}
+
+/// What `exec` maps: an executable and the `argv` and `envp` it starts with
+pub struct Program<'a> {
+    pub executable: Executable<'a>,
+    pub argv: &'a [String],
+    pub envp: &'a [String],
+}
+
+impl<'a> Program<'a> {
+    /// Follow the interpreters of `prm`, which becomes the file finally
+    /// run, and check it.
+    ///
+    /// `ENOEXEC` for a file no format takes, `ELOOP` past
+    /// [`MAX_INTERPRETERS`] interpreters, and the errors of looking an
+    /// interpreter up.
+    pub fn load(prm: &'a mut Binprm) -> Result<Self, Errno> {
+        for _ in 0..=MAX_INTERPRETERS {
+            let format = FORMATS
+                .iter()
+                .find(|format| format.recognizes(&prm.data))
+                .ok_or(Errno::ENOEXEC)?;
+            if let Some(next) = format.interpreter(prm)? {
+                log::debug!("exec: {} runs {} as {}", next.path, prm.path, format.name());
+                *prm = next;
+                continue;
+            }
+            let prm: &'a Binprm = prm;
+            return Ok(Self { executable: format.load(prm)?, argv: &prm.argv, envp: &prm.envp });
+        }
+        Err(Errno::ELOOP)
+    }
+
+    pub fn entry(&self) -> usize {
+        match &self.executable {
+            Executable::Elf(image) => image.entry,
+            Executable::Flat(_) => FLAT_BASE,
+        }
+    }
+
+    /// Build the address space, returns it with the base of the user stack.
+    pub fn map(&mut self, randomize: bool) -> (MemorySet, usize) {
+        match &mut self.executable {
+            Executable::Elf(image) => MemorySet::from_elf(image, randomize),
+            Executable::Flat(image) => MemorySet::from_flat(image, randomize),
+        }
+    }
+
+    /// Lay out the initial stack below `top` in the address space `token`,
+    /// as the psABI wants it: `argc`, the `argv` and `envp` arrays and the
+    /// auxiliary vector, the strings above them. Returns the stack pointer
+    /// to start with, `argv` is right above it.
+    pub fn push_initial_stack(&self, token: usize, top: usize) -> Result<usize, MemoryError> {
+        let auxv = match &self.executable {
+            Executable::Elf(image) => image.auxv(),
+            Executable::Flat(_) => vec![(AT_PAGESZ, PAGE_SIZE), (AT_ENTRY, FLAT_BASE), (AT_NULL, 0)],
+        };
+
+        let mut sp = top;
+        let mut push_strings = |strings: &[String], words: &mut Vec<usize>| -> Result<(), MemoryError> {
+            for string in strings {
+                sp -= string.len() + 1;
+                copy_to_user(token, sp as *mut u8, string.as_bytes())?;
+                copy_to_user(token, (sp + string.len()) as *mut u8, &[0])?;
+                words.push(sp);
+            }
+            // the NULL ending the array
+            words.push(0);
+            Ok(())
+        };
+        let mut words = vec![self.argv.len()];
+        push_strings(self.argv, &mut words)?;
+        push_strings(self.envp, &mut words)?;
+        for (key, value) in auxv {
+            words.extend([key, value]);
+        }
+
+        let bytes = unsafe {
+            core::slice::from_raw_parts(words.as_ptr() as *const u8, words.len() * 8)
+        };
+        // the psABI wants it 16 bytes aligned
+        let sp = (sp - bytes.len()) & !0xf;
+        copy_to_user(token, sp as *mut u8, bytes)?;
+        Ok(sp)
+    }
+}
+
+#[kernel_test]
+fn binfmt_test() {
+    let format = |data: &[u8]| FORMATS.iter().find(|format| format.recognizes(data)).map(|format| format.name());
+    assert_eq!(format(b"\x7fELF\x02\x01"), Some("elf"));
+    assert_eq!(format(b"#!/bin/sh\n"), Some("script"));
+    assert_eq!(format(&[0x13, 0x05, 0xa0, 0x02]), Some("flat"));
+    assert_eq!(format(b""), None);
+
+    assert_eq!(parse_shebang(b"#!/user_shell\nls\n"), Ok(("/user_shell", None)));
+    assert_eq!(parse_shebang(b"#! /probe  -x -y \n"), Ok(("/probe", Some("-x -y"))));
+    assert_eq!(parse_shebang(b"#!/probe"), Ok(("/probe", None)));
+    assert_eq!(parse_shebang(b"#!  \n/probe"), Err(Errno::ENOEXEC));
+    // the line has to end within `SHEBANG_MAX` bytes
+    let mut long = vec![b'#', b'!'];
+    long.resize(SHEBANG_MAX + 1, b'x');
+    assert_eq!(parse_shebang(&long), Err(Errno::ENOEXEC));
+}
@@ -1,67 +1,135 @@
-use super::{address::VirtAddr, error::MemoryError};
+//! Buffers the files read into and write from
+//!
+//! A [`Buffer`] is a run of bytes handed to a `File`, wherever it lies:
+//! - a [`UserBuffer`], a range of a user address space, as `read` and
+//!   `write` pass it. Its pages are checked mapped `U | R` before its bytes
+//!   are read, `U | W` before they are written: a read into read-only
+//!   memory fails instead of overwriting it. The bytes are seen through the
+//!   kernel's mapping of the frames, one piece per page, as the frames of
+//!   consecutive pages needn't be.
+//! - a kernel slice, `&[u8]` to write from, `&mut [u8]` to read into
+//!
+//! The pieces are only valid while the buffer is used: the caller keeps the
+//! range mapped meanwhile.
 
-/// 统一内存缓冲区抽象
+use alloc::{vec, vec::Vec};
+use os_macros::kernel_test;
+
+use super::{address::VirtAddr, error::MemoryError, page_table::{translated_pages, PTEFlags}};
+
+/// Bytes a file reads into or writes from
 pub trait Buffer {
-    /// 获取缓冲区起始虚拟地址
-    fn start_va(&self) -> VirtAddr;
-    
-    /// 获取缓冲区长度（字节数）
+    /// Length in bytes
     fn len(&self) -> usize;
-    
-    /// 检查缓冲区是否为空
+
     fn is_empty(&self) -> bool {
         self.len() == 0
     }
-    
-    /// 转换为只读字节切片（需权限验证）
-    /// 注意：必须确保映射在生命周期内有效
-    unsafe fn as_bytes(&self) -> Result<&[u8], MemoryError>;
-    
-    /// 转换为可写字节切片（需额外权限检查）
-    unsafe fn as_bytes_mut(&mut self) -> Result<&mut [u8], MemoryError>;
-    
-    // /// 获取物理页列表（用于 DMA 等操作）
-    // fn phys_pages(&self) -> Result<Vec<PhysAddr>, MemoryError>;
-    
-    // /// 检查是否连续物理内存（优化批处理操作）
-    // fn is_phys_contiguous(&self) -> bool;
-}
 
+    /// The bytes to read them, in pieces contiguous in kernel memory.
+    ///
+    /// `PageNotMapped` or `PermissionDenied` if they can't be read.
+    fn as_bytes(&self) -> Result<Vec<&[u8]>, MemoryError>;
 
+    /// The bytes to write them, in pieces contiguous in kernel memory.
+    ///
+    /// `PageNotMapped` or `PermissionDenied` if they can't be written.
+    fn as_bytes_mut(&mut self) -> Result<Vec<&mut [u8]>, MemoryError>;
+}
+
+/// `len` bytes at `start` in a user address space
 pub struct UserBuffer {
-    start_va: usize,
-    length: usize,
+    token: usize,
+    start: usize,
+    len: usize,
 }
 
 impl UserBuffer {
-    fn new(start_va: usize, length: usize) -> Result<Self, MemoryError> {
-        let start_va: VirtAddr = start_va.into();
-        if length == 0 {
-            return Err(MemoryError::EmptyBuffer)
+    /// The range `[start, start + len)` of the space of `token`, which must
+    /// lie in the user half. Its pages are only checked once accessed.
+    pub fn new(token: usize, start: *const u8, len: usize) -> Result<Self, MemoryError> {
+        let start = start as usize;
+        match start.checked_add(len) {
+            Some(end) if len == 0 || VirtAddr::new(end - 1).is_user() => Ok(Self { token, start, len }),
+            _ => Err(MemoryError::AddressOutOfRange { address: VirtAddr::new(start), max_valid: VirtAddr::USER_MAX }),
         }
+    }
+}
 
-        if !start_va.is_user() {
-            return Err(MemoryError::AddressOutOfRange {
-                address: start_va.into(),
-                max_valid: VirtAddr::USER_MAX,
-            });
-        }
+impl Buffer for UserBuffer {
+    fn len(&self) -> usize {
+        self.len
+    }
 
+    fn as_bytes(&self) -> Result<Vec<&[u8]>, MemoryError> {
+        let pages = translated_pages(self.token, self.start as *const u8, self.len, PTEFlags::R)?;
+        Ok(pages.into_iter().map(|page| &*page).collect())
+    }
 
-        let end_va = VirtAddr(start_va.0 + length);
+    fn as_bytes_mut(&mut self) -> Result<Vec<&mut [u8]>, MemoryError> {
+        translated_pages(self.token, self.start as *const u8, self.len, PTEFlags::W)
+    }
+}
 
-        if !end_va.is_user() {
-            log::error!("end_va is not a user address");
-            return Err(MemoryError::AddressOutOfRange {
-                address: start_va.into(),
-                max_valid: VirtAddr::USER_MAX,
-            });
-        }
+/// Kernel bytes to write from
+impl Buffer for &[u8] {
+    fn len(&self) -> usize {
+        <[u8]>::len(self)
+    }
 
-        
+    fn as_bytes(&self) -> Result<Vec<&[u8]>, MemoryError> {
+        Ok(vec![*self])
+    }
 
+    fn as_bytes_mut(&mut self) -> Result<Vec<&mut [u8]>, MemoryError> {
+        Err(MemoryError::PermissionDenied)
+    }
+}
 
+/// Kernel bytes to read into
+impl Buffer for &mut [u8] {
+    fn len(&self) -> usize {
+        <[u8]>::len(self)
+    }
+
+    fn as_bytes(&self) -> Result<Vec<&[u8]>, MemoryError> {
+        Ok(vec![&**self])
+    }
 
-        UserBuffer { start_va, length}
+    fn as_bytes_mut(&mut self) -> Result<Vec<&mut [u8]>, MemoryError> {
+        Ok(vec![&mut **self])
     }
-}
\ No newline at end of file
+}
+
+#[kernel_test]
+fn user_buffer_test() {
+    use crate::config::PAGE_SIZE;
+    use super::{map_area::{MapFlags, MapPermission}, memory_set::MemorySet};
+
+    let mut memory_set = MemorySet::new_bare();
+    let token = memory_set.token();
+    let rw = MapPermission::R | MapPermission::W;
+    let data = usize::from(memory_set.map_anonymous(0, 2 * PAGE_SIZE, rw, MapFlags::empty()).unwrap());
+    let read_only = usize::from(memory_set.map_anonymous(0, PAGE_SIZE, MapPermission::R, MapFlags::empty()).unwrap());
+
+    // a piece on each side of the page boundary
+    let mut buffer = UserBuffer::new(token, (data + PAGE_SIZE - 3) as *const u8, 8).unwrap();
+    let mut pieces = buffer.as_bytes_mut().unwrap();
+    assert_eq!(pieces.iter().map(|piece| piece.len()).collect::<Vec<_>>(), [3, 5]);
+    pieces[0].copy_from_slice(b"cro");
+    pieces[1].copy_from_slice(b"ssing");
+    let read: Vec<u8> = buffer.as_bytes().unwrap().concat();
+    assert_eq!(read, b"crossing");
+
+    let mut buffer = UserBuffer::new(token, read_only as *const u8, 4).unwrap();
+    assert!(buffer.as_bytes().is_ok());
+    assert_eq!(buffer.as_bytes_mut().err(), Some(MemoryError::PermissionDenied));
+    assert!(UserBuffer::new(token, usize::MAX as *const u8, 2).is_err());
+
+    let mut bytes = [1u8, 2, 3];
+    let mut slice: &mut [u8] = &mut bytes;
+    slice.as_bytes_mut().unwrap()[0][0] = 9;
+    assert_eq!(bytes, [9, 2, 3]);
+    let mut constant: &[u8] = b"ro";
+    assert!(constant.as_bytes_mut().is_err());
+}
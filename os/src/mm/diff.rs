@@ -0,0 +1,224 @@
+//! Forensic comparison of two address spaces
+//!
+//! Meant for kernel tests of fork/exec/munmap: instead of a failed
+//! `assert!` on one symptom, [`MemorySetDiff`] lists every area, PTE and
+//! page content difference between two [`MemorySet`]s.
+//!
+//! ```rust
+//! MemorySetDiff::between(&parent, &child)
+//!     .ignoring(trap_context_range)
+//!     .assert_empty("fork");
+//! ```
+//!
+//! Page contents are compared by hash, physical page numbers are not
+//! compared at all: two images are identical when they map the same data
+//! the same way, whatever frames hold it.
+
+use core::{fmt, ops::Range};
+
+use alloc::vec::Vec;
+use os_macros::kernel_test;
+
+use super::{
+    address::VirtPageNum,
+    elf::ElfImage,
+    memory_set::{AreaInfo, MemorySet},
+    page_table::PTEFlags,
+};
+
+/// One difference, `left` and `right` being the two compared sets
+#[derive(Debug, Clone, Copy)]
+pub enum Difference {
+    /// An area only the left set has
+    OnlyInLeft(AreaInfo),
+    /// An area only the right set has
+    OnlyInRight(AreaInfo),
+    /// Same start, different end, permission or map type
+    Area { left: AreaInfo, right: AreaInfo },
+    /// The page is mapped differently (or on one side only)
+    Mapping {
+        vpn: VirtPageNum,
+        left: Option<PTEFlags>,
+        right: Option<PTEFlags>,
+    },
+    /// Both pages are mapped the same way, their contents differ
+    Content { vpn: VirtPageNum, left: u64, right: u64 },
+    /// A valid PTE of the right set no area accounts for
+    StrayPte(VirtPageNum),
+}
+
+impl Difference {
+    /// Start address of what this difference is about
+    fn addr(&self) -> usize {
+        match self {
+            Difference::OnlyInLeft(info) | Difference::OnlyInRight(info) => info.start,
+            Difference::Area { left, .. } => left.start,
+            Difference::Mapping { vpn, .. } | Difference::Content { vpn, .. } | Difference::StrayPte(vpn) => {
+                vpn.0 << 12
+            }
+        }
+    }
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Difference::OnlyInLeft(info) => write!(f, "- area {:#x}..{:#x} perm {:#x}", info.start, info.end, info.perm),
+            Difference::OnlyInRight(info) => write!(f, "+ area {:#x}..{:#x} perm {:#x}", info.start, info.end, info.perm),
+            Difference::Area { left, right } => write!(
+                f,
+                "~ area {:#x}: ..{:#x} perm {:#x} type {} -> ..{:#x} perm {:#x} type {}",
+                left.start, left.end, left.perm, left.map_type, right.end, right.perm, right.map_type
+            ),
+            Difference::Mapping { vpn, left, right } => {
+                write!(f, "~ page {:#x}: pte {:?} -> {:?}", vpn.0 << 12, left, right)
+            }
+            Difference::Content { vpn, left, right } => {
+                write!(f, "~ page {:#x}: content {:#018x} -> {:#018x}", vpn.0 << 12, left, right)
+            }
+            Difference::StrayPte(vpn) => write!(f, "! page {:#x}: valid pte outside any area", vpn.0 << 12),
+        }
+    }
+}
+
+/// Every difference found between two memory sets
+pub struct MemorySetDiff {
+    differences: Vec<Difference>,
+}
+
+impl MemorySetDiff {
+    /// Compare areas, then the mapping and content of every page of the common areas.
+    ///
+    /// Stray PTEs are only looked for in `right`, the set under test.
+    pub fn between(left: &MemorySet, right: &MemorySet) -> Self {
+        let mut differences = Vec::new();
+        let left_areas = left.area_infos();
+        let right_areas = right.area_infos();
+
+        for left_area in left_areas.iter() {
+            match right_areas.iter().find(|area| area.start == left_area.start) {
+                None => differences.push(Difference::OnlyInLeft(*left_area)),
+                Some(right_area) => {
+                    if right_area.end != left_area.end
+                        || right_area.perm != left_area.perm
+                        || right_area.map_type != left_area.map_type
+                    {
+                        differences.push(Difference::Area { left: *left_area, right: *right_area });
+                    }
+                    let end = left_area.end.min(right_area.end);
+                    for vpn in (left_area.start >> 12)..(end >> 12) {
+                        compare_page(left, right, VirtPageNum(vpn), &mut differences);
+                    }
+                }
+            }
+        }
+        for right_area in right_areas.iter() {
+            if !left_areas.iter().any(|area| area.start == right_area.start) {
+                differences.push(Difference::OnlyInRight(*right_area));
+            }
+        }
+        differences.extend(right.stray_ptes().into_iter().map(Difference::StrayPte));
+
+        Self { differences }
+    }
+
+    /// Drop the differences about addresses in `range`, e.g. a per-task trap context page.
+    pub fn ignoring(mut self, range: Range<usize>) -> Self {
+        self.differences.retain(|difference| !range.contains(&difference.addr()));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.differences.is_empty()
+    }
+
+    pub fn differences(&self) -> &[Difference] {
+        &self.differences
+    }
+
+    /// Panic with the full report unless both sets are identical.
+    #[track_caller]
+    pub fn assert_empty(&self, what: &str) {
+        if !self.is_empty() {
+            panic!("{}: memory sets differ\n{}", what, self);
+        }
+    }
+}
+
+impl fmt::Display for MemorySetDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for difference in self.differences.iter() {
+            writeln!(f, "{}", difference)?;
+        }
+        write!(f, "{} difference(s)", self.differences.len())
+    }
+}
+
+/// Hardware-maintained bits, they don't make two images different
+fn significant_flags(flags: PTEFlags) -> PTEFlags {
+    flags - (PTEFlags::A | PTEFlags::D)
+}
+
+fn compare_page(left: &MemorySet, right: &MemorySet, vpn: VirtPageNum, differences: &mut Vec<Difference>) {
+    let left_pte = left.translate(vpn).filter(|pte| pte.is_valid());
+    let right_pte = right.translate(vpn).filter(|pte| pte.is_valid());
+    let left_flags = left_pte.map(|pte| significant_flags(pte.flags()));
+    let right_flags = right_pte.map(|pte| significant_flags(pte.flags()));
+    if left_flags != right_flags {
+        differences.push(Difference::Mapping { vpn, left: left_flags, right: right_flags });
+        return;
+    }
+    if let (Some(left_pte), Some(right_pte)) = (left_pte, right_pte) {
+        let left_hash = page_hash(left_pte.ppn().get_bytes_array_slice());
+        let right_hash = page_hash(right_pte.ppn().get_bytes_array_slice());
+        if left_hash != right_hash {
+            differences.push(Difference::Content { vpn, left: left_hash, right: right_hash });
+        }
+    }
+}
+
+/// FNV-1a
+fn page_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Address space of a program of the file system, as `exec` would build it
+/// without randomization
+fn load_image(name: &str) -> MemorySet {
+    let elf = crate::fs::vfs::lookup(name).unwrap().read_all();
+    MemorySet::from_elf(&mut ElfImage::parse(elf.as_slice()).unwrap(), false).0
+}
+
+#[kernel_test]
+fn fork_image_identical_test() {
+    let parent = load_image("init_proc");
+    let child = MemorySet::from_other_user(&parent);
+    MemorySetDiff::between(&parent, &child).assert_empty("fork");
+}
+
+#[kernel_test]
+fn exec_image_replaced_test() {
+    let old_image = load_image("init_proc");
+    let new_image = load_image("sleep");
+    // the new image is exactly a fresh load of the new program,
+    // and the old program differs from it
+    MemorySetDiff::between(&load_image("sleep"), &new_image).assert_empty("exec");
+    assert!(!MemorySetDiff::between(&old_image, &new_image).is_empty());
+}
+
+#[kernel_test]
+fn unmap_leaves_no_stray_pte_test() {
+    use super::{address::VirtAddr, map_area::MapPermission};
+
+    let mut memory_set = load_image("init_proc");
+    let before = MemorySet::from_other_user(&memory_set);
+    let start = VirtAddr::from(0x4000_0000);
+    memory_set.insert_framed_area(start, VirtAddr::from(0x4000_4000), MapPermission::R | MapPermission::W | MapPermission::U);
+    assert!(!MemorySetDiff::between(&before, &memory_set).is_empty());
+
+    memory_set.remove_area_with_start_vpn(start.down_to_vpn());
+    assert!(memory_set.stray_ptes().is_empty());
+    MemorySetDiff::between(&before, &memory_set).assert_empty("munmap");
+}
@@ -0,0 +1,245 @@
+//! ELF executables
+//!
+//! [`ElfImage::parse`] checks everything `exec` needs before the caller's
+//! image is torn down:
+//! - static executables (`ET_EXEC`) are loaded at their link addresses
+//! - position independent ones (`ET_DYN` without `PT_INTERP`) are loaded
+//!   at [`PIE_BASE`], moved up by a random offset with address space
+//!   randomization (see [`ElfImage::rebase`]), and fixed up by their
+//!   `R_RISCV_RELATIVE` relocations
+//! - anything that needs a dynamic linker or a symbol lookup is refused
+//!
+//! The auxiliary vector of the initial user stack comes from here, the
+//! stack itself is laid out by `binfmt`.
+
+use alloc::vec::Vec;
+
+use xmas_elf::{
+    dynamic::Tag,
+    header,
+    program::{ProgramHeader, SegmentData, Type},
+    ElfFile,
+};
+
+use super::{error::MemoryError, memory_set::MemorySet, page_table::copy_to_image};
+use crate::{
+    config::{PAGE_SIZE, PIE_BASE},
+    syscall::error::Errno,
+};
+
+const EM_RISCV: u16 = 243;
+/// Offset of `e_machine` in the file header
+const E_MACHINE: usize = 18;
+
+const R_RISCV_NONE: u32 = 0;
+const R_RISCV_RELATIVE: u32 = 3;
+/// `Elf64_Rela`: offset, info, addend
+const RELA_ENTRY_SIZE: usize = 24;
+
+/// Auxiliary vector keys
+pub const AT_NULL: usize = 0;
+pub const AT_PHDR: usize = 3;
+pub const AT_PHENT: usize = 4;
+pub const AT_PHNUM: usize = 5;
+pub const AT_PAGESZ: usize = 6;
+pub const AT_BASE: usize = 7;
+pub const AT_ENTRY: usize = 9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ElfError {
+    /// Not an ELF, or its headers don't hold together
+    Malformed(&'static str),
+    /// A valid ELF we can't run: wrong class or machine, not an executable
+    Unsupported(&'static str),
+    /// Needs an interpreter (`PT_INTERP`) or shared libraries
+    DynamicLinking,
+    /// A relocation other than `R_RISCV_RELATIVE`
+    Relocation(u32),
+}
+
+impl From<ElfError> for Errno {
+    fn from(_: ElfError) -> Self {
+        Errno::ENOEXEC
+    }
+}
+
+/// A checked executable, ready to be mapped
+pub struct ElfImage<'a> {
+    pub elf: ElfFile<'a>,
+    /// Load bias, added to every address of the file
+    pub bias: usize,
+    pub entry: usize,
+    /// Where the program headers are mapped, 0 if no segment covers them
+    pub phdr: usize,
+    /// `(offset, addend)` of the relative relocations, unbiased
+    relocations: Vec<(usize, usize)>,
+}
+
+impl<'a> ElfImage<'a> {
+    pub fn parse(data: &'a [u8]) -> Result<Self, ElfError> {
+        let elf = ElfFile::new(data).map_err(ElfError::Malformed)?;
+        if elf.header.pt1.class() != header::Class::SixtyFour {
+            return Err(ElfError::Unsupported("not a 64-bit ELF"));
+        }
+        let pt2 = &elf.header.pt2;
+        if u16::from_le_bytes([data[E_MACHINE], data[E_MACHINE + 1]]) != EM_RISCV {
+            return Err(ElfError::Unsupported("not a RISC-V ELF"));
+        }
+        let bias = match pt2.type_().as_type() {
+            header::Type::Executable => 0,
+            header::Type::SharedObject => PIE_BASE,
+            _ => return Err(ElfError::Unsupported("not an executable")),
+        };
+
+        let mut phdr = None;
+        let mut relocations = Vec::new();
+        for ph in elf.program_iter() {
+            match ph.get_type().map_err(ElfError::Malformed)? {
+                Type::Interp => return Err(ElfError::DynamicLinking),
+                Type::Phdr => phdr = Some(ph.virtual_addr() as usize),
+                Type::Dynamic => relocations = relative_relocations(&elf, ph)?,
+                Type::Load => {
+                    if ph.file_size() > ph.mem_size()
+                        || ph.offset() + ph.file_size() > data.len() as u64
+                    {
+                        return Err(ElfError::Malformed("load segment out of the file"));
+                    }
+                }
+                _ => {}
+            }
+        }
+        // every fix-up has to land in the image
+        if relocations.iter().any(|&(offset, _)| !in_load_segment(&elf, offset, 8)) {
+            return Err(ElfError::Malformed("relocation out of the image"));
+        }
+
+        // without `PT_PHDR`, the headers are mapped if a load segment covers them
+        let phoff = pt2.ph_offset();
+        let phdr = phdr.or_else(|| {
+            elf.program_iter()
+                .filter(|ph| ph.get_type() == Ok(Type::Load))
+                .find(|ph| ph.offset() <= phoff && phoff < ph.offset() + ph.file_size())
+                .map(|ph| (ph.virtual_addr() + phoff - ph.offset()) as usize)
+        });
+
+        Ok(Self {
+            bias,
+            entry: pt2.entry_point() as usize + bias,
+            phdr: phdr.map_or(0, |phdr| phdr + bias),
+            relocations,
+            elf,
+        })
+    }
+
+    /// Whether the image is position independent, and so can be moved
+    pub fn is_pie(&self) -> bool {
+        self.elf.header.pt2.type_().as_type() == header::Type::SharedObject
+    }
+
+    /// Move a position independent image to the load bias `bias`, before
+    /// it is mapped.
+    pub fn rebase(&mut self, bias: usize) {
+        assert!(self.is_pie() && bias % PAGE_SIZE == 0);
+        self.entry = self.entry - self.bias + bias;
+        if self.phdr != 0 {
+            self.phdr = self.phdr - self.bias + bias;
+        }
+        self.bias = bias;
+    }
+
+    /// Apply the relocations to the image mapped in `memory_set`.
+    pub fn relocate(&self, memory_set: &mut MemorySet) -> Result<(), MemoryError> {
+        let token = memory_set.token();
+        for &(offset, addend) in &self.relocations {
+            let target = (self.bias + offset) as *mut usize;
+            // one in the zeroed tail of a segment lands on a lazy page
+            memory_set.populate_range(target as usize, size_of::<usize>())?;
+            // `.data.rel.ro` is fixed up before the user runs, read-only or not
+            copy_to_image(token, target as *mut u8, &self.bias.wrapping_add(addend).to_ne_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// The auxiliary vector of the initial stack, see `binfmt::Program`
+    pub fn auxv(&self) -> Vec<(usize, usize)> {
+        let pt2 = &self.elf.header.pt2;
+        let mut auxv = Vec::new();
+        if self.phdr != 0 {
+            auxv.extend([
+                (AT_PHDR, self.phdr),
+                (AT_PHENT, pt2.ph_entry_size() as usize),
+                (AT_PHNUM, pt2.ph_count() as usize),
+            ]);
+        }
+        auxv.extend([
+            (AT_PAGESZ, PAGE_SIZE),
+            // no interpreter
+            (AT_BASE, 0),
+            (AT_ENTRY, self.entry),
+            (AT_NULL, 0),
+        ]);
+        auxv
+    }
+}
+
+/// Whether `[vaddr, vaddr + len)` lies in one load segment
+fn in_load_segment(elf: &ElfFile, vaddr: usize, len: usize) -> bool {
+    elf.program_iter().any(|ph| {
+        ph.get_type() == Ok(Type::Load)
+            && ph.virtual_addr() as usize <= vaddr
+            && vaddr + len <= (ph.virtual_addr() + ph.mem_size()) as usize
+    })
+}
+
+/// File offset of `[vaddr, vaddr + len)`, if the file holds it
+fn file_offset(elf: &ElfFile, vaddr: usize, len: usize) -> Option<usize> {
+    elf.program_iter()
+        .filter(|ph| ph.get_type() == Ok(Type::Load))
+        .find(|ph| {
+            ph.virtual_addr() as usize <= vaddr
+                && vaddr + len <= (ph.virtual_addr() + ph.file_size()) as usize
+        })
+        .map(|ph| vaddr - ph.virtual_addr() as usize + ph.offset() as usize)
+}
+
+/// The `R_RISCV_RELATIVE` entries of the `DT_RELA` table.
+fn relative_relocations(elf: &ElfFile, dynamic: ProgramHeader) -> Result<Vec<(usize, usize)>, ElfError> {
+    let Ok(SegmentData::Dynamic64(entries)) = dynamic.get_data(elf) else {
+        return Err(ElfError::Malformed("bad dynamic segment"));
+    };
+
+    let mut table = 0;
+    let mut size = 0;
+    let mut entry_size = RELA_ENTRY_SIZE;
+    for entry in entries {
+        match entry.get_tag().map_err(ElfError::Malformed)? {
+            Tag::Null => break,
+            // PLT slots are bound by symbol, by a dynamic linker
+            Tag::Needed | Tag::JmpRel => return Err(ElfError::DynamicLinking),
+            Tag::Rel => return Err(ElfError::Unsupported("REL relocations")),
+            Tag::Rela => table = entry.get_ptr().map_err(ElfError::Malformed)? as usize,
+            Tag::RelaSize => size = entry.get_val().map_err(ElfError::Malformed)? as usize,
+            Tag::RelaEnt => entry_size = entry.get_val().map_err(ElfError::Malformed)? as usize,
+            _ => {}
+        }
+    }
+    if size == 0 {
+        return Ok(Vec::new());
+    }
+    if entry_size != RELA_ENTRY_SIZE || size % RELA_ENTRY_SIZE != 0 {
+        return Err(ElfError::Malformed("bad DT_RELAENT"));
+    }
+    let start = file_offset(elf, table, size).ok_or(ElfError::Malformed("DT_RELA out of the file"))?;
+
+    let word = |bytes: &[u8]| u64::from_le_bytes(bytes.try_into().unwrap()) as usize;
+    let mut relocations = Vec::new();
+    for rela in elf.input[start..start + size].chunks_exact(RELA_ENTRY_SIZE) {
+        let (offset, info, addend) = (word(&rela[0..8]), word(&rela[8..16]), word(&rela[16..24]));
+        match info as u32 {
+            R_RISCV_NONE => {}
+            R_RISCV_RELATIVE => relocations.push((offset, addend)),
+            other => return Err(ElfError::Relocation(other)),
+        }
+    }
+    Ok(relocations)
+}
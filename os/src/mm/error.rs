@@ -1,3 +1,5 @@
+use crate::syscall::error::Errno;
+
 use super::address::VirtAddr;
 
 #[allow(unused)]
@@ -45,4 +47,23 @@ pub enum MemoryError {
     
     /// 空缓冲区操作（零长度）
     EmptyBuffer,
-}
\ No newline at end of file
+
+    /// The range overlaps an existing area
+    AddressInUse,
+}
+
+impl From<MemoryError> for Errno {
+    fn from(error: MemoryError) -> Self {
+        match error {
+            MemoryError::OutOfMemory => Errno::ENOMEM,
+            MemoryError::AddressInUse => Errno::EEXIST,
+            MemoryError::PermissionDenied => Errno::EACCES,
+            MemoryError::AddressOutOfRange { .. } | MemoryError::PageNotMapped | MemoryError::InvalidEntry => {
+                Errno::EFAULT
+            }
+            MemoryError::Misaligned { .. } | MemoryError::EmptyBuffer | MemoryError::NonContinuous(_) => {
+                Errno::EINVAL
+            }
+        }
+    }
+}
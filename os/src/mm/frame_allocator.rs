@@ -1,10 +1,30 @@
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
 use alloc::{fmt, format, vec::Vec};
 use lazy_static::lazy_static;
-use crate::{config::PHYSTOP, mm::address::PhysAddr, println, sync::spin::mutex::IRQSpinLock};
+use os_macros::{kernel_test, monitor_command};
+use crate::{config::PAGE_SIZE, mm::address::PhysAddr, println, sync::spin::mutex::IRQSpinLock};
+
+use super::{address::PhysPageNum, memmap, gfp::{check_context, GfpFlags}};
+
+type FrameAllocatorImpl = BitmapFrameAllocator;
+
+/// Frames only `GfpFlags::ATOMIC` requests may take
+const FRAME_RESERVE: usize = 16;
+
+/// Free frames under which memory is low, the allocator warns once per
+/// dip below
+const LOW_WATERMARK: usize = 4 * FRAME_RESERVE;
 
-use super::address::PhysPageNum;
+/// Frames handed to the allocator at init
+static TOTAL_FRAMES: AtomicUsize = AtomicUsize::new(0);
 
-type FrameAllocatorImpl = StackFrameAllocator;
+/// Requests which found no frame
+static ALLOC_FAILURES: AtomicUsize = AtomicUsize::new(0);
+/// Most frames ever in use at once
+static PEAK_USED: AtomicUsize = AtomicUsize::new(0);
+/// Free frames are below `LOW_WATERMARK`
+static LOW_MEMORY: AtomicBool = AtomicBool::new(false);
 
 
 
@@ -19,28 +39,176 @@ lazy_static! {
 pub fn init_frame_allocator() {
 
     log::info!("Frame allocator initializing.");
-    extern "C" {
-        fn ekernel();
-    }
 
-    FRAME_ALLOCATOR
-        .lock()
-        .init(PhysAddr::from(ekernel as usize).up_to_ppn(), PhysAddr::from(PHYSTOP).down_to_ppn());
+    // reserved regions (firmware, kernel, device tree, ...) are left out
+    let ranges = memmap::usable_ranges();
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    if let (Some(lo), Some(hi)) = (ranges.iter().map(|r| r.0).min(), ranges.iter().map(|r| r.1).max()) {
+        allocator.init(PhysAddr::from(lo).up_to_ppn(), PhysAddr::from(hi).down_to_ppn());
+    }
+    for (start, end) in ranges {
+        allocator.add_range(PhysAddr::from(start).up_to_ppn(), PhysAddr::from(end).down_to_ppn());
+    }
+    log::info!("{} frames available", allocator.free_count());
+    TOTAL_FRAMES.store(allocator.free_count(), Ordering::Relaxed);
+    drop(allocator);
 
     log::info!("Frame allocator initialized successfully.");
 }
 
+/// Allocate a frame without blocking, the emergency reserve is left alone.
 pub fn frame_alloc() -> Option<FrameTracker> {
-    FRAME_ALLOCATOR
-        .lock()
-        .alloc()
-        .map(|ppn| FrameTracker::new(ppn))
+    frame_alloc_gfp(GfpFlags::NOWAIT)
+}
+
+/// Allocate a frame in the context described by `flags`.
+///
+/// The last `FRAME_RESERVE` free frames only go to `ATOMIC` requests.
+pub fn frame_alloc_gfp(flags: GfpFlags) -> Option<FrameTracker> {
+    check_context(flags);
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    let ppn = if !flags.contains(GfpFlags::ATOMIC) && allocator.free_count() <= FRAME_RESERVE {
+        None
+    } else {
+        allocator.alloc()
+    };
+    account(allocator.free_count(), ppn.is_some());
+    drop(allocator);
+    ppn.map(|ppn| FrameTracker::new(ppn))
+}
+
+/// Update the statistics after a request, `free` frames being left.
+fn account(free: usize, found: bool) {
+    if !found {
+        ALLOC_FAILURES.fetch_add(1, Ordering::Relaxed);
+    }
+    PEAK_USED.fetch_max(total_frames().saturating_sub(free), Ordering::Relaxed);
+    let low = free < LOW_WATERMARK;
+    if LOW_MEMORY.swap(low, Ordering::Relaxed) != low && low {
+        log::warn!("[frame] low on memory, {} frames free", free);
+    }
+}
+
+/// Frames `frame_alloc` can still hand out, the reserve excluded.
+pub fn available_frames() -> usize {
+    FRAME_ALLOCATOR.lock().free_count().saturating_sub(FRAME_RESERVE)
+}
+
+/// Frames the allocator manages, free or not.
+pub fn total_frames() -> usize {
+    TOTAL_FRAMES.load(Ordering::Relaxed)
 }
 
 pub fn frame_dealloc(ppn: PhysPageNum) {
-    FRAME_ALLOCATOR
-        .lock()
-        .dealloc(ppn);
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    allocator.dealloc(ppn);
+    if allocator.free_count() >= LOW_WATERMARK {
+        LOW_MEMORY.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Frames of one usable range of physical memory
+pub struct ZoneStats {
+    pub start: PhysPageNum,
+    pub end: PhysPageNum,
+    pub free: usize,
+}
+
+/// Counters of the frame allocator, in frames
+pub struct FrameStats {
+    pub total: usize,
+    pub free: usize,
+    /// Left to `GfpFlags::ATOMIC` requests, a part of `free`
+    pub reserve: usize,
+    pub low_watermark: usize,
+    pub peak_used: usize,
+    /// Requests which found no frame
+    pub failures: usize,
+    pub zones: Vec<ZoneStats>,
+}
+
+pub fn frame_stats() -> FrameStats {
+    let allocator = FRAME_ALLOCATOR.lock();
+    let zones = allocator
+        .zones
+        .iter()
+        .map(|&(start, end)| ZoneStats {
+            start: start.into(),
+            end: end.into(),
+            free: (start..end).filter(|&ppn| allocator.is_free(ppn)).count(),
+        })
+        .collect();
+    FrameStats {
+        total: total_frames(),
+        free: allocator.free_count(),
+        reserve: FRAME_RESERVE,
+        low_watermark: LOW_WATERMARK,
+        peak_used: PEAK_USED.load(Ordering::Relaxed),
+        failures: ALLOC_FAILURES.load(Ordering::Relaxed),
+        zones,
+    }
+}
+
+#[monitor_command(name = "frames", help = "Show the frame allocator usage, per memory range")]
+fn frames_command(_args: &[&str]) {
+    let stats = frame_stats();
+    println!(
+        "{} frames, {} free ({} reserved), {} used at most, {} failed requests",
+        stats.total, stats.free, stats.reserve, stats.peak_used, stats.failures
+    );
+    for zone in stats.zones {
+        let (start, end) = (PhysAddr::from(zone.start).0, PhysAddr::from(zone.end).0);
+        println!("  [{:#x}, {:#x}) {:>6} frames, {:>6} free", start, end, zone.end.0 - zone.start.0, zone.free);
+    }
+}
+
+/// Allocate `pages` contiguous frames, the first one's number a multiple
+/// of `align`, in the context described by `flags`. Not zeroed.
+pub fn frame_alloc_contiguous(pages: usize, align: usize, flags: GfpFlags) -> Option<PhysPageNum> {
+    check_context(flags);
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    let start = if !flags.contains(GfpFlags::ATOMIC) && allocator.free_count() < FRAME_RESERVE + pages {
+        None
+    } else {
+        allocator.alloc_contiguous(pages, align)
+    };
+    account(allocator.free_count(), start.is_some());
+    start
+}
+
+/// Give back the `pages` frames from `start`.
+pub fn frame_dealloc_range(start: PhysPageNum, pages: usize) {
+    let mut allocator = FRAME_ALLOCATOR.lock();
+    for ppn in start.0..start.0 + pages {
+        allocator.dealloc(ppn.into());
+    }
+}
+
+/// Contiguous frames, zeroed, freed when dropped
+pub struct FrameRange {
+    pub start: PhysPageNum,
+    pub pages: usize,
+}
+
+impl FrameRange {
+    /// `pages` contiguous frames aligned to `align` frames, without
+    /// blocking and leaving the emergency reserve alone.
+    pub fn alloc(pages: usize, align: usize) -> Option<Self> {
+        let start = frame_alloc_contiguous(pages, align, GfpFlags::NOWAIT)?;
+        let pa: PhysAddr = start.into();
+        unsafe { core::ptr::write_bytes(pa.0 as *mut u8, 0, pages * PAGE_SIZE) };
+        Some(Self { start, pages })
+    }
+
+    pub fn pa(&self) -> PhysAddr {
+        self.start.into()
+    }
+}
+
+impl Drop for FrameRange {
+    fn drop(&mut self) {
+        frame_dealloc_range(self.start, self.pages);
+    }
 }
 
 
@@ -85,52 +253,118 @@ trait FrameAllocator {
     fn dealloc(&mut self, ppn: PhysPageNum);
 }
 
-pub struct StackFrameAllocator {
-    current: usize,
-    end: usize,
-    recycled: Vec<usize>,
+/// One bit per frame, set when free.
+///
+/// The bitmap spans every usable range, holes between them included (as
+/// used frames), and is sized once at init: neither allocating nor
+/// freeing touches the heap, which grows itself with frames from here.
+pub struct BitmapFrameAllocator {
+    /// Frame of the first bit
+    base: usize,
+    bits: Vec<u64>,
+    free: usize,
+    /// Word where the search for a single frame starts
+    hint: usize,
+    /// The ranges handed with `add_range`
+    zones: Vec<(usize, usize)>,
 }
 
-
-impl StackFrameAllocator {
+impl BitmapFrameAllocator {
+    /// Cover the frames `[l, r)`, all used until handed with `add_range`.
     pub fn init(&mut self, l: PhysPageNum, r: PhysPageNum) {
-        self.current = l.0;
-        self.end = r.0;
+        self.base = l.0;
+        self.bits = alloc::vec![0; r.0.saturating_sub(l.0).div_ceil(64)];
+        self.free = 0;
+        self.hint = 0;
+        self.zones.clear();
+    }
+
+    /// Hand `[l, r)` to the allocator, ranges may have holes between them.
+    pub fn add_range(&mut self, l: PhysPageNum, r: PhysPageNum) {
+        for ppn in l.0..r.0 {
+            assert!(!self.is_free(ppn), "frame ppn={:#x} added twice", ppn);
+            self.set_free(ppn, true);
+        }
+        self.zones.push((l.0, r.0));
+    }
+
+    pub fn free_count(&self) -> usize {
+        self.free
+    }
+
+    fn end(&self) -> usize {
+        self.base + self.bits.len() * 64
+    }
+
+    fn is_free(&self, ppn: usize) -> bool {
+        (self.base..self.end()).contains(&ppn) && {
+            let bit = ppn - self.base;
+            self.bits[bit / 64] & (1 << (bit % 64)) != 0
+        }
+    }
+
+    fn set_free(&mut self, ppn: usize, free: bool) {
+        assert!((self.base..self.end()).contains(&ppn), "frame ppn={:#x} out of the allocator", ppn);
+        let bit = ppn - self.base;
+        if free {
+            self.bits[bit / 64] |= 1 << (bit % 64);
+            self.free += 1;
+        } else {
+            self.bits[bit / 64] &= !(1 << (bit % 64));
+            self.free -= 1;
+        }
+    }
+
+    /// `pages` free frames in a row, the first one's number a multiple of
+    /// `align`, the lowest such run.
+    pub fn alloc_contiguous(&mut self, pages: usize, align: usize) -> Option<PhysPageNum> {
+        let align = align.max(1);
+        let mut start = self.base.next_multiple_of(align);
+        while start + pages <= self.end() {
+            match (start..start + pages).rev().find(|&ppn| !self.is_free(ppn)) {
+                // restart past the last used frame of the window
+                Some(used) => start = (used + 1).next_multiple_of(align),
+                None => {
+                    for ppn in start..start + pages {
+                        self.set_free(ppn, false);
+                    }
+                    return Some(start.into());
+                }
+            }
+        }
+        None
     }
 }
 
-impl FrameAllocator for StackFrameAllocator {
+impl FrameAllocator for BitmapFrameAllocator {
     fn new() -> Self {
         Self {
-            current: 0,
-            end: 0,
-            recycled: Vec::new(),
+            base: 0,
+            bits: Vec::new(),
+            free: 0,
+            hint: 0,
+            zones: Vec::new(),
         }
     }
 
     fn alloc(&mut self) -> Option<PhysPageNum> {
-        if let Some(ppn) = self.recycled.pop() {
-            Some(ppn.into())
-        } else {
-            if self.current == self.end {
-                None
-            }
-            else {
-                self.current += 1;
-                Some((self.current - 1).into())
-            }
-        }
+        let words = self.bits.len();
+        let word = (0..words)
+            .map(|offset| (self.hint + offset) % words)
+            .find(|&word| self.bits[word] != 0)?;
+        self.hint = word;
+        let ppn = self.base + word * 64 + self.bits[word].trailing_zeros() as usize;
+        self.set_free(ppn, false);
+        Some(ppn.into())
     }
 
     fn dealloc(&mut self, ppn: PhysPageNum) {
         let ppn = ppn.0;
-
-        if ppn >= self.current || self.recycled.contains(&ppn) {
-                panic!("Frame ppn={:#x} has not been allocated!", ppn)
+        if self.is_free(ppn) {
+            panic!("Frame ppn={:#x} has not been allocated!", ppn)
         }
-        self.recycled.push(ppn);
+        self.set_free(ppn, true);
     }
-
 }
 
 
@@ -148,4 +382,44 @@ pub fn frame_allocator_test() {
 
     drop(v);
     println!("frame_allocator_test passed!")
-}
\ No newline at end of file
+}
+#[kernel_test]
+fn frame_reserve_test() {
+    let mut frames: Vec<FrameTracker> = Vec::new();
+    while let Some(frame) = frame_alloc() {
+        frames.push(frame);
+    }
+    // only the reserve is left, and only atomic requests get it
+    assert_eq!(FRAME_ALLOCATOR.lock().free_count(), FRAME_RESERVE);
+    let failures = frame_stats().failures;
+    let atomic = frame_alloc_gfp(GfpFlags::ATOMIC);
+    assert!(atomic.is_some());
+    assert!(frame_alloc().is_none());
+    let stats = frame_stats();
+    assert_eq!(stats.failures, failures + 1);
+    assert_eq!(stats.peak_used, stats.total - stats.free);
+    drop(atomic);
+    drop(frames);
+}
+
+#[kernel_test]
+fn frame_contiguous_test() {
+    let mut allocator = BitmapFrameAllocator::new();
+    allocator.init(PhysPageNum(0x100), PhysPageNum(0x200));
+    allocator.add_range(PhysPageNum(0x100), PhysPageNum(0x110));
+    allocator.add_range(PhysPageNum(0x120), PhysPageNum(0x140));
+
+    let single = allocator.alloc().unwrap();
+    assert_eq!(single.0, 0x100);
+    // the first range is too short once a frame is taken
+    assert_eq!(allocator.alloc_contiguous(16, 1).map(|ppn| ppn.0), Some(0x120));
+    allocator.dealloc(single);
+    assert_eq!(allocator.alloc_contiguous(4, 8).map(|ppn| ppn.0), Some(0x100));
+    assert_eq!(allocator.alloc_contiguous(8, 8).map(|ppn| ppn.0), Some(0x108));
+    assert_eq!(allocator.alloc_contiguous(17, 1), None);
+    assert_eq!(allocator.free_count(), 16 + 4);
+    for ppn in 0x120..0x130 {
+        allocator.dealloc(PhysPageNum(ppn));
+    }
+    assert_eq!(allocator.alloc_contiguous(32, 1).map(|ppn| ppn.0), Some(0x120));
+}
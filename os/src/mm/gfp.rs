@@ -0,0 +1,63 @@
+//! Allocation context flags
+//!
+//! Whether an allocation may block depends on where it happens, not on
+//! what is allocated. Allocations made with interrupts disabled or under
+//! an [`IRQSpinLock`](crate::sync::spin::mutex::IRQSpinLock) (the timer
+//! path, the scheduler, anything holding a task lock) must never wait for
+//! reclaim, and must not fail for lack of memory more than necessary.
+//!
+//! - [`GfpFlags::ATOMIC`]: never blocks, may dip into the emergency reserve
+//! - [`GfpFlags::MAY_BLOCK`]: may block (once reclaim exists), only legal
+//!   outside atomic context, checked in debug builds
+//! - no flag: never blocks and leaves the reserve alone, what plain
+//!   `frame_alloc` does
+//!
+//! The heap can't take flags through `GlobalAlloc`, it picks
+//! [`current_gfp`] instead.
+
+use bitflags::bitflags;
+
+use crate::{interupt::{InterruptController, InterruptState}, processor::get_current_processor};
+
+bitflags! {
+    pub struct GfpFlags: u8 {
+        /// Must not block, may use the emergency reserve
+        const ATOMIC    = 1 << 0;
+        /// May block until memory is reclaimed
+        const MAY_BLOCK = 1 << 1;
+    }
+}
+
+impl GfpFlags {
+    /// Default for process context allocations
+    pub const KERNEL: GfpFlags = GfpFlags::MAY_BLOCK;
+    /// Never blocks, never touches the reserve
+    pub const NOWAIT: GfpFlags = GfpFlags::empty();
+}
+
+/// Whether blocking is illegal here: interrupts are off, an IRQ-safe lock
+/// is held or preemption is disabled.
+pub fn in_atomic_context() -> bool {
+    let processor = get_current_processor();
+    processor.nest_depth() > 0
+        || processor.preempt_disabled()
+        || InterruptController::get_state() == InterruptState::Disabled
+}
+
+/// The strongest flags legal in the current context.
+pub fn current_gfp() -> GfpFlags {
+    if in_atomic_context() {
+        GfpFlags::ATOMIC
+    } else {
+        GfpFlags::KERNEL
+    }
+}
+
+/// Catch a blocking allocation in atomic context, debug builds only.
+#[inline(always)]
+pub fn check_context(flags: GfpFlags) {
+    debug_assert!(
+        !(flags.contains(GfpFlags::MAY_BLOCK) && in_atomic_context()),
+        "MAY_BLOCK allocation in atomic context"
+    );
+}
@@ -1,25 +1,402 @@
-use core::{alloc::{GlobalAlloc, Layout}, ops::Deref, ptr::NonNull};
+//! Kernel heap
+//!
+//! Allocations of the exact layout of a hot kernel object (tasks, map
+//! areas, frame trackers) come from its slab cache (see [`super::slab`]).
+//! Other small allocations are served by per-size-class arenas, each behind its
+//! own lock, so unrelated allocations don't serialize on a single lock.
+//! Arenas are refilled in chunks from the buddy heap, which also serves
+//! every allocation larger than the biggest class.
+//!
+//! An arena keeps its chunks for good: a freed block goes back to its free
+//! list, never to the buddy heap. An arena is bounded to [`MAX_REFILLS`]
+//! chunks instead, past them its class is served by the buddy heap too, so
+//! a burst of one class pins at most that much of the heap.
+//!
+//! All locks stay IRQ-safe: the timer interrupt path allocates (e.g. when
+//! it puts a woken task back in the ready queue).
+//!
+//! The buddy heap starts as a static array of [`KERNEL_HEAP_SIZE`] bytes.
+//! Past that, chunks of contiguous frames are taken from the frame
+//! allocator, each managed by a buddy heap of its own. Physical memory is
+//! identity mapped in kernel space, a chunk is usable right away. A chunk
+//! goes back to the frame allocator as soon as its last block is freed,
+//! the buddy heap having coalesced it whole again.
+//!
+//! Lock order: a cache or class lock may be held while taking the buddy
+//! lock, then the lock of the grown chunks, then the frame allocator lock,
+//! never the other way around.
+//!
+//! A small emergency reserve, carved out of the heap at init, serves
+//! `GfpFlags::ATOMIC` requests the buddy heap can't. The context picks the
+//! flags (see [`current_gfp`]), `GlobalAlloc` has no way to pass them.
+
+use core::{alloc::{GlobalAlloc, Layout}, ops::Deref, ptr::NonNull, sync::atomic::{AtomicUsize, Ordering}};
 
 
 
 use buddy_system_allocator::Heap;
-use os_macros::kernel_test;
-use spin::Mutex;
-use crate::{config::KERNEL_HEAP_SIZE, println, sync::spin::{mutex::SpinLock, ticket::{IRQTicketMutex, TicketMutex}}};
+use os_macros::{kernel_test, monitor_command};
+use alloc::vec::Vec;
+use super::{
+    address::{PhysAddr, PhysPageNum},
+    frame_allocator::{frame_alloc_contiguous, frame_dealloc_range, FrameTracker},
+    gfp::{check_context, current_gfp, GfpFlags},
+    map_area::MapArea,
+    slab::{arc_layout, SlabCache, SlabStats},
+};
+use crate::{task::TaskControlBlock, config::{KERNEL_HEAP_SIZE, PAGE_SIZE}, println, sync::spin::{mutex::{IRQSpinLock, IRQSpinLockGuard}, ticket::{IRQTicketMutex, IRQTicketMutexGuard}}};
 
 type HeapLock<T> = IRQTicketMutex<T>;
+type ClassLock<T> = IRQSpinLock<T>;
+
+/// Block sizes of the arenas, requests above the last one go to the buddy heap
+const SIZE_CLASSES: [usize; 8] = [16, 32, 64, 128, 256, 512, 1024, 2048];
+const CLASS_COUNT: usize = SIZE_CLASSES.len();
+/// Bytes taken from the buddy heap when an arena runs empty, chunks are
+/// aligned to their size
+const REFILL_SIZE: usize = 8 * 1024;
+/// Chunks an arena takes at most, 64 KiB per class
+const MAX_REFILLS: usize = 8;
+/// Bytes of the emergency reserve, at least one refill
+const RESERVE_SIZE: usize = 4 * REFILL_SIZE;
+
+/// Smallest chunk of frames the heap grows by
+const GROW_SIZE: usize = 64 * PAGE_SIZE;
+/// Chunks the heap may grow by at the same time
+const MAX_GROWN_CHUNKS: usize = 32;
+
+/// Number of slab caches, see `LockedHeap::new`
+const CACHE_COUNT: usize = 3;
+
+/// Layout of the blocks of class `index`, for the blocks taken from the
+/// buddy heap once the arena is full
+fn class_layout(index: usize) -> Layout {
+    unsafe { Layout::from_size_align_unchecked(SIZE_CLASSES[index], SIZE_CLASSES[index]) }
+}
+
+/// Index of the smallest class fitting `layout`, if any.
+///
+/// Classes are powers of two and blocks are carved at multiples of their
+/// size, so a block is aligned to its size.
+fn class_index(layout: &Layout) -> Option<usize> {
+    let size = layout.size().max(layout.align());
+    SIZE_CLASSES.iter().position(|&class_size| size <= class_size)
+}
+
+/// Intrusive list of free blocks, the next pointer lives in the block itself
+struct FreeList {
+    /// Address of the first free block, 0 when empty
+    head: usize,
+    /// Blocks currently handed out
+    in_use: usize,
+    /// Chunks taken from the buddy heap
+    refills: usize,
+    /// Addresses of those chunks, to tell the blocks of the arena apart
+    chunks: [usize; MAX_REFILLS],
+    /// Blocks currently handed out by the buddy heap, the arena being full
+    spilled: usize,
+}
+
+impl FreeList {
+    const fn new() -> Self {
+        Self { head: 0, in_use: 0, refills: 0, chunks: [0; MAX_REFILLS], spilled: 0 }
+    }
+
+    /// Whether `block` lies in a chunk of the arena
+    fn owns(&self, block: usize) -> bool {
+        self.chunks[..self.refills].contains(&(block & !(REFILL_SIZE - 1)))
+    }
+
+    unsafe fn push(&mut self, block: usize) {
+        *(block as *mut usize) = self.head;
+        self.head = block;
+    }
 
-pub struct LockedHeap(HeapLock<Heap>);
+    unsafe fn pop(&mut self) -> Option<usize> {
+        if self.head == 0 {
+            return None;
+        }
+        let block = self.head;
+        self.head = *(block as *const usize);
+        Some(block)
+    }
+}
+
+struct SizeClassArena {
+    free_list: ClassLock<FreeList>,
+    /// Times the lock was found already held
+    contended: AtomicUsize,
+}
+
+impl SizeClassArena {
+    const fn new() -> Self {
+        Self {
+            free_list: ClassLock::new(FreeList::new()),
+            contended: AtomicUsize::new(0),
+        }
+    }
+
+    fn lock(&self) -> IRQSpinLockGuard<FreeList> {
+        if let Some(guard) = self.free_list.try_lock() {
+            return guard;
+        }
+        self.contended.fetch_add(1, Ordering::Relaxed);
+        self.free_list.lock()
+    }
+}
+
+/// Frames taken from the frame allocator, `pages == 0` for an unused slot
+struct GrownChunk {
+    heap: Heap,
+    start: usize,
+    pages: usize,
+}
+
+impl GrownChunk {
+    const fn new() -> Self {
+        Self { heap: Heap::new(), start: 0, pages: 0 }
+    }
+
+    fn contains(&self, addr: usize) -> bool {
+        self.start <= addr && addr < self.start + self.pages * PAGE_SIZE
+    }
+}
+
+pub struct LockedHeap {
+    /// Tried first, by exact layout
+    caches: [SlabCache; CACHE_COUNT],
+    classes: [SizeClassArena; CLASS_COUNT],
+    buddy: HeapLock<Heap>,
+    /// Address range of the static buddy heap, to route frees
+    buddy_start: AtomicUsize,
+    buddy_end: AtomicUsize,
+    /// Times the buddy lock was found already held
+    buddy_contended: AtomicUsize,
+    /// Grown when the buddy heap runs out
+    grown: HeapLock<[GrownChunk; MAX_GROWN_CHUNKS]>,
+    /// Chunks taken and given back since boot
+    grows: AtomicUsize,
+    shrinks: AtomicUsize,
+    /// Emergency reserve for `ATOMIC` requests
+    reserve: HeapLock<Heap>,
+    /// Address range of the reserve, to route frees
+    reserve_start: AtomicUsize,
+    reserve_end: AtomicUsize,
+    /// Allocations served by the reserve
+    reserve_hits: AtomicUsize,
+}
 
 impl LockedHeap {
     /// Creates an empty heap
     pub const fn new() -> LockedHeap {
-        LockedHeap(HeapLock::new(Heap::new()))
+        const EMPTY_ARENA: SizeClassArena = SizeClassArena::new();
+        const EMPTY_CHUNK: GrownChunk = GrownChunk::new();
+        LockedHeap {
+            caches: [
+                SlabCache::new("task_control_block", arc_layout::<TaskControlBlock>()),
+                SlabCache::new("map_area", Layout::new::<MapArea>()),
+                SlabCache::new("frame_tracker", Layout::new::<FrameTracker>()),
+            ],
+            classes: [EMPTY_ARENA; CLASS_COUNT],
+            buddy: HeapLock::new(Heap::new()),
+            buddy_start: AtomicUsize::new(0),
+            buddy_end: AtomicUsize::new(0),
+            buddy_contended: AtomicUsize::new(0),
+            grown: HeapLock::new([EMPTY_CHUNK; MAX_GROWN_CHUNKS]),
+            grows: AtomicUsize::new(0),
+            shrinks: AtomicUsize::new(0),
+            reserve: HeapLock::new(Heap::new()),
+            reserve_start: AtomicUsize::new(0),
+            reserve_end: AtomicUsize::new(0),
+            reserve_hits: AtomicUsize::new(0),
+        }
     }
 
     /// Creates an empty heap
     pub const fn empty() -> LockedHeap {
-        LockedHeap(HeapLock::new(Heap::new()))
+        Self::new()
+    }
+
+    fn lock_buddy(&self) -> IRQTicketMutexGuard<Heap> {
+        if let Some(guard) = self.buddy.try_lock() {
+            return guard;
+        }
+        self.buddy_contended.fetch_add(1, Ordering::Relaxed);
+        self.buddy.lock()
+    }
+
+    /// Hand `[start, start + size)` to the buddy heap.
+    unsafe fn init_buddy(&self, start: usize, size: usize) {
+        self.buddy.lock().init(start, size);
+        self.buddy_start.store(start, Ordering::Release);
+        self.buddy_end.store(start + size, Ordering::Release);
+    }
+
+    fn in_buddy(&self, ptr: *mut u8) -> bool {
+        let addr = ptr as usize;
+        self.buddy_start.load(Ordering::Acquire) <= addr && addr < self.buddy_end.load(Ordering::Acquire)
+    }
+
+    /// Hand `[start, start + size)` to the emergency reserve.
+    unsafe fn init_reserve(&self, start: usize, size: usize) {
+        self.reserve.lock().init(start, size);
+        self.reserve_start.store(start, Ordering::Release);
+        self.reserve_end.store(start + size, Ordering::Release);
+    }
+
+    fn in_reserve(&self, ptr: *mut u8) -> bool {
+        let addr = ptr as usize;
+        self.reserve_start.load(Ordering::Acquire) <= addr && addr < self.reserve_end.load(Ordering::Acquire)
+    }
+
+    unsafe fn alloc_large(&self, layout: Layout) -> *mut u8 {
+        self.alloc_large_gfp(layout, GfpFlags::NOWAIT)
+    }
+
+    unsafe fn alloc_large_gfp(&self, layout: Layout, flags: GfpFlags) -> *mut u8 {
+        if let Ok(allocation) = self.lock_buddy().alloc(layout) {
+            return allocation.as_ptr();
+        }
+        let ptr = self.alloc_grown(layout, flags);
+        if !ptr.is_null() || !flags.contains(GfpFlags::ATOMIC) {
+            return ptr;
+        }
+        match self.reserve.lock().alloc(layout) {
+            Ok(allocation) => {
+                self.reserve_hits.fetch_add(1, Ordering::Relaxed);
+                allocation.as_ptr()
+            }
+            Err(_) => 0 as *mut u8,
+        }
+    }
+
+    unsafe fn dealloc_large(&self, ptr: *mut u8, layout: Layout) {
+        if self.in_reserve(ptr) {
+            self.reserve.lock().dealloc(NonNull::new_unchecked(ptr), layout);
+        } else if self.in_buddy(ptr) {
+            self.lock_buddy().dealloc(NonNull::new_unchecked(ptr), layout);
+        } else {
+            self.dealloc_grown(ptr, layout);
+        }
+    }
+
+    /// Allocate from the grown chunks, growing by a new one if none fits.
+    unsafe fn alloc_grown(&self, layout: Layout, flags: GfpFlags) -> *mut u8 {
+        let mut grown = self.grown.lock();
+        for chunk in grown.iter_mut().filter(|chunk| chunk.pages != 0) {
+            if let Ok(allocation) = chunk.heap.alloc(layout) {
+                return allocation.as_ptr();
+            }
+        }
+        let Some(chunk) = grown.iter_mut().find(|chunk| chunk.pages == 0) else {
+            return 0 as *mut u8;
+        };
+        // buddy blocks are powers of two aligned to their size, twice the
+        // request leaves room for the alignment of the block
+        let size = (2 * layout.size().max(layout.align()).next_power_of_two()).max(GROW_SIZE);
+        let pages = size.div_ceil(PAGE_SIZE);
+        let Some(ppn) = frame_alloc_contiguous(pages, 1, flags) else {
+            return 0 as *mut u8;
+        };
+        chunk.start = PhysAddr::from(ppn).0;
+        chunk.pages = pages;
+        chunk.heap = Heap::new();
+        chunk.heap.init(chunk.start, pages * PAGE_SIZE);
+        self.grows.fetch_add(1, Ordering::Relaxed);
+        match chunk.heap.alloc(layout) {
+            Ok(allocation) => allocation.as_ptr(),
+            Err(_) => 0 as *mut u8,
+        }
+    }
+
+    /// Free into its grown chunk, which goes back to the frame allocator
+    /// once empty.
+    unsafe fn dealloc_grown(&self, ptr: *mut u8, layout: Layout) {
+        let (start, pages) = {
+            let mut grown = self.grown.lock();
+            let chunk = grown
+                .iter_mut()
+                .find(|chunk| chunk.pages != 0 && chunk.contains(ptr as usize))
+                .expect("freeing a block the heap never allocated");
+            chunk.heap.dealloc(NonNull::new_unchecked(ptr), layout);
+            if chunk.heap.stats_alloc_actual() != 0 {
+                return;
+            }
+            (chunk.start, core::mem::replace(&mut chunk.pages, 0))
+        };
+        self.shrinks.fetch_add(1, Ordering::Relaxed);
+        log::debug!("heap shrunk by {} pages at {:#x}", pages, start);
+        frame_dealloc_range(PhysPageNum::from(PhysAddr::from(start)), pages);
+    }
+
+    /// Bytes of the grown chunks, and allocated in them
+    fn grown_stats(&self) -> (usize, usize) {
+        let grown = self.grown.lock();
+        grown
+            .iter()
+            .filter(|chunk| chunk.pages != 0)
+            .fold((0, 0), |(total, allocated), chunk| {
+                (total + chunk.pages * PAGE_SIZE, allocated + chunk.heap.stats_alloc_actual())
+            })
+    }
+
+    fn cache_for(&self, layout: &Layout) -> Option<&SlabCache> {
+        self.caches.iter().find(|cache| cache.serves(layout))
+    }
+
+    /// Refills taken from the reserve stay in the arena, like any other chunk.
+    unsafe fn alloc_small(&self, index: usize, flags: GfpFlags) -> *mut u8 {
+        let mut free_list = self.classes[index].lock();
+        if free_list.head == 0 && free_list.refills == MAX_REFILLS {
+            let block = self.alloc_large_gfp(class_layout(index), flags);
+            if !block.is_null() {
+                free_list.spilled += 1;
+            }
+            return block;
+        }
+        if free_list.head == 0 {
+            let block_size = SIZE_CLASSES[index];
+            let chunk = self.alloc_large_gfp(Layout::from_size_align_unchecked(REFILL_SIZE, REFILL_SIZE), flags);
+            if chunk.is_null() {
+                return chunk;
+            }
+            // pushed in reverse so blocks are handed out by ascending address
+            for offset in (0..REFILL_SIZE).step_by(block_size).rev() {
+                free_list.push(chunk as usize + offset);
+            }
+            let refills = free_list.refills;
+            free_list.chunks[refills] = chunk as usize;
+            free_list.refills += 1;
+        }
+        free_list.in_use += 1;
+        free_list.pop().unwrap() as *mut u8
+    }
+
+    /// Blocks of the arena never go back to the buddy heap, the arena keeps
+    /// them for reuse. Those it spilled do.
+    unsafe fn dealloc_small(&self, index: usize, ptr: *mut u8) {
+        let mut free_list = self.classes[index].lock();
+        if free_list.owns(ptr as usize) {
+            free_list.in_use -= 1;
+            free_list.push(ptr as usize);
+        } else {
+            free_list.spilled -= 1;
+            self.dealloc_large(ptr, class_layout(index));
+        }
+    }
+}
+
+impl LockedHeap {
+    /// Allocate in the context described by `flags`.
+    pub unsafe fn alloc_gfp(&self, layout: Layout, flags: GfpFlags) -> *mut u8 {
+        check_context(flags);
+        if let Some(cache) = self.cache_for(&layout) {
+            return cache.alloc(|slab_layout| self.alloc_large_gfp(slab_layout, flags));
+        }
+        match class_index(&layout) {
+            Some(index) => self.alloc_small(index, flags),
+            None => self.alloc_large_gfp(layout, flags),
+        }
     }
 }
 
@@ -27,27 +404,26 @@ impl Deref for LockedHeap {
     type Target = HeapLock<Heap>;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.buddy
     }
 }
 
 unsafe impl GlobalAlloc for LockedHeap {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        log::debug!("alloc");
-        self.0
-            .lock()
-            .alloc(layout)
-            .ok()
-            .map_or(0 as *mut u8, |allocation| allocation.as_ptr())
+        self.alloc_gfp(layout, current_gfp())
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        self.0.lock().dealloc(NonNull::new_unchecked(ptr), layout);
-        log::debug!("dealloc");
+        if let Some(cache) = self.cache_for(&layout) {
+            return cache.dealloc(ptr, |slab, slab_layout| self.dealloc_large(slab, slab_layout));
+        }
+        match class_index(&layout) {
+            Some(index) => self.dealloc_small(index, ptr),
+            None => self.dealloc_large(ptr, layout),
+        }
     }
 }
 
-/// I should implement a slab allcator
 /// Request space for buddy dynamiclly
 #[global_allocator]
 static HEAP_ALLOCATOR: LockedHeap =  LockedHeap::empty();
@@ -60,9 +436,9 @@ static mut HEAP_SPACE: [u8; KERNEL_HEAP_SIZE] = [0; KERNEL_HEAP_SIZE];
 pub fn init_heap() {
     log::info!("heap allocator initializing.");
     unsafe {
-        HEAP_ALLOCATOR.
-            lock().
-            init(HEAP_SPACE.as_ptr() as usize, KERNEL_HEAP_SIZE);
+        let start = HEAP_SPACE.as_ptr() as usize;
+        HEAP_ALLOCATOR.init_reserve(start, RESERVE_SIZE);
+        HEAP_ALLOCATOR.init_buddy(start + RESERVE_SIZE, KERNEL_HEAP_SIZE - RESERVE_SIZE);
     }
     log::info!("heap allocator initialized successfully.");
 }
@@ -142,4 +518,192 @@ pub fn test_dead_lock_in_interrupt() {
         );
     }
     println!("pass");
-}
\ No newline at end of file
+}
+/// Byte counts of the kernel heap
+pub struct HeapStats {
+    /// Buddy heap, reserve and grown chunks together
+    pub total: usize,
+    /// Taken from the buddy heap, the reserve and the grown chunks, arena
+    /// chunks and slabs included
+    pub allocated: usize,
+    /// Grown past the static heap, a part of `total`
+    pub grown: usize,
+    /// Handed out from the arenas, a part of `allocated`
+    pub arena_in_use: usize,
+    /// Handed out from the slab caches, a part of `allocated`
+    pub slab_in_use: usize,
+}
+
+pub fn heap_stats() -> HeapStats {
+    let arena_in_use = HEAP_ALLOCATOR
+        .classes
+        .iter()
+        .zip(SIZE_CLASSES)
+        .map(|(arena, class_size)| arena.free_list.lock().in_use * class_size)
+        .sum();
+    let (grown, grown_allocated) = HEAP_ALLOCATOR.grown_stats();
+    HeapStats {
+        total: KERNEL_HEAP_SIZE + grown,
+        allocated: HEAP_ALLOCATOR.lock().stats_alloc_actual()
+            + HEAP_ALLOCATOR.reserve.lock().stats_alloc_actual()
+            + grown_allocated,
+        grown,
+        arena_in_use,
+        slab_in_use: HEAP_ALLOCATOR.caches.iter().map(SlabCache::in_use_bytes).sum(),
+    }
+}
+
+/// Counters of every slab cache
+pub fn slab_stats() -> Vec<SlabStats> {
+    HEAP_ALLOCATOR.caches.iter().map(SlabCache::stats).collect()
+}
+
+#[monitor_command(name = "heapstat", help = "Show slab caches, per-size-class heap arenas and lock contention")]
+fn heapstat_command(_args: &[&str]) {
+    println!("{:>6} {:>8} {:>8} {:>8} {:>10}", "CLASS", "IN_USE", "REFILLS", "SPILLED", "CONTENDED");
+    for (index, arena) in HEAP_ALLOCATOR.classes.iter().enumerate() {
+        let (in_use, refills, spilled) = {
+            let free_list = arena.free_list.lock();
+            (free_list.in_use, free_list.refills, free_list.spilled)
+        };
+        println!(
+            "{:>6} {:>8} {:>8} {:>8} {:>10}",
+            SIZE_CLASSES[index],
+            in_use,
+            refills,
+            spilled,
+            arena.contended.load(Ordering::Relaxed)
+        );
+    }
+    println!("{:<20} {:>6} {:>8} {:>6}", "CACHE", "SIZE", "IN_USE", "SLABS");
+    for stats in slab_stats() {
+        println!("{:<20} {:>6} {:>8} {:>6}", stats.name, stats.object_size, stats.in_use, stats.slabs);
+    }
+    println!(
+        "buddy: {} bytes allocated, lock contended {} times",
+        HEAP_ALLOCATOR.lock().stats_alloc_actual(),
+        HEAP_ALLOCATOR.buddy_contended.load(Ordering::Relaxed)
+    );
+    let (grown, grown_allocated) = HEAP_ALLOCATOR.grown_stats();
+    println!(
+        "grown: {} of {} bytes allocated, {} chunks taken, {} given back",
+        grown_allocated,
+        grown,
+        HEAP_ALLOCATOR.grows.load(Ordering::Relaxed),
+        HEAP_ALLOCATOR.shrinks.load(Ordering::Relaxed)
+    );
+    println!(
+        "reserve: {} of {} bytes allocated, {} atomic allocations served",
+        HEAP_ALLOCATOR.reserve.lock().stats_alloc_actual(),
+        RESERVE_SIZE,
+        HEAP_ALLOCATOR.reserve_hits.load(Ordering::Relaxed)
+    );
+}
+
+/// Blocks handed out, chunks taken and blocks spilled by each arena
+fn arena_counters() -> [(usize, usize, usize); CLASS_COUNT] {
+    core::array::from_fn(|index| {
+        let free_list = HEAP_ALLOCATOR.classes[index].free_list.lock();
+        (free_list.in_use, free_list.refills, free_list.spilled)
+    })
+}
+
+/// Compare the allocation pattern of task creation through the size-class
+/// arenas and through the buddy heap alone, and check that the arenas
+/// take every block back for reuse.
+///
+/// Tests run on the boot hart before the scheduler starts, nothing
+/// contends here: the difference shows up as time spent per allocation.
+/// The `arenatest` user test allocates from several tasks at once.
+#[kernel_test]
+fn heap_contention_bench() {
+    use crate::timer::get_time;
+
+    const ROUNDS: usize = 200;
+    // roughly what creating a task allocates: handles, contexts, fd table, names
+    const SIZES: [usize; 6] = [16, 24, 64, 112, 256, 1024];
+
+    let run = |use_classes: bool| {
+        let mut blocks = [(0 as *mut u8, Layout::new::<u8>()); SIZES.len()];
+        let start = get_time();
+        for _ in 0..ROUNDS {
+            for (block, &size) in blocks.iter_mut().zip(SIZES.iter()) {
+                let layout = Layout::from_size_align(size, 8).unwrap();
+                let ptr = unsafe {
+                    if use_classes {
+                        HEAP_ALLOCATOR.alloc(layout)
+                    } else {
+                        HEAP_ALLOCATOR.alloc_large(layout)
+                    }
+                };
+                assert!(!ptr.is_null());
+                *block = (ptr, layout);
+            }
+            for &(ptr, layout) in blocks.iter() {
+                unsafe {
+                    if use_classes {
+                        HEAP_ALLOCATOR.dealloc(ptr, layout);
+                    } else {
+                        HEAP_ALLOCATOR.dealloc_large(ptr, layout);
+                    }
+                }
+            }
+        }
+        get_time() - start
+    };
+
+    let buddy_only = run(false);
+    let before = arena_counters();
+    let size_classes = run(true);
+    let after = arena_counters();
+    for ((in_use, refills, spilled), (in_use_after, refills_after, spilled_after)) in before.into_iter().zip(after) {
+        assert_eq!(in_use_after, in_use);
+        assert_eq!(spilled_after, spilled);
+        // a block freed is the next one handed out, one chunk is enough
+        assert!(refills_after <= refills + 1);
+    }
+
+    println!(
+        "heap bench: {} allocations, buddy only {} cycles, size classes {} cycles",
+        ROUNDS * SIZES.len(),
+        buddy_only,
+        size_classes
+    );
+}
+
+#[kernel_test]
+fn arena_bound_test() {
+    let index = CLASS_COUNT - 1;
+    let layout = class_layout(index);
+    let (in_use, _, spilled) = arena_counters()[index];
+
+    // one more block than the arena can ever hold
+    let blocks: Vec<*mut u8> = (0..MAX_REFILLS * REFILL_SIZE / SIZE_CLASSES[index] + 1)
+        .map(|_| unsafe { HEAP_ALLOCATOR.alloc(layout) })
+        .collect();
+    assert!(blocks.iter().all(|block| !block.is_null()));
+    let (_, refills_full, spilled_full) = arena_counters()[index];
+    assert_eq!(refills_full, MAX_REFILLS);
+    assert!(spilled_full > spilled);
+
+    for &block in blocks.iter() {
+        unsafe { HEAP_ALLOCATOR.dealloc(block, layout) };
+    }
+    assert_eq!(arena_counters()[index], (in_use, MAX_REFILLS, spilled));
+}
+
+#[kernel_test]
+fn heap_grow_test() {
+    use super::frame_allocator::available_frames;
+
+    let frames_before = available_frames();
+    let layout = Layout::from_size_align(3 * GROW_SIZE, PAGE_SIZE).unwrap();
+    let ptr = unsafe { HEAP_ALLOCATOR.alloc_grown(layout, GfpFlags::NOWAIT) };
+    assert!(!ptr.is_null());
+    assert!(!HEAP_ALLOCATOR.in_buddy(ptr));
+    unsafe { ptr.write_bytes(0xa5, layout.size()) };
+    assert!(available_frames() < frames_before);
+    // the only block of its chunk, which goes back whole
+    unsafe { HEAP_ALLOCATOR.dealloc_large(ptr, layout) };
+    assert_eq!(available_frames(), frames_before);
+}
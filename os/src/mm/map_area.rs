@@ -1,7 +1,7 @@
-use alloc::collections::btree_map::BTreeMap;
+use alloc::{collections::btree_map::BTreeMap, sync::Arc};
 use bitflags::bitflags;
 
-use crate::{config::PAGE_SIZE, mm::address::StepByOne};
+use crate::{config::PAGE_SIZE, fs::vfs::Inode, mm::address::StepByOne};
 
 use super::{
     address::{
@@ -10,6 +10,7 @@ use super::{
         VirtAddr, 
         VirtPageNum
     }, 
+    error::MemoryError,
     frame_allocator::{
         frame_alloc, 
         FrameTracker
@@ -17,7 +18,8 @@ use super::{
     page_table::{
         PTEFlags, 
         PageTable
-    }
+    },
+    shm::ShmSegment,
 };
 
 // unit is page
@@ -26,12 +28,30 @@ pub struct MapArea {
     data_frames: BTreeMap<VirtPageNum, FrameTracker>,
     map_type: MapType,
     map_perm: MapPermission,
+    /// Lowest page a stack area may grow down to, on a fault below it
+    grows_down_to: Option<VirtPageNum>,
+    /// Segment of a shared area, with the index of its page at the start
+    shared: Option<(Arc<ShmSegment>, usize)>,
+    /// File of a file area, with the offset of its page at the start
+    file: Option<(Arc<dyn Inode>, usize)>,
 }
 
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum MapType {
     Identical,
+    /// Every page at the same distance from its frame: the VPN minus this
+    /// is the PPN, wrapping. For the kernel image, once it is moved
+    Linear(usize),
     Framed,
+    /// Framed, but each frame is only allocated on the first fault, or when
+    /// a syscall populates it before copying: the heap, anonymous mappings
+    /// and the zeroed tail of executable segments
+    Lazy,
+    /// The frames of a shared memory segment, owned by the segment
+    Shared,
+    /// Lazy, each frame is filled from a file on the first fault. Private:
+    /// writes land in the frame, never in the file
+    File,
 }
 
 bitflags! {
@@ -43,6 +63,14 @@ bitflags! {
     }
 }
 
+bitflags! {
+    /// How `MemorySet::map_anonymous` places an area
+    pub struct MapFlags: u32 {
+        /// Map exactly at the given address, no guard gap is kept
+        const FIXED = 0x10;
+    }
+}
+
 impl MapArea {
     pub fn new (
         start_va: VirtAddr,
@@ -59,15 +87,87 @@ impl MapArea {
             data_frames: BTreeMap::new(),
             map_type,
             map_perm,
+            grows_down_to: None,
+            shared: None,
+            file: None,
+        }
+    }
+
+    /// An area mapping all of `segment` from `start_va` on.
+    pub fn new_shared(start_va: VirtAddr, segment: Arc<ShmSegment>, map_perm: MapPermission) -> Self {
+        let start_vpn = start_va.down_to_vpn();
+        let end_vpn = VirtPageNum(start_vpn.0 + segment.pages());
+        Self {
+            vpn_range: VPNRange::new(start_vpn, end_vpn),
+            data_frames: BTreeMap::new(),
+            map_type: MapType::Shared,
+            map_perm,
+            grows_down_to: None,
+            shared: Some((segment, 0)),
+            file: None,
+        }
+    }
+
+    /// A private mapping of `inode` from the byte `offset` on, page aligned,
+    /// over `[start_va, end_va)`.
+    pub fn new_file(
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        inode: Arc<dyn Inode>,
+        offset: usize,
+        map_perm: MapPermission,
+    ) -> Self {
+        assert_eq!(offset % PAGE_SIZE, 0);
+        Self {
+            file: Some((inode, offset)),
+            ..Self::new(start_va, end_va, MapType::File, map_perm)
         }
     }
 
+    /// The segment of a shared area, if it maps all of it
+    pub fn shared_segment(&self) -> Option<&Arc<ShmSegment>> {
+        self.shared
+            .as_ref()
+            .filter(|(segment, first)| *first == 0 && self.vpn_range.get_end().0 - self.vpn_range.get_start().0 == segment.pages())
+            .map(|(segment, _)| segment)
+    }
+
     #[inline(always)]
     pub fn get_vpn_range(&self) -> VPNRange {
         self.vpn_range
     }
 
+    #[inline(always)]
+    pub fn get_map_type(&self) -> MapType {
+        self.map_type
+    }
+
+    #[inline(always)]
+    pub fn get_map_perm(&self) -> MapPermission {
+        self.map_perm
+    }
+
+    #[inline(always)]
+    pub fn grows_down_to(&self) -> Option<VirtPageNum> {
+        self.grows_down_to
+    }
+
+    /// Let the area grow down to `lowest` on faults, see `MemorySet::handle_lazy_fault`.
+    pub fn set_grows_down_to(&mut self, lowest: VirtPageNum) {
+        assert!(lowest <= self.vpn_range.get_start());
+        self.grows_down_to = Some(lowest);
+    }
+
+    /// Number of frames owned by this area
+    #[inline(always)]
+    pub fn frame_count(&self) -> usize {
+        self.data_frames.len()
+    }
+
     pub fn map(&mut self, page_table: &mut PageTable) {
+        if matches!(self.map_type, MapType::Lazy | MapType::File) {
+            return;
+        }
         for vpn in self.vpn_range {
             self.map_one(page_table, vpn);
         }
@@ -111,11 +211,18 @@ impl MapArea {
             MapType::Identical => {
                 ppn = PhysPageNum(vpn.0)
             }
-            MapType::Framed => {
+            MapType::Linear(offset) => {
+                ppn = PhysPageNum(vpn.0.wrapping_sub(offset))
+            }
+            MapType::Framed | MapType::Lazy | MapType::File => {
                 let frame = frame_alloc().unwrap();
                 ppn = frame.ppn;
                 self.data_frames.insert(vpn, frame);
             }
+            MapType::Shared => {
+                let (segment, first) = self.shared.as_ref().unwrap();
+                ppn = segment.frame(first + vpn.0 - self.vpn_range.get_start().0);
+            }
         }
 
         let pte_flags = PTEFlags::from_bits(self.map_perm.bits.into()).unwrap();
@@ -127,11 +234,112 @@ impl MapArea {
             MapType::Framed => {
                 self.data_frames.remove(&vpn);
             }
+            MapType::Lazy | MapType::File => {
+                // never touched, nothing to unmap
+                if self.data_frames.remove(&vpn).is_none() {
+                    return;
+                }
+            }
             _ => {}
         }
         page_table.unmap(vpn);
     }
 
+    /// Back `vpn` of a lazy area with a zeroed frame, or of a file area
+    /// with a frame holding its page of the file. What lies past the end
+    /// of the file reads as zeros.
+    ///
+    /// Unlike `map_one`, running out of frames is an error, not a panic:
+    /// it happens at fault time, long after the area was accepted.
+    pub fn populate(&mut self, page_table: &mut PageTable, vpn: VirtPageNum) -> Result<(), MemoryError> {
+        assert!(matches!(self.map_type, MapType::Lazy | MapType::File));
+        if self.data_frames.contains_key(&vpn) {
+            return Ok(());
+        }
+        let frame = frame_alloc().ok_or(MemoryError::OutOfMemory)?;
+        if let Some((inode, offset)) = self.file.as_ref() {
+            let page = vpn.0 - self.vpn_range.get_start().0;
+            inode.read_at(offset + page * PAGE_SIZE, frame.ppn.get_bytes_array_slice());
+        }
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits.into()).unwrap();
+        page_table.map(vpn, frame.ppn, pte_flags);
+        self.data_frames.insert(vpn, frame);
+        Ok(())
+    }
+
+    /// Map the pages from `start` up to the current start of a framed area,
+    /// the area then starts at `start`.
+    ///
+    /// Mapped from the top down: out of frames, the area keeps the pages
+    /// it got.
+    pub fn grow_down(&mut self, page_table: &mut PageTable, start: VirtPageNum) -> Result<(), MemoryError> {
+        assert_eq!(self.map_type, MapType::Framed);
+        let end = self.vpn_range.get_end();
+        let pte_flags = PTEFlags::from_bits(self.map_perm.bits.into()).unwrap();
+        while start < self.vpn_range.get_start() {
+            let vpn = VirtPageNum(self.vpn_range.get_start().0 - 1);
+            let frame = frame_alloc().ok_or(MemoryError::OutOfMemory)?;
+            page_table.map(vpn, frame.ppn, pte_flags);
+            self.data_frames.insert(vpn, frame);
+            self.vpn_range = VPNRange::new(vpn, end);
+        }
+        Ok(())
+    }
+
+    /// Give the area `perm`, the pages already mapped included.
+    ///
+    /// Their stale translations may still be cached, flushing them is up to the caller.
+    pub fn set_permission(&mut self, page_table: &mut PageTable, perm: MapPermission) {
+        self.map_perm = perm;
+        let pte_flags = PTEFlags::from_bits(perm.bits.into()).unwrap();
+        match self.map_type {
+            MapType::Identical | MapType::Linear(_) | MapType::Shared => {
+                for vpn in self.vpn_range {
+                    page_table.protect(vpn, pte_flags);
+                }
+            }
+            MapType::Framed | MapType::Lazy | MapType::File => {
+                // lazy pages not touched yet get `perm` on their first fault
+                for &vpn in self.data_frames.keys() {
+                    page_table.protect(vpn, pte_flags);
+                }
+            }
+        }
+    }
+
+    /// Move the end of a lazy area, the pages cut off must be unmapped already.
+    pub fn set_end(&mut self, end: VirtPageNum) {
+        assert_eq!(self.map_type, MapType::Lazy);
+        let start = self.vpn_range.get_start();
+        assert!(start < end);
+        assert!(self.data_frames.range(end..).next().is_none(), "resized over resident pages");
+        self.vpn_range = VPNRange::new(start, end);
+    }
+
+    /// Split the area at `at`, `self` keeps `[start, at)` and the
+    /// returned area takes `[at, end)` along with its frames.
+    pub fn split_off(&mut self, at: VirtPageNum) -> Self {
+        let (start, end) = (self.vpn_range.get_start(), self.vpn_range.get_end());
+        assert!(start < at && at < end, "split point {:?} outside the area", at);
+        self.vpn_range = VPNRange::new(start, at);
+        Self {
+            vpn_range: VPNRange::new(at, end),
+            data_frames: self.data_frames.split_off(&at),
+            map_type: self.map_type,
+            map_perm: self.map_perm,
+            // only the bottom part can still grow down
+            grows_down_to: None,
+            shared: self
+                .shared
+                .as_ref()
+                .map(|(segment, first)| (segment.clone(), first + at.0 - start.0)),
+            file: self
+                .file
+                .as_ref()
+                .map(|(inode, offset)| (inode.clone(), offset + (at.0 - start.0) * PAGE_SIZE)),
+        }
+    }
+
     pub fn get_vpn_end(&self) -> VirtPageNum {
         self.vpn_range.get_end()
     }
@@ -141,7 +349,11 @@ impl MapArea {
             vpn_range: VPNRange::new(other.vpn_range.get_start(), other.vpn_range.get_end()),
             data_frames: BTreeMap::new(),
             map_type : other.map_type,
-            map_perm: other.map_perm
+            map_perm: other.map_perm,
+            grows_down_to: other.grows_down_to,
+            // the copy maps the same frames
+            shared: other.shared.clone(),
+            file: other.file.clone(),
         }
     }
 
@@ -0,0 +1,162 @@
+//! Boot-time physical memory map
+//!
+//! Everything the frame allocator must not hand out is registered here
+//! before it starts: the SBI firmware below the kernel, the kernel image,
+//! the device tree blob, its reserved regions, the RAM disk image the
+//! bootloader loaded and the MMIO windows of the platform.
+//! [`init_frame_allocator`](super::frame_allocator::init_frame_allocator)
+//! then only gets the RAM left over, see [`usable_ranges`].
+//!
+//! The final map is readable from `/proc/iomem`.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use alloc::vec::Vec;
+
+use crate::{
+    boards::{fdt::DeviceTree, platform::platform},
+    config::{PAGE_SIZE, PHYSTOP},
+    sync::spin::mutex::IRQSpinLock,
+};
+
+type Mutex<T> = IRQSpinLock<T>;
+
+/// Start of RAM when no device tree says otherwise
+const DEFAULT_RAM_START: usize = 0x8000_0000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionKind {
+    /// Usable memory, unless another region overlaps it
+    Ram,
+    /// SBI firmware, below the kernel
+    Firmware,
+    /// The kernel image
+    Kernel,
+    /// The device tree blob
+    DeviceTree,
+    /// Reserved by the device tree
+    Reserved,
+    /// The RAM disk image loaded by the bootloader
+    Initrd,
+    /// Device registers
+    Mmio,
+}
+
+impl RegionKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            RegionKind::Ram => "System RAM",
+            RegionKind::Firmware => "firmware",
+            RegionKind::Kernel => "Kernel image",
+            RegionKind::DeviceTree => "device tree",
+            RegionKind::Reserved => "reserved",
+            RegionKind::Initrd => "initrd",
+            RegionKind::Mmio => "mmio",
+        }
+    }
+}
+
+/// `[start, end)` of physical memory
+#[derive(Debug, Clone, Copy)]
+pub struct Region {
+    pub start: usize,
+    pub end: usize,
+    pub kind: RegionKind,
+}
+
+static MEMORY_MAP: Mutex<Vec<Region>> = Mutex::new(Vec::new());
+/// Set once the frame allocator took its ranges, later reservations can't be honoured
+static FROZEN: AtomicBool = AtomicBool::new(false);
+
+/// Register a region, must happen before the frame allocator is initialized.
+pub fn reserve(start: usize, end: usize, kind: RegionKind) {
+    assert!(
+        !FROZEN.load(Ordering::Acquire),
+        "memory map reservation {:#x}..{:#x} after frame allocator init",
+        start,
+        end
+    );
+    if start < end {
+        MEMORY_MAP.lock().push(Region { start, end, kind });
+    }
+}
+
+/// Fill the map from the device tree, if any, and the platform.
+pub fn init(tree: Option<&DeviceTree>) {
+    extern "C" {
+        fn skernel();
+        fn ekernel();
+    }
+
+    match tree {
+        Some(tree) => {
+            reserve(tree.blob.0, tree.blob.0 + tree.blob.1, RegionKind::DeviceTree);
+            for &(start, size) in tree.ram.iter() {
+                reserve(start, start + size, RegionKind::Ram);
+            }
+            for &(start, size) in tree.reserved.iter() {
+                reserve(start, start + size, RegionKind::Reserved);
+            }
+            if let Some((start, size)) = tree.initrd {
+                reserve(start, start + size, RegionKind::Initrd);
+            }
+        }
+        None => reserve(DEFAULT_RAM_START, PHYSTOP, RegionKind::Ram),
+    }
+
+    let ram_start = MEMORY_MAP
+        .lock()
+        .iter()
+        .filter(|region| region.kind == RegionKind::Ram)
+        .map(|region| region.start)
+        .min()
+        .unwrap_or(DEFAULT_RAM_START);
+    reserve(ram_start, skernel as usize, RegionKind::Firmware);
+    reserve(skernel as usize, ekernel as usize, RegionKind::Kernel);
+    for &(start, size) in platform().mmio {
+        reserve(start, start + size, RegionKind::Mmio);
+    }
+
+    for region in regions() {
+        log::info!("memmap: [{:#x}, {:#x}) {}", region.start, region.end, region.kind.name());
+    }
+}
+
+/// Every region, by start address.
+pub fn regions() -> Vec<Region> {
+    let mut regions = MEMORY_MAP.lock().clone();
+    regions.sort_by_key(|region| (region.start, region.kind != RegionKind::Ram));
+    regions
+}
+
+/// Page aligned RAM ranges below the end of the platform RAM no other
+/// region overlaps.
+///
+/// The end of the RAM the kernel lies in stays the upper bound: the kernel
+/// identity maps only that much.
+/// Freezes the map.
+pub fn usable_ranges() -> Vec<(usize, usize)> {
+    FROZEN.store(true, Ordering::Release);
+    let regions = regions();
+    let mut usable = Vec::new();
+    for ram in regions.iter().filter(|region| region.kind == RegionKind::Ram) {
+        let mut ranges = alloc::vec![(ram.start, ram.end.min(platform().ram_end))];
+        for hole in regions.iter().filter(|region| region.kind != RegionKind::Ram) {
+            ranges = ranges
+                .into_iter()
+                .flat_map(|(start, end)| {
+                    if hole.end <= start || end <= hole.start {
+                        return [(start, end), (0, 0)];
+                    }
+                    [(start, hole.start.max(start)), (hole.end.min(end), end)]
+                })
+                .filter(|&(start, end)| start < end)
+                .collect();
+        }
+        usable.extend(ranges.into_iter().filter_map(|(start, end)| {
+            let (start, end) = ((start + PAGE_SIZE - 1) & !(PAGE_SIZE - 1), end & !(PAGE_SIZE - 1));
+            (start < end).then_some((start, end))
+        }));
+    }
+    usable
+}
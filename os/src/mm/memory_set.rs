@@ -1,19 +1,23 @@
-use core::arch::asm;
-
 use alloc::{sync::Arc, vec::Vec};
 
 use lazy_static::lazy_static;
+use os_macros::{kernel_test, monitor_command};
 use riscv::register::satp;
 
 use crate::{
-    boards::MMIO, 
-    config::{PAGE_SIZE, PHYSTOP, TRAMPOLINE}, 
-    mm::map_area::{MapArea, MapPermission, MapType}, 
+    boards::platform::platform, 
+    config::{ASLR_MMAP_PAGES, ASLR_PIE_PAGES, ASLR_STACK_PAGES, FLAT_BASE, PAGE_SIZE, PAGE_SIZE_BITS, SATP_ROOT_PPN_BITS, SIGRETURN_TRAMPOLINE, TRAMPOLINE, USER_GUARD_GAP, USER_MMAP_BASE, VA_WIDTH}, 
+    fs::vfs::Inode,
+    mm::map_area::{MapArea, MapFlags, MapPermission, MapType}, 
+    println,
+    random::random_page_offset,
     sync::spin::mutex::IRQSpinLock, 
+    syscall::syscall_num::SYSCALL_SIGRETURN,
+    task::current_task,
 };
 
 use super::{
-    address::{PhysAddr, VPNRange, VirtAddr, VirtPageNum}, page_table::{PTEFlags, PageTable, PageTableEntry}
+    address::{PhysAddr, VPNRange, VirtAddr, VirtPageNum}, asid::Asid, binfmt::{FlatImage, FLAT_BSS_SIZE}, elf::ElfImage, error::MemoryError, frame_allocator::available_frames, page_table::{PTEFlags, PageTable, PageTableEntry, Pbmt}, shm::ShmSegment, tlb
 };
 
 extern "C" {
@@ -62,10 +66,62 @@ struct UserMemorySetInfo {
 }
 
 
+/// Residency of one page, as reported by `sys_mincore`.
+#[derive(Copy, Clone, PartialEq, Debug)]
+#[repr(u8)]
+pub enum PageResidency {
+    /// No area covers the page
+    Unmapped = 0,
+    /// Backed by a frame
+    Resident = 1,
+    /// Covered by an area, but no frame yet (demand paging)
+    NotResident = 2,
+    /// Written out to a swap device, reserved: there is no swap yet
+    Swapped = 3,
+}
+
+/// Description of one area, as reported by `sys_vma_info`.
+///
+/// Layout shared with user space.
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+pub struct AreaInfo {
+    pub start: usize,
+    pub end: usize,
+    /// `MapPermission` bits
+    pub perm: usize,
+    /// 0 for `MapType::Identical` and `MapType::Linear`, 1 for `MapType::Framed`, 2 for `MapType::Lazy`,
+    /// 3 for `MapType::Shared`, 4 for `MapType::File`
+    pub map_type: usize,
+}
+
+/// Limits of a user address space, from the `RLIMIT_AS` and
+/// `RLIMIT_STACK` of its task group. In pages.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct MemoryLimits {
+    /// Pages every area spans together
+    pub address_space: usize,
+    /// Pages a growing stack spans
+    pub stack: usize,
+}
+
+impl MemoryLimits {
+    pub const UNLIMITED: Self = Self {
+        address_space: usize::MAX,
+        stack: usize::MAX,
+    };
+}
+
     pub struct MemorySet {
         page_table: PageTable,
+        /// Tags the translations of this space in the TLB
+        asid: Asid,
         areas: Vec<MapArea>,
     user_info: Option<UserMemorySetInfo>,
+    limits: MemoryLimits,
+    /// Where `find_free_range` starts looking, `USER_MMAP_BASE` moved up
+    /// by a random offset with address space randomization
+    mmap_base: usize,
 }
 
 impl MemorySet {
@@ -73,15 +129,22 @@ impl MemorySet {
         // log::debug!("new bare");
         let a = Self {
             page_table: PageTable::new(),
+            asid: Asid::alloc(),
             areas: Vec::new(),
             user_info: None,
+            limits: MemoryLimits::UNLIMITED,
+            mmap_base: USER_MMAP_BASE,
         };
         // log::debug!("new bare end");
         a
     }
 
     pub fn token(&self) -> usize {
-        self.page_table.token()
+        self.page_table.token() | self.asid() << SATP_ROOT_PPN_BITS
+    }
+
+    pub fn asid(&self) -> usize {
+        self.asid.get()
     }
 
     // push something data or not to the map area
@@ -96,6 +159,31 @@ impl MemorySet {
         self.areas.push(map_area);
     }
 
+    /// Map `[start_va, end_va)` of an executable, `data` at its start and
+    /// zeroes past it: the pages holding `data` are filled now, the
+    /// following ones are lazy, a large `.bss` takes no frame until it is
+    /// touched. Returns the end of the segment.
+    fn push_image_segment(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        permission: MapPermission,
+        data: &[u8],
+    ) -> VirtPageNum {
+        let end = end_va.up_to_vpn();
+        let zeroes = if data.is_empty() {
+            start_va.down_to_vpn()
+        } else {
+            let data_end = VirtAddr::from(usize::from(start_va) + data.len()).up_to_vpn().min(end);
+            self.push(MapArea::new(start_va, data_end.into(), MapType::Framed, permission), Some(data));
+            data_end
+        };
+        if zeroes < end {
+            self.push(MapArea::new(zeroes.into(), end.into(), MapType::Lazy, permission), None);
+        }
+        end
+    }
+
     pub fn insert_framed_area(
         &mut self,
         start_va: VirtAddr,
@@ -110,6 +198,392 @@ impl MemorySet {
     }
 
 
+    /// Map a framed stack `[start_va, end_va)`, which grows down on faults
+    /// as far as `lowest_va`.
+    pub fn insert_stack_area(
+        &mut self,
+        start_va: VirtAddr,
+        end_va: VirtAddr,
+        lowest_va: VirtAddr,
+        permission: MapPermission,
+    ) {
+        let mut area = MapArea::new(start_va, end_va, MapType::Framed, permission);
+        area.set_grows_down_to(lowest_va.down_to_vpn());
+        self.push(area, None);
+    }
+
+    /// Make the unmapped `vpn` a canary page, see [`PageTable::map_canary`].
+    pub fn insert_canary_page(&mut self, vpn: VirtPageNum) {
+        self.page_table.map_canary(vpn);
+    }
+
+    pub fn remove_canary_page(&mut self, vpn: VirtPageNum) {
+        self.page_table.unmap_canary(vpn);
+    }
+
+    /// Whether `[start, end)` widened by `gap` pages on both sides overlaps no area.
+    fn is_range_free(&self, start: VirtPageNum, end: VirtPageNum, gap: usize) -> bool {
+        let (start, end) = (start.0.saturating_sub(gap), end.0 + gap);
+        self.areas.iter().all(|area| {
+            let range = area.get_vpn_range();
+            range.get_end().0 <= start || end <= range.get_start().0
+        })
+    }
+
+    /// Lowest free range of `pages` pages from the mmap base on, guard gaps kept.
+    fn find_free_range(&self, pages: usize) -> Option<VirtPageNum> {
+        let gap = USER_GUARD_GAP / PAGE_SIZE;
+        let mut candidate = VirtAddr::from(self.mmap_base).down_to_vpn().0;
+        let mut ranges: Vec<(usize, usize)> = self
+            .areas
+            .iter()
+            .map(|area| (area.get_vpn_range().get_start().0, area.get_vpn_range().get_end().0))
+            .collect();
+        ranges.sort();
+        for (start, end) in ranges {
+            if end + gap <= candidate {
+                continue;
+            }
+            if candidate + pages + gap <= start {
+                break;
+            }
+            candidate = candidate.max(end + gap);
+        }
+        // stay in the lower half, under the trampoline
+        (candidate + pages <= 1 << (VA_WIDTH - 1 - PAGE_SIZE_BITS)).then(|| VirtPageNum(candidate))
+    }
+
+    /// Map `len` bytes of zeroed memory, returns where.
+    ///
+    /// Without `MapFlags::FIXED`, `addr` is only a hint and the area is kept
+    /// `USER_GUARD_GAP` away from every other area, so an overflow faults
+    /// instead of silently running into a neighbour.
+    /// With it, the area goes exactly at `addr`, adjacency allowed, and the
+    /// call fails if the range overlaps an existing area.
+    pub fn map_anonymous(
+        &mut self,
+        addr: usize,
+        len: usize,
+        permission: MapPermission,
+        flags: MapFlags,
+    ) -> Result<VirtAddr, MemoryError> {
+        if len == 0 {
+            return Err(MemoryError::EmptyBuffer);
+        }
+        let pages = (len + PAGE_SIZE - 1) / PAGE_SIZE;
+        if pages > available_frames() || !self.fits_limit(pages) {
+            return Err(MemoryError::OutOfMemory);
+        }
+        let start = self.place(addr, pages, flags)?;
+
+        let start_va = VirtAddr::from(start);
+        let end_va = VirtAddr::from(VirtPageNum(start.0 + pages));
+        self.insert_framed_area(start_va, end_va, permission | MapPermission::U);
+        Ok(start_va)
+    }
+
+    /// Map `len` bytes of `inode` from the byte `offset` on, placed like
+    /// [`Self::map_anonymous`] does, returns where.
+    ///
+    /// The mapping is private: each page is read from the file on its
+    /// first fault, and writes to it stay in this address space. Pages
+    /// past the end of the file read as zeros.
+    pub fn map_file(
+        &mut self,
+        addr: usize,
+        len: usize,
+        permission: MapPermission,
+        flags: MapFlags,
+        inode: Arc<dyn Inode>,
+        offset: usize,
+    ) -> Result<VirtAddr, MemoryError> {
+        if len == 0 {
+            return Err(MemoryError::EmptyBuffer);
+        }
+        if offset % PAGE_SIZE != 0 {
+            return Err(MemoryError::Misaligned { address: offset, alignment: PAGE_SIZE });
+        }
+        let pages = (len + PAGE_SIZE - 1) / PAGE_SIZE;
+        if !self.fits_limit(pages) {
+            return Err(MemoryError::OutOfMemory);
+        }
+        let start = self.place(addr, pages, flags)?;
+
+        let start_va = VirtAddr::from(start);
+        let end_va = VirtAddr::from(VirtPageNum(start.0 + pages));
+        self.push(MapArea::new_file(start_va, end_va, inode, offset, permission | MapPermission::U), None);
+        Ok(start_va)
+    }
+
+    /// Where a new area of `pages` goes, see [`Self::map_anonymous`].
+    fn place(&self, addr: usize, pages: usize, flags: MapFlags) -> Result<VirtPageNum, MemoryError> {
+        let hint = VirtAddr::from(addr);
+        if flags.contains(MapFlags::FIXED) {
+            if !hint.aligned() {
+                return Err(MemoryError::Misaligned { address: addr, alignment: PAGE_SIZE });
+            }
+            let start = hint.down_to_vpn();
+            if !self.is_range_free(start, VirtPageNum(start.0 + pages), 0) {
+                return Err(MemoryError::AddressInUse);
+            }
+            Ok(start)
+        } else {
+            let gap = USER_GUARD_GAP / PAGE_SIZE;
+            let start = hint.down_to_vpn();
+            if addr != 0 && self.is_range_free(start, VirtPageNum(start.0 + pages), gap) {
+                Ok(start)
+            } else {
+                self.find_free_range(pages).ok_or(MemoryError::OutOfMemory)
+            }
+        }
+    }
+
+    /// Map all of `segment`, placed like [`Self::map_anonymous`] does,
+    /// returns where.
+    pub fn attach_shared(
+        &mut self,
+        addr: usize,
+        segment: Arc<ShmSegment>,
+        permission: MapPermission,
+        flags: MapFlags,
+    ) -> Result<VirtAddr, MemoryError> {
+        if !self.fits_limit(segment.pages()) {
+            return Err(MemoryError::OutOfMemory);
+        }
+        let start_va = VirtAddr::from(self.place(addr, segment.pages(), flags)?);
+        self.push(MapArea::new_shared(start_va, segment, permission | MapPermission::U), None);
+        Ok(start_va)
+    }
+
+    /// Unmap the segment attached at `start`.
+    ///
+    /// `PageNotMapped` if no whole segment is attached there.
+    pub fn detach_shared(&mut self, start: VirtPageNum) -> Result<(), MemoryError> {
+        let idx = self
+            .areas
+            .iter()
+            .position(|area| area.get_vpn_range().get_start() == start && area.shared_segment().is_some())
+            .ok_or(MemoryError::PageNotMapped)?;
+        let mut area = self.areas.remove(idx);
+        area.unmap(&mut self.page_table);
+        // the frames go with the last attachment, not before the flush
+        tlb::flush_range(area.get_vpn_range(), self.asid());
+        Ok(())
+    }
+
+    /// Unmap every page of `[start, end)`, splitting the areas it cuts through.
+    ///
+    /// Pages that aren't mapped are skipped. Fails without unmapping
+    /// anything if the range touches an area user mode can't access
+    /// (e.g. the trap context).
+    pub fn unmap_range(&mut self, start: VirtPageNum, end: VirtPageNum) -> Result<(), MemoryError> {
+        let overlaps = |area: &MapArea| {
+            let range = area.get_vpn_range();
+            range.get_start() < end && start < range.get_end()
+        };
+        if self
+            .areas
+            .iter()
+            .any(|area| overlaps(area) && !area.get_map_perm().contains(MapPermission::U))
+        {
+            return Err(MemoryError::PermissionDenied);
+        }
+
+        // keep the parts on either side of the range
+        self.split_areas(start, end);
+        let mut idx = 0;
+        while idx < self.areas.len() {
+            if overlaps(&self.areas[idx]) {
+                self.areas.remove(idx).unmap(&mut self.page_table);
+            } else {
+                idx += 1;
+            }
+        }
+        // the frames are free again, no stale translation may reach them,
+        // on any hart running a thread of this address space
+        tlb::flush_range(VPNRange::new(start, end), self.asid());
+        Ok(())
+    }
+
+    /// Give every page of `[start, end)` `permission`, splitting the areas
+    /// it cuts through.
+    ///
+    /// Fails without changing anything with `PageNotMapped` if a page of
+    /// the range isn't in an area, or `PermissionDenied` if the range
+    /// touches an area user mode can't access.
+    pub fn protect_range(
+        &mut self,
+        start: VirtPageNum,
+        end: VirtPageNum,
+        permission: MapPermission,
+    ) -> Result<(), MemoryError> {
+        let overlap = |area: &MapArea| {
+            let range = area.get_vpn_range();
+            range.get_end().min(end).0.saturating_sub(range.get_start().max(start).0)
+        };
+        // areas don't overlap, the range is covered if their parts in it add up
+        if self.areas.iter().map(overlap).sum::<usize>() < end.0.saturating_sub(start.0) {
+            return Err(MemoryError::PageNotMapped);
+        }
+        if self
+            .areas
+            .iter()
+            .any(|area| overlap(area) > 0 && !area.get_map_perm().contains(MapPermission::U))
+        {
+            return Err(MemoryError::PermissionDenied);
+        }
+
+        self.split_areas(start, end);
+        for area in self.areas.iter_mut().filter(|area| overlap(area) > 0) {
+            area.set_permission(&mut self.page_table, permission | MapPermission::U);
+        }
+        // a revoked permission must not survive in any TLB
+        tlb::flush_range(VPNRange::new(start, end), self.asid());
+        Ok(())
+    }
+
+    /// Split the areas straddling `start` or `end`, each area is then
+    /// either inside `[start, end)` or outside of it.
+    fn split_areas(&mut self, start: VirtPageNum, end: VirtPageNum) {
+        let mut idx = 0;
+        while idx < self.areas.len() {
+            let range = self.areas[idx].get_vpn_range();
+            // the upper part comes next, and is checked against `end` in turn
+            for at in [start, end] {
+                if range.get_start() < at && at < range.get_end() {
+                    let rest = self.areas[idx].split_off(at);
+                    self.areas.insert(idx + 1, rest);
+                    break;
+                }
+            }
+            idx += 1;
+        }
+    }
+
+    /// Resolve a fault on `vpn` if it lies in a lazy or file area, or below a stack
+    /// which may grow down to it. Returns whether it did.
+    ///
+    /// A page that is already resident isn't handled: the fault came from
+    /// its permissions, not from the lazy allocation.
+    pub fn handle_lazy_fault(&mut self, vpn: VirtPageNum) -> Result<bool, MemoryError> {
+        let Some(area) = self.areas.iter_mut().find(|area| {
+            let range = area.get_vpn_range();
+            matches!(area.get_map_type(), MapType::Lazy | MapType::File)
+                && range.get_start() <= vpn
+                && vpn < range.get_end()
+        }) else {
+            return self.grow_stack(vpn);
+        };
+        if self.page_table.find_pte_by_vpn(vpn).is_some_and(|pte| pte.is_valid()) {
+            return Ok(false);
+        }
+        area.populate(&mut self.page_table, vpn)?;
+        // the invalid entry of the fault may be cached
+        tlb::flush_page(vpn, self.asid());
+        Ok(true)
+    }
+
+    /// Grow the stack area whose growth limit covers `vpn` down to it.
+    fn grow_stack(&mut self, vpn: VirtPageNum) -> Result<bool, MemoryError> {
+        let Some(index) = self.areas.iter().position(|area| {
+            area.grows_down_to().is_some_and(|lowest| lowest <= vpn && vpn < area.get_vpn_range().get_start())
+        }) else {
+            return Ok(false);
+        };
+        let range = self.areas[index].get_vpn_range();
+        let start = range.get_start();
+        // an area mapped in the way stops the growth, so do the limits
+        let size = range.get_end().0 - vpn.0;
+        if !self.is_range_free(vpn, start, 0) || size > self.limits.stack || !self.fits_limit(start.0 - vpn.0) {
+            return Ok(false);
+        }
+        let grown = self.areas[index].grow_down(&mut self.page_table, vpn);
+        // the invalid entries of the fault may be cached
+        let new_start = self.areas[index].get_vpn_range().get_start();
+        tlb::flush_range(VPNRange::new(new_start, start), self.asid());
+        grown.map(|()| true)
+    }
+
+    /// Whether `vpn` is the page right below the growth limit of a stack:
+    /// the stack overflowed.
+    pub fn in_stack_guard(&self, vpn: VirtPageNum) -> bool {
+        self.areas
+            .iter()
+            .any(|area| area.grows_down_to().is_some_and(|lowest| lowest.0 == vpn.0 + 1))
+    }
+
+    /// Fault in every lazy page of `[start, start + len)`.
+    ///
+    /// For the kernel touching a user buffer through the page table, where
+    /// no fault would be raised for a page not backed yet.
+    pub fn populate_range(&mut self, start: usize, len: usize) -> Result<(), MemoryError> {
+        let end = start.checked_add(len).ok_or(MemoryError::AddressOutOfRange {
+            address: VirtAddr::from(start),
+            max_valid: VirtAddr::from(usize::MAX),
+        })?;
+        for vpn in VPNRange::new(VirtAddr::from(start).down_to_vpn(), VirtAddr::from(end).up_to_vpn()) {
+            self.handle_lazy_fault(vpn)?;
+        }
+        Ok(())
+    }
+
+    /// Fault in every lazy page of the NUL-terminated string at `start`,
+    /// up to its end or the first page not mapped.
+    pub fn populate_str(&mut self, start: usize) -> Result<(), MemoryError> {
+        let mut va = VirtAddr::from(start);
+        loop {
+            let vpn = va.down_to_vpn();
+            self.handle_lazy_fault(vpn)?;
+            let Some(pte) = self.translate(vpn).filter(|pte| pte.is_valid()) else {
+                return Ok(());
+            };
+            if pte.ppn().get_bytes_array_slice()[va.page_offset()..].contains(&0) {
+                return Ok(());
+            }
+            va = VirtAddr::from(VirtPageNum(vpn.0 + 1));
+        }
+    }
+
+    /// Move the end of the lazy heap area starting at `bottom` from `old_end` to `new_end`.
+    ///
+    /// Growing only reserves the pages, frames come on the first fault.
+    /// Shrinking frees the pages cut off, and the area with them if none is left.
+    pub fn resize_heap(
+        &mut self,
+        bottom: VirtPageNum,
+        old_end: VirtPageNum,
+        new_end: VirtPageNum,
+    ) -> Result<(), MemoryError> {
+        if new_end <= old_end {
+            return self.unmap_range(new_end, old_end);
+        }
+        if !self.is_range_free(old_end, new_end, 0) {
+            return Err(MemoryError::AddressInUse);
+        }
+        if !self.fits_limit(new_end.0 - old_end.0) {
+            return Err(MemoryError::OutOfMemory);
+        }
+        match self.areas.iter_mut().find(|area| area.get_vpn_range().get_start() == bottom) {
+            Some(heap) => heap.set_end(new_end),
+            None => self.push(
+                MapArea::new(
+                    bottom.into(),
+                    new_end.into(),
+                    MapType::Lazy,
+                    MapPermission::R | MapPermission::W | MapPermission::U,
+                ),
+                None,
+            ),
+        }
+        Ok(())
+    }
+
+    /// Whether `vpn` is unmapped but within `USER_GUARD_GAP` of an area.
+    pub fn in_guard_gap(&self, vpn: VirtPageNum) -> bool {
+        let gap = USER_GUARD_GAP / PAGE_SIZE;
+        gap != 0 && self.area_of(vpn).is_none() && !self.is_range_free(vpn, VirtPageNum(vpn.0 + 1), gap)
+    }
+
     pub fn remove_area_with_start_vpn(&mut self, start_vpn: VirtPageNum) {
         if let Some((idx, area)) = self
             .areas
@@ -118,123 +592,233 @@ impl MemorySet {
             .find(|(_, area)| area.get_vpn_range().get_start() == start_vpn)
         {
             area.unmap(&mut self.page_table);
+            tlb::flush_range(area.get_vpn_range(), self.asid());
             self.areas.remove(idx);
         }
     }
 
+    /// Remove the area `vpn` lies in, e.g. a stack that may have grown
+    /// below the page it started at.
+    pub fn remove_area_containing(&mut self, vpn: VirtPageNum) {
+        if let Some(start) = self.area_of(vpn).map(|area| area.get_vpn_range().get_start()) {
+            self.remove_area_with_start_vpn(start);
+        }
+    }
+
+    /// Switch this hart to this address space.
+    ///
+    /// Only the translations tagged with its ASID are flushed, those of
+    /// the previous space can't be hit any more.
     pub fn activate(&self) {
-        let satp = self.page_table.token();
+        let satp = self.token();
         unsafe {
             satp::write(satp);
-            // sync
-            asm!("sfence.vma");
         }
+        tlb::flush_asid(self.asid());
     }
 
     pub fn translate(&self, vpn: VirtPageNum) -> Option<PageTableEntry> {
         self.page_table.find_pte_by_vpn(vpn)
     }
 
-    pub fn new_kernel() -> Self {
-        log::info!("New kernel starting.");
-        let mut memory_set = Self::new_bare();
+    /// Find the area covering `vpn`.
+    fn area_of(&self, vpn: VirtPageNum) -> Option<&MapArea> {
+        self.areas.iter().find(|area| {
+            let range = area.get_vpn_range();
+            range.get_start() <= vpn && vpn < range.get_end()
+        })
+    }
 
-        log::info!("Map trampoline.");
-        memory_set.map_trampoline();
+    pub fn page_residency(&self, vpn: VirtPageNum) -> PageResidency {
+        if self.area_of(vpn).is_none() {
+            return PageResidency::Unmapped;
+        }
+        match self.translate(vpn) {
+            Some(pte) if pte.is_valid() => PageResidency::Resident,
+            _ => PageResidency::NotResident,
+        }
+    }
 
-        log::info!(".text   [{:#x}, {:#x}]", stext as usize, etext as usize);
-        log::info!(".rodata [{:#x}, {:#x}]", srodata as usize, erodata as usize);
-        log::info!(".data   [{:#x}, {:#x}]", sdata as usize, edata as usize);
-        log::info!(
-            ".bsss   [{:#x}, {:#x}]",
-            sbss_with_stack as usize,
-            ebss as usize
-        );
+    /// Valid leaf PTEs no area accounts for, the trampoline excepted.
+    pub fn stray_ptes(&self) -> Vec<VirtPageNum> {
+        let trampoline = VirtAddr::from(TRAMPOLINE).down_to_vpn();
+        self.page_table
+            .leaf_entries()
+            .into_iter()
+            .map(|(vpn, _)| vpn)
+            .filter(|&vpn| vpn != trampoline && self.area_of(vpn).is_none())
+            .collect()
+    }
 
-        log::info!("Mapping .text section with identity mapping...");
-        memory_set.push(
-            MapArea::new(
-                (stext as usize).into(),
-                (etext as usize).into(),
-                MapType::Identical,
-                MapPermission::R | MapPermission::X,
-            ),
-            None,
-        );
+    /// Pages the areas span, resident or not
+    pub fn mapped_pages(&self) -> usize {
+        self.areas.iter().map(|area| {
+            let range = area.get_vpn_range();
+            range.get_end().0 - range.get_start().0
+        }).sum()
+    }
 
-        log::info!("Mapping .rodata section with identity mapping...");
-        memory_set.push(
-            MapArea::new(
-                (srodata as usize).into(),
-                (erodata as usize).into(),
-                MapType::Identical,
-                MapPermission::R | MapPermission::X,
-            ),
-            None,
-        );
+    pub fn limits(&self) -> MemoryLimits {
+        self.limits
+    }
 
-        log::info!("Mapping .data section with identity mapping...");
-        memory_set.push(
-            MapArea::new(
-                (sdata as usize).into(),
-                (edata as usize).into(),
-                MapType::Identical,
-                MapPermission::R,
-            ),
-            None,
-        );
+    /// Apply `limits` to what is mapped from now on, what is mapped already stays.
+    pub fn set_limits(&mut self, limits: MemoryLimits) {
+        self.limits = limits;
+    }
 
-        log::info!("Mapping .syscall_table section with identity mapping...");
-        memory_set.push(
-            MapArea::new(
-                (__syscall_table_start as usize).into(),
-                (__syscall_table_end as usize).into(),
-                MapType::Identical,
-                MapPermission::R | MapPermission::W,
-            ),
-            None,
-        );
+    /// Whether `pages` more pages stay within the address space limit
+    fn fits_limit(&self, pages: usize) -> bool {
+        self.mapped_pages().saturating_add(pages) <= self.limits.address_space
+    }
 
-        log::info!("Mapping .bss section with identity mapping...");
-        memory_set.push(
-            MapArea::new(
-                (sbss_with_stack as usize).into(),
-                (ebss as usize).into(),
-                MapType::Identical,
-                MapPermission::R | MapPermission::W,
-            ),
-            None,
-        );
+    /// Number of frames owned by the areas, identical mappings excluded.
+    pub fn resident_pages(&self) -> usize {
+        self.areas.iter().map(|area| area.frame_count()).sum()
+    }
+
+    /// Boundaries and permissions of every area, sorted by start address.
+    pub fn area_infos(&self) -> Vec<AreaInfo> {
+        let mut infos: Vec<AreaInfo> = self
+            .areas
+            .iter()
+            .map(|area| AreaInfo {
+                start: VirtAddr::from(area.get_vpn_range().get_start()).into(),
+                end: VirtAddr::from(area.get_vpn_range().get_end()).into(),
+                perm: area.get_map_perm().bits() as usize,
+                map_type: match area.get_map_type() {
+                    MapType::Identical | MapType::Linear(_) => 0,
+                    MapType::Framed => 1,
+                    MapType::Lazy => 2,
+                    MapType::Shared => 3,
+                    MapType::File => 4,
+                },
+            })
+            .collect();
+        infos.sort_by_key(|info| info.start);
+        infos
+    }
+
+    /// Print every area of this memory set, one per line.
+    pub fn dump(&self) {
+        println!("token {:#x}, {} areas", self.token(), self.areas.len());
+        for area in self.areas.iter() {
+            let start: VirtAddr = area.get_vpn_range().get_start().into();
+            let end: VirtAddr = area.get_vpn_range().get_end().into();
+            println!(
+                "  [{:#x}, {:#x}) {:?} {:?}",
+                usize::from(start),
+                usize::from(end),
+                area.get_map_type(),
+                area.get_map_perm()
+            );
+        }
+    }
+
+    pub fn new_kernel() -> Self {
+        log::info!("New kernel starting.");
+        let mut memory_set = Self::new_bare();
+        // entered from every user space, see `asid`
+        memory_set.asid = Asid::shared();
+
+        log::info!("Map trampoline.");
+        memory_set.map_trampoline();
+
+        for section in kernel_sections() {
+            log::info!("{:<14} [{:#x}, {:#x}) {:?}", section.name, section.start, section.end, section.perm);
+            memory_set.map_kernel_section(&section, KERNEL_VIRT_OFFSET);
+        }
 
         log::info!("Mapping .physical section with identity mapping...");
-        memory_set.push(
-            MapArea::new(
-                (ekernel as usize).into(),
-                PHYSTOP.into(),
-                MapType::Identical,
-                MapPermission::R | MapPermission::W,
-            ),
-            None,
-        );
+        memory_set.map_linear(ekernel as usize, ekernel as usize, platform().ram_end - ekernel as usize, MapPermission::R | MapPermission::W);
 
         log::info!("mapping memory-mapped registers");
-        for pair in MMIO {
-            memory_set.push(
-                MapArea::new(
-                    (*pair).0.into(),
-                    ((*pair).0 + (*pair).1).into(),
-                    MapType::Identical,
-                    MapPermission::R | MapPermission::W,
-                ),
-                None,
-            );
+        for &(start, size) in platform().mmio {
+            memory_set.map_mmio(start, size);
         }
+
+        let wx = memory_set.wx_pages();
+        assert!(wx.is_empty(), "kernel pages both writable and executable: {:x?}", wx);
         log::info!("New kernel sucessfully.");
         memory_set
     }
+
+    /// Map the physical `[pa, pa + len)` at `va`, every page at the same
+    /// distance from its frame. Both ends are page aligned.
+    pub fn map_linear(&mut self, va: usize, pa: usize, len: usize, perm: MapPermission) {
+        assert!(va % PAGE_SIZE == 0 && pa % PAGE_SIZE == 0, "unaligned linear mapping {:#x} -> {:#x}", va, pa);
+        let map_type = match va.wrapping_sub(pa) {
+            0 => MapType::Identical,
+            offset => MapType::Linear(offset / PAGE_SIZE),
+        };
+        self.push(MapArea::new(va.into(), (va + len).into(), map_type, perm), None);
+    }
+
+    /// Map the section of the kernel image `section`, moved up by
+    /// `virt_offset` bytes, onto where it was loaded.
+    fn map_kernel_section(&mut self, section: &KernelSection, virt_offset: usize) {
+        let start = section.start & !(PAGE_SIZE - 1);
+        self.map_linear(start + virt_offset, start, section.end - start, section.perm);
+    }
+
+    /// Identity map the device registers `[pa, pa + len)`, read-write and
+    /// never executable, as I/O memory if the harts have Svpbmt: strongly
+    /// ordered and not cached, whatever the PMA of the range.
+    pub fn map_mmio(&mut self, pa: usize, len: usize) {
+        let area = MapArea::new(pa.into(), (pa + len).into(), MapType::Identical, MapPermission::R | MapPermission::W);
+        let range = area.get_vpn_range();
+        self.push(area, None);
+        if platform().svpbmt {
+            for vpn in range {
+                self.page_table.set_pbmt(vpn, Pbmt::Io);
+            }
+        }
+    }
+
+    /// Mapped pages both writable and executable, none in a sound space.
+    pub fn wx_pages(&self) -> Vec<VirtPageNum> {
+        self.page_table
+            .leaf_entries()
+            .into_iter()
+            .filter(|(_, pte)| pte.writable() && pte.executable())
+            .map(|(vpn, _)| vpn)
+            .collect()
+    }
     
 }
 
+/// Where the kernel image is mapped, minus where it was loaded. The image
+/// is not relocatable yet: 0, it runs where it was loaded
+const KERNEL_VIRT_OFFSET: usize = 0;
+
+/// A piece of the kernel image, mapped with its own permissions
+struct KernelSection {
+    name: &'static str,
+    /// `[start, end)`, where it was loaded
+    start: usize,
+    end: usize,
+    perm: MapPermission,
+}
+
+/// The sections of the kernel image, in the order of the linker script.
+/// The trampoline lies in `.text`, and the stack of the boot hart in `.bss`.
+fn kernel_sections() -> [KernelSection; 5] {
+    let section = |name, start: usize, end: usize, perm| KernelSection { name, start, end, perm };
+    [
+        section(".text", stext as usize, etext as usize, MapPermission::R | MapPermission::X),
+        // the symbol table is patched in by the build, read-only at run time
+        section(".rodata", srodata as usize, erodata as usize, MapPermission::R),
+        section(".data", sdata as usize, edata as usize, MapPermission::R | MapPermission::W),
+        section(
+            ".syscall_table",
+            __syscall_table_start as usize,
+            __syscall_table_end as usize,
+            MapPermission::R | MapPermission::W,
+        ),
+        section(".bss", sbss_with_stack as usize, ebss as usize, MapPermission::R | MapPermission::W),
+    ]
+}
+
 impl MemorySet {
     
     /// Maps the user-space trampoline page to the kernel's trampoline code.
@@ -268,27 +852,69 @@ impl MemorySet {
         );
     }
 
+    /// Map the `sigreturn` stub at `SIGRETURN_TRAMPOLINE`.
+    ///
+    /// Signal handlers are entered with `ra` pointing here, so a handler
+    /// returning normally lands in `sigreturn`. Unlike the trampoline it
+    /// is user accessible, and a regular area: fork copies it like the rest.
+    fn map_sigreturn_trampoline(&mut self) {
+        const _: () = assert!(SYSCALL_SIGRETURN < 1 << 11, "doesn't fit an addi immediate");
+        let code: [u32; 2] = [
+            // li a7, SYSCALL_SIGRETURN
+            (SYSCALL_SIGRETURN as u32) << 20 | 17 << 7 | 0x13,
+            // ecall
+            0x0000_0073,
+        ];
+        let bytes: alloc::vec::Vec<u8> = code.iter().flat_map(|word| word.to_le_bytes()).collect();
+        self.push(
+            MapArea::new(
+                VirtAddr::from(SIGRETURN_TRAMPOLINE),
+                VirtAddr::from(SIGRETURN_TRAMPOLINE + PAGE_SIZE),
+                MapType::Framed,
+                MapPermission::R | MapPermission::X | MapPermission::U,
+            ),
+            Some(bytes.as_slice()),
+        );
+    }
+
 }
 
 impl MemorySet {
-    pub fn from_elf(elf_data: &[u8]) -> (Self, usize, usize) {
+    /// Map the load segments of a checked `image`, relocated if it is
+    /// position independent.
+    ///
+    /// With `randomize`, a position independent image is moved up to
+    /// `ASLR_PIE_PAGES` pages above its base, the user stack slots up to
+    /// `ASLR_STACK_PAGES` pages above the image, and the mmap base up to
+    /// `ASLR_MMAP_PAGES` pages above `USER_MMAP_BASE`.
+    ///
+    /// Returns the address space and the base of the user stack slots.
+    pub fn from_elf(image: &mut ElfImage, randomize: bool) -> (Self, usize) {
         let mut memory_set = Self::new_bare();
 
         memory_set.map_trampoline();
+        memory_set.map_sigreturn_trampoline();
+
+        let (pie_offset, stack_offset, mmap_offset) = if randomize {
+            (
+                if image.is_pie() { random_page_offset(ASLR_PIE_PAGES) } else { 0 },
+                random_page_offset(ASLR_STACK_PAGES),
+                random_page_offset(ASLR_MMAP_PAGES),
+            )
+        } else {
+            (0, 0, 0)
+        };
+        if pie_offset != 0 {
+            image.rebase(image.bias + pie_offset);
+        }
+        memory_set.mmap_base = USER_MMAP_BASE + mmap_offset;
 
-        let elf = xmas_elf::ElfFile::new(elf_data).unwrap();
-        let elf_header = elf.header;
-        let magic = elf_header.pt1.magic;
-        /// for the string <kbd>DEL</kbd>`ELF`.
-        static MAGIC: [u8; 4] = [0x7f, 0x45, 0x4c, 0x46];
-        assert_eq!(magic, MAGIC, "invalid elf!");
-        let ph_count = elf_header.pt2.ph_count();
+        let elf = &image.elf;
         let mut max_end_vpn = VirtPageNum(0);
-        for i in 0..ph_count {
-            let ph = elf.program_header(i).unwrap();
-            if ph.get_type().unwrap() == xmas_elf::program::Type::Load {
-                let start_va: VirtAddr = (ph.virtual_addr() as usize).into();
-                let end_va: VirtAddr = ((ph.virtual_addr() + ph.mem_size()) as usize).into();
+        for ph in elf.program_iter() {
+            if ph.get_type() == Ok(xmas_elf::program::Type::Load) {
+                let start_va: VirtAddr = (ph.virtual_addr() as usize + image.bias).into();
+                let end_va: VirtAddr = ((ph.virtual_addr() + ph.mem_size()) as usize + image.bias).into();
                 let mut map_perm = MapPermission::U;
                 let ph_flags = ph.flags();
                 if ph_flags.is_read() {
@@ -300,39 +926,90 @@ impl MemorySet {
                 if ph_flags.is_execute() {
                     map_perm |= MapPermission::X;
                 };
-                let map_area = MapArea::new(start_va, end_va, MapType::Framed, map_perm);
-
-                max_end_vpn = map_area.get_vpn_end();
-                memory_set.push(
-                    map_area,
-                    Some(&elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize]),
-                );
+                let data = &elf.input[ph.offset() as usize..(ph.offset() + ph.file_size()) as usize];
+                let end_vpn = memory_set.push_image_segment(start_va, end_va, map_perm, data);
+                max_end_vpn = max_end_vpn.max(end_vpn);
             }
         }
+        image
+            .relocate(&mut memory_set)
+            .expect("relocations are checked to land in the image");
+
         let max_end_va: VirtAddr = max_end_vpn.into();
         // Div by guard page
-        let user_stack_base: usize = usize::from(max_end_va) + PAGE_SIZE;
-        
-        (
-            memory_set,
-            user_stack_base,
-            elf.header.pt2.entry_point() as usize,
-        )
+        let user_stack_base: usize = usize::from(max_end_va) + PAGE_SIZE + stack_offset;
+        if randomize {
+            // enough to lay the same space out again
+            log::debug!(
+                "aslr: load bias {:#x}, stack base {:#x}, mmap base {:#x}",
+                image.bias, user_stack_base, memory_set.mmap_base,
+            );
+        }
 
+        (memory_set, user_stack_base)
     }
 
 
+    /// Map a flat binary at `FLAT_BASE`, readable, writable and executable
+    /// as a whole, with `binfmt::FLAT_BSS_SIZE` zeroed bytes past it.
+    ///
+    /// With `randomize`, the user stack slots and the mmap base move as
+    /// for [`Self::from_elf`], the image itself can't.
+    ///
+    /// Returns the address space and the base of the user stack slots.
+    pub fn from_flat(image: &FlatImage, randomize: bool) -> (Self, usize) {
+        let mut memory_set = Self::new_bare();
+
+        memory_set.map_trampoline();
+        memory_set.map_sigreturn_trampoline();
+
+        let (stack_offset, mmap_offset) = if randomize {
+            (random_page_offset(ASLR_STACK_PAGES), random_page_offset(ASLR_MMAP_PAGES))
+        } else {
+            (0, 0)
+        };
+        memory_set.mmap_base = USER_MMAP_BASE + mmap_offset;
+
+        let start_va: VirtAddr = FLAT_BASE.into();
+        let end_va: VirtAddr = (FLAT_BASE + image.data.len() + FLAT_BSS_SIZE).into();
+        let map_perm = MapPermission::U | MapPermission::R | MapPermission::W | MapPermission::X;
+        let max_end_va: VirtAddr = memory_set.push_image_segment(start_va, end_va, map_perm, image.data).into();
+
+        // Div by guard page
+        (memory_set, usize::from(max_end_va) + PAGE_SIZE + stack_offset)
+    }
+
     pub fn from_other_user(user_space: &MemorySet) -> MemorySet {
         let mut memory_set = Self::new_bare();
+        memory_set.limits = user_space.limits;
+        memory_set.mmap_base = user_space.mmap_base;
         // map trampoline
         memory_set.map_trampoline();
         // copy data sections/trap_context/user_stack
         for area in user_space.areas.iter() {
             let new_area = MapArea::from_other(area);
             memory_set.push(new_area, None);
+            // the same frames, nothing to copy
+            if area.get_map_type() == MapType::Shared {
+                continue;
+            }
             // copy data from another space
             for vpn in area.get_vpn_range() {
-                let src_ppn = user_space.translate(vpn).unwrap().ppn();
+                let src_ppn = match user_space.translate(vpn).filter(|pte| pte.is_valid()) {
+                    Some(pte) => pte.ppn(),
+                    // a lazy page never touched stays so in the copy, a
+                    // file page is read again from the file
+                    None if matches!(area.get_map_type(), MapType::Lazy | MapType::File) => continue,
+                    None => panic!("{:?} of a framed area is not mapped", vpn),
+                };
+                if matches!(area.get_map_type(), MapType::Lazy | MapType::File) {
+                    memory_set
+                        .areas
+                        .last_mut()
+                        .unwrap()
+                        .populate(&mut memory_set.page_table, vpn)
+                        .expect("out of frames while copying a lazy area");
+                }
                 let dst_ppn = memory_set.translate(vpn).unwrap().ppn();
                 dst_ppn
                     .get_bytes_array_slice()
@@ -343,38 +1020,315 @@ impl MemorySet {
     }
 }
 
+#[monitor_command(name = "memmap", help = "Dump memory areas: memmap [kernel|user]")]
+fn memmap_command(args: &[&str]) {
+    match args.get(1).copied().unwrap_or("kernel") {
+        "kernel" => KERNEL_SPACE.lock().dump(),
+        "user" => match current_task() {
+            Some(task) => {
+                let memory_set = task.lock().with_user_res(|user_res| user_res.memory_set.clone());
+                memory_set.lock().dump();
+            }
+            None => println!("no current task"),
+        },
+        other => println!("memmap: unknown space `{}`", other),
+    }
+}
+
 pub fn remap_test() {
     log::info!("Remap test starting");
     let kernel_space = KERNEL_SPACE.lock();
-    let mid_text: VirtAddr = ((stext as usize + etext as usize) / 2).into();
-    let mid_rodata: VirtAddr = ((sdata as usize + edata as usize) / 2).into();
-    let mid_data: VirtAddr = ((sdata as usize + edata as usize) / 2).into();
-    assert_eq!(
-        kernel_space
-            .page_table
-            .find_pte_by_vpn(mid_text.down_to_vpn())
-            .unwrap()
-            .writable(),
-        false
-    );
+    let pte = |addr: usize| kernel_space.page_table.find_pte_by_vpn(VirtAddr::from(addr).down_to_vpn()).unwrap();
+    let mid_text = pte((stext as usize + etext as usize) / 2);
+    assert!(mid_text.executable() && !mid_text.writable());
+    let mid_rodata = pte((srodata as usize + erodata as usize) / 2);
+    assert!(!mid_rodata.writable() && !mid_rodata.executable());
+    let mid_bss = pte((sbss_with_stack as usize + ebss as usize) / 2);
+    assert!(mid_bss.writable() && !mid_bss.executable());
+    assert!(kernel_space.wx_pages().is_empty());
+
+    log::info!("Remap test passed!");
+}
+
+#[kernel_test]
+fn linear_mapping_test() {
+    let mut memory_set = MemorySet::new_bare();
+    let va = USER_MMAP_BASE / 2;
+    let pa = 0x8000_0000;
+    memory_set.map_linear(va, pa, 2 * PAGE_SIZE, MapPermission::R | MapPermission::W | MapPermission::U);
+    let pte = memory_set.translate(VirtAddr::from(va + PAGE_SIZE).down_to_vpn()).unwrap();
+    assert_eq!(usize::from(PhysAddr::from(pte.ppn())), pa + PAGE_SIZE);
+    assert_eq!(memory_set.area_infos()[0].map_type, 0);
+
+    // the same distance after a split
+    let vpn = |page: usize| VirtPageNum(VirtAddr::from(va).down_to_vpn().0 + page);
+    memory_set.protect_range(vpn(1), vpn(2), MapPermission::R).unwrap();
+    let pte = memory_set.translate(vpn(1)).unwrap();
+    assert_eq!(usize::from(PhysAddr::from(pte.ppn())), pa + PAGE_SIZE);
+    assert!(!pte.writable());
+    memory_set.unmap_range(vpn(0), vpn(2)).unwrap();
+    assert!(memory_set.stray_ptes().is_empty());
+
+    memory_set.map_linear(va, va, PAGE_SIZE, MapPermission::R | MapPermission::X);
+    assert!(memory_set.wx_pages().is_empty());
+    memory_set.map_linear(va + PAGE_SIZE, pa, PAGE_SIZE, MapPermission::R | MapPermission::W | MapPermission::X);
+    assert_eq!(memory_set.wx_pages(), [vpn(1)]);
+}
+
+#[kernel_test]
+fn guard_gap_test() {
+    let mut memory_set = MemorySet::new_bare();
+    let rw = MapPermission::R | MapPermission::W;
+
+    let first = memory_set.map_anonymous(0, PAGE_SIZE, rw, MapFlags::empty()).unwrap();
+    let second = memory_set.map_anonymous(0, PAGE_SIZE, rw, MapFlags::empty()).unwrap();
+    assert_eq!(usize::from(second), usize::from(first) + PAGE_SIZE + USER_GUARD_GAP);
+    assert!(memory_set.in_guard_gap(VirtAddr::from(usize::from(first) + PAGE_SIZE).down_to_vpn()));
 
+    // a hint right next to an area is not honoured without FIXED
+    let hinted = memory_set
+        .map_anonymous(usize::from(second) + PAGE_SIZE, PAGE_SIZE, rw, MapFlags::empty())
+        .unwrap();
+    assert_ne!(usize::from(hinted), usize::from(second) + PAGE_SIZE);
+
+    // FIXED may be adjacent, never overlapping
+    let fixed = usize::from(first) + PAGE_SIZE;
+    assert_eq!(usize::from(memory_set.map_anonymous(fixed, PAGE_SIZE, rw, MapFlags::FIXED).unwrap()), fixed);
     assert_eq!(
-        kernel_space
-            .page_table
-            .find_pte_by_vpn(mid_rodata.down_to_vpn())
-            .unwrap()
-            .writable(),
-        false
+        memory_set.map_anonymous(fixed, PAGE_SIZE, rw, MapFlags::FIXED),
+        Err(MemoryError::AddressInUse)
     );
+}
+
+#[kernel_test]
+fn unmap_range_test() {
+    let mut memory_set = MemorySet::new_bare();
+    let rw = MapPermission::R | MapPermission::W;
+
+    let start = memory_set.map_anonymous(0, 4 * PAGE_SIZE, rw, MapFlags::empty()).unwrap();
+    let vpn = |page: usize| VirtPageNum(start.down_to_vpn().0 + page);
+
+    // a hole in the middle leaves two areas
+    memory_set.unmap_range(vpn(1), vpn(3)).unwrap();
+    assert_eq!(memory_set.area_infos().len(), 2);
+    assert_eq!(memory_set.page_residency(vpn(0)), PageResidency::Resident);
+    assert_eq!(memory_set.page_residency(vpn(1)), PageResidency::Unmapped);
+    assert_eq!(memory_set.page_residency(vpn(3)), PageResidency::Resident);
+    assert_eq!(memory_set.resident_pages(), 2);
+    assert!(memory_set.stray_ptes().is_empty());
+
+    // unmapped pages in the range are fine
+    memory_set.unmap_range(vpn(0), vpn(4)).unwrap();
+    assert!(memory_set.area_infos().is_empty());
+}
+
+#[kernel_test]
+fn protect_range_test() {
+    let mut memory_set = MemorySet::new_bare();
+    let rw = MapPermission::R | MapPermission::W;
+
+    let start = memory_set.map_anonymous(0, 4 * PAGE_SIZE, rw, MapFlags::empty()).unwrap();
+    let vpn = |page: usize| VirtPageNum(start.down_to_vpn().0 + page);
 
+    // the middle turns read-only, the ends stay writable
+    memory_set.protect_range(vpn(1), vpn(3), MapPermission::R).unwrap();
+    let perms: Vec<usize> = memory_set.area_infos().iter().map(|info| info.perm).collect();
+    let bits = |perm: MapPermission| (perm | MapPermission::U).bits() as usize;
+    assert_eq!(perms, [bits(rw), bits(MapPermission::R), bits(rw)]);
+    assert!(!memory_set.translate(vpn(1)).unwrap().writable());
+    assert!(memory_set.translate(vpn(3)).unwrap().writable());
+    // the frames stay where they were
+    assert_eq!(memory_set.resident_pages(), 4);
+
+    // a hole in the range fails it as a whole
+    memory_set.unmap_range(vpn(3), vpn(4)).unwrap();
+    assert_eq!(memory_set.protect_range(vpn(0), vpn(5), MapPermission::R), Err(MemoryError::PageNotMapped));
+    assert!(memory_set.translate(vpn(0)).unwrap().writable());
+
+    memory_set.unmap_range(vpn(0), vpn(3)).unwrap();
+    assert!(memory_set.stray_ptes().is_empty());
+}
+
+#[kernel_test]
+fn lazy_heap_test() {
+    let mut memory_set = MemorySet::new_bare();
+    let bottom = VirtAddr::from(USER_MMAP_BASE / 2).down_to_vpn();
+    let vpn = |page: usize| VirtPageNum(bottom.0 + page);
+
+    // growing reserves the pages without backing them
+    memory_set.resize_heap(bottom, bottom, vpn(1024)).unwrap();
+    assert_eq!(memory_set.resident_pages(), 0);
+    assert_eq!(memory_set.page_residency(vpn(10)), PageResidency::NotResident);
+
+    assert_eq!(memory_set.handle_lazy_fault(vpn(10)), Ok(true));
+    assert_eq!(memory_set.handle_lazy_fault(vpn(10)), Ok(false));
+    assert_eq!(memory_set.page_residency(vpn(10)), PageResidency::Resident);
+
+    // shrinking below a resident page frees it
+    memory_set.resize_heap(bottom, vpn(1024), vpn(4)).unwrap();
+    assert_eq!(memory_set.resident_pages(), 0);
+    assert_eq!(memory_set.page_residency(vpn(10)), PageResidency::Unmapped);
+    assert!(memory_set.stray_ptes().is_empty());
+}
+
+#[kernel_test]
+fn populate_str_test() {
+    let mut memory_set = MemorySet::new_bare();
+    let bottom = VirtAddr::from(USER_MMAP_BASE / 2).down_to_vpn();
+    let vpn = |page: usize| VirtPageNum(bottom.0 + page);
+    memory_set.resize_heap(bottom, bottom, vpn(4)).unwrap();
+
+    // a string running past the end of the first page backs the second
+    assert_eq!(memory_set.handle_lazy_fault(vpn(0)), Ok(true));
+    memory_set.translate(vpn(0)).unwrap().ppn().get_bytes_array_slice()[PAGE_SIZE - 4..].fill(b'a');
+    memory_set.populate_str(usize::from(VirtAddr::from(vpn(1))) - 4).unwrap();
+    assert_eq!(memory_set.page_residency(vpn(1)), PageResidency::Resident);
+    // and ends there, on its zeroes
+    assert_eq!(memory_set.page_residency(vpn(2)), PageResidency::NotResident);
+}
+
+#[kernel_test]
+fn stack_growth_test() {
+    let mut memory_set = MemorySet::new_bare();
+    let rw = MapPermission::R | MapPermission::W;
+    let top = VirtAddr::from(USER_MMAP_BASE / 2).down_to_vpn();
+    let vpn = |page: usize| VirtPageNum(top.0 - page);
+
+    memory_set.insert_stack_area(vpn(1).into(), top.into(), vpn(4).into(), rw);
+    // a fault a few pages below maps everything in between
+    assert_eq!(memory_set.handle_lazy_fault(vpn(3)), Ok(true));
+    assert_eq!(memory_set.resident_pages(), 3);
+    assert_eq!(memory_set.handle_lazy_fault(vpn(4)), Ok(true));
+    // the limit is reached, below is the guard page
+    assert_eq!(memory_set.handle_lazy_fault(vpn(5)), Ok(false));
+    assert!(memory_set.in_stack_guard(vpn(5)));
+
+    memory_set.remove_area_containing(vpn(1));
+    assert!(memory_set.area_infos().is_empty());
+    assert!(memory_set.stray_ptes().is_empty());
+}
+
+#[kernel_test]
+fn memory_limits_test() {
+    let mut memory_set = MemorySet::new_bare();
+    let rw = MapPermission::R | MapPermission::W;
+    let top = VirtAddr::from(USER_MMAP_BASE / 2).down_to_vpn();
+    let vpn = |page: usize| VirtPageNum(top.0 - page);
+    memory_set.insert_stack_area(vpn(1).into(), top.into(), vpn(8).into(), rw);
+    memory_set.set_limits(MemoryLimits { address_space: 6, stack: 3 });
+
+    // the stack stops at its own limit, before its growth limit
+    assert_eq!(memory_set.handle_lazy_fault(vpn(3)), Ok(true));
+    assert_eq!(memory_set.handle_lazy_fault(vpn(4)), Ok(false));
+    // 3 pages are left to the rest of the space
+    assert_eq!(memory_set.map_anonymous(0, 4 * PAGE_SIZE, rw, MapFlags::empty()), Err(MemoryError::OutOfMemory));
+    let start = memory_set.map_anonymous(0, 3 * PAGE_SIZE, rw, MapFlags::empty()).unwrap();
+    assert_eq!(memory_set.mapped_pages(), 6);
+    assert_eq!(memory_set.map_anonymous(0, PAGE_SIZE, rw, MapFlags::empty()), Err(MemoryError::OutOfMemory));
+    // a fork keeps the limits
+    assert_eq!(MemorySet::from_other_user(&memory_set).limits(), memory_set.limits());
+
+    memory_set.unmap_range(start.down_to_vpn(), VirtPageNum(start.down_to_vpn().0 + 3)).unwrap();
+    memory_set.remove_area_containing(vpn(1));
+    assert!(memory_set.stray_ptes().is_empty());
+}
+
+#[kernel_test]
+fn file_mapping_test() {
+    /// Two and a half pages, each byte holding the number of its page, from 1
+    struct Pages;
+    impl Inode for Pages {
+        fn is_dir(&self) -> bool {
+            false
+        }
+        fn size(&self) -> usize {
+            5 * PAGE_SIZE / 2
+        }
+        fn read_at(&self, offset: usize, buf: &mut [u8]) -> usize {
+            let len = buf.len().min(self.size().saturating_sub(offset));
+            for (i, byte) in buf[..len].iter_mut().enumerate() {
+                *byte = ((offset + i) / PAGE_SIZE + 1) as u8;
+            }
+            len
+        }
+    }
+
+    let mut memory_set = MemorySet::new_bare();
+    let rw = MapPermission::R | MapPermission::W;
+    let inode: Arc<dyn Inode> = Arc::new(Pages);
     assert_eq!(
-        kernel_space
-            .page_table
-            .find_pte_by_vpn(mid_data.down_to_vpn())
-            .unwrap()
-            .executable(),
-        false
+        memory_set.map_file(0, 3 * PAGE_SIZE, rw, MapFlags::empty(), inode.clone(), 1),
+        Err(MemoryError::Misaligned { address: 1, alignment: PAGE_SIZE })
     );
+    let start = memory_set.map_file(0, 3 * PAGE_SIZE, rw, MapFlags::empty(), inode, PAGE_SIZE).unwrap();
+    let vpn = |page: usize| VirtPageNum(start.down_to_vpn().0 + page);
+    let bytes = |memory_set: &MemorySet, page: usize| memory_set.translate(vpn(page)).unwrap().ppn().get_bytes_array_slice();
+    assert_eq!(memory_set.resident_pages(), 0);
 
-    log::info!("Remap test passed!");
+    assert_eq!(memory_set.handle_lazy_fault(vpn(0)), Ok(true));
+    assert!(bytes(&memory_set, 0).iter().all(|&byte| byte == 2));
+    // the part left after a split still reads its own pages
+    memory_set.unmap_range(vpn(0), vpn(1)).unwrap();
+    assert_eq!(memory_set.handle_lazy_fault(vpn(1)), Ok(true));
+    assert!(bytes(&memory_set, 1)[..PAGE_SIZE / 2].iter().all(|&byte| byte == 3));
+    assert!(bytes(&memory_set, 1)[PAGE_SIZE / 2..].iter().all(|&byte| byte == 0));
+
+    // a private write goes to the frame, a fork copies it
+    bytes(&memory_set, 1)[0] = 42;
+    let copy = MemorySet::from_other_user(&memory_set);
+    assert_eq!(bytes(&copy, 1)[0], 42);
+    assert_eq!(copy.page_residency(vpn(2)), PageResidency::NotResident);
+
+    // past the end of the file
+    assert_eq!(memory_set.handle_lazy_fault(vpn(2)), Ok(true));
+    assert!(bytes(&memory_set, 2).iter().all(|&byte| byte == 0));
+    memory_set.unmap_range(vpn(1), vpn(3)).unwrap();
+    assert!(memory_set.stray_ptes().is_empty());
+}
+
+#[kernel_test]
+fn randomized_layout_test() {
+    let elf = crate::fs::vfs::lookup("init_proc").unwrap().read_all();
+    let mut image = ElfImage::parse(elf.as_slice()).unwrap();
+    let (plain, plain_stack_base) = MemorySet::from_elf(&mut image, false);
+    assert_eq!(plain.mmap_base, USER_MMAP_BASE);
+
+    let mut image = ElfImage::parse(elf.as_slice()).unwrap();
+    let plain_bias = image.bias;
+    let (randomized, stack_base) = MemorySet::from_elf(&mut image, true);
+    let within = |moved: usize, base: usize, pages: usize| {
+        moved >= base && moved - base < pages * PAGE_SIZE && (moved - base) % PAGE_SIZE == 0
+    };
+    assert!(within(stack_base, plain_stack_base, ASLR_STACK_PAGES));
+    assert!(within(randomized.mmap_base, USER_MMAP_BASE, ASLR_MMAP_PAGES));
+    if image.is_pie() {
+        assert!(within(image.bias, plain_bias, ASLR_PIE_PAGES));
+    } else {
+        assert_eq!(image.bias, plain_bias);
+    }
+    // the entry point is still mapped
+    assert!(randomized.translate(VirtAddr::from(image.entry).down_to_vpn()).is_some_and(|pte| pte.is_valid()));
+}
+
+#[kernel_test]
+fn lazy_bss_test() {
+    let mut memory_set = MemorySet::new_bare();
+    let start = VirtAddr::from(USER_MMAP_BASE / 2).down_to_vpn();
+    let vpn = |page: usize| VirtPageNum(start.0 + page);
+    let rw = MapPermission::R | MapPermission::W | MapPermission::U;
+
+    // a page and a bit of data, then zeroes up to the eighth page
+    let data = alloc::vec![0xa5u8; PAGE_SIZE + 16];
+    assert_eq!(memory_set.push_image_segment(start.into(), vpn(8).into(), rw, &data), vpn(8));
+    assert_eq!(memory_set.resident_pages(), 2);
+    let page = memory_set.translate(vpn(1)).unwrap().ppn().get_bytes_array_slice();
+    assert!(page[..16].iter().all(|&byte| byte == 0xa5));
+    assert!(page[16..].iter().all(|&byte| byte == 0));
+
+    // the rest takes no frame until it is touched
+    assert_eq!(memory_set.page_residency(vpn(7)), PageResidency::NotResident);
+    assert_eq!(memory_set.handle_lazy_fault(vpn(7)), Ok(true));
+    let page = memory_set.translate(vpn(7)).unwrap().ppn().get_bytes_array_slice();
+    assert!(page.iter().all(|&byte| byte == 0));
+    assert_eq!(memory_set.resident_pages(), 3);
 }
@@ -1,26 +1,52 @@
 pub mod memory_set;
 pub mod heap_allocator;
+pub mod slab;
 pub mod address;
 pub mod page_table;
 pub mod frame_allocator;
 pub mod map_area;
 pub mod user_ptr;
+pub mod gfp;
+pub mod diff;
+pub mod memmap;
+pub mod elf;
+pub mod binfmt;
+pub mod shm;
+pub mod tlb;
+pub mod asid;
+pub mod oom;
+pub mod uaccess;
+pub mod buffer;
 mod error;
+mod syscall;
 // pub mod user;
-// mod buffer;
 
 
+use crate::{boards::{fdt, platform}, cmdline};
+
+pub use error::MemoryError;
 pub use memory_set::KERNEL_SPACE;
 
-pub use user_ptr::UserBuffer;
+pub use buffer::{Buffer, UserBuffer};
 
 
 
-pub fn init() {
+/// `dtb_pa` is the device tree blob handed over by the firmware, 0 if none.
+pub fn init(dtb_pa: usize) {
     log::info!("Memory manager initializing.");
     heap_allocator::init_heap();
+    // read by physical address, paging is still off
+    let tree = fdt::parse(dtb_pa);
+    if tree.is_none() {
+        log::warn!("no device tree at {:#x}, using the board defaults", dtb_pa);
+    }
+    platform::init(tree.as_ref());
+    cmdline::init(tree.as_ref().and_then(|tree| tree.bootargs.as_deref()));
+    // must be complete before the frame allocator takes the free ranges
+    memmap::init(tree.as_ref());
     frame_allocator::init_frame_allocator();
     KERNEL_SPACE.lock().activate();
+    asid::init();
     log::info!("Memory manager initialized successfully.");
 }
 
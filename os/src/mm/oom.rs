@@ -0,0 +1,80 @@
+//! Out of memory killer
+//!
+//! A user page which can't get a frame doesn't bring the kernel down, nor
+//! only the task which faulted: the task group with the most resident
+//! pages is sent `SIGKILL`, its frames come back once it exits, and the
+//! faulting instruction is retried meanwhile. `init_task` is never picked,
+//! kernel tasks have no user pages to give.
+//!
+//! While a victim dies no other one is picked, the faults just wait for
+//! its frames. A victim blocked in the kernel only dies once it wakes up.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use alloc::sync::Arc;
+
+use crate::task::{all_tasks, init_task, signal::Signal, TaskControlBlock, TaskState};
+
+/// Task groups killed
+static OOM_KILLS: AtomicUsize = AtomicUsize::new(0);
+
+/// What [`out_of_memory`] did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OomOutcome {
+    /// The group of this pid was sent `SIGKILL`
+    Killed(usize),
+    /// A victim is still dying, its frames are on their way back
+    Waiting,
+    /// Nothing can be killed
+    NoVictim,
+}
+
+/// Task groups killed since boot
+pub fn oom_kills() -> usize {
+    OOM_KILLS.load(Ordering::Relaxed)
+}
+
+/// Make room after a frame request failed, see the module doc.
+///
+/// Must be called with no task lock or memory set lock held: the resident
+/// pages of every group are counted.
+pub fn out_of_memory() -> OomOutcome {
+    let init = init_task();
+    let mut victim: Option<(Arc<TaskControlBlock>, usize)> = None;
+    for task in all_tasks() {
+        if !task.is_leader() || init.as_ref().is_some_and(|init| Arc::ptr_eq(init, &task)) {
+            continue;
+        }
+        let memory_set = {
+            let inner = task.lock();
+            if matches!(inner.get_state(), TaskState::Zombie(_) | TaskState::Dead) {
+                continue;
+            }
+            let Some(user_res) = inner.user_res.as_ref() else {
+                continue;
+            };
+            if inner.signals.is_pending(Signal::SIGKILL) {
+                return OomOutcome::Waiting;
+            }
+            user_res.memory_set.clone()
+        };
+        let pages = memory_set.lock().resident_pages();
+        if victim.as_ref().map_or(true, |(_, most)| pages > *most) {
+            victim = Some((task, pages));
+        }
+    }
+
+    let Some((victim, pages)) = victim else {
+        return OomOutcome::NoVictim;
+    };
+    let pid = usize::from(victim.get_tid());
+    log::error!(
+        "[oom] out of memory, killing task {} ({}) and its {} resident pages",
+        pid,
+        victim.get_name(),
+        pages
+    );
+    victim.lock().signal(Signal::SIGKILL);
+    OOM_KILLS.fetch_add(1, Ordering::Relaxed);
+    OomOutcome::Killed(pid)
+}
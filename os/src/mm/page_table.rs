@@ -5,20 +5,20 @@
 //! The page table also supports manual creation of page tables based on a provided SATP (Supervisor Address Translation and Protection) token.
 //! A custom frame allocator (`frame_alloc`) is used to allocate new frames for page table entries as needed.
 
-use core::ptr;
-
 use alloc::{string::String, vec};
 use alloc::vec::Vec;
 
 use bitflags::*;
 use os_macros::kernel_test;
+use riscv::register::satp;
 
 // Constants related to SATP (used to mask the PPN in the SATP register)
 use crate::{config::{PAGE_SIZE, PPN_MASK, SATP_PPN_MASK}, println};
 
 // Related modules for address and frame allocation
 use super::{
-    address::{PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum}, error::MemoryError, frame_allocator::{frame_alloc, FrameTracker}
+    address::{PhysAddr, PhysPageNum, StepByOne, VirtAddr, VirtPageNum}, error::MemoryError, frame_allocator::{frame_alloc, FrameTracker},
+    uaccess::UserAccess,
 };
 
 // Define the PTEFlags bitflags for page table entry attributes
@@ -38,6 +38,18 @@ bitflags! {
     }
 }
 
+/// Memory type of a leaf entry with Svpbmt, in bits 61 and 62: main
+/// memory (the PMA of the range), non-cacheable, or I/O
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pbmt {
+    Pma = 0,
+    Nc = 1,
+    Io = 2,
+}
+
+const PBMT_SHIFT: usize = 61;
+const PBMT_MASK: usize = 0b11 << PBMT_SHIFT;
+
 // PageTableEntry structure representing a page table entry
 #[derive(Copy, Clone)]
 #[repr(C)]
@@ -160,6 +172,11 @@ impl PageTableEntry {
     pub fn is_dirty(&self) -> bool {
         self.flags().contains(PTEFlags::D)
     }
+
+    /// Whether this is the entry of a canary page, see [`PageTable::map_canary`]
+    pub fn is_canary(&self) -> bool {
+        self.bits == PTEFlags::RSW0.bits as usize
+    }
 }
 
 // Test function to print the flags of a PTE
@@ -309,9 +326,71 @@ impl PageTable {
         // *pte = PageTableEntry::empty();
     }
 
+    /// Replace the `R`/`W`/`X`/`U` bits of the mapped `vpn` with those of
+    /// `flags`, the other bits (e.g. `A` and `D`) are kept.
+    ///
+    /// The old translation may still be cached, flushing it is up to the caller.
+    pub fn protect(&mut self, vpn: VirtPageNum, flags: PTEFlags) {
+        let pte = self.find_pte(vpn).unwrap();
+        assert!(pte.is_valid(), "vpn {:?} is invalid before protecting", vpn);
+        let access = PTEFlags::R | PTEFlags::W | PTEFlags::X | PTEFlags::U;
+        let pbmt = pte.bits & PBMT_MASK;
+        pte.update(pte.ppn(), (pte.flags() - access) | (flags & access));
+        pte.bits |= pbmt;
+    }
+
+    /// Give the mapped `vpn` the memory type `pbmt`. Only for a hart with
+    /// Svpbmt: elsewhere the bits are reserved, and the page faults.
+    pub fn set_pbmt(&mut self, vpn: VirtPageNum, pbmt: Pbmt) {
+        let pte = self.find_pte(vpn).unwrap();
+        assert!(pte.is_valid(), "vpn {:?} is invalid before setting its memory type", vpn);
+        pte.bits = (pte.bits & !PBMT_MASK) | (pbmt as usize) << PBMT_SHIFT;
+    }
+
+    /// Make `vpn` a canary page: its entry stays invalid, so any access
+    /// faults, but tells the fault apart from one on a page never mapped.
+    pub fn map_canary(&mut self, vpn: VirtPageNum) {
+        let pte = self.find_pte_or_create(vpn).unwrap();
+        assert!(!pte.is_valid(), "VPN 0x{:x} is mapped before becoming a canary", vpn.0);
+        pte.bits = PTEFlags::RSW0.bits as usize;
+    }
+
+    /// Undo [`Self::map_canary`].
+    pub fn unmap_canary(&mut self, vpn: VirtPageNum) {
+        if let Some(pte) = self.find_pte(vpn).filter(|pte| pte.is_canary()) {
+            pte.clear();
+        }
+    }
+
+    pub fn is_canary(&self, vpn: VirtPageNum) -> bool {
+        self.find_pte(vpn).is_some_and(|pte| pte.is_canary())
+    }
+
     pub fn token(&self) -> usize {
         8usize << 60 | self.root_ppn.0
     }
+
+    /// Every valid leaf entry with its VPN, by ascending VPN.
+    pub fn leaf_entries(&self) -> Vec<(VirtPageNum, PageTableEntry)> {
+        fn walk(ppn: PhysPageNum, level: usize, vpn_prefix: usize, out: &mut Vec<(VirtPageNum, PageTableEntry)>) {
+            for (idx, pte) in ppn.get_ptes_slice().iter().enumerate() {
+                if !pte.is_valid() {
+                    continue;
+                }
+                let vpn = (vpn_prefix << 9) | idx;
+                let is_leaf = pte.readable() || pte.writable() || pte.executable();
+                if is_leaf || level == 2 {
+                    out.push((VirtPageNum(vpn << (9 * (2 - level))), *pte));
+                } else {
+                    walk(pte.ppn(), level + 1, vpn, out);
+                }
+            }
+        }
+
+        let mut entries = Vec::new();
+        walk(self.root_ppn, 0, 0, &mut entries);
+        entries
+    }
 }
 
 // Internal helper functions for managing page table entries (PTEs)
@@ -458,20 +537,20 @@ impl PageTable {
     }
 }
 
-/// translate a pointer to a mutable u8 Vec through page table
-/// no consider to multiple threads
-#[allow(unused)]
-pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Option<Vec<&'static mut [u8]>> {
+/// The pages of `[ptr, ptr + len)` in the space of `token`, as slices of
+/// their frames, each mapped with `U` and `access`: `PageNotMapped` or
+/// `PermissionDenied` otherwise. See `mm::buffer`.
+pub fn translated_pages(token: usize, ptr: *const u8, len: usize, access: PTEFlags) -> Result<Vec<&'static mut [u8]>, MemoryError> {
     let page_table = PageTable::from_token(token);
     let mut start = ptr as usize;
     // [start, start+len)
-    let end = start + len;
+    let end = start.checked_add(len).ok_or(MemoryError::PageNotMapped)?;
     let mut v = Vec::new();
     //VPN range: [N*PAGESIZE, (N+1)*PAGESIZE)
     while start < end {
         let start_va = VirtAddr::from(start);
         let mut vpn = start_va.down_to_vpn();
-        let ppn = page_table.find_pte_by_vpn(vpn).unwrap().ppn();
+        let ppn = user_pte(&page_table, vpn, access)?.ppn();
         vpn.step();
         let mut end_va: VirtAddr = vpn.into();
         end_va = end_va.min(VirtAddr::from(end));
@@ -482,68 +561,169 @@ pub fn translated_byte_buffer(token: usize, ptr: *const u8, len: usize) -> Optio
         }
         start = end_va.into();
     }
-    Some(v)
+    Ok(v)
 }
 
-pub fn translated_str(token: usize, ptr: *const u8) -> String {
+/// Read the NUL-terminated string at `ptr`, every page it spans must be mapped `U | R`.
+pub fn translated_str(token: usize, ptr: *const u8) -> Result<String, MemoryError> {
     let page_table = PageTable::from_token(token);
     let mut string = String::new();
     let mut va = ptr as usize;
     loop {
-        let ch: u8 = *(page_table
-            .translate_va(VirtAddr::from(va))
-            .unwrap()
-            .get_mut());
-        if ch == 0 {
-            break;
+        let pte = user_pte(&page_table, VirtAddr::from(va).down_to_vpn(), PTEFlags::R)?;
+        // the rest of the page, scanned without another walk
+        let page = &pte.ppn().get_bytes_array_slice()[VirtAddr::from(va).page_offset()..];
+        match page.iter().position(|&ch| ch == 0) {
+            Some(len) => {
+                string.extend(page[..len].iter().map(|&ch| ch as char));
+                return Ok(string);
+            }
+            None => {
+                string.extend(page.iter().map(|&ch| ch as char));
+                va += page.len();
+            }
         }
-        string.push(ch as char);
-        va += 1;
     }
-    string
 }
 
+/// Copy `len` bytes at `user_src` into the kernel, every source page must be mapped `U | R`.
 pub fn copy_from_user(
     token: usize, 
     ker_dest: *mut u8, 
     user_src: *const u8, 
     len: usize
 ) -> Result<(), MemoryError>{
+    let dest = unsafe { core::slice::from_raw_parts_mut(ker_dest, len) };
+    for_each_user_chunk(token, user_src as usize, len, PTEFlags::R, false, |chunk, done| {
+        dest[done..done + chunk.len()].copy_from_slice(chunk);
+    })
+}
+
+/// Run `f` on each piece of `[va, va + len)` lying in one page, with the
+/// offset of the piece in the range, once the page is found mapped with
+/// `U` and `access`.
+///
+/// When `token` is the active space and the page allows the access
+/// itself (`W` if `write`, `R` otherwise), the piece is the user page,
+/// inside a [`UserAccess`] window. Otherwise the page is translated and
+/// the piece is its frame, seen through the kernel's mapping.
+fn for_each_user_chunk(
+    token: usize,
+    va: usize,
+    len: usize,
+    access: PTEFlags,
+    write: bool,
+    mut f: impl FnMut(&mut [u8], usize),
+) -> Result<(), MemoryError> {
     let page_table = PageTable::from_token(token);
-    let mut remaining = len;
-    let mut current_dest = ker_dest;
-    let mut current_src = user_src;
-
-    while remaining > 0 {
-        // 1. 获取当前页的起始地址和偏移量
-        let src_va = VirtAddr::new(current_src as usize);
-        let page_start = src_va.round_down();
-        let offset = src_va.page_offset();
-        let bytes_to_copy = core::cmp::min(PAGE_SIZE - offset, remaining);
-
-        // 2. 翻译用户虚拟地址到物理地址
-        let pte = page_table
-            .find_pte_by_vpn(page_start.into())
-            .ok_or(MemoryError::PageNotMapped)?;
-
-        // 4. 计算物理地址并执行复制
-        let phys_addr: PhysAddr = PhysAddr::from(pte.ppn()) + offset;
-        unsafe {
-            // 注意：这里假设 phys_addr 可以直接访问（需要物理内存映射）
-            ptr::copy_nonoverlapping(
-                usize::from(phys_addr) as *mut u8,
-                current_dest,
-                bytes_to_copy,
-            );
+    let end = va.checked_add(len).ok_or(MemoryError::PageNotMapped)?;
+    let active = token == satp::read().bits();
+    let hardware_access = if write { PTEFlags::W } else { PTEFlags::R };
+    let mut current = va;
+
+    while current < end {
+        let offset = VirtAddr::from(current).page_offset();
+        let chunk_len = core::cmp::min(PAGE_SIZE - offset, end - current);
+        let pte = user_pte(&page_table, VirtAddr::from(current).down_to_vpn(), access)?;
+        if active && pte.flags().contains(hardware_access) {
+            let _window = UserAccess::open();
+            f(unsafe { core::slice::from_raw_parts_mut(current as *mut u8, chunk_len) }, current - va);
+        } else {
+            f(&mut pte.ppn().get_bytes_array_slice()[offset..offset + chunk_len], current - va);
         }
+        current += chunk_len;
+    }
+
+    Ok(())
+}
 
-        // 5. 更新指针和剩余长度
-        remaining -= bytes_to_copy;
-        current_dest = unsafe { current_dest.add(bytes_to_copy) };
-        current_src = unsafe { current_src.add(bytes_to_copy) };
+/// The PTE of user page `vpn`, if it is mapped with `U` and `access`.
+///
+/// `PageNotMapped` if it isn't valid, `PermissionDenied` if the flags are missing.
+fn user_pte(page_table: &PageTable, vpn: VirtPageNum, access: PTEFlags) -> Result<PageTableEntry, MemoryError> {
+    let pte = page_table
+        .find_pte_by_vpn(vpn)
+        .filter(|pte| pte.is_valid())
+        .ok_or(MemoryError::PageNotMapped)?;
+    if !pte.flags().contains(PTEFlags::U | access) {
+        return Err(MemoryError::PermissionDenied);
     }
+    Ok(pte)
+}
 
+/// Copy `src` into the pages at `user_dest` mapped with `U` and `access`.
+fn copy_to_pages(token: usize, user_dest: *mut u8, src: &[u8], access: PTEFlags) -> Result<(), MemoryError> {
+    for_each_user_chunk(token, user_dest as usize, src.len(), access, true, |chunk, done| {
+        chunk.copy_from_slice(&src[done..done + chunk.len()]);
+    })
+}
 
+/// Copy `src` into user space at `user_dest`, page by page.
+///
+/// Every destination page must be mapped `U | W`, the user could not
+/// write there itself otherwise. Fails without copying the rest at the
+/// first page which isn't.
+pub fn copy_to_user(
+    token: usize,
+    user_dest: *mut u8,
+    src: &[u8],
+) -> Result<(), MemoryError> {
+    copy_to_pages(token, user_dest, src, PTEFlags::W)
+}
 
-    Ok(())
-}
\ No newline at end of file
+/// Like [`copy_to_user`], but read-only user pages are written too.
+///
+/// For the loader filling in an image, e.g. relocations in `.data.rel.ro`.
+pub fn copy_to_image(token: usize, user_dest: *mut u8, src: &[u8]) -> Result<(), MemoryError> {
+    copy_to_pages(token, user_dest, src, PTEFlags::empty())
+}
+
+/// Copy `value` into user space at `user_dest`.
+pub fn write_to_user<T: Copy>(token: usize, user_dest: *mut T, value: &T) -> Result<(), MemoryError> {
+    let bytes = unsafe {
+        core::slice::from_raw_parts(value as *const T as *const u8, core::mem::size_of::<T>())
+    };
+    copy_to_user(token, user_dest as *mut u8, bytes)
+}
+
+#[kernel_test]
+fn user_access_test() {
+    use super::{map_area::{MapFlags, MapPermission}, memory_set::MemorySet, uaccess};
+
+    let mut memory_set = MemorySet::new_bare();
+    let token = memory_set.token();
+    let rw = MapPermission::R | MapPermission::W;
+    let data = memory_set.map_anonymous(0, 2 * PAGE_SIZE, rw, MapFlags::empty()).unwrap();
+    let read_only = memory_set.map_anonymous(0, PAGE_SIZE, MapPermission::R, MapFlags::empty()).unwrap();
+
+    // across the page boundary, through the frames: the space isn't active
+    let ptr = (usize::from(data) + PAGE_SIZE - 3) as *mut u8;
+    copy_to_user(token, ptr, b"crossing").unwrap();
+    let mut back = [0u8; 8];
+    copy_from_user(token, back.as_mut_ptr(), ptr, back.len()).unwrap();
+    assert_eq!(&back, b"crossing");
+
+    // the user couldn't write there, only the loader may
+    let ptr = usize::from(read_only) as *mut u8;
+    assert_eq!(copy_to_user(token, ptr, b"x"), Err(MemoryError::PermissionDenied));
+    copy_to_image(token, ptr, b"x").unwrap();
+    assert_eq!(copy_from_user(token, back.as_mut_ptr(), ptr, 1), Ok(()));
+    assert_eq!(back[0], b'x');
+
+    // kernel only pages aren't user memory
+    let kernel_va = usize::from(read_only) + 16 * PAGE_SIZE;
+    memory_set.map_linear(kernel_va, kernel_va, PAGE_SIZE, MapPermission::R);
+    assert_eq!(
+        copy_from_user(token, back.as_mut_ptr(), kernel_va as *const u8, 1),
+        Err(MemoryError::PermissionDenied)
+    );
+
+    // the windows nest, the outer one closes
+    assert!(!uaccess::is_open());
+    {
+        let _outer = UserAccess::open();
+        drop(UserAccess::open());
+        assert!(uaccess::is_open());
+    }
+    assert!(!uaccess::is_open());
+}
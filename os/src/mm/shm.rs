@@ -0,0 +1,156 @@
+//! System V style shared memory segments
+//!
+//! A segment is a set of frames allocated once by `shmget` and kept in a
+//! registry under its id, and under its key unless it is `IPC_PRIVATE`.
+//! Each `shmat` maps the same frames into the caller with a
+//! [`MapType::Shared`](super::map_area::MapType::Shared) area, which holds
+//! the segment by an `Arc`; fork shares the attachment.
+//!
+//! `shmctl(IPC_RMID)` takes a segment out of the registry, its frames are
+//! freed along with the last attachment, on `shmdt`, `munmap` or exit.
+use alloc::{collections::btree_map::BTreeMap, sync::Arc, vec::Vec};
+use os_macros::{kernel_test, monitor_command};
+
+use crate::{config::PAGE_SIZE, println, sync::spin::mutex::IRQSpinLock, syscall::error::Errno};
+
+use super::{
+    address::PhysPageNum,
+    frame_allocator::{frame_alloc, FrameTracker},
+};
+
+type Mutex<T> = IRQSpinLock<T>;
+
+/// `key` of `shmget` always creating a new segment
+pub const IPC_PRIVATE: usize = 0;
+
+pub struct ShmSegment {
+    id: usize,
+    key: usize,
+    /// Size asked for, the segment spans whole pages
+    size: usize,
+    frames: Vec<FrameTracker>,
+}
+
+impl ShmSegment {
+    pub fn pages(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Frame of the page `index` of the segment
+    pub fn frame(&self, index: usize) -> PhysPageNum {
+        self.frames[index].ppn
+    }
+}
+
+struct Registry {
+    next_id: usize,
+    segments: BTreeMap<usize, Arc<ShmSegment>>,
+}
+
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+    next_id: 1,
+    segments: BTreeMap::new(),
+});
+
+/// The id of the segment of `key`, created with `size` bytes if missing
+/// and `create` is set.
+///
+/// `ENOENT` if it is missing and not created, `EEXIST` if it exists and
+/// `exclusive` is set, `EINVAL` for a zero `size` or one bigger than the
+/// existing segment, `ENOMEM` if the frames run out.
+pub fn get(key: usize, size: usize, create: bool, exclusive: bool) -> Result<usize, Errno> {
+    let mut registry = REGISTRY.lock();
+    if key != IPC_PRIVATE {
+        if let Some(segment) = registry.segments.values().find(|segment| segment.key == key) {
+            if create && exclusive {
+                return Err(Errno::EEXIST);
+            }
+            if size > segment.size {
+                return Err(Errno::EINVAL);
+            }
+            return Ok(segment.id);
+        }
+        if !create {
+            return Err(Errno::ENOENT);
+        }
+    }
+    if size == 0 {
+        return Err(Errno::EINVAL);
+    }
+
+    let pages = (size + PAGE_SIZE - 1) / PAGE_SIZE;
+    // zeroed, and given back at once if one is missing
+    let frames = (0..pages)
+        .map(|_| frame_alloc())
+        .collect::<Option<Vec<_>>>()
+        .ok_or(Errno::ENOMEM)?;
+    let id = registry.next_id;
+    registry.next_id += 1;
+    registry.segments.insert(id, Arc::new(ShmSegment { id, key, size, frames }));
+    Ok(id)
+}
+
+/// The segment `id`, for attaching it
+pub fn find(id: usize) -> Option<Arc<ShmSegment>> {
+    REGISTRY.lock().segments.get(&id).cloned()
+}
+
+/// Take the segment `id` out of the registry, returns whether it was there.
+///
+/// The tasks which attached it keep it until they detach.
+pub fn remove(id: usize) -> bool {
+    REGISTRY.lock().segments.remove(&id).is_some()
+}
+
+#[monitor_command(name = "ipcs", help = "List the shared memory segments")]
+fn ipcs_command(_args: &[&str]) {
+    println!("{:>6} {:>10} {:>8} {:>7}", "shmid", "key", "bytes", "nattch");
+    for segment in REGISTRY.lock().segments.values() {
+        // one reference is the registry's
+        let attached = Arc::strong_count(segment) - 1;
+        println!("{:>6} {:>#10x} {:>8} {:>7}", segment.id, segment.key, segment.size, attached);
+    }
+}
+
+#[kernel_test]
+fn shm_test() {
+    use super::{
+        address::VirtPageNum,
+        map_area::{MapFlags, MapPermission},
+        memory_set::MemorySet,
+    };
+
+    let id = get(IPC_PRIVATE, PAGE_SIZE + 1, true, false).unwrap();
+    let segment = find(id).unwrap();
+    assert_eq!(segment.pages(), 2);
+
+    let (mut writer, mut reader) = (MemorySet::new_bare(), MemorySet::new_bare());
+    let rw = MapPermission::R | MapPermission::W;
+    let at = writer.attach_shared(0, segment.clone(), rw, MapFlags::empty()).unwrap().down_to_vpn();
+    let ro = reader.attach_shared(0, segment.clone(), MapPermission::R, MapFlags::empty()).unwrap().down_to_vpn();
+    // both map the frames of the segment, with their own permissions
+    for page in 0..2 {
+        let ppn = writer.translate(VirtPageNum(at.0 + page)).unwrap().ppn();
+        assert!(ppn == reader.translate(VirtPageNum(ro.0 + page)).unwrap().ppn());
+        assert!(ppn == segment.frame(page));
+    }
+    assert!(!reader.translate(ro).unwrap().writable());
+
+    // removed, it lives on in the attachments
+    assert!(remove(id));
+    assert!(find(id).is_none());
+    assert_eq!(Arc::strong_count(&segment), 3);
+    writer.detach_shared(at).unwrap();
+    reader.detach_shared(ro).unwrap();
+    assert_eq!(Arc::strong_count(&segment), 1);
+    assert!(writer.stray_ptes().is_empty() && reader.stray_ptes().is_empty());
+
+    // keyed segments are found again, unless an exclusive one is asked for
+    let key = 0x5348_4d54;
+    assert_eq!(get(key, PAGE_SIZE, false, false), Err(Errno::ENOENT));
+    let id = get(key, PAGE_SIZE, true, false).unwrap();
+    assert_eq!(get(key, PAGE_SIZE, true, false), Ok(id));
+    assert_eq!(get(key, PAGE_SIZE, true, true), Err(Errno::EEXIST));
+    assert_eq!(get(key, 2 * PAGE_SIZE, false, false), Err(Errno::EINVAL));
+    assert!(remove(id));
+}
@@ -0,0 +1,277 @@
+//! Slab caches of hot kernel objects
+//!
+//! A [`SlabCache`] hands out objects of one layout from slabs: chunks of
+//! the buddy heap aligned to their size, a [`Slab`] header at the start
+//! and the objects after it. Freeing an object finds its slab by masking
+//! the address, no lookup needed.
+//!
+//! Unlike the size-class arenas, a slab whose objects are all free goes
+//! back to the buddy heap, except one kept per cache so that a cache
+//! alternating between one and zero objects doesn't churn.
+//!
+//! The heap routes every allocation of the exact layout of a cache to it,
+//! whatever its type, like merged SLUB caches on Linux.
+use core::{
+    alloc::Layout,
+    mem::{align_of, size_of},
+    ptr::null_mut,
+};
+
+use os_macros::kernel_test;
+
+use crate::{config::PAGE_SIZE, sync::spin::mutex::IRQSpinLock};
+
+type Mutex<T> = IRQSpinLock<T>;
+
+/// Objects a slab holds at least, slabs of big objects span several pages
+const MIN_OBJECTS: usize = 8;
+
+/// Layout of the allocation behind an `Arc<T>`: the two counters, then `T`
+pub const fn arc_layout<T>() -> Layout {
+    let align = if align_of::<T>() > align_of::<usize>() { align_of::<T>() } else { align_of::<usize>() };
+    let offset = (2 * size_of::<usize>() + align_of::<T>() - 1) / align_of::<T>() * align_of::<T>();
+    let size = (offset + size_of::<T>() + align - 1) / align * align;
+    match Layout::from_size_align(size, align) {
+        Ok(layout) => layout,
+        Err(_) => panic!("bad Arc layout"),
+    }
+}
+
+/// Start of every slab
+#[repr(C)]
+struct Slab {
+    /// Neighbours in the partial list
+    prev: *mut Slab,
+    next: *mut Slab,
+    /// Address of the first free object, 0 when full
+    free: usize,
+    in_use: usize,
+}
+
+struct SlabList {
+    /// Slabs with both free and used objects
+    partial: *mut Slab,
+    /// A slab without used objects, kept for the next allocation
+    empty: *mut Slab,
+    /// Slabs taken from the buddy heap, `empty` included
+    slabs: usize,
+    /// Objects handed out
+    in_use: usize,
+    /// Allocations served since boot
+    allocs: usize,
+}
+
+// only reached with the lock of the cache held
+unsafe impl Send for SlabList {}
+
+impl SlabList {
+    unsafe fn push_partial(&mut self, slab: *mut Slab) {
+        (*slab).prev = null_mut();
+        (*slab).next = self.partial;
+        if !self.partial.is_null() {
+            (*self.partial).prev = slab;
+        }
+        self.partial = slab;
+    }
+
+    unsafe fn unlink(&mut self, slab: *mut Slab) {
+        if (*slab).prev.is_null() {
+            self.partial = (*slab).next;
+        } else {
+            (*(*slab).prev).next = (*slab).next;
+        }
+        if !(*slab).next.is_null() {
+            (*(*slab).next).prev = (*slab).prev;
+        }
+    }
+}
+
+/// Counters of a cache, for `/proc/slabinfo`
+pub struct SlabStats {
+    pub name: &'static str,
+    pub object_size: usize,
+    pub objects_per_slab: usize,
+    pub slab_size: usize,
+    pub in_use: usize,
+    pub slabs: usize,
+    pub allocs: usize,
+}
+
+pub struct SlabCache {
+    name: &'static str,
+    /// Layout of the objects, as requested
+    object: Layout,
+    /// Layout of a slot of a slab, large enough for a free object
+    slot: Layout,
+    /// Size and alignment of a slab, a power of two
+    slab_size: usize,
+    /// Offset of the first object in a slab
+    first: usize,
+    list: Mutex<SlabList>,
+}
+
+impl SlabCache {
+    pub const fn new(name: &'static str, layout: Layout) -> Self {
+        // a free object holds the address of the next one
+        let align = if layout.align() > align_of::<usize>() { layout.align() } else { align_of::<usize>() };
+        let size = (layout.size() + align - 1) / align * align;
+        let size = if size > size_of::<usize>() { size } else { size_of::<usize>() };
+        let first = (size_of::<Slab>() + align - 1) / align * align;
+        let mut slab_size = PAGE_SIZE;
+        while slab_size < first + MIN_OBJECTS * size {
+            slab_size *= 2;
+        }
+        Self {
+            name,
+            object: layout,
+            slot: match Layout::from_size_align(size, align) {
+                Ok(layout) => layout,
+                Err(_) => panic!("bad slab layout"),
+            },
+            slab_size,
+            first,
+            list: Mutex::new(SlabList {
+                partial: null_mut(),
+                empty: null_mut(),
+                slabs: 0,
+                in_use: 0,
+                allocs: 0,
+            }),
+        }
+    }
+
+    /// Whether allocations of `layout` come from this cache
+    pub fn serves(&self, layout: &Layout) -> bool {
+        layout.size() == self.object.size() && layout.align() <= self.slot.align()
+    }
+
+    fn slab_layout(&self) -> Layout {
+        unsafe { Layout::from_size_align_unchecked(self.slab_size, self.slab_size) }
+    }
+
+    fn objects_per_slab(&self) -> usize {
+        (self.slab_size - self.first) / self.slot.size()
+    }
+
+    /// An object, from a new slab taken with `grow` if none is free.
+    ///
+    /// Null if `grow` fails. `grow` runs with the lock of the cache held.
+    pub unsafe fn alloc(&self, grow: impl FnOnce(Layout) -> *mut u8) -> *mut u8 {
+        let mut list = self.list.lock();
+        if list.partial.is_null() {
+            let slab = if !list.empty.is_null() {
+                core::mem::replace(&mut list.empty, null_mut())
+            } else {
+                let slab = grow(self.slab_layout()) as *mut Slab;
+                if slab.is_null() {
+                    return null_mut();
+                }
+                self.init_slab(slab);
+                list.slabs += 1;
+                slab
+            };
+            list.push_partial(slab);
+        }
+        let slab = list.partial;
+        let object = (*slab).free;
+        (*slab).free = *(object as *const usize);
+        (*slab).in_use += 1;
+        if (*slab).free == 0 {
+            list.unlink(slab);
+        }
+        list.in_use += 1;
+        list.allocs += 1;
+        object as *mut u8
+    }
+
+    /// Give back `ptr`, handed out by this cache. An empty slab goes back
+    /// with `shrink` if one is already kept, with the lock held.
+    pub unsafe fn dealloc(&self, ptr: *mut u8, shrink: impl FnOnce(*mut u8, Layout)) {
+        let slab = (ptr as usize & !(self.slab_size - 1)) as *mut Slab;
+        let mut list = self.list.lock();
+        let was_full = (*slab).free == 0;
+        *(ptr as *mut usize) = (*slab).free;
+        (*slab).free = ptr as usize;
+        (*slab).in_use -= 1;
+        list.in_use -= 1;
+        if was_full {
+            list.push_partial(slab);
+        }
+        if (*slab).in_use == 0 {
+            list.unlink(slab);
+            if list.empty.is_null() {
+                list.empty = slab;
+            } else {
+                list.slabs -= 1;
+                shrink(slab as *mut u8, self.slab_layout());
+            }
+        }
+    }
+
+    /// Chain all the objects of a fresh slab, by ascending address.
+    unsafe fn init_slab(&self, slab: *mut Slab) {
+        let start = slab as usize + self.first;
+        let size = self.slot.size();
+        let count = self.objects_per_slab();
+        for index in 0..count {
+            let object = start + index * size;
+            *(object as *mut usize) = if index + 1 < count { object + size } else { 0 };
+        }
+        slab.write(Slab {
+            prev: null_mut(),
+            next: null_mut(),
+            free: start,
+            in_use: 0,
+        });
+    }
+
+    /// Bytes of objects handed out
+    pub fn in_use_bytes(&self) -> usize {
+        self.list.lock().in_use * self.object.size()
+    }
+
+    pub fn stats(&self) -> SlabStats {
+        let list = self.list.lock();
+        SlabStats {
+            name: self.name,
+            object_size: self.object.size(),
+            objects_per_slab: self.objects_per_slab(),
+            slab_size: self.slab_size,
+            in_use: list.in_use,
+            slabs: list.slabs,
+            allocs: list.allocs,
+        }
+    }
+}
+
+#[kernel_test]
+fn slab_cache_test() {
+    use alloc::{alloc::{alloc, dealloc}, vec::Vec};
+
+    assert_eq!(arc_layout::<[u8; 40]>(), Layout::new::<[usize; 7]>());
+
+    let cache = SlabCache::new("test", Layout::new::<[u64; 40]>());
+    let per_slab = cache.objects_per_slab();
+    assert!(per_slab >= MIN_OBJECTS);
+    let grow = |layout| unsafe { alloc(layout) };
+    let shrink = |ptr, layout| unsafe { dealloc(ptr, layout) };
+
+    // three slabs, the last one partial
+    let objects: Vec<*mut u8> = (0..2 * per_slab + 1)
+        .map(|_| unsafe { cache.alloc(grow) })
+        .collect();
+    assert!(objects.iter().all(|&object| !object.is_null() && object as usize % 8 == 0));
+    let stats = cache.stats();
+    assert_eq!((stats.in_use, stats.slabs), (2 * per_slab + 1, 3));
+
+    // each slab empties in turn: the first is kept, the others given back
+    for &object in objects.iter() {
+        unsafe { cache.dealloc(object, shrink) };
+    }
+    let stats = cache.stats();
+    assert_eq!((stats.in_use, stats.slabs, stats.allocs), (0, 1, 2 * per_slab + 1));
+    // the kept slab serves the next allocation
+    let object = unsafe { cache.alloc(|_| panic!("grew with a slab kept")) };
+    unsafe { cache.dealloc(object, shrink) };
+    unsafe { dealloc(cache.list.lock().empty as *mut u8, cache.slab_layout()) };
+}
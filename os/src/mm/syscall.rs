@@ -0,0 +1,378 @@
+use bitflags::bitflags;
+use os_macros::syscall_register;
+
+use crate::{config::USER_MMAP_BASE, syscall::{args::{check_user_range, populate_user}, error::Errno}, task::current_task};
+
+use super::{
+    address::{VirtAddr, VirtPageNum},
+    error::MemoryError,
+    map_area::{MapFlags, MapPermission},
+    memory_set::AreaInfo,
+    page_table::copy_to_user,
+    shm,
+};
+
+bitflags! {
+    /// `prot` of `mmap`, Linux values
+    pub struct ProtFlags: usize {
+        const READ = 0x1;
+        const WRITE = 0x2;
+        const EXEC = 0x4;
+    }
+}
+
+bitflags! {
+    /// `flags` of `mmap`, Linux values
+    pub struct MmapFlags: usize {
+        const SHARED = 0x01;
+        const PRIVATE = 0x02;
+        const FIXED = 0x10;
+        const ANONYMOUS = 0x20;
+    }
+}
+
+impl From<ProtFlags> for MapPermission {
+    fn from(prot: ProtFlags) -> Self {
+        let mut permission = MapPermission::U;
+        // the page table has no write-only pages
+        if prot.intersects(ProtFlags::READ | ProtFlags::WRITE) {
+            permission |= MapPermission::R;
+        }
+        if prot.contains(ProtFlags::WRITE) {
+            permission |= MapPermission::W;
+        }
+        if prot.contains(ProtFlags::EXEC) {
+            permission |= MapPermission::X;
+        }
+        permission
+    }
+}
+
+/// Map `len` bytes of private memory, returns the start address.
+///
+/// With `MAP_ANONYMOUS` the memory is zeroed, and `fd` and `offset` are
+/// ignored. Without it, it holds the file open as `fd` from `offset` on,
+/// see [`MemorySet::map_file`](super::memory_set::MemorySet::map_file):
+/// the fd may be closed afterwards.
+/// Only `MAP_PRIVATE` mappings exist. `PROT_NONE` isn't supported either,
+/// a page without any permission can't be told from a page table pointer.
+///
+/// # Returns
+/// - `-EINVAL` for a zero `len`, unsupported `prot`/`flags`, a misaligned
+///   `MAP_FIXED` address or `offset`
+/// - `-EBADF` if `fd` isn't open
+/// - `-EACCES` if `fd` isn't open for reading
+/// - `-ENODEV` if `fd` isn't a regular file, e.g. a pipe or a device
+/// - `-EOVERFLOW` if the mapping would end past the largest file offset
+/// - `-EEXIST` if a `MAP_FIXED` range overlaps an existing mapping
+/// - `-ENOMEM` if no room or no frames are left
+#[syscall_register(SYSCALL_MMAP)]
+pub fn sys_mmap(addr: usize, len: usize, prot: usize, flags: usize, fd: usize, offset: usize) -> isize {
+    let (Some(prot), Some(flags)) = (ProtFlags::from_bits(prot), MmapFlags::from_bits(flags)) else {
+        return Errno::EINVAL.as_ret();
+    };
+    if prot.is_empty() || !flags.contains(MmapFlags::PRIVATE) || flags.contains(MmapFlags::SHARED) {
+        return Errno::EINVAL.as_ret();
+    }
+    let map_flags = if flags.contains(MmapFlags::FIXED) {
+        MapFlags::FIXED
+    } else {
+        MapFlags::empty()
+    };
+
+    let current_task = current_task().unwrap();
+    let mut task = current_task.lock();
+    let inode = if flags.contains(MmapFlags::ANONYMOUS) {
+        None
+    } else {
+        let file = match task.user_res.as_ref().unwrap().fd_table.lock().get(fd) {
+            Ok(file) => file,
+            Err(errno) => return errno.as_ret(),
+        };
+        if !file.readable() {
+            return Errno::EACCES.as_ret();
+        }
+        let Some(inode) = file.inode().filter(|inode| !inode.is_dir()) else {
+            return Errno::ENODEV.as_ret();
+        };
+        if offset.checked_add(len).map_or(true, |end| end > isize::MAX as usize) {
+            return Errno::EOVERFLOW.as_ret();
+        }
+        Some(inode)
+    };
+    let result = task.with_user_res(|user_res| {
+        let mut memory_set = user_res.memory_set.lock();
+        match inode {
+            Some(inode) => memory_set.map_file(addr, len, prot.into(), map_flags, inode, offset),
+            None => memory_set.map_anonymous(addr, len, prot.into(), map_flags),
+        }
+    });
+    match result {
+        Ok(start) => usize::from(start) as isize,
+        Err(error) => Errno::from(error).as_ret(),
+    }
+}
+
+/// Move the program break to `addr`, returns the break after the call.
+///
+/// Like the Linux syscall (not the libc wrapper), failure isn't an error
+/// code: the break is left where it was and returned unchanged, so
+/// `brk(0)` just queries it. The heap may not grow into `USER_MMAP_BASE`,
+/// and its pages are only backed by frames once touched.
+#[syscall_register(SYSCALL_BRK)]
+pub fn sys_brk(addr: usize) -> isize {
+    let current_task = current_task().unwrap();
+    let mut task_inner = current_task.lock();
+    task_inner.with_user_res(|user_res| {
+        let mut heap = user_res.heap.lock();
+        if addr < heap.bottom || addr > USER_MMAP_BASE {
+            return heap.brk as isize;
+        }
+        let bottom = VirtAddr::from(heap.bottom).down_to_vpn();
+        let old_end = VirtAddr::from(heap.brk).up_to_vpn();
+        let new_end = VirtAddr::from(addr).up_to_vpn();
+        match user_res.memory_set.lock().resize_heap(bottom, old_end, new_end) {
+            Ok(()) => heap.brk = addr,
+            Err(error) => log::debug!("brk to {:#x} refused: {:?}", addr, error),
+        }
+        heap.brk as isize
+    })
+}
+
+/// Unmap every page of `[addr, addr + len)`, pages not mapped are skipped.
+///
+/// # Returns
+/// - `-EINVAL` if `addr` isn't page aligned or the range is empty or wraps
+/// - `-EACCES` if the range touches a kernel owned area, e.g. the trap context
+#[syscall_register(SYSCALL_MUNMAP)]
+pub fn sys_munmap(addr: usize, len: usize) -> isize {
+    let start_va = VirtAddr::from(addr);
+    if !start_va.aligned() || len == 0 {
+        return Errno::EINVAL.as_ret();
+    }
+    let Some(end) = addr.checked_add(len) else {
+        return Errno::EINVAL.as_ret();
+    };
+    let (start_vpn, end_vpn) = (start_va.down_to_vpn(), VirtAddr::from(end).up_to_vpn());
+
+    let current_task = current_task().unwrap();
+    let result = current_task.lock().with_user_res(|user_res| {
+        user_res.memory_set.lock().unmap_range(start_vpn, end_vpn)
+    });
+    match result {
+        Ok(()) => 0,
+        Err(error) => Errno::from(error).as_ret(),
+    }
+}
+
+/// Change the permissions of every page of `[addr, addr + len)` to `prot`.
+///
+/// Like `mmap`, `PROT_NONE` isn't supported, and `PROT_WRITE` implies
+/// `PROT_READ`. The areas the range cuts through are split.
+///
+/// # Returns
+/// - `-EINVAL` if `addr` isn't page aligned, the range wraps, or for an
+///   unsupported `prot`
+/// - `-ENOMEM` if a page of the range isn't mapped
+/// - `-EACCES` if the range touches a kernel owned area, e.g. the trap context
+#[syscall_register(SYSCALL_MPROTECT)]
+pub fn sys_mprotect(addr: usize, len: usize, prot: usize) -> isize {
+    let start_va = VirtAddr::from(addr);
+    if !start_va.aligned() {
+        return Errno::EINVAL.as_ret();
+    }
+    let Some(prot) = ProtFlags::from_bits(prot).filter(|prot| !prot.is_empty()) else {
+        return Errno::EINVAL.as_ret();
+    };
+    let Some(end) = addr.checked_add(len) else {
+        return Errno::EINVAL.as_ret();
+    };
+    let (start_vpn, end_vpn) = (start_va.down_to_vpn(), VirtAddr::from(end).up_to_vpn());
+
+    let current_task = current_task().unwrap();
+    let result = current_task.lock().with_user_res(|user_res| {
+        user_res.memory_set.lock().protect_range(start_vpn, end_vpn, prot.into())
+    });
+    match result {
+        Ok(()) => 0,
+        Err(MemoryError::PageNotMapped) => Errno::ENOMEM.as_ret(),
+        Err(error) => Errno::from(error).as_ret(),
+    }
+}
+
+bitflags! {
+    /// `shmflg` of `shmget` and `shmat`, Linux values. The low 9 bits,
+    /// the permissions of the segment, are ignored.
+    pub struct ShmFlags: usize {
+        const IPC_CREAT = 0o1000;
+        const IPC_EXCL = 0o2000;
+        const SHM_RDONLY = 0o10000;
+        const MODE = 0o777;
+    }
+}
+
+/// `cmd` of `shmctl`: remove the segment
+const IPC_RMID: usize = 0;
+
+/// Get the id of the shared memory segment of `key`, see [`shm::get`].
+///
+/// `IPC_PRIVATE` always creates a new segment of `size` bytes. Otherwise
+/// one is created if none has `key` and `IPC_CREAT` is set.
+///
+/// # Returns
+/// - `-ENOENT` if there is no segment of `key` and `IPC_CREAT` isn't set
+/// - `-EEXIST` if there is one and `IPC_CREAT | IPC_EXCL` is set
+/// - `-EINVAL` for a zero `size`, one bigger than the segment, or unknown flags
+/// - `-ENOMEM` if no frames are left
+#[syscall_register(SYSCALL_SHMGET)]
+pub fn sys_shmget(key: usize, size: usize, shmflg: usize) -> isize {
+    let Some(flags) = ShmFlags::from_bits(shmflg) else {
+        return Errno::EINVAL.as_ret();
+    };
+    let (create, exclusive) = (flags.contains(ShmFlags::IPC_CREAT), flags.contains(ShmFlags::IPC_EXCL));
+    match shm::get(key, size, create, exclusive) {
+        Ok(id) => id as isize,
+        Err(errno) => errno.as_ret(),
+    }
+}
+
+/// Attach the segment `shmid` at `addr`, anywhere if `addr` is 0.
+///
+/// It is mapped read-only with `SHM_RDONLY`, read-write otherwise. Forked
+/// children share the attachment.
+///
+/// # Returns
+/// - The start address of the attachment
+/// - `-EINVAL` for an unknown `shmid` or flags, a misaligned `addr`, or
+///   one whose range overlaps an existing mapping
+/// - `-ENOMEM` if no room is left
+#[syscall_register(SYSCALL_SHMAT)]
+pub fn sys_shmat(shmid: usize, addr: usize, shmflg: usize) -> isize {
+    let (Some(segment), Some(flags)) = (shm::find(shmid), ShmFlags::from_bits(shmflg)) else {
+        return Errno::EINVAL.as_ret();
+    };
+    let permission = if flags.contains(ShmFlags::SHM_RDONLY) {
+        MapPermission::R
+    } else {
+        MapPermission::R | MapPermission::W
+    };
+    let map_flags = if addr != 0 { MapFlags::FIXED } else { MapFlags::empty() };
+
+    let current_task = current_task().unwrap();
+    let result = current_task.lock().with_user_res(|user_res| {
+        user_res.memory_set.lock().attach_shared(addr, segment, permission, map_flags)
+    });
+    match result {
+        Ok(start) => usize::from(start) as isize,
+        Err(MemoryError::OutOfMemory) => Errno::ENOMEM.as_ret(),
+        Err(_) => Errno::EINVAL.as_ret(),
+    }
+}
+
+/// Detach the segment attached at `addr`, `-EINVAL` if there is none.
+///
+/// The segment is freed with its last attachment once removed by `shmctl`.
+#[syscall_register(SYSCALL_SHMDT)]
+pub fn sys_shmdt(addr: usize) -> isize {
+    let start = VirtAddr::from(addr);
+    if !start.aligned() {
+        return Errno::EINVAL.as_ret();
+    }
+    let current_task = current_task().unwrap();
+    let result = current_task.lock().with_user_res(|user_res| {
+        user_res.memory_set.lock().detach_shared(start.down_to_vpn())
+    });
+    match result {
+        Ok(()) => 0,
+        Err(_) => Errno::EINVAL.as_ret(),
+    }
+}
+
+/// Control the segment `shmid`, only `IPC_RMID` is supported: the
+/// segment can't be attached any more, and is freed once detached
+/// everywhere. `buf` is unused.
+///
+/// `-EINVAL` for an unknown `shmid` or `cmd`.
+#[syscall_register(SYSCALL_SHMCTL)]
+pub fn sys_shmctl(shmid: usize, cmd: usize, _buf: usize) -> isize {
+    if cmd != IPC_RMID || !shm::remove(shmid) {
+        return Errno::EINVAL.as_ret();
+    }
+    0
+}
+
+/// Pages `mincore` looks up at a time, the locks held
+const MINCORE_CHUNK: usize = 512;
+
+/// Report the residency of every page in `[addr, addr + len)`.
+///
+/// Writes one `PageResidency` byte per page into `vec`, which must hold
+/// `(len + PAGE_SIZE - 1) / PAGE_SIZE` bytes. `addr` must be page aligned.
+/// The range is looked up [`MINCORE_CHUNK`] pages at a time, each chunk
+/// copied before the next is looked up.
+///
+/// # Returns
+/// - 0 on success
+/// - `-EINVAL` if `addr` isn't page aligned
+/// - `-ENOMEM` if the range goes past the user half of the address space
+/// - `-EFAULT` if `vec` is not mapped writable
+#[syscall_register(SYSCALL_MINCORE)]
+pub fn sys_mincore(addr: usize, len: usize, vec: *mut u8) -> isize {
+    let start_va = VirtAddr::from(addr);
+    if !start_va.aligned() {
+        return Errno::EINVAL.as_ret();
+    }
+    if check_user_range(addr, len).is_err() {
+        return Errno::ENOMEM.as_ret();
+    }
+    let start_vpn = start_va.down_to_vpn();
+    let end_vpn = VirtAddr::from(addr + len).up_to_vpn();
+
+    let current_task = current_task().unwrap();
+    let mut residency = [0u8; MINCORE_CHUNK];
+    for chunk_start in (start_vpn.0..end_vpn.0).step_by(MINCORE_CHUNK) {
+        let pages = (end_vpn.0 - chunk_start).min(MINCORE_CHUNK);
+        let token = current_task.lock().with_user_res(|user_res| {
+            let memory_set = user_res.memory_set.lock();
+            for (page, byte) in residency[..pages].iter_mut().enumerate() {
+                *byte = memory_set.page_residency(VirtPageNum(chunk_start + page)) as u8;
+            }
+            memory_set.token()
+        });
+
+        // no lock is held while touching user memory
+        let dest = vec.wrapping_add(chunk_start - start_vpn.0);
+        if populate_user(dest as usize, pages).is_err() || copy_to_user(token, dest, &residency[..pages]).is_err() {
+            return Errno::EFAULT.as_ret();
+        }
+    }
+    0
+}
+
+/// Describe the areas of the current address space.
+///
+/// Fills `buf` with up to `count` `AreaInfo`, sorted by start address,
+/// and returns the total number of areas, which may be more than `count`.
+#[syscall_register(SYSCALL_VMA_INFO)]
+pub fn sys_vma_info(buf: *mut AreaInfo, count: usize) -> isize {
+    let current_task = current_task().unwrap();
+    let (token, infos) = current_task.lock().with_user_res(|user_res| {
+        let memory_set = user_res.memory_set.lock();
+        (memory_set.token(), memory_set.area_infos())
+    });
+
+    let written = infos.len().min(count);
+    let bytes = unsafe {
+        core::slice::from_raw_parts(
+            infos.as_ptr() as *const u8,
+            written * core::mem::size_of::<AreaInfo>(),
+        )
+    };
+    if populate_user(buf as usize, bytes.len()).is_err() {
+        return Errno::EFAULT.as_ret();
+    }
+    match copy_to_user(token, buf as *mut u8, bytes) {
+        Ok(()) => infos.len() as isize,
+        Err(_) => Errno::EFAULT.as_ret(),
+    }
+}
@@ -0,0 +1,58 @@
+//! TLB maintenance
+//!
+//! A page table change which removes or narrows a translation isn't seen
+//! by a hart until it flushes the stale entry: every such change must be
+//! followed by one of the flushes below, with the page table lock still
+//! held so that the frames aren't reused before.
+//!
+//! Only the pages changed are flushed on this hart, with the ASID of
+//! their address space. The other harts may run threads of the same
+//! address space, or cache kernel space translations: they get a
+//! shootdown IPI (see [`crate::processor::ipi`]) and flush everything.
+use core::arch::asm;
+
+use super::address::{VPNRange, VirtAddr, VirtPageNum};
+use crate::processor::ipi;
+
+/// Past this many pages, a flush of the whole ASID is cheaper
+const FLUSH_ALL_THRESHOLD: usize = 32;
+
+/// Flush the translation of `vpn` in `asid`, on this hart only.
+#[inline]
+pub fn flush_page(vpn: VirtPageNum, asid: usize) {
+    let va: VirtAddr = vpn.into();
+    unsafe {
+        asm!("sfence.vma {}, {}", in(reg) va.0, in(reg) asid);
+    }
+}
+
+/// Flush every translation of `asid`, on this hart only.
+#[inline]
+pub fn flush_asid(asid: usize) {
+    unsafe {
+        asm!("sfence.vma zero, {}", in(reg) asid);
+    }
+}
+
+/// Flush every translation, on this hart only.
+#[inline]
+pub fn flush_all() {
+    unsafe {
+        asm!("sfence.vma");
+    }
+}
+
+/// Flush the translations of `range` in `asid`, on every hart.
+///
+/// For unmapping or remapping pages that may be cached elsewhere.
+pub fn flush_range(range: VPNRange, asid: usize) {
+    let (start, end) = (range.get_start(), range.get_end());
+    if end.0 - start.0 > FLUSH_ALL_THRESHOLD {
+        flush_asid(asid);
+    } else {
+        for vpn in range {
+            flush_page(vpn, asid);
+        }
+    }
+    ipi::tlb_shootdown();
+}
@@ -0,0 +1,51 @@
+//! Windows in which the kernel may touch user pages
+//!
+//! `sstatus.SUM` stays clear while the kernel runs: a kernel load or store
+//! to a page mapped `U` faults instead of going through silently. The
+//! accessors of `page_table` open a [`UserAccess`] window around the copy
+//! itself, once they checked the `U | R` or `U | W` flags of every page it
+//! touches.
+//!
+//! A window is only needed when the user space is the active one, the
+//! kernel otherwise reaches the frames through its own mapping of them.
+//! Nothing may sleep or switch tasks inside a window, the bit would go
+//! along.
+
+use riscv::register::sstatus;
+
+/// `sstatus.SUM` set while alive, restored as it was when dropped
+pub struct UserAccess {
+    was_open: bool,
+}
+
+impl UserAccess {
+    pub fn open() -> Self {
+        let was_open = is_open();
+        unsafe {
+            sstatus::set_sum();
+        }
+        Self { was_open }
+    }
+}
+
+impl Drop for UserAccess {
+    fn drop(&mut self) {
+        if !self.was_open {
+            unsafe {
+                sstatus::clear_sum();
+            }
+        }
+    }
+}
+
+/// Whether this hart is inside a [`UserAccess`] window
+pub fn is_open() -> bool {
+    sstatus::read().sum()
+}
+
+/// Close any window the firmware left open, on each hart at boot.
+pub fn init() {
+    unsafe {
+        sstatus::clear_sum();
+    }
+}
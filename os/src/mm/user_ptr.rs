@@ -1,6 +1,6 @@
 use core::{marker::PhantomData, mem::{self, MaybeUninit}};
-use alloc::{boxed::Box, string::String, vec::Vec};
-use super::{error::MemoryError, page_table::{copy_from_user, translated_str}};
+use alloc::{boxed::Box, string::String};
+use super::{error::MemoryError, page_table::{copy_from_user, copy_to_user, translated_str, write_to_user}};
 
 /// A zero-cost safe wrapper around user-space memory pointers.
 ///
@@ -89,30 +89,38 @@ where
         Ok(init_buffer) 
     }
 
-}
-
-impl UserPtr<u8> {
-    pub fn read_to_string(&self) -> String{
-        translated_str(self.token, self.addr)
+    /// Writes a single value of type T to user-space.
+    ///
+    /// # Returns
+    /// A MemoryError if the pages are not mapped user-writable (`U | W`).
+    pub fn write(&self, value: T) -> Result<(), MemoryError>
+    where
+        T: Copy,
+    {
+        write_to_user(self.token, self.addr as *mut T, &value)
     }
-}
-
 
+    /// Writes `values` to user-space from this pointer on, handling cross-page access automatically.
+    ///
+    /// # Returns
+    /// A MemoryError if the pages are not mapped user-writable (`U | W`),
+    /// the bytes before the first such page are written then.
+    pub fn write_slice(&self, values: &[T]) -> Result<(), MemoryError>
+    where
+        T: Copy,
+    {
+        let bytes = unsafe {
+            core::slice::from_raw_parts(values.as_ptr() as *const u8, mem::size_of_val(values))
+        };
+        copy_to_user(self.token, self.addr as *mut u8, bytes)
+    }
 
-pub struct UserBuffer {
-    pub buffers: Vec<&'static mut [u8]>,
 }
 
-impl UserBuffer {
-    pub fn new(buffers: Vec<&'static mut [u8]>) -> Self {
-        Self { buffers }
-    }
-    pub fn len(&self) -> usize {
-        let mut total: usize = 0;
-        for b in self.buffers.iter() {
-            total += b.len();
-        }
-        total
+impl UserPtr<u8> {
+    /// Reads the NUL-terminated string this points to.
+    pub fn read_to_string(&self) -> Result<String, MemoryError> {
+        translated_str(self.token, self.addr)
     }
 }
 
@@ -0,0 +1,140 @@
+//! Kernel debug monitor
+//!
+//! A tiny line-oriented shell running in kernel mode, used for introspection
+//! while bringing up new subsystems.
+//!
+//! Commands are not listed here: every subsystem registers its own commands
+//! with the [`monitor_command`](os_macros::monitor_command) attribute, which
+//! places a [`MonitorCommand`] into the `.monitor_registry` link section
+//! (the same mechanism `#[syscall_register]` uses for system calls).
+//!
+//! ```rust
+//! #[monitor_command(name = "ps", help = "List tasks known to the scheduler")]
+//! fn ps_command(_args: &[&str]) {
+//!     // ...
+//! }
+//! ```
+
+use alloc::{string::String, vec::Vec};
+use os_macros::monitor_command;
+
+use crate::{print, println, sbi::console_getchar};
+
+/// Type of a monitor command handler.
+/// `args[0]` is the command name itself.
+pub type MonitorHandler = fn(args: &[&str]);
+
+/// A command registered in the `.monitor_registry` link section.
+///
+/// # Fields
+/// - `name`: The word typed at the prompt to run this command
+/// - `help`: One-line description printed by `help`
+/// - `handler`: The function executing the command
+#[repr(C)]
+pub struct MonitorCommand {
+    /// The command name (e.g., "ps")
+    pub name: &'static str,
+    /// One-line description of the command
+    pub help: &'static str,
+    /// The handler function for this command
+    pub handler: MonitorHandler,
+}
+
+const PROMPT: &str = "xux-monitor> ";
+
+/// Returns all commands collected by the linker.
+fn commands() -> &'static [MonitorCommand] {
+    extern "C" {
+        // Linker-provided symbols marking start/end of registration section
+        static __monitor_registry_start: MonitorCommand;
+        static __monitor_registry_end: MonitorCommand;
+    }
+
+    unsafe {
+        let start = &__monitor_registry_start as *const MonitorCommand;
+        let end = &__monitor_registry_end as *const MonitorCommand;
+        let count = (end as usize - start as usize) / core::mem::size_of::<MonitorCommand>();
+        core::slice::from_raw_parts(start, count)
+    }
+}
+
+/// Looks up a command by its name.
+pub fn find_command(name: &str) -> Option<&'static MonitorCommand> {
+    commands().iter().find(|command| command.name == name)
+}
+
+/// Parses and executes one command line.
+///
+/// Returns `false` if the line asks to leave the monitor.
+pub fn execute(line: &str) -> bool {
+    let args: Vec<&str> = line.split_whitespace().collect();
+    let Some(&name) = args.first() else {
+        return true;
+    };
+
+    if name == "exit" {
+        return false;
+    }
+
+    match find_command(name) {
+        Some(command) => (command.handler)(&args),
+        None => println!("unknown command: {} (try `help`)", name),
+    }
+    true
+}
+
+/// Reads one line from the console, echoing it back.
+fn read_line() -> String {
+    let mut line = String::new();
+    loop {
+        let c = console_getchar();
+        if c == usize::MAX || c == 0 {
+            core::hint::spin_loop();
+            continue;
+        }
+
+        match c as u8 {
+            b'\r' | b'\n' => {
+                println!("");
+                return line;
+            }
+            // backspace / delete
+            0x08 | 0x7f => {
+                if line.pop().is_some() {
+                    print!("\x08 \x08");
+                }
+            }
+            ch => {
+                line.push(ch as char);
+                print!("{}", ch as char);
+            }
+        }
+    }
+}
+
+/// Runs the interactive monitor until `exit` is entered.
+pub fn run() {
+    println!("[monitor] {} commands registered, `exit` to continue booting", commands().len());
+    loop {
+        print!("{}", PROMPT);
+        let line = read_line();
+        if !execute(line.as_str()) {
+            break;
+        }
+    }
+}
+
+pub fn init() {
+    log::info!("monitor: {} commands registered", commands().len());
+    for command in commands() {
+        log::debug!("monitor command `{}`", command.name);
+    }
+}
+
+#[monitor_command(name = "help", help = "List all monitor commands")]
+fn help_command(_args: &[&str]) {
+    for command in commands() {
+        println!("  {:<12} {}", command.name, command.help);
+    }
+    println!("  {:<12} {}", "exit", "Leave the monitor");
+}
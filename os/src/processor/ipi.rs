@@ -0,0 +1,106 @@
+//! Inter-processor interrupts
+//!
+//! A hart asks others for something by recording the request in their
+//! [`ProcessorShared`], then raising a supervisor software interrupt on
+//! them through the SBI. The targets serve it in [`handle_ipi`].
+//!
+//! - reschedule: wakes a hart parked in the idle loop so that it fetches
+//!   the task just made ready, see [`kick_idle_hart`]
+//! - TLB flush: drops the stale translations of a hart after a mapping
+//!   was removed, the sender waits until every target flushed, see
+//!   [`tlb_shootdown`]
+//! - backtrace: the watchdog found the hart locked up, it prints where it
+//!   is if it still takes interrupts, see [`request_backtrace`]
+
+use core::arch::asm;
+use core::sync::atomic::Ordering;
+
+use super::{current_processor_id, current_processor_shared, get_processor_by_id, watchdog, ProcessorId, CPU_NUM};
+use crate::{mm::tlb, sbi};
+
+/// `sip.SSIP`, the pending supervisor software interrupt
+const SIP_SSIP: usize = 1 << 1;
+
+/// Raise a supervisor software interrupt on every hart of `hart_mask`.
+fn send_ipi(hart_mask: usize) {
+    if let Err(error) = sbi::send_ipi(hart_mask) {
+        log::warn!("IPI to harts {:#x} failed: SBI error {}", hart_mask, error);
+    }
+}
+
+/// Serve the requests sent to this hart, on a supervisor software interrupt.
+pub fn handle_ipi() {
+    unsafe {
+        asm!("csrc sip, {}", in(reg) SIP_SSIP);
+    }
+    poll_tlb_flush();
+    if current_processor_shared().backtrace_requested.swap(false, Ordering::AcqRel) {
+        watchdog::print_backtrace();
+    }
+    // a reschedule has nothing left to do: the idle loop fetches again
+    // once the trap returns
+}
+
+/// Serve a pending TLB flush without waiting for the interrupt.
+///
+/// For the spin loops, which may run with interrupts disabled while the
+/// hart holding the lock waits for this one to flush.
+#[inline]
+pub fn poll_tlb_flush() {
+    let shared = current_processor_shared();
+    let requested = shared.tlb_flush_requested.load(Ordering::Acquire);
+    if shared.tlb_flush_done.load(Ordering::Acquire) < requested {
+        tlb::flush_all();
+        shared.tlb_flush_done.fetch_max(requested, Ordering::AcqRel);
+    }
+}
+
+/// Flush the TLB of every other online hart, see `mm::tlb` for this one.
+///
+/// Returns once all of them flushed theirs: a frame unmapped before
+/// can't be reached through a stale translation any more. Flushes asked
+/// to this hart are served while waiting, two harts shooting down each
+/// other with interrupts disabled would wait forever otherwise.
+pub fn tlb_shootdown() {
+    let this_hart: usize = current_processor_id().into();
+    let mut targets = 0;
+    let mut tickets = [0; CPU_NUM];
+    for hart_id in (0..CPU_NUM).filter(|&hart_id| hart_id != this_hart) {
+        let shared = get_processor_by_id(ProcessorId(hart_id));
+        if shared.online.load(Ordering::Acquire) {
+            tickets[hart_id] = shared.tlb_flush_requested.fetch_add(1, Ordering::AcqRel) + 1;
+            targets |= 1 << hart_id;
+        }
+    }
+    if targets == 0 {
+        return;
+    }
+    send_ipi(targets);
+    for hart_id in (0..CPU_NUM).filter(|&hart_id| targets & (1 << hart_id) != 0) {
+        let shared = get_processor_by_id(ProcessorId(hart_id));
+        while shared.tlb_flush_done.load(Ordering::Acquire) < tickets[hart_id] {
+            poll_tlb_flush();
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// Wake a hart of `affinity` parked in the idle loop, other than this
+/// one, to run a task just made ready. Does nothing if every such hart
+/// is busy.
+pub fn kick_idle_hart(affinity: usize) {
+    let this_hart: usize = current_processor_id().into();
+    for hart_id in (0..CPU_NUM).filter(|&hart_id| hart_id != this_hart && affinity & (1 << hart_id) != 0) {
+        // a single waker per parked hart
+        if get_processor_by_id(ProcessorId(hart_id)).idle.swap(false, Ordering::AcqRel) {
+            send_ipi(1 << hart_id);
+            return;
+        }
+    }
+}
+
+/// Ask hart `hart_id` to print its backtrace.
+pub fn request_backtrace(hart_id: usize) {
+    get_processor_by_id(ProcessorId(hart_id)).backtrace_requested.store(true, Ordering::Release);
+    send_ipi(1 << hart_id);
+}
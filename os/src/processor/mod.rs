@@ -2,21 +2,44 @@
 //!
 //! This module provides isolation and synchronization primitives for SMP (Symmetric Multi-Processing)
 //! systems, with support for per-Processor task management and interrupt control.
+//!
+//! The boot hart brings the kernel up, then starts the other harts through
+//! the SBI HSM extension ([`start_secondary_harts`]). Each hart finds its
+//! [`ProcessorLocal`] through `tp`, which the trap entry restores from the
+//! trap context of the task it interrupts.
+//!
+//! Every hart runs its own scheduler loop over a single shared ready
+//! queue: a task runs on whichever hart fetches it first, and may run on
+//! another one after it was preempted or woken up. A hart with nothing
+//! to run parks in `wfi` until an [`ipi`] wakes it up.
+//!
+//! Kernel code is preempted too, by the tick of a timer interrupt taken
+//! in the kernel, unless it disabled preemption ([`preempt_disable`]):
+//! the tick is then deferred to the matching [`preempt_enable`]. A hart
+//! which stops scheduling for too long is reported by the [`watchdog`].
+
+pub mod ipi;
+pub mod watchdog;
 
 use core::mem::MaybeUninit;
 use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
+use core::arch::asm;
+
 use alloc::boxed::Box;
 use alloc::sync::Arc;
-use lazy_static::lazy_static;
 use alloc::vec::Vec;
 
 
+use crate::config::PAGE_SIZE;
+use crate::sbi;
 use crate::register::Tp;
 use crate::task::{TaskContext, TaskControlBlock};
-use crate::{interupt::InterruptState};
+use crate::interupt::{InterruptController, InterruptState};
 use crate::task::scheduler::Scheduler;
-use crate::sync::spin::mutex::IRQSpinLock;
+use crate::sync::spin::mutex::{IRQSpinLock, IRQSpinLockGuard};
+use crate::task::TaskControlBlockInner;
+use os_macros::kernel_test;
 
 /// A unique identifier for a Processor core (hart) in the system.
 ///
@@ -31,17 +54,113 @@ impl From<ProcessorId> for usize {
     }
 }
 
-/// The number of Processor cores supported by this system.
-pub const CPU_NUM: usize = 1;
+/// The number of Processor cores supported by this system, picked at
+/// build time with `SMP=<n>` (like `SCHEDULER`), 1 by default.
+///
+/// Harts are expected to be numbered from 0, like on QEMU's virt machine.
+pub const CPU_NUM: usize = parse_cpu_num(option_env!("SMP"));
+
+const fn parse_cpu_num(smp: Option<&str>) -> usize {
+    let Some(smp) = smp else {
+        return 1;
+    };
+    let bytes = smp.as_bytes();
+    if bytes.is_empty() {
+        return 1;
+    }
+    let mut num = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        assert!(bytes[i].is_ascii_digit(), "SMP must be a number of harts");
+        num = num * 10 + (bytes[i] - b'0') as usize;
+        i += 1;
+    }
+    assert!(num > 0, "SMP must be at least 1");
+    num
+}
+
+/// Size of the boot stack of a secondary hart, the boot hart's is in `entry.asm`
+const SECONDARY_STACK_SIZE: usize = 16 * PAGE_SIZE;
+
+/// Harts running their scheduler loop, or about to
+static ONLINE_HARTS: AtomicUsize = AtomicUsize::new(0);
+
+/// The ready queue every hart schedules from
+static SHARED_SCHEDULER: IRQSpinLock<Option<&'static dyn Scheduler>> = IRQSpinLock::new(None);
+
+/// Size of the stack a hart moves to once a kernel stack overflowed
+const OVERFLOW_STACK_SIZE: usize = 4 * PAGE_SIZE;
+
+#[repr(C, align(16))]
+struct OverflowStack([u8; OVERFLOW_STACK_SIZE]);
+
+/// In `.bss`, `init_processor` runs before the heap is set up
+static mut OVERFLOW_STACKS: [OverflowStack; CPU_NUM] = {
+    const INIT: OverflowStack = OverflowStack([0; OVERFLOW_STACK_SIZE]);
+    [INIT; CPU_NUM]
+};
 
 static mut PROCESSORS_LOCAL: [MaybeUninit<ProcessorLocal>; CPU_NUM] = 
     unsafe { MaybeUninit::uninit().assume_init() };
 
 
 pub fn init_processor(hart_id: usize) {
+    assert!(hart_id < CPU_NUM, "hart {} out of CPU_NUM ({})", hart_id, CPU_NUM);
     unsafe { init_processor_local(hart_id) ;}
 }
 
+/// Install the ready queue shared by every hart, on the boot hart.
+pub fn init_shared_scheduler(scheduler: Box<dyn Scheduler>) {
+    let scheduler: &'static dyn Scheduler = Box::leak(scheduler);
+    *SHARED_SCHEDULER.lock() = Some(scheduler);
+    get_current_processor().init_scheduler(scheduler);
+}
+
+/// Start every hart but the boot one, each at `_start_secondary` with a
+/// fresh stack, its `rust_main_secondary` joins the scheduling.
+pub fn start_secondary_harts(boot_hart_id: usize) {
+    extern "C" {
+        fn _start_secondary();
+    }
+    set_online();
+    for hart_id in (0..CPU_NUM).filter(|&hart_id| hart_id != boot_hart_id) {
+        // the kernel heap is identity mapped, the hart starts with paging off
+        let stack = Box::leak(alloc::vec![0u8; SECONDARY_STACK_SIZE].into_boxed_slice());
+        let stack_top = (stack.as_ptr() as usize + SECONDARY_STACK_SIZE) & !0xf;
+        if let Err(error) = sbi::hart_start(hart_id, _start_secondary as usize, stack_top) {
+            log::warn!("hart {} failed to start: SBI error {}", hart_id, error);
+        }
+    }
+}
+
+/// Set up the hart running this, once started by [`start_secondary_harts`].
+pub fn init_secondary_processor(hart_id: usize) {
+    init_processor(hart_id);
+    let scheduler = SHARED_SCHEDULER.lock().expect("secondary hart started before the scheduler");
+    get_current_processor().init_scheduler(scheduler);
+}
+
+/// Count the hart running this in, once it takes IPIs.
+///
+/// From then on it is a target of every [`ipi::tlb_shootdown`], which
+/// waits for it: it must not be marked online before it can answer.
+pub fn set_online() {
+    current_processor_shared().online.store(true, Ordering::Release);
+    ONLINE_HARTS.fetch_add(1, Ordering::AcqRel);
+}
+
+/// Harts which joined the scheduling
+pub fn online_harts() -> usize {
+    ONLINE_HARTS.load(Ordering::Acquire)
+}
+
+/// The online harts, bit `n` for hart `n`
+pub fn online_mask() -> usize {
+    (0..CPU_NUM)
+        .filter(|&hart_id| get_processor_by_id(ProcessorId(hart_id)).online.load(Ordering::Acquire))
+        .fold(0, |mask, hart_id| mask | 1 << hart_id)
+}
+
 unsafe fn init_processor_local(
     hart_id: usize,
 ) {
@@ -61,20 +180,19 @@ fn current_processor_local() -> &'static mut ProcessorLocal {
     // &mut PROCESSORS_LOCAL[id] // 或 get_unchecked
 }
 
-lazy_static! {
-    /// Per-CPU shared data (protected by IRQSpinLock)
-    static ref PROCESSORS_SHARED: Vec<IRQSpinLock<ProcessorShared>> = {
-        log::info!("Initializing {} processors (shared)", CPU_NUM);
-        (0..CPU_NUM).map(|_| IRQSpinLock::new(ProcessorShared::new())).collect()
-    };
-}
+/// Per-CPU shared data, atomics only: it is read from the spin loops of
+/// the locks, see [`ipi::poll_tlb_flush`]
+static PROCESSORS_SHARED: [ProcessorShared; CPU_NUM] = {
+    const INIT: ProcessorShared = ProcessorShared::new();
+    [INIT; CPU_NUM]
+};
 
 /// Safe access to current CPU's local data
 
 
 /// Safe access to current CPU's shared data
 #[inline]
-fn current_processor_shared() -> &'static IRQSpinLock<ProcessorShared> {
+fn current_processor_shared() -> &'static ProcessorShared {
     let id = current_processor_id().0;
     &PROCESSORS_SHARED[id]  // 或 get_unchecked
 }
@@ -82,16 +200,38 @@ fn current_processor_shared() -> &'static IRQSpinLock<ProcessorShared> {
 
 
 
+/// The state of a hart the other harts see, through [`get_processor_by_id`]
 pub struct ProcessorShared {
-    ipi_pending: AtomicBool,
-    wakeup_signal: AtomicBool,
+    /// Answers IPIs, see [`set_online`]
+    online: AtomicBool,
+    /// Parked in the idle loop, waiting for a task to be made ready
+    idle: AtomicBool,
+    /// Number of TLB flushes asked by other harts
+    tlb_flush_requested: AtomicUsize,
+    /// Highest request flushed for
+    tlb_flush_done: AtomicUsize,
+    /// `get_time()` when the hart last made progress, 0 before its
+    /// scheduler loop started, see [`watchdog`]
+    heartbeat: AtomicUsize,
+    /// Tid of the task the hart runs, 0 in its scheduler loop
+    current_tid: AtomicUsize,
+    /// The current lockup of the hart was reported
+    lockup_reported: AtomicBool,
+    /// The watchdog asks the hart to print its backtrace
+    backtrace_requested: AtomicBool,
 }
 
 impl ProcessorShared {
     pub const fn new() -> Self{
         Self {
-            ipi_pending: AtomicBool::new(false),
-            wakeup_signal: AtomicBool::new(false)
+            online: AtomicBool::new(false),
+            idle: AtomicBool::new(false),
+            tlb_flush_requested: AtomicUsize::new(0),
+            tlb_flush_done: AtomicUsize::new(0),
+            heartbeat: AtomicUsize::new(0),
+            current_tid: AtomicUsize::new(0),
+            lockup_reported: AtomicBool::new(false),
+            backtrace_requested: AtomicBool::new(false),
         }
     }
 }
@@ -102,12 +242,21 @@ impl ProcessorShared {
 /// Each Processor core maintains its own task queue, execution context,
 /// and interrupt locking state.
 /// A core can't visit B core's Processor struct, so I remove the atomic
+///
+/// The kernel trap entry in `trap.S` reads the first two fields through `tp`.
+#[repr(C)]
 pub struct ProcessorLocal {
+    /// Top of the stack the kernel trap entry moves to when the kernel
+    /// stack it trapped on overflowed
+    overflow_stack_top: usize,
+    /// Where the kernel trap entry keeps `t0` while it checks `sp`
+    trap_scratch: usize,
     // cann't be modify
     hart_id: usize,
     // - Task schedule
     /// maybe support multiple core scheduler
-    scheduler: MaybeUninit<Box<dyn Scheduler>>,
+    /// The shared ready queue, see [`init_shared_scheduler`]
+    scheduler: MaybeUninit<&'static dyn Scheduler>,
     current_task: Option<Arc<TaskControlBlock>>,
     // A medium other task return schduler loop
     pub schedule_loop_task_context: TaskContext,
@@ -118,6 +267,13 @@ pub struct ProcessorLocal {
     interrupt_nest_cnt: AtomicUsize,
     /// Saved interrupt state for restoration when unlocking.
     is_enable_interrupt: AtomicBool,
+
+    // - Preemption
+
+    /// Nesting counter of [`preempt_disable`]
+    preempt_count: usize,
+    /// A scheduler tick came while preemption was disabled
+    need_resched: bool,
 }
 
 impl ProcessorLocal {
@@ -125,13 +281,18 @@ impl ProcessorLocal {
     ///
     /// # Arguments
     pub fn new(hart_id: usize) -> Self {
+        let overflow_stack = unsafe { core::ptr::addr_of!(OVERFLOW_STACKS[hart_id]) };
         Self {
+            overflow_stack_top: overflow_stack as usize + OVERFLOW_STACK_SIZE,
+            trap_scratch: 0,
             hart_id,
             scheduler: MaybeUninit::uninit(),
             current_task: None,
             schedule_loop_task_context: TaskContext::zero_init(),
             interrupt_nest_cnt : AtomicUsize::new(0),
             is_enable_interrupt: AtomicBool::new(true),
+            preempt_count: 0,
+            need_resched: false,
         }
     }
 
@@ -139,22 +300,42 @@ impl ProcessorLocal {
 
         // log::debug!("timer tick");
         
-        self.get_scheduler().yield_current();
+        // the policy decides whether the current task is preempted
+        self.get_scheduler().on_tick();
 
         // log::debug!("timer tick handle finish")
     }
 
+    /// A timer tick taken in the kernel: handled at once if the current
+    /// task may be preempted here, deferred to [`preempt_enable`] else.
+    pub fn kernel_timer_tick(&mut self) {
+        if self.preempt_count == 0 {
+            self.timer_tick();
+        } else {
+            self.need_resched = true;
+        }
+    }
+
+    /// Whether [`preempt_disable`] is in effect on this processor
+    pub fn preempt_disabled(&self) -> bool {
+        self.preempt_count > 0
+    }
+
     #[inline]
-    fn get_scheduler(&self) -> &Box<dyn Scheduler>{
-        unsafe { self.scheduler.assume_init_ref() }
+    fn get_scheduler(&self) -> &'static dyn Scheduler {
+        unsafe { *self.scheduler.assume_init_ref() }
     }
 
 
     // should be call after memory init
-    pub fn init_scheduler(&mut self, schduler: Box<dyn Scheduler>) {
+    fn init_scheduler(&mut self, schduler: &'static dyn Scheduler) {
         self.scheduler.write(schduler);
     }
 
+    pub fn hart_id(&self) -> usize {
+        self.hart_id
+    }
+
 
     pub fn get_current_task(&self) -> Option<&Arc<TaskControlBlock>> {
         match &self.current_task {
@@ -168,10 +349,12 @@ impl ProcessorLocal {
     }
 
     pub fn set_current_task(&mut self, task: Arc<TaskControlBlock> ) {
+        current_processor_shared().current_tid.store(usize::from(task.get_tid()), Ordering::Relaxed);
         self.current_task = Some(task);
     }
 
     pub fn clean_current_task(&mut self) {
+        current_processor_shared().current_tid.store(0, Ordering::Relaxed);
         self.current_task = None;
     }
 
@@ -180,11 +363,19 @@ impl ProcessorLocal {
     }
 
     pub fn add_task(&self, task_control_block: Arc<TaskControlBlock>) {
+        task_control_block.stats().on_enqueue();
+        let affinity = task_control_block.affinity();
         self.get_scheduler().add_task(task_control_block);
+        ipi::kick_idle_hart(affinity);
     }
 
+    /// The next ready task allowed on this hart
     pub fn fetch_task(&self) -> Option<Arc<TaskControlBlock>> {
-        self.get_scheduler().fetch_task()
+        self.get_scheduler().fetch_task(self.hart_id)
+    }
+
+    pub fn ready_tasks(&self) -> Vec<Arc<TaskControlBlock>> {
+        self.get_scheduler().ready_tasks()
     }
 
     pub fn yield_current(&self) {
@@ -195,6 +386,37 @@ impl ProcessorLocal {
         self.get_scheduler().exit_current(exit_status);
     }
 
+    pub fn block_current(&self, current_task_guard: IRQSpinLockGuard<TaskControlBlockInner>) {
+        self.get_scheduler().block_current(current_task_guard);
+    }
+
+    pub fn wakeup_task(&self, task: Arc<TaskControlBlock>) {
+        let affinity = task.affinity();
+        self.get_scheduler().wakeup_task(task);
+        ipi::kick_idle_hart(affinity);
+    }
+
+    /// Wait for an interrupt with nothing to run, in the scheduler loop.
+    ///
+    /// The hart shows itself idle before looking at the ready queue one
+    /// last time: a task made ready after that kicks it out of `wfi`,
+    /// which returns on a pending interrupt even while they are disabled.
+    /// Tasks pinned to other harts don't keep it awake.
+    pub fn park(&self) {
+        InterruptController::global_disable();
+        let shared = current_processor_shared();
+        shared.idle.store(true, Ordering::SeqCst);
+        // nothing else to do: a good time for the console
+        crate::io::console::flush();
+        if !self.get_scheduler().has_ready(self.hart_id) {
+            unsafe {
+                asm!("wfi");
+            }
+        }
+        shared.idle.store(false, Ordering::SeqCst);
+        InterruptController::global_enable();
+    }
+
     // ========== 中断管理接口 ========== //
     pub fn get_saved_interrupt_state(&self) -> InterruptState {
         self.is_enable_interrupt.load(Ordering::Acquire).into()
@@ -212,30 +434,103 @@ impl ProcessorLocal {
     pub fn decrement_nest(&self) -> usize {
         self.interrupt_nest_cnt.fetch_sub(1, Ordering::Release)
     }
+
+    /// Number of IRQ-safe locks currently held on this processor
+    pub fn nest_depth(&self) -> usize {
+        self.interrupt_nest_cnt.load(Ordering::Acquire)
+    }
+
+    /// Forget the nesting of interrupt-disabled and preemption-disabled
+    /// sections left open by code abandoned midway, a kernel test which
+    /// panicked or timed out. Returns both depths, as they were.
+    #[cfg(test)]
+    pub fn reset_nesting(&mut self) -> (usize, usize) {
+        self.need_resched = false;
+        (
+            self.interrupt_nest_cnt.swap(0, Ordering::AcqRel),
+            core::mem::take(&mut self.preempt_count),
+        )
+    }
+}
+
+
+/// Keep the current task on this hart, until the matching [`preempt_enable`].
+///
+/// Nests. Unlike disabling interrupts, interrupts are still taken, only
+/// their scheduler tick waits. The task must not block meanwhile.
+pub fn preempt_disable() {
+    // the counter is the one of the hart we run on, interrupts are off
+    // from reading `tp` to the update
+    InterruptController::intr_disable_nested();
+    get_current_processor().preempt_count += 1;
+    InterruptController::intr_enable_nested();
 }
 
+/// Undo a [`preempt_disable`], taking the tick deferred meanwhile if it
+/// was the last one.
+pub fn preempt_enable() {
+    InterruptController::intr_disable_nested();
+    let processor = get_current_processor();
+    assert!(processor.preempt_count > 0, "preempt_enable without preempt_disable");
+    processor.preempt_count -= 1;
+    let resched = processor.preempt_count == 0 && core::mem::take(&mut processor.need_resched);
+    InterruptController::intr_enable_nested();
+
+    if resched {
+        get_current_processor().timer_tick();
+    }
+}
+
+/// Disables preemption for its lifetime, see [`preempt_disable`]
+pub struct PreemptGuard(());
+
+impl PreemptGuard {
+    pub fn new() -> Self {
+        preempt_disable();
+        Self(())
+    }
+}
+
+impl Drop for PreemptGuard {
+    fn drop(&mut self) {
+        preempt_enable();
+    }
+}
+
+#[kernel_test]
+fn preempt_test() {
+    assert!(!get_current_processor().preempt_disabled());
+    let outer = PreemptGuard::new();
+    preempt_disable();
+    assert!(get_current_processor().preempt_disabled());
+    // deferred while disabled
+    get_current_processor().need_resched = true;
+    preempt_enable();
+    assert!(get_current_processor().need_resched);
+    // the tests run before the scheduler is set up, nothing to take
+    get_current_processor().need_resched = false;
+    drop(outer);
+    assert!(!get_current_processor().preempt_disabled());
+}
 
 /// Returns the ID of the current Processor core.
 ///
-/// Reads the RISC-V `mhartid` CSR to determine which core is executing.
+/// `mhartid` can't be read from S-mode, the id is the one the SBI passed
+/// at boot, kept in the [`ProcessorLocal`] `tp` points to.
 #[inline(always)]
 pub fn current_processor_id() -> ProcessorId {
-    // ProcessorId(hartid::read())
-    ProcessorId(0)
+    ProcessorId(current_processor_local().hart_id)
 }
 
-/// Returns a mutable reference to the specified Processor core's structure.
+/// Returns the shared state of the specified Processor core.
 ///
 /// # Arguments
 /// * `id` - The Processor core identifier
 ///
-/// # Safety
-/// Caller must ensure:
-/// - No other references to this Processor exist
-/// - The ID is valid (0 ≤ id < CPU_NUM)
-pub fn get_processor_by_id(id: ProcessorId) -> &'static IRQSpinLock<ProcessorShared> {
+/// # Panics
+/// If the ID isn't valid (0 ≤ id < CPU_NUM)
+pub fn get_processor_by_id(id: ProcessorId) -> &'static ProcessorShared {
     let id: usize = id.into();
-    log::debug!("return processor[{}]", id);
     &PROCESSORS_SHARED[id]
 }
 
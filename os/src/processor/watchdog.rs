@@ -0,0 +1,129 @@
+//! Soft lockup detector
+//!
+//! A hart shows it is not stuck in the kernel with [`touch`]: each time
+//! its scheduler loop goes round, and each timer interrupt taken from
+//! user code. The time is stamped in its [`ProcessorShared`], which every
+//! timer interrupt, on any hart, [`check`]s for all of them: a hart which
+//! hasn't touched it for `kernel.watchdog_thresh` seconds loops in the
+//! kernel without scheduling, with preemption or interrupts disabled.
+//!
+//! A lockup is reported once, by the first hart to see it:
+//! - the hart, and the task it runs
+//! - the locks it holds, with `LOCKDEP=1`
+//! - its backtrace: the hart prints it itself when asked by an IPI, a
+//!   hart spinning with interrupts disabled never does
+//!
+//! The kernel panics then if `kernel.softlockup_panic` is set, and goes
+//! on otherwise, reporting the hart again once it made progress and
+//! locked up anew.
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use os_macros::kernel_test;
+
+use super::{current_processor_id, current_processor_shared, get_processor_by_id, ipi, ProcessorId, CPU_NUM};
+use crate::{
+    config::WATCHDOG_THRESH_S,
+    println,
+    timer::{cycles_to_ms, get_time, ms_to_cycles},
+    tools::{backtrace::trace_into, symbols::Symbolized},
+};
+
+/// Seconds without progress before a hart is locked up, 0 for never
+static THRESH_S: AtomicUsize = AtomicUsize::new(WATCHDOG_THRESH_S);
+/// Panic on a lockup rather than only report it
+static PANIC: AtomicBool = AtomicBool::new(false);
+
+/// Return addresses printed of a locked up hart
+const BACKTRACE_DEPTH: usize = 16;
+
+pub fn thresh_s() -> usize {
+    THRESH_S.load(Ordering::Relaxed)
+}
+
+pub fn set_thresh_s(seconds: usize) {
+    THRESH_S.store(seconds, Ordering::Relaxed);
+}
+
+pub fn panics() -> bool {
+    PANIC.load(Ordering::Relaxed)
+}
+
+pub fn set_panics(panic: bool) {
+    PANIC.store(panic, Ordering::Relaxed);
+}
+
+/// The hart running this made progress.
+pub fn touch() {
+    let shared = current_processor_shared();
+    shared.heartbeat.store(get_time(), Ordering::Relaxed);
+    shared.lockup_reported.store(false, Ordering::Relaxed);
+}
+
+/// Whether a hart last touched at `heartbeat` is locked up at `now`, after
+/// `thresh` timer cycles. A hart which never touched it is still booting.
+fn is_locked_up(heartbeat: usize, now: usize, thresh: usize) -> bool {
+    heartbeat != 0 && thresh != 0 && now.saturating_sub(heartbeat) > thresh
+}
+
+/// Look for locked up harts, on a timer interrupt.
+pub fn check() {
+    let thresh = ms_to_cycles(thresh_s() * 1000);
+    let now = get_time();
+    for hart_id in 0..CPU_NUM {
+        let shared = get_processor_by_id(ProcessorId(hart_id));
+        let heartbeat = shared.heartbeat.load(Ordering::Relaxed);
+        if shared.online.load(Ordering::Acquire)
+            && is_locked_up(heartbeat, now, thresh)
+            && !shared.lockup_reported.swap(true, Ordering::AcqRel)
+        {
+            report(hart_id, now - heartbeat);
+        }
+    }
+}
+
+fn report(hart_id: usize, stuck: usize) {
+    let shared = get_processor_by_id(ProcessorId(hart_id));
+    println!(
+        "[watchdog] soft lockup: hart {} made no progress for {} ms, running task {}",
+        hart_id,
+        cycles_to_ms(stuck),
+        shared.current_tid.load(Ordering::Relaxed),
+    );
+    #[cfg(feature = "lockdep")]
+    crate::sync::lockdep::print_held(hart_id);
+    if hart_id == usize::from(current_processor_id()) {
+        print_backtrace();
+    } else {
+        ipi::request_backtrace(hart_id);
+    }
+    if panics() {
+        panic!("soft lockup on hart {}", hart_id);
+    }
+}
+
+/// Print the backtrace of the hart running this, for a lockup report.
+pub fn print_backtrace() {
+    let mut ras = [0; BACKTRACE_DEPTH];
+    let depth = trace_into(&mut ras);
+    println!("[watchdog] hart {} backtrace:", usize::from(current_processor_id()));
+    for ra in &ras[..depth] {
+        println!("  ra={:#x} {}", ra, Symbolized(*ra));
+    }
+}
+
+#[kernel_test]
+fn watchdog_test() {
+    assert!(!is_locked_up(0, 1_000_000, 100));
+    assert!(!is_locked_up(500, 600, 100));
+    assert!(is_locked_up(500, 601, 100));
+    // disabled
+    assert!(!is_locked_up(500, 1_000_000, 0));
+    // a stamp of another hart may be a little ahead
+    assert!(!is_locked_up(700, 600, 100));
+
+    // this hart is running the test, so it made progress
+    touch();
+    let shared = current_processor_shared();
+    assert!(!is_locked_up(shared.heartbeat.load(Ordering::Relaxed), get_time(), ms_to_cycles(1000)));
+}
@@ -0,0 +1,200 @@
+//! Random numbers, for the kernel and `getrandom`
+//!
+//! Entropy comes from time: the `time` CSR when a hart's generator is
+//! seeded, and when each interrupt is taken along with the address it
+//! interrupted ([`add_interrupt_entropy`]), folded into a global pool.
+//! Every hart draws from its own xoshiro256** generator, seeded from the
+//! pool on first use and mixed with it again every [`RESEED_INTERVAL`]
+//! outputs.
+//!
+//! In deterministic mode, the seeds and reseeds are the fixed one of
+//! `determinism::seed` instead: address randomization and `getrandom` give
+//! the same values on every boot.
+//!
+//! Good enough for hash seeds and address randomization, not for keys:
+//! xoshiro is not a cryptographic generator, and a timer does not
+//! have much entropy.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use alloc::vec;
+use os_macros::{kernel_test, syscall_register};
+use riscv::register::time;
+
+use crate::{
+    config::PAGE_SIZE,
+    mm::page_table::copy_to_user,
+    processor::{current_processor_id, CPU_NUM},
+    sync::spin::mutex::IRQSpinLock,
+    syscall::error::Errno,
+    task::{current_task, determinism},
+    timer::realtime_ns,
+};
+
+type Mutex<T> = IRQSpinLock<T>;
+
+/// Outputs of a generator between two reseeds from the pool
+const RESEED_INTERVAL: usize = 1 << 16;
+
+/// `flags` of `getrandom`, Linux values, all accepted: nothing blocks
+const GRND_NONBLOCK: u32 = 0x1;
+const GRND_RANDOM: u32 = 0x2;
+const GRND_INSECURE: u32 = 0x4;
+
+/// Bytes filled by one `getrandom`, like Linux does past its own limit
+const MAX_GETRANDOM: usize = 16 * PAGE_SIZE;
+
+/// Interrupt timings, each mixed in with [`splitmix64`]
+static POOL: AtomicU64 = AtomicU64::new(0);
+
+/// A generator per hart, taken with interrupts off: a task that moved to
+/// another hart meanwhile just takes the lock of the one it left
+static HART_RNGS: [Mutex<Option<Xoshiro256>>; CPU_NUM] = {
+    const INIT: Mutex<Option<Xoshiro256>> = Mutex::new(None);
+    [INIT; CPU_NUM]
+};
+
+/// Step of the SplitMix64 generator, a good 64-bit mixer on its own
+fn splitmix64(state: u64) -> u64 {
+    let mut z = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Fold the time of an interrupt, taken at `pc`, into the pool.
+pub fn add_interrupt_entropy(pc: usize) {
+    let sample = time::read() as u64 ^ (pc as u64).rotate_left(32);
+    let _ = POOL.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |pool| Some(splitmix64(pool ^ sample)));
+}
+
+struct Xoshiro256 {
+    state: [u64; 4],
+    /// Outputs since the last reseed
+    outputs: usize,
+}
+
+impl Xoshiro256 {
+    fn new(seed: u64) -> Self {
+        let mut seed = seed;
+        let mut state = [0; 4];
+        for word in state.iter_mut() {
+            seed = splitmix64(seed);
+            *word = seed;
+        }
+        Self { state, outputs: 0 }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.outputs += 1;
+        if self.outputs == RESEED_INTERVAL {
+            self.outputs = 0;
+            self.state[0] ^= splitmix64(determinism::seed(POOL.load(Ordering::Relaxed) ^ time::read() as u64));
+        }
+        let s = &mut self.state;
+        let result = s[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = s[1] << 17;
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+        result
+    }
+}
+
+/// Run `f` on the generator of the current hart, seeded on first use.
+fn with_hart_rng<R>(f: impl FnOnce(&mut Xoshiro256) -> R) -> R {
+    let hart = usize::from(current_processor_id());
+    let mut rng = HART_RNGS[hart].lock();
+    let rng = rng.get_or_insert_with(|| {
+        let entropy = POOL.load(Ordering::Relaxed) ^ time::read() as u64 ^ realtime_ns().rotate_left(17);
+        let seed = determinism::seed(entropy);
+        // harts seeded in the same tick still differ
+        Xoshiro256::new(seed ^ (hart as u64).wrapping_mul(0x9e37_79b9_7f4a_7c15))
+    });
+    f(rng)
+}
+
+pub fn random_u64() -> u64 {
+    with_hart_rng(|rng| rng.next_u64())
+}
+
+/// Fill `buf` with random bytes.
+pub fn fill_bytes(buf: &mut [u8]) {
+    with_hart_rng(|rng| {
+        for chunk in buf.chunks_mut(8) {
+            chunk.copy_from_slice(&rng.next_u64().to_ne_bytes()[..chunk.len()]);
+        }
+    })
+}
+
+/// A random number in `[0, bound)`, `bound` not 0
+pub fn random_below(bound: usize) -> usize {
+    assert!(bound > 0);
+    // the bias of the modulo is at most `bound / 2^64`
+    (random_u64() % bound as u64) as usize
+}
+
+/// A random page aligned offset below `pages` pages, to move the base of
+/// a stack or of the mmap area by. 0 for no page.
+pub fn random_page_offset(pages: usize) -> usize {
+    if pages == 0 {
+        return 0;
+    }
+    random_below(pages) * PAGE_SIZE
+}
+
+/// Fill `len` bytes at `buf` with random bytes.
+///
+/// Never blocks: the generators are seeded at their first use, `flags`
+/// are accepted and have no effect. At most `MAX_GETRANDOM` bytes are
+/// filled at once.
+///
+/// # Returns
+/// - The number of bytes filled
+/// - `-EINVAL` for unknown `flags`
+/// - `-EFAULT` if the buffer is not mapped writable
+#[syscall_register(SYSCALL_GETRANDOM)]
+pub fn sys_getrandom(buf: *mut u8, len: usize, flags: u32) -> isize {
+    if flags & !(GRND_NONBLOCK | GRND_RANDOM | GRND_INSECURE) != 0 {
+        return Errno::EINVAL.as_ret();
+    }
+    let len = len.min(MAX_GETRANDOM);
+    let token = {
+        let current_task = current_task().unwrap();
+        let task = current_task.lock();
+        let mut memory_set = task.user_res.as_ref().unwrap().memory_set.lock();
+        // the buffer is accessed through the page table, lazy pages don't fault
+        if memory_set.populate_range(buf as usize, len).is_err() {
+            return Errno::EFAULT.as_ret();
+        }
+        memory_set.token()
+    };
+    let mut bytes = vec![0u8; len];
+    fill_bytes(&mut bytes);
+    match copy_to_user(token, buf, &bytes) {
+        Ok(()) => len as isize,
+        Err(_) => Errno::EFAULT.as_ret(),
+    }
+}
+
+#[kernel_test]
+fn random_test() {
+    // reference output of xoshiro256** for the state [1, 2, 3, 4]
+    let mut rng = Xoshiro256 { state: [1, 2, 3, 4], outputs: 0 };
+    assert_eq!(rng.next_u64(), 11520);
+    assert_eq!(rng.next_u64(), 0);
+    assert_eq!(rng.next_u64(), 1509978240);
+
+    let mut bytes = [0u8; 13];
+    fill_bytes(&mut bytes);
+    assert!(bytes.iter().any(|&byte| byte != 0));
+    assert_ne!(random_u64(), random_u64());
+    for _ in 0..64 {
+        let offset = random_page_offset(4);
+        assert!(offset < 4 * PAGE_SIZE && offset % PAGE_SIZE == 0);
+    }
+    assert_eq!(random_page_offset(0), 0);
+}
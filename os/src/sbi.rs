@@ -49,8 +49,28 @@ pub fn console_putchar(c: usize) {
 }
 
 
+/// Reads a character from the console.
+///
+/// This function is a wrapper around the deprecated `sbi_rt::legacy::console_getchar`
+/// function. It returns the received character as a `usize`, or `usize::MAX`
+/// (`-1` from the SBI) when no character is available.
+///
+/// # Example
+///
+/// ```rust
+/// let c = sbi::console_getchar();
+/// ```
+pub fn console_getchar() -> usize {
+    #[allow(deprecated)]
+    sbi_rt::legacy::console_getchar()
+}
+
+
 /// Initiates a system shutdown.
 ///
+/// This is the raw SBI call, kernel code should use `crate::shutdown::shutdown`
+/// so that the registered shutdown hooks run first.
+///
 /// This function performs a system reset, with the option to indicate a failure condition.
 /// If `failure` is `false`, the system resets without any reason. If `failure` is `true`, 
 /// it signals a system failure during the reset process.
@@ -106,3 +126,31 @@ pub fn set_timer(timer: usize) {
     sbi_rt::set_timer(timer as _);
 }
 
+
+
+/// Starts the stopped hart `hart_id` in S-mode at `start_addr`, with paging off.
+///
+/// The hart begins with its id in `a0` and `opaque` in `a1`.
+/// Returns the SBI error code if the hart couldn't be started.
+pub fn hart_start(hart_id: usize, start_addr: usize, opaque: usize) -> Result<(), isize> {
+    let ret = sbi_rt::hart_start(hart_id, start_addr, opaque);
+    if ret.error == 0 {
+        Ok(())
+    } else {
+        Err(ret.error as isize)
+    }
+}
+
+
+/// Raises a supervisor software interrupt on every hart of `hart_mask`,
+/// bit `i` standing for hart `i`.
+///
+/// Returns the SBI error code if the IPIs couldn't be sent.
+pub fn send_ipi(hart_mask: usize) -> Result<(), isize> {
+    let ret = sbi_rt::send_ipi(hart_mask, 0);
+    if ret.error == 0 {
+        Ok(())
+    } else {
+        Err(ret.error as isize)
+    }
+}
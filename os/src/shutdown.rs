@@ -0,0 +1,106 @@
+//! Orderly shutdown
+//!
+//! Every way out of the kernel (a normal power off, the end of the test
+//! run, a panic) goes through [`shutdown`] or [`panic_shutdown`], which run
+//! the hooks subsystems registered with the
+//! [`shutdown_hook`](os_macros::shutdown_hook) attribute before resetting.
+//!
+//! Hooks run in ascending `priority`, by convention:
+//! - `0..100`: filesystems and caches, e.g. syncing dirty blocks
+//! - `100..200`: drivers, e.g. quiescing devices
+//! - `200..`: diagnostics, e.g. flushing trace buffers
+//!
+//! After a panic only `panic_safe` hooks run: the panicking code may hold
+//! any lock, so those hooks must not take locks or allocate.
+//!
+//! ```rust
+//! #[shutdown_hook(priority = 10)]
+//! fn sync_block_cache() {
+//!     block_cache_sync_all();
+//! }
+//! ```
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{io::console, sbi};
+
+/// Type of a shutdown hook handler.
+pub type ShutdownHandler = fn();
+
+/// A hook registered in the `.shutdown_hooks` link section.
+#[repr(C)]
+pub struct ShutdownHook {
+    /// The handler's name, for the log
+    pub name: &'static str,
+    /// Lower runs first
+    pub priority: u32,
+    /// Whether the hook may run after a panic
+    pub panic_safe: bool,
+    /// The function run at shutdown
+    pub handler: ShutdownHandler,
+}
+
+/// Set by the first shutdown, a panic inside a hook skips the hooks left.
+static SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// Returns all hooks collected by the linker, in link order.
+fn hooks() -> &'static [ShutdownHook] {
+    extern "C" {
+        // Linker-provided symbols marking start/end of registration section
+        static __shutdown_hooks_start: ShutdownHook;
+        static __shutdown_hooks_end: ShutdownHook;
+    }
+
+    unsafe {
+        let start = &__shutdown_hooks_start as *const ShutdownHook;
+        let end = &__shutdown_hooks_end as *const ShutdownHook;
+        let count = (end as usize - start as usize) / core::mem::size_of::<ShutdownHook>();
+        core::slice::from_raw_parts(start, count)
+    }
+}
+
+/// Runs the hooks by ascending priority, ties in link order.
+///
+/// Doesn't allocate: the heap lock may be held when panicking.
+fn run_hooks(panicking: bool) {
+    let hooks = hooks();
+    // (priority, index) of the last hook run
+    let mut last: Option<(u32, usize)> = None;
+    loop {
+        let next = hooks
+            .iter()
+            .enumerate()
+            .filter(|(_, hook)| hook.panic_safe || !panicking)
+            .map(|(index, hook)| (hook.priority, index))
+            .filter(|&key| last.map_or(true, |last| key > last))
+            .min();
+
+        let Some(key @ (_, index)) = next else {
+            break;
+        };
+        let hook = &hooks[index];
+        if !panicking {
+            log::info!("shutdown hook `{}` (priority {})", hook.name, hook.priority);
+        }
+        (hook.handler)();
+        last = Some(key);
+    }
+}
+
+/// Runs every shutdown hook, then powers off.
+pub fn shutdown(failure: bool) -> ! {
+    if !SHUTTING_DOWN.swap(true, Ordering::AcqRel) {
+        run_hooks(false);
+    }
+    console::flush_all();
+    sbi::shutdown(failure)
+}
+
+/// Runs the panic-safe shutdown hooks, then powers off with a failure.
+pub fn panic_shutdown() -> ! {
+    if !SHUTTING_DOWN.swap(true, Ordering::AcqRel) {
+        run_hooks(true);
+    }
+    console::flush_all();
+    sbi::shutdown(true)
+}
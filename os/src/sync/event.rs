@@ -0,0 +1,60 @@
+//! Event
+//!
+//! A [`WaitQueue`] paired with a generation counter, for conditions that
+//! can't be checked under a lock the notifier also takes.
+//!
+//! A waiter snapshots the generation, checks its condition with whatever
+//! locks it needs, and only sleeps if nothing was notified in between:
+//!
+//! ```rust
+//! loop {
+//!     let seen = event.generation();
+//!     if let Some(result) = check() {
+//!         return result;
+//!     }
+//!     event.wait(seen);
+//! }
+//! ```
+//!
+//! The notifier bumps the generation before waking, so a notify racing
+//! with `check` makes `wait` return at once instead of being lost.
+
+use crate::task::WaitQueue;
+
+use super::spin::mutex::IRQSpinLock;
+
+type Mutex<T> = IRQSpinLock<T>;
+
+pub struct Event {
+    generation: Mutex<usize>,
+    waiters: WaitQueue,
+}
+
+impl Event {
+    pub const fn new() -> Self {
+        Self {
+            generation: Mutex::new(0),
+            waiters: WaitQueue::new(),
+        }
+    }
+
+    pub fn generation(&self) -> usize {
+        *self.generation.lock()
+    }
+
+    /// Block until the next notify, unless one already happened since `seen`.
+    pub fn wait(&self, seen: usize) {
+        let generation = self.generation.lock();
+        if *generation != seen {
+            return;
+        }
+        // released once queued, a notify can't slip in before the sleep
+        self.waiters.sleep_on_with(move || drop(generation));
+    }
+
+    /// Wake every waiter, returns how many were woken.
+    pub fn notify(&self) -> usize {
+        *self.generation.lock() += 1;
+        self.waiters.wake_all()
+    }
+}
@@ -0,0 +1,126 @@
+//! Futex
+//!
+//! Waiting on a user word: [`wait`] sleeps only while the word still holds
+//! the value the caller expects, [`wake`] wakes the tasks sleeping on it.
+//! User space does the fast path with atomics and only calls in to block.
+//!
+//! A word is identified by its address space (the `satp` token) and its
+//! physical address. Every word being waited on has its own wait queue,
+//! found through a fixed hash of buckets. The bucket lock is held from
+//! the value check until the waiter is queued, a [`wake`] in between
+//! can't be lost.
+
+use alloc::{sync::Arc, vec::Vec};
+
+use crate::{
+    mm::{address::{PhysAddr, VirtAddr}, page_table::PageTable, user_ptr::UserPtr},
+    syscall::error::Errno,
+    task::WaitQueue,
+};
+
+use super::spin::mutex::IRQSpinLock;
+
+type Mutex<T> = IRQSpinLock<T>;
+
+const FUTEX_BUCKETS: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FutexKey {
+    token: usize,
+    pa: usize,
+}
+
+impl FutexKey {
+    /// Fails if `uaddr` is misaligned or not mapped.
+    fn new(token: usize, uaddr: usize) -> Result<Self, Errno> {
+        if uaddr % core::mem::size_of::<u32>() != 0 {
+            return Err(Errno::EINVAL);
+        }
+        let va = VirtAddr::from(uaddr);
+        let pte = PageTable::from_token(token)
+            .find_pte_by_vpn(va.down_to_vpn())
+            .filter(|pte| pte.is_valid() && pte.is_user())
+            .ok_or(Errno::EFAULT)?;
+        let frame: PhysAddr = pte.ppn().into();
+        Ok(Self {
+            token,
+            pa: usize::from(frame) + va.page_offset(),
+        })
+    }
+
+    fn bucket(&self) -> &'static Mutex<Bucket> {
+        // words are 4 bytes aligned, the low bits carry nothing
+        &BUCKETS[(self.pa >> 2 ^ self.token) % FUTEX_BUCKETS]
+    }
+}
+
+/// The words of a bucket being waited on
+type Bucket = Vec<(FutexKey, Arc<WaitQueue>)>;
+
+static BUCKETS: [Mutex<Bucket>; FUTEX_BUCKETS] = [const { Mutex::new(Vec::new()) }; FUTEX_BUCKETS];
+
+/// Sleep on the word at `uaddr` if it holds `expected`, until `deadline`
+/// (see [`get_time`](crate::timer::get_time)) if there is one.
+///
+/// Returns once woken, at once with `EAGAIN` if the word changed, or with
+/// `ETIMEDOUT` once the deadline passed.
+pub fn wait(token: usize, uaddr: usize, expected: u32, deadline: Option<usize>) -> Result<(), Errno> {
+    let key = FutexKey::new(token, uaddr)?;
+    let mut bucket = key.bucket().lock();
+
+    let value = UserPtr::new(token, uaddr as *const u32)
+        .read()
+        .map_err(|_| Errno::EFAULT)?;
+    if value != expected {
+        return Err(Errno::EAGAIN);
+    }
+
+    let queue = match bucket.iter().find(|(waited, _)| *waited == key) {
+        Some((_, queue)) => queue.clone(),
+        None => {
+            let queue = Arc::new(WaitQueue::new());
+            bucket.push((key, queue.clone()));
+            queue
+        }
+    };
+    let Some(deadline) = deadline else {
+        queue.sleep_on_with(move || drop(bucket));
+        return Ok(());
+    };
+    if !queue.sleep_on_until(deadline, move || drop(bucket)) {
+        return Ok(());
+    }
+
+    // nobody woke the queue, it may be left empty
+    let mut bucket = key.bucket().lock();
+    if let Some(index) = bucket
+        .iter()
+        .position(|(waited, waiting)| *waited == key && Arc::ptr_eq(waiting, &queue))
+    {
+        if queue.is_empty() {
+            bucket.swap_remove(index);
+        }
+    }
+    Err(Errno::ETIMEDOUT)
+}
+
+/// Wake at most `count` tasks sleeping on the word at `uaddr`.
+///
+/// Returns how many were woken.
+pub fn wake(token: usize, uaddr: usize, count: usize) -> Result<usize, Errno> {
+    let key = FutexKey::new(token, uaddr)?;
+    let mut bucket = key.bucket().lock();
+
+    let Some(index) = bucket.iter().position(|(waited, _)| *waited == key) else {
+        return Ok(0);
+    };
+    let queue = &bucket[index].1;
+    let mut woken = 0;
+    while woken < count && queue.wake_one() {
+        woken += 1;
+    }
+    if queue.is_empty() {
+        bucket.swap_remove(index);
+    }
+    Ok(woken)
+}
@@ -0,0 +1,272 @@
+//! Lock dependency tracker, built with `LOCKDEP=1` (the `lockdep` feature)
+//!
+//! Every [`SpinLock`](super::spin::mutex::SpinLock) and
+//! [`IRQSpinLock`](super::spin::mutex::IRQSpinLock) reports here. Each hart
+//! keeps the stack of the locks it holds; taking lock `B` while holding
+//! `A` records the edge `A -> B`, with the backtraces of both acquisitions.
+//! A new edge closing a cycle (`B -> ... -> A` was seen before) is a lock
+//! order violation, which may deadlock once two harts (or a task and an
+//! interrupt) race: both chains are printed, and tracking stops.
+//!
+//! Locks are told apart by address, a lock that is dropped takes its edges
+//! with it. Checks happen before spinning, a report comes out even if the
+//! acquisition then deadlocks.
+//!
+//! Nothing here allocates, or takes a tracked lock: the heap and the
+//! console are behind tracked locks themselves. Their acquisitions from
+//! within the tracker (printing a report) are not tracked.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::{
+    interupt::InterruptController,
+    println,
+    processor::{current_processor_id, CPU_NUM},
+    tools::{backtrace::trace_into, symbols::Symbolized},
+};
+
+/// Return addresses kept per acquisition, the innermost few are the
+/// tracker's and the lock's own
+const DEPTH: usize = 10;
+/// Locks a hart holds at once
+const MAX_HELD: usize = 32;
+const MAX_EDGES: usize = 512;
+
+type Trace = [usize; DEPTH];
+
+#[derive(Clone, Copy)]
+struct Held {
+    lock: usize,
+    trace: Trace,
+}
+
+#[derive(Clone, Copy)]
+struct Edge {
+    from: usize,
+    to: usize,
+    /// Where `from` was taken
+    from_trace: Trace,
+    /// Where `to` was taken while `from` was held
+    to_trace: Trace,
+}
+
+struct State {
+    held: [[Held; MAX_HELD]; CPU_NUM],
+    depth: [usize; CPU_NUM],
+    edges: [Edge; MAX_EDGES],
+    edge_count: usize,
+    /// Scratch of the path search: the edge each edge was reached from,
+    /// itself for the first one of a path
+    reached_by: [u16; MAX_EDGES],
+}
+
+const NO_EDGE: u16 = u16::MAX;
+
+/// Not a tracked lock, it would report to itself
+static STATE: spin::Mutex<State> = spin::Mutex::new(State {
+    held: [[Held { lock: 0, trace: [0; DEPTH] }; MAX_HELD]; CPU_NUM],
+    depth: [0; CPU_NUM],
+    edges: [Edge { from: 0, to: 0, from_trace: [0; DEPTH], to_trace: [0; DEPTH] }; MAX_EDGES],
+    edge_count: 0,
+    reached_by: [NO_EDGE; MAX_EDGES],
+});
+
+/// Cleared by the first report
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// The hart is in the tracker, the locks it takes meanwhile are not tracked
+static IN_LOCKDEP: [AtomicBool; CPU_NUM] = [const { AtomicBool::new(false) }; CPU_NUM];
+
+/// Run `f` on the hart running this, unless tracking is off or the hart is
+/// in the tracker already. Interrupts are off throughout, `f` can't be
+/// interrupted by another hook on the same hart.
+fn tracked(f: impl FnOnce(usize)) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    InterruptController::intr_disable_nested();
+    let hart: usize = current_processor_id().into();
+    if !IN_LOCKDEP[hart].swap(true, Ordering::Acquire) {
+        f(hart);
+        IN_LOCKDEP[hart].store(false, Ordering::Release);
+    }
+    InterruptController::intr_enable_nested();
+}
+
+fn backtrace() -> Trace {
+    let mut trace = [0; DEPTH];
+    trace_into(&mut trace);
+    trace
+}
+
+/// `lock` is about to be taken (spinning), check it against the locks the
+/// hart holds.
+pub fn before_acquire(lock: usize) {
+    tracked(|hart| {
+        let trace = backtrace();
+        let mut state = STATE.lock();
+        for index in 0..state.depth[hart] {
+            let held = state.held[hart][index];
+            if held.lock == lock || state.find_edge(held.lock, lock).is_some() {
+                continue;
+            }
+            // the other way round already, through any number of locks
+            if let Some(last) = state.find_path(lock, held.lock) {
+                ENABLED.store(false, Ordering::Relaxed);
+                state.report(held, lock, &trace, last);
+                return;
+            }
+            if state.edge_count == MAX_EDGES {
+                ENABLED.store(false, Ordering::Relaxed);
+                println!("[lockdep] edge table full, tracking stops");
+                return;
+            }
+            let count = state.edge_count;
+            state.edges[count] = Edge {
+                from: held.lock,
+                to: lock,
+                from_trace: held.trace,
+                to_trace: trace,
+            };
+            state.edge_count += 1;
+        }
+    });
+}
+
+/// `lock` was taken, by a spin or a successful `try_lock`.
+///
+/// A `try_lock` can't deadlock, it adds no edge of its own: only the
+/// locks taken while it is held are checked against it.
+pub fn acquired(lock: usize) {
+    tracked(|hart| {
+        let trace = backtrace();
+        let mut state = STATE.lock();
+        let depth = state.depth[hart];
+        if depth == MAX_HELD {
+            ENABLED.store(false, Ordering::Relaxed);
+            println!("[lockdep] more than {} locks held on hart {}, tracking stops", MAX_HELD, hart);
+            return;
+        }
+        state.held[hart][depth] = Held { lock, trace };
+        state.depth[hart] += 1;
+    });
+}
+
+/// `lock` was released, maybe not in the reverse order of acquisition.
+pub fn released(lock: usize) {
+    tracked(|hart| {
+        let mut state = STATE.lock();
+        // a task may have been preempted with a lock held, and moved
+        let harts = core::iter::once(hart).chain((0..CPU_NUM).filter(|&other| other != hart));
+        for hart in harts {
+            let depth = state.depth[hart];
+            if let Some(index) = state.held[hart][..depth].iter().rposition(|held| held.lock == lock) {
+                state.held[hart].copy_within(index + 1..depth, index);
+                state.depth[hart] -= 1;
+                return;
+            }
+        }
+    });
+}
+
+/// `lock` is being dropped, another lock may take its address.
+pub fn forget(lock: usize) {
+    tracked(|_| {
+        let mut state = STATE.lock();
+        let mut index = 0;
+        while index < state.edge_count {
+            let edge = state.edges[index];
+            if edge.from == lock || edge.to == lock {
+                let last = state.edge_count - 1;
+                state.edges[index] = state.edges[last];
+                state.edge_count = last;
+            } else {
+                index += 1;
+            }
+        }
+    });
+}
+
+impl State {
+    fn find_edge(&self, from: usize, to: usize) -> Option<usize> {
+        self.edges[..self.edge_count]
+            .iter()
+            .position(|edge| edge.from == from && edge.to == to)
+    }
+
+    /// Breadth-first search of a path `from -> ... -> to`, returns the
+    /// index of its last edge, the others are found through `reached_by`.
+    fn find_path(&mut self, from: usize, to: usize) -> Option<usize> {
+        let count = self.edge_count;
+        self.reached_by[..count].fill(NO_EDGE);
+        // the edges of the frontier are the ones leaving the locks reached
+        // last, an edge is reached once: the search ends
+        let mut frontier_start = 0;
+        let mut reached: [u16; MAX_EDGES] = [0; MAX_EDGES];
+        let mut reached_count = 0;
+        for (index, edge) in self.edges[..count].iter().enumerate() {
+            if edge.from == from {
+                self.reached_by[index] = index as u16;
+                reached[reached_count] = index as u16;
+                reached_count += 1;
+            }
+        }
+        while frontier_start < reached_count {
+            let current = reached[frontier_start] as usize;
+            frontier_start += 1;
+            let lock = self.edges[current].to;
+            if lock == to {
+                return Some(current);
+            }
+            for index in 0..count {
+                if self.edges[index].from == lock && self.reached_by[index] == NO_EDGE {
+                    self.reached_by[index] = current as u16;
+                    reached[reached_count] = index as u16;
+                    reached_count += 1;
+                }
+            }
+        }
+        None
+    }
+
+    fn report(&self, held: Held, lock: usize, trace: &Trace, last: usize) {
+        println!("[lockdep] possible circular locking dependency on hart {}", usize::from(current_processor_id()));
+        println!("[lockdep] taking {:#x} while holding {:#x}:", lock, held.lock);
+        print_trace("  held, taken at", &held.trace);
+        print_trace("  taking, at", trace);
+        println!("[lockdep] but the opposite order was seen before (last link first):");
+        let mut index = last;
+        loop {
+            let edge = &self.edges[index];
+            println!("  {:#x} -> {:#x}", edge.from, edge.to);
+            print_trace("    first taken at", &edge.from_trace);
+            print_trace("    then at", &edge.to_trace);
+            let previous = self.reached_by[index] as usize;
+            if previous == index {
+                break;
+            }
+            index = previous;
+        }
+    }
+}
+
+/// Print the locks hart `hart` holds, for a lockup report. Skipped if the
+/// tracker is busy, the hart may be stuck in it.
+pub fn print_held(hart: usize) {
+    let Some(state) = STATE.try_lock() else {
+        println!("[lockdep] busy, locks held by hart {} unknown", hart);
+        return;
+    };
+    println!("[lockdep] hart {} holds {} lock(s):", hart, state.depth[hart]);
+    for held in &state.held[hart][..state.depth[hart]] {
+        println!("  {:#x}", held.lock);
+        print_trace("    taken at", &held.trace);
+    }
+}
+
+fn print_trace(what: &str, trace: &Trace) {
+    println!("{}:", what);
+    for ra in trace.iter().take_while(|&&ra| ra != 0) {
+        println!("      ra={:#x} {}", ra, Symbolized(*ra));
+    }
+}
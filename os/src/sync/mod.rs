@@ -1,6 +1,11 @@
 mod uniprocessor;
 pub mod spin;
 pub mod rw;
+pub mod event;
+pub mod futex;
+#[cfg(feature = "lockdep")]
+pub mod lockdep;
+mod syscall;
 
 
 // pub use uniprocessor::UPSafeCell;
\ No newline at end of file
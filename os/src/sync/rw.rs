@@ -7,6 +7,8 @@
 use core::sync::atomic::{AtomicU32, Ordering};
 use lock_api::GuardSend;
 
+use super::spin::backoff::Backoff;
+
 /// The raw implementation of a readers-writer lock.
 ///
 /// Uses an atomic u32 to track state:
@@ -60,11 +62,12 @@ unsafe impl lock_api::RawRwLock for RawRwLock {
     /// 3. Use Acquire ordering to ensure subsequent reads see the protected data
     fn lock_shared(&self) {
         let mut readers;
+        let mut backoff = Backoff::new();
         loop {
             readers = self.0.load(Ordering::Relaxed);
             // Wait if a writer holds the lock (low 16 bits != 0)
             if readers & 0xFFFF != 0 {
-                core::hint::spin_loop();
+                backoff.spin();
                 continue;
             }
             // Attempt to increment reader count (high 16 bits +1)
@@ -104,8 +107,9 @@ unsafe impl lock_api::RawRwLock for RawRwLock {
     /// 2. Set the writer flag (low 16 bits = 1)
     /// 3. Use Acquire ordering to ensure subsequent reads/writes see the protected data
     fn lock_exclusive(&self) {
+        let mut backoff = Backoff::new();
         while !self.try_lock_exclusive() {
-            core::hint::spin_loop();
+            backoff.spin();
         }
     }
 
@@ -0,0 +1,30 @@
+//! Exponential backoff for spin loops
+//!
+//! Every failed attempt doubles the pause before the next one, up to a
+//! cap: contending harts stop hammering the cache line of the lock, and
+//! the holder gets it back sooner to release it.
+
+use core::hint::spin_loop;
+
+/// log2 of the longest pause, in spin hints
+const SPIN_LIMIT: u32 = 6;
+
+pub struct Backoff {
+    step: u32,
+}
+
+impl Backoff {
+    pub const fn new() -> Self {
+        Self { step: 0 }
+    }
+
+    /// Pause for the current step, then lengthen the next pause.
+    pub fn spin(&mut self) {
+        for _ in 0..1 << self.step {
+            spin_loop();
+        }
+        if self.step < SPIN_LIMIT {
+            self.step += 1;
+        }
+    }
+}
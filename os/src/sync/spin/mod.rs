@@ -8,8 +8,8 @@
 //!   ▶ Prevents thread starvation at the cost of slightly higher latency
 //! - [x] [`IRQSpinLock`] - Basic spinlock implementation  
 //!     - [x] Core locking functionality (`lock()`, `try_lock()`)
-//!     - [ ] Backoff strategy optimization  
-//!       ▶ Exponential backoff for high-contention scenarios
+//!     - [x] Backoff strategy optimization  
+//!       ▶ Exponential backoff for high-contention scenarios, see [`backoff`]
 //! - [x] CpuSpinLock
 //!
 //! ### Blocking Locks
@@ -38,6 +38,7 @@
 //!   - Long-running operations (>1µs)
 //! - IRQ safety requirements marked with `#[interrupt_safe]`
 
+pub mod backoff;
 pub mod mutex;
 pub mod ticket;
 
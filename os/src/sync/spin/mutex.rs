@@ -8,6 +8,8 @@ use core::{sync::atomic::{AtomicBool, AtomicUsize, Ordering}};
 use lock_api::{GuardSend, RawMutex};
 use crate::{interupt::InterruptController, processor::current_processor_id};
 
+use super::backoff::Backoff;
+
 /// A mutual exclusion lock based on spinning (busy-waiting)
 ///
 /// This is a basic spinlock that provides mutually exclusive access to data
@@ -64,6 +66,18 @@ impl RawSpinLock {
     /// Sentinel value indicating no current holder
     const NO_HOLDER: AtomicUsize = AtomicUsize::new(usize::MAX);
 
+    fn try_lock_raw(&self) -> bool {
+        self.locked
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    /// The lock as the lock dependency tracker knows it
+    #[cfg(feature = "lockdep")]
+    fn lockdep_key(&self) -> usize {
+        self as *const Self as usize
+    }
+
     #[cfg(debug_assertions)]
     /// Check for potential deadlock situations in debug mode
     ///
@@ -82,6 +96,13 @@ impl RawSpinLock {
     }
 }
 
+#[cfg(feature = "lockdep")]
+impl Drop for RawSpinLock {
+    fn drop(&mut self) {
+        crate::sync::lockdep::forget(self.lockdep_key());
+    }
+}
+
 unsafe impl RawMutex for RawSpinLock {
     const INIT: RawSpinLock = RawSpinLock { 
         locked: AtomicBool::new(false),
@@ -95,16 +116,26 @@ unsafe impl RawMutex for RawSpinLock {
 
     /// Acquire the spinlock, spinning until available
     ///
-    /// This will busy-wait while the lock is held by another thread.
-    /// In debug builds, it also checks for recursive locking attempts.
+    /// This will busy-wait while the lock is held by another thread, with
+    /// exponential backoff. In debug builds, it also checks for recursive locking attempts.
     fn lock(&self) {
         log::debug!("accquiring lock");
 
         #[cfg(debug_assertions)]
         self.check_dead_lock();
         
-        while !self.try_lock() {
-            core::hint::spin_loop()
+        #[cfg(feature = "lockdep")]
+        crate::sync::lockdep::before_acquire(self.lockdep_key());
+
+        let mut backoff = Backoff::new();
+        while !self.try_lock_raw() {
+            // only read while it is held, a failed compare-exchange would
+            // take the line exclusive
+            while self.locked.load(Ordering::Relaxed) {
+                // the holder may be waiting for this hart to flush its TLB
+                crate::processor::ipi::poll_tlb_flush();
+                backoff.spin();
+            }
         }
         
         #[cfg(debug_assertions)]
@@ -128,9 +159,12 @@ unsafe impl RawMutex for RawSpinLock {
     ///
     /// Returns `true` if the lock was acquired, `false` otherwise.
     fn try_lock(&self) -> bool {
-        self.locked
-            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
-            .is_ok()
+        let locked = self.try_lock_raw();
+        #[cfg(feature = "lockdep")]
+        if locked {
+            crate::sync::lockdep::acquired(self.lockdep_key());
+        }
+        locked
     }
 
     /// Release the lock
@@ -138,6 +172,8 @@ unsafe impl RawMutex for RawSpinLock {
     /// # Safety
     /// - Must only be called when the lock is held by the current thread
     unsafe fn unlock(&self) {
+        #[cfg(feature = "lockdep")]
+        crate::sync::lockdep::released(self.lockdep_key());
         self.locked.store(false, Ordering::Release);
         #[cfg(debug_assertions)]
         {
@@ -147,6 +183,78 @@ unsafe impl RawMutex for RawSpinLock {
     }
 }
 
+/// Who acquired an [`IRQSpinLock`], checked on release (debug only)
+///
+/// A guard released by another task or on another hart than the one that
+/// acquired it has crossed a schedule boundary: the interrupt nesting count
+/// of one processor and the critical section of one task no longer match.
+/// The scheduler does this on purpose when it hands a task lock over
+/// `__switch`, such hand-offs are marked with [`sanction_handoff`].
+#[cfg(debug_assertions)]
+struct LockOwner {
+    /// Tid of the acquiring task, `NO_TASK` for the scheduler loop
+    task: AtomicUsize,
+    hart: AtomicUsize,
+    /// Set by [`sanction_handoff`], the next release is not checked
+    handoff: AtomicBool,
+}
+
+#[cfg(debug_assertions)]
+impl LockOwner {
+    const NO_TASK: usize = usize::MAX;
+
+    const fn new() -> Self {
+        Self {
+            task: AtomicUsize::new(Self::NO_TASK),
+            hart: AtomicUsize::new(usize::MAX),
+            handoff: AtomicBool::new(false),
+        }
+    }
+
+    fn current() -> (usize, usize) {
+        let task = crate::processor::get_current_processor()
+            .get_current_task()
+            .map_or(Self::NO_TASK, |task| task.get_tid().into());
+        (task, current_processor_id().into())
+    }
+
+    fn record(&self) {
+        let (task, hart) = Self::current();
+        self.task.store(task, Ordering::Relaxed);
+        self.hart.store(hart, Ordering::Relaxed);
+        self.handoff.store(false, Ordering::Relaxed);
+    }
+
+    fn check_release(&self) {
+        if self.handoff.swap(false, Ordering::Relaxed) {
+            return;
+        }
+        let (task, hart) = Self::current();
+        let (owner_task, owner_hart) = (self.task.load(Ordering::Relaxed), self.hart.load(Ordering::Relaxed));
+        if task != owner_task || hart != owner_hart {
+            let name = |task: usize| if task == Self::NO_TASK { alloc::string::String::from("scheduler") } else { alloc::format!("task {}", task) };
+            panic!(
+                "IRQSpinLock guard crossed a schedule boundary: acquired by {} on hart {}, released by {} on hart {} \
+                (hand a guard over `__switch` with `sanction_handoff`)",
+                name(owner_task), owner_hart, name(task), hart
+            );
+        }
+    }
+}
+
+/// Mark `guard` as deliberately handed over a context switch.
+///
+/// Its release may then happen on another task (or hart) without tripping
+/// the debug ownership check. Only the scheduler's task lock hand-off
+/// should need this.
+#[allow(unused_variables)]
+pub fn sanction_handoff<T: ?Sized>(guard: &IRQSpinLockGuard<'_, T>) {
+    #[cfg(debug_assertions)]
+    unsafe {
+        lock_api::MutexGuard::mutex(guard).raw().owner.handoff.store(true, Ordering::Relaxed);
+    }
+}
+
 /// The raw implementation of an interrupt-disabling spinlock
 ///
 /// This wraps a [`RawSpinLock`] and adds interrupt state management,
@@ -154,11 +262,17 @@ unsafe impl RawMutex for RawSpinLock {
 pub struct RawIrqSpinlock {
     /// The underlying spinlock implementation
     inner: RawSpinLock,
+
+    #[cfg(debug_assertions)]
+    /// Acquiring task and hart, for the cross-task release check
+    owner: LockOwner,
 }
 
 unsafe impl RawMutex for RawIrqSpinlock {
     const INIT: RawIrqSpinlock = RawIrqSpinlock { 
-        inner: RawSpinLock::INIT
+        inner: RawSpinLock::INIT,
+        #[cfg(debug_assertions)]
+        owner: LockOwner::new(),
     };
     
     type GuardMarker = GuardSend;
@@ -171,6 +285,8 @@ unsafe impl RawMutex for RawIrqSpinlock {
     fn lock(&self) {
         InterruptController::intr_disable_nested();
         self.inner.lock();
+        #[cfg(debug_assertions)]
+        self.owner.record();
     }
 
     /// Attempt to acquire the lock without spinning
@@ -178,8 +294,16 @@ unsafe impl RawMutex for RawIrqSpinlock {
     /// Returns `true` if the lock was acquired, `false` otherwise.
     /// Does not modify interrupt state for failed attempts.
     fn try_lock(&self) -> bool {
-
-        self.inner.try_lock()
+        InterruptController::intr_disable_nested();
+        if self.inner.try_lock() {
+            #[cfg(debug_assertions)]
+            self.owner.record();
+            true
+        } else {
+            // keep the nesting count balanced, `unlock` won't be called
+            InterruptController::intr_enable_nested();
+            false
+        }
     }
 
     /// Release the lock and restore interrupts
@@ -187,6 +311,8 @@ unsafe impl RawMutex for RawIrqSpinlock {
     /// # Safety
     /// - Must only be called when the lock is held by the current thread
     unsafe fn unlock(&self) {
+        #[cfg(debug_assertions)]
+        self.owner.check_release();
         self.inner.unlock();
         InterruptController::intr_enable_nested();
     }
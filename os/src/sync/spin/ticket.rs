@@ -3,6 +3,8 @@ use core::sync::atomic::{AtomicUsize, Ordering};
 use lock_api::{GuardSend, RawMutex};
 use crate::{interupt::InterruptController, processor::current_processor_id};
 
+use super::backoff::Backoff;
+
 /// A ticket-based mutex that ensures FIFO ordering for lock acquisition.
 ///
 /// This provides fair synchronization by assigning each thread a "ticket"
@@ -66,10 +68,11 @@ unsafe impl RawMutex for RawTicketMutex {
         log::debug!("prepare loop");
 
         // 2. Spin until it's our turn
+        let mut backoff = Backoff::new();
         while self.now_serving.load(Ordering::Acquire) != my_ticket {
             #[cfg(debug_assertions)]
             log::debug!("lock loop");
-            core::hint::spin_loop();
+            backoff.spin();
         }
 
         
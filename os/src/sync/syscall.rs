@@ -0,0 +1,69 @@
+use os_macros::syscall_register;
+
+use crate::{
+    mm::user_ptr::UserPtr,
+    syscall::error::Errno,
+    task::current_task,
+    timer::{get_time, ns_to_cycles, TimeSpec},
+};
+
+use super::futex;
+
+/// `op` of `futex`, Linux values
+const FUTEX_WAIT: usize = 0;
+const FUTEX_WAKE: usize = 1;
+/// Every futex is private to its address space anyway
+const FUTEX_PRIVATE_FLAG: usize = 128;
+
+/// Wait on, or wake waiters of, the `u32` at `uaddr`.
+///
+/// - `FUTEX_WAIT` sleeps while `*uaddr == val`, for at most the relative
+///   `timeout` unless it is null
+/// - `FUTEX_WAKE` wakes at most `val` waiters
+///
+/// # Returns
+/// - 0 once woken for `FUTEX_WAIT`, the number of woken tasks for `FUTEX_WAKE`
+/// - `-EAGAIN` if `*uaddr != val` when `FUTEX_WAIT` is called
+/// - `-ETIMEDOUT` if the timeout expired before a wakeup
+/// - `-EINVAL` for a misaligned `uaddr`, an invalid timeout or an unknown `op`
+/// - `-EFAULT` if `uaddr` or `timeout` is not mapped
+#[syscall_register(SYSCALL_FUTEX)]
+pub fn sys_futex(uaddr: usize, op: usize, val: usize, timeout: *const TimeSpec, _uaddr2: usize, _val3: usize) -> isize {
+    let task = current_task().unwrap();
+    let token = {
+        let task_guard = task.lock();
+        let mut memory_set = task_guard.user_res.as_ref().unwrap().memory_set.lock();
+        // the word is read through the page table, a lazy page doesn't fault
+        if memory_set.populate_range(uaddr, core::mem::size_of::<u32>()).is_err() {
+            return Errno::EFAULT.as_ret();
+        }
+        if !timeout.is_null()
+            && memory_set.populate_range(timeout as usize, core::mem::size_of::<TimeSpec>()).is_err()
+        {
+            return Errno::EFAULT.as_ret();
+        }
+        memory_set.token()
+    };
+
+    let result = match op & !FUTEX_PRIVATE_FLAG {
+        FUTEX_WAIT => wait_deadline(token, timeout)
+            .and_then(|deadline| futex::wait(token, uaddr, val as u32, deadline))
+            .map(|()| 0),
+        FUTEX_WAKE => futex::wake(token, uaddr, val),
+        _ => Err(Errno::EINVAL),
+    };
+    match result {
+        Ok(ret) => ret as isize,
+        Err(errno) => errno.as_ret(),
+    }
+}
+
+/// Absolute deadline of a `FUTEX_WAIT`, from its relative `timeout`
+fn wait_deadline(token: usize, timeout: *const TimeSpec) -> Result<Option<usize>, Errno> {
+    if timeout.is_null() {
+        return Ok(None);
+    }
+    let timeout = UserPtr::new(token, timeout).read().map_err(|_| Errno::EFAULT)?;
+    let ns = timeout.to_ns().ok_or(Errno::EINVAL)?;
+    Ok(Some(get_time().saturating_add(ns_to_cycles(ns))))
+}
@@ -0,0 +1,150 @@
+//! Arguments of system calls, as their handlers take them
+//!
+//! The wrapper `#[syscall_register]` generates converts each of the six
+//! registers to the type of its parameter with [`SyscallArg`], and returns
+//! the error without calling the handler when one doesn't convert:
+//! - `usize` and `isize` take the register as it is
+//! - the 32-bit integers take it zero or sign extended, as a caller
+//!   passing an `int` or an `unsigned int` leaves it, `-EINVAL` when the
+//!   upper bits hold anything else
+//! - raw pointers and [`UserPtr`] have to point to a `T` lying in the
+//!   user half of the address space, `-EFAULT` otherwise. Null passes,
+//!   for the calls where it means "none". The lazy pages of the `T` are
+//!   faulted in, see [`populate_user`]
+//!
+//! Whether the pointed to pages are mapped is still found out by the
+//! accessors of `page_table`, when the handler copies.
+
+use alloc::string::String;
+use os_macros::kernel_test;
+
+use super::error::Errno;
+use crate::{
+    config::USER_HIGH_BIT,
+    mm::{memory_set::MemorySet, user_ptr::UserPtr, MemoryError},
+    task::{current_task, current_user_token},
+};
+
+/// End of the user half: above are the trampoline and the kernel
+pub const USER_SPACE_END: usize = 1 << USER_HIGH_BIT;
+
+/// A type a system call handler can take a register as
+pub trait SyscallArg: Sized {
+    fn from_arg(raw: usize) -> Result<Self, Errno>;
+}
+
+impl SyscallArg for usize {
+    fn from_arg(raw: usize) -> Result<Self, Errno> {
+        Ok(raw)
+    }
+}
+
+impl SyscallArg for isize {
+    fn from_arg(raw: usize) -> Result<Self, Errno> {
+        Ok(raw as isize)
+    }
+}
+
+/// Whether `raw` holds a 32-bit value, zero or sign extended
+fn is_32_bits(raw: usize) -> bool {
+    raw >> 32 == 0 || (raw as isize) >> 31 == -1
+}
+
+impl SyscallArg for u32 {
+    fn from_arg(raw: usize) -> Result<Self, Errno> {
+        if is_32_bits(raw) {
+            Ok(raw as u32)
+        } else {
+            Err(Errno::EINVAL)
+        }
+    }
+}
+
+impl SyscallArg for i32 {
+    fn from_arg(raw: usize) -> Result<Self, Errno> {
+        u32::from_arg(raw).map(|value| value as i32)
+    }
+}
+
+/// Check that `len` bytes at `addr` lie in the user half.
+pub fn check_user_range(addr: usize, len: usize) -> Result<(), Errno> {
+    match addr.checked_add(len) {
+        Some(end) if end <= USER_SPACE_END => Ok(()),
+        _ => Err(Errno::EFAULT),
+    }
+}
+
+/// Run `f` on the address space of the caller, nothing to do for a
+/// kernel task.
+fn with_memory_set(f: impl FnOnce(&mut MemorySet) -> Result<(), MemoryError>) -> Result<(), Errno> {
+    let Some(task) = current_task() else {
+        return Ok(());
+    };
+    let task = task.lock();
+    let Some(user_res) = task.user_res.as_ref() else {
+        return Ok(());
+    };
+    let result = f(&mut user_res.memory_set.lock());
+    result.map_err(|_| Errno::EFAULT)
+}
+
+/// Fault in the lazy pages of `len` bytes at `addr` in the address space
+/// of the caller.
+///
+/// The accessors of `page_table` walk the page table, where a page not
+/// backed yet looks unmapped. Takes the locks of the task and of its
+/// address space: the caller holds neither.
+pub fn populate_user(addr: usize, len: usize) -> Result<(), Errno> {
+    if addr == 0 {
+        return Ok(());
+    }
+    with_memory_set(|memory_set| memory_set.populate_range(addr, len))
+}
+
+/// The NUL-terminated string at `ptr` in the address space of the
+/// caller, its lazy pages faulted in first like [`populate_user`] does.
+pub fn read_user_str(ptr: *const u8) -> Result<String, Errno> {
+    with_memory_set(|memory_set| memory_set.populate_str(ptr as usize))?;
+    UserPtr::new(current_user_token(), ptr).read_to_string().map_err(|_| Errno::EFAULT)
+}
+
+impl<T> SyscallArg for *const T {
+    fn from_arg(raw: usize) -> Result<Self, Errno> {
+        check_user_range(raw, core::mem::size_of::<T>())?;
+        populate_user(raw, core::mem::size_of::<T>())?;
+        Ok(raw as *const T)
+    }
+}
+
+impl<T> SyscallArg for *mut T {
+    fn from_arg(raw: usize) -> Result<Self, Errno> {
+        <*const T>::from_arg(raw).map(|ptr| ptr as *mut T)
+    }
+}
+
+/// A pointer into the address space of the caller
+impl<T> SyscallArg for UserPtr<T> {
+    fn from_arg(raw: usize) -> Result<Self, Errno> {
+        let addr = <*const T>::from_arg(raw)?;
+        Ok(UserPtr::new(current_user_token(), addr))
+    }
+}
+
+#[kernel_test]
+fn syscall_arg_test() {
+    assert_eq!(u32::from_arg(0xffff_ffff), Ok(u32::MAX));
+    assert_eq!(u32::from_arg(-1isize as usize), Ok(u32::MAX));
+    assert_eq!(u32::from_arg(1 << 32), Err(Errno::EINVAL));
+    assert_eq!(i32::from_arg(-5isize as usize), Ok(-5));
+    assert_eq!(i32::from_arg(0x8000_0000), Ok(i32::MIN));
+    assert_eq!(i32::from_arg(0xdead_0000_0001), Err(Errno::EINVAL));
+    assert_eq!(isize::from_arg(usize::MAX), Ok(-1));
+
+    assert!(<*const u8>::from_arg(0).is_ok());
+    assert!(<*const u64>::from_arg(USER_SPACE_END - 8).is_ok());
+    assert_eq!(<*const u64>::from_arg(USER_SPACE_END - 4), Err(Errno::EFAULT));
+    // the trampoline, the kernel, and a range wrapping around
+    assert_eq!(<*mut u8>::from_arg(0xffff_ffff_bfff_f000), Err(Errno::EFAULT));
+    assert_eq!(<*mut [u8; 16]>::from_arg(usize::MAX - 7), Err(Errno::EFAULT));
+    assert_eq!(check_user_range(0x1000, usize::MAX), Err(Errno::EFAULT));
+}
@@ -1,11 +1,19 @@
 //! 在Unix-like系统中，系统调用通常返回-1，并将错误码放在errno中
+//!
+//! [`Errno`] is the single table of error codes and their messages, the
+//! numbers follow Linux. User programs get the messages through
+//! `sys_strerror` instead of keeping their own copy.
 
 #![allow(missing_docs)] 
 
-use strum_macros::{Display, EnumString, FromRepr};
+use os_macros::syscall_register;
+use strum_macros::{Display, EnumString, FromRepr, IntoStaticStr};
 
+use super::args::populate_user;
+use crate::{mm::page_table::copy_to_user, task::current_task};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumString, FromRepr)]
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display, EnumString, FromRepr, IntoStaticStr)]
 #[repr(i32)]
 #[strum(serialize_all = "snake_case")]
 pub enum Errno {
@@ -13,9 +21,98 @@ pub enum Errno {
     EPERM = 1,
     #[strum(serialize = "No such file or directory")]
     ENOENT = 2,
+    #[strum(serialize = "No such process")]
+    ESRCH = 3,
+    #[strum(serialize = "Interrupted system call")]
+    EINTR = 4,
+    #[strum(serialize = "Input/output error")]
+    EIO = 5,
+    #[strum(serialize = "No such device or address")]
+    ENXIO = 6,
+    #[strum(serialize = "Argument list too long")]
+    E2BIG = 7,
+    #[strum(serialize = "Exec format error")]
+    ENOEXEC = 8,
+    #[strum(serialize = "Bad file descriptor")]
+    EBADF = 9,
+    #[strum(serialize = "No child processes")]
+    ECHILD = 10,
+    #[strum(serialize = "Resource temporarily unavailable")]
+    EAGAIN = 11,
+    #[strum(serialize = "Cannot allocate memory")]
+    ENOMEM = 12,
+    #[strum(serialize = "Permission denied")]
+    EACCES = 13,
+    #[strum(serialize = "Bad address")]
+    EFAULT = 14,
+    #[strum(serialize = "Block device required")]
+    ENOTBLK = 15,
+    #[strum(serialize = "Device or resource busy")]
+    EBUSY = 16,
+    #[strum(serialize = "File exists")]
+    EEXIST = 17,
+    #[strum(serialize = "Invalid cross-device link")]
+    EXDEV = 18,
+    #[strum(serialize = "No such device")]
+    ENODEV = 19,
+    #[strum(serialize = "Not a directory")]
+    ENOTDIR = 20,
+    #[strum(serialize = "Is a directory")]
+    EISDIR = 21,
+    #[strum(serialize = "Invalid argument")]
+    EINVAL = 22,
+    #[strum(serialize = "Too many open files in system")]
+    ENFILE = 23,
+    #[strum(serialize = "Too many open files")]
+    EMFILE = 24,
+    #[strum(serialize = "Inappropriate ioctl for device")]
+    ENOTTY = 25,
+    #[strum(serialize = "Text file busy")]
+    ETXTBSY = 26,
+    #[strum(serialize = "File too large")]
+    EFBIG = 27,
+    #[strum(serialize = "No space left on device")]
+    ENOSPC = 28,
+    #[strum(serialize = "Illegal seek")]
+    ESPIPE = 29,
+    #[strum(serialize = "Read-only file system")]
+    EROFS = 30,
+    #[strum(serialize = "Too many links")]
+    EMLINK = 31,
+    #[strum(serialize = "Broken pipe")]
+    EPIPE = 32,
+    #[strum(serialize = "Numerical argument out of domain")]
+    EDOM = 33,
+    #[strum(serialize = "Numerical result out of range")]
+    ERANGE = 34,
+    #[strum(serialize = "Resource deadlock avoided")]
+    EDEADLK = 35,
+    #[strum(serialize = "File name too long")]
+    ENAMETOOLONG = 36,
+    #[strum(serialize = "No locks available")]
+    ENOLCK = 37,
     #[strum(serialize = "Function not implemented")]
     ENOSYS = 38,
-    // ...
+    #[strum(serialize = "Directory not empty")]
+    ENOTEMPTY = 39,
+    #[strum(serialize = "Too many levels of symbolic links")]
+    ELOOP = 40,
+    #[strum(serialize = "Value too large for defined data type")]
+    EOVERFLOW = 75,
+    #[strum(serialize = "Connection timed out")]
+    ETIMEDOUT = 110,
+}
+
+impl Errno {
+    /// The short human readable message, e.g. "Bad file descriptor"
+    pub fn message(self) -> &'static str {
+        self.into()
+    }
+
+    /// The value a failing syscall returns, i.e. `-errno`
+    pub fn as_ret(self) -> isize {
+        -(self as isize)
+    }
 }
 
 // 自动实现 i32 -> Errno
@@ -25,4 +122,30 @@ impl TryFrom<i32> for Errno {
     fn try_from(value: i32) -> Result<Self, Self::Error> {
         Errno::from_repr(value).ok_or(())
     }
-}
\ No newline at end of file
+}
+
+/// Copy the message of `errno` into `buf`, at most `len` bytes and not NUL terminated.
+///
+/// Returns the full length of the message, which may exceed `len`, or
+/// `-EINVAL` if `errno` is unknown. Both `errno` and `-errno` are accepted,
+/// so a raw syscall return value can be passed as is.
+#[syscall_register(SYSCALL_STRERROR)]
+pub fn sys_strerror(errno: isize, buf: *mut u8, len: usize) -> isize {
+    let Some(errno) = i32::try_from(errno.unsigned_abs())
+        .ok()
+        .and_then(Errno::from_repr)
+    else {
+        return Errno::EINVAL.as_ret();
+    };
+    let message = errno.message().as_bytes();
+    let copied = &message[..message.len().min(len)];
+    if populate_user(buf as usize, copied.len()).is_err() {
+        return Errno::EFAULT.as_ret();
+    }
+
+    let token = current_task().unwrap().lock().get_user_token();
+    match copy_to_user(token, buf, copied) {
+        Ok(()) => message.len() as isize,
+        Err(_) => Errno::EFAULT.as_ret(),
+    }
+}
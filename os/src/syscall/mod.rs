@@ -7,6 +7,7 @@ mod registry;
 
 pub mod syscall_num;
 pub mod error;
+pub mod args;
 
 pub use registry::SyscallRegistry;
 
@@ -11,25 +11,14 @@ use crate::sync::rw::RWLock;
 type SyscallHandler = unsafe extern "C" fn(args: [usize; 6]) -> isize;
 
 
+/// Slots of the table: the Linux numbers, and the ones of this kernel
+/// from `SYSCALL_TEST` (511) on
+pub const SYSCALL_TABLE_SIZE: usize = 1024;
+
 #[used] // 强制保留符号
 #[link_section = ".syscall_table"]
-pub static SYSCALL_TABLE: RWLock<[Option<SyscallHandler>; 512]> = RWLock::new([None; 512]);
-// static SYSCALL_TABLE_INNER: [Option<SyscallHandler>; 512] = [None; 512];
-
-
-
-// lazy_static! {
-
-//     /// Global system call dispatch table (statically initialized).
-//     /// 
-//     /// # Safety
-//     /// - This is mutable static data and requires unsafe access
-//     /// - Must be properly initialized before use
-//     /// - Contains 512 entries (0-511) matching standard system call numbers
-//     #[link_section = ".syscall_table"]
-//     pub static ref SYSCALL_TABLE: IRQSpinLock<[Option<SyscallHandler>; 512]> = 
-//             RWLock::new([None; 512]);
-// }
+pub static SYSCALL_TABLE: RWLock<[Option<SyscallHandler>; SYSCALL_TABLE_SIZE]> =
+    RWLock::new([None; SYSCALL_TABLE_SIZE]);
 
 /// Structure representing a registered system call entry.
 /// Used by the linker to collect all system call registrations.
@@ -53,7 +42,7 @@ pub struct SyscallRegistry {
 /// - Modifies mutable static data
 ///
 /// # Panics
-/// - If any system call number is out of bounds (>511)
+/// - If any system call number is out of bounds (>= `SYSCALL_TABLE_SIZE`)
 pub unsafe fn init() {
     log::info!("syscall init");
 
@@ -83,6 +72,7 @@ pub unsafe fn init() {
         log::debug!("registry {}th syscall", i);
 
         let entry = &*start.add(i);
+        assert!(entry.num < SYSCALL_TABLE_SIZE, "syscall {} past the table", entry.num);
         syscall_table[entry.num] = Some(entry.handler);
     }
     
@@ -3,19 +3,73 @@
 
 // use strum_macros::FromRepr;
 
+pub const SYSCALL_DUP: usize = 23;
+/// `dup3`'s number, with `dup2` semantics when both fds are the same
+pub const SYSCALL_DUP2: usize = 24;
+pub const SYSCALL_IOPRIO_SET: usize = 30;
+pub const SYSCALL_IOPRIO_GET: usize = 31;
+/// `umount2`
+pub const SYSCALL_UMOUNT: usize = 39;
+pub const SYSCALL_MOUNT: usize = 40;
+pub const SYSCALL_FTRUNCATE: usize = 46;
 pub const SYSCALL_OPEN: usize = 56;
 pub const SYSCALL_CLOSE: usize = 57;
+pub const SYSCALL_PIPE: usize = 59;
+pub const SYSCALL_LSEEK: usize = 62;
 pub const SYSCALL_READ: usize = 63;
 pub const SYSCALL_WRITE: usize = 64;
+pub const SYSCALL_SENDFILE: usize = 71;
+/// `newfstatat`, handled by `sys_stat`
+pub const SYSCALL_STAT: usize = 79;
+pub const SYSCALL_FSTAT: usize = 80;
+pub const SYSCALL_PPOLL: usize = 73;
+pub const SYSCALL_PERSONALITY: usize = 92;
 pub const SYSCALL_EXIT: usize = 93;
+pub const SYSCALL_EXIT_GROUP: usize = 94;
+pub const SYSCALL_FUTEX: usize = 98;
+pub const SYSCALL_NANOSLEEP: usize = 101;
+pub const SYSCALL_GETITIMER: usize = 102;
+pub const SYSCALL_SETITIMER: usize = 103;
+pub const SYSCALL_CLOCK_GETTIME: usize = 113;
+/// `syslog`, handled by `sys_klog`
+pub const SYSCALL_SYSLOG: usize = 116;
+pub const SYSCALL_SCHED_SETAFFINITY: usize = 122;
+pub const SYSCALL_SCHED_GETAFFINITY: usize = 123;
 pub const SYSCALL_YIELD: usize = 124;
+pub const SYSCALL_KILL: usize = 129;
+pub const SYSCALL_SIGALTSTACK: usize = 132;
+pub const SYSCALL_SIGACTION: usize = 134;
+pub const SYSCALL_SIGPROCMASK: usize = 135;
+pub const SYSCALL_SIGRETURN: usize = 139;
+pub const SYSCALL_SETPRIORITY: usize = 140;
+pub const SYSCALL_GETPRIORITY: usize = 141;
+pub const SYSCALL_GETCPU: usize = 168;
 pub const SYSCALL_GET_TIME: usize = 169;
-// pub const SYSCALL_GETPID: usize = 172;
+pub const SYSCALL_GETPID: usize = 172;
+pub const SYSCALL_GETPPID: usize = 173;
+pub const SYSCALL_GETTID: usize = 178;
 
-pub const SYSCALL_FORK: usize = 220;
+pub const SYSCALL_SHMGET: usize = 194;
+pub const SYSCALL_SHMCTL: usize = 195;
+pub const SYSCALL_SHMAT: usize = 196;
+pub const SYSCALL_SHMDT: usize = 197;
+pub const SYSCALL_BRK: usize = 214;
+pub const SYSCALL_MUNMAP: usize = 215;
+/// `clone` with no flag is `fork`, like Linux
+pub const SYSCALL_CLONE: usize = 220;
 pub const SYSCALL_EXEC: usize = 221;
+pub const SYSCALL_MMAP: usize = 222;
+pub const SYSCALL_MPROTECT: usize = 226;
+pub const SYSCALL_MINCORE: usize = 232;
 pub const SYSCALL_WAITPID: usize = 260;
+pub const SYSCALL_PRLIMIT: usize = 261;
+pub const SYSCALL_GETRANDOM: usize = 278;
 pub const SYSCALL_TEST: usize = 511;
+pub const SYSCALL_VMA_INFO: usize = 512;
+pub const SYSCALL_STRERROR: usize = 513;
+pub const SYSCALL_CAPTURE_OUTPUT: usize = 514;
+pub const SYSCALL_SYSCTL: usize = 515;
+pub const SYSCALL_SCHED_STAT: usize = 516;
 
 // #[derive(Debug, FromRepr, PartialEq, Eq)]
 // #[repr(usize)] // 指定底层类型为 usize
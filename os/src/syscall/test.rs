@@ -1,7 +1,7 @@
 use alloc::string::String;
 use os_macros::syscall_register;
 
-use crate::{mm::user_ptr::UserBuffer, println, task::current_user_token};
+use crate::{mm::{Buffer, UserBuffer}, println, task::current_user_token};
 
 /// a
 #[syscall_register(SYSCALL_TEST)]
@@ -14,10 +14,14 @@ pub fn sys_test (
     arg5: usize
 ) {
     let great_cross_page_ptr = great_cross_page_ptr as *const u8;
-    // let string_buffer = UserBuffer::new(current_user_token(), great_cross_page_ptr, great_len);
-    // let great_str:String = string_buffer.into();
-
-    // println!("{}", great_str);
+    let string_buffer = UserBuffer::new(current_user_token(), great_cross_page_ptr, great_len);
+    match string_buffer.as_ref().map(|buffer| buffer.as_bytes()) {
+        Ok(Ok(pieces)) => {
+            let great_str: String = pieces.iter().map(|piece| String::from_utf8_lossy(piece)).collect();
+            println!("{}", great_str);
+        }
+        _ => println!("sys_test: the string is not mapped readable"),
+    }
 
     println!("arg2: {}, arg3: {}, arg4: {}, arg5: {}",
         arg2, arg3, arg4, arg5
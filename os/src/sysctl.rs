@@ -0,0 +1,130 @@
+//! Kernel parameters tunable at run time
+//!
+//! Each parameter is a number, read and written by name through
+//! `sys_sysctl` (a simpler take on Linux `sysctl`) or the `sysctl`
+//! monitor command. Names are dotted paths like on Linux, see [`PARAMS`].
+use os_macros::{monitor_command, syscall_register};
+
+use crate::{
+    mm::{page_table::translated_str, user_ptr::UserPtr},
+    println,
+    processor::watchdog,
+    syscall::error::Errno,
+    task::{current_task, time_slice},
+};
+
+struct Param {
+    name: &'static str,
+    get: fn() -> usize,
+    /// Whether the value was accepted
+    set: fn(usize) -> bool,
+}
+
+static PARAMS: &[Param] = &[
+    Param {
+        name: "kernel.sched_time_slice_ms",
+        get: time_slice::slice_ms,
+        set: |ms| {
+            ms > 0 && {
+                time_slice::set_slice_ms(ms);
+                true
+            }
+        },
+    },
+    Param {
+        name: "kernel.softlockup_panic",
+        get: || watchdog::panics() as usize,
+        set: |panic| {
+            panic <= 1 && {
+                watchdog::set_panics(panic == 1);
+                true
+            }
+        },
+    },
+    Param {
+        name: "kernel.watchdog_thresh",
+        get: watchdog::thresh_s,
+        // seconds, as on Linux
+        set: |seconds| {
+            seconds <= 60 && {
+                watchdog::set_thresh_s(seconds);
+                true
+            }
+        },
+    },
+];
+
+fn find(name: &str) -> Option<&'static Param> {
+    PARAMS.iter().find(|param| param.name == name)
+}
+
+/// Read and/or write the kernel parameter of NUL-terminated `name`.
+///
+/// Its value is stored at `old` if not null, then set to the one at `new`
+/// if not null.
+///
+/// # Returns
+/// - 0 on success
+/// - `-ENOENT` for an unknown `name`
+/// - `-EINVAL` if the parameter doesn't take the value at `new`
+/// - `-EFAULT` if `old` or `new` is not mapped
+#[syscall_register(SYSCALL_SYSCTL)]
+pub fn sys_sysctl(name: *const u8, old: *mut usize, new: *const usize) -> isize {
+    let size = core::mem::size_of::<usize>();
+    let (name, token) = {
+        let task_guard = current_task().unwrap().lock();
+        let mut memory_set = task_guard.user_res.as_ref().unwrap().memory_set.lock();
+        // accessed through the page table, a lazy page doesn't fault
+        let mut unmapped = |ptr: usize| ptr != 0 && memory_set.populate_range(ptr, size).is_err();
+        if unmapped(old as usize) || unmapped(new as usize) || memory_set.populate_str(name as usize).is_err() {
+            return Errno::EFAULT.as_ret();
+        }
+        let token = memory_set.token();
+        let Ok(name) = translated_str(token, name) else {
+            return Errno::EFAULT.as_ret();
+        };
+        (name, token)
+    };
+    let Some(param) = find(&name) else {
+        return Errno::ENOENT.as_ret();
+    };
+
+    if !old.is_null() && UserPtr::new(token, old).write((param.get)()).is_err() {
+        return Errno::EFAULT.as_ret();
+    }
+    if !new.is_null() {
+        let Ok(value) = UserPtr::new(token, new).read() else {
+            return Errno::EFAULT.as_ret();
+        };
+        if !(param.set)(value) {
+            return Errno::EINVAL.as_ret();
+        }
+    }
+    0
+}
+
+#[monitor_command(name = "sysctl", help = "Show or set kernel parameters: sysctl [name] [value]")]
+fn sysctl_command(args: &[&str]) {
+    match args[1..] {
+        [] => {
+            for param in PARAMS {
+                println!("{} = {}", param.name, (param.get)());
+            }
+        }
+        [name] => match find(name) {
+            Some(param) => println!("{} = {}", param.name, (param.get)()),
+            None => println!("sysctl: unknown parameter `{}`", name),
+        },
+        [name, value] => {
+            let Some(param) = find(name) else {
+                println!("sysctl: unknown parameter `{}`", name);
+                return;
+            };
+            match value.parse() {
+                Ok(value) if (param.set)(value) => {}
+                _ => println!("sysctl: invalid value `{}`", value),
+            }
+        }
+        _ => println!("sysctl: too many arguments"),
+    }
+}
@@ -2,8 +2,9 @@ use core::sync::atomic::{ AtomicUsize, Ordering};
 
 use alloc::{sync::Arc, vec::Vec};
 use lazy_static::lazy_static;
+use os_macros::kernel_test;
 
-use crate::{config::{KERNEL_STACK_BASE, KERNEL_STACK_SIZE, PAGE_SIZE, TRAP_CONTEXT_START, USER_STACK_SIZE}, mm::{address::{PhysPageNum, VirtAddr, VirtPageNum}, map_area::MapPermission, memory_set::MemorySet, KERNEL_SPACE}, sync::spin::mutex::IRQSpinLock, trap::TrapContext};
+use crate::{config::{KERNEL_STACK_BASE, KERNEL_STACK_SIZE, KERNEL_STACK_SLOT, MAX_USER_STACKS, PAGE_SIZE, TRAP_CONTEXT_START, USER_STACK_MAX_SIZE, USER_STACK_SIZE}, mm::{address::{PhysPageNum, VirtAddr, VirtPageNum}, map_area::MapPermission, memory_set::MemorySet, KERNEL_SPACE}, sync::spin::mutex::IRQSpinLock, trap::TrapContext};
 
 
 
@@ -15,9 +16,20 @@ lazy_static! {
 
 
 
+/// `(tids, kernel stacks)` held: by live tasks, and by zombies until reaped
+pub fn ids_in_use() -> (usize, usize) {
+    (TID_ALLOCATOR.in_use(), KERNEL_STACK_ID_ALLOCATOR.in_use())
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub struct TaskID(usize);
 
+impl From<TaskID> for usize {
+    fn from(value: TaskID) -> Self {
+        value.0
+    }
+}
+
 
 pub struct TaskHandleAllocator;
 impl TaskHandleAllocator {
@@ -81,11 +93,13 @@ impl KernelStackGuard {
 
         let (bottom, top) = Self::get_position(kernel_stack_id);
 
-        KERNEL_SPACE.lock().insert_framed_area(
+        let mut kernel_space = KERNEL_SPACE.lock();
+        kernel_space.insert_framed_area(
             bottom.into(),
             top.into(), 
             MapPermission::W | MapPermission::R
         );
+        kernel_space.insert_canary_page(VirtAddr::from(bottom - PAGE_SIZE).into());
 
         Self{ id: kernel_stack_id, bottom, top }
     }
@@ -94,13 +108,28 @@ impl KernelStackGuard {
     fn get_position(kernel_stack_id: usize) -> (usize, usize) {
         // |   Trampoline   | 
         // |      ...       |
-        // |   Guard Page   | 
+        // |  Canary Page   | 
+        // |    Unmapped    | 
         // | Current KStack | -new allocate
-        let top = KERNEL_STACK_BASE - kernel_stack_id * (KERNEL_STACK_SIZE + PAGE_SIZE);
+        // |  Canary Page   | 
+        // |    Unmapped    | 
+        //
+        // Stacks take the upper half of their slot: the trap entry tells
+        // an overflowed stack by a clear KERNEL_STACK_SIZE bit of sp
+        let top = KERNEL_STACK_BASE - kernel_stack_id * KERNEL_STACK_SLOT;
         let bottom = top - KERNEL_STACK_SIZE;
         (bottom, top)
     }
 
+    /// `(bottom, top)` of the kernel stack whose canary page `va` is in
+    pub fn overflowed_stack(va: usize) -> Option<(usize, usize)> {
+        if va >= KERNEL_STACK_BASE {
+            return None;
+        }
+        let (bottom, top) = Self::get_position((KERNEL_STACK_BASE - va - 1) / KERNEL_STACK_SLOT);
+        (bottom - PAGE_SIZE <= va && va < bottom).then_some((bottom, top))
+    }
+
 
     fn get_id(&self) -> usize{
         self.id
@@ -114,7 +143,10 @@ impl KernelStackGuard {
 impl Drop for KernelStackGuard {
     fn drop(&mut self) {
         let start_va: VirtAddr = self.bottom.into();
-        KERNEL_SPACE.lock().remove_area_with_start_vpn(start_va.into());
+        let mut kernel_space = KERNEL_SPACE.lock();
+        kernel_space.remove_area_with_start_vpn(start_va.into());
+        kernel_space.remove_canary_page(VirtAddr::from(self.bottom - PAGE_SIZE).into());
+        drop(kernel_space);
         KERNEL_STACK_ID_ALLOCATOR.dealloc(self.id);
 
     }
@@ -143,6 +175,12 @@ impl TrapContextPageGuard {
         TRAP_CONTEXT_START + (tid.0 * PAGE_SIZE)
     }
 
+    /// The page the trap context of `tid` lives in, in any address space
+    #[inline(always)]
+    pub fn vpn_of(tid: TaskID) -> VirtPageNum {
+        VirtAddr::from(Self::trap_context_bottom(tid)).into()
+    }
+
     pub fn get_mut_ref(&mut self) -> &'static mut TrapContext {
         self.get_trap_ppn().get_mut()
     }
@@ -200,8 +238,33 @@ impl Drop for TrapContextPageGuard {
 pub struct UserStackAlloctor;
 
 impl UserStackAlloctor {
-    pub fn alloc(memory_set: Arc<IRQSpinLock<MemorySet>>, base: usize, id: usize) -> UserStackGuard{
-        UserStackGuard::new(memory_set, base, id)
+    /// Map a user stack in a free slot of `id_allocator`, the slot is
+    /// given back once the stack is dropped.
+    ///
+    /// `None` if all [`MAX_USER_STACKS`] slots are taken.
+    pub fn alloc(
+        memory_set: Arc<IRQSpinLock<MemorySet>>,
+        base: usize,
+        id_allocator: Arc<IRQSpinLock<RecycleAllocator>>,
+    ) -> Option<UserStackGuard> {
+        let id = id_allocator.lock().alloc();
+        // the user heap starts right above the last slot
+        if id >= MAX_USER_STACKS {
+            id_allocator.lock().dealloc(id);
+            return None;
+        }
+        Some(UserStackGuard::new(memory_set, base, id, id_allocator))
+    }
+
+    /// Take ownership of a user stack that is already mapped in `memory_set`,
+    /// e.g. the copy made by [`MemorySet::from_other_user`] during fork.
+    pub fn adopt(
+        memory_set: Arc<IRQSpinLock<MemorySet>>,
+        base: usize,
+        id: usize,
+        id_allocator: Arc<IRQSpinLock<RecycleAllocator>>,
+    ) -> UserStackGuard{
+        UserStackGuard::adopt(memory_set, base, id, id_allocator)
     }
 }
 
@@ -211,11 +274,18 @@ pub struct UserStackGuard {
     ppn: PhysPageNum,
     size: usize,
     user_stack_id: usize,
+    /// The slots of the address space, `user_stack_id` goes back there
+    id_allocator: Arc<IRQSpinLock<RecycleAllocator>>,
     memory_set: Arc<IRQSpinLock<MemorySet>>,
 }
 
 impl UserStackGuard {
-    pub fn new(memory_set: Arc<IRQSpinLock<MemorySet>>, base: usize, id: usize) ->  Self{
+    fn new(
+        memory_set: Arc<IRQSpinLock<MemorySet>>,
+        base: usize,
+        id: usize,
+        id_allocator: Arc<IRQSpinLock<RecycleAllocator>>,
+    ) ->  Self{
         let top = Self::gen_top(base, id);
 
         let bottom = top - USER_STACK_SIZE;
@@ -228,9 +298,10 @@ impl UserStackGuard {
 
 
         let mut memory_set_guard = memory_set.lock();
-        memory_set_guard.insert_framed_area(
+        memory_set_guard.insert_stack_area(
             bottom_va,
             top_va,
+            VirtAddr::from(top - USER_STACK_MAX_SIZE),
             MapPermission::U | MapPermission::W | MapPermission::R
         );
 
@@ -250,6 +321,32 @@ impl UserStackGuard {
             ppn,
             size: PAGE_SIZE,
             user_stack_id: id,
+            id_allocator,
+            memory_set
+        }
+    }
+
+    fn adopt(
+        memory_set: Arc<IRQSpinLock<MemorySet>>,
+        base: usize,
+        id: usize,
+        id_allocator: Arc<IRQSpinLock<RecycleAllocator>>,
+    ) -> Self {
+        let top = Self::gen_top(base, id);
+        let bottom_vpn: VirtPageNum = VirtAddr::from(top - USER_STACK_SIZE).into();
+
+        let ppn = memory_set
+                .lock()
+                .translate(bottom_vpn)
+                .expect("adopted user stack is not mapped")
+                .ppn();
+
+        Self {
+            vpn: bottom_vpn,
+            ppn,
+            size: PAGE_SIZE,
+            user_stack_id: id,
+            id_allocator,
             memory_set
         }
     }
@@ -259,15 +356,37 @@ impl UserStackGuard {
         self.size + usize::from(base_va)
     }
 
+    #[inline(always)]
+    pub fn get_id(&self) -> usize {
+        self.user_stack_id
+    }
+
+    /// First page of the stack in slot `id`, as mapped before any growth
+    #[inline(always)]
+    pub fn slot_bottom(base: usize, id: usize) -> VirtPageNum {
+        VirtAddr::from(Self::gen_top(base, id) - USER_STACK_SIZE).into()
+    }
+
+    /// First address above every stack slot, guard page included
+    #[inline(always)]
+    pub fn slots_end(base: usize) -> usize {
+        Self::gen_top(base, MAX_USER_STACKS) - USER_STACK_MAX_SIZE
+    }
+
+    /// A slot is a guard page, then room for the stack to grow to
+    /// `USER_STACK_MAX_SIZE`
     #[inline(always)]
     fn gen_top(base: usize, id: usize) -> usize {
-        base + (id+1)* (PAGE_SIZE + USER_STACK_SIZE)
+        base + (id+1)* (PAGE_SIZE + USER_STACK_MAX_SIZE)
     }
 }
 
 impl Drop for UserStackGuard {
     fn drop(&mut self) {
-        self.memory_set.lock().remove_area_with_start_vpn(self.vpn);
+        // the stack may have grown below `vpn`
+        self.memory_set.lock().remove_area_containing(self.vpn);
+        // only reusable once unmapped
+        self.id_allocator.lock().dealloc(self.user_stack_id);
     }
 }
 
@@ -285,6 +404,15 @@ impl RecycleAllocator {
             recycled: IRQSpinLock::new(Vec::new()),
         }
     }
+    /// An allocator with only `id` handed out, the ids below it free
+    pub fn with_taken(id: usize) -> Self {
+        RecycleAllocator {
+            current: AtomicUsize::new(id + 1),
+            // popped from the end, the lowest first
+            recycled: IRQSpinLock::new((0..id).rev().collect()),
+        }
+    }
+
     pub fn alloc(&self) -> usize {
         if let Some(id) = self.recycled.lock().pop() {
             return id;
@@ -292,6 +420,12 @@ impl RecycleAllocator {
 
         self.current.fetch_add(1, Ordering::AcqRel)
     }
+    /// Ids handed out and not given back
+    pub fn in_use(&self) -> usize {
+        let recycled = self.recycled.lock();
+        self.current.load(Ordering::Acquire) - recycled.len()
+    }
+
     pub fn dealloc(&self, id: usize) {
         let mut recycled = self.recycled.lock();
         assert!(id < self.current.load(Ordering::Acquire));
@@ -302,4 +436,21 @@ impl RecycleAllocator {
         );
         recycled.push(id);
     }
-}
\ No newline at end of file
+}
+
+#[kernel_test]
+fn kernel_stack_canary_test() {
+    let stack = KernelStackALlocator::alloc();
+    let (bottom, top) = (stack.bottom, stack.top);
+    assert_eq!(top % KERNEL_STACK_SLOT, 0);
+    let canary: VirtPageNum = VirtAddr::from(bottom - PAGE_SIZE).into();
+    let canary_pte = KERNEL_SPACE.lock().translate(canary).unwrap();
+    assert!(canary_pte.is_canary() && !canary_pte.is_valid());
+
+    assert_eq!(KernelStackGuard::overflowed_stack(bottom - 8), Some((bottom, top)));
+    assert_eq!(KernelStackGuard::overflowed_stack(bottom), None);
+    assert_eq!(KernelStackGuard::overflowed_stack(bottom - PAGE_SIZE - 8), None);
+
+    drop(stack);
+    assert!(!KERNEL_SPACE.lock().translate(canary).is_some_and(|pte| pte.is_canary()));
+}
@@ -41,7 +41,7 @@ impl TaskContext {
     ///
     /// # Returns
     /// A zero-initialized `TaskContext` instance.
-    pub fn zero_init() -> Self {
+    pub const fn zero_init() -> Self {
         Self {
             ra: 0,
             sp: 0,
@@ -66,7 +66,13 @@ impl TaskContext {
         }
     }
 
-    // pub fn goto_new_kernel_task_start(kernel_stack_top: usize, kernenl_fn: Fn) -> Self{}
-
-    
+    /// A context which starts running `entry` on the stack of top
+    /// `stack_top` once switched to, in supervisor mode.
+    pub fn goto_kernel_entry(entry: fn() -> !, stack_top: usize) -> Self {
+        Self {
+            ra: entry as usize,
+            sp: stack_top,
+            s: [0; 12],
+        }
+    }
 }
@@ -0,0 +1,373 @@
+//! Core dumps
+//!
+//! A task killed by the default action of `SIGQUIT`, `SIGABRT` or
+//! `SIGSEGV` leaves an ELF core file of its address space, for a debugger
+//! to look at offline (`gdb <program> /core.<pid>`):
+//! - a `PT_NOTE` segment holding one `NT_PRSTATUS` note: the signal, the
+//!   pids and the registers of the dying thread, from its trap context,
+//!   laid out as the `elf_prstatus` of riscv64 Linux
+//! - a `PT_LOAD` segment per readable user area, page aligned in the
+//!   file. Pages never faulted in are dumped as zeros, not faulted in now
+//!
+//! The core goes to `/core.<pid>`. When that file can't be created, it is
+//! printed on the console instead, in hex lines between two markers which
+//! `xxd -r -p` turns back into the file, at most [`CONSOLE_CORE_MAX`]
+//! bytes of it.
+//!
+//! `RLIMIT_CORE` of the task group bounds the size of the dump, which is
+//! cut at the limit. It is 0 unless the task raised it: no dump.
+
+use alloc::{format, string::String, sync::Arc, vec, vec::Vec};
+use core::fmt::Write;
+
+use os_macros::kernel_test;
+
+use super::{signal::Signal, TaskControlBlock};
+use crate::{
+    config::PAGE_SIZE,
+    fs::{
+        vfs::{self, Inode},
+        OpenFlags,
+    },
+    mm::{
+        address::VirtAddr,
+        map_area::MapPermission,
+        memory_set::AreaInfo,
+    },
+    println,
+    syscall::error::Errno,
+    trap::TrapContext,
+};
+
+/// Bytes of a core printed on the console at most, a serial line is slow
+pub const CONSOLE_CORE_MAX: usize = 256 * 1024;
+/// Bytes of a console line, hex encoded
+const CONSOLE_LINE: usize = 32;
+
+const ET_CORE: u16 = 4;
+const EM_RISCV: u16 = 243;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+const NT_PRSTATUS: u32 = 1;
+/// Name of the notes of the kernel, padded to 4 bytes
+const NOTE_NAME: [u8; 8] = *b"CORE\0\0\0\0";
+
+/// `Elf64_Ehdr`
+#[repr(C)]
+#[derive(Default)]
+struct FileHeader {
+    ident: [u8; 16],
+    kind: u16,
+    machine: u16,
+    version: u32,
+    entry: u64,
+    phoff: u64,
+    shoff: u64,
+    flags: u32,
+    ehsize: u16,
+    phentsize: u16,
+    phnum: u16,
+    shentsize: u16,
+    shnum: u16,
+    shstrndx: u16,
+}
+
+/// `Elf64_Phdr`
+#[repr(C)]
+#[derive(Default)]
+struct ProgramHeader {
+    kind: u32,
+    flags: u32,
+    offset: u64,
+    vaddr: u64,
+    paddr: u64,
+    filesz: u64,
+    memsz: u64,
+    align: u64,
+}
+
+/// `Elf64_Nhdr`, followed by the name and the descriptor
+#[repr(C)]
+struct NoteHeader {
+    namesz: u32,
+    descsz: u32,
+    kind: u32,
+}
+
+/// `struct elf_prstatus` of riscv64 Linux, what `gdb` reads the
+/// registers of a thread from
+#[repr(C)]
+#[derive(Default)]
+struct PrStatus {
+    /// `elf_siginfo`: number, code and errno of the signal
+    signo: i32,
+    code: i32,
+    errno: i32,
+    cursig: u16,
+    _pad: u16,
+    sigpend: u64,
+    sighold: u64,
+    pid: i32,
+    ppid: i32,
+    pgrp: i32,
+    sid: i32,
+    /// user, system, and children's user and system times, as `timeval`s
+    times: [u64; 8],
+    /// `pc`, then `x1` to `x31`
+    regs: [u64; 32],
+    fpvalid: i32,
+    _pad2: i32,
+}
+
+impl PrStatus {
+    fn new(signal: Signal, pid: usize, ppid: usize, trap_context: &TrapContext) -> Self {
+        let mut regs = [0; 32];
+        regs[0] = trap_context.sepc as u64;
+        for (reg, &x) in regs.iter_mut().zip(trap_context.x.iter()).skip(1) {
+            *reg = x as u64;
+        }
+        Self {
+            signo: signal as i32,
+            cursig: signal as u16,
+            pid: pid as i32,
+            ppid: ppid as i32,
+            pgrp: pid as i32,
+            sid: pid as i32,
+            regs,
+            ..Self::default()
+        }
+    }
+}
+
+/// The bytes of a `#[repr(C)]` header without padding
+fn bytes_of<T>(value: &T) -> &[u8] {
+    unsafe { core::slice::from_raw_parts(value as *const T as *const u8, core::mem::size_of::<T>()) }
+}
+
+/// Whether the core has the pages of `area`: readable user memory
+fn is_dumped(area: &AreaInfo) -> bool {
+    MapPermission::from_bits_truncate(area.perm as u8).contains(MapPermission::R | MapPermission::U)
+}
+
+fn segment_flags(area: &AreaInfo) -> u32 {
+    let perm = MapPermission::from_bits_truncate(area.perm as u8);
+    let mut flags = PF_R;
+    if perm.contains(MapPermission::W) {
+        flags |= PF_W;
+    }
+    if perm.contains(MapPermission::X) {
+        flags |= PF_X;
+    }
+    flags
+}
+
+/// Everything the core holds before the memory of `areas`: the headers and
+/// the note, padded to the page the first segment starts on.
+fn headers(areas: &[AreaInfo], prstatus: &PrStatus) -> Vec<u8> {
+    let file_header_size = core::mem::size_of::<FileHeader>();
+    let program_header_size = core::mem::size_of::<ProgramHeader>();
+    let phnum = 1 + areas.len();
+    let note_offset = file_header_size + phnum * program_header_size;
+    let note_size = core::mem::size_of::<NoteHeader>() + NOTE_NAME.len() + core::mem::size_of::<PrStatus>();
+    let data_offset = (note_offset + note_size).next_multiple_of(PAGE_SIZE);
+
+    let mut ident = [0; 16];
+    // magic, 64-bit, little endian, version 1, System V ABI
+    ident[..7].copy_from_slice(b"\x7fELF\x02\x01\x01");
+    let file_header = FileHeader {
+        ident,
+        kind: ET_CORE,
+        machine: EM_RISCV,
+        version: 1,
+        phoff: file_header_size as u64,
+        ehsize: file_header_size as u16,
+        phentsize: program_header_size as u16,
+        phnum: phnum as u16,
+        ..FileHeader::default()
+    };
+
+    let mut out = Vec::with_capacity(data_offset);
+    out.extend_from_slice(bytes_of(&file_header));
+    let note = ProgramHeader {
+        kind: PT_NOTE,
+        offset: note_offset as u64,
+        filesz: note_size as u64,
+        align: 4,
+        ..ProgramHeader::default()
+    };
+    out.extend_from_slice(bytes_of(&note));
+    let mut offset = data_offset;
+    for area in areas {
+        let size = area.end - area.start;
+        let load = ProgramHeader {
+            kind: PT_LOAD,
+            flags: segment_flags(area),
+            offset: offset as u64,
+            vaddr: area.start as u64,
+            filesz: size as u64,
+            memsz: size as u64,
+            align: PAGE_SIZE as u64,
+            ..ProgramHeader::default()
+        };
+        out.extend_from_slice(bytes_of(&load));
+        offset += size;
+    }
+
+    let note_header = NoteHeader {
+        namesz: 5,
+        descsz: core::mem::size_of::<PrStatus>() as u32,
+        kind: NT_PRSTATUS,
+    };
+    out.extend_from_slice(bytes_of(&note_header));
+    out.extend_from_slice(&NOTE_NAME);
+    out.extend_from_slice(bytes_of(prstatus));
+    out.resize(data_offset, 0);
+    out
+}
+
+/// Where a core goes, cut at `limit` bytes
+struct CoreSink {
+    out: Output,
+    written: usize,
+    limit: usize,
+}
+
+enum Output {
+    File(Arc<dyn Inode>),
+    Console,
+}
+
+impl CoreSink {
+    /// Write what fits of `bytes` under the limit, false once the core
+    /// is cut or the file can't take more.
+    fn write(&mut self, bytes: &[u8]) -> bool {
+        let len = bytes.len().min(self.limit - self.written);
+        let bytes = &bytes[..len];
+        match &self.out {
+            Output::File(inode) => {
+                if inode.write_at(self.written, bytes) != len {
+                    log::warn!("coredump: file full after {} bytes", self.written);
+                    return false;
+                }
+            }
+            Output::Console => {
+                for chunk in bytes.chunks(CONSOLE_LINE) {
+                    let mut line = String::with_capacity(2 * CONSOLE_LINE);
+                    for byte in chunk {
+                        let _ = write!(line, "{:02x}", byte);
+                    }
+                    println!("{}", line);
+                }
+            }
+        }
+        self.written += len;
+        self.written < self.limit
+    }
+}
+
+/// Create `path` empty, for a new core.
+fn create(path: &str) -> Result<Arc<dyn Inode>, Errno> {
+    vfs::open(path, OpenFlags::CREATE | OpenFlags::WRONLY | OpenFlags::TRUNC)?;
+    vfs::lookup(path)
+}
+
+/// Dump the core of `task`, killed by `signal`, as its `RLIMIT_CORE`
+/// allows. The caller holds none of its locks. Returns whether anything
+/// was dumped.
+pub fn dump(task: &Arc<TaskControlBlock>, signal: Signal) -> bool {
+    let pid = usize::from(task.get_pid());
+    let ppid = task.get_ppid().map_or(0, usize::from);
+    let (prstatus, memory_set, limit) = {
+        let inner = task.lock();
+        let (pending, blocked) = (inner.signals.pending, inner.signals.blocked);
+        let Some((trap_context, memory_set, limit)) = inner.user_res.as_ref().map(|user_res| {
+            let trap_context = user_res.trap_context_ppn().get_ref::<TrapContext>().clone();
+            (trap_context, user_res.memory_set.clone(), user_res.rlimits.lock().core())
+        }) else {
+            return false;
+        };
+        let mut prstatus = PrStatus::new(signal, pid, ppid, &trap_context);
+        prstatus.sigpend = pending.bits() as u64;
+        prstatus.sighold = blocked.bits() as u64;
+        (prstatus, memory_set, limit)
+    };
+    if limit == 0 {
+        return false;
+    }
+
+    let areas: Vec<AreaInfo> = memory_set.lock().area_infos().into_iter().filter(is_dumped).collect();
+    let path = format!("/core.{}", pid);
+    let mut sink = match create(&path) {
+        Ok(inode) => CoreSink { out: Output::File(inode), written: 0, limit },
+        Err(errno) => {
+            log::warn!("coredump: can't create {}: {:?}, dumping on the console", path, errno);
+            println!("[coredump] ----- core of pid {} begin -----", pid);
+            CoreSink { out: Output::Console, written: 0, limit: limit.min(CONSOLE_CORE_MAX) }
+        }
+    };
+
+    let mut page = vec![0u8; PAGE_SIZE];
+    let mut more = sink.write(&headers(&areas, &prstatus));
+    'areas: for area in &areas {
+        for addr in (area.start..area.end).step_by(PAGE_SIZE) {
+            if !more {
+                break 'areas;
+            }
+            {
+                let memory_set = memory_set.lock();
+                match memory_set.translate(VirtAddr::from(addr).down_to_vpn()) {
+                    Some(pte) if pte.is_valid() => page.copy_from_slice(pte.ppn().get_bytes_array_slice()),
+                    _ => page.fill(0),
+                }
+            }
+            // the memory set is unlocked, writing the file may sleep
+            more = sink.write(&page);
+        }
+    }
+
+    match sink.out {
+        Output::File(_) => log::info!("coredump: pid {} dumped {} bytes to {}", pid, sink.written, path),
+        Output::Console => println!("[coredump] ----- core of pid {} end, {} bytes -----", pid, sink.written),
+    }
+    true
+}
+
+#[kernel_test]
+fn coredump_test() {
+    use core::mem::size_of;
+
+    assert_eq!(size_of::<FileHeader>(), 64);
+    assert_eq!(size_of::<ProgramHeader>(), 56);
+    assert_eq!(size_of::<PrStatus>(), 376);
+
+    let rw = (MapPermission::R | MapPermission::W | MapPermission::U).bits() as usize;
+    let kernel = (MapPermission::R | MapPermission::W).bits() as usize;
+    let areas = [
+        AreaInfo { start: 0x1000, end: 0x3000, perm: rw, map_type: 1 },
+        AreaInfo { start: 0x8000, end: 0x9000, perm: kernel, map_type: 1 },
+    ];
+    let dumped: Vec<AreaInfo> = areas.into_iter().filter(is_dumped).collect();
+    assert_eq!(dumped.len(), 1);
+
+    let mut trap_context: TrapContext = unsafe { core::mem::zeroed() };
+    trap_context.sepc = 0x1234;
+    trap_context.x[2] = 0x2ff0;
+    let prstatus = PrStatus::new(Signal::SIGSEGV, 7, 1, &trap_context);
+    assert_eq!(prstatus.regs[0], 0x1234);
+    assert_eq!(prstatus.regs[2], 0x2ff0);
+
+    let core = headers(&dumped, &prstatus);
+    assert_eq!(core.len(), PAGE_SIZE);
+    assert_eq!(&core[..4], b"\x7fELF");
+    assert_eq!(u16::from_le_bytes([core[16], core[17]]), ET_CORE);
+    assert_eq!(u16::from_le_bytes([core[56], core[57]]), 2);
+    // the load segment starts on the page after the headers
+    let load = 64 + 56;
+    assert_eq!(u32::from_le_bytes(core[load..load + 4].try_into().unwrap()), PT_LOAD);
+    assert_eq!(u64::from_le_bytes(core[load + 8..load + 16].try_into().unwrap()), PAGE_SIZE as u64);
+    let note = 64 + 2 * 56;
+    assert_eq!(&core[note + 12..note + 16], b"CORE");
+}
@@ -1,16 +1,28 @@
 mod context;
-mod switch;
+pub mod switch;
 mod task;
 mod syscall;
 mod allocator;
-mod signal;
+pub mod signal;
 pub mod scheduler;
-
-use alloc::{boxed::Box, string::{String, ToString}, sync::Arc};
+pub mod determinism;
+mod wait_queue;
+mod table;
+pub mod stats;
+pub mod time_slice;
+pub mod capture;
+pub mod rlimit;
+pub mod coredump;
+
+use alloc::{string::{String, ToString}, sync::Arc};
 pub use context::TaskContext;
-use scheduler::FiFoScheduler;
-pub use task::TaskControlBlock;
-use crate::{fs::{open_file, OpenFlags}, mm::address::VirtAddr, processor::get_current_processor, trap::TrapContext};
+use scheduler::Policy;
+pub use task::{TaskControlBlock, TaskControlBlockInner, TaskState};
+pub use wait_queue::WaitQueue;
+pub use signal::handle_signals;
+pub use table::{all_tasks, find_task};
+pub use allocator::KernelStackGuard;
+use crate::{fs::vfs, mm::address::VirtAddr, processor::get_current_processor, sync::spin::mutex::IRQSpinLock, trap::TrapContext};
 
 // use crate::sync::UPSafeCell;
 
@@ -26,28 +38,42 @@ use crate::{fs::{open_file, OpenFlags}, mm::address::VirtAddr, processor::get_cu
 // }   
 
 
+/// The first user program, unless the command line names another with `init=`
+const DEFAULT_INIT: &str = "/init_proc";
+
 pub fn init_scheduler() {
     log::info!("initialize scheduler");
+    determinism::init();
     let processor = get_current_processor();
-    processor.init_scheduler(Box::new(FiFoScheduler::new(1)));
+    let policy = Policy::from_cmdline();
+    log::info!("scheduler policy: {:?}", policy);
+    crate::processor::init_shared_scheduler(policy.build());
 
-    log::info!("load init_task");
+    let init_path = crate::cmdline::get("init").unwrap_or(DEFAULT_INIT);
+    log::info!("load init_task from {}", init_path);
 
-    if let Some(app_inode) = open_file("init_proc", OpenFlags::RDONLY) {
-        log::debug!("open file dead_loop2 success");
+    if let Ok(app_inode) = vfs::lookup(init_path) {
         let all_data = app_inode.read_all();
         // let task = current_task().unwrap();
-        processor.add_task(TaskControlBlock::new_from_elf(
+        let init_task = TaskControlBlock::new_from_elf(
             &all_data.as_slice(), 
             "init_task".to_string(), 
-            None)
-        );
+            None);
+        *INIT_TASK.lock() = Some(init_task.clone());
+        processor.add_task(init_task);
     }
     else {
-        panic!("not found init proc");
+        panic!("not found init proc {}", init_path);
     }
 }
 
+/// The first user task, orphans are handed to it
+static INIT_TASK: IRQSpinLock<Option<Arc<TaskControlBlock>>> = IRQSpinLock::new(None);
+
+pub fn init_task() -> Option<Arc<TaskControlBlock>> {
+    INIT_TASK.lock().clone()
+}
+
 pub fn current_task() -> Option<&'static Arc<TaskControlBlock>> {
     let current_task = get_current_processor().get_current_task();
     current_task
@@ -0,0 +1,138 @@
+//! Resource limits of a task group
+//!
+//! Each task group (process) has one table, shared by its threads, copied
+//! by fork and kept across exec. The table is the record of the limits,
+//! they are enforced where the resource is taken from:
+//! - `RLIMIT_NOFILE` by the fd table, on every new fd
+//! - `RLIMIT_AS` by the memory set, on `mmap`, `brk`, `shmat` and stack growth
+//! - `RLIMIT_STACK` by the memory set, on stack growth
+//! - `RLIMIT_CORE` by `coredump`, when a signal kills the task
+//!
+//! Only the soft limit is enforced. The hard limits start at what the
+//! kernel can do at all, and are only ever lowered: there are no
+//! privileged tasks to raise them. Core dumps are the one soft limit
+//! starting below its hard one, at 0: a task asks for them.
+
+use os_macros::kernel_test;
+
+use crate::{
+    config::{PAGE_SIZE, USER_STACK_MAX_SIZE},
+    fs::{FdTable, MAX_FDS},
+    mm::memory_set::{MemoryLimits, MemorySet},
+    syscall::error::Errno,
+};
+
+/// `resource` of `prlimit`, Linux values
+pub const RLIMIT_STACK: usize = 3;
+pub const RLIMIT_CORE: usize = 4;
+pub const RLIMIT_NOFILE: usize = 7;
+pub const RLIMIT_AS: usize = 9;
+
+pub const RLIM_INFINITY: usize = usize::MAX;
+
+/// `struct rlimit` of the Linux ABI
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RLimit {
+    /// Soft limit, the one enforced
+    pub cur: usize,
+    /// Hard limit, the ceiling of `cur`
+    pub max: usize,
+}
+
+impl RLimit {
+    const fn fixed(limit: usize) -> Self {
+        Self { cur: limit, max: limit }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RLimits {
+    /// Bytes
+    stack: RLimit,
+    nofile: RLimit,
+    /// Bytes
+    address_space: RLimit,
+    /// Bytes of a core dump, 0 for none
+    core: RLimit,
+}
+
+impl RLimits {
+    pub const fn new() -> Self {
+        Self {
+            stack: RLimit::fixed(USER_STACK_MAX_SIZE),
+            nofile: RLimit::fixed(MAX_FDS),
+            address_space: RLimit::fixed(RLIM_INFINITY),
+            core: RLimit { cur: 0, max: RLIM_INFINITY },
+        }
+    }
+
+    fn slot(&mut self, resource: usize) -> Result<&mut RLimit, Errno> {
+        match resource {
+            RLIMIT_STACK => Ok(&mut self.stack),
+            RLIMIT_NOFILE => Ok(&mut self.nofile),
+            RLIMIT_AS => Ok(&mut self.address_space),
+            RLIMIT_CORE => Ok(&mut self.core),
+            _ => Err(Errno::EINVAL),
+        }
+    }
+
+    /// The limit of `resource`, `EINVAL` if it isn't one of the supported
+    pub fn get(&mut self, resource: usize) -> Result<RLimit, Errno> {
+        self.slot(resource).copied()
+    }
+
+    /// Replace the limit of `resource`.
+    ///
+    /// `EINVAL` for an unsupported `resource` or a soft limit above the
+    /// hard one, `EPERM` for a hard limit raised.
+    pub fn set(&mut self, resource: usize, limit: RLimit) -> Result<(), Errno> {
+        let slot = self.slot(resource)?;
+        if limit.cur > limit.max {
+            return Err(Errno::EINVAL);
+        }
+        if limit.max > slot.max {
+            return Err(Errno::EPERM);
+        }
+        *slot = limit;
+        Ok(())
+    }
+
+    /// Largest core dump, in bytes
+    pub fn core(&self) -> usize {
+        self.core.cur
+    }
+
+    /// Hand the soft limits to where they are enforced.
+    pub fn apply(&self, memory_set: &mut MemorySet, fd_table: &mut FdTable) {
+        memory_set.set_limits(MemoryLimits {
+            address_space: self.address_space.cur / PAGE_SIZE,
+            stack: self.stack.cur / PAGE_SIZE,
+        });
+        fd_table.set_limit(self.nofile.cur);
+    }
+}
+
+#[kernel_test]
+fn rlimit_test() {
+    let mut limits = RLimits::new();
+    assert_eq!(limits.get(RLIMIT_NOFILE), Ok(RLimit::fixed(MAX_FDS)));
+    assert_eq!(limits.get(0), Err(Errno::EINVAL));
+
+    // lowered, then the soft limit moves up to the hard one only
+    assert_eq!(limits.set(RLIMIT_NOFILE, RLimit { cur: 8, max: 16 }), Ok(()));
+    assert_eq!(limits.set(RLIMIT_NOFILE, RLimit { cur: 16, max: 16 }), Ok(()));
+    assert_eq!(limits.set(RLIMIT_NOFILE, RLimit { cur: 17, max: 16 }), Err(Errno::EINVAL));
+    assert_eq!(limits.set(RLIMIT_NOFILE, RLimit { cur: 16, max: 32 }), Err(Errno::EPERM));
+    // core dumps are off until asked for
+    assert_eq!(limits.core(), 0);
+    assert_eq!(limits.set(RLIMIT_CORE, RLimit { cur: RLIM_INFINITY, max: RLIM_INFINITY }), Ok(()));
+    assert_eq!(limits.core(), RLIM_INFINITY);
+
+    let mut memory_set = MemorySet::new_bare();
+    let mut fd_table = FdTable::with_stdio();
+    limits.set(RLIMIT_AS, RLimit { cur: 8 * PAGE_SIZE, max: RLIM_INFINITY }).unwrap();
+    limits.apply(&mut memory_set, &mut fd_table);
+    assert_eq!(memory_set.limits().address_space, 8);
+    assert_eq!(memory_set.limits().stack, USER_STACK_MAX_SIZE / PAGE_SIZE);
+}
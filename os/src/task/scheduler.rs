@@ -1,35 +1,39 @@
 use core::{panic, sync::atomic::{AtomicBool, Ordering}};
 
-use alloc::{collections::vec_deque::VecDeque, sync::Arc};
+use alloc::{boxed::Box, collections::{btree_map::BTreeMap, vec_deque::VecDeque}, sync::Arc, vec::Vec};
+use os_macros::monitor_command;
 
 use crate::{
-    interupt::{InterruptController, InterruptState}, processor::{self, current_processor_id, get_current_processor}, sync::spin::mutex::{IRQSpinLock, IRQSpinLockGuard}, task::switch::__switch, trap::trap_return
+    interupt::{InterruptController, InterruptState}, println, processor::{self, get_current_processor, watchdog}, sync::spin::mutex::{IRQSpinLock, IRQSpinLockGuard}, task::switch::__switch, trap::trap_return
 };
 
 use super::{
-    current_task, task::{TaskControlBlock, TaskControlBlockInner, TaskState}, yield_current, TaskContext
+    all_tasks, allocator::ids_in_use, current_task, determinism::{self, Decision}, task::{TaskControlBlock, TaskControlBlockInner, TaskState}, yield_current, TaskContext
 };
 
 pub trait Scheduler: Send + Sync {
-    // drived by timer
-    fn schedule(&self, yiled_task_guard: IRQSpinLockGuard<TaskControlBlockInner>);
+    /// Put a ready task in the ready queue.
     fn add_task(&self, task_control_block: Arc<TaskControlBlock>);
-    fn fetch_task(&self) -> Option<Arc<TaskControlBlock>>;
-    fn yield_current(&self);
-    fn exit_current(&self, exit_code: i32);
-}
+    /// Take the next task to run on hart `hart` out of the ready queue,
+    /// passing over the tasks its affinity keeps off that hart.
+    fn fetch_task(&self, hart: usize) -> Option<Arc<TaskControlBlock>>;
+    /// Snapshot of the tasks currently waiting to run
+    fn ready_tasks(&self) -> Vec<Arc<TaskControlBlock>>;
+    /// Whether a task is waiting to run on hart `hart`
+    fn has_ready(&self, hart: usize) -> bool {
+        self.ready_tasks().iter().any(|task| task.runs_on(hart))
+    }
 
-pub struct FiFoScheduler {
-    ready_queue: IRQSpinLock<VecDeque<Arc<TaskControlBlock>>>,
-    
-    // blocked_tasks: IRQSpinLock<Vec<Weak<TaskControlBlock>>>,
-    time_interval: u64,
-    is_running: AtomicBool,
-}
+    /// A timer tick elapsed while a task was running, preempts it by default.
+    fn on_tick(&self) {
+        self.yield_current();
+    }
 
-impl Scheduler for FiFoScheduler {
+    // The switching below is the same for every policy, only the
+    // ready queue above differs.
 
     // switch current task and schduler_task to return schedule_loop
+    // drived by timer
     fn schedule(&self, yiled_task_guard: IRQSpinLockGuard<TaskControlBlockInner>) {
         assert_ne!(
             yiled_task_guard.get_state(),
@@ -41,71 +45,164 @@ impl Scheduler for FiFoScheduler {
             InterruptState::Enabled,
             "Cannot schedule duaring intterupt enable"
         );
+        assert!(
+            !get_current_processor().preempt_disabled(),
+            "Cannot schedule with preemption disabled"
+        );
 
         let schedule_loop_task_context = get_current_processor()
                                                             .get_schedule_loop_context();
-        let yield_task_context = &yiled_task_guard.context as *const TaskContext;
 
         let interrupt_state = get_current_processor().get_saved_interrupt_state();
 
         let yield_out_task = current_task().unwrap();
+        let name = yield_out_task.get_name();
+        log::debug!("hold {} 's lock across the switch to the scheduler loop", name);
+        let yield_task_context = yield_out_task.hold_across_switch(yiled_task_guard);
 
-        
         unsafe { 
-            let name = yield_out_task.get_name();
-            log::debug!("pass {} 's guard into scheduler loop in schedule", name);
-            yield_out_task.store_lock(yiled_task_guard);
-
-            __switch(yield_task_context as *mut TaskContext, schedule_loop_task_context);
+            __switch(yield_task_context, schedule_loop_task_context);
             
             log::debug!("{} switch back to schedule", name);
-            let switch_back_task = current_task().unwrap();
-            let switch_back_task_gurad = switch_back_task.take_lock();
             log::debug!("release current task lock");
             get_current_processor().set_saved_interrupt_state(interrupt_state);
-            drop(switch_back_task_gurad);
+            current_task().unwrap().release_switch_lock();
         };
 
 
     }
 
-
-    fn add_task(&self, task_control_block: Arc<TaskControlBlock>) {
-        log::debug!("task len before add: {}", self.ready_queue.lock().len());
-        self.ready_queue.lock().push_back(task_control_block);
-        log::debug!("task len after add: {}", self.ready_queue.lock().len());
-    }
-
-    fn fetch_task(&self) -> Option<Arc<TaskControlBlock>> {
-        log::debug!("task len before fetch: {}", self.ready_queue.lock().len());
-        let a = self.ready_queue.lock().pop_front();
-        log::debug!("task len after fetch: {}", self.ready_queue.lock().len());
-        a
-    }
-
+    // yield the current task
+    // the guard mode make sure the lock be accquire.
+    // Before yield, the task's state should be `TaskState::Ready`,
+    // the scheduler loop adds it back to the ready queue
     fn yield_current(&self) {
         log::debug!("yield out current");
-        let current_task = current_task();
-        if let Some(task) = current_task {
-            self.yield_task(task);
-        }
-        log::debug!("yield in current");
-    }
+        let Some(task) = current_task() else {
+            return;
+        };
 
+        assert_eq!(task.lock().get_state(), TaskState::Running);
 
+        log::debug!("yield out task {}", task.get_name());
+        determinism::record(Decision::Yield(task.get_tid()));
+
+        let mut task_guard = task.lock();
+        task_guard.set_state(TaskState::Ready);
+        self.schedule(task_guard);
+        log::debug!("yield in task {}", current_task().unwrap().get_name());
+    }
 
     fn exit_current(&self, exit_code: i32) {
         let current_task = current_task().unwrap();
         current_task.prepare_exit();
 
+        determinism::record(Decision::Exit(current_task.get_tid(), exit_code));
         let mut current_task_guard = current_task.lock();
         current_task_guard.set_state(TaskState::Zombie(exit_code));
         
-        //child task group, place to init
-        current_task_guard.notify_parent(exit_code);
+        // only a leader is a child of another group
+        if current_task.is_leader() {
+            current_task_guard.notify_parent(exit_code);
+        }
+        self.schedule(current_task_guard);
+    }
+
+    /// Put the current task to sleep, it won't run until [`Scheduler::wakeup_task`].
+    /// The caller must have registered the task somewhere to be woken up (see `WaitQueue`).
+    fn block_current(&self, mut current_task_guard: IRQSpinLockGuard<TaskControlBlockInner>) {
+        let current_task = current_task().unwrap();
+        determinism::record(Decision::Block(current_task.get_tid()));
+        log::debug!("block task {}", current_task.get_name());
+
+        current_task_guard.set_state(TaskState::Blocking);
         self.schedule(current_task_guard);
     }
 
+    /// Make a blocked task runnable again.
+    fn wakeup_task(&self, task: Arc<TaskControlBlock>) {
+        // spins until the scheduler loop releases the lock of a task
+        // that is still switching out
+        let mut task_guard = task.lock();
+        assert_eq!(task_guard.get_state(), TaskState::Blocking, "Cannot wake up a non-blocking task.");
+        task_guard.set_state(TaskState::Ready);
+        drop(task_guard);
+
+        determinism::record(Decision::Wakeup(task.get_tid()));
+        task.stats().on_enqueue();
+        self.add_task(task);
+    }
+}
+
+/// Scheduling policies, picked at boot with `sched=fifo|rr|priority` on the
+/// kernel command line, or at build time with `SCHEDULER` (see `cmdline`),
+/// FIFO by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    Fifo,
+    RoundRobin,
+    Priority,
+}
+
+impl Policy {
+    pub fn from_cmdline() -> Self {
+        match crate::cmdline::get("sched") {
+            None | Some("fifo") => Policy::Fifo,
+            Some("rr") => Policy::RoundRobin,
+            Some("priority") => Policy::Priority,
+            Some(other) => {
+                log::warn!("unknown scheduler `{}`, using fifo", other);
+                Policy::Fifo
+            }
+        }
+    }
+
+    pub fn build(self) -> Box<dyn Scheduler> {
+        match self {
+            Policy::Fifo => Box::new(FiFoScheduler::new(1)),
+            Policy::RoundRobin => Box::new(RoundRobinScheduler::new()),
+            Policy::Priority => Box::new(PriorityScheduler::new()),
+        }
+    }
+}
+
+/// Take the first task of `queue` allowed on hart `hart`, the others keep
+/// their place.
+fn take_first_on(queue: &mut VecDeque<Arc<TaskControlBlock>>, hart: usize) -> Option<Arc<TaskControlBlock>> {
+    let position = queue.iter().position(|task| task.runs_on(hart))?;
+    queue.remove(position)
+}
+
+/// First-in first-out scheduler.
+///
+/// Ties are impossible by construction: tasks run strictly in the order they
+/// were enqueued, which is what deterministic mode relies on.
+pub struct FiFoScheduler {
+    ready_queue: IRQSpinLock<VecDeque<Arc<TaskControlBlock>>>,
+    
+    // blocked_tasks: IRQSpinLock<Vec<Weak<TaskControlBlock>>>,
+    time_interval: u64,
+    is_running: AtomicBool,
+}
+
+impl Scheduler for FiFoScheduler {
+    fn add_task(&self, task_control_block: Arc<TaskControlBlock>) {
+        determinism::record(Decision::Enqueue(task_control_block.get_tid()));
+        log::debug!("task len before add: {}", self.ready_queue.lock().len());
+        self.ready_queue.lock().push_back(task_control_block);
+        log::debug!("task len after add: {}", self.ready_queue.lock().len());
+    }
+
+    fn fetch_task(&self, hart: usize) -> Option<Arc<TaskControlBlock>> {
+        log::debug!("task len before fetch: {}", self.ready_queue.lock().len());
+        let a = take_first_on(&mut self.ready_queue.lock(), hart);
+        log::debug!("task len after fetch: {}", self.ready_queue.lock().len());
+        a
+    }
+
+    fn ready_tasks(&self) -> Vec<Arc<TaskControlBlock>> {
+        self.ready_queue.lock().iter().cloned().collect()
+    }
 }
 
 impl FiFoScheduler {
@@ -123,50 +220,140 @@ impl FiFoScheduler {
         Self::setup_timer(self.time_interval);
     }
 
-    // yield the specified task
-    // the task must be current task
-    // and the guard mode make sure the lock be accquire.
-    // Before yield, the task's state should be `TaskState::Ready`
-    // and has been added to ready q
-    fn yield_task(&self, task : &Arc<TaskControlBlock>) {
+    fn setup_timer(_timer_interval: u64) {
+        return;
+    }
 
-        assert_eq!(task.lock().get_state(), TaskState::Running);
+    // fn task_complete(&mut self, task: Arc<TaskControlBlock>);
 
-        log::debug!("yield out task {}", task.get_name());
-        // let current_task = current_task();
+    // fn task_blocked(&mut self, task: Arc<TaskControlBlock>);
 
-        let mut task_guard = task.lock();
-        task_guard.set_state(TaskState::Ready);
-        // self.add_task(task.clone());
-        self.schedule(task_guard);
-        log::debug!("yield in task {}", current_task().unwrap().get_name());
+    // fn task_wakeup(&mut self, task: Arc<TaskControlBlock>);
+}
+
+/// Round-robin scheduler.
+///
+/// Same queue as [`FiFoScheduler`], but a running task is only preempted
+/// once it used up its time slice (see [`time_slice`](super::time_slice)),
+/// not at every tick. A task that blocks or yields keeps what is left of
+/// its slice for next time.
+pub struct RoundRobinScheduler {
+    ready_queue: IRQSpinLock<VecDeque<Arc<TaskControlBlock>>>,
+}
+
+impl RoundRobinScheduler {
+    pub fn new() -> Self {
+        Self {
+            ready_queue: IRQSpinLock::new(VecDeque::new()),
+        }
     }
+}
 
-    
+impl Scheduler for RoundRobinScheduler {
+    fn add_task(&self, task_control_block: Arc<TaskControlBlock>) {
+        determinism::record(Decision::Enqueue(task_control_block.get_tid()));
+        self.ready_queue.lock().push_back(task_control_block);
+    }
 
-    fn setup_timer(_timer_interval: u64) {
-        return;
+    fn fetch_task(&self, hart: usize) -> Option<Arc<TaskControlBlock>> {
+        take_first_on(&mut self.ready_queue.lock(), hart)
     }
 
-    // fn task_complete(&mut self, task: Arc<TaskControlBlock>);
+    fn ready_tasks(&self) -> Vec<Arc<TaskControlBlock>> {
+        self.ready_queue.lock().iter().cloned().collect()
+    }
 
-    // fn task_blocked(&mut self, task: Arc<TaskControlBlock>);
+    fn on_tick(&self) {
+        let Some(task) = current_task() else {
+            return;
+        };
+        if task.time_slice().charge() {
+            self.yield_current();
+        }
+    }
+}
 
-    // fn task_wakeup(&mut self, task: Arc<TaskControlBlock>);
+/// Static priority scheduler.
+///
+/// Always runs a ready task of the lowest `nice` among those allowed on
+/// the hart, round-robin among tasks of the same `nice`. A running task
+/// is preempted at the tick after a task of a lower `nice` it could give
+/// the hart to became ready, or once its time slice is used up. Lower
+/// priorities starve as long as a higher one stays runnable: there is no
+/// aging.
+pub struct PriorityScheduler {
+    /// One FIFO queue per `nice` value
+    ready_queues: IRQSpinLock<BTreeMap<i32, VecDeque<Arc<TaskControlBlock>>>>,
+}
+
+impl PriorityScheduler {
+    pub fn new() -> Self {
+        Self {
+            ready_queues: IRQSpinLock::new(BTreeMap::new()),
+        }
+    }
+}
+
+impl Scheduler for PriorityScheduler {
+    fn add_task(&self, task_control_block: Arc<TaskControlBlock>) {
+        determinism::record(Decision::Enqueue(task_control_block.get_tid()));
+        self.ready_queues
+            .lock()
+            .entry(task_control_block.nice())
+            .or_default()
+            .push_back(task_control_block);
+    }
+
+    fn fetch_task(&self, hart: usize) -> Option<Arc<TaskControlBlock>> {
+        let mut queues = self.ready_queues.lock();
+        let (&nice, queue) = queues.iter_mut().find(|(_, queue)| queue.iter().any(|task| task.runs_on(hart)))?;
+        let task = take_first_on(queue, hart);
+        if queue.is_empty() {
+            queues.remove(&nice);
+        }
+        task
+    }
+
+    /// By decreasing priority
+    fn ready_tasks(&self) -> Vec<Arc<TaskControlBlock>> {
+        self.ready_queues.lock().values().flatten().cloned().collect()
+    }
+
+    fn on_tick(&self) {
+        let Some(task) = current_task() else {
+            return;
+        };
+        // charged either way, a preempted task keeps the rest
+        let exhausted = task.time_slice().charge();
+        let hart = get_current_processor().hart_id();
+        let preempted = self
+            .ready_queues
+            .lock()
+            .iter()
+            .find(|(_, queue)| queue.iter().any(|task| task.runs_on(hart)))
+            .is_some_and(|(&nice, _)| nice < task.nice());
+        if exhausted || preempted {
+            self.yield_current();
+        }
+    }
 }
 
 
 pub fn schedule_loop() {
     let processor = get_current_processor();
+    let mut was_idle = false;
     loop {
         // Avoid deadlock by ensuring that devices can interrupt.
         // Example: just one process waiting disk, but we wait a `RUNNING` process
         // and need interrrupt to change the process's state to `RUNNING` from `SLEEPING`
         InterruptController::global_enable();
+        watchdog::touch();
 
         log::debug!("schedule_loop");
         // should disable_migrate in multiple core
         if let Some(next_task) = processor.fetch_task() {
+            was_idle = false;
+            determinism::record(Decision::Run(next_task.get_tid()));
             log::debug!("prepare switch to {:?}", next_task);
             // accquired by scheduler task from task A
             let mut next_task_guard = next_task.lock();
@@ -177,36 +364,37 @@ pub fn schedule_loop() {
             next_task_guard.state = TaskState::Running;
             processor.set_current_task(next_task.clone());
             
-            let next_task_context = &next_task_guard.context as *const TaskContext;
-            
-
+            let next_task_context = next_task.hold_across_switch(next_task_guard);
 
             unsafe {
-                next_task.store_lock(next_task_guard);
+                next_task.stats().on_switch_in();
+                next_task.time_slice().on_switch_in();
                 __switch(scheduler_context as *mut TaskContext, next_task_context);
                 log::debug!("switch back to scheduler loop");
                 
                 let current_task = current_task().unwrap();
-                let switch_back_task_gurad = current_task.take_lock();
-                
-                
                 processor.clean_current_task();
+                let state = current_task.release_switch_lock();
+                next_task.stats().on_switch_out(state);
+                log::debug!("release switch back task lock");
 
 
-                match switch_back_task_gurad.state {
+                match state {
                     TaskState::Ready => { 
                         processor.add_task(next_task);
                     },
+                    TaskState::Blocking => {
+                        // owned by a wait queue until woken up
+                    },
                     TaskState::Zombie(exit_code) => {
-                        // log::debug!("Zombie task {}, exit code: {}", current_task.get_name(), exit_code);
-                        // log::debug!("task arc count: {}", Arc::strong_count(&current_task))
+                        // a leader stays in the children of its parent, or of
+                        // init once orphaned, until `waitpid` reaps it; a
+                        // thread or a task without parent goes with `next_task`
+                        log::debug!("task {} exited with {}", current_task.get_name(), exit_code);
                     },
                     _ => ()
                     
                 }
-                
-                drop(switch_back_task_gurad);
-                log::debug!("release switch back task gurad");
             };
 
 
@@ -214,17 +402,50 @@ pub fn schedule_loop() {
             
             // released by scheduler task from task B
         } else {
-            log::info!("No task avaliable to run")
+            // logged once per idle period, not on every wakeup
+            if !was_idle {
+                determinism::record(Decision::Idle);
+                log::info!("No task avaliable to run");
+                was_idle = true;
+            }
+            processor.park();
         }
     }
 }
 
 
+#[monitor_command(name = "ps", help = "List the current task and the ready queue")]
+fn ps_command(_args: &[&str]) {
+    let processor = get_current_processor();
+    println!("{:>5} {:<16} {}", "TID", "NAME", "STATE");
+    let current = processor.get_current_task().cloned();
+    let tasks = current.into_iter().chain(processor.ready_tasks());
+    for task in tasks {
+        println!(
+            "{:>5} {:<16} {}",
+            usize::from(task.get_tid()),
+            task.get_name(),
+            task.lock().get_state()
+        );
+    }
+
+    let tasks = all_tasks();
+    let zombies = tasks
+        .iter()
+        .filter(|task| matches!(task.lock().get_state(), TaskState::Zombie(_)))
+        .count();
+    let (tids, kernel_stacks) = ids_in_use();
+    println!(
+        "{} tasks, {} zombies; {} tids and {} kernel stacks held",
+        tasks.len(), zombies, tids, kernel_stacks
+    );
+}
+
 #[allow(unused)]
 pub fn new_user_task_start() {
     log::debug!("new user task start");
     unsafe { 
-        drop(current_task().unwrap().take_lock())
+        current_task().unwrap().release_switch_lock();
     }
 
     
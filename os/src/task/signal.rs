@@ -1,9 +1,55 @@
-use crate::processor::get_current_processor;
+//! Signals
+//!
+//! Each task has its own pending set, blocked mask and handler table.
+//! Pending signals are delivered on the way back to user space
+//! ([`handle_signals`], called at the end of `trap_handler`):
+//! - `SIGKILL` always terminates the task
+//! - a blocked signal stays pending until it is unblocked
+//! - with a user handler, the trap context is saved and the task returns
+//!   into the handler, running on its own user stack, with `a0 = signum`
+//!   and `ra` on the `sigreturn` stub at `SIGRETURN_TRAMPOLINE`: the handler
+//!   either returns normally or calls `sigreturn` itself, both restore
+//!   the context. A handler installed with [`SA_ONSTACK`] runs on the
+//!   alternate stack of the thread instead, if it set one up with
+//!   `sigaltstack` and isn't on it already: the one way to handle the
+//!   `SIGSEGV` of a stack overflow.
+//! - otherwise the default action applies: terminate with `-signum`,
+//!   or ignore for `SIGCHLD` and `SIGTSTP`. `SIGQUIT`, `SIGABRT` and
+//!   `SIGSEGV` dump the core of the task first, see `coredump`
+//!
+//! A fault the task can't handle, its signal being blocked, ignored, or
+//! raised while a handler runs, gets the default action
+//! ([`SignalState::force`]): the faulting instruction would be retried
+//! forever otherwise.
+//!
+//! Stopping a task (`SIGSTOP`) is not supported yet, the signal is ignored.
+//! A task blocked in the kernel receives its signals once it gets back to
+//! user space: signals don't interrupt a sleep.
 
-use super::{current_task, task::TaskControlBlockInner, TaskControlBlock};
+use bitflags::bitflags;
+use strum_macros::FromRepr;
+
+use crate::{config::SIGRETURN_TRAMPOLINE, trap::TrapContext};
+
+use super::{coredump, current_task, exit_current};
+
+/// Highest signal number
+pub const MAX_SIG: usize = 31;
+
+/// `SignalAction::handler` value selecting the default action
+pub const SIG_DFL: usize = 0;
+/// `SignalAction::handler` value ignoring the signal
+pub const SIG_IGN: usize = 1;
+
+/// `how` of `sigprocmask`: add `set` to the blocked mask
+pub const SIG_BLOCK: usize = 0;
+/// `how` of `sigprocmask`: remove `set` from the blocked mask
+pub const SIG_UNBLOCK: usize = 1;
+/// `how` of `sigprocmask`: replace the blocked mask with `set`
+pub const SIG_SETMASK: usize = 2;
 
 #[repr(i32)]
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, FromRepr)]
 pub enum Signal {
     /// 1 - 终端挂断或控制进程终止 (可捕获)
     SIGHUP = 1,
@@ -15,8 +61,12 @@ pub enum Signal {
     SIGABRT = 6,
     /// 9 - 立即强制终止进程 (不可屏蔽!)
     SIGKILL = 9,
+    /// 10 - 用户自定义信号 1
+    SIGUSR1 = 10,
     /// 11 - 非法内存访问 (段错误)
     SIGSEGV = 11,
+    /// 12 - 用户自定义信号 2
+    SIGUSR2 = 12,
     /// 13 - 向无读端的管道写入
     SIGPIPE = 13,
     /// 14 - 定时器超时 (alarm/setitimer)
@@ -46,15 +96,22 @@ impl Signal {
         }
     }
 
+    /// 判断信号的默认动作是否生成核心转储 (见 `coredump`)
+    pub fn dumps_core(&self) -> bool {
+        matches!(self, Signal::SIGQUIT | Signal::SIGABRT | Signal::SIGSEGV)
+    }
+
     /// 获取信号描述 (兼容 strsignal(3))
     pub fn description(&self) -> &'static str {
         match self {
             Signal::SIGHUP => "Hangup",
             Signal::SIGINT => "Interrupt",
-            Signal::SIGQUIT => "Quit (core dumped)",
+            Signal::SIGQUIT => "Quit",
             Signal::SIGABRT => "Aborted",
             Signal::SIGKILL => "Killed",
+            Signal::SIGUSR1 => "User defined signal 1",
             Signal::SIGSEGV => "Segmentation fault",
+            Signal::SIGUSR2 => "User defined signal 2",
             Signal::SIGPIPE => "Broken pipe",
             Signal::SIGALRM => "Alarm clock",
             Signal::SIGTERM => "Terminated",
@@ -65,11 +122,267 @@ impl Signal {
     }
 }
 
-impl TaskControlBlock {
-    pub fn handler_signal(&mut self, signal: Signal) {
-        match signal {
-            Signal::SIGTERM => get_current_processor().exit_current(-1),
-            _ => unreachable!()
+impl Signal {
+    pub fn from_signum(signum: usize) -> Option<Self> {
+        i32::try_from(signum).ok().and_then(Signal::from_repr)
+    }
+
+    pub fn flag(&self) -> SignalFlags {
+        SignalFlags::from_bits_truncate(1 << (*self as u32))
+    }
+}
+
+bitflags! {
+    /// A set of signals, bit `n` stands for signal `n`
+    pub struct SignalFlags: u32 {
+        const SIGHUP  = 1 << 1;
+        const SIGINT  = 1 << 2;
+        const SIGQUIT = 1 << 3;
+        const SIGABRT = 1 << 6;
+        const SIGKILL = 1 << 9;
+        const SIGUSR1 = 1 << 10;
+        const SIGSEGV = 1 << 11;
+        const SIGUSR2 = 1 << 12;
+        const SIGPIPE = 1 << 13;
+        const SIGALRM = 1 << 14;
+        const SIGTERM = 1 << 15;
+        const SIGCHLD = 1 << 17;
+        const SIGSTOP = 1 << 19;
+        const SIGTSTP = 1 << 20;
+    }
+}
+
+/// `SignalAction::flags`: run the handler on the alternate stack
+pub const SA_ONSTACK: u32 = 0x0800_0000;
+
+/// Disposition of one signal, layout shared with user space.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SignalAction {
+    /// Handler address, or `SIG_DFL` / `SIG_IGN`
+    pub handler: usize,
+    /// Signals blocked while the handler runs, on top of the signal itself
+    pub mask: SignalFlags,
+    /// `SA_*` flags, [`SA_ONSTACK`] is the only one known
+    pub flags: u32,
+}
+
+impl Default for SignalAction {
+    fn default() -> Self {
+        Self {
+            handler: SIG_DFL,
+            mask: SignalFlags::empty(),
+            flags: 0,
+        }
+    }
+}
+
+/// `SignalStack::flags`: the thread is running on the stack, as read back
+pub const SS_ONSTACK: i32 = 1;
+/// `SignalStack::flags`: no alternate stack
+pub const SS_DISABLE: i32 = 2;
+/// Smallest alternate stack `sigaltstack` takes, as on Linux
+pub const MINSIGSTKSZ: usize = 2048;
+
+/// An alternate signal stack as `sigaltstack` takes and gives it, the
+/// `stack_t` of Linux
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SignalStack {
+    /// Lowest address of the stack
+    pub sp: usize,
+    pub flags: i32,
+    pub size: usize,
+}
+
+/// The alternate signal stack of a thread, see [`SA_ONSTACK`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AltStack {
+    pub base: usize,
+    pub size: usize,
+}
+
+impl AltStack {
+    /// Where a handler starts, aligned as the psABI wants it
+    pub fn top(&self) -> usize {
+        (self.base + self.size) & !0xf
+    }
+
+    /// Whether a thread at `sp` is running on the stack: the stack pointer
+    /// of a full stack is at its base, of an empty one at its top
+    pub fn contains(&self, sp: usize) -> bool {
+        sp > self.base && sp - self.base <= self.size
+    }
+}
+
+/// Per-task signal state, lives in `TaskControlBlockInner`.
+#[derive(Clone)]
+pub struct SignalState {
+    pub pending: SignalFlags,
+    pub blocked: SignalFlags,
+    pub actions: [SignalAction; MAX_SIG + 1],
+    /// Context and blocked mask to restore at `sigreturn`,
+    /// `Some` while a handler is running
+    saved: Option<(TrapContext, SignalFlags)>,
+}
+
+impl SignalState {
+    pub fn new() -> Self {
+        Self {
+            pending: SignalFlags::empty(),
+            blocked: SignalFlags::empty(),
+            actions: [SignalAction::default(); MAX_SIG + 1],
+            saved: None,
+        }
+    }
+
+    /// State of a forked child: same handlers and mask, nothing pending.
+    pub fn fork(&self) -> Self {
+        Self {
+            pending: SignalFlags::empty(),
+            blocked: self.blocked,
+            actions: self.actions,
+            saved: None,
+        }
+    }
+
+    /// State after `exec`: the handlers pointed into the old image.
+    pub fn exec(&mut self) {
+        for action in self.actions.iter_mut() {
+            if action.handler != SIG_IGN {
+                *action = SignalAction::default();
+            }
+        }
+        self.saved = None;
+    }
+
+    pub fn raise(&mut self, signal: Signal) {
+        self.pending.insert(signal.flag());
+    }
+
+    /// Raise `signal` for a fault of the task. One it can't handle now,
+    /// blocked, ignored, or raised in a handler, gets the default action.
+    pub fn force(&mut self, signal: Signal) {
+        let action = &mut self.actions[signal as usize];
+        if self.blocked.contains(signal.flag()) || action.handler == SIG_IGN || self.saved.is_some() {
+            *action = SignalAction::default();
+            self.blocked.remove(signal.flag());
+        }
+        self.raise(signal);
+    }
+
+    pub fn is_pending(&self, signal: Signal) -> bool {
+        self.pending.contains(signal.flag())
+    }
+
+    /// Take the next signal to act on, lowest number first.
+    ///
+    /// While a handler runs, no other one is entered: only `SIGKILL`, and
+    /// the signals of default action, are delivered.
+    fn take_deliverable(&mut self) -> Option<Signal> {
+        let mut deliverable = self.pending - (self.blocked - SignalFlags::SIGKILL);
+        if self.saved.is_some() {
+            let defaulted = (1..=MAX_SIG)
+                .filter(|&signum| self.actions[signum].handler == SIG_DFL)
+                .fold(0, |bits, signum| bits | 1 << signum);
+            deliverable &= SignalFlags::SIGKILL | SignalFlags::from_bits_truncate(defaulted);
+        }
+        let signum = (1..=MAX_SIG).find(|&signum| deliverable.bits() & (1 << signum) != 0)?;
+        self.pending.remove(SignalFlags::from_bits_truncate(1 << signum));
+        Signal::from_signum(signum)
+    }
+
+    pub fn set_mask(&mut self, how: usize, set: SignalFlags) -> Option<SignalFlags> {
+        let old = self.blocked;
+        let blocked = match how {
+            SIG_BLOCK => old | set,
+            SIG_UNBLOCK => old - set,
+            SIG_SETMASK => set,
+            _ => return None,
+        };
+        // SIGKILL and SIGSTOP can't be blocked
+        self.blocked = blocked - (SignalFlags::SIGKILL | SignalFlags::SIGSTOP);
+        Some(old)
+    }
+
+    /// Restore the context saved when the running handler was entered.
+    pub fn restore(&mut self, trap_context: &mut TrapContext) -> bool {
+        match self.saved.take() {
+            Some((saved_context, saved_blocked)) => {
+                *trap_context = saved_context;
+                self.blocked = saved_blocked;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Act on the pending signals of the current task.
+///
+/// Called right before returning to user space, may not return if the
+/// task gets terminated.
+pub fn handle_signals() {
+    let Some(task) = current_task() else {
+        return;
+    };
+
+    loop {
+        let mut inner = task.lock();
+        let Some(signal) = inner.signals.take_deliverable() else {
+            return;
+        };
+
+        if signal == Signal::SIGKILL {
+            drop(inner);
+            // a leader killed by `exit_group` in another thread
+            exit_current(task.group_exit_code().unwrap_or(-(signal as i32)));
+            unreachable!();
+        }
+
+        let action = inner.signals.actions[signal as usize];
+        match action.handler {
+            SIG_IGN => continue,
+            SIG_DFL => {
+                if signal == Signal::SIGSTOP {
+                    log::warn!("task {}: SIGSTOP is not supported, ignored", task.get_name());
+                    continue;
+                }
+                if !signal.is_fatal() {
+                    continue;
+                }
+                drop(inner);
+                let dumped = signal.dumps_core() && coredump::dump(&task, signal);
+                log::info!(
+                    "task {} terminated: {}{}",
+                    task.get_name(),
+                    signal.description(),
+                    if dumped { " (core dumped)" } else { "" }
+                );
+                exit_current(-(signal as i32));
+                unreachable!();
+            }
+            handler => {
+                let (trap_context, alt_stack): (&mut TrapContext, _) = inner.with_user_res(|user_res| {
+                    (user_res.trap_context_ppn().get_mut(), user_res.alt_stack)
+                });
+                let old_blocked = inner.signals.blocked;
+                inner.signals.saved = Some((trap_context.clone(), old_blocked));
+                inner.signals.blocked |= signal.flag() | action.mask;
+                inner.signals.blocked -= SignalFlags::SIGKILL | SignalFlags::SIGSTOP;
+
+                // enter the handler on the current user stack, or the
+                // alternate one, returning from it runs `sigreturn`
+                if action.flags & SA_ONSTACK != 0 {
+                    if let Some(alt_stack) = alt_stack.filter(|alt_stack| !alt_stack.contains(trap_context.x[2])) {
+                        trap_context.set_sp(alt_stack.top());
+                    }
+                }
+                trap_context.sepc = handler;
+                trap_context.x[10] = signal as usize;
+                trap_context.x[1] = SIGRETURN_TRAMPOLINE;
+                return;
+            }
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,255 @@
+//! Per-task and per-group accounting
+//!
+//! Every task counts its own cpu time, syscalls and context switches in a
+//! [`TaskStats`], lock free so the hot paths never touch the task lock.
+//! The scheduler also records how long the task waited in a ready queue,
+//! and whether it left the cpu asleep (voluntary) or still runnable
+//! (involuntary: preempted, or yielding). They are read by
+//! [`sys_sched_stat`] and from `/proc/<tid>/sched`.
+//!
+//! A task group (the threads sharing a leader) shares one [`GroupStats`]:
+//! - a thread leaving the group folds its counters into `exited`
+//! - the group total is `exited` plus every live member, summed on demand
+//!
+//! Memory is a property of the shared address space, it is not summed.
+
+use core::{
+    ops::AddAssign,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use alloc::sync::Arc;
+use os_macros::{kernel_test, syscall_register};
+
+use crate::{
+    mm::user_ptr::UserPtr,
+    sync::spin::mutex::IRQSpinLock,
+    syscall::error::Errno,
+    timer::{cycles_to_ns, get_time},
+};
+
+use super::{current_task, find_task, task::TaskState, TaskControlBlock};
+
+type Mutex<T> = IRQSpinLock<T>;
+
+/// Counters of a single task
+pub struct TaskStats {
+    /// Time spent running, in timer cycles
+    cpu_cycles: AtomicUsize,
+    syscalls: AtomicUsize,
+    switches: AtomicUsize,
+    /// `get_time()` when the task was last switched in
+    switched_in: AtomicUsize,
+    /// Time spent ready in a run queue, in timer cycles
+    wait_cycles: AtomicUsize,
+    /// `get_time()` when the task was put in a run queue, 0 out of one
+    enqueued: AtomicUsize,
+    voluntary_switches: AtomicUsize,
+    involuntary_switches: AtomicUsize,
+}
+
+/// Scheduling counters of a task, as written by [`sys_sched_stat`]
+///
+/// Layout shared with user space.
+#[derive(Debug, Default, Clone, Copy)]
+#[repr(C)]
+pub struct SchedStat {
+    /// Time spent running, in ns
+    pub run_ns: u64,
+    /// Time spent ready in a run queue, in ns
+    pub wait_ns: u64,
+    /// Times switched in
+    pub switches: u64,
+    /// Times switched out asleep
+    pub voluntary_switches: u64,
+    /// Times switched out still runnable
+    pub involuntary_switches: u64,
+}
+
+/// A plain copy of some counters, summable
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StatsSnapshot {
+    pub cpu_cycles: usize,
+    pub syscalls: usize,
+    pub switches: usize,
+}
+
+impl AddAssign for StatsSnapshot {
+    fn add_assign(&mut self, other: Self) {
+        self.cpu_cycles += other.cpu_cycles;
+        self.syscalls += other.syscalls;
+        self.switches += other.switches;
+    }
+}
+
+impl TaskStats {
+    pub const fn new() -> Self {
+        Self {
+            cpu_cycles: AtomicUsize::new(0),
+            syscalls: AtomicUsize::new(0),
+            switches: AtomicUsize::new(0),
+            switched_in: AtomicUsize::new(0),
+            wait_cycles: AtomicUsize::new(0),
+            enqueued: AtomicUsize::new(0),
+            voluntary_switches: AtomicUsize::new(0),
+            involuntary_switches: AtomicUsize::new(0),
+        }
+    }
+
+    /// The task was put in a run queue.
+    pub fn on_enqueue(&self) {
+        self.enqueued.store(get_time(), Ordering::Relaxed);
+    }
+
+    /// The scheduler is about to switch to this task.
+    pub fn on_switch_in(&self) {
+        let now = get_time();
+        let enqueued = self.enqueued.swap(0, Ordering::Relaxed);
+        if enqueued != 0 {
+            self.wait_cycles.fetch_add(now.saturating_sub(enqueued), Ordering::Relaxed);
+        }
+        self.switches.fetch_add(1, Ordering::Relaxed);
+        self.switched_in.store(now, Ordering::Relaxed);
+    }
+
+    /// The task just switched back to the scheduler, leaving the cpu in `state`.
+    pub fn on_switch_out(&self, state: TaskState) {
+        let ran = get_time().saturating_sub(self.switched_in.load(Ordering::Relaxed));
+        self.cpu_cycles.fetch_add(ran, Ordering::Relaxed);
+        match state {
+            TaskState::Blocking => self.voluntary_switches.fetch_add(1, Ordering::Relaxed),
+            TaskState::Ready => self.involuntary_switches.fetch_add(1, Ordering::Relaxed),
+            _ => 0,
+        };
+    }
+
+    /// The scheduling counters, with the slice a `running` task is in
+    /// and the wait of a queued one counted so far.
+    pub fn sched_stat(&self, running: bool) -> SchedStat {
+        let now = get_time();
+        let mut run = self.cpu_cycles.load(Ordering::Relaxed);
+        if running {
+            run += now.saturating_sub(self.switched_in.load(Ordering::Relaxed));
+        }
+        let mut wait = self.wait_cycles.load(Ordering::Relaxed);
+        let enqueued = self.enqueued.load(Ordering::Relaxed);
+        if enqueued != 0 {
+            wait += now.saturating_sub(enqueued);
+        }
+        SchedStat {
+            run_ns: cycles_to_ns(run),
+            wait_ns: cycles_to_ns(wait),
+            switches: self.switches.load(Ordering::Relaxed) as u64,
+            voluntary_switches: self.voluntary_switches.load(Ordering::Relaxed) as u64,
+            involuntary_switches: self.involuntary_switches.load(Ordering::Relaxed) as u64,
+        }
+    }
+
+    pub fn on_syscall(&self) {
+        self.syscalls.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            cpu_cycles: self.cpu_cycles.load(Ordering::Relaxed),
+            syscalls: self.syscalls.load(Ordering::Relaxed),
+            switches: self.switches.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Roll-up of a task group, shared by all its members
+pub struct GroupStats {
+    /// Sum over the threads that already left the group
+    exited: Mutex<StatsSnapshot>,
+}
+
+impl GroupStats {
+    pub const fn new() -> Self {
+        Self {
+            exited: Mutex::new(StatsSnapshot {
+                cpu_cycles: 0,
+                syscalls: 0,
+                switches: 0,
+            }),
+        }
+    }
+
+    /// Account a thread leaving the group.
+    pub fn fold(&self, stats: &TaskStats) {
+        *self.exited.lock() += stats.snapshot();
+    }
+
+    /// Sum over the live `members` and every thread that left.
+    pub fn total(&self, members: &[Arc<TaskControlBlock>]) -> StatsSnapshot {
+        let mut total = *self.exited.lock();
+        for member in members {
+            total += member.stats().snapshot();
+        }
+        total
+    }
+}
+
+/// Store the scheduling counters of task `tid` in `*stat`, of the caller
+/// if `tid` is 0.
+///
+/// # Returns
+/// - 0 on success
+/// - `-ESRCH` if there is no task `tid`
+/// - `-EFAULT` if `stat` is not mapped writable
+#[syscall_register(SYSCALL_SCHED_STAT)]
+pub fn sys_sched_stat(tid: usize, stat: UserPtr<SchedStat>) -> isize {
+    let task = if tid == 0 {
+        current_task().unwrap()
+    } else {
+        match find_task(tid) {
+            Some(task) => task,
+            None => return Errno::ESRCH.as_ret(),
+        }
+    };
+    let running = task.lock().get_state() == TaskState::Running;
+    match stat.write(task.stats().sched_stat(running)) {
+        Ok(()) => 0,
+        Err(_) => Errno::EFAULT.as_ret(),
+    }
+}
+
+#[kernel_test]
+fn group_stats_fold_test() {
+    let group = GroupStats::new();
+    let thread = TaskStats::new();
+    thread.on_syscall();
+    thread.on_syscall();
+    thread.on_switch_in();
+    thread.on_switch_out(TaskState::Ready);
+
+    group.fold(&thread);
+    group.fold(&thread);
+    let total = group.total(&[]);
+    assert_eq!(total.syscalls, 4);
+    assert_eq!(total.switches, 2);
+}
+
+#[kernel_test]
+fn sched_stat_test() {
+    let stats = TaskStats::new();
+    stats.on_enqueue();
+    stats.on_switch_in();
+    stats.on_switch_out(TaskState::Blocking);
+    stats.on_enqueue();
+    stats.on_switch_in();
+    stats.on_switch_out(TaskState::Ready);
+    stats.on_enqueue();
+    stats.on_switch_in();
+    stats.on_switch_out(TaskState::Zombie(0));
+
+    let stat = stats.sched_stat(false);
+    assert_eq!(stat.switches, 3);
+    assert_eq!(stat.voluntary_switches, 1);
+    assert_eq!(stat.involuntary_switches, 1);
+    // a queued task keeps waiting until it is switched in
+    stats.on_enqueue();
+    let queued = stats.sched_stat(false);
+    assert!(queued.wait_ns >= stat.wait_ns);
+    assert_eq!(queued.run_ns, stat.run_ns);
+}
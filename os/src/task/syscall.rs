@@ -1,8 +1,15 @@
+use alloc::{string::String, sync::Arc, vec, vec::Vec};
 use os_macros::syscall_register;
 
-use crate::{fs::{open_file, OpenFlags}, mm::page_table::translated_str, task::exit_current};
+use crate::{drivers::block::IoPriority, mm::{address::{VPNRange, VirtAddr}, binfmt::{Binprm, Program, ARG_MAX}, page_table::{write_to_user, PTEFlags}, user_ptr::UserPtr}, processor::{current_processor_id, get_current_processor, online_mask}, syscall::{args::{check_user_range, populate_user, read_user_str}, error::Errno}, task::exit_current};
 
-use super::{current_task, yield_current};
+use super::{
+    capture::start_capture,
+    current_task, current_user_trap_context, find_task,
+    rlimit::RLimit,
+    signal::{AltStack, Signal, SignalAction, SignalFlags, SignalStack, MINSIGSTKSZ, SS_DISABLE, SS_ONSTACK},
+    task::{CloneFlags, TaskState, ADDR_NO_RANDOMIZE, NICE_MAX, NICE_MIN}, yield_current, TaskControlBlock,
+};
 
 #[syscall_register(SYSCALL_EXIT)]
 pub fn sys_exit(exit_status: i32) -> ! {
@@ -11,26 +18,699 @@ pub fn sys_exit(exit_status: i32) -> ! {
     unreachable!()
 }
 
+/// Exit every thread of the calling task group, the group reports `exit_status`.
+///
+/// From a thread, the leader is sent `SIGKILL` and exits with the code of
+/// the group once it heads back to user space, killing the other threads.
+#[syscall_register(SYSCALL_EXIT_GROUP)]
+pub fn sys_exit_group(exit_status: i32) -> ! {
+    let current_task = current_task().unwrap();
+    if !current_task.is_leader() {
+        let leader = current_task.lock().with_user_res(|user_res| user_res.group_leader.upgrade());
+        if let Some(leader) = leader {
+            leader.set_group_exit_code(exit_status);
+            leader.lock().signal(Signal::SIGKILL);
+        }
+    }
+    exit_current(exit_status);
+    unreachable!()
+}
+
 #[syscall_register(SYSCALL_YIELD)]
 pub fn sys_yield() -> isize {
     yield_current();
     0
 }
 
-// #[syscall_register(SYSCALL_EXEC)]
-// pub fn sys_exec(path: *const u8) -> isize {
-//     let task = current_task().unwrap().lock();
-//     let token = task.user_res
-//         .as_ref().unwrap()
-//         .memory_set.lock()
-//         .token();
-//     let path = translated_str(token, path);
-//     if let Some(app_inode) = open_file(path.as_str(), OpenFlags::RDONLY) {
-//         let all_data = app_inode.read_all();
-//         let task = current_task().unwrap();
-//         task.exec(all_data.as_slice());
-//         0
-//     } else {
-//         -1
-//     }
-// }
\ No newline at end of file
+/// The pid of the caller, the tid of its group leader
+#[syscall_register(SYSCALL_GETPID)]
+pub fn sys_getpid() -> isize {
+    usize::from(current_task().unwrap().get_pid()) as isize
+}
+
+/// The pid of the parent of the caller, 0 if it has none
+#[syscall_register(SYSCALL_GETPPID)]
+pub fn sys_getppid() -> isize {
+    current_task().unwrap().get_ppid().map_or(0, usize::from) as isize
+}
+
+/// The tid of the caller, its pid if it is the group leader
+#[syscall_register(SYSCALL_GETTID)]
+pub fn sys_gettid() -> isize {
+    usize::from(current_task().unwrap().get_tid()) as isize
+}
+
+/// The low byte of `clone` flags, the signal a child sends on exit, ignored
+const CSIGNAL: usize = 0xff;
+
+/// Create a process (`fork`) or a thread.
+///
+/// - no flags: a copy of the caller in a new task group, like `fork`
+/// - `CLONE_VM | CLONE_THREAD`: a thread of the caller's group, sharing its
+///   address space, fd table and heap (`CLONE_FS` and `CLONE_FILES` are
+///   implied and accepted). It starts on `stack`, 16 bytes aligned, or on
+///   a fresh user stack slot if `stack` is 0.
+///
+/// Returns the tid of the new task in the caller and 0 in the new task,
+/// `-EINVAL` for any other combination of flags, `-EAGAIN` if the group
+/// has no free user stack slot for a thread.
+#[syscall_register(SYSCALL_CLONE)]
+pub fn sys_clone(flags: usize, stack: usize) -> isize {
+    let Some(flags) = u32::try_from(flags & !CSIGNAL).ok().and_then(CloneFlags::from_bits) else {
+        return Errno::EINVAL.as_ret();
+    };
+    if flags.is_empty() {
+        return sys_fork();
+    }
+    let thread_flags = CloneFlags::CLONE_VM | CloneFlags::CLONE_THREAD;
+    let optional = CloneFlags::CLONE_FS | CloneFlags::CLONE_FILES;
+    if !flags.contains(thread_flags) || !(thread_flags | optional).contains(flags) || stack % 16 != 0 {
+        return Errno::EINVAL.as_ret();
+    }
+
+    let current_task = current_task().unwrap();
+    let Some(thread) = current_task.clone_thread(stack) else {
+        return Errno::EAGAIN.as_ret();
+    };
+    let thread_tid: usize = thread.get_tid().into();
+    get_current_processor().add_task(thread);
+    thread_tid as isize
+}
+
+/// `fork`, which riscv64 only has as `clone` without flags: the child gets
+/// a copy of the address space, the fd table and the trap context of the
+/// caller, with 0 in `a0`, and is made runnable at once. Called from a
+/// thread, the child is a single task on the copy of that thread's stack.
+fn sys_fork() -> isize {
+    let current_task = current_task().unwrap();
+    let child = current_task.fork();
+    let child_tid: usize = child.get_tid().into();
+    if current_task.lock().capture_children {
+        // before the child is runnable, none of its output is missed
+        start_capture(&child, current_task.get_tid().into());
+    }
+
+    get_current_processor().add_task(child);
+    child_tid as isize
+}
+
+/// Replace the calling program with the one at `path`, run with the
+/// strings of `argv` and `envp`, arrays ending with a NULL pointer. A
+/// NULL `argv` is taken as `[path]`, a NULL `envp` as empty. The new
+/// image starts with `argc` and `argv` in `a0` and `a1`.
+///
+/// `path` is an ELF executable, a `#!` script or a flat binary, see
+/// `mm::binfmt`. Only returns on failure, the old image is left untouched
+/// then:
+/// - `-ENOENT` if `path`, or the interpreter of a script, doesn't exist
+/// - `-EFAULT` if `path`, `argv`, `envp` or one of their strings is not mapped
+/// - `-E2BIG` if the strings of `argv` and `envp` take more than `ARG_MAX` bytes
+/// - `-EACCES` if it is a directory
+/// - `-ENOEXEC` if it isn't runnable: a bad ELF, an empty file, or a
+///   script without interpreter
+/// - `-ELOOP` if scripts name each other as interpreters too deep
+#[syscall_register(SYSCALL_EXEC)]
+pub fn sys_exec(path: *const u8, argv: *const usize, envp: *const usize) -> isize {
+    let current_task = current_task().unwrap();
+    let token = current_task.lock().get_user_token();
+    let Ok(path) = read_user_str(path) else {
+        return Errno::EFAULT.as_ret();
+    };
+    let strings = |array: *const usize| read_string_array(token, array);
+    let argv = match strings(argv) {
+        Ok(_) if argv.is_null() => vec![path.clone()],
+        Ok(argv) => argv,
+        Err(errno) => return errno.as_ret(),
+    };
+    let envp = match strings(envp) {
+        Ok(envp) => envp,
+        Err(errno) => return errno.as_ret(),
+    };
+
+    // checked before the current image is torn down
+    let mut prm = match Binprm::open(path.as_str(), argv, envp) {
+        Ok(prm) => prm,
+        Err(errno) => return errno.as_ret(),
+    };
+    let mut program = match Program::load(&mut prm) {
+        Ok(program) => program,
+        Err(errno) => return errno.as_ret(),
+    };
+    current_task.exec(&mut program);
+    // returned in `a0` of the new image, which starts with `argc` there
+    program.argv.len() as isize
+}
+
+/// The strings of the NULL-terminated array of pointers `array` in the
+/// address space `token`, none for a NULL `array`.
+///
+/// `EFAULT` if a pointer or a string isn't mapped, `E2BIG` once there
+/// are more strings than `ARG_MAX` has room for.
+fn read_string_array(token: usize, array: *const usize) -> Result<Vec<String>, Errno> {
+    let mut strings = Vec::new();
+    if array.is_null() {
+        return Ok(strings);
+    }
+    loop {
+        let entry = array.wrapping_add(strings.len());
+        populate_user(entry as usize, size_of::<usize>())?;
+        let string = UserPtr::new(token, entry).read().map_err(|_| Errno::EFAULT)?;
+        if string == 0 {
+            return Ok(strings);
+        }
+        if strings.len() == ARG_MAX / size_of::<usize>() {
+            return Err(Errno::E2BIG);
+        }
+        strings.push(read_user_str(string as *const u8)?);
+    }
+}
+
+/// `options` bit of `waitpid`: return at once if no child has exited
+const WNOHANG: usize = 1;
+
+/// Wait for a child to exit and collect its exit code.
+///
+/// `pid == -1` waits for any child. The caller sleeps until a matching
+/// child exits, unless `WNOHANG` is set in `options`.
+///
+/// # Returns
+/// - The tid of the reaped child
+/// - 0 with `WNOHANG`, if matching children exist but none of them has exited yet
+/// - `-ECHILD` if there is no matching child
+/// - `-EFAULT` if `exit_code_ptr` is not mapped writable, the child is reaped still
+#[syscall_register(SYSCALL_WAITPID)]
+pub fn sys_waitpid(pid: isize, exit_code_ptr: *mut i32, options: usize) -> isize {
+    let current_task = current_task().unwrap();
+    let (token, children) = current_task.lock().with_user_res(|user_res| {
+        (user_res.memory_set.lock().token(), user_res.children.clone())
+    });
+
+    let matches = |child: &Arc<TaskControlBlock>| {
+        pid == -1 || pid as usize == usize::from(child.get_tid())
+    };
+
+    let (child, exit_code) = loop {
+        // an exit after this point makes `wait` return at once
+        let seen = current_task.child_exit().generation();
+
+        let mut children = children.lock();
+        if !children.iter().any(|child| matches(child)) {
+            return Errno::ECHILD.as_ret();
+        }
+
+        let zombie = children.iter().enumerate().find_map(|(idx, child)| {
+            match child.lock().get_state() {
+                TaskState::Zombie(exit_code) if matches(child) => Some((idx, exit_code)),
+                _ => None,
+            }
+        });
+
+        match zombie {
+            // the child's TaskHandle and kernel stack are released with it
+            Some((idx, exit_code)) => break (children.remove(idx), exit_code),
+            None if options & WNOHANG != 0 => return 0,
+            None => {
+                drop(children);
+                current_task.child_exit().wait(seen);
+            }
+        }
+    };
+
+    let child_tid: usize = child.get_tid().into();
+    // the child is reaped anyway, like on Linux
+    if !exit_code_ptr.is_null() && write_to_user(token, exit_code_ptr, &exit_code).is_err() {
+        return Errno::EFAULT.as_ret();
+    }
+    child_tid as isize
+}
+
+/// Send signal `signum` to task `pid`.
+///
+/// `-EINVAL` for an unknown `signum`, `-ESRCH` if there is no task `pid`.
+#[syscall_register(SYSCALL_KILL)]
+pub fn sys_kill(pid: usize, signum: usize) -> isize {
+    let Some(signal) = Signal::from_signum(signum) else {
+        return Errno::EINVAL.as_ret();
+    };
+    match find_task(pid) {
+        Some(task) => {
+            task.lock().signal(signal);
+            0
+        }
+        None => Errno::ESRCH.as_ret(),
+    }
+}
+
+/// Set the action of `signum` to `*action` if not null,
+/// and store the previous one in `*old_action` if not null.
+///
+/// `-EINVAL` for an unknown signal, `SIGKILL` or `SIGSTOP`, `-EFAULT` if
+/// a pointer is not mapped.
+#[syscall_register(SYSCALL_SIGACTION)]
+pub fn sys_sigaction(
+    signum: usize,
+    action: *const SignalAction,
+    old_action: *mut SignalAction,
+) -> isize {
+    let Some(signal) = Signal::from_signum(signum) else {
+        return Errno::EINVAL.as_ret();
+    };
+    if signal.is_unmaskable() {
+        return Errno::EINVAL.as_ret();
+    }
+
+    let current_task = current_task().unwrap();
+    let token = current_task.lock().get_user_token();
+
+    let new_action = if action.is_null() {
+        None
+    } else {
+        match UserPtr::new(token, action).read() {
+            Ok(mut action) => {
+                // drop bits of unknown signals
+                action.mask = SignalFlags::from_bits_truncate(action.mask.bits());
+                Some(action)
+            }
+            Err(_) => return Errno::EFAULT.as_ret(),
+        }
+    };
+
+    let previous = {
+        let mut inner = current_task.lock();
+        let previous = inner.signals.actions[signum];
+        if let Some(new_action) = new_action {
+            inner.signals.actions[signum] = new_action;
+        }
+        previous
+    };
+
+    if !old_action.is_null() && write_to_user(token, old_action, &previous).is_err() {
+        return Errno::EFAULT.as_ret();
+    }
+    0
+}
+
+/// Set the alternate signal stack of the thread to `*stack` if not null,
+/// and store the previous one in `*old_stack` if not null.
+///
+/// The stack has to be mapped writable, its pages are faulted in at once:
+/// a handler running on it after a stack overflow doesn't fault again.
+/// `SS_DISABLE` in `flags` removes it.
+///
+/// # Returns
+/// - 0 on success
+/// - `-EPERM` if the thread is running on its alternate stack
+/// - `-EINVAL` for flags other than `SS_DISABLE`, or a stack going past
+///   the user half of the address space
+/// - `-ENOMEM` for a stack smaller than `MINSIGSTKSZ`
+/// - `-EFAULT` if a pointer, or the stack, is not mapped
+#[syscall_register(SYSCALL_SIGALTSTACK)]
+pub fn sys_sigaltstack(stack: *const SignalStack, old_stack: *mut SignalStack) -> isize {
+    let current_task = current_task().unwrap();
+    let token = current_task.lock().get_user_token();
+    let sp = current_user_trap_context().x[2];
+
+    let new_stack = if stack.is_null() {
+        None
+    } else {
+        match UserPtr::new(token, stack).read() {
+            Ok(stack) => Some(stack),
+            Err(_) => return Errno::EFAULT.as_ret(),
+        }
+    };
+
+    let previous = current_task.lock().with_user_res(|user_res| {
+        let previous = user_res.alt_stack;
+        if let Some(new_stack) = new_stack {
+            if previous.is_some_and(|alt_stack| alt_stack.contains(sp)) {
+                return Err(Errno::EPERM);
+            }
+            user_res.alt_stack = match new_stack.flags {
+                SS_DISABLE => None,
+                0 if new_stack.size < MINSIGSTKSZ => return Err(Errno::ENOMEM),
+                // the end is computed below, it must not wrap
+                0 if check_user_range(new_stack.sp, new_stack.size).is_err() => return Err(Errno::EINVAL),
+                0 => {
+                    let mut memory_set = user_res.memory_set.lock();
+                    let writable = memory_set.populate_range(new_stack.sp, new_stack.size).is_ok()
+                        && VPNRange::new(
+                            VirtAddr::from(new_stack.sp).down_to_vpn(),
+                            VirtAddr::from(new_stack.sp + new_stack.size).up_to_vpn(),
+                        )
+                        .into_iter()
+                        .all(|vpn| {
+                            memory_set
+                                .translate(vpn)
+                                .is_some_and(|pte| pte.is_valid() && pte.flags().contains(PTEFlags::U | PTEFlags::W))
+                        });
+                    if !writable {
+                        return Err(Errno::EFAULT);
+                    }
+                    Some(AltStack { base: new_stack.sp, size: new_stack.size })
+                }
+                _ => return Err(Errno::EINVAL),
+            };
+        }
+        Ok(previous)
+    });
+    let previous = match previous {
+        Ok(previous) => previous,
+        Err(errno) => return errno.as_ret(),
+    };
+
+    let previous = match previous {
+        Some(alt_stack) => SignalStack {
+            sp: alt_stack.base,
+            flags: if alt_stack.contains(sp) { SS_ONSTACK } else { 0 },
+            size: alt_stack.size,
+        },
+        None => SignalStack { sp: 0, flags: SS_DISABLE, size: 0 },
+    };
+    if !old_stack.is_null() && write_to_user(token, old_stack, &previous).is_err() {
+        return Errno::EFAULT.as_ret();
+    }
+    0
+}
+
+/// Change the blocked mask as selected by `how` (`SIG_BLOCK`,
+/// `SIG_UNBLOCK` or `SIG_SETMASK`), storing the previous one in `*old_set`.
+/// A null `set` only queries the mask.
+///
+/// `-EINVAL` for an unknown `how`, `-EFAULT` if a pointer is not mapped.
+#[syscall_register(SYSCALL_SIGPROCMASK)]
+pub fn sys_sigprocmask(how: usize, set: *const u32, old_set: *mut u32) -> isize {
+    let current_task = current_task().unwrap();
+    let token = current_task.lock().get_user_token();
+
+    let new_set = if set.is_null() {
+        None
+    } else {
+        match UserPtr::new(token, set).read() {
+            Ok(bits) => Some(SignalFlags::from_bits_truncate(bits)),
+            Err(_) => return Errno::EFAULT.as_ret(),
+        }
+    };
+
+    let previous = {
+        let mut inner = current_task.lock();
+        match new_set {
+            Some(new_set) => match inner.signals.set_mask(how, new_set) {
+                Some(previous) => previous,
+                None => return Errno::EINVAL.as_ret(),
+            },
+            None => inner.signals.blocked,
+        }
+    };
+
+    if !old_set.is_null() && write_to_user(token, old_set, &previous.bits()).is_err() {
+        return Errno::EFAULT.as_ret();
+    }
+    0
+}
+
+/// Return from a signal handler to where the task was interrupted,
+/// `-EINVAL` if no handler is running.
+#[syscall_register(SYSCALL_SIGRETURN)]
+pub fn sys_sigreturn() -> isize {
+    let current_task = current_task().unwrap();
+    let trap_context = current_user_trap_context();
+    if current_task.lock().signals.restore(trap_context) {
+        // the syscall result goes to a0, give back the interrupted one
+        trap_context.x[10] as isize
+    } else {
+        Errno::EINVAL.as_ret()
+    }
+}
+
+/// `which` value of `ioprio_set` / `ioprio_get` naming a single task
+const IOPRIO_WHO_PROCESS: usize = 1;
+
+/// The task an ioprio call targets, `who == 0` being the caller
+///
+/// `EINVAL` for an unsupported `which`, `ESRCH` if there is no task `who`.
+fn ioprio_target(which: usize, who: usize) -> Result<Arc<TaskControlBlock>, Errno> {
+    if which != IOPRIO_WHO_PROCESS {
+        return Err(Errno::EINVAL);
+    }
+    find_target(who)
+}
+
+/// The caller if `who == 0`, task `who` otherwise
+fn find_target(who: usize) -> Result<Arc<TaskControlBlock>, Errno> {
+    match who {
+        0 => Ok(current_task().unwrap().clone()),
+        tid => find_task(tid).ok_or(Errno::ESRCH),
+    }
+}
+
+/// Set the I/O priority of a task, `ioprio` is `class << 13 | level`.
+#[syscall_register(SYSCALL_IOPRIO_SET)]
+pub fn sys_ioprio_set(which: usize, who: usize, ioprio: usize) -> isize {
+    let Some(priority) = IoPriority::from_raw(ioprio) else {
+        return Errno::EINVAL.as_ret();
+    };
+    match ioprio_target(which, who) {
+        Ok(task) => {
+            task.lock().io_priority = priority;
+            0
+        }
+        Err(errno) => errno.as_ret(),
+    }
+}
+
+#[syscall_register(SYSCALL_IOPRIO_GET)]
+pub fn sys_ioprio_get(which: usize, who: usize) -> isize {
+    match ioprio_target(which, who) {
+        Ok(task) => task.lock().io_priority.to_raw() as isize,
+        Err(errno) => errno.as_ret(),
+    }
+}
+
+/// `which` value of `setpriority` / `getpriority` naming a single task
+const PRIO_PROCESS: usize = 0;
+
+/// Set the `nice` of a task (`who == 0` for the caller), clamped to -20..=19.
+///
+/// Only matters under the priority scheduler, and from the next time
+/// the task is enqueued.
+#[syscall_register(SYSCALL_SETPRIORITY)]
+pub fn sys_setpriority(which: usize, who: usize, nice: isize) -> isize {
+    if which != PRIO_PROCESS {
+        return Errno::EINVAL.as_ret();
+    }
+    match find_target(who) {
+        Ok(task) => {
+            task.set_nice(nice.clamp(NICE_MIN as isize, NICE_MAX as isize) as i32);
+            0
+        }
+        Err(errno) => errno.as_ret(),
+    }
+}
+
+/// Get the `nice` of a task, as `20 - nice` like Linux: a positive value
+/// can't be mistaken for an error.
+#[syscall_register(SYSCALL_GETPRIORITY)]
+pub fn sys_getpriority(which: usize, who: usize) -> isize {
+    if which != PRIO_PROCESS {
+        return Errno::EINVAL.as_ret();
+    }
+    match find_target(who) {
+        Ok(task) => (20 - task.nice()) as isize,
+        Err(errno) => errno.as_ret(),
+    }
+}
+
+/// Pin task `pid` (0 for the caller) to the harts of the mask at `mask`,
+/// bit `n` for hart `n`, `len` bytes long.
+///
+/// A hart fetches only the ready tasks its bit is set in. The caller
+/// moves at once when it leaves out the hart it runs on, another task
+/// running on such a hart moves once it switches out. Threads and forked
+/// children start with the mask of their creator.
+///
+/// # Returns
+/// - 0 on success
+/// - `-EINVAL` if `len` is shorter than the mask, or the mask has no
+///   online hart
+/// - `-ESRCH` if there is no task `pid`
+/// - `-EFAULT` if `mask` is not mapped
+#[syscall_register(SYSCALL_SCHED_SETAFFINITY)]
+pub fn sys_sched_setaffinity(pid: usize, len: usize, mask: UserPtr<usize>) -> isize {
+    if len < core::mem::size_of::<usize>() {
+        return Errno::EINVAL.as_ret();
+    }
+    let Ok(mask) = mask.read() else {
+        return Errno::EFAULT.as_ret();
+    };
+    if mask & online_mask() == 0 {
+        return Errno::EINVAL.as_ret();
+    }
+    let task = match find_target(pid) {
+        Ok(task) => task,
+        Err(errno) => return errno.as_ret(),
+    };
+    task.set_affinity(mask);
+    if Arc::ptr_eq(&task, current_task().unwrap()) && !task.runs_on(current_processor_id().into()) {
+        yield_current();
+    }
+    0
+}
+
+/// Store the harts task `pid` (0 for the caller) may run on at `mask`,
+/// `len` bytes long, as `sched_setaffinity` takes them. Only online
+/// harts are reported.
+///
+/// # Returns
+/// - The size of the mask written
+/// - `-EINVAL` if `len` is shorter than the mask
+/// - `-ESRCH` if there is no task `pid`
+/// - `-EFAULT` if `mask` is not mapped
+#[syscall_register(SYSCALL_SCHED_GETAFFINITY)]
+pub fn sys_sched_getaffinity(pid: usize, len: usize, mask: UserPtr<usize>) -> isize {
+    if len < core::mem::size_of::<usize>() {
+        return Errno::EINVAL.as_ret();
+    }
+    let task = match find_target(pid) {
+        Ok(task) => task,
+        Err(errno) => return errno.as_ret(),
+    };
+    match mask.write(task.affinity() & online_mask()) {
+        Ok(()) => core::mem::size_of::<usize>() as isize,
+        Err(_) => Errno::EFAULT.as_ret(),
+    }
+}
+
+/// Store the hart the caller runs on at `cpu`, and its NUMA node, always
+/// 0, at `node`. Either may be null, the third argument is ignored.
+///
+/// The caller may have moved by the time it reads the result, unless
+/// pinned to a single hart.
+///
+/// # Returns
+/// - 0 on success
+/// - `-EFAULT` if `cpu` or `node` is not mapped
+#[syscall_register(SYSCALL_GETCPU)]
+pub fn sys_getcpu(cpu: *mut u32, node: *mut u32) -> isize {
+    let hart: usize = current_processor_id().into();
+    let token = current_task().unwrap().lock().get_user_token();
+    for (ptr, value) in [(cpu, hart as u32), (node, 0)] {
+        if !ptr.is_null() && UserPtr::new(token, ptr as *const u32).write(value).is_err() {
+            return Errno::EFAULT.as_ret();
+        }
+    }
+    0
+}
+
+/// Execution domain of `personality`: the only one there is
+const PER_LINUX: u32 = 0;
+/// `persona` of `personality` that only queries
+const PERSONALITY_QUERY: u32 = 0xffff_ffff;
+
+/// Set the personality of the caller to `persona`, returns the previous
+/// one. `0xffffffff` just queries it.
+///
+/// The personality is kept across `fork` and `exec`. Of its flags, only
+/// `ADDR_NO_RANDOMIZE` has an effect: the next `exec` lays the address
+/// space out without randomization.
+///
+/// # Returns
+/// - The previous personality
+/// - `-EINVAL` for an execution domain other than `PER_LINUX`, or an unknown flag
+#[syscall_register(SYSCALL_PERSONALITY)]
+pub fn sys_personality(persona: u32) -> isize {
+    let current_task = current_task().unwrap();
+    let mut task = current_task.lock();
+    let user_res = task.user_res.as_mut().unwrap();
+    let previous = user_res.personality;
+    if persona != PERSONALITY_QUERY {
+        if persona & 0xff != PER_LINUX || persona & !(0xff | ADDR_NO_RANDOMIZE) != 0 {
+            return Errno::EINVAL.as_ret();
+        }
+        user_res.personality = persona;
+    }
+    previous as isize
+}
+
+/// Get the limit of `resource` of the process of task `pid` (0 for the
+/// caller) into `*old_limit`, then set it to `*new_limit`. Either pointer
+/// may be null. Resources are `RLIMIT_STACK`, `RLIMIT_CORE`,
+/// `RLIMIT_NOFILE` and `RLIMIT_AS`, see `task::rlimit`.
+///
+/// # Returns
+/// - `-EINVAL` for another resource, or a soft limit above the hard one
+/// - `-EPERM` if the hard limit is raised
+/// - `-ESRCH` if there is no user task `pid`
+/// - `-EFAULT` if a pointer is not mapped
+#[syscall_register(SYSCALL_PRLIMIT)]
+pub fn sys_prlimit(pid: usize, resource: usize, new_limit: *const RLimit, old_limit: *mut RLimit) -> isize {
+    let current_task = current_task().unwrap();
+    let token = current_task.lock().get_user_token();
+
+    let new_limit = if new_limit.is_null() {
+        None
+    } else {
+        match UserPtr::new(token, new_limit).read() {
+            Ok(limit) => Some(limit),
+            Err(_) => return Errno::EFAULT.as_ret(),
+        }
+    };
+
+    let target = match find_target(pid) {
+        Ok(task) => task,
+        Err(errno) => return errno.as_ret(),
+    };
+    let Some((rlimits, memory_set, fd_table)) = target.lock().user_res.as_ref().map(|user_res| {
+        (user_res.rlimits.clone(), user_res.memory_set.clone(), user_res.fd_table.clone())
+    }) else {
+        return Errno::ESRCH.as_ret();
+    };
+
+    let previous = {
+        let mut rlimits = rlimits.lock();
+        let previous = match rlimits.get(resource) {
+            Ok(limit) => limit,
+            Err(errno) => return errno.as_ret(),
+        };
+        if let Some(limit) = new_limit {
+            if let Err(errno) = rlimits.set(resource, limit) {
+                return errno.as_ret();
+            }
+            rlimits.apply(&mut memory_set.lock(), &mut fd_table.lock());
+        }
+        previous
+    };
+
+    if !old_limit.is_null() && UserPtr::new(token, old_limit).write(previous).is_err() {
+        return Errno::EFAULT.as_ret();
+    }
+    0
+}
+
+/// Capture the stdout/stderr output of a child, see `task::capture`.
+///
+/// `pid == 0` captures every child the caller forks from now on.
+/// The output is read from `/proc/<pid>/output`. `-ECHILD` if `pid` is
+/// not a child of the caller.
+#[syscall_register(SYSCALL_CAPTURE_OUTPUT)]
+pub fn sys_capture_output(pid: usize) -> isize {
+    let current_task = current_task().unwrap();
+    if pid == 0 {
+        current_task.lock().capture_children = true;
+        return 0;
+    }
+    let child = current_task.lock().with_user_res(|user_res| {
+        user_res
+            .children
+            .lock()
+            .iter()
+            .find(|child| usize::from(child.get_tid()) == pid)
+            .cloned()
+    });
+    match child {
+        Some(child) => {
+            start_capture(&child, current_task.get_tid().into());
+            0
+        }
+        None => Errno::ECHILD.as_ret(),
+    }
+}
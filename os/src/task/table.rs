@@ -0,0 +1,37 @@
+//! Global tid -> task lookup
+//!
+//! Holds weak references only: a task is owned by its scheduler queue,
+//! its parent and its group, the table never keeps one alive.
+
+use alloc::{collections::btree_map::BTreeMap, sync::{Arc, Weak}, vec::Vec};
+
+use crate::sync::spin::mutex::IRQSpinLock;
+
+use super::{allocator::TaskID, TaskControlBlock};
+
+type Mutex<T> = IRQSpinLock<T>;
+
+static TASK_TABLE: Mutex<BTreeMap<usize, Weak<TaskControlBlock>>> = Mutex::new(BTreeMap::new());
+
+pub fn register_task(task: &Arc<TaskControlBlock>) {
+    TASK_TABLE
+        .lock()
+        .insert(task.get_tid().into(), Arc::downgrade(task));
+}
+
+pub fn unregister_task(tid: TaskID) {
+    TASK_TABLE.lock().remove(&usize::from(tid));
+}
+
+pub fn find_task(tid: usize) -> Option<Arc<TaskControlBlock>> {
+    TASK_TABLE.lock().get(&tid).and_then(Weak::upgrade)
+}
+
+/// Every task still around, zombies included.
+///
+/// Collected before any is looked at: dropping the last reference to one
+/// unregisters it.
+pub fn all_tasks() -> Vec<Arc<TaskControlBlock>> {
+    let tasks = TASK_TABLE.lock().values().filter_map(Weak::upgrade).collect();
+    tasks
+}
@@ -1,15 +1,21 @@
-use core::{cell::UnsafeCell, fmt::{self, Display}, ptr, sync::atomic::{AtomicBool, AtomicPtr, Ordering}, usize};
+use core::{fmt::{self, Display}, ptr, sync::atomic::{AtomicI32, AtomicPtr, AtomicUsize, Ordering}, usize};
 
 use alloc::{boxed::Box, format, string::String, sync::{Arc, Weak}, vec::{self, Vec}};
 use bitflags::bitflags;
 
-use crate::{fs::{File, Stdin, Stdout}, mm::{address::{PhysPageNum, VirtPageNum}, memory_set::MemorySet, KERNEL_SPACE}, println, processor::get_current_processor, sync::spin::mutex::{IRQSpinLock,IRQSpinLockGuard}, trap::{trap_handler, TrapContext}};
+use crate::{config::{ASLR, MAX_USER_STACKS}, drivers::block::IoPriority, fs::FdTable, mm::{address::{PhysPageNum, VirtPageNum}, binfmt::{Executable, Program}, elf::ElfImage, memory_set::MemorySet, KERNEL_SPACE}, println, processor::get_current_processor, sync::{event::Event, spin::mutex::{sanction_handoff, IRQSpinLock, IRQSpinLockGuard}}, timer::itimer::RealTimer, trap::{trap_handler, TrapContext}};
 
-use super::{allocator::{KernelStackALlocator, KernelStackGuard, RecycleAllocator, TaskHandle, TaskHandleAllocator, TaskID, TrapContextPageAllocator, TrapContextPageGuard, UserStackAlloctor, UserStackGuard}, signal::Signal, yield_current, TaskContext};
+use super::{init_task, allocator::{KernelStackALlocator, KernelStackGuard, RecycleAllocator, TaskHandle, TaskHandleAllocator, TaskID, TrapContextPageAllocator, TrapContextPageGuard, UserStackAlloctor, UserStackGuard}, capture::{release_owned_by, OutputCapture}, rlimit::RLimits, signal::{AltStack, Signal, SignalState}, stats::{GroupStats, TaskStats}, table::{register_task, unregister_task}, time_slice::TimeSlice, yield_current, TaskContext};
 
 
 type Mutex<T> = IRQSpinLock<T>;
 
+/// Range of `TaskControlBlock::nice`, as in Linux
+pub const NICE_MIN: i32 = -20;
+pub const NICE_MAX: i32 = 19;
+/// Affinity of a task allowed on every hart, that of the first one
+pub const AFFINITY_ALL: usize = usize::MAX;
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum TaskState {
     // UnInitialized,
@@ -45,45 +51,9 @@ bitflags! {
     }
 }
 
-
-pub struct PendingTaskLockGuard {
-    slot: UnsafeCell<Option<IRQSpinLockGuard<'static, TaskControlBlockInner>>>,
-    occupied: AtomicBool,
-}
-
-
-impl PendingTaskLockGuard {
-    pub const fn new() -> Self {
-        Self { slot: UnsafeCell::new(None), occupied: AtomicBool::new(false) }
-    }
-
-    pub unsafe fn store_lock(&self, guard: IRQSpinLockGuard<'_, TaskControlBlockInner>) {
-        if self.occupied.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
-            panic!("PendingTaskLockGuard already occupied");
-        }
-
-        // 将guard的生命周期延长到'static（需确保安全）
-        unsafe { 
-            self.slot.get().write(
-                Some( core::mem::transmute(guard) )
-            );
-        }
-    }
-    
-    /// 取出锁守卫
-    /// 安全要求：必须确保之前已经调用了store_lock
-    pub unsafe fn take_lock(&self) -> IRQSpinLockGuard<'_, TaskControlBlockInner> {
-        if !self.occupied.swap(false, Ordering::Release) {
-            panic!("No lock stored in PendingTaskLockGuard");
-        }
-
-        (*self.slot.get()).take().expect("PendingTaskLockGuard was empty")
-    }
-}
-
-unsafe impl Sync for PendingTaskLockGuard {}
-
-
+/// Bit of the `personality` of a task: the address spaces it builds by
+/// `exec` are not randomized
+pub const ADDR_NO_RANDOMIZE: u32 = 0x0040000;
 
 
 pub struct TaskControlBlock { 
@@ -92,8 +62,25 @@ pub struct TaskControlBlock {
     is_leader: bool,
     kernel_stack_guard: KernelStackGuard,
     
+    stats: TaskStats,
+    /// Budget left before a preemptive scheduler switches it out
+    time_slice: TimeSlice,
+    /// Static priority, -20 (highest) to 19, see `PriorityScheduler`.
+    /// Kept out of `inner`: schedulers read it with the task lock held elsewhere
+    nice: AtomicI32,
+    /// Harts the task may run on, bit `n` for hart `n`, see
+    /// `sched_setaffinity`. Out of `inner` like `nice`
+    affinity: AtomicUsize,
+    /// Notified whenever a child exits, `waitpid` sleeps on it
+    child_exit: Event,
+    /// Notified whenever a thread of the group exits, the leader exits last
+    member_exit: Event,
+    /// Exit code of the group, set on the leader by the first `exit_group`
+    /// of another thread, the leader reports it instead of its own
+    group_exit_code: Mutex<Option<i32>>,
+
+    /// Held across every `__switch` of the task, see [`Self::hold_across_switch`]
     inner: Mutex<TaskControlBlockInner>,
-    lock_guard: PendingTaskLockGuard,
 }
 
 /// Task's Control information used by kernel
@@ -101,20 +88,31 @@ pub struct TaskControlBlock {
 pub struct TaskControlBlockInner {
     pub state: TaskState,              // 运行状态（就绪/阻塞等）
     pub context: TaskContext,          // 寄存器等硬件上下文
+    /// Leader of the parent group, `init_task` once that one exited.
+    /// Out of `user_res`: an exiting task reads it after releasing that
+    pub parent: Option<Weak<TaskControlBlock>>,
     
     pub user_res: Option<TaskUserResource>,
-      
+
+    pub signals: SignalState,          // 信号（挂起/屏蔽/处理函数）
+    pub io_priority: IoPriority,       // 块设备请求的优先级
+    pub output_capture: Option<Arc<OutputCapture>>, // stdout/stderr 的副本
+    pub capture_children: bool,        // 新建子进程时开启输出捕获
 }
 
 
 
+/// The user heap `[bottom, brk)`, moved by `sys_brk`
+pub struct UserHeap {
+    /// Start of the heap, above the user stack slots
+    pub bottom: usize,
+    /// Current program break
+    pub brk: usize,
+}
+
 /// UserResource
 /// It's not necessary for a Task
 pub struct TaskUserResource {
-    pub parent_group_id: Option<TaskID>,
-    pub parent: Option<Weak<TaskControlBlock>>, // parent leader
-
-
     pub group_leader: Weak<TaskControlBlock>,
     // pub fs: Arc<FileSystem>,           // 文件系统上下文
     // pub files: Arc<Mutex<FileTable>>,  // 文件描述符表
@@ -125,13 +123,31 @@ pub struct TaskUserResource {
 
     pub children: Arc<Mutex<Vec<Arc<TaskControlBlock>>>>,   // the leader of child task group
     pub task_group: Arc<Mutex<Vec<Arc<TaskControlBlock>>>>, // task_group
+    pub group_stats: Arc<GroupStats>,
 
     user_stack_id_allocator: Arc<Mutex<RecycleAllocator>>,
+    pub user_stack_base: usize,
     pub user_stack_guard: UserStackGuard,
     pub entry_point: usize,
     pub trap_context_guard: TrapContextPageGuard,
 
-    pub fd_table: Arc<Mutex <Vec<Option<Arc<dyn File + Send + Sync>>> >>,
+    /// The user heap, shared by the threads of the group
+    pub heap: Arc<Mutex<UserHeap>>,
+
+    pub fd_table: Arc<Mutex<FdTable>>,
+
+    /// Resource limits of the group, see `task::rlimit`
+    pub rlimits: Arc<Mutex<RLimits>>,
+
+    /// Execution domain and flags, set by `personality`, see [`ADDR_NO_RANDOMIZE`]
+    pub personality: u32,
+
+    /// Where the handlers of `SA_ONSTACK` run, set by `sigaltstack`.
+    /// Kept by `fork`, not by a new thread or `exec`
+    pub alt_stack: Option<AltStack>,
+
+    /// `ITIMER_REAL` of the group, see `timer::itimer`
+    pub real_timer: Arc<RealTimer>,
 }
 
 
@@ -154,10 +170,10 @@ impl fmt::Debug for TaskUserResource {
         // 避免直接打印需要锁的字段，而是打印它们的摘要信息
         
         f.debug_struct("TaskUserResource")
-            .field("parent_group_id", &self.parent_group_id)
-            .field("\ntask_group_id", &self.group_leader.upgrade().unwrap().task_handle)
+            .field("task_group_id", &self.group_leader.upgrade().unwrap().task_handle)
             .field("\nuser_stack top", &self.user_stack_guard.get_top()) // 假设 UserStackGuard 实现了 Debug
             .field("\nentry_point", &format_args!("{:#x}", self.entry_point))
+            .field("\nprogram_brk", &format_args!("{:#x}", self.heap.lock().brk))
             .field("\ntrap_context_page vpn:", &self.trap_context_guard.get_trap_vpn()) // 假设 TrapContextPageGuard 实现了 Debug
             .finish()
     }
@@ -185,12 +201,91 @@ impl TaskControlBlock {
         return self.is_leader;
     }
 
-    pub fn store_lock(&self, guard: IRQSpinLockGuard<'_, TaskControlBlockInner>) {
-        unsafe { self.lock_guard.store_lock(guard); }
+    /// The pid of the task: the tid of its group leader.
+    ///
+    /// A task group is a process, its threads share the pid of the task
+    /// which started it. Tids and pids never change, not even across exec.
+    pub fn get_pid(&self) -> TaskID {
+        if self.is_leader {
+            return self.get_tid();
+        }
+        self.lock()
+            .user_res
+            .as_ref()
+            .and_then(|user_res| user_res.group_leader.upgrade())
+            .map_or(self.get_tid(), |leader| leader.get_tid())
     }
-    
-    pub fn take_lock(&self) -> IRQSpinLockGuard<'_, TaskControlBlockInner> {
-        unsafe { self.lock_guard.take_lock() }
+
+    /// The pid of the parent process, `None` for `init_task` and kernel tasks
+    pub fn get_ppid(&self) -> Option<TaskID> {
+        let parent = self.lock().parent.as_ref()?.upgrade()?;
+        Some(parent.get_pid())
+    }
+
+    #[inline]
+    pub fn stats(&self) -> &TaskStats {
+        &self.stats
+    }
+
+    #[inline]
+    pub fn time_slice(&self) -> &TimeSlice {
+        &self.time_slice
+    }
+
+    #[inline]
+    pub fn nice(&self) -> i32 {
+        self.nice.load(Ordering::Relaxed)
+    }
+
+    #[inline]
+    pub fn child_exit(&self) -> &Event {
+        &self.child_exit
+    }
+
+    /// Takes effect the next time the task is enqueued.
+    pub fn set_nice(&self, nice: i32) {
+        self.nice.store(nice.clamp(NICE_MIN, NICE_MAX), Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn affinity(&self) -> usize {
+        self.affinity.load(Ordering::Relaxed)
+    }
+
+    /// Whether the affinity of the task lets it run on hart `hart`
+    #[inline]
+    pub fn runs_on(&self, hart: usize) -> bool {
+        self.affinity() & (1 << hart) != 0
+    }
+
+    /// Takes effect the next time a hart fetches the task: a task running
+    /// on a hart it left out keeps it until it switches out.
+    pub fn set_affinity(&self, affinity: usize) {
+        self.affinity.store(affinity, Ordering::Relaxed);
+    }
+
+    /// Keep the lock taken by `guard` held across a `__switch` of this
+    /// task, returning its saved context to switch from or to.
+    ///
+    /// Whoever runs on the other side of the switch, the task or the
+    /// scheduler loop, releases it with [`Self::release_switch_lock`]: no
+    /// other hart picks the task up, nor wakes it, before its context is
+    /// saved. The guard itself is given up, the lock alone tells it is held.
+    pub fn hold_across_switch(&self, guard: IRQSpinLockGuard<'_, TaskControlBlockInner>) -> *mut TaskContext {
+        sanction_handoff(&guard);
+        &mut IRQSpinLockGuard::leak(guard).context
+    }
+
+    /// Release the lock held by [`Self::hold_across_switch`], returning the
+    /// state the task switched out in.
+    ///
+    /// # Safety
+    /// Once per `hold_across_switch`, on the other side of that switch.
+    pub unsafe fn release_switch_lock(&self) -> TaskState {
+        assert!(self.inner.is_locked(), "task {} switched without holding its lock", self.name);
+        let state = (*self.inner.data_ptr()).state;
+        self.inner.force_unlock();
+        state
     }
 
     pub fn new_from_elf(
@@ -198,13 +293,18 @@ impl TaskControlBlock {
         app_name: String, 
         parent_task: Option<Arc<TaskControlBlock>>
     ) -> Arc<Self> {
+        let image = ElfImage::parse(elf_data)
+            .unwrap_or_else(|err| panic!("{} is not runnable: {:?}", app_name, err));
+        let argv = [app_name.clone()];
+        let mut program = Program { executable: Executable::Elf(image), argv: &argv, envp: &[] };
         let task_handle = TaskHandleAllocator::allocate();
         let task_id = task_handle.id();
 
         let kernel_stack_guard = KernelStackALlocator::alloc();
         let kernel_stack_top = kernel_stack_guard.get_top();
 
-        let inner = TaskControlBlockInner::new(kernel_stack_top);
+        let mut inner = TaskControlBlockInner::new(kernel_stack_top);
+        inner.parent = parent_task.as_ref().map(Arc::downgrade);
 
 
         let task_control_block = Arc::new(
@@ -214,8 +314,14 @@ impl TaskControlBlock {
                 name: app_name,
                 kernel_stack_guard,
                 is_leader: true,
+                stats: TaskStats::new(),
+                time_slice: TimeSlice::new(),
+                nice: AtomicI32::new(0),
+                affinity: AtomicUsize::new(AFFINITY_ALL),
+                child_exit: Event::new(),
+                member_exit: Event::new(),
+                group_exit_code: Mutex::new(None),
                 inner: Mutex::new(inner),
-                lock_guard: PendingTaskLockGuard::new(),
             }
         );
 
@@ -225,30 +331,288 @@ impl TaskControlBlock {
         task_control_block.inner.lock().user_res = Some(
             TaskUserResource::new(
                 task_id, 
-                elf_data,
+                &mut program,
                 group_leader,
-                parent_task,
                 kernel_stack_top, 
+                0,
             )
         );
 
         task_control_block.lock().with_user_res(|user_res| {
             user_res.add_group_member(task_control_block.clone());
         });
+        register_task(&task_control_block);
 
 
         task_control_block
     }
 
-    
-    pub fn prepare_exit(&self) {
+    /// Duplicate the calling task into a new task group.
+    ///
+    /// The child gets a copy of the parent's address space and fd table,
+    /// and its trap context is a copy of the parent's with `a0` set to 0,
+    /// so that `fork` returns 0 in the child.
+    /// The child is registered in the parent's `children` but not yet
+    /// added to any scheduler.
+    pub fn fork(self: &Arc<Self>) -> Arc<Self> {
+        let task_handle = TaskHandleAllocator::allocate();
+        let task_id = task_handle.id();
+
+        let kernel_stack_guard = KernelStackALlocator::alloc();
+        let kernel_stack_top = kernel_stack_guard.get_top();
+
+        let inner = TaskControlBlockInner::new(kernel_stack_top);
+
+        let child = Arc::new(
+            TaskControlBlock {
+                task_handle,
+                name: self.name.clone(),
+                kernel_stack_guard,
+                is_leader: true,
+                stats: TaskStats::new(),
+                time_slice: TimeSlice::new(),
+                nice: AtomicI32::new(self.nice()),
+                affinity: AtomicUsize::new(self.affinity()),
+                child_exit: Event::new(),
+                member_exit: Event::new(),
+                group_exit_code: Mutex::new(None),
+                inner: Mutex::new(inner),
+            }
+        );
+
+        let group_leader = Arc::downgrade(&child);
+
+        let (user_res, signals, io_priority) = {
+            let mut parent_inner = self.lock();
+            let signals = parent_inner.signals.fork();
+            let io_priority = parent_inner.io_priority;
+            let user_res = parent_inner.with_user_res(|parent_res| {
+                TaskUserResource::from_parent(
+                    task_id,
+                    parent_res,
+                    group_leader,
+                    self,
+                    kernel_stack_top,
+                )
+            });
+            (user_res, signals, io_priority)
+        };
+        let mut child_inner = child.inner.lock();
+        child_inner.parent = Some(Arc::downgrade(self));
+        child_inner.user_res = Some(user_res);
+        child_inner.signals = signals;
+        child_inner.io_priority = io_priority;
+        drop(child_inner);
+
+        child.lock().with_user_res(|user_res| {
+            user_res.add_group_member(child.clone());
+        });
+
+        self.lock().with_user_res(|user_res| {
+            user_res.add_child(child.clone());
+        });
+        register_task(&child);
+
+        child
+    }
+
+    /// Create a thread in the task group of the calling task.
+    ///
+    /// The thread shares the address space, fd table and heap of the
+    /// group, gets its own user stack slot and trap context page, and
+    /// starts as a copy of the caller with `a0` set to 0. Its stack
+    /// pointer is `stack`, or the top of its own stack slot if 0.
+    /// It is not yet added to any scheduler.
+    ///
+    /// `None` if the group has no free user stack slot.
+    pub fn clone_thread(self: &Arc<Self>, stack: usize) -> Option<Arc<Self>> {
+        // taken first, nothing of the thread is built for a group out of slots
+        let user_stack_guard = self.lock().with_user_res(|caller_res| {
+            UserStackAlloctor::alloc(
+                caller_res.memory_set.clone(),
+                caller_res.user_stack_base,
+                caller_res.user_stack_id_allocator.clone(),
+            )
+        })?;
+
+        let task_handle = TaskHandleAllocator::allocate();
+        let task_id = task_handle.id();
+
+        let kernel_stack_guard = KernelStackALlocator::alloc();
+        let kernel_stack_top = kernel_stack_guard.get_top();
+
+        let inner = TaskControlBlockInner::new(kernel_stack_top);
+
+        let thread = Arc::new(
+            TaskControlBlock {
+                task_handle,
+                name: self.name.clone(),
+                kernel_stack_guard,
+                is_leader: false,
+                stats: TaskStats::new(),
+                time_slice: TimeSlice::new(),
+                nice: AtomicI32::new(self.nice()),
+                affinity: AtomicUsize::new(self.affinity()),
+                child_exit: Event::new(),
+                member_exit: Event::new(),
+                group_exit_code: Mutex::new(None),
+                inner: Mutex::new(inner),
+            }
+        );
+
+        let (user_res, parent, signals, io_priority, output_capture) = {
+            let mut caller_inner = self.lock();
+            let parent = caller_inner.parent.clone();
+            let signals = caller_inner.signals.fork();
+            let io_priority = caller_inner.io_priority;
+            // the output of a thread is the output of its process
+            let output_capture = caller_inner.output_capture.clone();
+            let user_res = caller_inner.with_user_res(|caller_res| {
+                TaskUserResource::from_thread(task_id, caller_res, kernel_stack_top, stack, user_stack_guard)
+            });
+            (user_res, parent, signals, io_priority, output_capture)
+        };
+        let mut thread_inner = thread.inner.lock();
+        thread_inner.parent = parent;
+        thread_inner.user_res = Some(user_res);
+        thread_inner.signals = signals;
+        thread_inner.io_priority = io_priority;
+        thread_inner.output_capture = output_capture;
+        drop(thread_inner);
+
+        thread.lock().with_user_res(|user_res| {
+            user_res.add_group_member(thread.clone());
+        });
+        register_task(&thread);
+
+        Some(thread)
+    }
+
+    /// Replace the user image of this task with `program`.
+    ///
+    /// Family links (parent, children, task group) and the fd table
+    /// survive, but for its close-on-exec fds, and so does the personality.
+    /// Everything else in the user resource is rebuilt.
+    pub fn exec(&self, program: &mut Program) {
+        let kernel_stack_top = self.kernel_stack_guard.get_top();
+
+        let mut inner = self.lock();
+        let old_user_res = inner.user_res.take().unwrap();
+
+        let mut new_user_res = TaskUserResource::new(
+            self.get_tid(),
+            program,
+            old_user_res.group_leader.clone(),
+            kernel_stack_top,
+            old_user_res.personality,
+        );
+
+        new_user_res.children = old_user_res.children.clone();
+        new_user_res.task_group = old_user_res.task_group.clone();
+        new_user_res.group_stats = old_user_res.group_stats.clone();
+        new_user_res.fd_table = old_user_res.fd_table.clone();
+        new_user_res.fd_table.lock().close_on_exec();
+        new_user_res.rlimits = old_user_res.rlimits.clone();
+        new_user_res.rlimits.lock().apply(&mut new_user_res.memory_set.lock(), &mut new_user_res.fd_table.lock());
+        new_user_res.real_timer = old_user_res.real_timer.clone();
+
+        inner.user_res = Some(new_user_res);
+        inner.signals.exec();
+        drop(inner);
+
+        // unmap and free the old image
+        drop(old_user_res);
+    }
+
+
+    /// Kill the other threads of the group and wait until they are gone.
+    ///
+    /// A thread blocked in the kernel only notices the `SIGKILL` once it
+    /// is woken up and heads back to user space.
+    fn wait_group_exit(&self) {
+        let task_group = self.lock().with_user_res(|user_res| user_res.task_group.clone());
+        loop {
+            // an exit after this point makes `wait` return at once
+            let seen = self.member_exit.generation();
+            let members = task_group.lock();
+            if members.iter().all(|member| member.is_leader()) {
+                break;
+            }
+            for member in members.iter().filter(|member| !member.is_leader()) {
+                member.lock().signal(Signal::SIGKILL);
+            }
+            drop(members);
+            self.member_exit.wait(seen);
+        }
+        task_group.lock().clear();
+    }
+
+    /// Hand the children of the group over to `init_task`, which reaps
+    /// them from now on, including those which are zombies already.
+    fn reparent_children(self: &Arc<Self>) {
+        let Some(init) = init_task().filter(|init| !Arc::ptr_eq(init, self)) else {
+            // init itself, its children are reaped by no one
+            return;
+        };
+        let children = self.lock().with_user_res(|user_res| user_res.children.clone());
+        let orphans = core::mem::take(&mut *children.lock());
+        if orphans.is_empty() {
+            return;
+        }
+        for orphan in orphans.iter() {
+            let members = orphan.lock().user_res.as_ref().map(|user_res| user_res.task_group.clone());
+            // the whole group, `exit` reads it from the leader only
+            match members {
+                Some(members) => {
+                    for member in members.lock().iter() {
+                        member.lock().parent = Some(Arc::downgrade(&init));
+                    }
+                }
+                None => orphan.lock().parent = Some(Arc::downgrade(&init)),
+            }
+        }
+        init.lock().with_user_res(|user_res| user_res.children.lock().extend(orphans));
+        // for the zombies, whose exit went to this task
+        init.child_exit().notify();
+    }
+
+    /// The group exit code set by [`Self::set_group_exit_code`], if any
+    pub fn group_exit_code(&self) -> Option<i32> {
+        *self.group_exit_code.lock()
+    }
+
+    /// Record the exit code of the group on its leader, the first one wins.
+    pub fn set_group_exit_code(&self, exit_code: i32) {
+        self.group_exit_code.lock().get_or_insert(exit_code);
+    }
+
+    /// Tear down what the task owns before it turns into a zombie.
+    ///
+    /// A leader first waits for the rest of its group, then gives its
+    /// children to `init_task`. The user resource is released here, once:
+    /// the zombie only keeps its kernel stack and handle until reaped.
+    pub fn prepare_exit(self: &Arc<Self>) {
         if self.is_leader() {
-            self.lock().wait_group_eixt();
+            self.wait_group_exit();
+            self.reparent_children();
         }
-        // mound_child_to_init
 
         // release whole task group resource
-        drop(self.lock().user_res.take().unwrap());
+        release_owned_by(self.get_tid().into());
+
+        let user_res = self.lock().user_res.take().expect("task exited twice");
+        if !self.is_leader() {
+            // leave the group and fold in one step, a concurrent
+            // `GroupStats::total` sees this thread exactly once
+            let mut task_group = user_res.task_group.lock();
+            task_group.retain(|member| member.get_tid() != self.get_tid());
+            user_res.group_stats.fold(&self.stats);
+            drop(task_group);
+            if let Some(leader) = user_res.group_leader.upgrade() {
+                leader.member_exit.notify();
+            }
+        }
+        drop(user_res);
 
     }
 
@@ -262,7 +626,12 @@ impl TaskControlBlockInner {
         Self {
             state: TaskState::Ready,
             context: TaskContext::goto_new_user_task_start(kernel_stack_top),
+            parent: None,
             user_res: None,
+            signals: SignalState::new(),
+            io_priority: IoPriority::DEFAULT,
+            output_capture: None,
+            capture_children: false,
         }
     }
 
@@ -278,31 +647,6 @@ impl TaskControlBlockInner {
         self.user_res.as_ref().unwrap().memory_set.lock().token()
     }
 
-    fn wait_group_eixt(&mut self) {
-        // notity all group member
-        if let Some(user_res) = self.user_res.as_ref() {
-            for task in user_res.task_group.lock().iter() {
-                if ! task.is_leader() {
-                    let mut task = task.inner.lock();
-                    task.signal(Signal::SIGTERM);
-                }
-            };
-
-            // maybe add timeout
-            loop {
-                if user_res.task_group.lock().len() == 1 {
-                    break;
-                }
-                
-                //sleep
-            }
-
-            user_res.task_group.lock().pop();
-
-            assert!(user_res.task_group.lock().is_empty())
-        }
-    }
-
     /// Provides controlled access to the task's user resource within a locked context
     ///
     /// # Contract
@@ -335,19 +679,26 @@ impl TaskControlBlockInner {
         f(self.user_res.as_mut().unwrap())
     }
 
-    fn mount_child_to_init() {
-        unimplemented!()
-    }
-    
-
+    /// Wake the parent if it waits for a child, the state must be `Zombie` already.
+    ///
+    /// Called with the lock held: [`TaskControlBlock::reparent_children`]
+    /// sees the task either still alive, or a zombie notified to the old parent.
     pub fn notify_parent(&self, exit_code: i32) {
-        println!("notify parent (faker)");
+        let parent = self.parent.as_ref().and_then(|parent| parent.upgrade());
+        if let Some(parent) = parent {
+            log::debug!("notify parent {} of exit code {}", parent.get_name(), exit_code);
+            parent.child_exit().notify();
+        }
     }
 
+    /// Make `signal` pending, it is acted on when the task returns to user space.
     pub fn signal(&mut self, signal: Signal) {
-        println!("(faker) signal {}", signal.description());
-        // self.handler_signal(signal);
-        
+        self.signals.raise(signal);
+    }
+
+    /// Raise `signal` for a fault of the task, see [`SignalState::force`].
+    pub fn force_signal(&mut self, signal: Signal) {
+        self.signals.force(signal);
     }
 
 }
@@ -357,16 +708,18 @@ impl TaskUserResource {
 
     pub fn new(
         tid: TaskID, 
-        elf_data: &[u8],
+        program: &mut Program,
         group_leader: Weak<TaskControlBlock>,
-        parent: Option<Arc<TaskControlBlock>>,
         kernel_stack_top: usize,
+        personality: u32,
     ) -> Self {
 
         log::debug!("new TaskUserResource");
 
-        let (memory_set, user_stack_base, entry_point) = 
-        MemorySet::from_elf(elf_data);
+        let randomize = ASLR && personality & ADDR_NO_RANDOMIZE == 0;
+        let (memory_set, user_stack_base) = program.map(randomize);
+        let entry_point = program.entry();
+
 
         let memory_set = Arc::new(Mutex::new(memory_set));
 
@@ -374,64 +727,186 @@ impl TaskUserResource {
             RecycleAllocator::new()
         ));
 
-        let user_stack_id = user_stack_id_allocator.lock().alloc();
-
         let user_stack_guard =  UserStackAlloctor::alloc(
             memory_set.clone(), 
             user_stack_base, 
-            user_stack_id
-        );
+            user_stack_id_allocator.clone(),
+        )
+        .expect("the slots of a new address space are free");
 
         let mut trap_context_guard = TrapContextPageAllocator::alloc(tid, memory_set.clone());
 
-        let trap_context = TrapContext::app_init_context(
+        let token = memory_set.lock().token();
+        let user_sp = program
+            .push_initial_stack(token, user_stack_guard.get_top())
+            .expect("the user stack is mapped");
+        let mut trap_context = TrapContext::app_init_context(
             entry_point, 
-            user_stack_guard.get_top(), 
+            user_sp, 
             KERNEL_SPACE.lock().token(), 
             kernel_stack_top, 
             trap_handler as usize
         );
+        // `main(argc, argv)` may be called straight away
+        trap_context.x[10] = program.argv.len();
+        trap_context.x[11] = user_sp + size_of::<usize>();
         trap_context_guard.update(trap_context);
 
 
         let task_group = Arc::new(Mutex::new(Vec::new()));
 
-        let (parent_group_id, parent) = match parent {
-            Some(parent) => {
-                ( Some(parent.task_handle.id()),
-                Some(Arc::downgrade(&parent)))
-            },
-            None => {
-                (None, None)
-            },
-        };
+        let heap_bottom = UserStackGuard::slots_end(user_stack_base);
+        let real_timer = RealTimer::new(group_leader.clone());
 
         Self { 
-            parent_group_id,
             group_leader,
             memory_set, 
-            parent, 
             children: Arc::new(Mutex::new(Vec::new())), 
             task_group, 
+            group_stats: Arc::new(GroupStats::new()),
+            user_stack_base,
             user_stack_guard,
             entry_point,
             user_stack_id_allocator,
             trap_context_guard,
-            fd_table: Arc::new(Mutex::new(
-                    alloc::vec![
-                    // 0 -> stdin
-                    Some(Arc::new(Stdin)),
-                    // 1 -> stdout
-                    Some(Arc::new(Stdout)),
-                    // 2 -> stderr
-                    Some(Arc::new(Stdout)),
-                ]
-            )),
+            heap: Arc::new(Mutex::new(UserHeap {
+                bottom: heap_bottom,
+                brk: heap_bottom,
+            })),
+            fd_table: Arc::new(Mutex::new(FdTable::with_stdio())),
+            rlimits: Arc::new(Mutex::new(RLimits::new())),
+            personality,
+            alt_stack: None,
+            real_timer,
         }
     }
 
 
 
+    /// Build the user resource of a forked child from its parent's.
+    ///
+    /// The whole address space is copied, then the parent's trap context
+    /// page is replaced by one at the child's own slot. The parent may be
+    /// any thread of its group, the child keeps only its stack.
+    pub fn from_parent(
+        tid: TaskID,
+        parent_res: &TaskUserResource,
+        group_leader: Weak<TaskControlBlock>,
+        parent: &Arc<TaskControlBlock>,
+        kernel_stack_top: usize,
+    ) -> Self {
+        let memory_set = MemorySet::from_other_user(&parent_res.memory_set.lock());
+        let memory_set = Arc::new(Mutex::new(memory_set));
+
+        // the copied trap context page belongs to the parent's slot
+        memory_set.lock().remove_area_with_start_vpn(parent_res.trap_context_vpn());
+
+        // the stack of the caller stays where it is, a thread's slot too:
+        // its frames point into it. The slots below are free in the child
+        let user_stack_id = parent_res.user_stack_guard.get_id();
+        let user_stack_id_allocator = Arc::new(Mutex::new(
+            RecycleAllocator::with_taken(user_stack_id)
+        ));
+
+        // the other threads of the parent don't follow into the child
+        {
+            let mut memory_set = memory_set.lock();
+            for member in parent_res.task_group.lock().iter() {
+                if member.get_tid() != parent.get_tid() {
+                    memory_set.remove_area_with_start_vpn(TrapContextPageGuard::vpn_of(member.get_tid()));
+                }
+            }
+            for id in (0..MAX_USER_STACKS).filter(|&id| id != user_stack_id) {
+                memory_set.remove_area_containing(UserStackGuard::slot_bottom(parent_res.user_stack_base, id));
+            }
+        }
+
+        let user_stack_guard = UserStackAlloctor::adopt(
+            memory_set.clone(),
+            parent_res.user_stack_base,
+            user_stack_id,
+            user_stack_id_allocator.clone(),
+        );
+
+        let mut trap_context_guard = TrapContextPageAllocator::alloc(tid, memory_set.clone());
+
+        let mut trap_context = parent_res.trap_context_ppn().get_mut::<TrapContext>().clone();
+        trap_context.kernel_sp = kernel_stack_top;
+        // fork returns 0 in the child
+        trap_context.x[10] = 0;
+        trap_context_guard.update(trap_context);
+
+        let fd_table = parent_res.fd_table.lock().clone();
+        // timers are not inherited
+        let real_timer = RealTimer::new(group_leader.clone());
+
+        Self {
+            group_leader,
+            memory_set,
+            children: Arc::new(Mutex::new(Vec::new())),
+            task_group: Arc::new(Mutex::new(Vec::new())),
+            group_stats: Arc::new(GroupStats::new()),
+            user_stack_base: parent_res.user_stack_base,
+            user_stack_guard,
+            entry_point: parent_res.entry_point,
+            user_stack_id_allocator,
+            trap_context_guard,
+            heap: {
+                let heap = parent_res.heap.lock();
+                Arc::new(Mutex::new(UserHeap { bottom: heap.bottom, brk: heap.brk }))
+            },
+            fd_table: Arc::new(Mutex::new(fd_table)),
+            // the memory set and the fd table are copies, with the limits applied
+            rlimits: Arc::new(Mutex::new(parent_res.rlimits.lock().clone())),
+            personality: parent_res.personality,
+            alt_stack: parent_res.alt_stack,
+            real_timer,
+        }
+    }
+
+    /// Build the user resource of a new thread from the calling thread's.
+    ///
+    /// Everything of the group is shared, only the user stack slot, taken
+    /// beforehand in `user_stack_guard`, and the trap context page are the
+    /// thread's own.
+    pub fn from_thread(
+        tid: TaskID,
+        caller_res: &TaskUserResource,
+        kernel_stack_top: usize,
+        stack: usize,
+        user_stack_guard: UserStackGuard,
+    ) -> Self {
+        let memory_set = caller_res.memory_set.clone();
+
+        let mut trap_context_guard = TrapContextPageAllocator::alloc(tid, memory_set.clone());
+
+        let mut trap_context = caller_res.trap_context_ppn().get_mut::<TrapContext>().clone();
+        trap_context.kernel_sp = kernel_stack_top;
+        // clone returns 0 in the thread
+        trap_context.x[10] = 0;
+        trap_context.set_sp(if stack != 0 { stack } else { user_stack_guard.get_top() });
+        trap_context_guard.update(trap_context);
+
+        Self {
+            group_leader: caller_res.group_leader.clone(),
+            memory_set,
+            children: caller_res.children.clone(),
+            task_group: caller_res.task_group.clone(),
+            group_stats: caller_res.group_stats.clone(),
+            user_stack_base: caller_res.user_stack_base,
+            user_stack_guard,
+            entry_point: caller_res.entry_point,
+            user_stack_id_allocator: caller_res.user_stack_id_allocator.clone(),
+            trap_context_guard,
+            heap: caller_res.heap.clone(),
+            fd_table: caller_res.fd_table.clone(),
+            rlimits: caller_res.rlimits.clone(),
+            personality: caller_res.personality,
+            alt_stack: None,
+            real_timer: caller_res.real_timer.clone(),
+        }
+    }
+
     #[inline(always)]
     pub fn trap_context_ppn(&self) -> PhysPageNum {
         self.trap_context_guard.get_trap_ppn()
@@ -451,15 +926,6 @@ impl TaskUserResource {
         self.children.lock().push(new_child);
     }
 
-    pub fn alloc_fd(&mut self) -> usize {
-        let mut fd_table = self.fd_table.lock();
-        if let Some(fd) = (0..fd_table.len()).find(|fd| fd_table[*fd].is_none()) {
-            fd
-        } else {
-            fd_table.push(None);
-            fd_table.len() - 1
-        }
-    }
 
 
 }
@@ -468,6 +934,7 @@ impl TaskUserResource {
 impl Drop for TaskControlBlock {
     fn drop(&mut self) {
         log::debug!("drop task {}", self.get_name());
+        unregister_task(self.get_tid());
     }
 }
 
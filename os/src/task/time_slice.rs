@@ -0,0 +1,93 @@
+//! Time slices of the preemptive policies
+//!
+//! Every task carries a budget of timer cycles. At each tick the running
+//! task is charged the time it actually ran since it was switched in or
+//! last charged, not a whole tick: a task which keeps giving the cpu
+//! away (syscalls which sleep, short bursts between I/O) only pays for
+//! what it used, and keeps the rest of its budget across switches.
+//!
+//! A task is preempted once its budget is gone, and gets a full one back.
+//! The length of a full slice is a run time parameter, see `sysctl`.
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use os_macros::kernel_test;
+
+use crate::{
+    config::TIME_SLICE_MS,
+    timer::{cycles_to_ms, get_time, ms_to_cycles},
+};
+
+/// Length of a full slice, in timer cycles, 0 until set
+static SLICE_CYCLES: AtomicUsize = AtomicUsize::new(0);
+
+/// Length of a full slice, in milliseconds
+pub fn slice_ms() -> usize {
+    cycles_to_ms(slice_cycles())
+}
+
+/// Set the length of the slices handed out from now on.
+pub fn set_slice_ms(ms: usize) {
+    SLICE_CYCLES.store(ms_to_cycles(ms.max(1)), Ordering::Relaxed);
+}
+
+fn slice_cycles() -> usize {
+    match SLICE_CYCLES.load(Ordering::Relaxed) {
+        0 => ms_to_cycles(TIME_SLICE_MS),
+        cycles => cycles,
+    }
+}
+
+/// The budget of a task, only touched by the hart running it
+pub struct TimeSlice {
+    /// Cycles left before preemption
+    left: AtomicUsize,
+    /// `get_time()` when the task was last switched in or charged
+    charged_at: AtomicUsize,
+}
+
+impl TimeSlice {
+    pub fn new() -> Self {
+        Self {
+            left: AtomicUsize::new(slice_cycles()),
+            charged_at: AtomicUsize::new(0),
+        }
+    }
+
+    /// The task is about to run.
+    pub fn on_switch_in(&self) {
+        self.charged_at.store(get_time(), Ordering::Relaxed);
+    }
+
+    /// Charge the time run since last time, returns whether the budget
+    /// is exhausted, a full one is handed out then.
+    pub fn charge(&self) -> bool {
+        self.charge_at(get_time())
+    }
+
+    fn charge_at(&self, now: usize) -> bool {
+        let ran = now.saturating_sub(self.charged_at.swap(now, Ordering::Relaxed));
+        let left = self.left.load(Ordering::Relaxed).saturating_sub(ran);
+        if left == 0 {
+            self.left.store(slice_cycles(), Ordering::Relaxed);
+            true
+        } else {
+            self.left.store(left, Ordering::Relaxed);
+            false
+        }
+    }
+}
+
+#[kernel_test]
+fn time_slice_charge_test() {
+    let full = slice_cycles();
+    let slice = TimeSlice::new();
+    slice.charged_at.store(1000, Ordering::Relaxed);
+    // a partial tick only costs what ran
+    assert!(!slice.charge_at(1000 + full / 2));
+    assert_eq!(slice.left.load(Ordering::Relaxed), full - full / 2);
+    // switched out, then back in much later: the time away is free
+    slice.charged_at.store(10 * full, Ordering::Relaxed);
+    assert!(!slice.charge_at(10 * full + 1));
+    assert!(slice.charge_at(11 * full));
+    assert_eq!(slice.left.load(Ordering::Relaxed), full);
+}
@@ -0,0 +1,142 @@
+//! Wait queue
+//!
+//! A [`WaitQueue`] holds tasks in [`TaskState::Blocking`] until some event
+//! wakes them up. Blocked tasks are not in the scheduler's ready queue, so
+//! they consume no CPU time, unlike spinning on `yield_current`.
+//!
+//! ```rust
+//! static DEVICE_READY: WaitQueue = WaitQueue::new();
+//!
+//! // task side
+//! while !device_ready() {
+//!     DEVICE_READY.sleep_on();
+//! }
+//!
+//! // interrupt side
+//! DEVICE_READY.wake_all();
+//! ```
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use alloc::{collections::vec_deque::VecDeque, sync::Arc};
+
+use crate::{
+    processor::get_current_processor,
+    sync::spin::mutex::IRQSpinLock,
+    timer::{add_timer, cancel_timer},
+};
+
+use super::{current_task, task::TaskState, TaskControlBlock};
+
+type Mutex<T> = IRQSpinLock<T>;
+
+pub struct WaitQueue {
+    queue: Mutex<VecDeque<Arc<TaskControlBlock>>>,
+}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        Self {
+            queue: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Block the current task until it is woken up.
+    ///
+    /// Like a condition variable, a wakeup doesn't say anything about the
+    /// condition itself, callers should re-check it in a loop.
+    pub fn sleep_on(&self) {
+        self.sleep_on_with(|| {});
+    }
+
+    /// Block the current task, running `on_queued` once it is in the queue.
+    ///
+    /// `on_queued` runs with the task lock held and interrupts disabled, so
+    /// anything it arms (a timer, a device request, ...) cannot wake the
+    /// queue before the task is actually asleep.
+    pub fn sleep_on_with(&self, on_queued: impl FnOnce()) {
+        let task = current_task().expect("sleep_on called without a current task");
+        // The task lock is held until the scheduler loop has switched away
+        // from this task, a waker spins on it instead of racing the switch.
+        let task_guard = task.lock();
+        assert_eq!(task_guard.get_state(), TaskState::Running);
+
+        self.queue.lock().push_back(task.clone());
+        on_queued();
+
+        get_current_processor().block_current(task_guard);
+    }
+
+    /// Like [`sleep_on_with`](Self::sleep_on_with), but a timer wakes the
+    /// task up at `deadline` (see [`get_time`](crate::timer::get_time)) if
+    /// nothing else did before.
+    ///
+    /// Returns `true` if the wakeup came from the timer.
+    pub fn sleep_on_until(self: &Arc<Self>, deadline: usize, on_queued: impl FnOnce()) -> bool {
+        let task = current_task().expect("sleep_on called without a current task");
+        let timed_out = Arc::new(AtomicBool::new(false));
+        let mut timer = None;
+        self.sleep_on_with(|| {
+            on_queued();
+            let (queue, timed_out) = (self.clone(), timed_out.clone());
+            timer = Some(add_timer(deadline, move || {
+                // a no-op if someone else already woke the task
+                if queue.wake_task(&task) {
+                    timed_out.store(true, Ordering::Relaxed);
+                }
+            }));
+        });
+        if let Some(timer) = timer {
+            cancel_timer(&timer);
+        }
+        timed_out.load(Ordering::Relaxed)
+    }
+
+    /// Wake up the task waiting the longest.
+    ///
+    /// Returns `false` if the queue was empty.
+    pub fn wake_one(&self) -> bool {
+        let task = self.queue.lock().pop_front();
+        match task {
+            Some(task) => {
+                get_current_processor().wakeup_task(task);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Wake up every waiting task, returns how many were woken.
+    pub fn wake_all(&self) -> usize {
+        let tasks = core::mem::take(&mut *self.queue.lock());
+        let count = tasks.len();
+        for task in tasks {
+            get_current_processor().wakeup_task(task);
+        }
+        count
+    }
+
+    /// Wake up `task` if it is waiting here.
+    ///
+    /// Returns `false` if it wasn't in the queue.
+    pub fn wake_task(&self, task: &Arc<TaskControlBlock>) -> bool {
+        let task = {
+            let mut queue = self.queue.lock();
+            match queue.iter().position(|waiting| Arc::ptr_eq(waiting, task)) {
+                Some(index) => queue.remove(index),
+                None => None,
+            }
+        };
+        match task {
+            Some(task) => {
+                get_current_processor().wakeup_task(task);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.lock().is_empty()
+    }
+}
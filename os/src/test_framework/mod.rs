@@ -1,16 +1,184 @@
-use crate::{color_println, println, sbi::shutdown};
-// use crate::io::console::{ Color, Colorize };
+//! Kernel test runner
+//!
+//! Every [`kernel_test`](os_macros::kernel_test) is a [`KernelTest`],
+//! collected by the `custom_test_frameworks` harness and run from
+//! `test_main`, on the boot hart, before the scheduler starts.
+//!
+//! A test runs on a stack of its own, switched to with `__switch`, so a
+//! failing one can be abandoned midway: a panic is caught by a panic hook,
+//! and a test still running after its timeout is stopped by a timer event,
+//! both switch back to the runner, which goes on with the next test. Timer
+//! interrupts are enabled while a test runs, for the timeout, a test
+//! spinning with interrupts disabled (holding an IRQ-safe lock) can't be
+//! stopped.
+//!
+//! What an abandoned test held stays held, its heap memory leaks, and the
+//! nesting of its interrupt-disabled sections is reset.
 
-/// test_runner
+use core::{
+    panic::PanicInfo,
+    ptr::{addr_of, addr_of_mut},
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+use alloc::vec;
+use os_macros::kernel_test;
+use riscv::register::sie;
+
+use crate::{
+    color_println,
+    interupt::InterruptController,
+    io::console::Color,
+    lang_iterms::set_panic_hook,
+    println,
+    processor::get_current_processor,
+    shutdown::shutdown,
+    task::{switch::__switch, TaskContext},
+    timer::{add_timer, cancel_timer, get_time, ns_to_cycles},
+    trap::enable_timer_interrupt,
+};
+
+/// Timeout of the tests which don't set `timeout_ms`
+pub const DEFAULT_TIMEOUT_MS: usize = 10_000;
+
+/// Stack of a test, four times a kernel stack
+const TEST_STACK_SIZE: usize = 64 * 1024;
+
+/// A test, as generated by `#[kernel_test]`
+pub struct KernelTest {
+    pub name: &'static str,
+    /// Module path of the test
+    pub path: &'static str,
+    pub func: fn(),
+    /// `#[kernel_test(should_panic)]`, the test passes if it panics
+    pub should_panic: bool,
+    /// `#[kernel_test(timeout_ms = N)]`, or [`DEFAULT_TIMEOUT_MS`]
+    pub timeout_ms: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+enum Outcome {
+    Returned,
+    Panicked,
+    TimedOut,
+}
+
+/// Where the runner waits for the test to end
+static mut RUNNER_CONTEXT: TaskContext = TaskContext::zero_init();
+/// Of the test running, switched to once, saved for nothing afterwards
+static mut TEST_CONTEXT: TaskContext = TaskContext::zero_init();
+/// The function of the test running, for [`test_entry`]
+static mut TEST_FN: Option<fn()> = None;
+static OUTCOME: AtomicU8 = AtomicU8::new(Outcome::Returned as u8);
+
+/// Abandon the test running, from its stack or from an interrupt on it
+fn back_to_runner(outcome: Outcome) -> ! {
+    OUTCOME.store(outcome as u8, Ordering::Release);
+    unsafe {
+        __switch(addr_of_mut!(TEST_CONTEXT), addr_of!(RUNNER_CONTEXT));
+    }
+    unreachable!("an abandoned test was switched to")
+}
+
+/// Bottom of the stack of a test
+fn test_entry() -> ! {
+    let func = unsafe { (*addr_of_mut!(TEST_FN)).take() }.expect("no test to run");
+    func();
+    back_to_runner(Outcome::Returned)
+}
+
+fn on_test_panic(_info: &PanicInfo) {
+    back_to_runner(Outcome::Panicked)
+}
+
+/// Run `test` until it returns, panics or times out
+fn run(test: &KernelTest) -> Outcome {
+    let stack = vec![0u8; TEST_STACK_SIZE];
+    let stack_top = (stack.as_ptr() as usize + TEST_STACK_SIZE) & !0xf;
+    unsafe {
+        TEST_FN = Some(test.func);
+        *addr_of_mut!(TEST_CONTEXT) = TaskContext::goto_kernel_entry(test_entry, stack_top);
+    }
+
+    set_panic_hook(Some(on_test_panic));
+    let deadline = get_time() + ns_to_cycles(test.timeout_ms as u64 * 1_000_000);
+    let watchdog = add_timer(deadline, || back_to_runner(Outcome::TimedOut));
+    enable_timer_interrupt();
+    InterruptController::global_enable();
+
+    unsafe {
+        __switch(addr_of_mut!(RUNNER_CONTEXT), addr_of!(TEST_CONTEXT));
+    }
+
+    InterruptController::global_disable();
+    unsafe {
+        sie::clear_stimer();
+    }
+    cancel_timer(&watchdog);
+    set_panic_hook(None);
+
+    let outcome = match OUTCOME.load(Ordering::Acquire) {
+        0 => Outcome::Returned,
+        1 => Outcome::Panicked,
+        _ => Outcome::TimedOut,
+    };
+    if outcome != Outcome::Returned {
+        let (interrupts, preemption) = get_current_processor().reset_nesting();
+        if interrupts + preemption > 0 {
+            println!(
+                "[test] abandoned with {} interrupt-disabled and {} preemption-disabled sections open",
+                interrupts, preemption
+            );
+        }
+    }
+    // a timed out test may still have events of its own in the timer queue,
+    // they can only fire while the next tests run
+    drop(stack);
+    outcome
+}
+
+/// Run every test, print a summary, and power off: with a failure if any
+/// test failed.
 #[allow(unused)]
-pub fn test_runner(tests: &[&dyn Fn()]) {
+pub fn test_runner(tests: &[&KernelTest]) {
     println!("Running {} tests", tests.len());
+    let (mut passed, mut failed, mut timed_out) = (0, 0, 0);
     for test in tests {
-        // 模拟捕获 panic
-        let result = test();
-
+        color_println!(Color::Blue, "\nTesting > {} ({}) ...", test.name, test.path);
+        match (run(test), test.should_panic) {
+            (Outcome::Returned, false) | (Outcome::Panicked, true) => {
+                passed += 1;
+                color_println!(Color::Green, "========[Test passed!]========");
+            }
+            (Outcome::Returned, true) => {
+                failed += 1;
+                color_println!(Color::Red, "========[Test failed: did not panic]========");
+            }
+            (Outcome::Panicked, false) => {
+                failed += 1;
+                color_println!(Color::Red, "========[Test failed: panicked]========");
+            }
+            (Outcome::TimedOut, _) => {
+                timed_out += 1;
+                color_println!(Color::Red, "========[Test timed out after {} ms]========", test.timeout_ms);
+            }
+        }
     }
-    color_println!(crate::io::console::Color::Green, "\n      All tests passed!");
 
-    shutdown(true)
+    let color = if failed + timed_out == 0 { Color::Green } else { Color::Red };
+    color_println!(
+        color,
+        "\n      {} passed, {} failed, {} timed out",
+        passed, failed, timed_out
+    );
+    shutdown(failed + timed_out > 0)
+}
+
+#[kernel_test(should_panic, timeout_ms = 1000)]
+fn should_panic_test() {
+    let values = [1, 2, 3];
+    let index = values.len();
+    // the runner goes on after this
+    assert_eq!(values.get(index), Some(&4));
 }
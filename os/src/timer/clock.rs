@@ -0,0 +1,69 @@
+//! The clocks of `clock_gettime`
+//!
+//! Both run off the `time` CSR: `CLOCK_MONOTONIC` counts from boot,
+//! `CLOCK_REALTIME` adds the time of day at boot, read from the RTC of
+//! the board if any (see `drivers::rtc`), the epoch else.
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use riscv::register::time;
+
+use crate::boards::platform::clock_freq;
+
+pub const CLOCK_REALTIME: usize = 0;
+pub const CLOCK_MONOTONIC: usize = 1;
+
+const NSEC_PER_SEC: u64 = 1_000_000_000;
+
+/// `struct timespec` of the Linux ABI
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimeSpec {
+    pub tv_sec: usize,
+    pub tv_nsec: usize,
+}
+
+impl TimeSpec {
+    pub fn from_ns(ns: u64) -> Self {
+        Self {
+            tv_sec: (ns / NSEC_PER_SEC) as usize,
+            tv_nsec: (ns % NSEC_PER_SEC) as usize,
+        }
+    }
+
+    /// The duration in nanoseconds, `None` if `tv_nsec` is out of range.
+    /// Saturates rather than overflowing for huge `tv_sec`.
+    pub fn to_ns(&self) -> Option<u64> {
+        if self.tv_nsec as u64 >= NSEC_PER_SEC {
+            return None;
+        }
+        Some((self.tv_sec as u64).saturating_mul(NSEC_PER_SEC).saturating_add(self.tv_nsec as u64))
+    }
+}
+
+/// Nanoseconds since the epoch at boot
+static BOOT_REALTIME_NS: AtomicU64 = AtomicU64::new(0);
+
+/// Nanoseconds since boot
+pub fn monotonic_ns() -> u64 {
+    // the product overflows 64 bits after a few minutes at 400MHz
+    (time::read() as u128 * NSEC_PER_SEC as u128 / clock_freq() as u128) as u64
+}
+
+/// Nanoseconds since the epoch
+pub fn realtime_ns() -> u64 {
+    BOOT_REALTIME_NS.load(Ordering::Relaxed) + monotonic_ns()
+}
+
+/// Set `CLOCK_REALTIME` to `now`, in nanoseconds since the epoch.
+pub fn set_realtime_ns(now: u64) {
+    BOOT_REALTIME_NS.store(now.saturating_sub(monotonic_ns()), Ordering::Relaxed);
+}
+
+/// The current time of `clock`, `None` for an unknown clock.
+pub fn clock_gettime(clock: usize) -> Option<TimeSpec> {
+    match clock {
+        CLOCK_REALTIME => Some(TimeSpec::from_ns(realtime_ns())),
+        CLOCK_MONOTONIC => Some(TimeSpec::from_ns(monotonic_ns())),
+        _ => None,
+    }
+}
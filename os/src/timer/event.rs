@@ -0,0 +1,167 @@
+//! One-shot timer events
+//!
+//! Each hart keeps its own queue of events, ordered by absolute deadline
+//! in timer cycles (see [`get_time`]), and programs its timer for the
+//! earliest of them or of its next scheduler tick, whichever comes first.
+//! An event fires once, from the timer interrupt of the hart it was added
+//! on, so its resolution is the one of the timer rather than of the tick.
+//!
+//! The scheduler tick isn't an event: it is re-armed by every interrupt
+//! which finds it due, time slices keep their length whatever fires in
+//! between.
+
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use alloc::{boxed::Box, collections::btree_map::BTreeMap};
+use os_macros::kernel_test;
+
+use crate::{
+    interupt::InterruptController,
+    processor::{get_current_processor, CPU_NUM},
+    sbi::set_timer,
+    sync::spin::mutex::IRQSpinLock,
+    task::determinism,
+};
+
+use super::{get_time, tick_interval};
+
+type Mutex<T> = IRQSpinLock<T>;
+
+type Action = Box<dyn FnOnce() + Send>;
+
+/// An event added by [`add_timer`], to cancel it
+pub struct TimerHandle {
+    hart: usize,
+    key: (usize, u64),
+}
+
+struct TimerQueue {
+    /// By deadline, then by order of addition
+    events: BTreeMap<(usize, u64), Action>,
+    /// Deadline of the next scheduler tick
+    next_tick: usize,
+    /// Deadline the timer of the hart is programmed for
+    programmed: usize,
+}
+
+impl TimerQueue {
+    const fn new() -> Self {
+        Self {
+            events: BTreeMap::new(),
+            next_tick: usize::MAX,
+            programmed: usize::MAX,
+        }
+    }
+
+    /// Program the timer of the current hart, which must own the queue,
+    /// for the earliest deadline.
+    fn program(&mut self) {
+        let earliest = self.events.keys().next().map_or(usize::MAX, |&(deadline, _)| deadline);
+        self.programmed = earliest.min(self.next_tick);
+        set_timer(self.programmed);
+    }
+
+    fn arm_tick(&mut self, now: usize) {
+        self.next_tick = determinism::align_deadline(now + tick_interval(), tick_interval());
+    }
+}
+
+static QUEUES: [Mutex<TimerQueue>; CPU_NUM] = [const { Mutex::new(TimerQueue::new()) }; CPU_NUM];
+
+/// Breaks the ties between events of the same deadline
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Run `f` on the queue of the current hart. Interrupts stay disabled
+/// throughout, the caller can't migrate to another hart in between.
+fn with_local_queue<R>(f: impl FnOnce(usize, &mut TimerQueue) -> R) -> R {
+    InterruptController::intr_disable_nested();
+    let hart = get_current_processor().hart_id();
+    let ret = f(hart, &mut QUEUES[hart].lock());
+    InterruptController::intr_enable_nested();
+    ret
+}
+
+/// Start the scheduler tick of the current hart.
+pub fn start_tick() {
+    with_local_queue(|_, queue| {
+        queue.arm_tick(get_time());
+        queue.program();
+    });
+}
+
+/// Run `action` from the timer interrupt once `get_time() >= deadline`.
+///
+/// `action` runs in interrupt context: it must not block, and should
+/// stay short, e.g. waking a wait queue. A deadline already passed fires
+/// on the next interrupt, at once.
+pub fn add_timer(deadline: usize, action: impl FnOnce() + Send + 'static) -> TimerHandle {
+    let key = (deadline, NEXT_ID.fetch_add(1, Ordering::Relaxed));
+    with_local_queue(|hart, queue| {
+        queue.events.insert(key, Box::new(action));
+        if deadline < queue.programmed {
+            queue.program();
+        }
+        TimerHandle { hart, key }
+    })
+}
+
+/// Cancel the event of `handle`, returns whether it hadn't fired yet.
+///
+/// Works from any hart. The timer of the hart stays programmed, at worst
+/// for an interrupt with nothing to do.
+pub fn cancel_timer(handle: &TimerHandle) -> bool {
+    QUEUES[handle.hart].lock().events.remove(&handle.key).is_some()
+}
+
+/// Fire the expired events of the current hart, then program its timer
+/// for the next deadline. Returns whether the scheduler tick is due.
+///
+/// Called from the timer interrupt handlers.
+pub fn handle_timer_interrupt() -> bool {
+    let now = get_time();
+    loop {
+        // the queue is unlocked while an action runs, it may add events
+        let expired = with_local_queue(|_, queue| match queue.events.first_key_value() {
+            Some((&(deadline, _), _)) if deadline <= now => queue.events.pop_first(),
+            _ => None,
+        });
+        match expired {
+            Some((_, action)) => action(),
+            None => break,
+        }
+    }
+    with_local_queue(|_, queue| {
+        let tick = queue.next_tick <= now;
+        if tick {
+            queue.arm_tick(now);
+        }
+        queue.program();
+        tick
+    })
+}
+
+#[kernel_test]
+fn timer_event_test() {
+    use alloc::{sync::Arc, vec::Vec};
+
+    let fired = Arc::new(Mutex::new(Vec::new()));
+    let record = |label: usize| {
+        let fired = fired.clone();
+        move || fired.lock().push(label)
+    };
+    // the runner enables the timer interrupt, it would fire them first
+    InterruptController::intr_disable_nested();
+    let now = get_time();
+    // added out of order, fired by deadline
+    add_timer(now - 1, record(2));
+    add_timer(now - 2, record(1));
+    let cancelled = add_timer(now - 3, record(0));
+    let later = add_timer(now + tick_interval() * 100, record(3));
+    assert!(cancel_timer(&cancelled));
+    assert!(!cancel_timer(&cancelled));
+
+    handle_timer_interrupt();
+    assert_eq!(*fired.lock(), [1, 2]);
+    assert!(cancel_timer(&later));
+    InterruptController::intr_enable_nested();
+}
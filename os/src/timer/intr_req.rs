@@ -1,11 +1,13 @@
-use crate::{processor::get_current_processor, task::yield_current};
-use super::set_next_trigger;
+use crate::{drivers::console, io, processor::{get_current_processor, watchdog}};
+use super::event::handle_timer_interrupt;
 
 /// Handles timer interrupt requests.
 ///
 /// This function is called when a timer interrupt occurs. It performs two main tasks:
-/// 1. Sets up the next timer interrupt by calling `set_next_trigger()`
-/// 2. Notifies the scheduler about the timer tick, allowing it to perform time-related
+/// 1. Fires the expired timer events (e.g. waking up the tasks whose sleep
+///    has expired) and programs the timer for the next deadline
+/// 2. If the interrupt was the scheduler tick, notifies the scheduler about it,
+///    allowing it to perform time-related
 ///    scheduling operations such as:
 ///    - Updating process/thread time quanta
 ///    - Checking for timeouts
@@ -27,19 +29,29 @@ use super::set_next_trigger;
 /// interrupt_request_handler();
 /// ```
 pub fn kernel_irq_handler() {
-    log::debug!("set next time trigger");
-    // Set up the next timer interrupt
-    set_next_trigger();
-
-    
     log::debug!("Handle timer interrupt");
-    // Notify the scheduler about the timer tick
-    get_current_processor().timer_tick();
-    
+    let tick = handle_timer_interrupt();
+    console::poll();
+    io::console::flush();
+    watchdog::check();
+
+    // Notify the scheduler about the timer tick, last: it may switch tasks,
+    // unless the interrupted kernel code disabled preemption
+    if tick {
+        get_current_processor().kernel_timer_tick();
+    }
 }
 
 
+/// Like [`kernel_irq_handler`], for an interrupt taken from user code:
+/// the hart isn't stuck in the kernel.
 pub fn user_irq_handler() {
-    set_next_trigger();
-    yield_current();
+    watchdog::touch();
+    let tick = handle_timer_interrupt();
+    console::poll();
+    io::console::flush();
+    watchdog::check();
+    if tick {
+        get_current_processor().timer_tick();
+    }
 }
\ No newline at end of file
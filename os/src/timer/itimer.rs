@@ -0,0 +1,176 @@
+//! Interval timers of `setitimer`
+//!
+//! Only `ITIMER_REAL` is supported: it runs on the wall clock and raises
+//! `SIGALRM` at the leader of the task group on expiry, like a `kill` of
+//! the process. Each group has one [`RealTimer`], shared by its threads
+//! and kept across `exec`. A forked child starts without one armed.
+//!
+//! The timer is a timer event (see [`add_timer`]) for its next expiry,
+//! which re-arms itself `interval` later from the timer interrupt. An
+//! expiry missed by more than an interval, on a hart busy with interrupts
+//! disabled, is folded into the one raised: signals don't queue.
+
+use alloc::sync::{Arc, Weak};
+
+use os_macros::kernel_test;
+
+use super::{add_timer, cancel_timer, cycles_to_ns, get_time, ns_to_cycles, TimerHandle};
+use crate::{
+    sync::spin::mutex::IRQSpinLock,
+    task::{signal::Signal, TaskControlBlock},
+};
+
+type Mutex<T> = IRQSpinLock<T>;
+
+/// `which` of `setitimer`
+pub const ITIMER_REAL: usize = 0;
+pub const ITIMER_VIRTUAL: usize = 1;
+pub const ITIMER_PROF: usize = 2;
+
+const USEC_PER_SEC: u64 = 1_000_000;
+
+/// `struct timeval` of the Linux ABI
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimeVal {
+    pub tv_sec: usize,
+    pub tv_usec: usize,
+}
+
+impl TimeVal {
+    fn from_cycles(cycles: usize) -> Self {
+        let us = cycles_to_ns(cycles).div_ceil(1000);
+        Self { tv_sec: (us / USEC_PER_SEC) as usize, tv_usec: (us % USEC_PER_SEC) as usize }
+    }
+
+    /// The duration in timer cycles, `None` if `tv_usec` is out of range.
+    fn to_cycles(self) -> Option<usize> {
+        if self.tv_usec as u64 >= USEC_PER_SEC {
+            return None;
+        }
+        let us = (self.tv_sec as u64).saturating_mul(USEC_PER_SEC).saturating_add(self.tv_usec as u64);
+        Some(ns_to_cycles(us.saturating_mul(1000)))
+    }
+}
+
+/// `struct itimerval`: the period, and the time left to the next expiry,
+/// 0 for a disarmed timer
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ITimerVal {
+    pub interval: TimeVal,
+    pub value: TimeVal,
+}
+
+impl ITimerVal {
+    /// `(interval, value)` in timer cycles, `None` if a field is out of range.
+    pub fn to_cycles(self) -> Option<(usize, usize)> {
+        Some((self.interval.to_cycles()?, self.value.to_cycles()?))
+    }
+}
+
+struct TimerState {
+    /// Timer cycles between expiries, 0 for a one shot timer
+    interval: usize,
+    /// Time of the next expiry, `None` while disarmed
+    deadline: Option<usize>,
+    /// The timer event of that expiry
+    event: Option<TimerHandle>,
+}
+
+/// The `ITIMER_REAL` of a task group
+pub struct RealTimer {
+    /// The task `SIGALRM` is raised at
+    leader: Weak<TaskControlBlock>,
+    state: Mutex<TimerState>,
+}
+
+impl RealTimer {
+    pub fn new(leader: Weak<TaskControlBlock>) -> Arc<Self> {
+        Arc::new(Self { leader, state: Mutex::new(TimerState { interval: 0, deadline: None, event: None }) })
+    }
+
+    /// The timer as `getitimer` reports it
+    pub fn get(&self) -> ITimerVal {
+        let state = self.state.lock();
+        let left = state.deadline.map_or(0, |deadline| deadline.saturating_sub(get_time()).max(1));
+        ITimerVal { interval: TimeVal::from_cycles(state.interval), value: TimeVal::from_cycles(left) }
+    }
+
+    /// Arm the timer to expire `value` cycles from now, then every
+    /// `interval`, or disarm it for a `value` of 0. Returns the setting
+    /// it replaced.
+    pub fn set(self: &Arc<Self>, interval: usize, value: usize) -> ITimerVal {
+        let old = self.get();
+        let mut state = self.state.lock();
+        if let Some(event) = state.event.take() {
+            cancel_timer(&event);
+        }
+        state.interval = interval;
+        state.deadline = None;
+        if value != 0 {
+            self.arm(&mut state, get_time().saturating_add(value));
+        }
+        old
+    }
+
+    fn arm(self: &Arc<Self>, state: &mut TimerState, deadline: usize) {
+        let timer = Arc::downgrade(self);
+        state.deadline = Some(deadline);
+        state.event = Some(add_timer(deadline, move || {
+            if let Some(timer) = timer.upgrade() {
+                timer.expire(deadline);
+            }
+        }));
+    }
+
+    /// The expiry at `deadline` came, from the timer interrupt.
+    fn expire(self: &Arc<Self>, deadline: usize) {
+        let mut state = self.state.lock();
+        // set again meanwhile, the event of the old setting
+        if state.deadline != Some(deadline) {
+            return;
+        }
+        state.event = None;
+        state.deadline = None;
+        if let Some(leader) = self.leader.upgrade() {
+            leader.lock().signal(Signal::SIGALRM);
+        }
+        if state.interval != 0 {
+            let now = get_time();
+            let mut next = deadline.saturating_add(state.interval);
+            if next <= now {
+                next = now.saturating_add(state.interval);
+            }
+            self.arm(&mut state, next);
+        }
+    }
+}
+
+impl Drop for RealTimer {
+    fn drop(&mut self) {
+        if let Some(event) = self.state.get_mut().event.take() {
+            cancel_timer(&event);
+        }
+    }
+}
+
+#[kernel_test]
+fn itimer_test() {
+    let value = TimeVal { tv_sec: 1, tv_usec: 500_000 };
+    let cycles = value.to_cycles().unwrap();
+    assert_eq!(TimeVal::from_cycles(cycles), value);
+    assert_eq!(TimeVal { tv_sec: 0, tv_usec: 1_000_000 }.to_cycles(), None);
+
+    // without a leader to signal, only the bookkeeping is checked
+    let timer = RealTimer::new(Weak::new());
+    assert_eq!(timer.get(), ITimerVal::default());
+    let old = timer.set(cycles, cycles * 100);
+    assert_eq!(old, ITimerVal::default());
+    let armed = timer.get();
+    assert_eq!(armed.interval, value);
+    assert!(armed.value.tv_sec >= 149 && armed.value.tv_sec <= 150);
+    // disarmed, the old setting is returned
+    assert_eq!(timer.set(0, 0).interval, value);
+    assert_eq!(timer.get(), ITimerVal::default());
+}
@@ -1,13 +1,28 @@
 use riscv::register::time;
-use crate::{config::CLOCK_FREQ, sbi::set_timer};
+use crate::boards::platform::clock_freq;
 
 
 mod syscall;
+mod sleep;
+mod clock;
+mod event;
 pub mod intr_req;
+pub mod itimer;
+
+pub use sleep::sleep_until;
+pub use clock::{clock_gettime, realtime_ns, set_realtime_ns, TimeSpec};
+pub use event::{add_timer, cancel_timer, TimerHandle};
 
 // const TICKS_PER_SEC: usize = 100;
 const TICKS_PER_SEC: usize = 50;
 const MICRO_PER_SEC: usize = 1_000_000;
+const MSEC_PER_SEC: usize = 1_000;
+const NSEC_PER_SEC: usize = 1_000_000_000;
+
+/// Timer cycles between two scheduler ticks
+fn tick_interval() -> usize {
+    clock_freq() / TICKS_PER_SEC / 5
+}
 
 
 
@@ -16,39 +31,23 @@ pub fn get_time() -> usize {
 }
 
 
-/// Sets the next timer interrupt at a fixed interval (e.g., 10ms).
+/// Starts the scheduler tick of the current hart, every `tick_interval()`
+/// timer cycles.
 ///
-/// The function `set_next_trigger` works by first reading the current value 
-/// of the machine timer (`mtime`), then computing the time interval for the 
-/// next interrupt (e.g., 10ms) based on the constants `CLOCK_FREQ` and 
-/// `TICKS_PER_SEC`. The timer interrupt is scheduled by setting the `mtimecmp` 
-/// register to the sum of the current `mtime` value and the computed increment 
-/// (which corresponds to the desired time interval).
+/// The timer itself (`mtimecmp`, set through SBI) is programmed for the
+/// earliest of the next tick and of the pending timer events, see
+/// [`event`]. Each timer interrupt which finds the tick due re-arms it
+/// `tick_interval()` later, from the time of the interrupt.
 ///
-/// The constant `CLOCK_FREQ` represents the clock frequency of the platform 
-/// (in Hz), i.e., the number of clock cycles per second. The value of 
-/// `CLOCK_FREQ` is typically determined at compile time based on the target 
-/// hardware platform. The constant `TICKS_PER_SEC` defines how many timer 
-/// interrupts are expected to occur per second. By dividing `CLOCK_FREQ` by 
-/// `TICKS_PER_SEC`, we compute the increment value that will correspond to 
-/// the desired timer interrupt frequency.
-///
-/// This process ensures that a timer interrupt is triggered after a fixed 
-/// interval (e.g., every 10ms), enabling periodic tasks to be scheduled at 
-/// the specified frequency.
-///
-/// # Constants:
-/// - `CLOCK_FREQ`: The platform's clock frequency in Hz (cycles per second).
-/// - `TICKS_PER_SEC`: The number of timer ticks per second (e.g., 100).
+/// In deterministic mode the tick deadline is aligned down to the tick
+/// grid, so interrupts land on the same timer values from one run to the
+/// next.
 ///
 /// # Example:
-/// If `CLOCK_FREQ` is 1,000,000 Hz (1 MHz) and `TICKS_PER_SEC` is 100, 
-/// the increment value will be 10,000 clock cycles, meaning the next timer 
-/// interrupt will occur 10ms after the current time.
-
+/// If `CLOCK_FREQ` is 1,000,000 Hz (1 MHz) and `TICKS_PER_SEC` is 100,
+/// the tick comes every 2,000 clock cycles, i.e. every 2ms.
 pub fn set_next_trigger() {
-    const TICK_TIME: usize = CLOCK_FREQ / TICKS_PER_SEC;
-    set_timer(get_time() + TICK_TIME/5);
+    event::start_tick();
 }
 
 /// Returns the current time **in microseconds (µs)**.
@@ -67,6 +66,31 @@ pub fn set_next_trigger() {
 /// If `CLOCK_FREQ` is 1,000,000 (1 MHz), this function will return the current 
 /// time in microseconds by converting the clock cycle count returned by `time::read()`.
 pub fn get_time_us() -> usize {
-    time::read() / (CLOCK_FREQ / MICRO_PER_SEC)
+    time::read() / (clock_freq() / MICRO_PER_SEC)
 }
 
+/// Returns the current time **in milliseconds (ms)**.
+pub fn get_time_ms() -> usize {
+    time::read() / (clock_freq() / MSEC_PER_SEC)
+}
+
+/// Converts a duration in milliseconds to timer cycles.
+pub fn ms_to_cycles(ms: usize) -> usize {
+    ms.saturating_mul(clock_freq() / MSEC_PER_SEC)
+}
+
+/// Converts a duration in nanoseconds to timer cycles.
+pub fn ns_to_cycles(ns: u64) -> usize {
+    // the product overflows 64 bits for durations of a few minutes
+    (ns as u128 * clock_freq() as u128 / NSEC_PER_SEC as u128).min(usize::MAX as u128) as usize
+}
+
+/// Converts a duration in timer cycles to milliseconds.
+pub fn cycles_to_ms(cycles: usize) -> usize {
+    cycles / (clock_freq() / MSEC_PER_SEC)
+}
+
+/// Converts a duration in timer cycles to nanoseconds.
+pub fn cycles_to_ns(cycles: usize) -> u64 {
+    (cycles as u128 * NSEC_PER_SEC as u128 / clock_freq() as u128).min(u64::MAX as u128) as u64
+}
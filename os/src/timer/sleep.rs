@@ -0,0 +1,27 @@
+//! Timed sleep
+//!
+//! Sleeping tasks block on a private [`WaitQueue`], woken up by a timer
+//! event armed for their deadline (see [`add_timer`]), so the resolution
+//! of a sleep is the one of the timer rather than of the scheduler tick.
+
+use alloc::sync::Arc;
+
+use crate::task::WaitQueue;
+
+use super::{add_timer, get_time};
+
+/// Block the current task until `get_time() >= deadline`.
+pub fn sleep_until(deadline: usize) {
+    if get_time() >= deadline {
+        return;
+    }
+
+    let queue = Arc::new(WaitQueue::new());
+    // armed only once the task is queued, the timer can't fire in between
+    queue.sleep_on_with(|| {
+        let queue = queue.clone();
+        add_timer(deadline, move || {
+            queue.wake_all();
+        });
+    });
+}
@@ -1,7 +1,153 @@
 use os_macros::syscall_register;
-use super::get_time_us;
+use super::{
+    clock_gettime, get_time, get_time_us,
+    itimer::{ITimerVal, ITIMER_PROF, ITIMER_REAL, ITIMER_VIRTUAL},
+    ns_to_cycles, sleep_until, TimeSpec,
+};
+use crate::{mm::user_ptr::UserPtr, syscall::error::Errno, task::current_task};
 
 #[syscall_register(SYSCALL_GET_TIME)]
 pub fn sys_get_time() -> isize {
     get_time_us() as isize
-}
\ No newline at end of file
+}
+
+/// Sleep for at least the duration at `req`, without using the CPU.
+///
+/// Signals don't interrupt the sleep, the time left stored at `rem`
+/// (unless null) is always zero.
+///
+/// # Returns
+/// - 0 once the duration elapsed
+/// - `-EINVAL` if `tv_nsec` of `req` is not below one second
+/// - `-EFAULT` if `req` or `rem` is not mapped
+#[syscall_register(SYSCALL_NANOSLEEP)]
+pub fn sys_nanosleep(req: *const TimeSpec, rem: *mut TimeSpec) -> isize {
+    let token = {
+        let task_guard = current_task().unwrap().lock();
+        let mut memory_set = task_guard.user_res.as_ref().unwrap().memory_set.lock();
+        let size = core::mem::size_of::<TimeSpec>();
+        if memory_set.populate_range(req as usize, size).is_err()
+            || (!rem.is_null() && memory_set.populate_range(rem as usize, size).is_err())
+        {
+            return Errno::EFAULT.as_ret();
+        }
+        memory_set.token()
+    };
+    let Ok(req) = UserPtr::new(token, req).read() else {
+        return Errno::EFAULT.as_ret();
+    };
+    let Some(ns) = req.to_ns() else {
+        return Errno::EINVAL.as_ret();
+    };
+
+    sleep_until(get_time().saturating_add(ns_to_cycles(ns)));
+    if !rem.is_null() && UserPtr::new(token, rem as *const TimeSpec).write(TimeSpec::default()).is_err() {
+        return Errno::EFAULT.as_ret();
+    }
+    0
+}
+
+/// Store the current time of `clock` at `tp`.
+///
+/// # Returns
+/// - 0 on success
+/// - `-EINVAL` for a clock other than `CLOCK_REALTIME` and `CLOCK_MONOTONIC`
+/// - `-EFAULT` if `tp` is not mapped
+#[syscall_register(SYSCALL_CLOCK_GETTIME)]
+pub fn sys_clock_gettime(clock: usize, tp: *mut TimeSpec) -> isize {
+    let Some(now) = clock_gettime(clock) else {
+        return Errno::EINVAL.as_ret();
+    };
+    let token = {
+        let task_guard = current_task().unwrap().lock();
+        let mut memory_set = task_guard.user_res.as_ref().unwrap().memory_set.lock();
+        // written through the page table, a lazy page doesn't fault
+        if memory_set.populate_range(tp as usize, core::mem::size_of::<TimeSpec>()).is_err() {
+            return Errno::EFAULT.as_ret();
+        }
+        memory_set.token()
+    };
+    match UserPtr::new(token, tp).write(now) {
+        Ok(()) => 0,
+        Err(_) => Errno::EFAULT.as_ret(),
+    }
+}
+
+/// Check that `which` names a supported timer.
+fn check_itimer(which: usize) -> Result<(), Errno> {
+    match which {
+        ITIMER_REAL => Ok(()),
+        // the CPU time of the group isn't accounted to the timers
+        ITIMER_VIRTUAL | ITIMER_PROF => Err(Errno::EINVAL),
+        _ => Err(Errno::EINVAL),
+    }
+}
+
+/// Store the interval timer `which` of the task group at `curr`.
+///
+/// # Returns
+/// - 0 on success
+/// - `-EINVAL` for a timer other than `ITIMER_REAL`
+/// - `-EFAULT` if `curr` is not mapped
+#[syscall_register(SYSCALL_GETITIMER)]
+pub fn sys_getitimer(which: usize, curr: *mut ITimerVal) -> isize {
+    if let Err(errno) = check_itimer(which) {
+        return errno.as_ret();
+    }
+    let (token, timer) = {
+        let task_guard = current_task().unwrap().lock();
+        let user_res = task_guard.user_res.as_ref().unwrap();
+        let mut memory_set = user_res.memory_set.lock();
+        if memory_set.populate_range(curr as usize, core::mem::size_of::<ITimerVal>()).is_err() {
+            return Errno::EFAULT.as_ret();
+        }
+        (memory_set.token(), user_res.real_timer.clone())
+    };
+    match UserPtr::new(token, curr).write(timer.get()) {
+        Ok(()) => 0,
+        Err(_) => Errno::EFAULT.as_ret(),
+    }
+}
+
+/// Arm the interval timer `which` of the task group to the setting at
+/// `new`, storing the one it replaces at `old` unless null.
+///
+/// On expiry `ITIMER_REAL` raises `SIGALRM` at the group, then runs again
+/// for `it_interval` unless that is zero. A zero `it_value` disarms it.
+///
+/// # Returns
+/// - 0 on success
+/// - `-EINVAL` for a timer other than `ITIMER_REAL`, or a `tv_usec` of
+///   `new` not below one second
+/// - `-EFAULT` if `new` or `old` is not mapped
+#[syscall_register(SYSCALL_SETITIMER)]
+pub fn sys_setitimer(which: usize, new: *const ITimerVal, old: *mut ITimerVal) -> isize {
+    if let Err(errno) = check_itimer(which) {
+        return errno.as_ret();
+    }
+    let (token, timer) = {
+        let task_guard = current_task().unwrap().lock();
+        let user_res = task_guard.user_res.as_ref().unwrap();
+        let mut memory_set = user_res.memory_set.lock();
+        let size = core::mem::size_of::<ITimerVal>();
+        if memory_set.populate_range(new as usize, size).is_err()
+            || (!old.is_null() && memory_set.populate_range(old as usize, size).is_err())
+        {
+            return Errno::EFAULT.as_ret();
+        }
+        (memory_set.token(), user_res.real_timer.clone())
+    };
+    let Ok(new) = UserPtr::new(token, new).read() else {
+        return Errno::EFAULT.as_ret();
+    };
+    let Some((interval, value)) = new.to_cycles() else {
+        return Errno::EINVAL.as_ret();
+    };
+
+    // the task lock is dropped: the expiry takes it under the timer lock
+    let replaced = timer.set(interval, value);
+    if !old.is_null() && UserPtr::new(token, old as *const ITimerVal).write(replaced).is_err() {
+        return Errno::EFAULT.as_ret();
+    }
+    0
+}
@@ -10,6 +10,28 @@ pub struct Frame {
 #[inline(never)]
 pub fn trace(max_depth: usize) -> Vec<Frame> {
     let mut frames = Vec::new();
+    walk(max_depth, |frame| frames.push(frame));
+    frames
+}
+
+/// Fill `ras` with the return addresses of the callers, innermost first,
+/// returns how many were found.
+///
+/// Doesn't allocate, for callers which can't (e.g. under a heap lock).
+#[inline(never)]
+pub fn trace_into(ras: &mut [usize]) -> usize {
+    let mut depth = 0;
+    walk(ras.len(), |frame| {
+        ras[depth] = frame.ra;
+        depth += 1;
+    });
+    depth
+}
+
+/// Call `f` on at most `max_depth` frames, from the frame of the caller of
+/// [`trace`] or [`trace_into`] up.
+#[inline(always)]
+fn walk(max_depth: usize, mut f: impl FnMut(Frame)) {
     let mut current_fp: usize;
 
     // 获取初始帧指针 (RISC-V 使用 s0)
@@ -24,13 +46,11 @@ pub fn trace(max_depth: usize) -> Vec<Frame> {
 
         // 获取返回地址 (RISC-V: fp - 8)
         let ra = unsafe { (current_fp as *const usize).sub(1).read_volatile() };
-        frames.push(Frame { fp: current_fp, ra });
+        f(Frame { fp: current_fp, ra });
 
         // 上一级帧指针 (RISC-V: fp - 16)
         current_fp = unsafe { (current_fp as *const usize).sub(2).read_volatile() };
     }
-
-    frames
 }
 
 extern "C" {
@@ -1 +1,2 @@
-pub mod backtrace;
\ No newline at end of file
+pub mod backtrace;
+pub mod symbols;
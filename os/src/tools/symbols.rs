@@ -0,0 +1,115 @@
+//! Kernel symbol table, to name the addresses of a backtrace
+//!
+//! The `.ksymtab` section is reserved at build time, zeroed, and filled
+//! once the kernel is linked by `scripts/ksymtab.py`, with the function
+//! symbols of the very image: in place, nothing moves. A kernel built
+//! without the script (`cargo build` alone) resolves nothing.
+//!
+//! Layout, little endian:
+//! ```text
+//! magic "KSYMTAB\0"             8 bytes
+//! count                         u64
+//! entries, by address           count * { addr: u64, name: u32, len: u32 }
+//! names                         `name` is an offset from here, not NUL-terminated
+//! ```
+
+use core::{cell::UnsafeCell, fmt};
+
+use os_macros::kernel_test;
+
+/// Size of `.ksymtab`, `scripts/ksymtab.py` reads it from the image
+const KSYMTAB_SIZE: usize = 512 * 1024;
+const MAGIC: &[u8; 8] = b"KSYMTAB\0";
+const HEADER_SIZE: usize = 16;
+const ENTRY_SIZE: usize = 16;
+
+/// Written behind the back of the compiler, which must not assume it zero
+struct Reserved(UnsafeCell<[u8; KSYMTAB_SIZE]>);
+
+unsafe impl Sync for Reserved {}
+
+#[used]
+#[link_section = ".ksymtab"]
+static KSYMTAB: Reserved = Reserved(UnsafeCell::new([0; KSYMTAB_SIZE]));
+
+/// The filled part of the table: entry count, entries and names, `None`
+/// if it wasn't filled
+fn table() -> Option<(usize, &'static [u8], &'static [u8])> {
+    let bytes: &'static [u8] = unsafe { &*KSYMTAB.0.get() };
+    if &bytes[..8] != MAGIC {
+        return None;
+    }
+    let count = read_u64(bytes, 8) as usize;
+    let names = HEADER_SIZE + count * ENTRY_SIZE;
+    if names > bytes.len() {
+        return None;
+    }
+    Some((count, &bytes[HEADER_SIZE..names], &bytes[names..]))
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+/// The function `addr` is in, and the offset of `addr` in it.
+///
+/// Neither allocates nor locks, it can be called from the panic handler or
+/// under any lock.
+pub fn resolve(addr: usize) -> Option<(&'static str, usize)> {
+    let (count, entries, names) = table()?;
+    let addr_of = |index: usize| read_u64(entries, index * ENTRY_SIZE) as usize;
+    // the last symbol starting at or before `addr`
+    let (mut low, mut high) = (0, count);
+    while low < high {
+        let middle = (low + high) / 2;
+        if addr_of(middle) <= addr {
+            low = middle + 1;
+        } else {
+            high = middle;
+        }
+    }
+    let index = low.checked_sub(1)?;
+    let entry = index * ENTRY_SIZE;
+    let name = read_u32(entries, entry + 8) as usize;
+    let len = read_u32(entries, entry + 12) as usize;
+    let name = core::str::from_utf8(names.get(name..name + len)?).ok()?;
+    Some((name, addr - addr_of(index)))
+}
+
+/// A return address, named after the call it returns from: the call may be
+/// the last instruction of its function, `ra` then points to the next one.
+pub fn resolve_return_address(ra: usize) -> Option<(&'static str, usize)> {
+    let (name, offset) = resolve(ra.checked_sub(1)?)?;
+    Some((name, offset + 1))
+}
+
+/// Formats a return address as `function+0xoffset`, or `?`
+pub struct Symbolized(pub usize);
+
+impl fmt::Display for Symbolized {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match resolve_return_address(self.0) {
+            Some((name, offset)) => write!(f, "{}+{:#x}", name, offset),
+            None => write!(f, "?"),
+        }
+    }
+}
+
+#[kernel_test]
+fn symbols_test() {
+    // nothing to check in an image the script didn't fill
+    if table().is_none() {
+        return;
+    }
+    let (name, offset) = resolve(symbols_test as usize).unwrap();
+    assert!(name.ends_with("symbols_test"));
+    assert_eq!(offset, 0);
+    let (name, offset) = resolve(symbols_test as usize + 4).unwrap();
+    assert!(name.ends_with("symbols_test"));
+    assert_eq!(offset, 4);
+    assert!(resolve(0).is_none());
+}
@@ -1,6 +1,7 @@
 //! Implementation of [`TrapContext`]
 
 use alloc::{format, vec::Vec};
+use riscv::register::scause::{Exception, Interrupt, Trap};
 use riscv::register::sstatus::{
     self, Sstatus, SPP
 };
@@ -49,6 +50,7 @@ use crate::register;
 /// +--------------------------------------------------------+
 /// ```
 #[repr(C)]
+#[derive(Clone)]
 pub struct TrapContext {
     // =====================================+
     // | Save   | when (user  ) -> (kernel) |
@@ -169,9 +171,54 @@ impl TrapContext {
 
 
 
+/// The frame `__alltraps_kernel` pushes on the stack a trap is taken on
+/// in the kernel, restored by `__restore_kernel`.
+///
+/// Unlike a [`TrapContext`], it holds the CSRs describing the trap too:
+/// a nested trap may overwrite them before the handler is done.
+#[repr(C)]
+pub struct KernelTrapContext {
+    /// (0~31) common registers, `x[2]` is the `sp` before the trap and
+    /// `x[4]` (`tp`) is not restored
+    pub x: [usize; 32],
+    /// (32) CSR sstatus
+    pub sstatus: usize,
+    /// (33) CSR sepc, where the trapped code resumes
+    pub sepc: usize,
+    /// (34) CSR scause
+    pub scause: usize,
+    /// (35) CSR stval
+    pub stval: usize,
+}
+
+impl KernelTrapContext {
+    /// Decode [`scause`](Self::scause)
+    pub fn cause(&self) -> Trap {
+        const INTERRUPT: usize = 1 << (usize::BITS - 1);
+        let code = self.scause & !INTERRUPT;
+        if self.scause & INTERRUPT != 0 {
+            Trap::Interrupt(Interrupt::from(code))
+        } else {
+            Trap::Exception(Exception::from(code))
+        }
+    }
+}
 
 use core::fmt;
 
+impl fmt::Debug for KernelTrapContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KernelTrapContext")
+            .field("ra", &format_args!("{:#x}", self.x[1]))
+            .field("sp", &format_args!("{:#x}", self.x[2]))
+            .field("sstatus", &format_args!("{:#x}", self.sstatus))
+            .field("sepc", &format_args!("{:#x}", self.sepc))
+            .field("scause", &self.cause())
+            .field("stval", &format_args!("{:#x}", self.stval))
+            .finish()
+    }
+}
+
 impl fmt::Debug for TrapContext {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         // 按你的注释分块输出
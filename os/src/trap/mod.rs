@@ -11,32 +11,56 @@ use core::arch::asm;
 
 use alloc::boxed::Box;
 use riscv::register::utvec::TrapMode;
-use riscv::register::{scause, sepc, sscratch, sstatus, stval, stvec};
+use riscv::register::{satp, scause, sepc, sscratch, stval, stvec};
 use riscv::register::scause::{Exception, Interrupt, Trap};
 
-use crate::config::TRAMPOLINE;
+use crate::config::{KERNEL_STACK_SIZE, TRAMPOLINE};
 use crate::interupt::InterruptController;
-use crate::register::Sstatus;
+use crate::register::Tp;
+use crate::mm::address::VirtAddr;
+use crate::mm::oom::{out_of_memory, OomOutcome};
+use crate::mm::MemoryError;
+use crate::mm::page_table::{PTEFlags, PageTable};
+use crate::mm::uaccess;
+use crate::processor::ipi;
+use crate::random;
 use crate::syscall::syscall_handler;
-use crate::task::{current_task, current_user_token, current_user_trap_context, current_user_trap_context_va, exit_current};
+use crate::task::signal::Signal;
+use crate::task::{current_task, yield_current, KernelStackGuard, current_user_token, current_user_trap_context, current_user_trap_context_va, exit_current, handle_signals};
 use crate::timer::{self, set_next_trigger};
+use crate::drivers::plic;
 use crate::{global_asm, println};
 
 use riscv::register::sie;
 
-pub use context::TrapContext;
+pub use context::{KernelTrapContext, TrapContext};
 
 pub fn enable_timer_interrupt() {
     unsafe { sie::set_stimer();}
 }
 
+pub fn enable_external_interrupt() {
+    unsafe { sie::set_sext(); }
+}
+
+/// Take the IPIs of the other harts
+pub fn enable_soft_interrupt() {
+    unsafe { sie::set_ssoft(); }
+}
+
 
 // Include the trap assembly implementation.
 global_asm!(include_str!("trap.S"));
 
+// `KERNEL_STACK_SHIFT` of trap.S
+const _: () = assert!(KERNEL_STACK_SIZE == 1 << 14);
+// `KERNEL_TRAP_FRAME` of trap.S, which keeps `sp` 16 bytes aligned
+const _: () = assert!(core::mem::size_of::<KernelTrapContext>() == 36 * 8);
+
 /// Initialize the CSR `stvec` to point to the trap entry `__alltraps`.
 pub fn init() {
     set_kernel_trap_entry();
+    uaccess::init();
 }
 
 fn set_kernel_trap_entry() {
@@ -99,12 +123,16 @@ pub fn trap_handler() -> ! {
 
     use scause::Trap;
 
+    if scause.is_interrupt() {
+        random::add_interrupt_entropy(sepc::read());
+    }
     match scause.cause() {
         // Handle system calls.
         Trap::Exception(Exception::UserEnvCall) => {
             let current_trap_context = current_user_trap_context();
             // Advance the program counter to skip the ecall instruction.
             current_trap_context.sepc += 4;
+            current_task().unwrap().stats().on_syscall();
 
             InterruptController::global_enable();
             // Handlding the system call using a0~a5
@@ -130,18 +158,40 @@ pub fn trap_handler() -> ! {
         | Trap::Exception(Exception::LoadFault)
         | Trap::Exception(Exception::LoadPageFault) => {
             let task = current_task().unwrap();
-            task.lock().with_user_res(|user_res| {
-                log::info!("user res: {:?}", user_res);
+            let mut task_inner = task.lock();
+            let vpn = VirtAddr::from(stval).down_to_vpn();
+            let (lazy_fault, in_guard_gap, in_stack_guard) = task_inner.with_user_res(|user_res| {
+                let mut memory_set = user_res.memory_set.lock();
+                (memory_set.handle_lazy_fault(vpn), memory_set.in_guard_gap(vpn), memory_set.in_stack_guard(vpn))
             });
-
-            log::error!("Page Fault in application, kernel killed it."); 
-            log::error!("{:?}, stval = {:#x}!",
-                scause.cause(),
-                stval);
-            // exit whole task group and run other task
-            log::debug!("task user stack: ");
-            // unimplemented!()
-            exit_current(-1);
+            // on the first touch of a lazily allocated page (e.g. the heap),
+            // or below a stack that may grow, the faulting instruction is
+            // just retried
+            if lazy_fault == Err(MemoryError::OutOfMemory) {
+                drop(task_inner);
+                // retried too, once the victim gave its frames back: this
+                // task may be the victim, it dies on its way out then
+                match out_of_memory() {
+                    OomOutcome::Killed(_) | OomOutcome::Waiting => yield_current(),
+                    OomOutcome::NoVictim => {
+                        log::error!("{:?} in application, stval = {:#x} (out of memory)", scause.cause(), stval);
+                        task.lock().force_signal(Signal::SIGSEGV);
+                    }
+                }
+            } else if lazy_fault != Ok(true) {
+                log::info!("user res: {:?}", task_inner.user_res);
+                log::error!("{:?} in application, stval = {:#x}{}",
+                    scause.cause(),
+                    stval,
+                    match lazy_fault {
+                        Err(_) => " (out of memory)",
+                        _ if in_stack_guard => " (stack overflow)",
+                        _ if in_guard_gap => " (guard gap)",
+                        _ => "",
+                    });
+                // fatal unless the task handles it, see `handle_signals`
+                task_inner.force_signal(Signal::SIGSEGV);
+            }
             
         },
 
@@ -160,6 +210,14 @@ pub fn trap_handler() -> ! {
             timer::intr_req::user_irq_handler();
         },
 
+        Trap::Interrupt(Interrupt::SupervisorExternal) => {
+            plic::handle_external_irq();
+        },
+
+        Trap::Interrupt(Interrupt::SupervisorSoft) => {
+            ipi::handle_ipi();
+        },
+
         // Handle unsupported traps.
         _ => {
             panic!(
@@ -170,6 +228,9 @@ pub fn trap_handler() -> ! {
         }
     }
 
+    // Deliver pending signals, may redirect the return into a user handler
+    handle_signals();
+
     // Return the updated trap context.
     // And then return to trap.S 
     // and continue from __restore 
@@ -215,6 +276,8 @@ pub fn trap_return() -> ! {
 
     let user_satp = current_user_token();
     let trap_cx_ptr: usize = current_user_trap_context_va().into();
+    // the task may have moved to another hart since it trapped
+    current_user_trap_context().kernel_tp = Tp::read();
 
     log::debug!("current user toekn: 0x{:x}, trap_cx_ptr: 0x{:x}", user_satp, trap_cx_ptr);
 
@@ -242,44 +305,75 @@ pub fn trap_return() -> ! {
 }
 
 
+/// Handle a trap taken in the kernel, on the frame `__alltraps_kernel`
+/// pushed, which `__restore_kernel` resumes from once this returns.
+///
+/// Interrupts stay disabled throughout, as the hardware left them. A
+/// scheduler tick may still switch the current task out from here (see
+/// [`ProcessorLocal::kernel_timer_tick`](crate::processor::ProcessorLocal::kernel_timer_tick)):
+/// the frame stays on its kernel stack until the task runs again, on
+/// whichever hart, and the CSRs of the trap are restored from it.
+///
+/// Exceptions other than a kernel stack overflow are kernel bugs.
 #[no_mangle]
-// Unimplement: traps/interrupts/exceptions
-pub fn trap_from_kernel(trap_context: &TrapContext){
-    log::debug!("trap from kernel");
-
-    
-    let scause = scause::read();
-    let stval = stval::read();
-    
+pub fn trap_from_kernel(trap_context: &mut KernelTrapContext) {
     log::debug!("trap from kernel: scause.cause {:?}, stval {:#x}",
-    scause.cause(), stval);
-    
-    let sepc_ = sepc::read();
-    let sstatus_ = sstatus::read();
+        trap_context.cause(), trap_context.stval);
 
-    match scause.cause() {
+    if let Trap::Interrupt(_) = trap_context.cause() {
+        random::add_interrupt_entropy(trap_context.sepc);
+    }
+    match trap_context.cause() {
         Trap::Interrupt(Interrupt::SupervisorExternal) => {
-            unimplemented!()
+            plic::handle_external_irq();
         },
         Trap::Interrupt(Interrupt::SupervisorTimer) => {
             timer::intr_req::kernel_irq_handler();
         },
-        _ => {
-
+        Trap::Interrupt(Interrupt::SupervisorSoft) => {
+            ipi::handle_ipi();
+        },
+        Trap::Exception(Exception::LoadPageFault | Exception::StorePageFault)
+            if PageTable::from_token(satp::read().bits())
+                .is_canary(VirtAddr::from(trap_context.stval).down_to_vpn()) =>
+        {
+            kernel_stack_overflow(trap_context.stval, trap_context.sepc);
+        },
+        Trap::Exception(Exception::LoadPageFault | Exception::StorePageFault)
+            if trap_context.stval < TRAMPOLINE && !uaccess::is_open()
+                && PageTable::from_token(satp::read().bits())
+                    .find_pte_by_vpn(VirtAddr::from(trap_context.stval).down_to_vpn())
+                    .is_some_and(|pte| pte.is_valid() && pte.flags().contains(PTEFlags::U)) =>
+        {
+            panic!("kernel touched user page {:#x} outside of a user access window, sepc {:#x}",
+                trap_context.stval, trap_context.sepc
+            );
+        },
+        cause => {
             println!("{:?}", trap_context);
-            panic!("Unsupport trap from kernel: scause.cause {:?}, stval {:#x}",
-                scause.cause(), stval
+            panic!("Unsupport trap from kernel: scause.cause {:?}, stval {:#x}, sepc {:#x}",
+                cause, trap_context.stval, trap_context.sepc
             );
         }
     }
 
-    // the yield() may have caused some traps to occur,
-    // so restore trap registers for use by kernelvec.S's sepc instruction.
-    sepc::write(sepc_);
-    Sstatus::write(sstatus_.bits());
-
     log::debug!("finish trap_from_kernel");
-    
 }
 
-
+/// A kernel stack overflowed into its canary page, the trap entry moved
+/// to the overflow stack of the hart: report it and the task it belongs to.
+fn kernel_stack_overflow(stval: usize, sepc: usize) -> ! {
+    // the stack of the task running on this hart
+    match current_task() {
+        Some(task) => println!(
+            "[kernel] task {} ({}) overflowed its kernel stack",
+            usize::from(task.get_tid()),
+            task.get_name()
+        ),
+        None => println!("[kernel] kernel stack overflow outside of any task"),
+    }
+    if let Some((bottom, top)) = KernelStackGuard::overflowed_stack(stval) {
+        println!("[kernel] stack [{:#x}, {:#x}), canary page hit at {:#x}", bottom, top, stval);
+    }
+    panic!("kernel stack overflow, sepc {:#x}, stval {:#x}", sepc, stval);
+}
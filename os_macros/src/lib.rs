@@ -2,7 +2,7 @@
 use proc_macro::TokenStream;
 use quote::{format_ident, quote};
 use syn::spanned::Spanned;
-use syn::{parse_macro_input, Expr, FnArg, ItemFn, ReturnType};
+use syn::{parse_macro_input, Expr, FnArg, ItemFn, LitInt, LitStr, ReturnType};
 
 /// System call registration procedural macro
 ///
@@ -13,10 +13,12 @@ use syn::{parse_macro_input, Expr, FnArg, ItemFn, ReturnType};
 ///
 /// Usage: #[syscall_register(N)] where N is the syscall number
 ///
-/// Safety requirements:
-/// - Only accepts types implementing From<usize>
-/// - Requires SYSCALL_TABLE to be defined externally
-/// - Generates unsafe argument conversion code
+/// Each argument is converted to the type of its parameter with
+/// `crate::syscall::args::SyscallArg`: one which doesn't convert makes the
+/// wrapper return its error, `-EINVAL` or `-EFAULT`, without calling the
+/// handler.
+///
+/// Requires SYSCALL_TABLE to be defined externally
 #[proc_macro_attribute]
 pub fn syscall_register(attr: TokenStream, item: TokenStream) -> TokenStream {
     // Parse attribute as expression and input function
@@ -44,8 +46,15 @@ pub fn syscall_register(attr: TokenStream, item: TokenStream) -> TokenStream {
     // Generate argument conversion code for wrapper
     let arg_conversions = params.clone().map(|(i, arg_name, arg_type)| {
         quote! {
-            let #arg_name = unsafe {
-                 args[#i] as #arg_type
+            let #arg_name = match <#arg_type as crate::syscall::args::SyscallArg>::from_arg(args[#i]) {
+                Ok(arg) => arg,
+                Err(errno) => {
+                    log::debug!(
+                        "{}: argument {} is {:#x}, not a {}: {:?}",
+                        stringify!(#fn_name), #i, args[#i], stringify!(#arg_type), errno
+                    );
+                    return errno.as_ret();
+                }
             };
         }
     });
@@ -138,45 +147,159 @@ fn is_never_type(ty: &syn::Type) -> bool {
 
 /// Kernel test case procedural macro
 ///
-/// Enhances test cases with:
-/// - Automatic test identification
-/// - Colored output formatting
-/// - Source location reporting
+/// Registers the function as a `crate::test_framework::KernelTest`, run by
+/// the kernel test runner on a stack of its own:
+/// - `#[kernel_test]`: passes if it returns
+/// - `#[kernel_test(should_panic)]`: passes if it panics
+/// - `#[kernel_test(timeout_ms = N)]`: fails if still running after `N`
+///   ms, instead of the default timeout of the runner
 ///
-/// Generates both original function and test wrapper
+/// The original function is kept, and can still be called directly.
 #[proc_macro_attribute]
-pub fn kernel_test(_attr: TokenStream, input: TokenStream) -> TokenStream {
+pub fn kernel_test(attr: TokenStream, input: TokenStream) -> TokenStream {
     let input_fn = parse_macro_input!(input as ItemFn);
     let fn_name = &input_fn.sig.ident;
 
-    let wrapper_name = format_ident!("__{}_test_wrapper", fn_name);
+    let mut should_panic = false;
+    let mut timeout_ms: Option<LitInt> = None;
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("should_panic") {
+            should_panic = true;
+            Ok(())
+        } else if meta.path.is_ident("timeout_ms") {
+            timeout_ms = Some(meta.value()?.parse()?);
+            Ok(())
+        } else {
+            Err(meta.error("unsupported kernel_test property"))
+        }
+    });
+    parse_macro_input!(attr with parser);
+
+    let timeout_ms = match timeout_ms {
+        Some(timeout_ms) => quote! { #timeout_ms },
+        None => quote! { crate::test_framework::DEFAULT_TIMEOUT_MS },
+    };
+    let test_name = format_ident!("__KERNEL_TEST_{}", fn_name.to_string().to_uppercase());
 
-    // Generate test wrapper with:
-    // 1. Test identification header
-    // 2. Original function execution
-    // 3. Success/failure reporting
     let output = quote! {
         // Original function (unchanged)
         #[allow(unused)]
         #input_fn
 
-        // Generated test wrapper
+        // Test descriptor, collected by the test harness
         #[doc(hidden)]
         #[test_case]
-        fn #wrapper_name () {
-            crate::color_println!(crate::io::console::Color::Blue,
-                "\nTesting > {} ({}::{}) ...",
-                stringify!(#fn_name),
-                file!(),
-                stringify!(#fn_name)
-            );
-
-            #fn_name ();
-            crate::color_println!(crate::io::console::Color::Green, 
-                "========[Test passed!]========"
-            );
-        }
+        static #test_name: crate::test_framework::KernelTest = crate::test_framework::KernelTest {
+            name: stringify!(#fn_name),
+            path: concat!(module_path!(), "::", stringify!(#fn_name)),
+            func: #fn_name,
+            should_panic: #should_panic,
+            timeout_ms: #timeout_ms,
+        };
     };
 
     output.into()
 }
+
+
+/// Monitor command registration procedural macro
+///
+/// Registers a function as a debug monitor command by placing a
+/// `MonitorCommand` descriptor into the `.monitor_registry` link section,
+/// the same way `#[syscall_register]` collects system calls.
+///
+/// Usage: #[monitor_command(name = "ps", help = "List tasks")]
+///
+/// - `name` defaults to the function name
+/// - `help` defaults to an empty string
+/// - The function must have the signature `fn(&[&str])`
+#[proc_macro_attribute]
+pub fn monitor_command(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let input_fn = parse_macro_input!(item as ItemFn);
+    let fn_name = &input_fn.sig.ident;
+
+    let mut name: Option<LitStr> = None;
+    let mut help: Option<LitStr> = None;
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("name") {
+            name = Some(meta.value()?.parse()?);
+            Ok(())
+        } else if meta.path.is_ident("help") {
+            help = Some(meta.value()?.parse()?);
+            Ok(())
+        } else {
+            Err(meta.error("unsupported monitor_command property"))
+        }
+    });
+    parse_macro_input!(attr with parser);
+
+    let name = name.unwrap_or_else(|| LitStr::new(&fn_name.to_string(), fn_name.span()));
+    let help = help.unwrap_or_else(|| LitStr::new("", fn_name.span()));
+
+    let register_name = format_ident!("MONITOR_COMMAND_{}", fn_name.to_string().to_uppercase());
+
+    let expanded = quote! {
+        #input_fn
+
+        #[used]
+        #[link_section = ".monitor_registry"]
+        static #register_name: crate::monitor::MonitorCommand = crate::monitor::MonitorCommand {
+            name: #name,
+            help: #help,
+            handler: #fn_name,
+        };
+    };
+
+    expanded.into()
+}
+
+/// Shutdown hook registration procedural macro
+///
+/// Places a `crate::shutdown::ShutdownHook` into the `.shutdown_hooks`
+/// link section, run by every shutdown path in ascending `priority`.
+///
+/// Usage: #[shutdown_hook(priority = 10, panic_safe)]
+///
+/// - `priority` defaults to 100
+/// - `panic_safe` marks hooks that may also run after a panic
+/// - The function must have the signature `fn()`
+#[proc_macro_attribute]
+pub fn shutdown_hook(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let input_fn = parse_macro_input!(item as ItemFn);
+    let fn_name = &input_fn.sig.ident;
+
+    let mut priority: Option<LitInt> = None;
+    let mut panic_safe = false;
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("priority") {
+            priority = Some(meta.value()?.parse()?);
+            Ok(())
+        } else if meta.path.is_ident("panic_safe") {
+            panic_safe = true;
+            Ok(())
+        } else {
+            Err(meta.error("unsupported shutdown_hook property"))
+        }
+    });
+    parse_macro_input!(attr with parser);
+
+    let priority = priority.unwrap_or_else(|| LitInt::new("100", fn_name.span()));
+    let name = LitStr::new(&fn_name.to_string(), fn_name.span());
+
+    let register_name = format_ident!("SHUTDOWN_HOOK_{}", fn_name.to_string().to_uppercase());
+
+    let expanded = quote! {
+        #input_fn
+
+        #[used]
+        #[link_section = ".shutdown_hooks"]
+        static #register_name: crate::shutdown::ShutdownHook = crate::shutdown::ShutdownHook {
+            name: #name,
+            priority: #priority,
+            panic_safe: #panic_safe,
+            handler: #fn_name,
+        };
+    };
+
+    expanded.into()
+}
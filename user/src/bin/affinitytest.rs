@@ -0,0 +1,73 @@
+#![no_std]
+#![no_main]
+
+use user::{exit, fork, getcpu, println, sched_getaffinity, sched_setaffinity, waitpid, yield_};
+
+const ESRCH: isize = 3;
+const EINVAL: isize = 22;
+
+/// Yields a pinned task goes through, each a chance to be moved off its hart
+const YIELDS: usize = 20;
+
+fn harts(mask: usize) -> impl Iterator<Item = usize> {
+    (0..usize::BITS as usize).filter(move |&hart| mask & (1 << hart) != 0)
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let all = sched_getaffinity(0).unwrap();
+    assert_ne!(all & (1 << getcpu()), 0);
+    assert_eq!(sched_setaffinity(0, 0), -EINVAL);
+    // past the last hart
+    assert_eq!(sched_setaffinity(0, 1 << (usize::BITS - 1)), -EINVAL);
+    assert_eq!(sched_getaffinity(0), Some(all));
+    assert_eq!(sched_getaffinity(usize::MAX >> 1), None);
+    assert_eq!(sched_setaffinity(usize::MAX >> 1, all), -ESRCH);
+
+    // pinned, the caller moves at once and stays
+    for hart in harts(all) {
+        assert_eq!(sched_setaffinity(0, 1 << hart), 0);
+        for _ in 0..YIELDS {
+            assert_eq!(getcpu(), hart);
+            yield_();
+        }
+        assert_eq!(sched_getaffinity(0), Some(1 << hart));
+    }
+
+    // a child starts with the mask of its parent
+    let last = harts(all).last().unwrap();
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(sched_getaffinity(0), Some(1 << last));
+        for _ in 0..YIELDS {
+            assert_eq!(getcpu(), last);
+            yield_();
+        }
+        exit(0);
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    // moved by another task, once it switches out
+    let first = harts(all).next().unwrap();
+    assert_eq!(sched_setaffinity(0, all), 0);
+    let pid = fork();
+    if pid == 0 {
+        while sched_getaffinity(0) != Some(1 << first) {
+            yield_();
+        }
+        yield_();
+        for _ in 0..YIELDS {
+            assert_eq!(getcpu(), first);
+            yield_();
+        }
+        exit(0);
+    }
+    assert_eq!(sched_setaffinity(pid as usize, 1 << first), 0);
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    println!("affinitytest passed!");
+    0
+}
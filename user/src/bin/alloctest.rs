@@ -0,0 +1,97 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+
+use alloc::{collections::BTreeMap, format, string::String, vec, vec::Vec};
+use user::{
+    brk, free, malloc, println, thread_create,
+    sync::{Condvar, Mutex},
+};
+
+const THREADS: usize = 4;
+
+/// Threads done, each checked its own allocations
+static DONE: Mutex<usize> = Mutex::new(0);
+static ALL_DONE: Condvar = Condvar::new();
+
+/// Blocks of many sizes, interleaved with frees
+fn churn(seed: usize) -> usize {
+    let mut blocks: Vec<Vec<u8>> = Vec::new();
+    for i in 0..200 {
+        let len = (i * 37 + seed) % 3000 + 1;
+        blocks.push(vec![(i + seed) as u8; len]);
+        if i % 3 == 0 {
+            blocks.swap_remove(i % blocks.len());
+        }
+    }
+    // nobody wrote over anybody
+    for block in blocks.iter() {
+        assert!(block.iter().all(|&byte| byte == block[0]));
+    }
+    blocks.iter().map(Vec::len).sum()
+}
+
+extern "C" fn worker(seed: usize) -> i32 {
+    churn(seed);
+    *DONE.lock() += 1;
+    ALL_DONE.notify_all();
+    0
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let bottom = brk(0);
+
+    let numbers: Vec<usize> = (0..10_000).collect();
+    assert_eq!(numbers.iter().sum::<usize>(), 10_000 * 9_999 / 2);
+    let mut text = String::new();
+    for i in 0..100 {
+        text += &format!("{},", i);
+    }
+    assert!(text.starts_with("0,1,2,") && text.ends_with("98,99,"));
+    let mut map = BTreeMap::new();
+    for i in 0..500 {
+        map.insert(i * 7 % 500, i);
+    }
+    assert_eq!(map.len(), 500);
+    assert_eq!(map.keys().next(), Some(&0));
+    assert!(brk(0) > bottom);
+    drop((numbers, text, map));
+
+    // freed blocks are reused, the heap stops growing
+    churn(1);
+    let grown = brk(0);
+    for _ in 0..8 {
+        churn(1);
+    }
+    assert_eq!(brk(0), grown);
+
+    // a big block is a mapping of its own, zeroed
+    let big = vec![0u8; 1 << 20];
+    assert!(big.iter().all(|&byte| byte == 0));
+    assert!((big.as_ptr() as usize) >= grown);
+    assert_eq!(brk(0), grown);
+    drop(big);
+
+    let block = malloc(100);
+    assert!(!block.is_null() && block as usize % 16 == 0);
+    unsafe {
+        block.write_bytes(0xab, 100);
+        free(block);
+        free(core::ptr::null_mut());
+    }
+    assert!(malloc(usize::MAX).is_null());
+
+    for seed in 0..THREADS {
+        assert!(thread_create(worker, seed) > 0);
+    }
+    let mut done = DONE.lock();
+    while *done < THREADS {
+        done = ALL_DONE.wait(done);
+    }
+    drop(done);
+
+    println!("alloctest passed!");
+    0
+}
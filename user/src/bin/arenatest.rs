@@ -0,0 +1,86 @@
+#![no_std]
+#![no_main]
+
+use user::{close, exit, fork, open, pipe, println, read, waitpid, write, yield_, O_RDONLY};
+
+/// Tasks allocating in the kernel at the same time, on every hart there is
+const WORKERS: usize = 4;
+/// Pipes and children each worker creates, one after the other
+const ROUNDS: usize = 50;
+/// What the arenas may keep in use past the workers: caches warmed meanwhile
+const SLACK_KB: usize = 16;
+
+/// `HeapArenaUsed` of `/proc/meminfo`, in kB
+fn arena_used_kb() -> usize {
+    let fd = open("/proc/meminfo\0", O_RDONLY);
+    assert!(fd >= 0);
+    let mut buf = [0u8; 1024];
+    let len = read(fd as usize, &mut buf);
+    assert!(len > 0);
+    close(fd as usize);
+
+    let meminfo = &buf[..len as usize];
+    let key = b"HeapArenaUsed:\t";
+    let at = meminfo
+        .windows(key.len())
+        .position(|window| window == key)
+        .expect("no HeapArenaUsed in /proc/meminfo")
+        + key.len();
+    meminfo[at..]
+        .iter()
+        .take_while(|byte| byte.is_ascii_digit())
+        .fold(0, |kb, &digit| kb * 10 + (digit - b'0') as usize)
+}
+
+/// Create and drop pipes and children: fd table entries, pipe buffers and
+/// task names, all served by the size-class arenas.
+fn work() -> ! {
+    for round in 0..ROUNDS {
+        let mut fds = [0usize; 2];
+        assert_eq!(pipe(&mut fds), 0);
+        let message = [round as u8; 8];
+        assert_eq!(write(fds[1], &message), 8);
+        let mut received = [0u8; 8];
+        assert_eq!(read(fds[0], &mut received), 8);
+        assert_eq!(received, message);
+        close(fds[0]);
+        close(fds[1]);
+
+        let pid = fork();
+        if pid == 0 {
+            exit(0);
+        }
+        let mut exit_code = -1;
+        assert_eq!(waitpid(pid, &mut exit_code), pid);
+        assert_eq!(exit_code, 0);
+        yield_();
+    }
+    exit(0)
+}
+
+/// Allocate from several tasks at once: every block must go back to its
+/// arena once they are gone, none lost to a race on the counters.
+#[no_mangle]
+fn main() -> i32 {
+    let before = arena_used_kb();
+
+    let mut workers = [0isize; WORKERS];
+    for worker in workers.iter_mut() {
+        *worker = fork();
+        if *worker == 0 {
+            work();
+        }
+    }
+    for worker in workers {
+        let mut exit_code = -1;
+        assert_eq!(waitpid(worker, &mut exit_code), worker);
+        assert_eq!(exit_code, 0);
+    }
+
+    let after = arena_used_kb();
+    println!("arena in use: {} kB before, {} kB after", before, after);
+    assert!(after <= before + SLACK_KB);
+
+    println!("arenatest passed!");
+    0
+}
@@ -0,0 +1,19 @@
+#![no_std]
+#![no_main]
+
+use user::{args, close, open, write, O_CREAT, O_TRUNC, O_WRONLY};
+
+/// Where `binfmttest` expects the arguments
+const REPORT: &str = "argsprobe.out\0";
+
+/// Report the arguments, one per line, for `binfmttest`. Exits with their number.
+#[no_mangle]
+fn main() -> i32 {
+    let fd = open(REPORT, O_CREAT | O_TRUNC | O_WRONLY) as usize;
+    for arg in args() {
+        write(fd, arg.as_bytes());
+        write(fd, b"\n");
+    }
+    close(fd);
+    args().count() as i32
+}
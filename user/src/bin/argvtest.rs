@@ -0,0 +1,37 @@
+#![no_std]
+#![no_main]
+
+use user::{args, env, execve, println};
+
+const E2BIG: isize = 7;
+const ENOENT: isize = 2;
+
+/// Longer than the kernel takes for `argv` and `envp` together
+const BIG_LEN: usize = 4096;
+static BIG: [u8; BIG_LEN] = {
+    let mut bytes = [b'x'; BIG_LEN];
+    bytes[BIG_LEN - 1] = 0;
+    bytes
+};
+
+/// Run with its name only, then again with arguments and an environment.
+#[no_mangle]
+fn main() -> i32 {
+    if args().nth(1) != Some("again") {
+        assert!(args().eq(["argvtest"]));
+        assert_eq!(env().count(), 0);
+
+        let big = core::str::from_utf8(&BIG).unwrap();
+        assert_eq!(execve("argvtest\0", &["argvtest\0", big], &[]), -E2BIG);
+        assert_eq!(execve("argvtest\0", &["argvtest\0"], &[big]), -E2BIG);
+        assert_eq!(execve("no_such_program\0", &["argvtest\0"], &[]), -ENOENT);
+
+        execve("argvtest\0", &["argvtest\0", "again\0", "two words\0"], &["HOME=/\0", "EMPTY=\0"]);
+        panic!("execve returned");
+    }
+
+    assert!(args().eq(["argvtest", "again", "two words"]));
+    assert!(env().eq(["HOME=/", "EMPTY="]));
+    println!("argvtest passed!");
+    0
+}
@@ -0,0 +1,20 @@
+#![no_std]
+#![no_main]
+
+use user::{mmap, write, MAP_ANONYMOUS, MAP_PRIVATE, PROT_READ, PROT_WRITE};
+
+/// Where `aslrtest` expects the layout
+const REPORT_FD: usize = 3;
+
+/// Report where the stack, the code and a new mapping landed, for `aslrtest`.
+#[no_mangle]
+fn main() -> i32 {
+    let local = 0usize;
+    let mapped = mmap(0, 4096, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS);
+    let layout = [&local as *const usize as usize, main as usize, mapped as usize];
+    let bytes = unsafe {
+        core::slice::from_raw_parts(layout.as_ptr() as *const u8, core::mem::size_of_val(&layout))
+    };
+    write(REPORT_FD, bytes);
+    0
+}
@@ -0,0 +1,57 @@
+#![no_std]
+#![no_main]
+
+use user::{
+    close, dup2, exec, exit, fork, personality, pipe, println, read, waitpid, ADDR_NO_RANDOMIZE,
+    PERSONALITY_QUERY,
+};
+
+const EINVAL: isize = 22;
+/// Where `aslrprobe` reports the layout
+const REPORT_FD: usize = 3;
+
+/// Stack, code and mmap addresses of a fresh `aslrprobe`
+fn probe() -> [usize; 3] {
+    let mut pipe_fds = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fds), 0);
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(dup2(pipe_fds[1], REPORT_FD), REPORT_FD as isize);
+        close(pipe_fds[0]);
+        exec("aslrprobe\0");
+        exit(-1);
+    }
+    close(pipe_fds[1]);
+    let mut layout = [0usize; 3];
+    let bytes = unsafe {
+        core::slice::from_raw_parts_mut(layout.as_mut_ptr() as *mut u8, core::mem::size_of_val(&layout))
+    };
+    assert_eq!(read(pipe_fds[0], bytes), bytes.len() as isize);
+    close(pipe_fds[0]);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+    layout
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    assert_eq!(personality(PERSONALITY_QUERY), 0);
+    assert_eq!(personality(1), -EINVAL);
+    assert_eq!(personality(0x0400000), -EINVAL);
+
+    // the stack and the mmap base both move, a repeat is a 1 in 2^32 chance
+    let first = probe();
+    let second = probe();
+    assert_ne!(first, second);
+
+    // kept across fork and exec, the layout repeats
+    assert_eq!(personality(ADDR_NO_RANDOMIZE), 0);
+    assert_eq!(personality(PERSONALITY_QUERY), ADDR_NO_RANDOMIZE as isize);
+    let first = probe();
+    assert_eq!(probe(), first);
+    assert_eq!(personality(0), ADDR_NO_RANDOMIZE as isize);
+
+    println!("aslrtest passed!");
+    0
+}
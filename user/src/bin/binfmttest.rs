@@ -0,0 +1,75 @@
+#![no_std]
+#![no_main]
+
+use user::{close, exec, exit, fork, open, println, read, waitpid, write, O_CREAT, O_RDONLY, O_TRUNC, O_WRONLY};
+
+const ENOENT: isize = 2;
+const ENOEXEC: isize = 8;
+const ELOOP: isize = 40;
+const REPORT: &str = "argsprobe.out\0";
+
+/// `li a0, 42; li a7, 93 (exit); ecall`
+const FLAT_EXIT_42: [u32; 3] = [0x02a0_0513, 0x05d0_0893, 0x0000_0073];
+
+fn create(path: &str, data: &[u8]) {
+    let fd = open(path, O_CREAT | O_TRUNC | O_WRONLY) as usize;
+    assert_eq!(write(fd, data), data.len() as isize);
+    close(fd);
+}
+
+/// Exit code of `path` run in a child
+fn run(path: &str) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        exec(path);
+        exit(-1);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    exit_code
+}
+
+/// Whether `argsprobe` reported `expected`
+fn reported(expected: &str) -> bool {
+    let mut buf = [0u8; 128];
+    let fd = open(REPORT, O_RDONLY) as usize;
+    let len = read(fd, &mut buf);
+    close(fd);
+    len >= 0 && &buf[..len as usize] == expected.as_bytes()
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    // the interpreter, its argument, then the script
+    create("binfmt.sh\0", b"#!/argsprobe -x\nignored\n");
+    assert_eq!(run("binfmt.sh\0"), 3);
+    assert!(reported("/argsprobe\n-x\nbinfmt.sh\n"));
+    // a script interpreting another
+    create("binfmt2.sh\0", b"#!binfmt.sh\n");
+    assert_eq!(run("binfmt2.sh\0"), 4);
+    assert!(reported("/argsprobe\n-x\nbinfmt.sh\nbinfmt2.sh\n"));
+    // the lines of a shell script are run
+    create("binfmt3.sh\0", b"#!/user_shell\nargsprobe\n");
+    assert_eq!(run("binfmt3.sh\0"), 0);
+    assert!(reported("argsprobe\n"));
+
+    // failing, the caller is left as it was
+    create("loop.sh\0", b"#!loop.sh\n");
+    assert_eq!(exec("loop.sh\0"), -ELOOP);
+    create("nointerp.sh\0", b"#!  \nargsprobe\n");
+    assert_eq!(exec("nointerp.sh\0"), -ENOEXEC);
+    create("missing.sh\0", b"#!/no_such_interpreter\n");
+    assert_eq!(exec("missing.sh\0"), -ENOENT);
+    create("empty\0", b"");
+    assert_eq!(exec("empty\0"), -ENOEXEC);
+
+    // raw code, entered at its first byte
+    let code = unsafe {
+        core::slice::from_raw_parts(FLAT_EXIT_42.as_ptr() as *const u8, core::mem::size_of_val(&FLAT_EXIT_42))
+    };
+    create("flat.bin\0", code);
+    assert_eq!(run("flat.bin\0"), 42);
+
+    println!("binfmttest passed!");
+    0
+}
@@ -0,0 +1,47 @@
+#![no_std]
+#![no_main]
+
+use core::fmt::Write;
+
+use user::{capture_output, close, exit, fork, open, println, read, waitpid};
+
+const MESSAGE: &str = "hello from the captured child";
+
+struct PathBuf {
+    bytes: [u8; 32],
+    len: usize,
+}
+
+impl Write for PathBuf {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.bytes[self.len..self.len + s.len()].copy_from_slice(s.as_bytes());
+        self.len += s.len();
+        Ok(())
+    }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    assert_eq!(capture_output(0), 0);
+    let pid = fork();
+    if pid == 0 {
+        println!("{}", MESSAGE);
+        exit(0);
+    }
+
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+
+    // the child is gone, its output is not
+    let mut path = PathBuf { bytes: [0; 32], len: 0 };
+    write!(path, "/proc/{}/output\0", pid).unwrap();
+    let fd = open(core::str::from_utf8(&path.bytes[..path.len]).unwrap(), 0);
+    assert!(fd >= 0);
+    let mut output = [0u8; 128];
+    let len = read(fd as usize, &mut output) as usize;
+    close(fd as usize);
+    assert_eq!(&output[..len], b"hello from the captured child\n");
+
+    println!("capture passed!");
+    0
+}
@@ -0,0 +1,20 @@
+#![no_std]
+#![no_main]
+
+use user::{read, write};
+
+const STDIN: usize = 0;
+const STDOUT: usize = 1;
+
+/// Copy stdin to stdout line by line, until Ctrl-D on an empty line.
+#[no_mangle]
+unsafe fn main() -> i32 {
+    let mut buffer = [0u8; 256];
+    loop {
+        let len = read(STDIN, &mut buffer);
+        if len <= 0 {
+            return len as i32;
+        }
+        write(STDOUT, &buffer[..len as usize]);
+    }
+}
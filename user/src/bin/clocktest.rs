@@ -0,0 +1,29 @@
+#![no_std]
+#![no_main]
+
+use user::{clock_gettime, println, sleep, TimeSpec, CLOCK_MONOTONIC, CLOCK_REALTIME};
+
+const EINVAL: isize = 22;
+
+#[no_mangle]
+fn main() -> i32 {
+    let mut before = TimeSpec::default();
+    let mut after = TimeSpec::default();
+    assert_eq!(clock_gettime(CLOCK_MONOTONIC, &mut before), 0);
+    sleep(20);
+    assert_eq!(clock_gettime(CLOCK_MONOTONIC, &mut after), 0);
+    assert!(after > before);
+    assert!(after.tv_nsec < 1_000_000_000);
+    let elapsed_ms = (after.tv_sec - before.tv_sec) * 1000 + after.tv_nsec / 1_000_000 - before.tv_nsec / 1_000_000;
+    assert!(elapsed_ms >= 20, "slept {}ms only", elapsed_ms);
+
+    let mut now = TimeSpec::default();
+    assert_eq!(clock_gettime(CLOCK_REALTIME, &mut now), 0);
+    // the epoch at boot without an RTC, never behind the monotonic clock
+    assert!(now >= after);
+    println!("realtime: {}.{:09}", now.tv_sec, now.tv_nsec);
+
+    assert_eq!(clock_gettime(42, &mut now), -EINVAL);
+    println!("clocktest passed!");
+    0
+}
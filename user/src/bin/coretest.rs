@@ -0,0 +1,143 @@
+#![no_std]
+#![no_main]
+
+use user::{
+    close, exit, fork, getpid, getrlimit, kill, lseek, open, println, read, setrlimit, waitpid, RLimit, O_RDONLY,
+    RLIMIT_CORE, RLIM_INFINITY, SEEK_END,
+};
+
+const SIGABRT: i32 = 6;
+const SIGSEGV: i32 = 11;
+const PAGE_SIZE: usize = 4096;
+const ET_CORE: u16 = 4;
+const EM_RISCV: u16 = 243;
+const PT_NOTE: u32 = 4;
+
+/// Bytes the crashing child leaves in its memory, for the core to hold.
+/// Aligned, so it doesn't straddle the pages read back.
+#[repr(align(64))]
+struct Marker([u8; 64]);
+
+static mut MARKER: Marker = Marker([0; 64]);
+
+/// Computed, so that the bytes are found nowhere in the binary itself
+fn marker_byte(i: usize) -> u8 {
+    (i * 37 + 11) as u8 ^ 0xa5
+}
+
+/// `/core.<pid>\0` in `buf`
+fn core_path(pid: usize, buf: &mut [u8; 32]) -> &str {
+    let mut digits = [0u8; 20];
+    let mut len = 0;
+    let mut rest = pid;
+    loop {
+        digits[len] = b'0' + (rest % 10) as u8;
+        len += 1;
+        rest /= 10;
+        if rest == 0 {
+            break;
+        }
+    }
+    let mut at = 0;
+    for &byte in b"/core.".iter().chain(digits[..len].iter().rev()).chain(b"\0".iter()) {
+        buf[at] = byte;
+        at += 1;
+    }
+    core::str::from_utf8(&buf[..at]).unwrap()
+}
+
+fn open_core(pid: isize) -> isize {
+    let mut buf = [0u8; 32];
+    open(core_path(pid as usize, &mut buf), O_RDONLY)
+}
+
+fn u16_at(bytes: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([bytes[at], bytes[at + 1]])
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+}
+
+fn u64_at(bytes: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+}
+
+/// Run `die` in a child with a core limit of `limit`, returns its pid
+fn crash(limit: usize, die: fn()) -> isize {
+    let pid = fork();
+    if pid == 0 {
+        let core = getrlimit(RLIMIT_CORE).unwrap();
+        assert_eq!(core.cur, 0);
+        assert_eq!(setrlimit(RLIMIT_CORE, RLimit { cur: limit, max: core.max }), 0);
+        die();
+        exit(100);
+    }
+    pid
+}
+
+fn segfault() {
+    unsafe {
+        let marker = &mut *core::ptr::addr_of_mut!(MARKER);
+        for (i, byte) in marker.0.iter_mut().enumerate() {
+            core::ptr::write_volatile(byte, marker_byte(i));
+        }
+        core::ptr::write_volatile(0x10 as *mut u8, 1);
+    }
+}
+
+fn abort() {
+    kill(getpid() as usize, SIGABRT as usize);
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let mut exit_code = 0;
+
+    // a full dump: the headers, the registers, the memory
+    let pid = crash(RLIM_INFINITY, segfault);
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    assert_eq!(exit_code, -SIGSEGV);
+    let fd = open_core(pid);
+    assert!(fd >= 0);
+    let fd = fd as usize;
+    let mut page = [0u8; PAGE_SIZE];
+    assert_eq!(read(fd, &mut page), PAGE_SIZE as isize);
+    assert_eq!(&page[..4], b"\x7fELF");
+    assert_eq!(u16_at(&page, 16), ET_CORE);
+    assert_eq!(u16_at(&page, 18), EM_RISCV);
+    let phoff = u64_at(&page, 32) as usize;
+    let phnum = u16_at(&page, 56) as usize;
+    assert!(phnum > 1);
+    assert_eq!(u32_at(&page, phoff), PT_NOTE);
+    // the prstatus note: the signal, then the pc faulting
+    let note = u64_at(&page, phoff + 8) as usize;
+    assert_eq!(&page[note + 12..note + 17], b"CORE\0");
+    let prstatus = note + 20;
+    assert_eq!(u32_at(&page, prstatus), SIGSEGV as u32);
+    assert_ne!(u64_at(&page, prstatus + 112), 0);
+
+    let mut found = false;
+    while read(fd, &mut page) > 0 {
+        found |= page.chunks(64).any(|chunk| chunk.iter().enumerate().all(|(i, &byte)| byte == marker_byte(i)));
+    }
+    close(fd);
+    assert!(found);
+
+    // the limit cuts the file
+    let pid = crash(PAGE_SIZE, segfault);
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    let fd = open_core(pid);
+    assert!(fd >= 0);
+    assert_eq!(lseek(fd as usize, 0, SEEK_END), PAGE_SIZE as isize);
+    close(fd as usize);
+
+    // no dump by default, the task still dies
+    let pid = crash(0, abort);
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    assert_eq!(exit_code, -SIGABRT);
+    assert!(open_core(pid) < 0);
+
+    println!("coretest passed!");
+    0
+}
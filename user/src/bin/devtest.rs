@@ -0,0 +1,43 @@
+#![no_std]
+#![no_main]
+
+use user::{close, open, println, read, sleep, write, O_RDONLY, O_RDWR, O_WRONLY};
+
+/// Time since boot in microseconds
+fn rtc_now(fd: usize) -> u64 {
+    let mut now = [0u8; 8];
+    assert_eq!(read(fd, &mut now), 8);
+    u64::from_ne_bytes(now)
+}
+
+#[no_mangle]
+unsafe fn main() -> i32 {
+    let null = open("/dev/null\0", O_RDWR) as usize;
+    assert_eq!(write(null, b"discarded"), 9);
+    let mut buf = [0xffu8; 64];
+    assert_eq!(read(null, &mut buf), 0);
+    close(null);
+
+    let zero = open("/dev/zero\0", O_RDONLY) as usize;
+    assert_eq!(read(zero, &mut buf), 64);
+    assert!(buf.iter().all(|&byte| byte == 0));
+    close(zero);
+
+    let tty = open("/dev/tty\0", O_WRONLY) as usize;
+    assert_eq!(write(tty, b"written to /dev/tty\n"), 20);
+    close(tty);
+
+    let rtc = open("/dev/rtc\0", O_RDONLY) as usize;
+    let before = rtc_now(rtc);
+    sleep(10);
+    let after = rtc_now(rtc);
+    assert!(after - before >= 10_000);
+    close(rtc);
+    // the clock can't be set
+    let rtc = open("/dev/rtc\0", O_WRONLY) as usize;
+    assert!(write(rtc, &before.to_ne_bytes()) < 0);
+    close(rtc);
+
+    println!("devtest passed!");
+    0
+}
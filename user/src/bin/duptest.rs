@@ -0,0 +1,56 @@
+#![no_std]
+#![no_main]
+
+use user::{
+    close, dup, dup2, dup3, exec, exit, fork, println, pipe, read, try_waitpid, waitpid, write,
+    O_CLOEXEC,
+};
+
+const EBADF: isize = 9;
+const EINVAL: isize = 22;
+/// `try_waitpid` of a child still running
+const STILL_RUNNING: isize = 0;
+
+#[no_mangle]
+unsafe fn main() -> i32 {
+    // the lowest free fd is taken
+    let fd = dup(1);
+    assert_eq!(fd, 3);
+    assert_eq!(write(fd as usize, b"written through a dup of stdout\n"), 32);
+    assert_eq!(close(fd as usize), 0);
+    assert_eq!(dup(42), -EBADF);
+
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let [read_end, write_end] = fds;
+
+    // both fds refer to the same write end
+    assert_eq!(dup2(write_end, 10), 10);
+    assert_eq!(write(10, b"ping"), 4);
+    let mut buf = [0u8; 4];
+    assert_eq!(read(read_end, &mut buf), 4);
+    assert_eq!(&buf, b"ping");
+    assert_eq!(dup2(10, 10), 10);
+    assert_eq!(dup3(10, 11, 0xff), -EINVAL);
+    assert_eq!(close(10), 0);
+
+    let pid = fork();
+    if pid == 0 {
+        // the only write end left in the child goes away with exec
+        assert_eq!(dup3(write_end, 12, O_CLOEXEC), 12);
+        close(write_end);
+        close(read_end);
+        exec("sleep\0");
+        exit(-1);
+    }
+    close(write_end);
+    // end of file while the child still runs: the pipe was closed on exec
+    assert_eq!(read(read_end, &mut buf), 0);
+    let mut exit_code = 0;
+    assert_eq!(try_waitpid(pid, &mut exit_code), STILL_RUNNING);
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    println!("duptest passed!");
+    0
+}
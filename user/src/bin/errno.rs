@@ -0,0 +1,21 @@
+#![no_std]
+#![no_main]
+
+use user::{check, close, perror, println, strerror, Errno};
+
+#[no_mangle]
+fn main() -> i32 {
+    let mut message = [0u8; 64];
+    let len = strerror(9, &mut message);
+    assert!(len > 0);
+    println!("errno 9: {}", core::str::from_utf8(&message[..len as usize]).unwrap());
+
+    // unknown codes are rejected with EINVAL
+    assert_eq!(strerror(4095, &mut message), -22);
+
+    if let Err(err) = check(close(1000)) {
+        perror("close(1000)", err);
+    }
+    println!("{}", Errno(38));
+    0
+}
@@ -0,0 +1,35 @@
+#![no_std]
+#![no_main]
+
+use user::{exit_group, fork, println, thread_create, waitpid, yield_};
+
+const GROUP_EXIT_CODE: i32 = 7;
+
+extern "C" fn quitter(_: usize) -> i32 {
+    yield_();
+    exit_group(GROUP_EXIT_CODE)
+}
+
+extern "C" fn spinner(_: usize) -> i32 {
+    loop {
+        yield_();
+    }
+}
+
+#[no_mangle]
+unsafe fn main() -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        // the leader and a bystander both die with the group
+        assert!(thread_create(spinner, 0) > 0);
+        assert!(thread_create(quitter, 0) > 0);
+        loop {
+            yield_();
+        }
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    assert_eq!(exit_code, GROUP_EXIT_CODE);
+    println!("exitgroup passed!");
+    0
+}
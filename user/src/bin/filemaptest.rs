@@ -0,0 +1,67 @@
+#![no_std]
+#![no_main]
+
+use user::{
+    close, mmap_file, munmap, open, pipe, println, read, write, MAP_ANONYMOUS, MAP_PRIVATE,
+    O_CREAT, O_RDONLY, O_RDWR, O_TRUNC, O_WRONLY, PROT_READ, PROT_WRITE,
+};
+
+const PAGE_SIZE: usize = 4096;
+const EBADF: isize = 9;
+const EACCES: isize = 13;
+const ENODEV: isize = 19;
+const EINVAL: isize = 22;
+const FILE: &str = "filemaptest.txt\0";
+
+#[no_mangle]
+unsafe fn main() -> i32 {
+    // three pages and a half, each byte holding the number of its page
+    let fd = open(FILE, O_CREAT | O_TRUNC | O_RDWR) as usize;
+    for page in 0..3u8 {
+        assert_eq!(write(fd, &[page; PAGE_SIZE]), PAGE_SIZE as isize);
+    }
+    assert_eq!(write(fd, &[3u8; PAGE_SIZE / 2]), (PAGE_SIZE / 2) as isize);
+    close(fd);
+
+    // from the second page on, one page past the end of the file
+    let fd = open(FILE, O_RDONLY) as usize;
+    let prot = PROT_READ | PROT_WRITE;
+    let start = mmap_file(0, 4 * PAGE_SIZE, prot, MAP_PRIVATE, fd, PAGE_SIZE);
+    assert!(start > 0);
+    // the mapping keeps the file
+    close(fd);
+    let memory = core::slice::from_raw_parts_mut(start as *mut u8, 4 * PAGE_SIZE);
+    assert!(memory[..PAGE_SIZE].iter().all(|&byte| byte == 1));
+    assert!(memory[PAGE_SIZE..2 * PAGE_SIZE].iter().all(|&byte| byte == 2));
+    assert!(memory[2 * PAGE_SIZE..5 * PAGE_SIZE / 2].iter().all(|&byte| byte == 3));
+    assert!(memory[5 * PAGE_SIZE / 2..].iter().all(|&byte| byte == 0));
+
+    // private: the file doesn't see the write
+    memory[0] = 42;
+    let fd = open(FILE, O_RDONLY) as usize;
+    let mut buf = [0u8; 1];
+    let mut skipped = [0u8; PAGE_SIZE];
+    assert_eq!(read(fd, &mut skipped), PAGE_SIZE as isize);
+    assert_eq!(read(fd, &mut buf), 1);
+    assert_eq!(buf[0], 1);
+    assert_eq!(munmap(start as usize, 4 * PAGE_SIZE), 0);
+
+    assert_eq!(mmap_file(0, PAGE_SIZE, PROT_READ, MAP_PRIVATE, fd, 1), -EINVAL);
+    assert_eq!(mmap_file(0, 0, PROT_READ, MAP_PRIVATE, fd, 0), -EINVAL);
+    assert_eq!(mmap_file(0, PAGE_SIZE, PROT_READ, MAP_PRIVATE, 99, 0), -EBADF);
+    close(fd);
+    let fd = open(FILE, O_WRONLY) as usize;
+    assert_eq!(mmap_file(0, PAGE_SIZE, PROT_READ, MAP_PRIVATE, fd, 0), -EACCES);
+    close(fd);
+    let mut pipe_fds = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fds), 0);
+    assert_eq!(mmap_file(0, PAGE_SIZE, PROT_READ, MAP_PRIVATE, pipe_fds[0], 0), -ENODEV);
+    close(pipe_fds[0]);
+    close(pipe_fds[1]);
+    // without a file it has to be anonymous
+    assert_eq!(mmap_file(0, PAGE_SIZE, PROT_READ, MAP_PRIVATE, usize::MAX, 0), -EBADF);
+    assert!(mmap_file(0, PAGE_SIZE, PROT_READ, MAP_PRIVATE | MAP_ANONYMOUS, usize::MAX, 0) > 0);
+
+    println!("filemaptest passed!");
+    0
+}
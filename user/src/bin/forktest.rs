@@ -0,0 +1,41 @@
+#![no_std]
+#![no_main]
+
+use user::{check, exec, exit, fork, println, waitpid, Errno};
+
+const CHILDREN: usize = 8;
+
+#[no_mangle]
+unsafe fn main() -> i32 {
+    for i in 0..CHILDREN {
+        let pid = fork();
+        assert!(pid >= 0);
+        if pid == 0 {
+            // the child returns the index it was forked at
+            exit(i as i32);
+        }
+    }
+
+    let mut seen = [false; CHILDREN];
+    for _ in 0..CHILDREN {
+        let mut exit_code = 0;
+        assert!(waitpid(-1, &mut exit_code) > 0);
+        assert!(!seen[exit_code as usize]);
+        seen[exit_code as usize] = true;
+    }
+    // every child is reaped
+    let mut exit_code = 0;
+    assert_eq!(check(waitpid(-1, &mut exit_code)), Err(Errno::ECHILD));
+
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(check(exec("no_such_program\0")), Err(Errno::ENOENT));
+        exec("sleep\0");
+        unreachable!();
+    }
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    println!("forktest passed!");
+    0
+}
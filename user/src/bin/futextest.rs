@@ -0,0 +1,37 @@
+#![no_std]
+#![no_main]
+
+use core::sync::atomic::AtomicU32;
+
+use user::{
+    futex_wait, futex_wake, println,
+    sync::{Condvar, Mutex},
+};
+
+const EAGAIN: isize = 11;
+
+static WORD: AtomicU32 = AtomicU32::new(1);
+static COUNTER: Mutex<usize> = Mutex::new(0);
+static CHANGED: Condvar = Condvar::new();
+
+#[no_mangle]
+unsafe fn main() -> i32 {
+    // a stale value never sleeps, nobody to wake is not an error
+    assert_eq!(futex_wait(&WORD, 0), -EAGAIN);
+    assert_eq!(futex_wake(&WORD, 1), 0);
+
+    // a word of a lazy heap page is waited on like any other
+    let heap = user::sbrk(4096).unwrap();
+    let heap_word = &*(heap as *const AtomicU32);
+    assert_eq!(futex_wait(heap_word, 1), -EAGAIN);
+
+    let mut counter = COUNTER.lock();
+    assert!(COUNTER.try_lock().is_none());
+    *counter += 1;
+    drop(counter);
+    assert_eq!(*COUNTER.try_lock().unwrap(), 1);
+
+    CHANGED.notify_all();
+    println!("futextest passed!");
+    0
+}
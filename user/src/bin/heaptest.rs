@@ -0,0 +1,38 @@
+#![no_std]
+#![no_main]
+
+use user::{brk, mincore, println, sbrk, PAGE_NOT_RESIDENT, PAGE_RESIDENT, PAGE_UNMAPPED};
+
+const PAGE_SIZE: usize = 4096;
+const HEAP_PAGES: usize = 1024;
+
+#[no_mangle]
+unsafe fn main() -> i32 {
+    let bottom = brk(0);
+    assert_eq!(bottom % PAGE_SIZE, 0);
+
+    // a big heap costs nothing until it is touched
+    let start = sbrk((HEAP_PAGES * PAGE_SIZE) as isize).unwrap();
+    assert_eq!(start, bottom);
+    let heap = core::slice::from_raw_parts_mut(start as *mut u8, HEAP_PAGES * PAGE_SIZE);
+    heap[0] = 1;
+    heap[HEAP_PAGES * PAGE_SIZE - 1] = 2;
+
+    let mut residency = [0u8; 3];
+    assert_eq!(mincore(start, 3 * PAGE_SIZE, &mut residency), 0);
+    assert_eq!(residency, [PAGE_RESIDENT, PAGE_NOT_RESIDENT, PAGE_NOT_RESIDENT]);
+    assert_eq!(heap[PAGE_SIZE], 0);
+
+    // shrinking gives the pages back
+    assert_eq!(brk(bottom + PAGE_SIZE), bottom + PAGE_SIZE);
+    assert_eq!(mincore(start, 3 * PAGE_SIZE, &mut residency), 0);
+    assert_eq!(residency, [PAGE_RESIDENT, PAGE_UNMAPPED, PAGE_UNMAPPED]);
+    assert_eq!(heap[0], 1);
+
+    // below the bottom is refused
+    assert_eq!(brk(bottom - PAGE_SIZE), bottom + PAGE_SIZE);
+    assert_eq!(brk(bottom), bottom);
+
+    println!("heaptest passed!");
+    0
+}
@@ -1,15 +1,30 @@
 //! This is the first user proc
 //! other user proc start by it
+//!
+//! It starts the shell, then adopts the orphans of every exiting process
+//! and reaps them.
 
 #![no_std]
 #![no_main]
 
-use user::println;
+use user::{exec, exit, fork, println, waitpid, yield_};
 
 #[no_mangle]
 fn main() -> i32{
+    println!("init_proc");
+    if fork() == 0 {
+        exec("user_shell\0");
+        println!("init_proc: no user_shell");
+        exit(-1);
+    }
     loop {
-        println!("init_proc");
+        let mut exit_code = 0;
+        let pid = waitpid(-1, &mut exit_code);
+        if pid < 0 {
+            // no child for now, orphans may come later
+            yield_();
+            continue;
+        }
+        println!("[init_proc] reaped {}, exit code {}", pid, exit_code);
     }
-    0
-}
\ No newline at end of file
+}
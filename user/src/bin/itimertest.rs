@@ -0,0 +1,85 @@
+#![no_std]
+#![no_main]
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use user::{
+    fork, getitimer, println, setitimer, sigaction, sleep, waitpid, ITimerVal, SignalAction, TimeVal,
+    ITIMER_PROF, ITIMER_REAL, SIGALRM,
+};
+
+const EINVAL: isize = 22;
+
+static ALARMS: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn on_alarm(_signum: usize) {
+    ALARMS.fetch_add(1, Ordering::Relaxed);
+}
+
+fn millis(ms: usize) -> TimeVal {
+    TimeVal { tv_sec: ms / 1000, tv_usec: ms % 1000 * 1000 }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let action = SignalAction { handler: on_alarm as usize, mask: 0, flags: 0 };
+    assert_eq!(sigaction(SIGALRM, Some(&action), None), 0);
+
+    let mut curr = ITimerVal::default();
+    assert_eq!(getitimer(ITIMER_REAL, &mut curr), 0);
+    assert_eq!(curr, ITimerVal::default());
+    assert_eq!(setitimer(ITIMER_PROF, &ITimerVal::default(), None), -EINVAL);
+    let bad = ITimerVal { interval: TimeVal::default(), value: TimeVal { tv_sec: 0, tv_usec: 1_000_000 } };
+    assert_eq!(setitimer(ITIMER_REAL, &bad, None), -EINVAL);
+
+    // a one shot timer fires once
+    let once = ITimerVal { interval: TimeVal::default(), value: millis(20) };
+    assert_eq!(setitimer(ITIMER_REAL, &once, None), 0);
+    while ALARMS.load(Ordering::Relaxed) == 0 {
+        sleep(5);
+    }
+    sleep(100);
+    assert_eq!(ALARMS.load(Ordering::Relaxed), 1);
+    assert_eq!(getitimer(ITIMER_REAL, &mut curr), 0);
+    assert_eq!(curr, ITimerVal::default());
+
+    // a periodic one keeps firing, and reports what is left of the period
+    let periodic = ITimerVal { interval: millis(10), value: millis(10) };
+    assert_eq!(setitimer(ITIMER_REAL, &periodic, None), 0);
+    while ALARMS.load(Ordering::Relaxed) < 6 {
+        sleep(5);
+    }
+    assert_eq!(getitimer(ITIMER_REAL, &mut curr), 0);
+    assert_eq!(curr.interval, millis(10));
+    assert!(curr.value > TimeVal::default() && curr.value <= millis(10));
+
+    // disarmed, the old setting comes back
+    let mut old = ITimerVal::default();
+    assert_eq!(setitimer(ITIMER_REAL, &ITimerVal::default(), Some(&mut old)), 0);
+    assert_eq!(old.interval, millis(10));
+    let alarms = ALARMS.load(Ordering::Relaxed);
+    sleep(50);
+    assert_eq!(ALARMS.load(Ordering::Relaxed), alarms);
+
+    // a forked child starts disarmed, and dies of the default action
+    assert_eq!(setitimer(ITIMER_REAL, &ITimerVal { interval: TimeVal::default(), value: millis(1000) }, None), 0);
+    let pid = fork();
+    if pid == 0 {
+        let mut curr = ITimerVal::default();
+        assert_eq!(getitimer(ITIMER_REAL, &mut curr), 0);
+        assert_eq!(curr, ITimerVal::default());
+        let default = SignalAction::default();
+        assert_eq!(sigaction(SIGALRM, Some(&default), None), 0);
+        assert_eq!(setitimer(ITIMER_REAL, &ITimerVal { interval: TimeVal::default(), value: millis(10) }, None), 0);
+        loop {
+            sleep(5);
+        }
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    assert_eq!(exit_code, -(SIGALRM as i32));
+    assert_eq!(setitimer(ITIMER_REAL, &ITimerVal::default(), None), 0);
+
+    println!("itimertest passed!");
+    0
+}
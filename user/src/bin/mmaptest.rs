@@ -0,0 +1,44 @@
+#![no_std]
+#![no_main]
+
+use user::{
+    mincore, mmap, munmap, println, MAP_ANONYMOUS, MAP_FIXED, MAP_PRIVATE, PAGE_RESIDENT, PAGE_UNMAPPED,
+    PROT_READ, PROT_WRITE,
+};
+
+const PAGE_SIZE: usize = 4096;
+const EEXIST: isize = 17;
+const EINVAL: isize = 22;
+
+#[no_mangle]
+unsafe fn main() -> i32 {
+    let flags = MAP_PRIVATE | MAP_ANONYMOUS;
+    let start = mmap(0, 4 * PAGE_SIZE, PROT_READ | PROT_WRITE, flags);
+    assert!(start > 0);
+    let start = start as usize;
+
+    let memory = core::slice::from_raw_parts_mut(start as *mut u8, 4 * PAGE_SIZE);
+    assert!(memory.iter().all(|&byte| byte == 0));
+    memory.fill(0x5a);
+
+    // punch a hole in the middle
+    assert_eq!(munmap(start + PAGE_SIZE, 2 * PAGE_SIZE), 0);
+    let mut residency = [0u8; 4];
+    assert_eq!(mincore(start, 4 * PAGE_SIZE, &mut residency), 0);
+    assert_eq!(residency, [PAGE_RESIDENT, PAGE_UNMAPPED, PAGE_UNMAPPED, PAGE_RESIDENT]);
+    assert_eq!(memory[0], 0x5a);
+    assert_eq!(memory[3 * PAGE_SIZE], 0x5a);
+
+    // the hole can be filled again, but not overlapped
+    let fixed = start + PAGE_SIZE;
+    assert_eq!(mmap(fixed, PAGE_SIZE, PROT_READ, flags | MAP_FIXED), fixed as isize);
+    assert_eq!(mmap(fixed, PAGE_SIZE, PROT_READ, flags | MAP_FIXED), -EEXIST);
+
+    assert_eq!(mmap(0, 0, PROT_READ, flags), -EINVAL);
+    assert_eq!(mmap(0, PAGE_SIZE, PROT_READ, MAP_ANONYMOUS), -EINVAL);
+    assert_eq!(munmap(start + 1, PAGE_SIZE), -EINVAL);
+
+    assert_eq!(munmap(start, 4 * PAGE_SIZE), 0);
+    println!("mmaptest passed!");
+    0
+}
@@ -0,0 +1,47 @@
+#![no_std]
+#![no_main]
+
+use user::{close, mount, open, println, read, umount, O_RDONLY};
+
+const ENOENT: isize = 2;
+const EBUSY: isize = 16;
+const ENODEV: isize = 19;
+const EINVAL: isize = 22;
+
+/// Read `path` whole, `None` if it can't be opened
+fn read_file<'a>(path: &str, buf: &'a mut [u8]) -> Option<&'a [u8]> {
+    let fd = open(path, O_RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let len = read(fd as usize, buf);
+    close(fd as usize);
+    Some(&buf[..len.max(0) as usize])
+}
+
+#[no_mangle]
+unsafe fn main() -> i32 {
+    let mut buf = [0u8; 512];
+    assert!(read_file("/mnt/iomem\0", &mut buf).is_none());
+
+    assert_eq!(mount("/mnt\0", "nofs\0"), -ENODEV);
+    assert_eq!(mount("/mnt\0", "procfs\0"), 0);
+    assert_eq!(mount("/mnt\0", "procfs\0"), -EBUSY);
+
+    // the same files through both mount points
+    let mounts = read_file("/mnt/mounts\0", &mut buf).unwrap();
+    let mounts = core::str::from_utf8(mounts).unwrap();
+    println!("{}", mounts);
+    assert!(mounts.contains("procfs /mnt procfs"));
+    assert!(read_file("/mnt/../proc/iomem\0", &mut buf).is_some());
+    // files of the root file system are still found around it
+    assert!(read_file("/./sleep\0", &mut buf).is_some());
+
+    assert_eq!(umount("/mnt\0"), 0);
+    assert_eq!(umount("/mnt\0"), -EINVAL);
+    assert_eq!(umount("/\0"), -EBUSY);
+    assert_eq!(open("/mnt/iomem\0", O_RDONLY), -ENOENT);
+
+    println!("mounttest passed!");
+    0
+}
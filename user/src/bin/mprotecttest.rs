@@ -0,0 +1,57 @@
+#![no_std]
+#![no_main]
+
+use user::{
+    close, exit, fork, mmap, mprotect, munmap, open, println, read, waitpid, MAP_ANONYMOUS, MAP_PRIVATE,
+    O_RDONLY, PROT_READ, PROT_WRITE,
+};
+
+const PAGE_SIZE: usize = 4096;
+const EFAULT: isize = 14;
+const EINVAL: isize = 22;
+const ENOMEM: isize = 12;
+const SIGSEGV: i32 = 11;
+
+#[no_mangle]
+unsafe fn main() -> i32 {
+    let start = mmap(0, 3 * PAGE_SIZE, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS);
+    assert!(start > 0);
+    let start = start as usize;
+    let memory = core::slice::from_raw_parts_mut(start as *mut u8, 3 * PAGE_SIZE);
+    memory.fill(0x5a);
+
+    // the middle page turns read-only, its content stays
+    let middle = start + PAGE_SIZE;
+    assert_eq!(mprotect(middle, PAGE_SIZE, PROT_READ), 0);
+    assert_eq!(memory[PAGE_SIZE], 0x5a);
+    memory[0] = 1;
+    memory[2 * PAGE_SIZE] = 1;
+
+    // a store to it faults
+    let pid = fork();
+    if pid == 0 {
+        core::ptr::write_volatile(middle as *mut u8, 0);
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    assert_eq!(exit_code, -SIGSEGV);
+
+    // and the kernel doesn't write to it either
+    let zero = open("/dev/zero\0", O_RDONLY) as usize;
+    assert_eq!(read(zero, &mut memory[PAGE_SIZE..2 * PAGE_SIZE]), -EFAULT);
+    close(zero);
+
+    // writable again
+    assert_eq!(mprotect(middle, PAGE_SIZE, PROT_READ | PROT_WRITE), 0);
+    memory[PAGE_SIZE] = 1;
+
+    assert_eq!(mprotect(start + 1, PAGE_SIZE, PROT_READ), -EINVAL);
+    assert_eq!(mprotect(start, PAGE_SIZE, 0), -EINVAL);
+    // past the end of the mapping
+    assert_eq!(mprotect(start, 4 * PAGE_SIZE, PROT_READ), -ENOMEM);
+
+    assert_eq!(munmap(start, 3 * PAGE_SIZE), 0);
+    println!("mprotecttest passed!");
+    0
+}
@@ -0,0 +1,52 @@
+#![no_std]
+#![no_main]
+
+use user::{
+    println, thread_create, yield_,
+    sync::{Condvar, Mutex},
+};
+
+const THREADS: usize = 4;
+const ROUNDS: usize = 50;
+
+static COUNTER: Mutex<usize> = Mutex::new(0);
+static DONE: Mutex<usize> = Mutex::new(0);
+static ALL_DONE: Condvar = Condvar::new();
+
+extern "C" fn worker(_: usize) -> i32 {
+    for _ in 0..ROUNDS {
+        let mut counter = COUNTER.lock();
+        let seen = *counter;
+        // give the others the hart while holding the lock
+        yield_();
+        *counter = seen + 1;
+    }
+    *DONE.lock() += 1;
+    ALL_DONE.notify_all();
+    0
+}
+
+#[no_mangle]
+unsafe fn main() -> i32 {
+    assert_eq!(COUNTER.stats(), Default::default());
+    for i in 0..THREADS {
+        assert!(thread_create(worker, i) > 0);
+    }
+
+    let mut done = DONE.lock();
+    while *done < THREADS {
+        done = ALL_DONE.wait(done);
+    }
+    drop(done);
+    // no increment was lost across the yields
+    assert_eq!(*COUNTER.lock(), THREADS * ROUNDS);
+
+    let stats = COUNTER.stats();
+    println!("mutextest: {:?}", stats);
+    assert!(stats.contended > 0);
+    // a waiter sleeps only after it gave up spinning and yielding
+    assert!(stats.sleeps == 0 || stats.yields > 0);
+
+    println!("mutextest passed!");
+    0
+}
@@ -0,0 +1,27 @@
+#![no_std]
+#![no_main]
+
+use user::{exit, fork, getpriority, println, setpriority, waitpid};
+
+#[no_mangle]
+unsafe fn main() -> i32 {
+    assert_eq!(getpriority(0), Some(0));
+    assert_eq!(setpriority(0, 5), 0);
+    assert_eq!(getpriority(0), Some(5));
+    // clamped to the valid range
+    assert_eq!(setpriority(0, -100), 0);
+    assert_eq!(getpriority(0), Some(-20));
+
+    // inherited on fork
+    let pid = fork();
+    if pid == 0 {
+        exit(getpriority(0).unwrap() as i32);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    assert_eq!(exit_code, -20);
+
+    assert_eq!(getpriority(usize::MAX), None);
+    println!("nice passed!");
+    0
+}
@@ -0,0 +1,56 @@
+#![no_std]
+#![no_main]
+
+use user::{check, close, exit, fork, open, println, sleep, waitpid, Errno, O_RDONLY};
+
+/// `/proc/<pid>/status\0` in `buf`
+fn status_path(pid: usize, buf: &mut [u8; 32]) -> &str {
+    let mut digits = [0u8; 20];
+    let mut len = 0;
+    let mut rest = pid;
+    loop {
+        digits[len] = b'0' + (rest % 10) as u8;
+        len += 1;
+        rest /= 10;
+        if rest == 0 {
+            break;
+        }
+    }
+    let mut at = 0;
+    for &byte in b"/proc/".iter().chain(digits[..len].iter().rev()).chain(b"/status\0".iter()) {
+        buf[at] = byte;
+        at += 1;
+    }
+    core::str::from_utf8(&buf[..at]).unwrap()
+}
+
+#[no_mangle]
+unsafe fn main() -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        // outlive the parent, init adopts and reaps it
+        let orphan = fork();
+        if orphan == 0 {
+            sleep(50);
+            exit(0);
+        }
+        exit(orphan as i32);
+    }
+    let mut orphan = 0;
+    assert_eq!(waitpid(pid, &mut orphan), pid);
+    // not a child of ours
+    assert_eq!(check(waitpid(orphan as isize, &mut 0)), Err(Errno::ECHILD));
+
+    let mut buf = [0u8; 32];
+    let path = status_path(orphan as usize, &mut buf);
+    for _ in 0..100 {
+        let fd = open(path, O_RDONLY);
+        if fd < 0 {
+            println!("orphan passed!");
+            return 0;
+        }
+        close(fd as usize);
+        sleep(10);
+    }
+    panic!("orphan {} was never reaped", orphan);
+}
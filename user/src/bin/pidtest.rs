@@ -0,0 +1,56 @@
+#![no_std]
+#![no_main]
+
+use core::sync::atomic::{AtomicIsize, Ordering};
+
+use user::{exit, fork, getpid, getppid, gettid, println, thread_create, waitpid, yield_};
+
+/// `(pid, tid)` seen by the thread, 0 until it ran
+static THREAD_PID: AtomicIsize = AtomicIsize::new(0);
+static THREAD_TID: AtomicIsize = AtomicIsize::new(0);
+
+extern "C" fn thread(_arg: usize) -> i32 {
+    THREAD_TID.store(gettid(), Ordering::Relaxed);
+    THREAD_PID.store(getpid(), Ordering::Release);
+    0
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let pid = getpid();
+    assert!(pid > 0);
+    // the main thread leads the process
+    assert_eq!(gettid(), pid);
+    assert!(getppid() > 0);
+    assert_ne!(getppid(), pid);
+
+    // the child's parent is this process, and its ids don't move
+    let child = fork();
+    if child == 0 {
+        let own = getpid();
+        assert_ne!(own, pid);
+        assert_eq!(getppid(), pid);
+        for _ in 0..10 {
+            yield_();
+            assert_eq!(getpid(), own);
+            assert_eq!(gettid(), own);
+        }
+        exit(own as i32);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(child, &mut exit_code), child);
+    assert_eq!(exit_code as isize, child);
+
+    // a thread shares the pid, with a tid of its own
+    let tid = thread_create(thread, 0);
+    assert!(tid > 0);
+    while THREAD_PID.load(Ordering::Acquire) == 0 {
+        yield_();
+    }
+    assert_eq!(THREAD_PID.load(Ordering::Relaxed), pid);
+    assert_eq!(THREAD_TID.load(Ordering::Relaxed), tid);
+    assert_ne!(tid, pid);
+
+    println!("pidtest passed!");
+    0
+}
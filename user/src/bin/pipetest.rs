@@ -0,0 +1,26 @@
+#![no_std]
+#![no_main]
+
+use user::{close, pipe, println, read, write};
+
+const MESSAGE: &str = "Hello, pipe!";
+
+#[no_mangle]
+unsafe fn main() -> i32 {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let [read_end, write_end] = fds;
+
+    assert_eq!(write(write_end, MESSAGE.as_bytes()), MESSAGE.len() as isize);
+    close(write_end);
+
+    let mut buffer = [0u8; 32];
+    let read_size = read(read_end, &mut buffer);
+    assert_eq!(&buffer[..read_size as usize], MESSAGE.as_bytes());
+    // every write end is closed: end of file
+    assert_eq!(read(read_end, &mut buffer), 0);
+    close(read_end);
+
+    println!("pipetest passed!");
+    0
+}
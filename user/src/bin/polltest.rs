@@ -0,0 +1,60 @@
+#![no_std]
+#![no_main]
+
+use user::{
+    clock_gettime, close, exit, fork, pipe, poll, println, read, sleep, waitpid, write, PollFd, TimeSpec,
+    CLOCK_MONOTONIC, POLLIN, POLLNVAL, POLLOUT,
+};
+
+fn now_ms() -> usize {
+    let mut now = TimeSpec::default();
+    assert_eq!(clock_gettime(CLOCK_MONOTONIC, &mut now), 0);
+    now.tv_sec * 1000 + now.tv_nsec / 1_000_000
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let [read_end, write_end] = fds;
+
+    // empty: only the write end is ready, a zero timeout doesn't block
+    let mut both = [PollFd::new(read_end, POLLIN), PollFd::new(write_end, POLLOUT)];
+    assert_eq!(poll(&mut both, 0), 1);
+    assert_eq!(both[0].revents, 0);
+    assert_eq!(both[1].revents, POLLOUT);
+
+    // nothing comes: times out
+    let mut input = [PollFd::new(read_end, POLLIN)];
+    let start = now_ms();
+    assert_eq!(poll(&mut input, 20), 0);
+    assert!(now_ms() - start >= 20);
+
+    // woken up by a write from another task
+    let pid = fork();
+    if pid == 0 {
+        sleep(20);
+        write(write_end, b"x");
+        exit(0);
+    }
+    assert_eq!(poll(&mut input, -1), 1);
+    assert_eq!(input[0].revents, POLLIN);
+    let mut byte = [0u8; 1];
+    assert_eq!(read(read_end, &mut byte), 1);
+    assert_eq!(waitpid(pid, &mut 0), pid);
+
+    // an fd not open is reported, a negative one skipped
+    let mut odd = [PollFd::new(42, POLLIN), PollFd { fd: -1, events: POLLIN, revents: 0 }];
+    assert_eq!(poll(&mut odd, 0), 1);
+    assert_eq!(odd[0].revents, POLLNVAL);
+    assert_eq!(odd[1].revents, 0);
+
+    // no write end left: a read returns at once, with the end of file
+    close(write_end);
+    assert_eq!(poll(&mut input, -1), 1);
+    assert_eq!(read(read_end, &mut byte), 0);
+    close(read_end);
+
+    println!("polltest passed!");
+    0
+}
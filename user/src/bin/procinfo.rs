@@ -0,0 +1,25 @@
+#![no_std]
+#![no_main]
+
+use user::{close, open, print, read, O_RDONLY};
+
+/// Print the files of `/proc` describing the whole system
+#[no_mangle]
+fn main() -> i32 {
+    let mut buf = [0u8; 256];
+    for path in ["/proc/cmdline\0", "/proc/uptime\0", "/proc/meminfo\0", "/proc/slabinfo\0", "/proc/mounts\0", "/proc/iosched\0"] {
+        let fd = open(path, O_RDONLY);
+        if fd < 0 {
+            return -1;
+        }
+        loop {
+            let len = read(fd as usize, &mut buf);
+            if len <= 0 {
+                break;
+            }
+            print!("{}", core::str::from_utf8(&buf[..len as usize]).unwrap_or("<not utf-8>\n"));
+        }
+        close(fd as usize);
+    }
+    0
+}
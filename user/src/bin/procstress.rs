@@ -0,0 +1,121 @@
+#![no_std]
+#![no_main]
+
+use user::{close, exit, fork, getpid, open, println, read, waitpid, write, yield_, O_RDONLY};
+
+const EBADF: isize = 9;
+
+/// Tasks forking and switching while `/proc` is read
+const CHURNERS: usize = 4;
+/// Children each churner forks, one after the other
+const ROUNDS: usize = 30;
+/// Passes over the `/proc` files
+const READS: usize = 100;
+
+/// `/proc/<pid>/<file>\0` in `buf`
+fn proc_path<'a>(pid: usize, file: &str, buf: &'a mut [u8; 48]) -> &'a str {
+    let mut digits = [0u8; 20];
+    let mut len = 0;
+    let mut rest = pid;
+    loop {
+        digits[len] = b'0' + (rest % 10) as u8;
+        len += 1;
+        rest /= 10;
+        if rest == 0 {
+            break;
+        }
+    }
+    let mut at = 0;
+    let parts = b"/proc/".iter().chain(digits[..len].iter().rev()).chain(b"/").chain(file.as_bytes());
+    for &byte in parts.chain(b"\0") {
+        buf[at] = byte;
+        at += 1;
+    }
+    core::str::from_utf8(&buf[..at]).unwrap()
+}
+
+/// The whole content of `path` in `buf`, `None` if it can't be opened:
+/// the file of a task which is gone.
+fn read_all<'a>(path: &str, buf: &'a mut [u8; 1024]) -> Option<&'a [u8]> {
+    let fd = open(path, O_RDONLY);
+    if fd < 0 {
+        return None;
+    }
+    let fd = fd as usize;
+    let mut len = 0;
+    loop {
+        let n = read(fd, &mut buf[len..]);
+        assert!(n >= 0);
+        if n == 0 || len + n as usize == buf.len() {
+            len += n as usize;
+            break;
+        }
+        len += n as usize;
+    }
+    close(fd);
+    Some(&buf[..len])
+}
+
+/// Fork short-lived children, which yield before exiting.
+fn churn() -> ! {
+    for _ in 0..ROUNDS {
+        let pid = fork();
+        if pid == 0 {
+            for _ in 0..3 {
+                yield_();
+            }
+            exit(0);
+        }
+        let mut exit_code = -1;
+        assert_eq!(waitpid(pid, &mut exit_code), pid);
+        assert_eq!(exit_code, 0);
+        yield_();
+    }
+    exit(0)
+}
+
+/// Read `/proc` files while other tasks are created, switched and reaped:
+/// every read must see a whole rendering, without hanging the kernel.
+#[no_mangle]
+fn main() -> i32 {
+    let mut churners = [0isize; CHURNERS];
+    for churner in churners.iter_mut() {
+        *churner = fork();
+        if *churner == 0 {
+            churn();
+        }
+    }
+
+    let pid = getpid() as usize;
+    let mut path = [0u8; 48];
+    let mut buf = [0u8; 1024];
+    for round in 0..READS {
+        let meminfo = read_all("/proc/meminfo\0", &mut buf).unwrap();
+        assert!(meminfo.starts_with(b"MemTotal:"));
+        assert!(meminfo.ends_with(b"\n"));
+        assert!(!read_all("/proc/uptime\0", &mut buf).unwrap().is_empty());
+
+        let status = read_all(proc_path(pid, "status", &mut path), &mut buf).unwrap();
+        assert!(status.starts_with(b"Name:"));
+        // a churner may be exiting, or gone
+        let churner = churners[round % CHURNERS] as usize;
+        if let Some(sched) = read_all(proc_path(churner, "sched", &mut path), &mut buf) {
+            assert!(sched.is_empty() || sched.ends_with(b"\n"));
+        }
+        yield_();
+    }
+
+    // a snapshot is read-only
+    let fd = open("/proc/meminfo\0", O_RDONLY) as usize;
+    assert_eq!(write(fd, b"x"), -EBADF);
+    close(fd);
+
+    for churner in churners {
+        let mut exit_code = -1;
+        assert_eq!(waitpid(churner, &mut exit_code), churner);
+        assert_eq!(exit_code, 0);
+    }
+
+    println!("procstress passed!");
+    0
+}
@@ -0,0 +1,43 @@
+#![no_std]
+#![no_main]
+
+use user::{exit, fork, getrandom, pipe, println, read, waitpid, write, GRND_NONBLOCK, GRND_RANDOM};
+
+const EINVAL: isize = 22;
+
+#[no_mangle]
+fn main() -> i32 {
+    let mut first = [0u8; 64];
+    let mut second = [0u8; 64];
+    assert_eq!(getrandom(&mut first, 0), 64);
+    assert_eq!(getrandom(&mut second, GRND_NONBLOCK | GRND_RANDOM), 64);
+    assert_ne!(first, second);
+    // not a constant byte either
+    assert!(first.iter().any(|&byte| byte != first[0]));
+
+    // an odd length is filled to its last byte
+    let mut odd = [0u8; 13];
+    while odd[12] == 0 {
+        assert_eq!(getrandom(&mut odd, 0), 13);
+    }
+    assert_eq!(getrandom(&mut odd, 0x100), -EINVAL);
+    assert_eq!(getrandom(&mut [], 0), 0);
+
+    // a child doesn't repeat what its parent draws
+    let mut pipe_fds = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fds), 0);
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(getrandom(&mut first, 0), 64);
+        assert_eq!(write(pipe_fds[1], &first), 64);
+        exit(0);
+    }
+    assert_eq!(getrandom(&mut second, 0), 64);
+    assert_eq!(read(pipe_fds[0], &mut first), 64);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    assert_ne!(first, second);
+
+    println!("randomtest passed!");
+    0
+}
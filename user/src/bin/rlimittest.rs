@@ -0,0 +1,88 @@
+#![no_std]
+#![no_main]
+
+use user::{
+    brk, close, dup2, exit, fork, getrlimit, mmap, pipe, prlimit, println, setrlimit, waitpid, RLimit,
+    MAP_ANONYMOUS, MAP_PRIVATE, PROT_READ, PROT_WRITE, RLIMIT_AS, RLIMIT_NOFILE, RLIMIT_STACK, RLIM_INFINITY,
+};
+
+const PAGE_SIZE: usize = 4096;
+const EPERM: isize = 1;
+const EBADF: isize = 9;
+const ENOMEM: isize = 12;
+const EINVAL: isize = 22;
+const EMFILE: isize = 24;
+const SIGSEGV: i32 = 11;
+
+/// Use `depth` KiB of stack
+#[inline(never)]
+fn dig(depth: usize) -> usize {
+    let mut frame = [0u8; 1024];
+    for (i, byte) in frame.iter_mut().enumerate() {
+        unsafe { core::ptr::write_volatile(byte, (depth + i) as u8) };
+    }
+    let below = if depth > 1 { dig(depth - 1) } else { 0 };
+    below + unsafe { core::ptr::read_volatile(&frame[depth % 1024]) } as usize
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let nofile = getrlimit(RLIMIT_NOFILE).unwrap();
+    assert_eq!(nofile.cur, nofile.max);
+    assert!(getrlimit(0).is_none());
+
+    // the soft limit can't pass the hard one, which can't be raised
+    let bad = RLimit { cur: nofile.max + 1, max: nofile.max };
+    assert_eq!(setrlimit(RLIMIT_NOFILE, bad), -EINVAL);
+    let bad = RLimit { cur: nofile.max, max: nofile.max + 1 };
+    assert_eq!(setrlimit(RLIMIT_NOFILE, bad), -EPERM);
+
+    // fds: 0, 1 and 2 are open, the limit of 6 leaves one pipe
+    assert_eq!(setrlimit(RLIMIT_NOFILE, RLimit { cur: 6, max: nofile.max }), 0);
+    let mut fds = [0; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    assert_eq!(pipe(&mut [0; 2]), -EMFILE);
+    assert_eq!(dup2(fds[0], 6), -EBADF);
+    assert_eq!(dup2(fds[0], 5), 5);
+    close(5);
+
+    // a child inherits the limits
+    let pid = fork();
+    if pid == 0 {
+        let mut limit = RLimit::default();
+        assert_eq!(prlimit(0, RLIMIT_NOFILE, None, Some(&mut limit)), 0);
+        exit(limit.cur as i32);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    assert_eq!(exit_code, 6);
+    assert_eq!(setrlimit(RLIMIT_NOFILE, nofile), 0);
+    close(fds[0]);
+    close(fds[1]);
+
+    // no room left in the address space, in a child
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(setrlimit(RLIMIT_AS, RLimit { cur: 0, max: RLIM_INFINITY }), 0);
+        let prot = PROT_READ | PROT_WRITE;
+        assert_eq!(mmap(0, PAGE_SIZE, prot, MAP_PRIVATE | MAP_ANONYMOUS), -ENOMEM);
+        let top = brk(0);
+        assert_eq!(brk(top + PAGE_SIZE), top);
+        exit(0);
+    }
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    // a stack of 4 pages can't hold 32 KiB
+    let pid = fork();
+    if pid == 0 {
+        assert_eq!(setrlimit(RLIMIT_STACK, RLimit { cur: 4 * PAGE_SIZE, max: 4 * PAGE_SIZE }), 0);
+        dig(32);
+        exit(0);
+    }
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    assert_eq!(exit_code, -SIGSEGV);
+
+    println!("rlimittest passed!");
+    0
+}
@@ -0,0 +1,96 @@
+#![no_std]
+#![no_main]
+
+use user::{
+    close, exit, fork, get_time, getpid, open, println, read, sched_stat, sleep, waitpid, yield_, SchedStat,
+    O_RDONLY,
+};
+
+const ESRCH: isize = 3;
+
+fn stat(tid: usize) -> SchedStat {
+    let mut stat = SchedStat::default();
+    assert_eq!(sched_stat(tid, &mut stat), 0);
+    stat
+}
+
+/// Busy for at least `us` microseconds.
+fn spin(us: isize) {
+    let start = get_time();
+    while get_time() - start < us {}
+}
+
+/// `/proc/<pid>/sched\0` in `buf`
+fn sched_path(pid: usize, buf: &mut [u8; 32]) -> &str {
+    let mut digits = [0u8; 20];
+    let mut len = 0;
+    let mut rest = pid;
+    loop {
+        digits[len] = b'0' + (rest % 10) as u8;
+        len += 1;
+        rest /= 10;
+        if rest == 0 {
+            break;
+        }
+    }
+    let mut at = 0;
+    for &byte in b"/proc/".iter().chain(digits[..len].iter().rev()).chain(b"/sched\0".iter()) {
+        buf[at] = byte;
+        at += 1;
+    }
+    core::str::from_utf8(&buf[..at]).unwrap()
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    // yielding leaves the task runnable
+    let before = stat(0);
+    for _ in 0..5 {
+        yield_();
+    }
+    let after = stat(0);
+    assert!(after.involuntary_switches >= before.involuntary_switches + 5);
+    assert!(after.switches >= before.switches + 5);
+
+    // sleeping does not
+    let before = after;
+    sleep(10);
+    let after = stat(0);
+    assert!(after.voluntary_switches > before.voluntary_switches);
+
+    // the slice running now is counted
+    let before = stat(0);
+    spin(20_000);
+    let after = stat(0);
+    assert!(after.run_ns > before.run_ns);
+
+    let pid = fork();
+    if pid == 0 {
+        spin(50_000);
+        exit(0);
+    }
+    let before = stat(0);
+    for _ in 0..10 {
+        yield_();
+    }
+    let after = stat(0);
+    assert!(after.wait_ns >= before.wait_ns);
+    // readable for another task too
+    stat(pid as usize);
+    let mut code = 0;
+    assert_eq!(waitpid(pid, &mut code), pid);
+    assert_eq!(sched_stat(99999, &mut SchedStat::default()), -ESRCH);
+
+    let mut buf = [0u8; 32];
+    let fd = open(sched_path(getpid() as usize, &mut buf), O_RDONLY);
+    assert!(fd >= 0);
+    let mut text = [0u8; 512];
+    let len = read(fd as usize, &mut text);
+    close(fd as usize);
+    assert!(len > 0);
+    let text = core::str::from_utf8(&text[..len as usize]).unwrap();
+    assert!(text.contains("Switches:"));
+
+    println!("schedstattest passed!");
+    0
+}
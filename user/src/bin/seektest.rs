@@ -0,0 +1,56 @@
+#![no_std]
+#![no_main]
+
+use user::{
+    close, exit, fork, lseek, open, println, read, waitpid, write, O_APPEND, O_CREAT, O_RDONLY,
+    O_RDWR, O_TRUNC, O_WRONLY, SEEK_CUR, SEEK_END, SEEK_SET,
+};
+
+const EINVAL: isize = 22;
+const ESPIPE: isize = 29;
+const FILE: &str = "seektest.txt\0";
+
+#[no_mangle]
+unsafe fn main() -> i32 {
+    let fd = open(FILE, O_CREAT | O_TRUNC | O_RDWR) as usize;
+    assert_eq!(write(fd, b"0123456789"), 10);
+    assert_eq!(lseek(fd, 0, SEEK_CUR), 10);
+    assert_eq!(lseek(fd, -4, SEEK_END), 6);
+    let mut buf = [0u8; 4];
+    assert_eq!(read(fd, &mut buf), 4);
+    assert_eq!(&buf, b"6789");
+    assert_eq!(lseek(fd, 2, SEEK_SET), 2);
+    assert_eq!(write(fd, b"ab"), 2);
+    assert_eq!(lseek(fd, -1, SEEK_SET), -EINVAL);
+    assert_eq!(lseek(fd, 0, 3), -EINVAL);
+    assert_eq!(lseek(0, 0, SEEK_SET), -ESPIPE);
+    close(fd);
+
+    // writes through a shared fd don't overwrite each other
+    let fd = open(FILE, O_WRONLY | O_APPEND) as usize;
+    let pid = fork();
+    for _ in 0..8 {
+        assert_eq!(write(fd, if pid == 0 { b"c" } else { b"p" }), 1);
+    }
+    if pid == 0 {
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    // appending ignores the offset
+    assert_eq!(lseek(fd, 0, SEEK_SET), 0);
+    assert_eq!(write(fd, b"!"), 1);
+    close(fd);
+
+    let fd = open(FILE, O_RDONLY) as usize;
+    let mut content = [0u8; 64];
+    let len = read(fd, &mut content) as usize;
+    close(fd);
+    assert_eq!(len, 27);
+    assert_eq!(&content[..10], b"01ab456789");
+    assert_eq!(content[10..26].iter().filter(|&&byte| byte == b'c').count(), 8);
+    assert_eq!(content[26], b'!');
+
+    println!("seektest passed!");
+    0
+}
@@ -0,0 +1,96 @@
+#![no_std]
+#![no_main]
+
+use user::{
+    close, fork, lseek, open, pipe, println, read, sendfile, waitpid, write, O_CREAT, O_RDONLY, O_RDWR,
+    O_TRUNC, O_WRONLY, SEEK_CUR, SEEK_SET,
+};
+
+const EBADF: isize = 9;
+const ESPIPE: isize = 29;
+const SOURCE: &str = "sendfile_src.txt\0";
+const DEST: &str = "sendfile_dst.txt\0";
+/// Spans several kernel buffers, and ends mid-block
+const LEN: usize = 3 * 4096 + 123;
+
+fn byte_at(i: usize) -> u8 {
+    (i * 7 + i / 4096) as u8
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let src = open(SOURCE, O_CREAT | O_TRUNC | O_RDWR) as usize;
+    let mut block = [0u8; 512];
+    for start in (0..LEN).step_by(block.len()) {
+        let len = block.len().min(LEN - start);
+        for (i, byte) in block[..len].iter_mut().enumerate() {
+            *byte = byte_at(start + i);
+        }
+        assert_eq!(write(src, &block[..len]), len as isize);
+    }
+
+    // the whole file, from the offset of the fd and moving it
+    let dst = open(DEST, O_CREAT | O_TRUNC | O_WRONLY) as usize;
+    assert_eq!(lseek(src, 0, SEEK_SET), 0);
+    assert_eq!(sendfile(dst, src, None, LEN * 2), LEN as isize);
+    assert_eq!(lseek(src, 0, SEEK_CUR), LEN as isize);
+    assert_eq!(sendfile(dst, src, None, 100), 0);
+    close(dst);
+    let dst = open(DEST, O_RDONLY) as usize;
+    let mut copied = 0;
+    loop {
+        let len = read(dst, &mut block);
+        if len <= 0 {
+            break;
+        }
+        for (i, &byte) in block[..len as usize].iter().enumerate() {
+            assert_eq!(byte, byte_at(copied + i));
+        }
+        copied += len as usize;
+    }
+    assert_eq!(copied, LEN);
+    close(dst);
+
+    // from an explicit offset, which moves instead of the one of the fd
+    let mut fds = [0usize; 2];
+    assert_eq!(pipe(&mut fds), 0);
+    let mut offset = 4000isize;
+    assert_eq!(sendfile(fds[1], src, Some(&mut offset), 200), 200);
+    assert_eq!(offset, 4200);
+    assert_eq!(lseek(src, 0, SEEK_CUR), LEN as isize);
+    assert_eq!(read(fds[0], &mut block[..200]), 200);
+    for (i, &byte) in block[..200].iter().enumerate() {
+        assert_eq!(byte, byte_at(4000 + i));
+    }
+
+    // a pipe as the source, read to its end
+    let pid = fork();
+    if pid == 0 {
+        close(fds[0]);
+        assert_eq!(write(fds[1], b"through a pipe"), 14);
+        return 0;
+    }
+    close(fds[1]);
+    let dst = open(DEST, O_CREAT | O_TRUNC | O_RDWR) as usize;
+    assert_eq!(sendfile(dst, fds[0], None, 1000), 14);
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    assert_eq!(lseek(dst, 0, SEEK_SET), 0);
+    assert_eq!(read(dst, &mut block), 14);
+    assert_eq!(&block[..14], b"through a pipe");
+    let mut offset = 0isize;
+    assert_eq!(sendfile(dst, fds[0], Some(&mut offset), 10), -ESPIPE);
+
+    // the ends must be open the right way
+    assert_eq!(sendfile(src, dst, None, 10), 0);
+    let reader = open(SOURCE, O_RDONLY) as usize;
+    assert_eq!(sendfile(reader, dst, None, 10), -EBADF);
+    assert_eq!(sendfile(dst, 99, None, 10), -EBADF);
+    close(reader);
+    close(fds[0]);
+    close(dst);
+    close(src);
+
+    println!("sendfiletest passed!");
+    0
+}
@@ -0,0 +1,68 @@
+#![no_std]
+#![no_main]
+
+use core::ptr::{read_volatile, write_volatile};
+
+use user::{
+    exit, fork, println, shmat, shmctl, shmdt, shmget, waitpid, IPC_CREAT, IPC_EXCL, IPC_PRIVATE, IPC_RMID,
+    SHM_RDONLY,
+};
+
+const PAGE_SIZE: usize = 4096;
+const EEXIST: isize = 17;
+const EINVAL: isize = 22;
+const SIGSEGV: i32 = 11;
+const KEY: usize = 0x73686d;
+
+#[no_mangle]
+unsafe fn main() -> i32 {
+    let id = shmget(IPC_PRIVATE, 2 * PAGE_SIZE, IPC_CREAT | 0o600);
+    assert!(id > 0);
+    let id = id as usize;
+    let shared = shmat(id, 0, 0);
+    assert!(shared > 0);
+    let shared = shared as *mut u32;
+    assert_eq!(read_volatile(shared), 0);
+
+    // the child writes through the attachment it inherited, and through one of its own
+    let pid = fork();
+    if pid == 0 {
+        write_volatile(shared, 42);
+        let own = shmat(id, 0, 0) as *mut u32;
+        write_volatile(own.add(PAGE_SIZE / 4), 43);
+        exit(0);
+    }
+    assert_eq!(waitpid(pid, &mut 0), pid);
+    assert_eq!(read_volatile(shared), 42);
+    assert_eq!(read_volatile(shared.add(PAGE_SIZE / 4)), 43);
+
+    // a read-only attachment sees the data but faults on a store
+    let read_only = shmat(id, 0, SHM_RDONLY) as *mut u32;
+    assert_eq!(read_volatile(read_only), 42);
+    let pid = fork();
+    if pid == 0 {
+        write_volatile(read_only, 0);
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    assert_eq!(exit_code, -SIGSEGV);
+    assert_eq!(shmdt(read_only as usize), 0);
+    assert_eq!(shmdt(read_only as usize), -EINVAL);
+
+    // removed, it can't be attached again but stays mapped here
+    assert_eq!(shmctl(id, IPC_RMID), 0);
+    assert_eq!(shmat(id, 0, 0), -EINVAL);
+    assert_eq!(read_volatile(shared), 42);
+    assert_eq!(shmdt(shared as usize), 0);
+
+    // keyed segments are found by their key
+    let keyed = shmget(KEY, PAGE_SIZE, IPC_CREAT | 0o600);
+    assert!(keyed > 0);
+    assert_eq!(shmget(KEY, PAGE_SIZE, 0), keyed);
+    assert_eq!(shmget(KEY, PAGE_SIZE, IPC_CREAT | IPC_EXCL), -EEXIST);
+    assert_eq!(shmctl(keyed as usize, IPC_RMID), 0);
+
+    println!("shmtest passed!");
+    0
+}
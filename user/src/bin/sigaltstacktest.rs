@@ -0,0 +1,78 @@
+#![no_std]
+#![no_main]
+
+use user::{
+    exit, fork, println, sigaction, sigaltstack, waitpid, SignalAction, SignalStack, MINSIGSTKSZ, SA_ONSTACK,
+    SIGSEGV, SS_DISABLE, SS_ONSTACK,
+};
+
+const EFAULT: isize = 14;
+const EINVAL: isize = 22;
+const ENOMEM: isize = 12;
+
+const ALT_STACK_SIZE: usize = 4 * 4096;
+/// Exit code of a handler which found itself on the alternate stack
+const ON_ALT_STACK: i32 = 42;
+
+static mut ALT_STACK: [u8; ALT_STACK_SIZE] = [0; ALT_STACK_SIZE];
+
+/// Use `depth` KiB of stack
+#[inline(never)]
+fn dig(depth: usize) -> usize {
+    let mut frame = [0u8; 1024];
+    for (i, byte) in frame.iter_mut().enumerate() {
+        unsafe { core::ptr::write_volatile(byte, (depth + i) as u8) };
+    }
+    let below = if depth > 1 { dig(depth - 1) } else { 0 };
+    below + unsafe { core::ptr::read_volatile(&frame[depth % 1024]) } as usize
+}
+
+extern "C" fn on_segv(_signum: usize) {
+    let mut current = SignalStack::default();
+    assert_eq!(sigaltstack(None, Some(&mut current)), 0);
+    exit(if current.flags == SS_ONSTACK { ON_ALT_STACK } else { 1 });
+}
+
+/// Overflow the stack in a child, with a `SIGSEGV` handler installed with
+/// `flags`, returns its exit code.
+fn overflow(flags: u32) -> i32 {
+    let pid = fork();
+    if pid == 0 {
+        let action = SignalAction { handler: on_segv as usize, mask: 0, flags };
+        assert_eq!(sigaction(SIGSEGV, Some(&action), None), 0);
+        dig(usize::MAX);
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    exit_code
+}
+
+#[no_mangle]
+unsafe fn main() -> i32 {
+    let mut old = SignalStack::default();
+    assert_eq!(sigaltstack(None, Some(&mut old)), 0);
+    assert_eq!(old.flags, SS_DISABLE);
+
+    let base = core::ptr::addr_of_mut!(ALT_STACK) as usize;
+    let stack = SignalStack { sp: base, flags: 0, size: ALT_STACK_SIZE };
+    assert_eq!(sigaltstack(Some(&SignalStack { size: MINSIGSTKSZ - 1, ..stack }), None), -ENOMEM);
+    assert_eq!(sigaltstack(Some(&SignalStack { flags: SS_ONSTACK, ..stack }), None), -EINVAL);
+    // nothing mapped there
+    assert_eq!(sigaltstack(Some(&SignalStack { sp: 0x1000, ..stack }), None), -EFAULT);
+    assert_eq!(sigaltstack(Some(&SignalStack { sp: usize::MAX - 4096, ..stack }), None), -EINVAL);
+    assert_eq!(sigaltstack(Some(&stack), None), 0);
+    assert_eq!(sigaltstack(None, Some(&mut old)), 0);
+    assert_eq!((old.sp, old.flags, old.size), (base, 0, ALT_STACK_SIZE));
+
+    // the handler of an overflow runs on the alternate stack, inherited
+    assert_eq!(overflow(SA_ONSTACK), ON_ALT_STACK);
+    // and can't run on the exhausted one: the default action applies
+    assert_eq!(overflow(0), -(SIGSEGV as i32));
+
+    assert_eq!(sigaltstack(Some(&SignalStack { flags: SS_DISABLE, ..stack }), None), 0);
+    assert_eq!(sigaltstack(None, Some(&mut old)), 0);
+    assert_eq!(old.flags, SS_DISABLE);
+    println!("sigaltstacktest passed!");
+    0
+}
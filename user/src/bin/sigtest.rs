@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use user::{
+    exit, fork, kill, println, sigaction, sigprocmask, waitpid, yield_, SignalAction, SIGUSR1, SIGUSR2,
+    SIG_BLOCK, SIG_UNBLOCK,
+};
+
+static HANDLED: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns normally, the `sigreturn` stub restores the context
+extern "C" fn on_signal(signum: usize) {
+    HANDLED.fetch_add(signum, Ordering::SeqCst);
+}
+
+#[no_mangle]
+unsafe fn main() -> i32 {
+    let action = SignalAction {
+        handler: on_signal as usize,
+        mask: 0,
+        flags: 0,
+    };
+    assert_eq!(sigaction(SIGUSR1, Some(&action), None), 0);
+    assert_eq!(sigaction(SIGUSR2, Some(&action), None), 0);
+
+    // the child inherits the handlers, and blocks SIGUSR2 for now
+    assert_eq!(sigprocmask(SIG_BLOCK, Some(1 << SIGUSR2), None), 0);
+    let pid = fork();
+    if pid == 0 {
+        while HANDLED.load(Ordering::SeqCst) == 0 {
+            yield_();
+        }
+        assert_eq!(HANDLED.load(Ordering::SeqCst), SIGUSR1);
+        // the blocked signal is delivered once unblocked
+        assert_eq!(sigprocmask(SIG_UNBLOCK, Some(1 << SIGUSR2), None), 0);
+        assert_eq!(HANDLED.load(Ordering::SeqCst), SIGUSR1 + SIGUSR2);
+        exit(0);
+    }
+
+    assert_eq!(kill(pid as usize, SIGUSR2), 0);
+    assert_eq!(kill(pid as usize, SIGUSR1), 0);
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    assert_eq!(exit_code, 0);
+
+    println!("sigtest passed!");
+    0
+}
@@ -1,17 +1,17 @@
 #![no_std]
 #![no_main]
 
-use user::{get_time, println, yield_};
+use user::{get_time, println, sleep};
 
 #[no_mangle]
 unsafe fn main() -> i32 {
     println!("testing sleep!");
-    let current_timer = get_time();
-    let wait_for = current_timer + 3000;
-    while get_time() < wait_for {
-        println!("prepare yield");
-        yield_();
-    }
+    let start = get_time();
+    sleep(300);
+    // get_time returns microseconds
+    let elapsed = get_time() - start;
+    println!("slept for {} us", elapsed);
+    assert!(elapsed >= 300_000);
     println!("Test sleep OK!");
     0
-}
\ No newline at end of file
+}
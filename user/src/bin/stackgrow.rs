@@ -0,0 +1,35 @@
+#![no_std]
+#![no_main]
+
+use user::{exit, fork, println, waitpid};
+
+const SIGSEGV: i32 = 11;
+
+/// Use `depth` KiB of stack, the kernel maps it as it goes
+#[inline(never)]
+fn dig(depth: usize) -> usize {
+    let mut frame = [0u8; 1024];
+    for (i, byte) in frame.iter_mut().enumerate() {
+        unsafe { core::ptr::write_volatile(byte, (depth + i) as u8) };
+    }
+    let below = if depth > 1 { dig(depth - 1) } else { 0 };
+    below + unsafe { core::ptr::read_volatile(&frame[depth % 1024]) } as usize
+}
+
+#[no_mangle]
+unsafe fn main() -> i32 {
+    // well past the single page mapped at start
+    dig(32);
+
+    // unbounded recursion ends in the guard page
+    let pid = fork();
+    if pid == 0 {
+        dig(usize::MAX);
+        exit(0);
+    }
+    let mut exit_code = 0;
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    assert_eq!(exit_code, -SIGSEGV);
+    println!("stackgrow passed!");
+    0
+}
@@ -0,0 +1,66 @@
+#![no_std]
+#![no_main]
+
+use user::{
+    close, fstat, open, pipe, println, stat, write, Stat, O_CREAT, O_RDONLY, O_RDWR, O_TRUNC,
+    S_IFCHR, S_IFDIR, S_IFIFO, S_IFMT, S_IFREG,
+};
+
+const EBADF: isize = 9;
+const ENOENT: isize = 2;
+const ENOTDIR: isize = 20;
+const FILE: &str = "stattest.txt\0";
+
+#[no_mangle]
+fn main() -> i32 {
+    let fd = open(FILE, O_CREAT | O_TRUNC | O_RDWR) as usize;
+    assert_eq!(write(fd, &[7u8; 600]), 600);
+    let mut by_fd = Stat::default();
+    assert_eq!(fstat(fd, &mut by_fd), 0);
+    assert_eq!(by_fd.st_mode & S_IFMT, S_IFREG);
+    assert_eq!(by_fd.st_size, 600);
+    assert_eq!(by_fd.st_blocks, 2);
+    assert_eq!(by_fd.st_nlink, 1);
+    assert_ne!(by_fd.st_ino, 0);
+    close(fd);
+
+    // the same node by path
+    let mut by_path = Stat::default();
+    assert_eq!(stat(FILE, &mut by_path), 0);
+    assert_eq!(by_path.st_ino, by_fd.st_ino);
+    assert_eq!(by_path.st_size, 600);
+
+    let mut root = Stat::default();
+    assert_eq!(stat("/\0", &mut root), 0);
+    assert_eq!(root.st_mode & S_IFMT, S_IFDIR);
+    assert_ne!(root.st_ino, by_fd.st_ino);
+
+    let mut device = Stat::default();
+    assert_eq!(stat("/dev/null\0", &mut device), 0);
+    assert_eq!(device.st_mode & S_IFMT, S_IFCHR);
+    assert_eq!(fstat(0, &mut device), 0);
+    assert_eq!(device.st_mode & S_IFMT, S_IFCHR);
+
+    let mut pipe_fds = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fds), 0);
+    let mut fifo = Stat::default();
+    assert_eq!(fstat(pipe_fds[0], &mut fifo), 0);
+    assert_eq!(fifo.st_mode & S_IFMT, S_IFIFO);
+    close(pipe_fds[0]);
+    close(pipe_fds[1]);
+
+    // a /proc file is sized when opened
+    let fd = open("/proc/uptime\0", O_RDONLY) as usize;
+    let mut snapshot = Stat::default();
+    assert_eq!(fstat(fd, &mut snapshot), 0);
+    assert!(snapshot.st_size > 0);
+    assert!(snapshot.st_mtime.tv_sec > 0 || snapshot.st_mtime.tv_nsec > 0);
+    close(fd);
+
+    assert_eq!(stat("nothere\0", &mut by_path), -ENOENT);
+    assert_eq!(stat("stattest.txt/x\0", &mut by_path), -ENOTDIR);
+    assert_eq!(fstat(99, &mut by_path), -EBADF);
+
+    println!("stattest passed!");
+    0
+}
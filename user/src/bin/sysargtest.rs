@@ -0,0 +1,47 @@
+#![no_std]
+#![no_main]
+
+use user::{println, raw_syscall, sched_stat, SchedStat};
+
+const EFAULT: isize = 14;
+const EINVAL: isize = 22;
+
+const SYSCALL_OPEN: usize = 56;
+const SYSCALL_WRITE: usize = 64;
+const SYSCALL_GETRANDOM: usize = 278;
+const SYSCALL_SCHED_STAT: usize = 516;
+
+/// The trampoline, mapped in every address space, not for the caller to pass
+const TRAMPOLINE: usize = 0xffff_ffff_bfff_f000;
+/// Where the kernel image lies
+const KERNEL: usize = 0xffff_ffc0_8020_0000;
+
+#[no_mangle]
+fn main() -> i32 {
+    let text = b"sysargtest\n";
+    // pointers out of the user half
+    for addr in [TRAMPOLINE, KERNEL, usize::MAX] {
+        assert_eq!(raw_syscall(SYSCALL_WRITE, [1, addr, 4, 0, 0, 0]), -EFAULT);
+        assert_eq!(raw_syscall(SYSCALL_OPEN, [addr, 0, 0, 0, 0, 0]), -EFAULT);
+        assert_eq!(raw_syscall(SYSCALL_SCHED_STAT, [0, addr, 0, 0, 0, 0]), -EFAULT);
+    }
+    // a stat ending past the user half
+    let end = 1usize << 38;
+    assert_eq!(raw_syscall(SYSCALL_SCHED_STAT, [0, end - 8, 0, 0, 0, 0]), -EFAULT);
+
+    // 32-bit arguments with garbage above them
+    let path = "/proc/uptime\0";
+    assert_eq!(raw_syscall(SYSCALL_OPEN, [path.as_ptr() as usize, 1 << 40, 0, 0, 0, 0]), -EINVAL);
+    let mut buf = [0u8; 8];
+    let dirty_flags = 0xdead_0000_0000;
+    assert_eq!(raw_syscall(SYSCALL_GETRANDOM, [buf.as_mut_ptr() as usize, 8, dirty_flags, 0, 0, 0]), -EINVAL);
+    // the same call with clean flags
+    assert_eq!(raw_syscall(SYSCALL_GETRANDOM, [buf.as_mut_ptr() as usize, 8, 0, 0, 0, 0]), 8);
+
+    // the arguments a wrapper passes still work
+    assert_eq!(raw_syscall(SYSCALL_WRITE, [1, text.as_ptr() as usize, text.len(), 0, 0, 0]), text.len() as isize);
+    assert_eq!(sched_stat(0, &mut SchedStat::default()), 0);
+
+    println!("sysargtest passed!");
+    0
+}
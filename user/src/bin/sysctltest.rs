@@ -0,0 +1,39 @@
+#![no_std]
+#![no_main]
+
+use user::{println, sysctl};
+
+const ENOENT: isize = 2;
+const EINVAL: isize = 22;
+
+const TIME_SLICE: &str = "kernel.sched_time_slice_ms\0";
+const WATCHDOG_THRESH: &str = "kernel.watchdog_thresh\0";
+const SOFTLOCKUP_PANIC: &str = "kernel.softlockup_panic\0";
+
+#[no_mangle]
+unsafe fn main() -> i32 {
+    let mut slice_ms = 0;
+    assert_eq!(sysctl(TIME_SLICE, Some(&mut slice_ms), None), 0);
+    assert!(slice_ms > 0);
+
+    // the old value comes back while the new one is set
+    let mut old = 0;
+    assert_eq!(sysctl(TIME_SLICE, Some(&mut old), Some(2 * slice_ms)), 0);
+    assert_eq!(old, slice_ms);
+    let mut new = 0;
+    assert_eq!(sysctl(TIME_SLICE, Some(&mut new), Some(slice_ms)), 0);
+    assert_eq!(new, 2 * slice_ms);
+
+    assert_eq!(sysctl(TIME_SLICE, None, Some(0)), -EINVAL);
+    assert_eq!(sysctl("kernel.nothing\0", None, None), -ENOENT);
+
+    // the watchdog takes 0 to stop, but not a threshold past a minute
+    let mut thresh = 0;
+    assert_eq!(sysctl(WATCHDOG_THRESH, Some(&mut thresh), Some(0)), 0);
+    assert!(thresh > 0);
+    assert_eq!(sysctl(WATCHDOG_THRESH, None, Some(61)), -EINVAL);
+    assert_eq!(sysctl(WATCHDOG_THRESH, None, Some(thresh)), 0);
+    assert_eq!(sysctl(SOFTLOCKUP_PANIC, None, Some(2)), -EINVAL);
+    println!("sysctltest passed!");
+    0
+}
@@ -0,0 +1,100 @@
+#![no_std]
+#![no_main]
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use user::{
+    exit, fork, println, thread_create, waitpid, yield_,
+    sync::{Condvar, Mutex},
+};
+
+const EAGAIN: isize = 11;
+
+const THREADS: usize = 4;
+const ROUNDS: usize = 100;
+/// User stack slots of a process, the main thread takes one
+const MAX_THREADS: usize = 64;
+
+/// `(done threads, total of their increments)`
+static STATE: Mutex<(usize, usize)> = Mutex::new((0, 0));
+static ALL_DONE: Condvar = Condvar::new();
+
+extern "C" fn worker(increment: usize) -> i32 {
+    for _ in 0..ROUNDS {
+        STATE.lock().1 += increment;
+    }
+    let mut state = STATE.lock();
+    state.0 += 1;
+    drop(state);
+    ALL_DONE.notify_all();
+    0
+}
+
+static RELEASE: AtomicBool = AtomicBool::new(false);
+/// Threads started by `parked` and not done yet
+static PARKED: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn parked(_: usize) -> i32 {
+    while !RELEASE.load(Ordering::Acquire) {
+        yield_();
+    }
+    PARKED.fetch_sub(1, Ordering::AcqRel);
+    0
+}
+
+static FORKED: AtomicBool = AtomicBool::new(false);
+
+/// Fork from a thread: the child runs on the copy of its stack
+extern "C" fn forker(arg: usize) -> i32 {
+    let marker = [arg; 4];
+    let pid = fork();
+    if pid == 0 {
+        exit(marker.iter().sum::<usize>() as i32);
+    }
+    let mut exit_code = -1;
+    assert_eq!(waitpid(pid, &mut exit_code), pid);
+    assert_eq!(exit_code, 4 * arg as i32);
+    FORKED.store(true, Ordering::Release);
+    0
+}
+
+#[no_mangle]
+unsafe fn main() -> i32 {
+    for i in 0..THREADS {
+        assert!(thread_create(worker, i + 1) > 0);
+    }
+
+    let mut state = STATE.lock();
+    while state.0 < THREADS {
+        state = ALL_DONE.wait(state);
+    }
+    // every thread wrote the same memory
+    assert_eq!(state.1, ROUNDS * (1..=THREADS).sum::<usize>());
+    drop(state);
+
+    assert!(thread_create(forker, 5) > 0);
+    while !FORKED.load(Ordering::Acquire) {
+        yield_();
+    }
+
+    // threads past the stack slots of the process are refused
+    let mut created = 0;
+    let refused = loop {
+        PARKED.fetch_add(1, Ordering::AcqRel);
+        let tid = thread_create(parked, 0);
+        if tid < 0 {
+            PARKED.fetch_sub(1, Ordering::AcqRel);
+            break tid;
+        }
+        created += 1;
+        assert!(created < MAX_THREADS, "more threads than user stack slots");
+    };
+    assert_eq!(refused, -EAGAIN);
+    RELEASE.store(true, Ordering::Release);
+    while PARKED.load(Ordering::Acquire) != 0 {
+        yield_();
+    }
+
+    println!("threadtest passed!");
+    0
+}
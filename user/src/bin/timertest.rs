@@ -0,0 +1,55 @@
+#![no_std]
+#![no_main]
+
+use core::sync::atomic::AtomicU32;
+
+use user::{clock_gettime, futex_wait_timeout, nanosleep, println, TimeSpec, CLOCK_MONOTONIC};
+
+const EAGAIN: isize = 11;
+const EINVAL: isize = 22;
+const ETIMEDOUT: isize = 110;
+
+static WORD: AtomicU32 = AtomicU32::new(0);
+
+fn now_us() -> usize {
+    let mut now = TimeSpec::default();
+    assert_eq!(clock_gettime(CLOCK_MONOTONIC, &mut now), 0);
+    now.tv_sec * 1_000_000 + now.tv_nsec / 1000
+}
+
+fn micros(us: usize) -> TimeSpec {
+    TimeSpec {
+        tv_sec: us / 1_000_000,
+        tv_nsec: us % 1_000_000 * 1000,
+    }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    // short sleeps end on their own deadline, not on the next scheduler tick
+    let start = now_us();
+    for _ in 0..10 {
+        assert_eq!(nanosleep(&micros(500)), 0);
+    }
+    let elapsed = now_us() - start;
+    assert!(elapsed >= 5_000, "slept {}us only", elapsed);
+    assert!(elapsed < 30_000, "10 sleeps of 500us took {}us", elapsed);
+
+    // nobody wakes the word
+    let start = now_us();
+    assert_eq!(futex_wait_timeout(&WORD, 0, &micros(10_000)), -ETIMEDOUT);
+    let elapsed = now_us() - start;
+    assert!(elapsed >= 10_000, "timed out after {}us only", elapsed);
+    // a stale value still returns at once
+    assert_eq!(futex_wait_timeout(&WORD, 1, &micros(10_000)), -EAGAIN);
+
+    let invalid = TimeSpec {
+        tv_sec: 0,
+        tv_nsec: 1_000_000_000,
+    };
+    assert_eq!(nanosleep(&invalid), -EINVAL);
+    assert_eq!(futex_wait_timeout(&WORD, 0, &invalid), -EINVAL);
+
+    println!("timertest passed!");
+    0
+}
@@ -0,0 +1,66 @@
+#![no_std]
+#![no_main]
+
+use user::{
+    close, ftruncate, lseek, open, pipe, println, read, write, O_CREAT, O_RDONLY, O_RDWR,
+    O_TRUNC, SEEK_CUR, SEEK_END, SEEK_SET,
+};
+
+const EBADF: isize = 9;
+const EINVAL: isize = 22;
+const FILE: &str = "truncatetest.txt\0";
+
+fn size(fd: usize) -> isize {
+    let offset = lseek(fd, 0, SEEK_CUR);
+    let size = lseek(fd, 0, SEEK_END);
+    lseek(fd, offset, SEEK_SET);
+    size
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let fd = open(FILE, O_CREAT | O_TRUNC | O_RDWR) as usize;
+    assert_eq!(write(fd, &[7u8; 1000]), 1000);
+
+    // shrinking keeps the offset, the bytes past the end read as zeros
+    // once grown back
+    assert_eq!(ftruncate(fd, 100), 0);
+    assert_eq!(size(fd), 100);
+    assert_eq!(lseek(fd, 0, SEEK_CUR), 1000);
+    assert_eq!(ftruncate(fd, 600), 0);
+    let mut buf = [0xffu8; 600];
+    lseek(fd, 0, SEEK_SET);
+    assert_eq!(read(fd, &mut buf), 600);
+    assert!(buf[..100].iter().all(|&byte| byte == 7));
+    assert!(buf[100..].iter().all(|&byte| byte == 0));
+
+    // through the indirect blocks, and back
+    assert_eq!(ftruncate(fd, 200 * 1024), 0);
+    assert_eq!(size(fd), 200 * 1024);
+    assert_eq!(ftruncate(fd, 0), 0);
+    assert_eq!(size(fd), 0);
+    // written at the offset the read left, after a hole
+    assert_eq!(write(fd, b"kept"), 4);
+    close(fd);
+
+    // O_CREAT alone opens an existing file as it is, O_TRUNC empties it
+    let fd = open(FILE, O_CREAT | O_RDWR) as usize;
+    assert_eq!(size(fd), 604);
+    close(fd);
+    let fd = open(FILE, O_RDONLY) as usize;
+    assert_eq!(ftruncate(fd, 0), -EINVAL);
+    close(fd);
+    let fd = open(FILE, O_TRUNC | O_RDWR) as usize;
+    assert_eq!(size(fd), 0);
+    close(fd);
+
+    let mut pipe_fds = [0usize; 2];
+    assert_eq!(pipe(&mut pipe_fds), 0);
+    assert_eq!(ftruncate(pipe_fds[1], 0), -EINVAL);
+    close(pipe_fds[0]);
+    close(pipe_fds[1]);
+    assert_eq!(ftruncate(99, 0), -EBADF);
+
+    println!("truncatetest passed!");
+    0
+}
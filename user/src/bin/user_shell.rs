@@ -0,0 +1,187 @@
+//! A minimal shell: each line names a program of the file system to run.
+//!
+//! `name` runs it in the foreground, `name &` in the background, the
+//! background jobs are reported once they exited, before the next prompt.
+//! Builtins: `help`, `exit`, and `cp <from> <to>`, which copies a file
+//! in the kernel with `sendfile`. Arguments are not passed on to the
+//! programs, `exec` takes none.
+//!
+//! `user_shell <script>` runs the lines of the script instead of those of
+//! stdin, without prompt: a script starting with `#!/user_shell` runs by
+//! itself.
+
+#![no_std]
+#![no_main]
+
+use user::{
+    args, check, close, exec, exit, fork, open, perror, print, println, read, sendfile, try_waitpid, waitpid, Errno,
+    O_CREAT, O_RDONLY, O_TRUNC, O_WRONLY,
+};
+
+const STDIN: usize = 0;
+const LINE_MAX: usize = 128;
+
+/// Read a line from `fd` into `buf`, without its `\n`, `None` at the end of input.
+///
+/// Reads a byte at a time: the rest of the input is left to the programs
+/// run, or to the next line.
+fn read_line(fd: usize, buf: &mut [u8; LINE_MAX]) -> Option<&str> {
+    let mut len = 0;
+    loop {
+        let read_len = read(fd, &mut buf[len..len + 1]);
+        if read_len <= 0 {
+            return if len == 0 { None } else { core::str::from_utf8(&buf[..len]).ok() };
+        }
+        len += read_len as usize;
+        if buf[len - 1] == b'\n' {
+            return Some(core::str::from_utf8(&buf[..len - 1]).unwrap_or(""));
+        }
+        if len == LINE_MAX {
+            println!("shell: line too long");
+            return Some("");
+        }
+    }
+}
+
+/// Fork and exec `name`, returns the pid of the child.
+fn spawn(name: &str) -> Option<isize> {
+    // the kernel takes NUL-terminated paths
+    let mut path = [0u8; LINE_MAX + 1];
+    path[..name.len()].copy_from_slice(name.as_bytes());
+    let path = core::str::from_utf8(&path[..name.len() + 1]).unwrap();
+
+    let pid = fork();
+    if pid == 0 {
+        match check(exec(path)) {
+            Err(Errno::ENOENT) => println!("shell: {}: command not found", name),
+            Err(err) => perror(name, err),
+            Ok(_) => unreachable!(),
+        }
+        exit(-1);
+    }
+    (pid > 0).then_some(pid)
+}
+
+/// `name` followed by the `\0` the kernel takes, in `buf`
+fn with_nul<'a>(name: &str, buf: &'a mut [u8; LINE_MAX + 1]) -> &'a str {
+    buf[..name.len()].copy_from_slice(name.as_bytes());
+    buf[name.len()] = 0;
+    core::str::from_utf8(&buf[..name.len() + 1]).unwrap()
+}
+
+/// Copy the file `from` to `to`, created or truncated, in the kernel.
+fn copy(from: &str, to: &str) {
+    let mut path = [0u8; LINE_MAX + 1];
+    let in_fd = match check(open(with_nul(from, &mut path), O_RDONLY)) {
+        Ok(fd) => fd,
+        Err(err) => return perror(from, err),
+    };
+    let out_fd = match check(open(with_nul(to, &mut path), O_WRONLY | O_CREAT | O_TRUNC)) {
+        Ok(fd) => fd,
+        Err(err) => {
+            close(in_fd);
+            return perror(to, err);
+        }
+    };
+    if let Err(err) = check(sendfile(out_fd, in_fd, None, isize::MAX as usize)) {
+        perror("cp", err);
+    }
+    close(out_fd);
+    close(in_fd);
+}
+
+/// Report the background jobs which exited.
+fn reap_jobs() {
+    loop {
+        let mut exit_code = 0;
+        let pid = try_waitpid(-1, &mut exit_code);
+        // 0 while jobs still run, -ECHILD once there is none
+        if pid <= 0 {
+            return;
+        }
+        println!("[{}] done, exit code {}", pid, exit_code);
+    }
+}
+
+/// Open the script named by the arguments, if any.
+fn open_script() -> Result<Option<usize>, ()> {
+    let Some(script) = args().nth(1) else {
+        return Ok(None);
+    };
+    let mut path = [0u8; LINE_MAX + 1];
+    if script.len() > LINE_MAX {
+        println!("shell: {}: name too long", script);
+        return Err(());
+    }
+    path[..script.len()].copy_from_slice(script.as_bytes());
+    let path = core::str::from_utf8(&path[..script.len() + 1]).unwrap();
+    match check(open(path, O_RDONLY)) {
+        Ok(fd) => Ok(Some(fd)),
+        Err(err) => {
+            perror(script, err);
+            Err(())
+        }
+    }
+}
+
+#[no_mangle]
+fn main() -> i32 {
+    let Ok(script) = open_script() else {
+        return -1;
+    };
+    let input = script.unwrap_or(STDIN);
+    let mut line = [0u8; LINE_MAX];
+    loop {
+        reap_jobs();
+        if script.is_none() {
+            print!(">> ");
+        }
+        let Some(command) = read_line(input, &mut line) else {
+            if let Some(fd) = script {
+                close(fd);
+            }
+            return 0;
+        };
+        // the `#!` line of the script, and comments
+        if command.starts_with('#') {
+            continue;
+        }
+        let mut words = command.split_whitespace();
+        let Some(name) = words.next() else {
+            continue;
+        };
+        if name == "cp" {
+            match (words.next(), words.next(), words.next()) {
+                (Some(from), Some(to), None) => copy(from, to),
+                _ => println!("usage: cp <from> <to>"),
+            }
+            continue;
+        }
+        let mut background = false;
+        for word in words {
+            match word {
+                "&" => background = true,
+                _ => println!("shell: argument `{}` ignored", word),
+            }
+        }
+
+        match name {
+            "exit" => return 0,
+            "help" => {
+                println!("Run a program: <name> [&]");
+                println!("Builtins: help, exit, cp <from> <to>");
+            }
+            _ => match spawn(name) {
+                Some(pid) if background => println!("[{}]", pid),
+                Some(pid) => {
+                    let mut exit_code = 0;
+                    waitpid(pid, &mut exit_code);
+                    if exit_code != 0 {
+                        println!("shell: {} exited with {}", name, exit_code);
+                    }
+                }
+                None => println!("shell: fork failed"),
+            },
+        }
+    }
+}
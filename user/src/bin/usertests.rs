@@ -0,0 +1,83 @@
+//! Run every self-checking test program, one after the other.
+//!
+//! Each one exits with 0 once its checks passed, anything else is a
+//! failure. Started from the shell, it is the integration test of the kernel.
+
+#![no_std]
+#![no_main]
+
+use user::{exec, exit, fork, println, waitpid};
+
+/// NUL-terminated names of the programs
+const TESTS: &[&str] = &[
+    "affinitytest\0",
+    "alloctest\0",
+    "arenatest\0",
+    "argvtest\0",
+    "aslrtest\0",
+    "binfmttest\0",
+    "capture\0",
+    "clocktest\0",
+    "coretest\0",
+    "devtest\0",
+    "duptest\0",
+    "errno\0",
+    "exitgroup\0",
+    "filemaptest\0",
+    "forktest\0",
+    "futextest\0",
+    "heaptest\0",
+    "itimertest\0",
+    "mmaptest\0",
+    "mounttest\0",
+    "mprotecttest\0",
+    "mutextest\0",
+    "nice\0",
+    "orphan\0",
+    "pidtest\0",
+    "pipetest\0",
+    "polltest\0",
+    "procstress\0",
+    "randomtest\0",
+    "rlimittest\0",
+    "schedstattest\0",
+    "seektest\0",
+    "sendfiletest\0",
+    "shmtest\0",
+    "sigaltstacktest\0",
+    "sigtest\0",
+    "sleep\0",
+    "stackgrow\0",
+    "stattest\0",
+    "sysargtest\0",
+    "sysctltest\0",
+    "threadtest\0",
+    "timertest\0",
+    "truncatetest\0",
+    "vm_inspect\0",
+    "wildptr\0",
+];
+
+#[no_mangle]
+fn main() -> i32 {
+    let mut failed = 0;
+    for test in TESTS {
+        let name = test.trim_end_matches('\0');
+        println!("usertests: running {}", name);
+        let pid = fork();
+        if pid == 0 {
+            exec(test);
+            exit(-1);
+        }
+        let mut exit_code = 0;
+        if waitpid(pid, &mut exit_code) != pid || exit_code != 0 {
+            println!("usertests: {} FAILED, exit code {}", name, exit_code);
+            failed += 1;
+        }
+    }
+    println!("usertests: {} passed, {} failed", TESTS.len() - failed, failed);
+    if failed == 0 {
+        println!("usertests passed!");
+    }
+    failed as i32
+}
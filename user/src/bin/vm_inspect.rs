@@ -0,0 +1,37 @@
+#![no_std]
+#![no_main]
+
+use user::{mincore, println, vma_info, AreaInfo, PAGE_NOT_RESIDENT, PAGE_RESIDENT, PAGE_UNMAPPED};
+
+const PAGE_SIZE: usize = 4096;
+const ENOMEM: isize = 12;
+
+#[no_mangle]
+unsafe fn main() -> i32 {
+    let mut areas = [AreaInfo::default(); 16];
+    let total = vma_info(&mut areas);
+    assert!(total > 0);
+    println!("{} areas", total);
+
+    for area in areas.iter().take(total as usize) {
+        let pages = (area.end - area.start) / PAGE_SIZE;
+        let mut residency = [0u8; 16];
+        let checked = pages.min(residency.len());
+        assert_eq!(mincore(area.start, checked * PAGE_SIZE, &mut residency[..checked]), 0);
+        let resident = residency[..checked].iter().filter(|&&r| r == PAGE_RESIDENT).count();
+        println!(
+            "[{:#x}, {:#x}) perm {:#x} type {}: {}/{} resident",
+            area.start, area.end, area.perm, area.map_type, resident, checked
+        );
+    }
+
+    // more pages than the kernel looks up at a time
+    let mut residency = [0xffu8; 600];
+    assert_eq!(mincore(areas[0].start, residency.len() * PAGE_SIZE, &mut residency), 0);
+    assert!(residency.iter().all(|&r| [PAGE_RESIDENT, PAGE_NOT_RESIDENT, PAGE_UNMAPPED].contains(&r)));
+    // past the user half
+    assert_eq!(mincore(0, 1 << 46, &mut residency), -ENOMEM);
+
+    println!("vm_inspect OK!");
+    0
+}
@@ -0,0 +1,50 @@
+#![no_std]
+#![no_main]
+
+use core::slice;
+
+use user::{
+    check, close, exec, exit, fork, open, println, read, waitpid, write, Errno, O_RDONLY,
+};
+
+/// Never mapped, below the first program segment
+const UNMAPPED: usize = 0x10;
+/// The trampoline, mapped without `U`
+const KERNEL_ONLY: usize = 0xffff_ffff_bfff_f000;
+
+unsafe fn wild_bytes(addr: usize, len: usize) -> &'static mut [u8] {
+    slice::from_raw_parts_mut(addr as *mut u8, len)
+}
+
+unsafe fn wild_str(addr: usize) -> &'static str {
+    core::str::from_utf8_unchecked(wild_bytes(addr, 1))
+}
+
+#[no_mangle]
+unsafe fn main() -> i32 {
+    let efault = Err(Errno::EFAULT);
+
+    for addr in [UNMAPPED, KERNEL_ONLY] {
+        assert_eq!(check(write(1, wild_bytes(addr, 16))), efault);
+        assert_eq!(check(open(wild_str(addr), O_RDONLY)), efault);
+        assert_eq!(check(exec(wild_str(addr))), efault);
+    }
+
+    // mapped, but read-only: the kernel doesn't write to it either
+    let zero = open("/dev/zero\0", O_RDONLY) as usize;
+    let text = main as usize;
+    assert_eq!(check(read(zero, wild_bytes(text, 16))), efault);
+    assert_eq!(check(read(zero, wild_bytes(UNMAPPED, 16))), efault);
+    close(zero);
+
+    // the child is reaped even though its exit code can't be stored
+    let pid = fork();
+    if pid == 0 {
+        exit(0);
+    }
+    assert_eq!(check(waitpid(pid, &mut *(text as *mut i32))), efault);
+    assert_eq!(check(waitpid(pid, &mut 0)), Err(Errno::ECHILD));
+
+    println!("wildptr passed!");
+    0
+}
@@ -24,7 +24,7 @@ pub fn print(args : Arguments) {
 #[macro_export]
 macro_rules! print {
     ($fmt: literal $(, $($arg: tt)+)?) => {
-        $crate::console::print(format_args!($fmt $(, $($arg: tt)+)?));
+        $crate::console::print(format_args!($fmt $(, $($arg)+)?));
     };
 }
 
@@ -0,0 +1,171 @@
+//! The global allocator of user programs, and `malloc` / `free`
+//!
+//! Small blocks come from a first-fit free list over the heap, grown with
+//! `sbrk` when nothing fits and never shrunk. Blocks of `MMAP_THRESHOLD`
+//! bytes and more get mappings of their own, given back by `munmap` when
+//! freed.
+//!
+//! The allocator owns the program break once it grew it: a program that
+//! allocates must not move the break itself.
+
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    mem::size_of,
+    ptr::{self, null_mut},
+};
+
+use crate::{mmap, munmap, sbrk, sync::Mutex, MAP_ANONYMOUS, MAP_PRIVATE, PROT_READ, PROT_WRITE};
+
+const PAGE_SIZE: usize = 4096;
+/// Alignment, and size granule, of every block of the free list
+const ALIGN: usize = 16;
+/// Smallest growth of the heap
+const GROW_MIN: usize = 16 * PAGE_SIZE;
+/// Blocks from this size on are mapped on their own
+const MMAP_THRESHOLD: usize = 64 * 1024;
+
+/// A free range, linked at its start in address order
+struct FreeBlock {
+    size: usize,
+    next: *mut FreeBlock,
+}
+
+struct FreeList {
+    head: *mut FreeBlock,
+}
+
+// only reached through the lock
+unsafe impl Send for FreeList {}
+
+impl FreeList {
+    /// First fit: carve `size` bytes aligned to `align` out of a free
+    /// block, the rest of it stays free.
+    unsafe fn take(&mut self, size: usize, align: usize) -> Option<*mut u8> {
+        let mut link: *mut *mut FreeBlock = &mut self.head;
+        while !(*link).is_null() {
+            let block = *link;
+            let (start, end) = (block as usize, block as usize + (*block).size);
+            let aligned = (start + align - 1) & !(align - 1);
+            if aligned + size <= end {
+                // unlink it, give back what lies around the allocation
+                *link = (*block).next;
+                self.insert(start, aligned);
+                self.insert(aligned + size, end);
+                return Some(aligned as *mut u8);
+            }
+            link = &mut (*block).next;
+        }
+        None
+    }
+
+    /// Free `[start, end)`, merged with its neighbours.
+    unsafe fn insert(&mut self, start: usize, end: usize) {
+        if start >= end {
+            return;
+        }
+        // the last free block below `start`, and the first above
+        let mut prev: *mut FreeBlock = null_mut();
+        let mut next = self.head;
+        while !next.is_null() && (next as usize) < start {
+            prev = next;
+            next = (*next).next;
+        }
+
+        let block = start as *mut FreeBlock;
+        block.write(FreeBlock { size: end - start, next });
+        if !next.is_null() && next as usize == end {
+            (*block).size += (*next).size;
+            (*block).next = (*next).next;
+        }
+        if prev.is_null() {
+            self.head = block;
+        } else if prev as usize + (*prev).size == start {
+            (*prev).size += (*block).size;
+            (*prev).next = (*block).next;
+        } else {
+            (*prev).next = block;
+        }
+    }
+}
+
+struct UserHeap {
+    free: Mutex<FreeList>,
+}
+
+#[global_allocator]
+static HEAP: UserHeap = UserHeap { free: Mutex::new(FreeList { head: null_mut() }) };
+
+/// Size and alignment of the block backing `layout` in the free list
+fn block_layout(layout: Layout) -> (usize, usize) {
+    let size = layout.size().max(size_of::<FreeBlock>());
+    ((size + ALIGN - 1) & !(ALIGN - 1), layout.align().max(ALIGN))
+}
+
+fn is_mapped(layout: Layout) -> bool {
+    layout.size() >= MMAP_THRESHOLD && layout.align() <= PAGE_SIZE
+}
+
+unsafe impl GlobalAlloc for UserHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if is_mapped(layout) {
+            let start = mmap(0, layout.size(), PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS);
+            return if start < 0 { null_mut() } else { start as *mut u8 };
+        }
+        let (size, align) = block_layout(layout);
+        let mut free = self.free.lock();
+        if let Some(block) = free.take(size, align) {
+            return block;
+        }
+        // room for the block wherever the new range starts
+        let grow = (size + align).max(GROW_MIN).next_multiple_of(PAGE_SIZE);
+        let Some(start) = sbrk(grow as isize) else {
+            return null_mut();
+        };
+        let aligned = (start + ALIGN - 1) & !(ALIGN - 1);
+        free.insert(aligned, (start + grow) & !(ALIGN - 1));
+        free.take(size, align).unwrap_or(null_mut())
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if is_mapped(layout) {
+            munmap(ptr as usize, layout.size());
+            return;
+        }
+        let (size, _) = block_layout(layout);
+        self.free.lock().insert(ptr as usize, ptr as usize + size);
+    }
+}
+
+/// Bytes in front of a `malloc` block, holding its size
+const MALLOC_HEADER: usize = ALIGN;
+
+/// Allocate `size` bytes aligned to 16, null if there is no memory left.
+pub fn malloc(size: usize) -> *mut u8 {
+    let Some(total) = size.checked_add(MALLOC_HEADER) else {
+        return null_mut();
+    };
+    let Ok(layout) = Layout::from_size_align(total, ALIGN) else {
+        return null_mut();
+    };
+    unsafe {
+        let block = HEAP.alloc(layout);
+        if block.is_null() {
+            return null_mut();
+        }
+        (block as *mut usize).write(total);
+        block.add(MALLOC_HEADER)
+    }
+}
+
+/// Free a block of [`malloc`], null is ignored.
+///
+/// # Safety
+/// `ptr` is null or came from `malloc` and wasn't freed yet.
+pub unsafe fn free(ptr: *mut u8) {
+    if ptr.is_null() {
+        return;
+    }
+    let block = ptr.sub(MALLOC_HEADER);
+    let total = ptr::read(block as *const usize);
+    HEAP.dealloc(block, Layout::from_size_align_unchecked(total, ALIGN));
+}
@@ -2,19 +2,64 @@
 #![no_std]
 
 pub mod console;
+pub mod sync;
+mod heap;
 mod lang_items;
 mod syscall;
 
 use syscall::*;
 
+pub use heap::{free, malloc};
+
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+// the kernel passes `argc` and `argv` in `a0` and `a1`, the `envp` array
+// follows `argv` on the stack
+core::arch::global_asm!(
+    ".section .text.entry, \"ax\"",
+    ".globl _start",
+    "_start:",
+    "    call start_main",
+);
+
+static ARGC: AtomicUsize = AtomicUsize::new(0);
+static ARGV: AtomicPtr<*const u8> = AtomicPtr::new(core::ptr::null_mut());
+
 #[no_mangle]
-#[link_section = ".text.entry"]
-pub extern "C" fn _start() -> ! {
+extern "C" fn start_main(argc: usize, argv: *const *const u8) -> ! {
     // clear_bss();
+    ARGC.store(argc, Ordering::Relaxed);
+    ARGV.store(argv as *mut *const u8, Ordering::Relaxed);
     exit(main());
     panic!("unreacheable after sys_exit!");
 }
 
+/// The string at `ptr`, up to its NUL
+unsafe fn c_str(ptr: *const u8) -> &'static str {
+    let len = (0..).take_while(|&offset| *ptr.add(offset) != 0).count();
+    core::str::from_utf8(core::slice::from_raw_parts(ptr, len)).unwrap_or("")
+}
+
+/// The arguments the program was run with, `argv[0]` first.
+///
+/// `exec` runs a program with its path alone, `execve` with the given
+/// ones, a `#!` script runs its interpreter with the interpreter, its
+/// argument if any, and the script.
+pub fn args() -> impl Iterator<Item = &'static str> {
+    let argv = ARGV.load(Ordering::Relaxed);
+    (0..ARGC.load(Ordering::Relaxed)).map(move |idx| unsafe { c_str(*argv.add(idx)) })
+}
+
+/// The environment the program was run with, `KEY=value` strings, empty
+/// unless it was given to `execve`.
+pub fn env() -> impl Iterator<Item = &'static str> {
+    let envp = unsafe { ARGV.load(Ordering::Relaxed).add(ARGC.load(Ordering::Relaxed) + 1) };
+    (0..)
+        .map(move |idx| unsafe { *envp.add(idx) })
+        .take_while(|var| !var.is_null())
+        .map(|var| unsafe { c_str(var) })
+}
+
 
 #[linkage = "weak"] //need #![feature(linkage)]
 #[no_mangle]
@@ -38,18 +83,770 @@ pub fn write(fd: usize, buf: &[u8]) -> isize {
     sys_write(fd, buf)
 }
 
+pub fn read(fd: usize, buf: &mut [u8]) -> isize {
+    sys_read(fd, buf)
+}
+
+/// Move the offset of `fd`, returns the new offset.
+pub fn lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    sys_lseek(fd, offset, whence)
+}
+
+pub const SEEK_SET: usize = 0;
+pub const SEEK_CUR: usize = 1;
+pub const SEEK_END: usize = 2;
+
+/// Copy up to `count` bytes from `in_fd` to `out_fd` in the kernel,
+/// returns the number copied. Reads at `*offset` and moves it if given,
+/// else at the offset of `in_fd`.
+pub fn sendfile(out_fd: usize, in_fd: usize, offset: Option<&mut isize>, count: usize) -> isize {
+    sys_sendfile(out_fd, in_fd, offset.map_or(core::ptr::null_mut(), |offset| offset as *mut _), count)
+}
+
+pub fn close(fd: usize) -> isize {
+    sys_close(fd)
+}
+
+/// Duplicate `fd` onto the lowest free fd.
+pub fn dup(fd: usize) -> isize {
+    sys_dup(fd)
+}
+
+/// Make `new_fd` refer to the file of `old_fd`, closing it first if open.
+pub fn dup2(old_fd: usize, new_fd: usize) -> isize {
+    sys_dup2(old_fd, new_fd, 0)
+}
+
+/// `dup2`, with `O_CLOEXEC` allowed in `flags`.
+pub fn dup3(old_fd: usize, new_fd: usize, flags: u32) -> isize {
+    sys_dup2(old_fd, new_fd, flags)
+}
+
+/// Mount a file system of type `fstype` (`"easyfs"`, `"procfs"`, `"devfs"`) on `target`.
+///
+/// Both strings end with `\0`.
+pub fn mount(target: &str, fstype: &str) -> isize {
+    sys_mount("\0", target, fstype, 0)
+}
+
+/// Unmount the file system mounted on `target`, which ends with `\0`.
+pub fn umount(target: &str) -> isize {
+    sys_umount(target, 0)
+}
+
+/// `events` and `revents` bits of `poll`
+pub const POLLIN: i16 = 0x001;
+pub const POLLOUT: i16 = 0x004;
+pub const POLLNVAL: i16 = 0x020;
+
+/// `struct pollfd`
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(C)]
+pub struct PollFd {
+    pub fd: i32,
+    pub events: i16,
+    pub revents: i16,
+}
+
+impl PollFd {
+    pub fn new(fd: usize, events: i16) -> Self {
+        Self {
+            fd: fd as i32,
+            events,
+            revents: 0,
+        }
+    }
+}
+
+/// Wait until one of `fds` is ready, for at most `timeout_ms` milliseconds,
+/// forever if it is negative.
+///
+/// Returns how many have their `revents` set, 0 on timeout.
+pub fn poll(fds: &mut [PollFd], timeout_ms: isize) -> isize {
+    if timeout_ms < 0 {
+        return sys_ppoll(fds, core::ptr::null());
+    }
+    let timeout = TimeSpec {
+        tv_sec: timeout_ms as usize / 1000,
+        tv_nsec: timeout_ms as usize % 1000 * 1_000_000,
+    };
+    sys_ppoll(fds, &timeout)
+}
+
+/// Create a pipe, `pipe[0]` is the read end and `pipe[1]` the write end.
+pub fn pipe(pipe: &mut [usize; 2]) -> isize {
+    sys_pipe(pipe)
+}
+
+pub const O_RDONLY: u32 = 0;
+pub const O_WRONLY: u32 = 1 << 0;
+pub const O_RDWR: u32 = 1 << 1;
+pub const O_CREAT: u32 = 1 << 9;
+pub const O_TRUNC: u32 = 1 << 10;
+/// Every write goes to the end of the file
+pub const O_APPEND: u32 = 1 << 11;
+/// The fd is closed by `exec`
+pub const O_CLOEXEC: u32 = 1 << 19;
+
+/// Open `path`, which must end with a `\0`.
+pub fn open(path: &str, flags: u32) -> isize {
+    sys_open(path, flags)
+}
+
+/// Type bits of `Stat::st_mode`
+pub const S_IFMT: u32 = 0o170_000;
+pub const S_IFREG: u32 = 0o100_000;
+pub const S_IFDIR: u32 = 0o040_000;
+pub const S_IFCHR: u32 = 0o020_000;
+pub const S_IFIFO: u32 = 0o010_000;
+
+/// `struct stat`, as written by `fstat` and `stat`
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(C)]
+pub struct Stat {
+    pub st_dev: u64,
+    pub st_ino: u64,
+    pub st_mode: u32,
+    pub st_nlink: u32,
+    pub st_uid: u32,
+    pub st_gid: u32,
+    pub st_rdev: u64,
+    __pad1: u64,
+    pub st_size: i64,
+    pub st_blksize: i32,
+    __pad2: i32,
+    pub st_blocks: i64,
+    pub st_atime: TimeSpec,
+    pub st_mtime: TimeSpec,
+    pub st_ctime: TimeSpec,
+    __unused: [u32; 2],
+}
+
+pub fn ftruncate(fd: usize, len: usize) -> isize {
+    sys_ftruncate(fd, len)
+}
+
+pub fn fstat(fd: usize, stat: &mut Stat) -> isize {
+    sys_fstat(fd, stat)
+}
+
+/// The `Stat` of `path`, which must end with a `\0`.
+pub fn stat(path: &str, stat: &mut Stat) -> isize {
+    sys_stat(path, stat)
+}
+
+pub fn fork() -> isize {
+    sys_fork()
+}
+
+/// Start a thread running `entry(arg)` in this process, returns its tid.
+///
+/// The thread shares memory and fds with the caller, its stack is a
+/// single page. It exits with what `entry` returns.
+pub fn thread_create(entry: extern "C" fn(usize) -> i32, arg: usize) -> isize {
+    sys_thread_create(entry, arg)
+}
+
+/// Run the program at `path`, which must end with a `\0`: an ELF
+/// executable, a `#!` script or a flat binary.
+///
+/// Only returns on failure, with `-ENOENT` if there is no such program,
+/// `-ENOEXEC` if it can't run, `-ELOOP` if scripts interpret each other.
+pub fn exec(path: &str) -> isize {
+    sys_exec(path, core::ptr::null(), core::ptr::null())
+}
+
+/// Most strings `execve` passes in `argv` or `envp`
+pub const EXECVE_MAX_ARGS: usize = 16;
+
+/// Like [`exec`], the program gets `argv` and `envp`, whose strings must
+/// end with a `\0` too. Also `-E2BIG` if they are too long for its stack.
+///
+/// Panics past [`EXECVE_MAX_ARGS`] strings in either.
+pub fn execve(path: &str, argv: &[&str], envp: &[&str]) -> isize {
+    fn pointers(strings: &[&str]) -> [usize; EXECVE_MAX_ARGS + 1] {
+        assert!(strings.len() <= EXECVE_MAX_ARGS, "too many strings for execve");
+        // NULL past the last one
+        let mut array = [0; EXECVE_MAX_ARGS + 1];
+        for (pointer, string) in array.iter_mut().zip(strings) {
+            *pointer = string.as_ptr() as usize;
+        }
+        array
+    }
+    let (argv, envp) = (pointers(argv), pointers(envp));
+    sys_exec(path, argv.as_ptr(), envp.as_ptr())
+}
+
+pub const WNOHANG: usize = 1;
+
+/// Wait for child `pid` (-1 for any) to exit, returns its pid.
+///
+/// Sleeps in the kernel until it exits, `-ECHILD` if there is no such child.
+pub fn waitpid(pid: isize, exit_code: &mut i32) -> isize {
+    sys_waitpid(pid, exit_code as *mut i32, 0)
+}
+
+/// Like `waitpid`, but returns 0 instead of sleeping if no child exited yet.
+pub fn try_waitpid(pid: isize, exit_code: &mut i32) -> isize {
+    sys_waitpid(pid, exit_code as *mut i32, WNOHANG)
+}
+
+/// Capture the output of child `pid`, or of every future child if `pid` is 0.
+///
+/// Read it back from `/proc/<pid>/output`.
+pub fn capture_output(pid: usize) -> isize {
+    sys_capture_output(pid)
+}
+
 pub fn exit(exite_code: i32) ->! {
     sys_exit(exite_code)
 }
 
+/// Exit every thread of the process, which reports `exit_code`.
+pub fn exit_group(exit_code: i32) -> ! {
+    sys_exit_group(exit_code)
+}
+
 pub fn yield_() -> isize {
     sys_yield()
 }
 
+/// The pid of the process, shared by all of its threads
+pub fn getpid() -> isize {
+    sys_getpid()
+}
+
+/// The pid of the parent process, 0 for `initproc`
+pub fn getppid() -> isize {
+    sys_getppid()
+}
+
+/// The tid of the calling thread, the pid for the main thread
+pub fn gettid() -> isize {
+    sys_gettid()
+}
+
+/// Make system call `id` with `args` as they are, for tests passing the
+/// kernel what the wrappers never would.
+pub fn raw_syscall(id: usize, args: [usize; 6]) -> isize {
+    sys_raw(id, args)
+}
+
 pub fn get_time() -> isize {
     sys_get_time()
 }
 
+/// Sleep for at least `ms` milliseconds.
+pub fn sleep(ms: usize) -> isize {
+    nanosleep(&TimeSpec {
+        tv_sec: ms / 1000,
+        tv_nsec: ms % 1000 * 1_000_000,
+    })
+}
+
+/// Sleep for at least `duration`, with the resolution of the timer.
+pub fn nanosleep(duration: &TimeSpec) -> isize {
+    sys_nanosleep(duration, core::ptr::null_mut())
+}
+
+pub const CLOCK_REALTIME: usize = 0;
+pub const CLOCK_MONOTONIC: usize = 1;
+
+/// `struct timespec`, as written by `clock_gettime` and read by `nanosleep`
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(C)]
+pub struct TimeSpec {
+    pub tv_sec: usize,
+    pub tv_nsec: usize,
+}
+
+pub fn clock_gettime(clock: usize, tp: &mut TimeSpec) -> isize {
+    sys_clock_gettime(clock, tp)
+}
+
+/// `which` of `setitimer`, only `ITIMER_REAL` is supported
+pub const ITIMER_REAL: usize = 0;
+pub const ITIMER_VIRTUAL: usize = 1;
+pub const ITIMER_PROF: usize = 2;
+
+/// `struct timeval`
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(C)]
+pub struct TimeVal {
+    pub tv_sec: usize,
+    pub tv_usec: usize,
+}
+
+/// `struct itimerval`: the period, and the time to the next expiry
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[repr(C)]
+pub struct ITimerVal {
+    pub interval: TimeVal,
+    pub value: TimeVal,
+}
+
+pub fn getitimer(which: usize, curr: &mut ITimerVal) -> isize {
+    sys_getitimer(which, curr)
+}
+
+/// Arm the timer `which` to raise `SIGALRM` after `new.value`, then every
+/// `new.interval`, or disarm it for a zero `new.value`.
+pub fn setitimer(which: usize, new: &ITimerVal, old: Option<&mut ITimerVal>) -> isize {
+    sys_setitimer(which, new, old.map_or(core::ptr::null_mut(), |old| old as *mut _))
+}
+
+/// I/O scheduling classes, see `ioprio_set`
+pub const IOPRIO_CLASS_RT: usize = 1;
+pub const IOPRIO_CLASS_BE: usize = 2;
+pub const IOPRIO_CLASS_IDLE: usize = 3;
+pub const IOPRIO_WHO_PROCESS: usize = 1;
+
+/// Encode a class and a level (0 most urgent, 7 least) as an `ioprio` value.
+pub const fn ioprio_value(class: usize, level: usize) -> usize {
+    class << 13 | level
+}
+
+/// Set the I/O priority of task `who`, 0 for the caller.
+pub fn ioprio_set(who: usize, ioprio: usize) -> isize {
+    sys_ioprio_set(IOPRIO_WHO_PROCESS, who, ioprio)
+}
+
+pub fn ioprio_get(who: usize) -> isize {
+    sys_ioprio_get(IOPRIO_WHO_PROCESS, who)
+}
+
+const PRIO_PROCESS: usize = 0;
+
+/// Set the nice value (-20 highest to 19 lowest) of task `who`, 0 for the caller.
+pub fn setpriority(who: usize, nice: isize) -> isize {
+    sys_setpriority(PRIO_PROCESS, who, nice)
+}
+
+/// The nice value of task `who`, `None` if there is no such task.
+pub fn getpriority(who: usize) -> Option<isize> {
+    match sys_getpriority(PRIO_PROCESS, who) {
+        ret if ret < 0 => None,
+        ret => Some(20 - ret),
+    }
+}
+
+pub const SIGHUP: usize = 1;
+pub const SIGINT: usize = 2;
+pub const SIGQUIT: usize = 3;
+pub const SIGABRT: usize = 6;
+pub const SIGKILL: usize = 9;
+pub const SIGUSR1: usize = 10;
+pub const SIGSEGV: usize = 11;
+pub const SIGUSR2: usize = 12;
+pub const SIGPIPE: usize = 13;
+pub const SIGALRM: usize = 14;
+pub const SIGTERM: usize = 15;
+pub const SIGCHLD: usize = 17;
+pub const SIGSTOP: usize = 19;
+pub const SIGTSTP: usize = 20;
+
+/// `SignalAction::handler` values
+pub const SIG_DFL: usize = 0;
+pub const SIG_IGN: usize = 1;
+
+/// `how` values of `sigprocmask`
+pub const SIG_BLOCK: usize = 0;
+pub const SIG_UNBLOCK: usize = 1;
+pub const SIG_SETMASK: usize = 2;
+
+/// Disposition of a signal, see `sigaction`
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(C)]
+pub struct SignalAction {
+    /// `extern "C" fn(signum: usize)`, or `SIG_DFL` / `SIG_IGN`.
+    /// Returning from it resumes the interrupted code, like `sigreturn()`
+    pub handler: usize,
+    /// Signals blocked while the handler runs, bit `n` for signal `n`
+    pub mask: u32,
+    /// `SA_ONSTACK` or 0
+    pub flags: u32,
+}
+
+/// `SignalAction::flags`: run the handler on the alternate stack
+pub const SA_ONSTACK: u32 = 0x0800_0000;
+
+/// `SignalStack::flags`: running on the stack, as read back
+pub const SS_ONSTACK: i32 = 1;
+/// `SignalStack::flags`: no alternate stack
+pub const SS_DISABLE: i32 = 2;
+/// Smallest alternate stack
+pub const MINSIGSTKSZ: usize = 2048;
+
+/// An alternate signal stack, see `sigaltstack`
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(C)]
+pub struct SignalStack {
+    /// Lowest address of the stack
+    pub sp: usize,
+    pub flags: i32,
+    pub size: usize,
+}
+
+pub fn kill(pid: usize, signum: usize) -> isize {
+    sys_kill(pid, signum)
+}
+
+pub fn sigaction(
+    signum: usize,
+    action: Option<&SignalAction>,
+    old_action: Option<&mut SignalAction>,
+) -> isize {
+    sys_sigaction(
+        signum,
+        action.map_or(core::ptr::null(), |action| action as *const _),
+        old_action.map_or(core::ptr::null_mut(), |old_action| old_action as *mut _),
+    )
+}
+
+/// Set the stack the handlers installed with `SA_ONSTACK` run on, and/or
+/// get the previous one.
+///
+/// `-EPERM` while running on it, `-ENOMEM` if it is smaller than
+/// `MINSIGSTKSZ`, `-EFAULT` if it is not mapped writable.
+pub fn sigaltstack(stack: Option<&SignalStack>, old_stack: Option<&mut SignalStack>) -> isize {
+    sys_sigaltstack(
+        stack.map_or(core::ptr::null(), |stack| stack as *const _),
+        old_stack.map_or(core::ptr::null_mut(), |old_stack| old_stack as *mut _),
+    )
+}
+
+pub fn sigprocmask(how: usize, set: Option<u32>, old_set: Option<&mut u32>) -> isize {
+    let set = set.as_ref().map_or(core::ptr::null(), |set| set as *const u32);
+    sys_sigprocmask(
+        how,
+        set,
+        old_set.map_or(core::ptr::null_mut(), |old_set| old_set as *mut u32),
+    )
+}
+
+/// Must end every signal handler
+pub fn sigreturn() -> isize {
+    sys_sigreturn()
+}
+
+/// Residency of a page, as written by `mincore`
+pub const PAGE_UNMAPPED: u8 = 0;
+pub const PAGE_RESIDENT: u8 = 1;
+pub const PAGE_NOT_RESIDENT: u8 = 2;
+pub const PAGE_SWAPPED: u8 = 3;
+
+/// One area of the address space, as written by `vma_info`
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(C)]
+pub struct AreaInfo {
+    pub start: usize,
+    pub end: usize,
+    /// R = 1 << 1, W = 1 << 2, X = 1 << 3, U = 1 << 4
+    pub perm: usize,
+    /// 0 identical, 1 framed, 2 lazy, 3 shared, 4 file
+    pub map_type: usize,
+}
+
+/// Move the program break to `addr`, returns the new break.
+///
+/// On failure the break doesn't move and the old one is returned,
+/// `brk(0)` gives the current break. The global allocator grows the
+/// break too, a program that allocates must leave it alone.
+pub fn brk(addr: usize) -> usize {
+    sys_brk(addr) as usize
+}
+
+/// Grow the heap by `increment` bytes, returns the old break or `None` if it can't grow.
+pub fn sbrk(increment: isize) -> Option<usize> {
+    let old = brk(0);
+    let new = old.checked_add_signed(increment)?;
+    (brk(new) == new).then_some(old)
+}
+
+pub const PROT_READ: usize = 0x1;
+pub const PROT_WRITE: usize = 0x2;
+pub const PROT_EXEC: usize = 0x4;
+
+pub const MAP_PRIVATE: usize = 0x02;
+pub const MAP_FIXED: usize = 0x10;
+pub const MAP_ANONYMOUS: usize = 0x20;
+
+/// Map `len` bytes of anonymous memory, returns its address or `-errno`.
+///
+/// `flags` must include `MAP_PRIVATE | MAP_ANONYMOUS`.
+pub fn mmap(addr: usize, len: usize, prot: usize, flags: usize) -> isize {
+    sys_mmap(addr, len, prot, flags, usize::MAX, 0)
+}
+
+/// Map `len` bytes of the file open as `fd` from `offset` on, returns
+/// the address or `-errno`.
+///
+/// `flags` must include `MAP_PRIVATE`: writes never reach the file.
+pub fn mmap_file(addr: usize, len: usize, prot: usize, flags: usize, fd: usize, offset: usize) -> isize {
+    sys_mmap(addr, len, prot, flags, fd, offset)
+}
+
+pub fn munmap(addr: usize, len: usize) -> isize {
+    sys_munmap(addr, len)
+}
+
+pub const IPC_PRIVATE: usize = 0;
+pub const IPC_CREAT: usize = 0o1000;
+pub const IPC_EXCL: usize = 0o2000;
+pub const SHM_RDONLY: usize = 0o10000;
+pub const IPC_RMID: usize = 0;
+
+/// Get the id of the shared memory segment of `key`, creating one of
+/// `size` bytes with `IPC_CREAT`. `IPC_PRIVATE` always creates one.
+pub fn shmget(key: usize, size: usize, shmflg: usize) -> isize {
+    sys_shmget(key, size, shmflg)
+}
+
+/// Attach the segment `shmid` at `addr` (anywhere if 0), returns where.
+pub fn shmat(shmid: usize, addr: usize, shmflg: usize) -> isize {
+    sys_shmat(shmid, addr, shmflg)
+}
+
+/// Detach the segment attached at `addr`.
+pub fn shmdt(addr: usize) -> isize {
+    sys_shmdt(addr)
+}
+
+/// Only `IPC_RMID`: the segment goes once every task detached it.
+pub fn shmctl(shmid: usize, cmd: usize) -> isize {
+    sys_shmctl(shmid, cmd)
+}
+
+/// Change the protection of the pages of `[addr, addr + len)` to `prot`.
+///
+/// `addr` must be page aligned, every page in the range mapped.
+pub fn mprotect(addr: usize, len: usize, prot: usize) -> isize {
+    sys_mprotect(addr, len, prot)
+}
+
+pub const FUTEX_WAIT: usize = 0;
+pub const FUTEX_WAKE: usize = 1;
+
+/// Sleep while `*word == expected`, returns 0 once woken or `-EAGAIN`
+/// if the word already changed.
+pub fn futex_wait(word: &core::sync::atomic::AtomicU32, expected: u32) -> isize {
+    sys_futex(word.as_ptr(), FUTEX_WAIT, expected as usize, core::ptr::null())
+}
+
+/// Like [`futex_wait`], but gives up with `-ETIMEDOUT` after `timeout`.
+pub fn futex_wait_timeout(word: &core::sync::atomic::AtomicU32, expected: u32, timeout: &TimeSpec) -> isize {
+    sys_futex(word.as_ptr(), FUTEX_WAIT, expected as usize, timeout)
+}
+
+/// Wake at most `count` tasks sleeping on `word`, returns how many were woken.
+pub fn futex_wake(word: &core::sync::atomic::AtomicU32, count: usize) -> isize {
+    sys_futex(word.as_ptr(), FUTEX_WAKE, count, core::ptr::null())
+}
+
+/// Fill `vec` with the residency of each page from `addr` (page aligned) on.
+pub fn mincore(addr: usize, len: usize, vec: &mut [u8]) -> isize {
+    sys_mincore(addr, len, vec)
+}
+
+/// Fill `buf` with the areas of the address space, returns the total number of areas.
+pub fn vma_info(buf: &mut [AreaInfo]) -> isize {
+    sys_vma_info(buf)
+}
+
+/// Scheduling counters of a task, as written by `sched_stat`
+#[derive(Copy, Clone, Debug, Default)]
+#[repr(C)]
+pub struct SchedStat {
+    /// Time spent running, in ns
+    pub run_ns: u64,
+    /// Time spent ready in a run queue, in ns
+    pub wait_ns: u64,
+    /// Times switched in
+    pub switches: u64,
+    /// Times switched out asleep
+    pub voluntary_switches: u64,
+    /// Times switched out still runnable: preempted, or yielding
+    pub involuntary_switches: u64,
+}
+
+/// Store the scheduling counters of task `tid` (0 for the caller) in `stat`.
+///
+/// `-ESRCH` if there is no such task.
+pub fn sched_stat(tid: usize, stat: &mut SchedStat) -> isize {
+    sys_sched_stat(tid, stat as *mut SchedStat)
+}
+
+/// Let task `pid` (0 for the caller) run only on the harts of `mask`, bit
+/// `n` for hart `n`.
+///
+/// `-EINVAL` if `mask` has no online hart, `-ESRCH` if there is no such task.
+pub fn sched_setaffinity(pid: usize, mask: usize) -> isize {
+    sys_sched_setaffinity(pid, core::mem::size_of::<usize>(), &mask)
+}
+
+/// The harts task `pid` (0 for the caller) may run on, `None` if there is
+/// no such task.
+pub fn sched_getaffinity(pid: usize) -> Option<usize> {
+    let mut mask = 0;
+    (sys_sched_getaffinity(pid, core::mem::size_of::<usize>(), &mut mask) > 0).then_some(mask)
+}
+
+/// The hart the caller runs on
+pub fn getcpu() -> usize {
+    let mut cpu = 0;
+    sys_getcpu(&mut cpu, core::ptr::null_mut());
+    cpu as usize
+}
+
+/// An error code returned by a syscall, as `-errno`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Errno(pub isize);
+
+impl Errno {
+    pub const EPERM: Self = Self(1);
+    pub const ENOENT: Self = Self(2);
+    pub const ESRCH: Self = Self(3);
+    pub const ENOEXEC: Self = Self(8);
+    pub const EBADF: Self = Self(9);
+    pub const ECHILD: Self = Self(10);
+    pub const EAGAIN: Self = Self(11);
+    pub const ENOMEM: Self = Self(12);
+    pub const EACCES: Self = Self(13);
+    pub const EFAULT: Self = Self(14);
+    pub const EINVAL: Self = Self(22);
+    pub const EMFILE: Self = Self(24);
+    pub const ELOOP: Self = Self(40);
+}
+
+pub type SysResult = Result<usize, Errno>;
+
+/// Split a raw syscall return value into a result, every syscall fails
+/// with `-errno`.
+///
+/// ```ignore
+/// match check(open("/nothing\0", O_RDONLY)) {
+///     Ok(fd) => ...,
+///     Err(Errno::ENOENT) => ...,
+///     Err(err) => perror("open", err),
+/// }
+/// ```
+pub fn check(ret: isize) -> SysResult {
+    if ret < 0 {
+        Err(Errno(-ret))
+    } else {
+        Ok(ret as usize)
+    }
+}
+
+impl core::fmt::Display for Errno {
+    /// Prints the kernel's message for this code, e.g. "Bad file descriptor (errno 9)".
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let mut buf = [0u8; 64];
+        let len = sys_strerror(self.0, &mut buf);
+        let message = (len >= 0)
+            .then(|| core::str::from_utf8(&buf[..(len as usize).min(buf.len())]).ok())
+            .flatten();
+        match message {
+            Some(message) => write!(f, "{} (errno {})", message, self.0),
+            None => write!(f, "Unknown error {}", self.0),
+        }
+    }
+}
+
+/// Copy the kernel's message for `errno` into `buf`, returns the full message length.
+pub fn strerror(errno: isize, buf: &mut [u8]) -> isize {
+    sys_strerror(errno, buf)
+}
+
+/// `action` of `klog`, like Linux `syslog`
+pub const SYSLOG_ACTION_READ: usize = 2;
+pub const SYSLOG_ACTION_READ_ALL: usize = 3;
+pub const SYSLOG_ACTION_READ_CLEAR: usize = 4;
+pub const SYSLOG_ACTION_CLEAR: usize = 5;
+pub const SYSLOG_ACTION_CONSOLE_LEVEL: usize = 8;
+pub const SYSLOG_ACTION_SIZE_UNREAD: usize = 9;
+pub const SYSLOG_ACTION_SIZE_BUFFER: usize = 10;
+
+/// Read (or clear) the kernel log ring into `buf`, like C's `klogctl`.
+pub fn klogctl(action: usize, buf: &mut [u8]) -> isize {
+    sys_klog(action, buf.as_mut_ptr(), buf.len())
+}
+
+/// Set the kernel log level, from 0 (off) to 5 (trace), of the modules
+/// under `prefix` (NUL-terminated), of all the others if `None`.
+pub fn klog_set_level(prefix: Option<&str>, level: usize) -> isize {
+    let prefix = prefix.map_or(core::ptr::null_mut(), |prefix| prefix.as_ptr() as *mut u8);
+    sys_klog(SYSLOG_ACTION_CONSOLE_LEVEL, prefix, level)
+}
+
+/// Read the kernel parameter `name` (NUL-terminated, e.g.
+/// `"kernel.sched_time_slice_ms\0"`) into `old`, then set it to `new`.
+pub fn sysctl(name: &str, old: Option<&mut usize>, new: Option<usize>) -> isize {
+    let old = old.map_or(core::ptr::null_mut(), |old| old as *mut usize);
+    let new = new.as_ref().map_or(core::ptr::null(), |new| new as *const usize);
+    sys_sysctl(name.as_ptr(), old, new)
+}
+
+/// `resource` of `getrlimit`/`setrlimit`
+pub const RLIMIT_STACK: usize = 3;
+pub const RLIMIT_CORE: usize = 4;
+pub const RLIMIT_NOFILE: usize = 7;
+pub const RLIMIT_AS: usize = 9;
+pub const RLIM_INFINITY: usize = usize::MAX;
+
+/// `struct rlimit`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RLimit {
+    /// Soft limit, the one enforced
+    pub cur: usize,
+    /// Hard limit, the ceiling of `cur`, which can only be lowered
+    pub max: usize,
+}
+
+/// Get the limit of `resource` of the process `pid` (0 for this one) into
+/// `old`, then set it to `new`.
+pub fn prlimit(pid: usize, resource: usize, new: Option<&RLimit>, old: Option<&mut RLimit>) -> isize {
+    let new = new.map_or(core::ptr::null(), |new| new as *const RLimit);
+    let old = old.map_or(core::ptr::null_mut(), |old| old as *mut RLimit);
+    sys_prlimit(pid, resource, new, old)
+}
+
+/// `flags` of `getrandom`, accepted but without effect: it never blocks
+pub const GRND_NONBLOCK: u32 = 0x1;
+pub const GRND_RANDOM: u32 = 0x2;
+
+/// Fill `buf` with random bytes, returns how many or `-errno`.
+///
+/// Not for keys: the kernel generator isn't a cryptographic one.
+pub fn getrandom(buf: &mut [u8], flags: u32) -> isize {
+    sys_getrandom(buf, flags)
+}
+
+/// Flag of `personality`: the next `exec` doesn't randomize the layout
+pub const ADDR_NO_RANDOMIZE: u32 = 0x0040000;
+/// `personality` that only queries the current one
+pub const PERSONALITY_QUERY: u32 = 0xffff_ffff;
+
+/// Set the personality, returns the previous one or `-errno`.
+pub fn personality(persona: u32) -> isize {
+    sys_personality(persona)
+}
+
+pub fn getrlimit(resource: usize) -> Option<RLimit> {
+    let mut limit = RLimit::default();
+    (prlimit(0, resource, None, Some(&mut limit)) == 0).then_some(limit)
+}
+
+pub fn setrlimit(resource: usize, limit: RLimit) -> isize {
+    prlimit(0, resource, Some(&limit), None)
+}
+
+/// Print `prefix: message` for a failed syscall result, like C's `perror`.
+pub fn perror(prefix: &str, err: Errno) {
+    crate::println!("{}: {}", prefix, err);
+}
+
 pub fn test_syscall(buf: &[u8]) -> isize {
     sys_test(buf.as_ptr() as usize, buf.len())
 }
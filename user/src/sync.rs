@@ -0,0 +1,175 @@
+//! Blocking locks on top of `futex`
+//!
+//! The uncontended paths are plain atomics, the kernel is only called to
+//! sleep on a contended lock, or to wake someone sleeping on it.
+//!
+//! A contended [`Mutex`] is adaptive: it first spins [`SPIN_LIMIT`] times,
+//! for a holder running on another hart, then yields the hart
+//! [`YIELD_LIMIT`] times, for one waiting for it, and only then sleeps on
+//! the futex. What a lock went through is counted in its [`MutexStats`].
+
+use core::{
+    cell::UnsafeCell,
+    ops::{Deref, DerefMut},
+    hint,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use crate::{futex_wait, futex_wake, yield_};
+
+const UNLOCKED: u32 = 0;
+const LOCKED: u32 = 1;
+/// Locked, and someone may be sleeping on it
+const CONTENDED: u32 = 2;
+
+/// Looks at a taken lock before yielding
+pub const SPIN_LIMIT: u32 = 100;
+/// Yields on a taken lock before sleeping on it
+pub const YIELD_LIMIT: u32 = 4;
+
+pub struct Mutex<T> {
+    state: AtomicU32,
+    contended: AtomicU32,
+    yields: AtomicU32,
+    sleeps: AtomicU32,
+    data: UnsafeCell<T>,
+}
+
+/// Contention of a [`Mutex`] since it was made
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct MutexStats {
+    /// Locks which found it taken
+    pub contended: u32,
+    /// Times a waiter yielded the hart
+    pub yields: u32,
+    /// Times a waiter slept on the futex
+    pub sleeps: u32,
+}
+
+unsafe impl<T: Send> Sync for Mutex<T> {}
+unsafe impl<T: Send> Send for Mutex<T> {}
+
+pub struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<T> Mutex<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            state: AtomicU32::new(UNLOCKED),
+            contended: AtomicU32::new(0),
+            yields: AtomicU32::new(0),
+            sleeps: AtomicU32::new(0),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        if !self.try_acquire() {
+            self.lock_contended();
+        }
+        MutexGuard { mutex: self }
+    }
+
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.try_acquire().then(|| MutexGuard { mutex: self })
+    }
+
+    pub fn stats(&self) -> MutexStats {
+        MutexStats {
+            contended: self.contended.load(Ordering::Relaxed),
+            yields: self.yields.load(Ordering::Relaxed),
+            sleeps: self.sleeps.load(Ordering::Relaxed),
+        }
+    }
+
+    fn try_acquire(&self) -> bool {
+        self.state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    #[cold]
+    fn lock_contended(&self) {
+        self.contended.fetch_add(1, Ordering::Relaxed);
+        for _ in 0..SPIN_LIMIT {
+            hint::spin_loop();
+            if self.state.load(Ordering::Relaxed) == UNLOCKED && self.try_acquire() {
+                return;
+            }
+        }
+        for _ in 0..YIELD_LIMIT {
+            self.yields.fetch_add(1, Ordering::Relaxed);
+            yield_();
+            if self.try_acquire() {
+                return;
+            }
+        }
+        // once marked contended, whoever unlocks has to wake us up
+        while self.state.swap(CONTENDED, Ordering::Acquire) != UNLOCKED {
+            self.sleeps.fetch_add(1, Ordering::Relaxed);
+            futex_wait(&self.state, CONTENDED);
+        }
+    }
+
+    fn unlock(&self) {
+        if self.state.swap(UNLOCKED, Ordering::Release) == CONTENDED {
+            futex_wake(&self.state, 1);
+        }
+    }
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+/// A condition variable, used with a [`Mutex`]
+pub struct Condvar {
+    /// Bumped by every notify, a waiter only sleeps if it hasn't moved
+    sequence: AtomicU32,
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self {
+            sequence: AtomicU32::new(0),
+        }
+    }
+
+    /// Release the lock, sleep until notified, then take the lock again.
+    ///
+    /// May return without a notify, callers check their condition in a loop.
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let seen = self.sequence.load(Ordering::Relaxed);
+        let mutex = guard.mutex;
+        drop(guard);
+        futex_wait(&self.sequence, seen);
+        mutex.lock()
+    }
+
+    pub fn notify_one(&self) {
+        self.sequence.fetch_add(1, Ordering::Relaxed);
+        futex_wake(&self.sequence, 1);
+    }
+
+    pub fn notify_all(&self) {
+        self.sequence.fetch_add(1, Ordering::Relaxed);
+        futex_wake(&self.sequence, usize::MAX);
+    }
+}
@@ -1,9 +1,68 @@
 use core::arch::asm;
 
+/// `dirfd` of the `*at` calls for the working directory
+const AT_FDCWD: isize = -100;
+
+const SYSCALL_IOPRIO_SET: usize = 30;
+const SYSCALL_IOPRIO_GET: usize = 31;
+const SYSCALL_UMOUNT: usize = 39;
+const SYSCALL_MOUNT: usize = 40;
+const SYSCALL_FTRUNCATE: usize = 46;
+const SYSCALL_OPEN: usize = 56;
+const SYSCALL_CLOSE: usize = 57;
+const SYSCALL_DUP: usize = 23;
+const SYSCALL_DUP2: usize = 24;
+const SYSCALL_PIPE: usize = 59;
+const SYSCALL_LSEEK: usize = 62;
+const SYSCALL_READ: usize = 63;
 const SYSCALL_WRITE: usize = 64;
+const SYSCALL_SENDFILE: usize = 71;
+const SYSCALL_PPOLL: usize = 73;
+const SYSCALL_NEWFSTATAT: usize = 79;
+const SYSCALL_FSTAT: usize = 80;
+const SYSCALL_PERSONALITY: usize = 92;
 const SYSCALL_EXIT: usize = 93;
+const SYSCALL_EXIT_GROUP: usize = 94;
+const SYSCALL_FUTEX: usize = 98;
+const SYSCALL_NANOSLEEP: usize = 101;
+const SYSCALL_GETITIMER: usize = 102;
+const SYSCALL_SETITIMER: usize = 103;
+const SYSCALL_CLOCK_GETTIME: usize = 113;
+const SYSCALL_SYSLOG: usize = 116;
+const SYSCALL_SCHED_SETAFFINITY: usize = 122;
+const SYSCALL_SCHED_GETAFFINITY: usize = 123;
 const SYSCALL_YIELD: usize = 124;
+const SYSCALL_KILL: usize = 129;
+const SYSCALL_SIGALTSTACK: usize = 132;
+const SYSCALL_SIGACTION: usize = 134;
+const SYSCALL_SIGPROCMASK: usize = 135;
+const SYSCALL_SIGRETURN: usize = 139;
+const SYSCALL_SETPRIORITY: usize = 140;
+const SYSCALL_GETPRIORITY: usize = 141;
+const SYSCALL_GETCPU: usize = 168;
 const SYSCALL_GET_TIME: usize = 169;
+const SYSCALL_GETPID: usize = 172;
+const SYSCALL_GETPPID: usize = 173;
+const SYSCALL_GETTID: usize = 178;
+const SYSCALL_SHMGET: usize = 194;
+const SYSCALL_SHMCTL: usize = 195;
+const SYSCALL_SHMAT: usize = 196;
+const SYSCALL_SHMDT: usize = 197;
+const SYSCALL_BRK: usize = 214;
+const SYSCALL_MUNMAP: usize = 215;
+const SYSCALL_CLONE: usize = 220;
+const SYSCALL_EXEC: usize = 221;
+const SYSCALL_MMAP: usize = 222;
+const SYSCALL_MPROTECT: usize = 226;
+const SYSCALL_MINCORE: usize = 232;
+const SYSCALL_WAITPID: usize = 260;
+const SYSCALL_PRLIMIT: usize = 261;
+const SYSCALL_GETRANDOM: usize = 278;
+const SYSCALL_VMA_INFO: usize = 512;
+const SYSCALL_STRERROR: usize = 513;
+const SYSCALL_CAPTURE_OUTPUT: usize = 514;
+const SYSCALL_SYSCTL: usize = 515;
+const SYSCALL_SCHED_STAT: usize = 516;
 
 const SYSCALL_TEST: usize = 114514;
 
@@ -15,6 +74,9 @@ fn syscall(id: usize, args: [usize; 6]) -> isize {
             inlateout("x10") args[0] => ret,
             in("x11") args[1],
             in("x12") args[2],
+            in("x13") args[3],
+            in("x14") args[4],
+            in("x15") args[5],
             in("x17") id
         );
     }
@@ -27,6 +89,57 @@ pub fn sys_write(fd: usize, buffer: &[u8]) -> isize {
     syscall(SYSCALL_WRITE, [fd, buffer.as_ptr() as usize, buffer.len(), 0, 0, 0])
 }
 
+pub fn sys_read(fd: usize, buffer: &mut [u8]) -> isize {
+    syscall(SYSCALL_READ, [fd, buffer.as_mut_ptr() as usize, buffer.len(), 0, 0, 0])
+}
+
+pub fn sys_lseek(fd: usize, offset: isize, whence: usize) -> isize {
+    syscall(SYSCALL_LSEEK, [fd, offset as usize, whence, 0, 0, 0])
+}
+
+pub fn sys_sendfile(out_fd: usize, in_fd: usize, offset: *mut isize, count: usize) -> isize {
+    syscall(SYSCALL_SENDFILE, [out_fd, in_fd, offset as usize, count, 0, 0])
+}
+
+pub fn sys_ftruncate(fd: usize, len: usize) -> isize {
+    syscall(SYSCALL_FTRUNCATE, [fd, len, 0, 0, 0, 0])
+}
+
+pub fn sys_fstat(fd: usize, stat: &mut crate::Stat) -> isize {
+    syscall(SYSCALL_FSTAT, [fd, stat as *mut _ as usize, 0, 0, 0, 0])
+}
+
+pub fn sys_stat(path: &str, stat: &mut crate::Stat) -> isize {
+    syscall(SYSCALL_NEWFSTATAT, [AT_FDCWD as usize, path.as_ptr() as usize, stat as *mut _ as usize, 0, 0, 0])
+}
+
+pub fn sys_close(fd: usize) -> isize {
+    syscall(SYSCALL_CLOSE, [fd, 0, 0, 0, 0, 0])
+}
+
+pub fn sys_dup(fd: usize) -> isize {
+    syscall(SYSCALL_DUP, [fd, 0, 0, 0, 0, 0])
+}
+
+pub fn sys_dup2(old_fd: usize, new_fd: usize, flags: u32) -> isize {
+    syscall(SYSCALL_DUP2, [old_fd, new_fd, flags as usize, 0, 0, 0])
+}
+
+pub fn sys_mount(source: &str, target: &str, fstype: &str, flags: usize) -> isize {
+    syscall(
+        SYSCALL_MOUNT,
+        [source.as_ptr() as usize, target.as_ptr() as usize, fstype.as_ptr() as usize, flags, 0, 0],
+    )
+}
+
+pub fn sys_umount(target: &str, flags: usize) -> isize {
+    syscall(SYSCALL_UMOUNT, [target.as_ptr() as usize, flags, 0, 0, 0, 0])
+}
+
+pub fn sys_pipe(pipe: &mut [usize; 2]) -> isize {
+    syscall(SYSCALL_PIPE, [pipe.as_mut_ptr() as usize, 0, 0, 0, 0, 0])
+}
+
 
 
 pub fn sys_exit(exit_code: i32) -> ! {
@@ -39,6 +152,11 @@ pub fn sys_exit(exit_code: i32) -> ! {
     unreachable!()
 }
 
+pub fn sys_exit_group(exit_code: i32) -> ! {
+    syscall(SYSCALL_EXIT_GROUP, [exit_code as usize, 0, 0, 0, 0, 0]);
+    unreachable!()
+}
+
 pub fn sys_yield() -> isize {
     let args = [0; 6];
     syscall(SYSCALL_YIELD, args)
@@ -49,6 +167,214 @@ pub fn sys_get_time() -> isize {
     syscall(SYSCALL_GET_TIME, args)
 }
 
+pub fn sys_ppoll(fds: &mut [crate::PollFd], timeout: *const crate::TimeSpec) -> isize {
+    syscall(SYSCALL_PPOLL, [fds.as_mut_ptr() as usize, fds.len(), timeout as usize, 0, 0, 0])
+}
+
+pub fn sys_clock_gettime(clock: usize, tp: &mut crate::TimeSpec) -> isize {
+    syscall(SYSCALL_CLOCK_GETTIME, [clock, tp as *mut _ as usize, 0, 0, 0, 0])
+}
+
+pub fn sys_kill(pid: usize, signum: usize) -> isize {
+    syscall(SYSCALL_KILL, [pid, signum, 0, 0, 0, 0])
+}
+
+pub fn sys_sigaction(
+    signum: usize,
+    action: *const crate::SignalAction,
+    old_action: *mut crate::SignalAction,
+) -> isize {
+    syscall(SYSCALL_SIGACTION, [signum, action as usize, old_action as usize, 0, 0, 0])
+}
+
+pub fn sys_sigaltstack(stack: *const crate::SignalStack, old_stack: *mut crate::SignalStack) -> isize {
+    syscall(SYSCALL_SIGALTSTACK, [stack as usize, old_stack as usize, 0, 0, 0, 0])
+}
+
+pub fn sys_sigprocmask(how: usize, set: *const u32, old_set: *mut u32) -> isize {
+    syscall(SYSCALL_SIGPROCMASK, [how, set as usize, old_set as usize, 0, 0, 0])
+}
+
+pub fn sys_sigreturn() -> isize {
+    syscall(SYSCALL_SIGRETURN, [0; 6])
+}
+
+pub fn sys_ioprio_set(which: usize, who: usize, ioprio: usize) -> isize {
+    syscall(SYSCALL_IOPRIO_SET, [which, who, ioprio, 0, 0, 0])
+}
+
+pub fn sys_ioprio_get(which: usize, who: usize) -> isize {
+    syscall(SYSCALL_IOPRIO_GET, [which, who, 0, 0, 0, 0])
+}
+
+pub fn sys_setpriority(which: usize, who: usize, nice: isize) -> isize {
+    syscall(SYSCALL_SETPRIORITY, [which, who, nice as usize, 0, 0, 0])
+}
+
+pub fn sys_getpriority(which: usize, who: usize) -> isize {
+    syscall(SYSCALL_GETPRIORITY, [which, who, 0, 0, 0, 0])
+}
+
+pub fn sys_getpid() -> isize {
+    syscall(SYSCALL_GETPID, [0; 6])
+}
+
+pub fn sys_getppid() -> isize {
+    syscall(SYSCALL_GETPPID, [0; 6])
+}
+
+pub fn sys_gettid() -> isize {
+    syscall(SYSCALL_GETTID, [0; 6])
+}
+
+pub fn sys_open(path: &str, flags: u32) -> isize {
+    syscall(SYSCALL_OPEN, [path.as_ptr() as usize, flags as usize, 0, 0, 0, 0])
+}
+
+pub fn sys_fork() -> isize {
+    syscall(SYSCALL_CLONE, [0; 6])
+}
+
+const CLONE_VM: usize = 0x100;
+const CLONE_THREAD: usize = 0x10000;
+
+/// `clone(CLONE_VM | CLONE_THREAD, 0)`: the thread starts on a fresh stack
+/// of its own, calls `entry(arg)` and exits with what it returns.
+pub fn sys_thread_create(entry: extern "C" fn(usize) -> i32, arg: usize) -> isize {
+    let ret: isize;
+    unsafe {
+        asm!(
+            "ecall",
+            // the thread gets 0, and every other register of ours
+            "bnez a0, 1f",
+            "mv a0, s3",
+            "jalr s2",
+            "li a7, {exit}",
+            "ecall",
+            "1:",
+            exit = const SYSCALL_EXIT,
+            inlateout("x10") CLONE_VM | CLONE_THREAD => ret,
+            in("x11") 0,
+            in("x17") SYSCALL_CLONE,
+            in("x18") entry,
+            in("x19") arg,
+        );
+    }
+    ret
+}
+
+pub fn sys_exec(path: &str, argv: *const usize, envp: *const usize) -> isize {
+    syscall(SYSCALL_EXEC, [path.as_ptr() as usize, argv as usize, envp as usize, 0, 0, 0])
+}
+
+pub fn sys_waitpid(pid: isize, exit_code: *mut i32, options: usize) -> isize {
+    syscall(SYSCALL_WAITPID, [pid as usize, exit_code as usize, options, 0, 0, 0])
+}
+
+pub fn sys_capture_output(pid: usize) -> isize {
+    syscall(SYSCALL_CAPTURE_OUTPUT, [pid, 0, 0, 0, 0, 0])
+}
+
+pub fn sys_nanosleep(req: &crate::TimeSpec, rem: *mut crate::TimeSpec) -> isize {
+    syscall(SYSCALL_NANOSLEEP, [req as *const _ as usize, rem as usize, 0, 0, 0, 0])
+}
+
+pub fn sys_getitimer(which: usize, curr: &mut crate::ITimerVal) -> isize {
+    syscall(SYSCALL_GETITIMER, [which, curr as *mut _ as usize, 0, 0, 0, 0])
+}
+
+pub fn sys_setitimer(which: usize, new: &crate::ITimerVal, old: *mut crate::ITimerVal) -> isize {
+    syscall(SYSCALL_SETITIMER, [which, new as *const _ as usize, old as usize, 0, 0, 0])
+}
+
+pub fn sys_brk(addr: usize) -> isize {
+    syscall(SYSCALL_BRK, [addr, 0, 0, 0, 0, 0])
+}
+
+pub fn sys_mmap(addr: usize, len: usize, prot: usize, flags: usize, fd: usize, offset: usize) -> isize {
+    syscall(SYSCALL_MMAP, [addr, len, prot, flags, fd, offset])
+}
+
+pub fn sys_futex(uaddr: *const u32, op: usize, val: usize, timeout: *const crate::TimeSpec) -> isize {
+    syscall(SYSCALL_FUTEX, [uaddr as usize, op, val, timeout as usize, 0, 0])
+}
+
+pub fn sys_munmap(addr: usize, len: usize) -> isize {
+    syscall(SYSCALL_MUNMAP, [addr, len, 0, 0, 0, 0])
+}
+
+pub fn sys_mprotect(addr: usize, len: usize, prot: usize) -> isize {
+    syscall(SYSCALL_MPROTECT, [addr, len, prot, 0, 0, 0])
+}
+
+pub fn sys_shmget(key: usize, size: usize, shmflg: usize) -> isize {
+    syscall(SYSCALL_SHMGET, [key, size, shmflg, 0, 0, 0])
+}
+
+pub fn sys_shmat(shmid: usize, addr: usize, shmflg: usize) -> isize {
+    syscall(SYSCALL_SHMAT, [shmid, addr, shmflg, 0, 0, 0])
+}
+
+pub fn sys_shmdt(addr: usize) -> isize {
+    syscall(SYSCALL_SHMDT, [addr, 0, 0, 0, 0, 0])
+}
+
+pub fn sys_shmctl(shmid: usize, cmd: usize) -> isize {
+    syscall(SYSCALL_SHMCTL, [shmid, cmd, 0, 0, 0, 0])
+}
+
+pub fn sys_mincore(addr: usize, len: usize, vec: &mut [u8]) -> isize {
+    syscall(SYSCALL_MINCORE, [addr, len, vec.as_mut_ptr() as usize, 0, 0, 0])
+}
+
+pub fn sys_vma_info(buf: &mut [crate::AreaInfo]) -> isize {
+    syscall(SYSCALL_VMA_INFO, [buf.as_mut_ptr() as usize, buf.len(), 0, 0, 0, 0])
+}
+
+pub fn sys_strerror(errno: isize, buf: &mut [u8]) -> isize {
+    syscall(SYSCALL_STRERROR, [errno as usize, buf.as_mut_ptr() as usize, buf.len(), 0, 0, 0])
+}
+
+pub fn sys_klog(action: usize, buf: *mut u8, len: usize) -> isize {
+    syscall(SYSCALL_SYSLOG, [action, buf as usize, len, 0, 0, 0])
+}
+
+pub fn sys_sysctl(name: *const u8, old: *mut usize, new: *const usize) -> isize {
+    syscall(SYSCALL_SYSCTL, [name as usize, old as usize, new as usize, 0, 0, 0])
+}
+
+pub fn sys_sched_stat(tid: usize, stat: *mut crate::SchedStat) -> isize {
+    syscall(SYSCALL_SCHED_STAT, [tid, stat as usize, 0, 0, 0, 0])
+}
+
+pub fn sys_sched_setaffinity(pid: usize, len: usize, mask: *const usize) -> isize {
+    syscall(SYSCALL_SCHED_SETAFFINITY, [pid, len, mask as usize, 0, 0, 0])
+}
+
+pub fn sys_sched_getaffinity(pid: usize, len: usize, mask: *mut usize) -> isize {
+    syscall(SYSCALL_SCHED_GETAFFINITY, [pid, len, mask as usize, 0, 0, 0])
+}
+
+pub fn sys_getcpu(cpu: *mut u32, node: *mut u32) -> isize {
+    syscall(SYSCALL_GETCPU, [cpu as usize, node as usize, 0, 0, 0, 0])
+}
+
+pub fn sys_raw(id: usize, args: [usize; 6]) -> isize {
+    syscall(id, args)
+}
+
+pub fn sys_prlimit(pid: usize, resource: usize, new_limit: *const crate::RLimit, old_limit: *mut crate::RLimit) -> isize {
+    syscall(SYSCALL_PRLIMIT, [pid, resource, new_limit as usize, old_limit as usize, 0, 0])
+}
+
+pub fn sys_getrandom(buf: &mut [u8], flags: u32) -> isize {
+    syscall(SYSCALL_GETRANDOM, [buf.as_mut_ptr() as usize, buf.len(), flags as usize, 0, 0, 0])
+}
+
+pub fn sys_personality(persona: u32) -> isize {
+    syscall(SYSCALL_PERSONALITY, [persona as usize, 0, 0, 0, 0, 0])
+}
+
 pub fn sys_test(
     great_cross_page_ptr: usize,
     great_len: usize, 